@@ -0,0 +1,37 @@
+/// Benchmark for calling closures whose bodies are shared rather than copied
+///
+/// Runs `fib 25` with the naive doubly recursive definition and a tail
+/// recursive loop of 100000 iterations with the tree-walking evaluator, and
+/// prints the time of each. Every call re-creates the recursive closure, so
+/// these measure what a call costs apart from the work done in its body.
+///
+/// Run with `cargo bench --bench closure_calls`.
+use parlang::{eval, parse, Environment, Value};
+use std::time::Instant;
+
+const PROGRAMS: &[(&str, &str, i64)] = &[
+    (
+        "fib 25",
+        "let rec fib = fun n -> if n < 2 then n else fib (n - 1) + fib (n - 2) in fib 25",
+        75_025,
+    ),
+    (
+        "loop 100000",
+        "let rec loop = fun n -> if n == 0 then 0 else \
+         let a = n * 2 in let b = a - n in let c = b + 1 in \
+         match c with | 0 -> 0 | _ -> loop (c - 2) in loop 100000",
+        0,
+    ),
+];
+
+fn main() {
+    for (name, source, expected) in PROGRAMS {
+        let expr = parse(source).expect("benchmark program parses");
+        let env = Environment::new();
+        let start = Instant::now();
+        let result = eval(&expr, &env).expect("benchmark program evaluates");
+        let elapsed = start.elapsed();
+        assert_eq!(result, Value::Int(*expected));
+        println!("{name:<12}: {elapsed:?}");
+    }
+}
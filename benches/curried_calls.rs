@@ -0,0 +1,40 @@
+/// Benchmark for saturated curried calls
+///
+/// Runs a tail-recursive loop over a counter and an accumulator that calls a
+/// 3-argument curried function once per iteration to compute the next counter
+/// value, once with the call written as a single saturated application
+/// (`add3 n (-1) 0`) and once with the partial applications forced to happen
+/// one at a time, and prints the time of each.
+///
+/// Run with `cargo bench --bench curried_calls`.
+use parlang::{eval, parse, Environment, Value};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: i64 = 1_000_000;
+
+fn program(call: &str) -> String {
+    format!(
+        "let add3 = fun a -> fun b -> fun c -> a + b + c in \
+         (rec loop -> fun n -> fun calls -> if n == 0 then calls else loop ({call}) (calls + 1)) \
+         {ITERATIONS} 0"
+    )
+}
+
+fn run(name: &str, call: &str) -> Duration {
+    let expr = parse(&program(call)).expect("benchmark program parses");
+    let start = Instant::now();
+    let result = eval(&expr, &Environment::new()).expect("benchmark program evaluates");
+    let elapsed = start.elapsed();
+    assert_eq!(result, Value::Int(ITERATIONS));
+    println!("{name:<12} {ITERATIONS} calls: {elapsed:?}");
+    elapsed
+}
+
+fn main() {
+    let stepwise = run("stepwise", "(let f = add3 n in let g = f (-1) in g) 0");
+    let saturated = run("saturated", "add3 n (-1) 0");
+    println!(
+        "speedup: {:.2}x",
+        stepwise.as_secs_f64() / saturated.as_secs_f64()
+    );
+}
@@ -0,0 +1,55 @@
+/// Benchmark for `Environment::merge` against environments of different sizes
+///
+/// Merges a 10-binding environment into environments of 1,000 and 100,000
+/// bindings, and the other way round, many times each and prints the time per
+/// merge. `merge` only copies the smaller side, so merging into the larger
+/// environment must not take anywhere near 100 times as long; the benchmark
+/// fails if it takes more than 10 times as long, which keeps refactors of
+/// `Environment` from quietly making `load` proportional to the session.
+///
+/// Run with `cargo bench --bench env_merge`.
+use parlang::{Environment, Value};
+use std::time::{Duration, Instant};
+
+const SMALL: usize = 10;
+const MERGES: u32 = 10_000;
+const MAX_RATIO: f64 = 10.0;
+
+fn environment(prefix: &str, size: usize) -> Environment {
+    let mut env = Environment::new();
+    for i in 0..size {
+        env.bind(format!("{prefix}{i}"), Value::Int(i64::try_from(i).unwrap()));
+    }
+    env
+}
+
+fn run(name: &str, left: &Environment, right: &Environment) -> Duration {
+    let start = Instant::now();
+    for _ in 0..MERGES {
+        let merged = left.merge(right);
+        assert!(merged.lookup("small0").is_some());
+    }
+    let elapsed = start.elapsed() / MERGES;
+    println!("{name:<24} {elapsed:?} per merge");
+    elapsed
+}
+
+fn check(what: &str, base: Duration, large: Duration) {
+    let ratio = large.as_secs_f64() / base.as_secs_f64();
+    println!("{what}: 100k/1k ratio {ratio:.2}");
+    assert!(ratio < MAX_RATIO, "{what} scales with the large side: {ratio:.2}x");
+}
+
+fn main() {
+    let small = environment("small", SMALL);
+    let thousand = environment("big", 1_000);
+    let hundred_thousand = environment("big", 100_000);
+
+    let into_1k = run("10 into 1k", &thousand, &small);
+    let into_100k = run("10 into 100k", &hundred_thousand, &small);
+    check("merging into", into_1k, into_100k);
+
+    let onto_1k = run("1k into 10", &small, &thousand);
+    let onto_100k = run("100k into 10", &small, &hundred_thousand);
+    check("merging from", onto_1k, onto_100k);
+}
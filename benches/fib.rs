@@ -0,0 +1,33 @@
+/// Benchmark for evaluating with variables resolved to slots
+///
+/// Computes `fib 27` with the naive doubly recursive definition, once with
+/// the tree-walking evaluator and once with variables resolved ahead of
+/// evaluation, and prints the time of each.
+///
+/// Run with `cargo bench --bench fib`.
+use parlang::{eval, parse, Environment, Value};
+use std::time::{Duration, Instant};
+
+const N: i64 = 27;
+
+/// `fib N`
+const EXPECTED: i64 = 196_418;
+
+fn run(name: &str, resolved: bool) -> Duration {
+    let source = format!("let rec fib = fun n -> if n < 2 then n else fib (n - 1) + fib (n - 2) in fib {N}");
+    let expr = parse(&source).expect("benchmark program parses");
+    let mut env = Environment::new();
+    env.set_resolved(resolved);
+    let start = Instant::now();
+    let result = eval(&expr, &env).expect("benchmark program evaluates");
+    let elapsed = start.elapsed();
+    assert_eq!(result, Value::Int(EXPECTED));
+    println!("{name:<12} fib {N}: {elapsed:?}");
+    elapsed
+}
+
+fn main() {
+    let walked = run("tree-walker", false);
+    let resolved = run("resolved", true);
+    println!("speedup: {:.2}x", walked.as_secs_f64() / resolved.as_secs_f64());
+}
@@ -0,0 +1,33 @@
+/// Benchmark for the integer arithmetic fast path
+///
+/// Runs a tail-recursive loop that evaluates a cubic polynomial of the
+/// counter once per iteration, as the loop's exit test, once with the fast
+/// path turned off and once with its default threshold, and prints the time
+/// of each.
+///
+/// Run with `cargo bench --bench int_arith`.
+use parlang::{eval, parse, Environment, Value};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: i64 = 1_000_000;
+
+fn run(name: &str, fast_path: Option<usize>) -> Duration {
+    let source = format!(
+        "(rec loop -> fun n -> if 3 * n * n * n - 2 * n * n + 5 * n - 7 == 0 - 7 then 0 else loop (n - 1)) {ITERATIONS}"
+    );
+    let expr = parse(&source).expect("benchmark program parses");
+    let mut env = Environment::new();
+    env.set_int_fast_path(fast_path);
+    let start = Instant::now();
+    let result = eval(&expr, &env).expect("benchmark program evaluates");
+    let elapsed = start.elapsed();
+    assert_eq!(result, Value::Int(0));
+    println!("{name:<10} {ITERATIONS} evaluations: {elapsed:?}");
+    elapsed
+}
+
+fn main() {
+    let general = run("general", None);
+    let fast = run("fast path", Some(parlang::core::INT_FAST_PATH_MIN_OPS));
+    println!("speedup: {:.2}x", general.as_secs_f64() / fast.as_secs_f64());
+}
@@ -0,0 +1,40 @@
+/// Benchmark for the local reference optimization
+///
+/// Runs a tail-recursive counting loop that keeps the counter in a reference
+/// created, updated and read within each iteration, once as written and once
+/// after `optimize::localize_refs` has replaced the reference with plain
+/// bindings, and prints the time of each.
+///
+/// Run with `cargo bench --bench local_refs`.
+use parlang::{eval, optimize, parse, Environment, Expr, Value};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: i64 = 1_000_000;
+
+fn program() -> String {
+    format!(
+        "(rec loop -> fun n -> if n == 0 then 0 else let r = ref n in let d = r := !r - 1 in loop !r) \
+         {ITERATIONS}"
+    )
+}
+
+fn run(name: &str, expr: &Expr) -> Duration {
+    let start = Instant::now();
+    let result = eval(expr, &Environment::new()).expect("benchmark program evaluates");
+    let elapsed = start.elapsed();
+    assert_eq!(result, Value::Int(0));
+    println!("{name:<10} {ITERATIONS} iterations: {elapsed:?}");
+    elapsed
+}
+
+fn main() {
+    let expr = parse(&program()).expect("benchmark program parses");
+    let optimized = optimize::localize_refs(&expr);
+    assert_ne!(optimized, expr, "the reference should be localized");
+    let with_refs = run("ref cell", &expr);
+    let localized = run("localized", &optimized);
+    println!(
+        "speedup: {:.2}x",
+        with_refs.as_secs_f64() / localized.as_secs_f64()
+    );
+}
@@ -26,6 +26,7 @@
 /// - Let bindings: `Let(name, ty_ann, value, body)`
 /// - Sequential bindings: `Seq(bindings, body)`
 /// - Conditionals: `If(cond, then_expr, else_expr)`
+/// - Conditional chains: `IfChain(branches, else_expr)`
 /// - Binary operations: `BinOp(op, left, right)`
 /// - Pattern matching: `Match(expr, arms)`
 /// - Tuples: `Tuple(elements)`
@@ -35,7 +36,7 @@
 /// - Type aliases: `TypeAlias(name, type_expr, body)`
 /// - Sum types: `SumType(name, params, constructors, body)`
 /// - Constructors: `Constructor(name, args)`
-/// - Library loading: `Load(filepath, body)`
+/// - Library loading: `Load(filepath, body)`, `LoadAs(filepath, namespace, body)`
 /// 
 /// # Pattern Matching
 /// 
@@ -49,8 +50,8 @@
 /// 
 /// # Example
 /// 
-/// ```text
-/// let x = 42 in x + 1
+/// ```parlang
+/// let x = 42 in x + 1 -- => 43
 /// ```
 /// 
 /// Is represented as:
@@ -64,6 +65,7 @@ use std::fmt;
 
 /// Literal values for pattern matching
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Literal {
     /// Integer literal
     Int(i64),
@@ -77,6 +79,7 @@ pub enum Literal {
 
 /// Pattern for pattern matching
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Pattern {
     /// Literal pattern: 0, 1, true, false
     Literal(Literal),
@@ -96,6 +99,7 @@ pub enum Pattern {
 
 /// Type expressions for type aliases
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeExpr {
     /// Integer type: Int
     Int,
@@ -109,6 +113,7 @@ pub enum TypeExpr {
 
 /// Type annotations for sum type definitions
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypeAnnotation {
     /// Concrete type: Int, Bool
     Concrete(String),
@@ -122,6 +127,7 @@ pub enum TypeAnnotation {
 
 /// Expression types in the language
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     /// Integer literal: 42
     Int(i64),
@@ -147,6 +153,11 @@ pub enum Expr {
     /// If-then-else: if e1 then e2 else e3
     If(Box<Expr>, Box<Expr>, Box<Expr>),
     
+    /// Multi-branch conditional: if c1 then e1 elif c2 then e2 else e3
+    /// Vector of (condition, branch) pairs tried in order, followed by the final else branch
+    /// Produced by the parser for `elif` and `else if` chains of two or more conditions
+    IfChain(Vec<(Expr, Expr)>, Box<Expr>),
+    
     /// Let binding: let x = e1 in e2
     /// Optional type annotation for the variable
     Let(String, Option<TypeAnnotation>, Box<Expr>, Box<Expr>),
@@ -160,18 +171,26 @@ pub enum Expr {
     
     /// Load expression: load "filepath" in e
     Load(String, Box<Expr>),
+
+    /// Namespaced load expression: load "filepath" as Name in e
+    /// The library's bindings are reached as `Name.binding` in e
+    LoadAs(String, String, Box<Expr>),
     
     /// Sequential let bindings: let x = e1; let y = e2; expr
     /// Vector of (name, optional type annotation, value) triples, followed by a body expression
     Seq(Vec<(String, Option<TypeAnnotation>, Expr)>, Box<Expr>),
     
+    /// Missing final expression of a program or load: `let x = 1;`, `load "lib.par"`
+    /// Evaluates to the unit value `()`
+    EmptyBody,
+    
     /// Recursive function definition: rec name -> body
     /// The function can reference itself by name within its body
     Rec(String, Box<Expr>),
     
-    /// Pattern matching: match e with | p1 -> e1 | p2 -> e2 | ...
-    /// (scrutinee expression, vector of (pattern, result expression) arms)
-    Match(Box<Expr>, Vec<(Pattern, Expr)>),
+    /// Pattern matching: match e with | p1 -> e1 | p2 when g -> e2 | ...
+    /// (scrutinee expression, vector of (pattern, optional guard, result expression) arms)
+    Match(Box<Expr>, Vec<(Pattern, Option<Expr>, Expr)>),
     
     /// Tuple construction: (e1, e2, e3, ...)
     Tuple(Vec<Expr>),
@@ -223,6 +242,10 @@ pub enum Expr {
     /// Reference dereference: !expr
     /// Reads the value from a reference
     Deref(Box<Expr>),
+
+    /// Unary minus: -expr
+    /// Negates an Int or a Float
+    Neg(Box<Expr>),
     
     /// Reference assignment: ref_expr := value_expr
     /// Mutates the value stored in a reference
@@ -231,10 +254,16 @@ pub enum Expr {
     /// Range construction: start..end
     /// Creates an inclusive integer range from start to end
     Range(Box<Expr>, Box<Expr>),
+    
+    /// Error recovery: try body catch err -> handler
+    /// (body, name bound to the error record, handler)
+    /// Evaluates the handler when the body fails with a recoverable error
+    Try(Box<Expr>, String, Box<Expr>),
 }
 
 /// Binary operators
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOp {
     Add,  // +
     Sub,  // -
@@ -246,6 +275,167 @@ pub enum BinOp {
     Le,   // <=
     Gt,   // >
     Ge,   // >=
+    Concat, // ^ (string concatenation)
+}
+
+impl Expr {
+    /// The direct sub-expressions of this node, in source order
+    ///
+    /// Patterns and type expressions are not included; only `Expr` children.
+    /// The missing body of a body-less program or load (`EmptyBody`) is not
+    /// a child either.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Int(_)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Float(_)
+            | Expr::Byte(_)
+            | Expr::Var(_)
+            | Expr::EmptyBody => vec![],
+            Expr::BinOp(_, left, right)
+            | Expr::Let(_, _, left, right)
+            | Expr::App(left, right)
+            | Expr::ArrayIndex(left, right)
+            | Expr::RefAssign(left, right)
+            | Expr::Range(left, right)
+            | Expr::Try(left, _, right) => vec![left, right],
+            Expr::If(cond, then_branch, else_branch) => vec![cond, then_branch, else_branch],
+            Expr::IfChain(branches, else_branch) => branches
+                .iter()
+                .flat_map(|(cond, branch)| [cond, branch])
+                .chain(std::iter::once(else_branch.as_ref()))
+                .collect(),
+            Expr::Load(_, body) | Expr::LoadAs(_, _, body) if **body == Expr::EmptyBody => vec![],
+            Expr::Fun(_, _, body)
+            | Expr::Load(_, body)
+            | Expr::LoadAs(_, _, body)
+            | Expr::Rec(_, body)
+            | Expr::TupleProj(body, _)
+            | Expr::TypeAlias(_, _, body)
+            | Expr::FieldAccess(body, _)
+            | Expr::TypeDef { body, .. }
+            | Expr::Ref(body)
+            | Expr::Deref(body)
+            | Expr::Neg(body) => vec![body],
+            Expr::Seq(bindings, body) => bindings
+                .iter()
+                .map(|(_, _, value)| value)
+                .chain(std::iter::once(body.as_ref()).filter(|body| **body != Expr::EmptyBody))
+                .collect(),
+            Expr::Match(scrutinee, arms) => std::iter::once(scrutinee.as_ref())
+                .chain(arms.iter().flat_map(|(_, guard, result)| guard.iter().chain(std::iter::once(result))))
+                .collect(),
+            Expr::Tuple(elements) | Expr::Constructor(_, elements) | Expr::Array(elements) => {
+                elements.iter().collect()
+            }
+            Expr::Record(fields) => fields.iter().map(|(_, e)| e).collect(),
+        }
+    }
+
+    /// Count the expression nodes in this tree (including this one)
+    ///
+    /// Uses an explicit stack, so it works on trees too deep to traverse recursively.
+    pub fn node_count(&self) -> usize {
+        self.subexpressions().count()
+    }
+
+    /// Every expression in this tree, starting with this one, in pre-order
+    ///
+    /// A node comes before its children, and the children come in the order
+    /// of [`children`](Self::children). Uses an explicit stack, so it works
+    /// on trees too deep to traverse recursively.
+    pub fn subexpressions(&self) -> impl Iterator<Item = &Expr> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let expr = stack.pop()?;
+            stack.extend(expr.children().into_iter().rev());
+            Some(expr)
+        })
+    }
+
+    /// Call `f` on every expression in this tree, in the order of
+    /// [`subexpressions`](Self::subexpressions)
+    pub fn walk(&self, f: &mut impl FnMut(&Expr)) {
+        self.subexpressions().for_each(f);
+    }
+
+    /// Rebuild this node with `f` applied to each direct sub-expression
+    ///
+    /// `f` sees the sub-expressions [`children`](Self::children) lists, in
+    /// the same order; patterns, type annotations and names are kept as
+    /// they are.
+    #[must_use]
+    pub fn map_children(self, f: &mut impl FnMut(Expr) -> Expr) -> Expr {
+        let mut go = |e: Box<Expr>| Box::new(f(*e));
+        match self {
+            Expr::Int(_)
+            | Expr::Bool(_)
+            | Expr::Char(_)
+            | Expr::Float(_)
+            | Expr::Byte(_)
+            | Expr::Var(_)
+            | Expr::EmptyBody => self,
+            Expr::BinOp(op, left, right) => Expr::BinOp(op, go(left), go(right)),
+            Expr::If(cond, then_branch, else_branch) => Expr::If(go(cond), go(then_branch), go(else_branch)),
+            Expr::IfChain(branches, else_branch) => {
+                let branches = branches.into_iter().map(|(cond, branch)| (f(cond), f(branch))).collect();
+                Expr::IfChain(branches, Box::new(f(*else_branch)))
+            }
+            Expr::Let(name, ty_ann, value, body) => Expr::Let(name, ty_ann, go(value), go(body)),
+            Expr::Fun(param, ty_ann, body) => Expr::Fun(param, ty_ann, go(body)),
+            Expr::App(func, arg) => Expr::App(go(func), go(arg)),
+            Expr::Load(filepath, body) if *body == Expr::EmptyBody => Expr::Load(filepath, body),
+            Expr::Load(filepath, body) => Expr::Load(filepath, go(body)),
+            Expr::LoadAs(filepath, namespace, body) if *body == Expr::EmptyBody => Expr::LoadAs(filepath, namespace, body),
+            Expr::LoadAs(filepath, namespace, body) => Expr::LoadAs(filepath, namespace, go(body)),
+            Expr::Seq(bindings, body) => {
+                let bindings = bindings.into_iter().map(|(name, ty_ann, value)| (name, ty_ann, f(value))).collect();
+                let body = if *body == Expr::EmptyBody { body } else { Box::new(f(*body)) };
+                Expr::Seq(bindings, body)
+            }
+            Expr::Rec(name, body) => Expr::Rec(name, go(body)),
+            Expr::Match(scrutinee, arms) => {
+                let scrutinee = Box::new(f(*scrutinee));
+                let arms = arms
+                    .into_iter()
+                    .map(|(pattern, guard, result)| {
+                        let guard = guard.map(&mut *f);
+                        (pattern, guard, f(result))
+                    })
+                    .collect();
+                Expr::Match(scrutinee, arms)
+            }
+            Expr::Tuple(elements) => Expr::Tuple(elements.into_iter().map(f).collect()),
+            Expr::TupleProj(tuple, index) => Expr::TupleProj(go(tuple), index),
+            Expr::TypeAlias(alias, ty_expr, body) => Expr::TypeAlias(alias, ty_expr, go(body)),
+            Expr::Record(fields) => Expr::Record(fields.into_iter().map(|(field, e)| (field, f(e))).collect()),
+            Expr::FieldAccess(record, field) => Expr::FieldAccess(go(record), field),
+            Expr::TypeDef { name, type_params, constructors, body } => {
+                Expr::TypeDef { name, type_params, constructors, body: go(body) }
+            }
+            Expr::Constructor(ctor, args) => Expr::Constructor(ctor, args.into_iter().map(f).collect()),
+            Expr::Array(elements) => Expr::Array(elements.into_iter().map(f).collect()),
+            Expr::ArrayIndex(arr, index) => Expr::ArrayIndex(go(arr), go(index)),
+            Expr::Ref(inner) => Expr::Ref(go(inner)),
+            Expr::Deref(inner) => Expr::Deref(go(inner)),
+            Expr::Neg(inner) => Expr::Neg(go(inner)),
+            Expr::RefAssign(target, value) => Expr::RefAssign(go(target), go(value)),
+            Expr::Range(start, end) => Expr::Range(go(start), go(end)),
+            Expr::Try(body, err, handler) => Expr::Try(go(body), err, go(handler)),
+        }
+    }
+
+    /// Rewrite this tree bottom-up: each node's children are transformed
+    /// first, then `f` is applied to the rebuilt node
+    ///
+    /// This is the driver for rewriting passes, which only need to handle
+    /// the nodes they change and return the others as they are.
+    #[must_use]
+    pub fn transform(self, f: &mut impl FnMut(Expr) -> Expr) -> Expr {
+        let rebuilt = self.map_children(&mut |child| child.transform(f));
+        f(rebuilt)
+    }
 }
 
 impl fmt::Display for Expr {
@@ -265,6 +455,8 @@ impl fmt::Display for Expr {
                 }
                 write!(f, "'")
             }
+            // Keep whole floats apart from integers: `1.0`, not `1`
+            Expr::Float(fl) if fl.is_finite() && fl.fract() == 0.0 => write!(f, "{fl:.1}"),
             Expr::Float(fl) => write!(f, "{fl}"),
             Expr::Byte(b) => write!(f, "{}b", b),
             Expr::Var(name) => write!(f, "{name}"),
@@ -272,6 +464,14 @@ impl fmt::Display for Expr {
             Expr::If(cond, then_branch, else_branch) => {
                 write!(f, "(if {cond} then {then_branch} else {else_branch})")
             }
+            Expr::IfChain(branches, else_branch) => {
+                write!(f, "(")?;
+                for (i, (cond, branch)) in branches.iter().enumerate() {
+                    let keyword = if i == 0 { "if" } else { " elif" };
+                    write!(f, "{keyword} {cond} then {branch}")?;
+                }
+                write!(f, " else {else_branch})")
+            }
             Expr::Let(name, ty_ann, value, body) => {
                 if let Some(ty) = ty_ann {
                     write!(f, "(let {name} : {ty} = {value} in {body})")
@@ -287,7 +487,12 @@ impl fmt::Display for Expr {
                 }
             }
             Expr::App(func, arg) => write!(f, "({func} {arg})"),
+            Expr::Load(filepath, body) if **body == Expr::EmptyBody => write!(f, "(load \"{filepath}\")"),
             Expr::Load(filepath, body) => write!(f, "(load \"{filepath}\" in {body})"),
+            Expr::LoadAs(filepath, namespace, body) if **body == Expr::EmptyBody => {
+                write!(f, "(load \"{filepath}\" as {namespace})")
+            }
+            Expr::LoadAs(filepath, namespace, body) => write!(f, "(load \"{filepath}\" as {namespace} in {body})"),
             Expr::Seq(bindings, body) => {
                 write!(f, "(")?;
                 for (i, (name, ty_ann, value)) in bindings.iter().enumerate() {
@@ -300,13 +505,22 @@ impl fmt::Display for Expr {
                         write!(f, "let {name} = {value}")?;
                     }
                 }
-                write!(f, "; {body})")
+                if **body == Expr::EmptyBody {
+                    write!(f, ";)")
+                } else {
+                    write!(f, "; {body})")
+                }
             }
+            Expr::EmptyBody => Ok(()),
             Expr::Rec(name, body) => write!(f, "(rec {name} -> {body})"),
             Expr::Match(scrutinee, arms) => {
                 write!(f, "(match {scrutinee} with")?;
-                for (pattern, result) in arms {
-                    write!(f, " | {pattern} -> {result}")?;
+                for (pattern, guard, result) in arms {
+                    write!(f, " | {pattern}")?;
+                    if let Some(guard) = guard {
+                        write!(f, " when {guard}")?;
+                    }
+                    write!(f, " -> {result}")?;
                 }
                 write!(f, ")")
             }
@@ -372,8 +586,10 @@ impl fmt::Display for Expr {
             Expr::ArrayIndex(arr, index) => write!(f, "{arr}[{index}]"),
             Expr::Ref(expr) => write!(f, "(ref {expr})"),
             Expr::Deref(expr) => write!(f, "(!{expr})"),
+            Expr::Neg(expr) => write!(f, "(-{expr})"),
             Expr::RefAssign(ref_expr, value) => write!(f, "({ref_expr} := {value})"),
             Expr::Range(start, end) => write!(f, "{start}..{end}"),
+            Expr::Try(body, name, handler) => write!(f, "(try {body} catch {name} -> {handler})"),
         }
     }
 }
@@ -463,7 +679,10 @@ impl fmt::Display for Pattern {
             Pattern::Constructor(name, patterns) => {
                 write!(f, "{}", name)?;
                 for pattern in patterns {
-                    write!(f, " {}", pattern)?;
+                    match pattern {
+                        Pattern::Constructor(_, args) if !args.is_empty() => write!(f, " ({pattern})")?,
+                        _ => write!(f, " {}", pattern)?,
+                    }
                 }
                 Ok(())
             }
@@ -484,6 +703,7 @@ impl fmt::Display for BinOp {
             BinOp::Le => "<=",
             BinOp::Gt => ">",
             BinOp::Ge => ">=",
+            BinOp::Concat => "^",
         };
         write!(f, "{s}")
     }
@@ -688,6 +908,100 @@ mod tests {
         assert_eq!(format!("{expr}"), "(if true then 1 else 2)");
     }
 
+    #[test]
+    fn test_node_count() {
+        assert_eq!(Expr::Int(1).node_count(), 1);
+        let expr = Expr::BinOp(
+            BinOp::Add,
+            Box::new(Expr::Int(1)),
+            Box::new(Expr::Var("x".to_string())),
+        );
+        assert_eq!(expr.node_count(), 3);
+        let expr = Expr::Match(
+            Box::new(Expr::Var("p".to_string())),
+            vec![(Pattern::Wildcard, None, Expr::Int(0)), (Pattern::Var("n".to_string()), None, Expr::Int(1))],
+        );
+        assert_eq!(expr.node_count(), 4);
+    }
+
+    #[test]
+    fn test_subexpressions_are_in_pre_order() {
+        let expr = Expr::If(
+            Box::new(Expr::BinOp(BinOp::Lt, Box::new(Expr::Var("a".to_string())), Box::new(Expr::Int(1)))),
+            Box::new(Expr::Var("b".to_string())),
+            Box::new(Expr::Neg(Box::new(Expr::Int(2)))),
+        );
+        let shown: Vec<String> = expr.subexpressions().skip(1).map(ToString::to_string).collect();
+        assert_eq!(shown, ["(a < 1)", "a", "1", "b", "(-2)", "2"]);
+    }
+
+    #[test]
+    fn test_walk_visits_every_node() {
+        let expr = crate::parser::parse("let f = fun x -> match x with | 0 -> (1, 2) | n -> n in f 3").unwrap();
+        let mut vars = Vec::new();
+        let mut count = 0;
+        expr.walk(&mut |node| {
+            count += 1;
+            if let Expr::Var(name) = node {
+                vars.push(name.clone());
+            }
+        });
+        // let, fun, match, x, tuple, 1, 2, n, app, f, 3
+        assert_eq!(count, 11);
+        assert_eq!(count, expr.node_count());
+        assert_eq!(vars, ["x", "n", "f"]);
+    }
+
+    #[test]
+    fn test_transform_folds_constant_additions() {
+        let expr = crate::parser::parse("let x = 1 + 2 + 3 in [x + (4 + 5), if true then 6 + 7 else x + 1]").unwrap();
+        let folded = expr.transform(&mut |node| match node {
+            Expr::BinOp(BinOp::Add, left, right) => match (*left, *right) {
+                (Expr::Int(a), Expr::Int(b)) => Expr::Int(a + b),
+                (left, right) => Expr::BinOp(BinOp::Add, Box::new(left), Box::new(right)),
+            },
+            other => other,
+        });
+        assert_eq!(folded, crate::parser::parse("let x = 6 in [x + 9, if true then 13 else x + 1]").unwrap());
+    }
+
+    #[test]
+    fn test_map_children_keeps_missing_bodies() {
+        let expr = crate::parser::parse("let x = 1; let y = 2;").unwrap();
+        let doubled = expr.clone().map_children(&mut |child| Expr::BinOp(BinOp::Mul, Box::new(child), Box::new(Expr::Int(2))));
+        assert_eq!(doubled, crate::parser::parse("let x = 1 * 2; let y = 2 * 2;").unwrap());
+        assert_eq!(doubled.children().len(), expr.children().len());
+    }
+
+    #[test]
+    fn test_node_count_deep_tree() {
+        let mut expr = Expr::Int(0);
+        for _ in 0..100_000 {
+            expr = Expr::Ref(Box::new(expr));
+        }
+        assert_eq!(expr.node_count(), 100_001);
+        // Dismantle iteratively so the recursive Drop does not overflow the stack
+        while let Expr::Ref(inner) = expr {
+            expr = *inner;
+        }
+    }
+
+    #[test]
+    fn test_display_if_chain() {
+        let expr = Expr::IfChain(
+            vec![
+                (Expr::Var("a".to_string()), Expr::Int(1)),
+                (Expr::Var("b".to_string()), Expr::Int(2)),
+                (Expr::Var("c".to_string()), Expr::Int(3)),
+            ],
+            Box::new(Expr::Int(4)),
+        );
+        assert_eq!(
+            format!("{expr}"),
+            "(if a then 1 elif b then 2 elif c then 3 else 4)"
+        );
+    }
+
     #[test]
     fn test_display_let() {
         let expr = Expr::Let(
@@ -733,6 +1047,18 @@ mod tests {
         assert_eq!(format!("{expr}"), "(let x = 42; let y = 10; x)");
     }
 
+    #[test]
+    fn test_display_without_body() {
+        let expr = Expr::Seq(
+            vec![("x".to_string(), None, Expr::Int(42))],
+            Box::new(Expr::EmptyBody),
+        );
+        assert_eq!(format!("{expr}"), "(let x = 42;)");
+        let expr = Expr::Load("lib.par".to_string(), Box::new(Expr::EmptyBody));
+        assert_eq!(format!("{expr}"), "(load \"lib.par\")");
+        assert_eq!(expr.children(), Vec::<&Expr>::new());
+    }
+
     // Test Display implementation for BinOp
     #[test]
     fn test_binop_display_add() {
@@ -956,8 +1282,8 @@ mod tests {
     #[test]
     fn test_expr_match() {
         let arms = vec![
-            (Pattern::Literal(Literal::Int(0)), Expr::Int(1)),
-            (Pattern::Var("n".to_string()), Expr::Var("n".to_string())),
+            (Pattern::Literal(Literal::Int(0)), None, Expr::Int(1)),
+            (Pattern::Var("n".to_string()), None, Expr::Var("n".to_string())),
         ];
         let expr = Expr::Match(Box::new(Expr::Var("x".to_string())), arms.clone());
         assert_eq!(
@@ -969,15 +1295,24 @@ mod tests {
     #[test]
     fn test_display_match() {
         let arms = vec![
-            (Pattern::Literal(Literal::Int(0)), Expr::Int(1)),
-            (Pattern::Var("n".to_string()), Expr::Var("n".to_string())),
-            (Pattern::Wildcard, Expr::Int(42)),
+            (Pattern::Literal(Literal::Int(0)), None, Expr::Int(1)),
+            (Pattern::Var("n".to_string()), None, Expr::Var("n".to_string())),
+            (Pattern::Wildcard, None, Expr::Int(42)),
         ];
         let expr = Expr::Match(Box::new(Expr::Var("x".to_string())), arms);
         assert_eq!(
             format!("{expr}"),
             "(match x with | 0 -> 1 | n -> n | _ -> 42)"
         );
+        let guarded = Expr::Match(
+            Box::new(Expr::Var("x".to_string())),
+            vec![(
+                Pattern::Var("n".to_string()),
+                Some(Expr::BinOp(BinOp::Gt, Box::new(Expr::Var("n".to_string())), Box::new(Expr::Int(10)))),
+                Expr::Int(1),
+            )],
+        );
+        assert_eq!(format!("{guarded}"), "(match x with | n when (n > 10) -> 1)");
     }
 
     // Test Tuple expression
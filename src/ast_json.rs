@@ -0,0 +1,189 @@
+//! The AST as JSON, for tools written in other languages
+//!
+//! Only built with the `serde` feature. A document is an object holding the
+//! [`FORMAT_VERSION`] it was written with and the program:
+//!
+//! ```json
+//! {"parlang_format": 1, "expr": {"BinOp": ["Add", {"Int": 1}, {"Int": 2}]}}
+//! ```
+//!
+//! Each `Expr`, `Pattern` and type annotation is written the way serde
+//! writes Rust enums: a unit variant as its name, any other variant as an
+//! object with the variant name as its only key.
+//!
+//! A dump limited to some number of expression nodes (see
+//! [`expr_to_json_with_limit`]) also holds whether it was `truncated` and
+//! the `node_count` of the whole program, and each subtree it leaves out is
+//! written as the marker `"Truncated"`:
+//!
+//! ```json
+//! {"parlang_format": 1, "expr": {"BinOp": ["Add", {"Int": 1}, "Truncated"]}, "truncated": true, "node_count": 3}
+//! ```
+
+use crate::ast::Expr;
+use crate::format::{check_version, FORMAT_FIELD, FORMAT_VERSION};
+use crate::parser::parse;
+use serde::Deserialize;
+use serde_json::Value as Json;
+
+/// Parse a program and write its AST as a JSON document
+///
+/// # Errors
+///
+/// Returns the parse error, rendered as text, when `input` does not parse.
+pub fn parse_to_json(input: &str) -> Result<String, String> {
+    let expr = parse(input).map_err(|e| e.to_string())?;
+    expr_to_json(&expr)
+}
+
+/// Write an AST as a JSON document
+///
+/// # Errors
+///
+/// Returns serde's message when the AST cannot be written; trees built by
+/// the parser always can be.
+pub fn expr_to_json(expr: &Expr) -> Result<String, String> {
+    expr_to_json_with_limit(expr, None).map(|(json, _)| json)
+}
+
+/// Write an AST as a JSON document with at most `limit` expression nodes
+///
+/// Nodes are kept in pre-order, and each subtree past the limit is written
+/// as the marker `"Truncated"`, which [`expr_from_json`] does not read back.
+/// With a limit the document also records whether it was truncated and the
+/// [`node_count`](Expr::node_count) of the whole AST. Returns the document
+/// and whether it was truncated.
+///
+/// # Errors
+///
+/// Returns serde's message when the AST cannot be written; trees built by
+/// the parser always can be.
+pub fn expr_to_json_with_limit(expr: &Expr, limit: Option<usize>) -> Result<(String, bool), String> {
+    let write = |document: &Document<_>| serde_json::to_string(document).map_err(|e| e.to_string());
+    let Some(limit) = limit else {
+        let document = Document { parlang_format: FORMAT_VERSION, expr, truncated: None, node_count: None };
+        return write(&document).map(|json| (json, false));
+    };
+    let node_count = expr.node_count();
+    if node_count <= limit {
+        let document = Document { parlang_format: FORMAT_VERSION, expr, truncated: Some(false), node_count: Some(node_count) };
+        return write(&document).map(|json| (json, false));
+    }
+    // Cut subtrees become a variable no program can name, written as the marker
+    let mut budget = limit;
+    let mut kept = serde_json::to_value(keep_nodes(expr.clone(), &mut budget)).map_err(|e| e.to_string())?;
+    mark_truncated(&mut kept);
+    let document = Document { parlang_format: FORMAT_VERSION, expr: &kept, truncated: Some(true), node_count: Some(node_count) };
+    serde_json::to_string(&document).map(|json| (json, true)).map_err(|e| e.to_string())
+}
+
+/// A JSON document as written, with the version first
+#[derive(serde::Serialize)]
+struct Document<'a, E> {
+    parlang_format: u32,
+    expr: &'a E,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    node_count: Option<usize>,
+}
+
+/// Stand-in for a subtree past the node limit; not a valid identifier
+const CUT: &str = "<truncated>";
+
+/// `expr` with the nodes after the first `budget` in pre-order replaced by [`CUT`]
+fn keep_nodes(expr: Expr, budget: &mut usize) -> Expr {
+    if *budget == 0 {
+        return Expr::Var(CUT.to_string());
+    }
+    *budget -= 1;
+    expr.map_children(&mut |child| keep_nodes(child, budget))
+}
+
+/// Replace each cut subtree in a written expression with the `"Truncated"` marker
+fn mark_truncated(json: &mut Json) {
+    match json {
+        Json::Object(object) if object.len() == 1 && object.get("Var").and_then(Json::as_str) == Some(CUT) => {
+            *json = Json::String("Truncated".to_string());
+        }
+        Json::Object(object) => object.values_mut().for_each(mark_truncated),
+        Json::Array(items) => items.iter_mut().for_each(mark_truncated),
+        _ => {}
+    }
+}
+
+/// Read an AST from a JSON document written by [`parse_to_json`]
+///
+/// # Errors
+///
+/// Returns a message when `json` is not valid JSON, has no format version,
+/// was written by a newer parlang, or does not hold an expression.
+pub fn expr_from_json(json: &str) -> Result<Expr, String> {
+    // Programs nest far deeper than serde_json's default limit allows
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    deserializer.disable_recursion_limit();
+    let mut document = Json::deserialize(&mut deserializer)
+        .and_then(|document| deserializer.end().map(|()| document))
+        .map_err(|e| format!("Invalid AST JSON: {e}"))?;
+    let version = document
+        .get(FORMAT_FIELD)
+        .and_then(Json::as_u64)
+        .ok_or_else(|| format!("Invalid AST JSON: missing the \"{FORMAT_FIELD}\" version field"))?;
+    check_version("AST JSON", Some(u32::try_from(version).unwrap_or(u32::MAX))).map_err(|e| e.to_string())?;
+    let expr = document.get_mut("expr").map(Json::take).ok_or("Invalid AST JSON: missing the \"expr\" field")?;
+    serde_json::from_value(expr).map_err(|e| format!("Invalid AST JSON: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_layout() {
+        assert_eq!(
+            parse_to_json("1 + x").unwrap(),
+            r#"{"parlang_format":1,"expr":{"BinOp":["Add",{"Int":1},{"Var":"x"}]}}"#
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = "type Option a = Some a | None in let x: Int = 2 in let f = fun o -> match o with | Some n -> n | None -> 0 in f (Some x)";
+        let expr = parse(source).unwrap();
+        assert_eq!(expr_from_json(&parse_to_json(source).unwrap()), Ok(expr));
+    }
+
+    #[test]
+    fn test_deep_programs_round_trip() {
+        let source = format!("{}x", "let x = 1 in ".repeat(200));
+        let expr = parse(&source).unwrap();
+        assert_eq!(expr_from_json(&parse_to_json(&source).unwrap()), Ok(expr));
+    }
+
+    #[test]
+    fn test_limited_dump() {
+        let expr = parse("(1 + 2, x)").unwrap();
+        assert_eq!(expr.node_count(), 5);
+        let (json, truncated) = expr_to_json_with_limit(&expr, Some(3)).unwrap();
+        assert!(truncated);
+        assert_eq!(
+            json,
+            r#"{"parlang_format":1,"expr":{"Tuple":[{"BinOp":["Add",{"Int":1},"Truncated"]},"Truncated"]},"truncated":true,"node_count":5}"#
+        );
+        // Within the limit the whole AST is written, with the count alongside
+        let (json, truncated) = expr_to_json_with_limit(&expr, Some(5)).unwrap();
+        assert!(!truncated);
+        assert!(json.ends_with(r#""truncated":false,"node_count":5}"#), "{json}");
+        assert_eq!(expr_from_json(&json), Ok(expr));
+    }
+
+    #[test]
+    fn test_rejected_documents() {
+        assert!(parse_to_json("let x = in").unwrap_err().contains("parse error"));
+        assert!(expr_from_json("{").unwrap_err().starts_with("Invalid AST JSON: "));
+        assert!(expr_from_json(r#"{"expr": {"Int": 1}}"#).unwrap_err().contains("missing the \"parlang_format\""));
+        let newer = format!(r#"{{"parlang_format": {}, "expr": {{"Int": 1}}}}"#, FORMAT_VERSION + 1);
+        assert!(expr_from_json(&newer).unwrap_err().starts_with("AST JSON has format version"));
+        assert!(expr_from_json(r#"{"parlang_format": 1, "expr": {"Nope": 1}}"#).unwrap_err().contains("unknown variant"));
+    }
+}
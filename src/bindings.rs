@@ -0,0 +1,233 @@
+//! Persistent map of local bindings behind [`Environment`](crate::eval::Environment)
+//!
+//! Environments are cloned constantly: every `let`, every function call and
+//! every `load` derives a new environment from an existing one. Copying a
+//! hash map each time would make a binding cost proportional to everything
+//! already in scope, so [`Bindings`] shares its contents instead.
+//!
+//! # Representation
+//!
+//! The bindings are a persistent hash trie: each branch splits on four bits
+//! of the name's hash into sixteen children shared through `Rc`, and leaves
+//! hold the bindings whose names hash alike. Binding a name copies only the
+//! branches on the path to its leaf, so every earlier version stays valid and
+//! shares everything else with the new one.
+//!
+//! Each binding carries a sequence number recording when it was defined,
+//! which gives the definition order without keeping a separate list.
+//!
+//! # Complexity
+//!
+//! With `n` bindings in scope:
+//!
+//! - `clone`: `O(1)`
+//! - `insert`: `O(log n)`, copying at most one sixteen-slot branch per level
+//! - `get`: `O(log n)`, one branch per level; a 64-bit hash bounds the depth at 16
+//! - `merge` with `m` bindings on the smaller side: `O(m log n)`; the larger
+//!   side's trie is shared, never copied
+//! - `order`, `iter`: `O(n log n)` and `O(n)`; meant for listings, not evaluation
+
+use crate::eval::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Bits of the hash consumed per trie level
+const BITS: u32 = 4;
+const WIDTH: usize = 1 << BITS;
+
+/// Names bound to values, cheap to clone and to extend
+#[derive(Clone, Default)]
+pub(crate) struct Bindings {
+    root: Option<Rc<Node>>,
+    len: usize,
+    /// Sequence number of the first binding; `merge` may number bindings below zero
+    first_seq: i64,
+    /// Sequence number given to the next binding
+    next_seq: i64,
+    /// Bindings numbered below this are left out of [`order`](Self::order)
+    order_floor: i64,
+}
+
+/// A binding: name, sequence number, value
+type Entry = (String, i64, Value);
+
+enum Node {
+    /// Children indexed by the next bits of the hash
+    Branch([Option<Rc<Node>>; WIDTH]),
+    /// Bindings whose names share the full hash
+    Leaf(u64, Vec<Entry>),
+}
+
+impl Bindings {
+    /// Bind `name`, shadowing any earlier binding of it
+    pub(crate) fn insert(&mut self, name: String, value: Value) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.insert_entry((name, seq, value));
+    }
+
+    /// The value bound to `name`
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        let hash = hash_name(name);
+        let mut node = self.root.as_deref()?;
+        let mut shift = 0;
+        loop {
+            match node {
+                Node::Branch(children) => {
+                    node = children[slot(hash, shift)].as_deref()?;
+                    shift += BITS;
+                }
+                Node::Leaf(_, entries) => {
+                    return entries.iter().find(|(bound, _, _)| bound == name).map(|(_, _, value)| value);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Every binding, in no particular order
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.entries().into_iter().map(|(name, _, value)| (name, value))
+    }
+
+    /// Names of the bindings from [`start_order`](Self::start_order) on, in
+    /// the order they were last defined
+    pub(crate) fn order(&self) -> Vec<String> {
+        let mut entries: Vec<_> = self.entries().into_iter().filter(|(_, seq, _)| *seq >= self.order_floor).collect();
+        entries.sort_unstable_by_key(|(_, seq, _)| *seq);
+        entries.into_iter().map(|(name, _, _)| name.clone()).collect()
+    }
+
+    /// Leave the bindings made so far out of [`order`](Self::order), keeping them bound
+    pub(crate) fn start_order(&mut self) {
+        self.order_floor = self.next_seq;
+    }
+
+    /// Remove and return every binding
+    pub(crate) fn take(&mut self) -> HashMap<String, Value> {
+        let taken = std::mem::take(self);
+        taken.iter().map(|(name, value)| (name.clone(), value.clone())).collect()
+    }
+
+    /// The bindings of `self` followed by those in `other`'s [`order`](Self::order),
+    /// which win over bindings of the same name
+    ///
+    /// The larger side's trie is reused and only the smaller side's bindings
+    /// are inserted into it.
+    pub(crate) fn merge(&self, other: &Bindings) -> Bindings {
+        if other.len <= self.len || !self.is_fully_ordered() || !other.is_fully_ordered() {
+            let mut merged = self.clone();
+            for name in other.order() {
+                let value = other.get(&name).cloned().expect("ordered names are bound");
+                merged.insert(name, value);
+            }
+            return merged;
+        }
+
+        // `other` is larger: keep its trie and slip our surviving bindings in
+        // underneath, numbered before its first binding
+        let kept: Vec<String> = self.order().into_iter().filter(|name| !other.contains(name)).collect();
+        let mut merged = other.clone();
+        merged.first_seq -= i64::try_from(kept.len()).expect("binding count fits in i64");
+        merged.order_floor = merged.first_seq;
+        for (name, seq) in kept.into_iter().zip(merged.first_seq..) {
+            let value = self.get(&name).cloned().expect("ordered names are bound");
+            merged.insert_entry((name, seq, value));
+        }
+        merged
+    }
+
+    /// Whether [`order`](Self::order) lists every binding
+    fn is_fully_ordered(&self) -> bool {
+        self.order_floor <= self.first_seq
+    }
+
+    fn insert_entry(&mut self, entry: Entry) {
+        let hash = hash_name(&entry.0);
+        let (root, added) = insert_into(self.root.as_ref(), hash, 0, entry);
+        self.root = Some(root);
+        if added {
+            self.len += 1;
+        }
+    }
+
+    fn entries(&self) -> Vec<&Entry> {
+        let mut entries = Vec::with_capacity(self.len);
+        let mut stack: Vec<&Node> = self.root.as_deref().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Branch(children) => stack.extend(children.iter().flatten().map(Rc::as_ref)),
+                Node::Leaf(_, leaf) => entries.extend(leaf),
+            }
+        }
+        entries
+    }
+}
+
+fn hash_name(name: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The child of a branch at depth `shift / BITS` that `hash` belongs to
+fn slot(hash: u64, shift: u32) -> usize {
+    // Masked to BITS bits, so the cast cannot truncate
+    #[allow(clippy::cast_possible_truncation)]
+    let slot = ((hash >> shift) as usize) & (WIDTH - 1);
+    slot
+}
+
+/// A copy of `node` with `entry` bound, and whether the name was new
+fn insert_into(node: Option<&Rc<Node>>, hash: u64, shift: u32, entry: Entry) -> (Rc<Node>, bool) {
+    let Some(node) = node else {
+        return (Rc::new(Node::Leaf(hash, vec![entry])), true);
+    };
+    match node.as_ref() {
+        Node::Leaf(leaf_hash, entries) if *leaf_hash == hash => {
+            let mut entries = entries.clone();
+            let existing = entries.iter_mut().find(|(bound, _, _)| *bound == entry.0);
+            let added = existing.is_none();
+            if let Some(existing) = existing {
+                *existing = entry;
+            } else {
+                entries.push(entry);
+            }
+            (Rc::new(Node::Leaf(hash, entries)), added)
+        }
+        Node::Leaf(leaf_hash, _) => {
+            // Hashes differ, so they part ways at this level or a deeper one
+            let mut children: [Option<Rc<Node>>; WIDTH] = Default::default();
+            children[slot(*leaf_hash, shift)] = Some(Rc::clone(node));
+            let branch = Rc::new(Node::Branch(children));
+            insert_into(Some(&branch), hash, shift, entry)
+        }
+        Node::Branch(children) => {
+            let mut children = children.clone();
+            let index = slot(hash, shift);
+            let (child, added) = insert_into(children[index].as_ref(), hash, shift + BITS, entry);
+            children[index] = Some(child);
+            (Rc::new(Node::Branch(children)), added)
+        }
+    }
+}
+
+impl fmt::Debug for Bindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for Bindings {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self.order() == other.order()
+            && self.iter().all(|(name, value)| other.get(name) == Some(value))
+    }
+}
@@ -0,0 +1,811 @@
+/// Built-in functions of the `ParLang` prelude
+///
+/// Built-ins are native Rust functions exposed to programs as ordinary
+/// curried function values (`Value::Builtin`). Each one is registered once
+/// in the table below with its arity, implementation, and type scheme, so
+/// the evaluator (`Environment::with_builtins`) and the type checker
+/// (`TypeEnv::with_builtins`) always agree on what the prelude contains.
+///
+/// # Comparison Built-ins
+///
+/// `min`, `max`, and `clamp` work at runtime on any comparable kind (Int,
+/// Float, Char) and fail with an `EvalError` when given values of different
+/// or incomparable kinds. The type system has no constraint mechanism for
+/// "comparable" types, so they are typed on Int, with `float_`/`char_`
+/// prefixed variants typed on Float and Char. `min_by`/`max_by` take a
+/// less-than predicate and work on any type.
+///
+/// # Numeric Built-ins
+///
+/// `abs` and `float_abs` are the absolute value on Int and Float (both
+/// accept either kind at runtime, like `min`); `abs` of the smallest Int
+/// overflows and is an `EvalError`.
+///
+/// # Scalar Conversion Built-ins
+///
+/// `ord` and `chr` convert between a Char and its Unicode code point; `chr`
+/// of a value that is not a code point (negative, above `0x10FFFF`, or a
+/// surrogate) is an `EvalError`. `int_to_float` converts exactly for Ints up
+/// to 2^53 and rounds to the nearest Float beyond. `float_to_int` truncates
+/// toward zero and fails on NaN, infinities, and Floats outside the Int
+/// range. `bool_to_int` (also available as `int_of_bool`) maps `true` to 1
+/// and `false` to 0.
+///
+/// # Conversion Built-ins
+///
+/// `tuple_to_array`, `array_to_tuple`, `field_names`, and `has_field`
+/// convert between compound values. The element types and array sizes they
+/// produce depend on runtime values, so these have permissive schemes and are
+/// checked at runtime: `tuple_to_array` and `array_to_tuple` are typed
+/// `a -> b`, and `field_names` returns `Array[List Char, 0]` (0 standing for
+/// an unknown size, as for array indexing). Field names are strings, i.e.
+/// `List Char` values.
+///
+/// # Float Built-ins
+///
+/// `approx_eq a b epsilon` is `true` when `a` and `b` differ by at most
+/// `epsilon`. NaN is never approximately equal to anything, and an infinity
+/// only to the same infinity, whatever the tolerance. Comparing floats with
+/// `==` is flagged by the `float-equality` lint, which suggests it.
+///
+/// # String Built-ins
+///
+/// `show` renders any value as a string: strings and characters as their
+/// text, everything else as the REPL prints it. String interpolation
+/// (`"x = {x}"`) desugars to `show` and `^`. `print` writes the same text and
+/// a newline to standard output and returns its argument unchanged, so it can
+/// wrap any sub-expression to trace it.
+///
+/// # Debug Built-ins
+///
+/// `debug_env ()` returns a record of the bindings in scope where it is
+/// called, prelude built-ins excluded. Functions in it, including ones inside
+/// tuples, records, arrays and constructed values, are replaced by their
+/// rendering as a string, so the record does not keep their environments
+/// alive. Its type is `Unit -> a`, so fields can be read from the result
+/// (`(debug_env ()).x`) without the checker knowing them. It is only available with debug built-ins enabled
+/// (`Environment::set_debug_builtins`, `EvalConfig::debug_builtins`,
+/// `--debug-builtins`, or `:set debug on` in the REPL) and is an
+/// `EvalError` otherwise, so programs cannot come to depend on it. It must be
+/// called directly: passed to another built-in such as `memo`, it cannot see
+/// the caller's environment and fails.
+///
+/// # Memoization Built-ins
+///
+/// `memo f` returns `f` with its results cached by argument, and
+/// `memo_fix step` returns the function `g` with `g n = step g n`, cached the
+/// same way. Recursive calls inside a `rec` function go to the function
+/// itself rather than through `memo`'s wrapper, so `memo_fix` is the one that
+/// speeds up recursion: `memo_fix (fun fib -> fun n -> if n < 2 then n else
+/// fib (n - 1) + fib (n - 2))` runs in linear time. Caches are keyed by the
+/// Int argument. Applying a memoized function to any other value is an
+/// `EvalError`. Each cache keeps at most [`MEMO_CAPACITY`] results and evicts
+/// the least recently used one when full. Only memoize pure functions, since
+/// a cached call does not repeat the function's effects.
+///
+/// # Prelude Types
+///
+/// `type List a = Nil | Cons a (List a)` is declared in every prelude
+/// environment, so list literals (`[1, 2]`), `::`, and list patterns work
+/// without a `type` definition. A program may still declare its own `List`,
+/// which replaces the prelude's.
+use crate::ast::TypeAnnotation;
+use crate::eval::{apply, EvalError, Value};
+use crate::types::{RowVar, Type, TypeScheme, TypeVar};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// Native implementation of a built-in, called with all of its arguments
+pub type BuiltinFn = Rc<dyn Fn(&[Value]) -> Result<Value, EvalError>>;
+
+/// A built-in function value, possibly partially applied
+#[derive(Clone)]
+pub struct Builtin {
+    /// Name the built-in is bound to in the prelude
+    pub name: String,
+    /// Number of arguments needed before the implementation runs
+    pub arity: usize,
+    /// Arguments supplied so far (always fewer than `arity`)
+    pub args: Vec<Value>,
+    func: BuiltinFn,
+    /// Results cached by a function made with `memo` or `memo_fix`
+    cache: Option<Rc<RefCell<MemoCache>>>,
+}
+
+impl Builtin {
+    /// Create an unapplied built-in
+    pub fn new(name: impl Into<String>, arity: usize, func: BuiltinFn) -> Self {
+        Builtin { name: name.into(), arity, args: Vec::new(), func, cache: None }
+    }
+
+    /// Number of results cached, for functions made with `memo` or `memo_fix`
+    pub fn cache_len(&self) -> Option<usize> {
+        self.cache.as_ref().map(|cache| cache.borrow().entries.len())
+    }
+
+    /// Supply the next argument, running the implementation once all are present
+    ///
+    /// # Errors
+    ///
+    /// Returns the error reported by the implementation.
+    pub fn apply(mut self, arg: Value) -> Result<Value, EvalError> {
+        self.args.push(arg);
+        if self.args.len() >= self.arity {
+            (self.func)(&self.args)
+        } else {
+            Ok(Value::Builtin(self))
+        }
+    }
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builtin")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Built-ins are compared by name and applied arguments; memoized functions
+/// are only equal when they share a cache
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        let same_cache = match (&self.cache, &other.cache) {
+            (Some(a), Some(b)) => Rc::ptr_eq(a, b),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.name == other.name && self.args == other.args && same_cache
+    }
+}
+
+/// Most results a `memo` or `memo_fix` cache keeps
+pub const MEMO_CAPACITY: usize = 10_000;
+
+/// Results of a memoized function by Int argument, evicting the least recently used
+#[derive(Debug)]
+struct MemoCache {
+    capacity: usize,
+    /// Result and last use of each cached argument
+    entries: HashMap<i64, (Value, u64)>,
+    /// Cached arguments by last use, oldest first
+    recency: BTreeMap<u64, i64>,
+    clock: u64,
+}
+
+impl MemoCache {
+    fn new(capacity: usize) -> Self {
+        MemoCache { capacity, entries: HashMap::new(), recency: BTreeMap::new(), clock: 0 }
+    }
+
+    /// The cached result for `key`, marking it as just used
+    fn get(&mut self, key: i64) -> Option<Value> {
+        let (value, used) = self.entries.get_mut(&key)?;
+        self.recency.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.recency.insert(self.clock, key);
+        Some(value.clone())
+    }
+
+    /// Cache `value` for `key`, evicting the least recently used result if full
+    fn insert(&mut self, key: i64, value: Value) {
+        if let Some((_, used)) = self.entries.remove(&key) {
+            self.recency.remove(&used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.recency.insert(self.clock, key);
+        self.entries.insert(key, (value, self.clock));
+    }
+}
+
+/// Registry entry for a prelude built-in
+struct BuiltinDef {
+    name: &'static str,
+    arity: usize,
+    func: fn(&[Value]) -> Result<Value, EvalError>,
+    scheme: fn() -> TypeScheme,
+}
+
+/// All prelude built-ins
+const BUILTINS: &[BuiltinDef] = &[
+    BuiltinDef { name: "min", arity: 2, func: builtin_min, scheme: || binary_scheme(Type::Int) },
+    BuiltinDef { name: "max", arity: 2, func: builtin_max, scheme: || binary_scheme(Type::Int) },
+    BuiltinDef { name: "clamp", arity: 3, func: builtin_clamp, scheme: || clamp_scheme(Type::Int) },
+    BuiltinDef { name: "float_min", arity: 2, func: builtin_min, scheme: || binary_scheme(Type::Float) },
+    BuiltinDef { name: "float_max", arity: 2, func: builtin_max, scheme: || binary_scheme(Type::Float) },
+    BuiltinDef { name: "float_clamp", arity: 3, func: builtin_clamp, scheme: || clamp_scheme(Type::Float) },
+    BuiltinDef { name: "char_min", arity: 2, func: builtin_min, scheme: || binary_scheme(Type::Char) },
+    BuiltinDef { name: "char_max", arity: 2, func: builtin_max, scheme: || binary_scheme(Type::Char) },
+    BuiltinDef { name: "char_clamp", arity: 3, func: builtin_clamp, scheme: || clamp_scheme(Type::Char) },
+    BuiltinDef { name: "abs", arity: 1, func: builtin_abs, scheme: || unary_scheme(Type::Int) },
+    BuiltinDef { name: "float_abs", arity: 1, func: builtin_abs, scheme: || unary_scheme(Type::Float) },
+    BuiltinDef { name: "int_of_bool", arity: 1, func: builtin_bool_to_int, scheme: || conversion_scheme(Type::Bool, Type::Int) },
+    BuiltinDef { name: "bool_to_int", arity: 1, func: builtin_bool_to_int, scheme: || conversion_scheme(Type::Bool, Type::Int) },
+    BuiltinDef { name: "ord", arity: 1, func: builtin_ord, scheme: || conversion_scheme(Type::Char, Type::Int) },
+    BuiltinDef { name: "chr", arity: 1, func: builtin_chr, scheme: || conversion_scheme(Type::Int, Type::Char) },
+    BuiltinDef { name: "int_to_float", arity: 1, func: builtin_int_to_float, scheme: || conversion_scheme(Type::Int, Type::Float) },
+    BuiltinDef { name: "float_to_int", arity: 1, func: builtin_float_to_int, scheme: || conversion_scheme(Type::Float, Type::Int) },
+    BuiltinDef { name: "min_by", arity: 3, func: builtin_min_by, scheme: by_scheme },
+    BuiltinDef { name: "max_by", arity: 3, func: builtin_max_by, scheme: by_scheme },
+    BuiltinDef { name: "tuple_to_array", arity: 1, func: builtin_tuple_to_array, scheme: dynamic_scheme },
+    BuiltinDef { name: "array_to_tuple", arity: 1, func: builtin_array_to_tuple, scheme: dynamic_scheme },
+    BuiltinDef { name: "field_names", arity: 1, func: builtin_field_names, scheme: field_names_scheme },
+    BuiltinDef { name: "has_field", arity: 2, func: builtin_has_field, scheme: has_field_scheme },
+    BuiltinDef { name: "show", arity: 1, func: builtin_show, scheme: show_scheme },
+    BuiltinDef { name: "print", arity: 1, func: builtin_print, scheme: identity_scheme },
+    BuiltinDef { name: "approx_eq", arity: 3, func: builtin_approx_eq, scheme: approx_eq_scheme },
+    BuiltinDef { name: "memo", arity: 1, func: builtin_memo, scheme: memo_scheme },
+    BuiltinDef { name: "memo_fix", arity: 1, func: builtin_memo_fix, scheme: memo_fix_scheme },
+    BuiltinDef { name: DEBUG_ENV, arity: 1, func: builtin_debug_env, scheme: debug_env_scheme },
+];
+
+/// Name of the debug built-in returning the bindings in scope where it is called
+pub const DEBUG_ENV: &str = "debug_env";
+
+/// Largest tuple `array_to_tuple` will build
+const MAX_TUPLE_ARITY: usize = 8;
+
+/// All prelude built-ins as unapplied function values, in registration order
+pub fn prelude_values() -> Vec<(String, Value)> {
+    BUILTINS
+        .iter()
+        .map(|def| {
+            let builtin = Builtin::new(def.name, def.arity, Rc::new(def.func));
+            (def.name.to_string(), Value::Builtin(builtin))
+        })
+        .collect()
+}
+
+/// Type schemes of all prelude built-ins, in registration order
+pub fn prelude_schemes() -> Vec<(String, TypeScheme)> {
+    BUILTINS
+        .iter()
+        .map(|def| (def.name.to_string(), (def.scheme)()))
+        .collect()
+}
+
+/// A sum type of the prelude, as a `type` definition declares it: name,
+/// type parameters, and constructors with their payload types
+pub type PreludeType = (String, Vec<String>, Vec<(String, Vec<TypeAnnotation>)>);
+
+/// Sum types declared in every prelude environment: `List a`
+pub fn prelude_types() -> Vec<PreludeType> {
+    let a = || TypeAnnotation::Var("a".to_string());
+    vec![(
+        "List".to_string(),
+        vec!["a".to_string()],
+        vec![
+            ("Nil".to_string(), vec![]),
+            ("Cons".to_string(), vec![a(), TypeAnnotation::App("List".to_string(), vec![a()])]),
+        ],
+    )]
+}
+
+/// Build the curried function type `a1 -> a2 -> ... -> ret`
+fn fun_type(params: Vec<Type>, ret: Type) -> Type {
+    params
+        .into_iter()
+        .rev()
+        .fold(ret, |acc, param| Type::Fun(Box::new(param), Box::new(acc)))
+}
+
+fn monomorphic(ty: Type) -> TypeScheme {
+    TypeScheme { vars: vec![], row_vars: vec![], ty }
+}
+
+/// `t -> t`
+fn unary_scheme(ty: Type) -> TypeScheme {
+    monomorphic(fun_type(vec![ty.clone()], ty))
+}
+
+/// `t -> t -> t`
+fn binary_scheme(ty: Type) -> TypeScheme {
+    monomorphic(fun_type(vec![ty.clone(), ty.clone()], ty))
+}
+
+/// `t -> t -> t -> t` (lower bound, upper bound, value)
+fn clamp_scheme(ty: Type) -> TypeScheme {
+    monomorphic(fun_type(vec![ty.clone(), ty.clone(), ty.clone()], ty))
+}
+
+/// `∀a. (a -> a -> Bool) -> a -> a -> a`
+fn by_scheme() -> TypeScheme {
+    let a = Type::Var(TypeVar(0));
+    let less_than = fun_type(vec![a.clone(), a.clone()], Type::Bool);
+    TypeScheme {
+        vars: vec![TypeVar(0)],
+        row_vars: vec![],
+        ty: fun_type(vec![less_than, a.clone(), a.clone()], a),
+    }
+}
+
+/// `∀a b. a -> b`, for built-ins whose argument and result are checked at runtime
+fn dynamic_scheme() -> TypeScheme {
+    TypeScheme {
+        vars: vec![TypeVar(0), TypeVar(1)],
+        row_vars: vec![],
+        ty: fun_type(vec![Type::Var(TypeVar(0))], Type::Var(TypeVar(1))),
+    }
+}
+
+/// `{..r}`: any record
+fn any_record() -> Type {
+    Type::RecordRow(HashMap::new(), RowVar(0))
+}
+
+/// `List Char`, the type of string literals
+pub(crate) fn string_type() -> Type {
+    Type::SumType("List".to_string(), vec![Type::Char])
+}
+
+/// `∀r. {..r} -> Array[List Char, 0]`
+fn field_names_scheme() -> TypeScheme {
+    TypeScheme {
+        vars: vec![],
+        row_vars: vec![RowVar(0)],
+        ty: fun_type(vec![any_record()], Type::Array(Box::new(string_type()), 0)),
+    }
+}
+
+/// `∀r. {..r} -> List Char -> Bool`
+fn has_field_scheme() -> TypeScheme {
+    TypeScheme {
+        vars: vec![],
+        row_vars: vec![RowVar(0)],
+        ty: fun_type(vec![any_record(), string_type()], Type::Bool),
+    }
+}
+
+/// `∀a. a -> List Char`
+fn show_scheme() -> TypeScheme {
+    TypeScheme {
+        vars: vec![TypeVar(0)],
+        row_vars: vec![],
+        ty: fun_type(vec![Type::Var(TypeVar(0))], string_type()),
+    }
+}
+
+/// `∀a. a -> a`
+fn identity_scheme() -> TypeScheme {
+    let a = Type::Var(TypeVar(0));
+    TypeScheme { vars: vec![TypeVar(0)], row_vars: vec![], ty: fun_type(vec![a.clone()], a) }
+}
+
+/// `from -> to`
+fn conversion_scheme(from: Type, to: Type) -> TypeScheme {
+    monomorphic(fun_type(vec![from], to))
+}
+
+/// `∀a. Unit -> a`, as the record's fields depend on where it is called
+fn debug_env_scheme() -> TypeScheme {
+    TypeScheme { vars: vec![TypeVar(0)], row_vars: vec![], ty: fun_type(vec![Type::Unit], Type::Var(TypeVar(0))) }
+}
+
+/// `Float -> Float -> Float -> Bool`
+fn approx_eq_scheme() -> TypeScheme {
+    monomorphic(fun_type(vec![Type::Float, Type::Float, Type::Float], Type::Bool))
+}
+
+/// `∀a. (Int -> a) -> Int -> a`
+fn memo_scheme() -> TypeScheme {
+    let f = fun_type(vec![Type::Int], Type::Var(TypeVar(0)));
+    TypeScheme { vars: vec![TypeVar(0)], row_vars: vec![], ty: fun_type(vec![f.clone()], f) }
+}
+
+/// `∀a. ((Int -> a) -> Int -> a) -> Int -> a`
+fn memo_fix_scheme() -> TypeScheme {
+    let f = fun_type(vec![Type::Int], Type::Var(TypeVar(0)));
+    TypeScheme {
+        vars: vec![TypeVar(0)],
+        row_vars: vec![],
+        ty: fun_type(vec![fun_type(vec![f.clone()], f.clone())], f),
+    }
+}
+
+/// Compare two values of the same comparable kind (Int, Float, or Char)
+fn compare(name: &str, a: &Value, b: &Value) -> Result<Ordering, EvalError> {
+    let ordering = match (a, b) {
+        (Value::Int(x), Value::Int(y)) => Some(x.cmp(y)),
+        (Value::Char(x), Value::Char(y)) => Some(x.cmp(y)),
+        (Value::Float(x), Value::Float(y)) => x.partial_cmp(y),
+        _ => None,
+    };
+    ordering.ok_or_else(|| {
+        EvalError::TypeError(format!(
+            "{name}: cannot compare {a} and {b} (expected two Ints, Floats, or Chars)"
+        ))
+    })
+}
+
+fn builtin_min(args: &[Value]) -> Result<Value, EvalError> {
+    let (a, b) = (&args[0], &args[1]);
+    match compare("min", a, b)? {
+        Ordering::Greater => Ok(b.clone()),
+        _ => Ok(a.clone()),
+    }
+}
+
+fn builtin_max(args: &[Value]) -> Result<Value, EvalError> {
+    let (a, b) = (&args[0], &args[1]);
+    match compare("max", a, b)? {
+        Ordering::Less => Ok(b.clone()),
+        _ => Ok(a.clone()),
+    }
+}
+
+/// Absolute value of an Int or a Float
+fn builtin_abs(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        Value::Int(n) => n
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or_else(|| EvalError::TypeError("Integer overflow in abs".to_string())),
+        Value::Float(x) => Ok(Value::Float(x.abs())),
+        other => Err(EvalError::TypeError(format!("abs: expected an Int or a Float, got {other}"))),
+    }
+}
+
+/// `bool_to_int b` is 1 for `true` and 0 for `false`
+fn builtin_bool_to_int(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        Value::Bool(b) => Ok(Value::Int(i64::from(*b))),
+        other => Err(EvalError::TypeError(format!("bool_to_int: expected a Bool, got {other}"))),
+    }
+}
+
+/// The Unicode code point of a Char
+fn builtin_ord(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        Value::Char(c) => Ok(Value::Int(i64::from(u32::from(*c)))),
+        other => Err(EvalError::TypeError(format!("ord: expected a Char, got {other}"))),
+    }
+}
+
+/// The Char with the given Unicode code point
+fn builtin_chr(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        Value::Int(n) => u32::try_from(*n)
+            .ok()
+            .and_then(char::from_u32)
+            .map(Value::Char)
+            .ok_or_else(|| EvalError::TypeError(format!("chr: {n} is not a valid code point"))),
+        other => Err(EvalError::TypeError(format!("chr: expected an Int, got {other}"))),
+    }
+}
+
+/// The Float nearest to an Int
+#[allow(clippy::cast_precision_loss)]
+fn builtin_int_to_float(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        Value::Int(n) => Ok(Value::Float(*n as f64)),
+        other => Err(EvalError::TypeError(format!("int_to_float: expected an Int, got {other}"))),
+    }
+}
+
+/// A Float truncated toward zero, if the result is a representable Int
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn builtin_float_to_int(args: &[Value]) -> Result<Value, EvalError> {
+    match &args[0] {
+        // -(i64::MIN as f64) is exactly 2^63, the smallest Float above the range
+        Value::Float(x) if x.is_finite() && *x >= i64::MIN as f64 && *x < -(i64::MIN as f64) => {
+            Ok(Value::Int(x.trunc() as i64))
+        }
+        Value::Float(x) => Err(EvalError::TypeError(format!("float_to_int: {x} is out of the Int range"))),
+        other => Err(EvalError::TypeError(format!("float_to_int: expected a Float, got {other}"))),
+    }
+}
+
+/// `clamp lo hi x` restricts `x` to the inclusive range `lo..hi`
+fn builtin_clamp(args: &[Value]) -> Result<Value, EvalError> {
+    let (lo, hi, x) = (&args[0], &args[1], &args[2]);
+    if compare("clamp", lo, hi)? == Ordering::Greater {
+        return Err(EvalError::TypeError(format!(
+            "clamp: lower bound {lo} is greater than upper bound {hi}"
+        )));
+    }
+    if compare("clamp", x, lo)? == Ordering::Less {
+        Ok(lo.clone())
+    } else if compare("clamp", x, hi)? == Ordering::Greater {
+        Ok(hi.clone())
+    } else {
+        Ok(x.clone())
+    }
+}
+
+/// Call a user-supplied less-than predicate
+fn less_than(name: &str, lt: &Value, a: &Value, b: &Value) -> Result<bool, EvalError> {
+    let partial = apply(lt.clone(), a.clone())?;
+    match apply(partial, b.clone())? {
+        Value::Bool(result) => Ok(result),
+        other => Err(EvalError::TypeError(format!(
+            "{name}: comparison function must return a Bool, got {other}"
+        ))),
+    }
+}
+
+/// `min_by lt a b` returns `b` if `lt b a`, otherwise `a`
+fn builtin_min_by(args: &[Value]) -> Result<Value, EvalError> {
+    let (lt, a, b) = (&args[0], &args[1], &args[2]);
+    if less_than("min_by", lt, b, a)? {
+        Ok(b.clone())
+    } else {
+        Ok(a.clone())
+    }
+}
+
+/// `max_by lt a b` returns `b` if `lt a b`, otherwise `a`
+fn builtin_max_by(args: &[Value]) -> Result<Value, EvalError> {
+    let (lt, a, b) = (&args[0], &args[1], &args[2]);
+    if less_than("max_by", lt, a, b)? {
+        Ok(b.clone())
+    } else {
+        Ok(a.clone())
+    }
+}
+
+/// `approx_eq a b epsilon`: `|a - b| <= epsilon`, never for NaN, and
+/// infinities only equal themselves
+fn builtin_approx_eq(args: &[Value]) -> Result<Value, EvalError> {
+    let (Value::Float(a), Value::Float(b), Value::Float(epsilon)) = (&args[0], &args[1], &args[2]) else {
+        return Err(EvalError::TypeError(format!(
+            "approx_eq: expected three Floats, got {}, {} and {}",
+            args[0], args[1], args[2]
+        )));
+    };
+    if *epsilon < 0.0 {
+        return Err(EvalError::TypeError(format!("approx_eq: tolerance {epsilon} is negative")));
+    }
+    let equal = if a.is_infinite() || b.is_infinite() {
+        a == b
+    } else {
+        (a - b).abs() <= *epsilon
+    };
+    Ok(Value::Bool(equal))
+}
+
+/// Build a string (`List Char`) value
+pub(crate) fn string_value(s: &str) -> Value {
+    s.chars().rev().fold(Value::Variant("Nil".to_string(), vec![]), |rest, c| {
+        Value::Variant("Cons".to_string(), vec![Value::Char(c), rest])
+    })
+}
+
+/// Read a string (`List Char`) value, or `None` if the value is not a string
+fn string_from_value(value: &Value) -> Option<String> {
+    let mut result = String::new();
+    let mut current = value;
+    loop {
+        match current {
+            Value::Variant(name, args) if name == "Nil" && args.is_empty() => return Some(result),
+            Value::Variant(name, args) if name == "Cons" && args.len() == 2 => {
+                let Value::Char(c) = args[0] else { return None };
+                result.push(c);
+                current = &args[1];
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// `tuple_to_array (1, 2, 3)` is `[|1, 2, 3|]`; all elements must have the same kind
+fn builtin_tuple_to_array(args: &[Value]) -> Result<Value, EvalError> {
+    if args[0] == Value::Unit {
+        return Ok(Value::Array(0, vec![]));
+    }
+    let Value::Tuple(elements) = &args[0] else {
+        return Err(EvalError::TypeError(format!(
+            "tuple_to_array: expected a tuple, got {} {}",
+            args[0].kind_name(),
+            args[0]
+        )));
+    };
+    if let Some(first) = elements.first() {
+        if let Some((index, other)) = elements
+            .iter()
+            .enumerate()
+            .find(|(_, element)| element.kind_name() != first.kind_name())
+        {
+            return Err(EvalError::TypeError(format!(
+                "tuple_to_array: elements must all have the same kind, but element 0 is {} and element {index} is {}",
+                first.kind_name(),
+                other.kind_name()
+            )));
+        }
+    }
+    Ok(Value::Array(elements.len(), elements.clone()))
+}
+
+/// `array_to_tuple [|1, 2|]` is `(1, 2)`; the array must have 2 to `MAX_TUPLE_ARITY` elements
+fn builtin_array_to_tuple(args: &[Value]) -> Result<Value, EvalError> {
+    let Value::Array(_, elements) = &args[0] else {
+        return Err(EvalError::TypeError(format!(
+            "array_to_tuple: expected an array, got {} {}",
+            args[0].kind_name(),
+            args[0]
+        )));
+    };
+    if !(2..=MAX_TUPLE_ARITY).contains(&elements.len()) {
+        return Err(EvalError::TypeError(format!(
+            "array_to_tuple: array has {} elements, but tuples built from arrays must have 2 to {MAX_TUPLE_ARITY}",
+            elements.len()
+        )));
+    }
+    Ok(Value::Tuple(elements.clone()))
+}
+
+/// `field_names { y: 1, x: 2 }` is `[|"x", "y"|]`, sorted alphabetically
+fn builtin_field_names(args: &[Value]) -> Result<Value, EvalError> {
+    let Value::Record(fields) = &args[0] else {
+        return Err(EvalError::RecordExpected(args[0].to_string()));
+    };
+    let mut names: Vec<&String> = fields.keys().collect();
+    names.sort();
+    let names: Vec<Value> = names.into_iter().map(|name| string_value(name)).collect();
+    Ok(Value::Array(names.len(), names))
+}
+
+/// `has_field r "x"` checks whether record `r` has a field named `x`
+fn builtin_has_field(args: &[Value]) -> Result<Value, EvalError> {
+    let Value::Record(fields) = &args[0] else {
+        return Err(EvalError::RecordExpected(args[0].to_string()));
+    };
+    let name = string_from_value(&args[1]).ok_or_else(|| {
+        EvalError::TypeError(format!("has_field: expected a field name string, got {}", args[1]))
+    })?;
+    Ok(Value::Bool(fields.contains_key(&name)))
+}
+
+/// `show v` renders `v` as a string; strings and characters are kept as text
+#[allow(clippy::unnecessary_wraps)] // every built-in has the same signature
+fn builtin_show(args: &[Value]) -> Result<Value, EvalError> {
+    Ok(string_value(&show_text(&args[0])))
+}
+
+/// The text `show` renders a value as
+fn show_text(value: &Value) -> String {
+    match value {
+        Value::Char(c) => c.to_string(),
+        value => string_from_value(value).unwrap_or_else(|| value.to_string()),
+    }
+}
+
+/// `print x` writes `show x` and a newline to standard output and returns `x`
+#[allow(clippy::unnecessary_wraps)] // every built-in has the same signature
+fn builtin_print(args: &[Value]) -> Result<Value, EvalError> {
+    println!("{}", show_text(&args[0]));
+    Ok(args[0].clone())
+}
+
+/// `memo f`: `f` with its results cached by argument
+fn builtin_memo(args: &[Value]) -> Result<Value, EvalError> {
+    let f = expect_function("memo", &args[0])?;
+    Ok(memoized("memo", f, false, Rc::new(RefCell::new(MemoCache::new(MEMO_CAPACITY)))))
+}
+
+/// `memo_fix step`: the function `g` with `g n = step g n`, cached by argument
+fn builtin_memo_fix(args: &[Value]) -> Result<Value, EvalError> {
+    let step = expect_function("memo_fix", &args[0])?;
+    Ok(memoized("memo_fix", step, true, Rc::new(RefCell::new(MemoCache::new(MEMO_CAPACITY)))))
+}
+
+/// `debug_env ()` when debug built-ins are off
+///
+/// With debug built-ins on, the evaluator answers calls of `debug_env`
+/// itself, since only it knows the environment at the call.
+fn builtin_debug_env(_args: &[Value]) -> Result<Value, EvalError> {
+    Err(EvalError::TypeError(format!(
+        "{DEBUG_ENV} is disabled; enable debug built-ins with --debug-builtins (or :set debug on in the REPL)"
+    )))
+}
+
+fn expect_function(name: &str, value: &Value) -> Result<Value, EvalError> {
+    match value {
+        Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_) | Value::Partial(..) | Value::Builtin(_) => {
+            Ok(value.clone())
+        }
+        other => Err(EvalError::TypeError(format!("{name}: expected a function, got {other}"))),
+    }
+}
+
+/// A one-argument built-in that looks its Int argument up in `cache` before
+/// calling `func`; with `fix`, `func` also receives the memoized function
+fn memoized(name: &'static str, func: Value, fix: bool, cache: Rc<RefCell<MemoCache>>) -> Value {
+    let shared = Rc::clone(&cache);
+    let call = move |args: &[Value]| {
+        let Value::Int(key) = args[0] else {
+            return Err(EvalError::TypeError(format!(
+                "{name}: memoized functions take an Int argument, got {}",
+                args[0]
+            )));
+        };
+        // Release the cache before calling out, as the call may recurse into it
+        let cached = shared.borrow_mut().get(key);
+        if let Some(value) = cached {
+            return Ok(value);
+        }
+        let result = if fix {
+            let this = memoized(name, func.clone(), true, Rc::clone(&shared));
+            apply(apply(func.clone(), this)?, args[0].clone())?
+        } else {
+            apply(func.clone(), args[0].clone())?
+        };
+        shared.borrow_mut().insert(key, result.clone());
+        Ok(result)
+    };
+    let mut builtin = Builtin::new(name, 1, Rc::new(call));
+    builtin.cache = Some(cache);
+    Value::Builtin(builtin)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin(name: &str) -> Builtin {
+        match prelude_values().into_iter().find(|(n, _)| n == name) {
+            Some((_, Value::Builtin(b))) => b,
+            _ => panic!("no builtin {name}"),
+        }
+    }
+
+    #[test]
+    fn test_partial_application_collects_args() {
+        let partial = builtin("max").apply(Value::Int(3)).unwrap();
+        match &partial {
+            Value::Builtin(b) => assert_eq!(b.args, vec![Value::Int(3)]),
+            _ => panic!("Expected partially applied builtin"),
+        }
+        let Value::Builtin(b) = partial else { unreachable!() };
+        assert_eq!(b.apply(Value::Int(5)), Ok(Value::Int(5)));
+    }
+
+    #[test]
+    fn test_equality_by_name_and_args() {
+        assert_eq!(builtin("min"), builtin("min"));
+        assert_ne!(builtin("min"), builtin("max"));
+        let applied = builtin("min").apply(Value::Int(1)).unwrap();
+        assert_ne!(applied, Value::Builtin(builtin("min")));
+    }
+
+    #[test]
+    fn test_values_and_schemes_agree() {
+        let values: Vec<String> = prelude_values().into_iter().map(|(n, _)| n).collect();
+        let schemes: Vec<String> = prelude_schemes().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(values, schemes);
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        assert_eq!(string_from_value(&string_value("héllo")), Some("héllo".to_string()));
+        assert_eq!(string_from_value(&string_value("")), Some(String::new()));
+        assert_eq!(string_from_value(&Value::Int(1)), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Value::Builtin(builtin("clamp")).to_string(), "<builtin clamp>");
+    }
+
+    #[test]
+    fn test_memo_cache_evicts_least_recently_used() {
+        let mut cache = MemoCache::new(2);
+        cache.insert(1, Value::Int(10));
+        cache.insert(2, Value::Int(20));
+        assert_eq!(cache.get(1), Some(Value::Int(10)));
+        cache.insert(3, Value::Int(30));
+        assert_eq!(cache.get(2), None);
+        assert_eq!((cache.get(1), cache.get(3)), (Some(Value::Int(10)), Some(Value::Int(30))));
+        cache.insert(3, Value::Int(31));
+        assert_eq!((cache.entries.len(), cache.recency.len()), (2, 2));
+        assert_eq!(cache.get(3), Some(Value::Int(31)));
+    }
+}
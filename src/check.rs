@@ -0,0 +1,281 @@
+//! Health check for example and library files
+//!
+//! [`check_examples`] walks a directory for `.par` files and runs each one
+//! through every stage a user would: parsing, type checking, and evaluation
+//! under a step limit, so a file that loops forever fails instead of hanging
+//! the check. The per-file [`FileCheck`] results are what the
+//! `parlang check-examples` command prints with [`render_table`].
+//!
+//! Files the type checker cannot handle yet can opt out of that stage with a
+//! first line of exactly `-- no-typecheck`. Type errors in such files are still
+//! reported but do not fail the check. The marker line is removed before the
+//! file is parsed, since the language has no comment syntax yet.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use parlang::check::{check_examples, render_table, CheckOptions};
+//!
+//! let results = check_examples("examples".as_ref(), CheckOptions::default()).unwrap();
+//! print!("{}", render_table(&results));
+//! let all_passed = results.iter().all(|file| file.passed());
+//! ```
+
+use crate::eval::{eval, Environment};
+use crate::load_path::LoadPath;
+use crate::parser::{parse, ParseErrorInfo};
+use crate::typechecker::{typecheck_in, TypeEnv};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// First line that exempts a file from the type checking stage
+pub const NO_TYPECHECK_MARKER: &str = "-- no-typecheck";
+
+/// Evaluation steps allowed per file unless configured otherwise
+pub const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+/// Stack for each file's check, so deep but finite recursion in an example
+/// runs out of steps rather than overflowing the stack
+const CHECK_STACK_SIZE: usize = 512 * 1024 * 1024;
+
+/// Widest a cell of the summary table may be before it is shortened
+const MAX_CELL_WIDTH: usize = 48;
+
+/// Settings for [`check_examples`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckOptions {
+    /// Evaluation steps allowed per file (see `Environment::set_step_limit`)
+    pub step_limit: u64,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions { step_limit: DEFAULT_STEP_LIMIT }
+    }
+}
+
+/// What happened to one file at each stage
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileCheck {
+    /// Path of the file, relative to the checked directory
+    pub path: PathBuf,
+    /// Whether the file starts with the `-- no-typecheck` marker
+    pub no_typecheck: bool,
+    /// The parse error, if parsing failed
+    pub parse: Result<(), ParseErrorInfo>,
+    /// The inferred type or type error; `None` when parsing failed
+    pub typecheck: Option<Result<String, String>>,
+    /// The value or evaluation error; `None` when parsing failed
+    pub eval: Option<Result<String, String>>,
+}
+
+impl FileCheck {
+    /// Whether every stage the file is not exempted from succeeded
+    pub fn passed(&self) -> bool {
+        self.parse.is_ok()
+            && (self.no_typecheck || matches!(self.typecheck, Some(Ok(_))))
+            && matches!(self.eval, Some(Ok(_)))
+    }
+}
+
+/// Parse, type check, and evaluate every `.par` file under `dir`
+///
+/// Files are visited recursively in path order. Each file's `load`s are
+/// looked up the way they are for `parlang <file>`: next to the file, then in
+/// the `PARLANG_PATH` directories, then in the current directory.
+///
+/// # Errors
+///
+/// Returns a message when the directory or one of its files cannot be read.
+pub fn check_examples(dir: &Path, options: CheckOptions) -> Result<Vec<FileCheck>, String> {
+    let mut files = Vec::new();
+    collect_par_files(dir, &mut files)?;
+    files.sort();
+    files
+        .iter()
+        .map(|path| {
+            let source =
+                fs::read_to_string(path).map_err(|e| format!("Failed to read file '{}': {e}", path.display()))?;
+            let relative = path.strip_prefix(dir).unwrap_or(path).to_path_buf();
+            let load_path = LoadPath::from_env().for_file(path);
+            thread::Builder::new()
+                .stack_size(CHECK_STACK_SIZE)
+                .spawn(move || check_source_from(relative, &source, options, load_path))
+                .and_then(|handle| handle.join().map_err(|_| io::Error::other("check panicked")))
+                .map_err(|e| format!("Failed to check file '{}': {e}", path.display()))
+        })
+        .collect()
+}
+
+/// Run the stages on the contents of one file, loading from the current directory
+pub fn check_source(path: PathBuf, source: &str, options: CheckOptions) -> FileCheck {
+    check_source_from(path, source, options, LoadPath::default())
+}
+
+/// Run the stages on the contents of one file, loading through `load_path`
+fn check_source_from(path: PathBuf, source: &str, options: CheckOptions, load_path: LoadPath) -> FileCheck {
+    let first_line = source.lines().next().unwrap_or_default();
+    let no_typecheck = first_line.trim() == NO_TYPECHECK_MARKER;
+    // Keep the marker's line break so positions in errors stay the same
+    let source = if no_typecheck { &source[first_line.len()..] } else { source };
+
+    let expr = match parse(source) {
+        Ok(expr) => expr,
+        Err(e) => return FileCheck { path, no_typecheck, parse: Err(e), typecheck: None, eval: None },
+    };
+    let mut type_env = TypeEnv::with_builtins();
+    type_env.set_load_path(load_path.clone());
+    let typecheck = typecheck_in(&expr, &mut type_env).map(|ty| ty.to_string()).map_err(|e| e.to_string());
+    let mut env = Environment::with_builtins();
+    env.set_step_limit(Some(options.step_limit));
+    env.set_load_path(load_path);
+    // A table cell has room for the error itself, not the trace leading to it
+    let eval = eval(&expr, &env).map(|value| value.to_string()).map_err(|e| e.root().to_string());
+    FileCheck { path, no_typecheck, parse: Ok(()), typecheck: Some(typecheck), eval: Some(eval) }
+}
+
+/// Add the `.par` files under `dir` to `files`
+fn collect_par_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to read directory '{}': {e}", dir.display()))?;
+    for entry in entries {
+        let path = entry.map_err(|e| format!("Failed to read directory '{}': {e}", dir.display()))?.path();
+        if path.is_dir() {
+            collect_par_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "par") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Summary table with one row per file and a closing pass count
+pub fn render_table(results: &[FileCheck]) -> String {
+    let mut rows = vec![["file".to_string(), "parse".to_string(), "type".to_string(), "eval".to_string()]];
+    for file in results {
+        let parse = match &file.parse {
+            Ok(()) => "ok".to_string(),
+            Err(e) => format!("error: line {}, column {}: {}", e.line, e.column, e.description()),
+        };
+        let typecheck = match &file.typecheck {
+            None => "-".to_string(),
+            Some(Ok(ty)) => ty.clone(),
+            Some(Err(e)) if file.no_typecheck => format!("skipped: {e}"),
+            Some(Err(e)) => format!("error: {e}"),
+        };
+        let eval = match &file.eval {
+            None => "-".to_string(),
+            Some(Ok(value)) => value.clone(),
+            Some(Err(e)) => format!("error: {e}"),
+        };
+        rows.push([file.path.display().to_string(), parse, typecheck, eval].map(|cell| shorten(&cell)));
+    }
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let line = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+    let passed = results.iter().filter(|file| file.passed()).count();
+    let _ = writeln!(out, "{passed} of {} files passed", results.len());
+    out
+}
+
+/// First line of a cell, cut to `MAX_CELL_WIDTH` characters
+fn shorten(cell: &str) -> String {
+    let line = cell.lines().next().unwrap_or_default();
+    if line.chars().count() > MAX_CELL_WIDTH {
+        let cut: String = line.chars().take(MAX_CELL_WIDTH - 3).collect();
+        format!("{cut}...")
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn write_examples(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("parlang_check_{}_{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_per_file_statuses() {
+        let dir = write_examples(
+            "statuses",
+            &[
+                ("good.par", "let double = fun x -> x * 2;\ndouble 21\n"),
+                ("nested/ill_typed.par", "if true then 1 else false\n"),
+                ("loop.par", "(rec loop -> fun n -> loop (n + 1)) 0\n"),
+                ("notes.txt", "not a ParLang file"),
+            ],
+        );
+        let results = check_examples(&dir, CheckOptions { step_limit: 10_000 }).unwrap();
+        let paths: Vec<_> = results.iter().map(|file| file.path.clone()).collect();
+        assert_eq!(paths, [PathBuf::from("good.par"), PathBuf::from("loop.par"), Path::new("nested").join("ill_typed.par")]);
+
+        let [good, looping, ill_typed] = &results[..] else { unreachable!() };
+        assert_eq!(good.typecheck, Some(Ok("Int".to_string())));
+        assert_eq!(good.eval, Some(Ok("42".to_string())));
+        assert!(good.passed());
+
+        assert!(matches!(&looping.eval, Some(Err(e)) if e.contains("Step limit exceeded")), "{looping:?}");
+        assert!(!looping.passed());
+
+        assert!(matches!(ill_typed.typecheck, Some(Err(_))));
+        assert_eq!(ill_typed.eval, Some(Ok("1".to_string())));
+        assert!(!ill_typed.passed());
+
+        let table = render_table(&results);
+        assert!(table.starts_with("file"), "{table}");
+        assert!(table.ends_with("1 of 3 files passed\n"), "{table}");
+    }
+
+    #[test]
+    fn test_no_typecheck_marker_and_parse_errors() {
+        let exempt = check_source(
+            PathBuf::from("exempt.par"),
+            "-- no-typecheck\nif true then 1 else false\n",
+            CheckOptions::default(),
+        );
+        assert!(exempt.no_typecheck);
+        assert!(matches!(exempt.typecheck, Some(Err(_))));
+        assert!(exempt.passed());
+        assert!(render_table(&[exempt]).contains("skipped: "));
+
+        let broken = check_source(PathBuf::from("broken.par"), "let x = in", CheckOptions::default());
+        assert!(broken.parse.is_err());
+        assert_eq!((broken.typecheck, broken.eval), (None, None));
+    }
+
+    #[test]
+    fn test_repository_examples_pass() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let results = check_examples(&examples, CheckOptions::default()).unwrap();
+        assert!(!results.is_empty());
+        let failed: Vec<_> = results.iter().filter(|file| !file.passed()).collect();
+        assert!(failed.is_empty(), "{}", render_table(&results));
+    }
+}
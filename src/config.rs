@@ -0,0 +1,580 @@
+//! Project configuration read from `parlang.toml`
+//!
+//! Instead of repeating command-line flags, a project can put them in a
+//! `parlang.toml` next to its sources. [`Config::discover`] looks for the file
+//! in the directory of the input file and then in each parent directory; the
+//! nearest file wins and files further up are not read. Flags given on the
+//! command line always take precedence over the file ([`Config::merge`]).
+//!
+//! The file is a small subset of TOML: `key = value` lines with booleans,
+//! non-negative integers, strings, and single-line arrays of strings, plus
+//! `#` comments. Keys mirror the command-line flags:
+//!
+//! ```toml
+//! float_precision = 6        # --float-precision
+//! optimize = true            # --optimize
+//! typecheck = true           # --typecheck
+//! strict_matches = true      # --strict-matches
+//! deny_warnings = true       # --deny-warnings
+//! prune_unused = true        # --prune-unused
+//! verbose_load = false       # --verbose-load
+//! dump_limit = 500           # --dump-limit
+//! max_steps = 1000000        # --max-steps (and check-examples --step-limit)
+//! max_depth = 10000          # --max-depth
+//! prelude = true             # --no-prelude
+//! warnings = ["float-equality"]  # --warn
+//! include_paths = ["lib"]    # --include-dir
+//! ```
+//!
+//! Relative `include_paths` are resolved against the directory of the file.
+//!
+//! Unknown keys, sections, and warning names are reported through
+//! [`Config::unknown`] rather than rejected, so a file written for a newer
+//! version still works. Malformed lines and values of the wrong type are
+//! errors. Reading files is behind the `config` feature (on by default).
+
+use crate::lint;
+use std::fmt;
+#[cfg(feature = "config")]
+use std::fs;
+#[cfg(feature = "config")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Name of the configuration file searched for by [`Config::discover`]
+pub const CONFIG_FILE_NAME: &str = "parlang.toml";
+
+/// Effective settings for running a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+// The switches mirror independent command-line flags
+#[allow(clippy::struct_excessive_bools)]
+pub struct Config {
+    /// Significant digits used when printing floats (`None` = exact)
+    pub float_precision: Option<usize>,
+    /// Apply source-level optimizations before evaluating
+    pub optimize: bool,
+    /// Type check programs before evaluating them
+    pub typecheck: bool,
+    /// Stop before evaluating when a `match` is non-exhaustive or has unreachable arms
+    pub strict_matches: bool,
+    /// Stop before evaluating when there are any warnings: lints and match problems
+    pub deny_warnings: bool,
+    /// Skip library bindings the program never references
+    pub prune_unused: bool,
+    /// Report library bindings skipped by `prune_unused`
+    pub verbose_load: bool,
+    /// Maximum number of AST nodes written with `--dump-ast` (`None` = unlimited)
+    pub dump_limit: Option<usize>,
+    /// Evaluation steps allowed before giving up (`None` = unlimited)
+    pub max_steps: Option<u64>,
+    /// Function calls that may be in progress at once before giving up (`None` = unlimited)
+    pub max_depth: Option<usize>,
+    /// Whether the built-in functions are bound
+    pub prelude: bool,
+    /// Lint rules whose warnings are reported, by name
+    pub warnings: Vec<String>,
+    /// Directories `load` searches for libraries, before those in `PARLANG_PATH`
+    pub include_paths: Vec<PathBuf>,
+    /// File the settings were read from, if any
+    pub path: Option<PathBuf>,
+    /// Messages about unknown keys, sections, and warning names in the file
+    pub unknown: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            float_precision: None,
+            optimize: false,
+            typecheck: false,
+            strict_matches: false,
+            deny_warnings: false,
+            prune_unused: false,
+            verbose_load: false,
+            dump_limit: None,
+            max_steps: None,
+            max_depth: None,
+            prelude: true,
+            warnings: Vec::new(),
+            include_paths: Vec::new(),
+            path: None,
+            unknown: Vec::new(),
+        }
+    }
+}
+
+/// Settings given on the command line; `None` leaves the file's value in place
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigOverrides {
+    pub float_precision: Option<usize>,
+    pub optimize: Option<bool>,
+    pub typecheck: Option<bool>,
+    pub strict_matches: Option<bool>,
+    pub deny_warnings: Option<bool>,
+    pub prune_unused: Option<bool>,
+    pub verbose_load: Option<bool>,
+    pub dump_limit: Option<usize>,
+    pub max_steps: Option<u64>,
+    pub max_depth: Option<usize>,
+    pub prelude: Option<bool>,
+    pub warnings: Option<Vec<String>>,
+    pub include_paths: Option<Vec<PathBuf>>,
+}
+
+impl Config {
+    /// Find and read the nearest `parlang.toml` in `start_dir` or its parents
+    ///
+    /// Returns the default configuration when there is no such file.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the nearest file cannot be read or is malformed.
+    #[cfg(feature = "config")]
+    pub fn discover(start_dir: &Path) -> Result<Config, String> {
+        let start = start_dir.canonicalize().unwrap_or_else(|_| start_dir.to_path_buf());
+        match start.ancestors().map(|dir| dir.join(CONFIG_FILE_NAME)).find(|path| path.is_file()) {
+            Some(path) => Config::load(&path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Read the configuration file at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the file cannot be read or is malformed.
+    #[cfg(feature = "config")]
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {e}", path.display()))?;
+        let mut config = Config::parse(&source).map_err(|e| format!("{}:{e}", path.display()))?;
+        for message in &mut config.unknown {
+            *message = format!("{}:{message}", path.display());
+        }
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for include in &mut config.include_paths {
+            *include = dir.join(&*include);
+        }
+        config.path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Read settings from the contents of a configuration file
+    ///
+    /// Messages in errors and in [`unknown`](Self::unknown) start with the
+    /// 1-based line number, e.g. `3: unknown key 'colour'`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message for a malformed line or a value of the wrong type.
+    pub fn parse(source: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut in_section = false;
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                config.unknown.push(format!("{number}: unknown section [{}]; its keys are ignored", section.trim()));
+                in_section = true;
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("{number}: expected `key = value`, found '{line}'"))?;
+            let (key, value) = (key.trim(), parse_value(value.trim()).map_err(|e| format!("{number}: {e}"))?);
+            if !in_section {
+                config.set(key, value, number).map_err(|e| format!("{number}: {e}"))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Apply one `key = value` line
+    fn set(&mut self, key: &str, value: TomlValue, number: usize) -> Result<(), String> {
+        match key {
+            "float_precision" => match value.integer(key)? {
+                0 => return Err("float_precision must be at least 1".to_string()),
+                digits => self.float_precision = Some(to_usize(digits)),
+            },
+            "optimize" => self.optimize = value.boolean(key)?,
+            "typecheck" => self.typecheck = value.boolean(key)?,
+            "strict_matches" => self.strict_matches = value.boolean(key)?,
+            "deny_warnings" => self.deny_warnings = value.boolean(key)?,
+            "prune_unused" => self.prune_unused = value.boolean(key)?,
+            "verbose_load" => self.verbose_load = value.boolean(key)?,
+            "dump_limit" => self.dump_limit = Some(to_usize(value.integer(key)?)),
+            "max_steps" => self.max_steps = Some(value.integer(key)?),
+            "max_depth" => self.max_depth = Some(to_usize(value.integer(key)?)),
+            "prelude" => self.prelude = value.boolean(key)?,
+            "warnings" => {
+                let rules = value.strings(key)?;
+                for rule in rules.iter().filter(|rule| !lint::RULES.contains(&rule.as_str())) {
+                    self.unknown.push(format!("{number}: unknown warning '{rule}'"));
+                }
+                self.warnings = rules;
+            }
+            "include_paths" => self.include_paths = value.strings(key)?.into_iter().map(PathBuf::from).collect(),
+            _ => self.unknown.push(format!("{number}: unknown key '{key}'")),
+        }
+        Ok(())
+    }
+
+    /// Override file settings with the ones given on the command line
+    #[must_use]
+    pub fn merge(mut self, cli: &ConfigOverrides) -> Config {
+        self.float_precision = cli.float_precision.or(self.float_precision);
+        self.optimize = cli.optimize.unwrap_or(self.optimize);
+        self.typecheck = cli.typecheck.unwrap_or(self.typecheck);
+        self.strict_matches = cli.strict_matches.unwrap_or(self.strict_matches);
+        self.deny_warnings = cli.deny_warnings.unwrap_or(self.deny_warnings);
+        self.prune_unused = cli.prune_unused.unwrap_or(self.prune_unused);
+        self.verbose_load = cli.verbose_load.unwrap_or(self.verbose_load);
+        self.dump_limit = cli.dump_limit.or(self.dump_limit);
+        self.max_steps = cli.max_steps.or(self.max_steps);
+        self.max_depth = cli.max_depth.or(self.max_depth);
+        self.prelude = cli.prelude.unwrap_or(self.prelude);
+        if let Some(warnings) = &cli.warnings {
+            self.warnings.clone_from(warnings);
+        }
+        if let Some(include_paths) = &cli.include_paths {
+            self.include_paths.clone_from(include_paths);
+        }
+        self
+    }
+}
+
+/// Renders the settings in configuration file syntax, for `--show-config`
+///
+/// Settings without a value are shown as comments.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.path {
+            Some(path) => writeln!(f, "# read from {}", path.display())?,
+            None => writeln!(f, "# no {CONFIG_FILE_NAME} found; defaults and command-line flags")?,
+        }
+        match self.float_precision {
+            Some(digits) => writeln!(f, "float_precision = {digits}")?,
+            None => writeln!(f, "# float_precision is not set (exact)")?,
+        }
+        writeln!(f, "optimize = {}", self.optimize)?;
+        writeln!(f, "typecheck = {}", self.typecheck)?;
+        writeln!(f, "strict_matches = {}", self.strict_matches)?;
+        writeln!(f, "deny_warnings = {}", self.deny_warnings)?;
+        writeln!(f, "prune_unused = {}", self.prune_unused)?;
+        writeln!(f, "verbose_load = {}", self.verbose_load)?;
+        match self.dump_limit {
+            Some(limit) => writeln!(f, "dump_limit = {limit}")?,
+            None => writeln!(f, "# dump_limit is not set (unlimited)")?,
+        }
+        match self.max_steps {
+            Some(steps) => writeln!(f, "max_steps = {steps}")?,
+            None => writeln!(f, "# max_steps is not set (unlimited)")?,
+        }
+        match self.max_depth {
+            Some(depth) => writeln!(f, "max_depth = {depth}")?,
+            None => writeln!(f, "# max_depth is not set (unlimited)")?,
+        }
+        writeln!(f, "prelude = {}", self.prelude)?;
+        let warnings: Vec<String> = self.warnings.iter().map(|rule| format!("\"{rule}\"")).collect();
+        writeln!(f, "warnings = [{}]", warnings.join(", "))?;
+        let include_paths: Vec<String> = self
+            .include_paths
+            .iter()
+            .map(|dir| format!("\"{}\"", dir.display().to_string().replace('\\', "\\\\").replace('"', "\\\"")))
+            .collect();
+        writeln!(f, "include_paths = [{}]", include_paths.join(", "))
+    }
+}
+
+/// A value on the right of `=`
+#[derive(Debug, Clone, PartialEq)]
+enum TomlValue {
+    Boolean(bool),
+    Integer(u64),
+    String(String),
+    Array(Vec<TomlValue>),
+}
+
+impl TomlValue {
+    fn describe(&self) -> &'static str {
+        match self {
+            TomlValue::Boolean(_) => "a boolean",
+            TomlValue::Integer(_) => "an integer",
+            TomlValue::String(_) => "a string",
+            TomlValue::Array(_) => "an array",
+        }
+    }
+
+    fn boolean(&self, key: &str) -> Result<bool, String> {
+        match self {
+            TomlValue::Boolean(b) => Ok(*b),
+            other => Err(format!("{key} must be true or false, found {}", other.describe())),
+        }
+    }
+
+    fn integer(&self, key: &str) -> Result<u64, String> {
+        match self {
+            TomlValue::Integer(n) => Ok(*n),
+            other => Err(format!("{key} must be a non-negative integer, found {}", other.describe())),
+        }
+    }
+
+    fn strings(self, key: &str) -> Result<Vec<String>, String> {
+        let invalid = |found: &TomlValue| format!("{key} must be an array of strings, found {}", found.describe());
+        match self {
+            TomlValue::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    TomlValue::String(s) => Ok(s),
+                    other => Err(invalid(&other)),
+                })
+                .collect(),
+            other => Err(invalid(&other)),
+        }
+    }
+}
+
+/// Parse a boolean, integer, string, or array of those
+fn parse_value(text: &str) -> Result<TomlValue, String> {
+    match text {
+        "true" => return Ok(TomlValue::Boolean(true)),
+        "false" => return Ok(TomlValue::Boolean(false)),
+        _ => {}
+    }
+    if let Some(items) = text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        return split_items(items)?.into_iter().map(parse_value).collect::<Result<_, _>>().map(TomlValue::Array);
+    }
+    if let Some(body) = text.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        return unescape(body).map(TomlValue::String);
+    }
+    text.replace('_', "")
+        .parse()
+        .map(TomlValue::Integer)
+        .map_err(|_| format!("invalid value '{text}'"))
+}
+
+/// Split the inside of an array at commas outside strings
+fn split_items(items: &str) -> Result<Vec<&str>, String> {
+    let mut parts = Vec::new();
+    let (mut start, mut in_string, mut escaped) = (0, false, false);
+    for (i, c) in items.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                parts.push(items[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return Err(format!("unterminated string in '[{items}]'"));
+    }
+    let last = items[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(format!("empty item in '[{items}]'"));
+    }
+    Ok(parts)
+}
+
+/// Resolve `\"` and `\\` in a string body
+fn unescape(body: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('"' | '\\')) => out.push(escaped),
+                other => return Err(format!("unsupported escape '\\{}'", other.map(String::from).unwrap_or_default())),
+            },
+            '"' => return Err(format!("unexpected quote in \"{body}\"")),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Remove a `#` comment that is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn to_usize(n: u64) -> usize {
+    usize::try_from(n).unwrap_or(usize::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_all_keys() {
+        let config = Config::parse(
+            "# project settings\n\
+             float_precision = 6\n\
+             optimize = true   # faster\n\
+             typecheck = true\n\
+             strict_matches = true\n\
+             deny_warnings = true\n\
+             prune_unused = true\n\
+             verbose_load = true\n\
+             dump_limit = 500\n\
+             max_steps = 1_000_000\n\
+             max_depth = 5000\n\
+             prelude = false\n\
+             warnings = [\"float-equality\"]\n\
+             include_paths = [\"lib\", \"/opt/parlang\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                float_precision: Some(6),
+                optimize: true,
+                typecheck: true,
+                strict_matches: true,
+                deny_warnings: true,
+                prune_unused: true,
+                verbose_load: true,
+                dump_limit: Some(500),
+                max_steps: Some(1_000_000),
+                max_depth: Some(5000),
+                prelude: false,
+                warnings: vec!["float-equality".to_string()],
+                include_paths: vec![PathBuf::from("lib"), PathBuf::from("/opt/parlang")],
+                path: None,
+                unknown: vec![],
+            }
+        );
+        assert_eq!(Config::parse("\n# nothing\n").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_unknown_keys_are_warnings() {
+        let config = Config::parse(
+            "colour = \"always\"\noptimize = true\nwarnings = [\"shadowing\", \"float-equality\"]\n[paths]\ninclude = [\"lib\"]\n",
+        )
+        .unwrap();
+        assert!(config.optimize);
+        assert_eq!(config.warnings, ["shadowing", "float-equality"]);
+        assert_eq!(
+            config.unknown,
+            [
+                "1: unknown key 'colour'",
+                "3: unknown warning 'shadowing'",
+                "4: unknown section [paths]; its keys are ignored",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_files_are_errors() {
+        assert_eq!(Config::parse("optimize\n"), Err("1: expected `key = value`, found 'optimize'".to_string()));
+        assert_eq!(
+            Config::parse("\noptimize = 1\n"),
+            Err("2: optimize must be true or false, found an integer".to_string())
+        );
+        assert_eq!(
+            Config::parse("max_steps = -5"),
+            Err("1: invalid value '-5'".to_string())
+        );
+        assert!(Config::parse("float_precision = 0").is_err());
+        assert!(Config::parse("warnings = \"float-equality\"").is_err());
+        assert!(Config::parse("warnings = [\"a\",, \"b\"]").is_err());
+        // Values of unknown keys must still be well-formed
+        assert!(Config::parse("colour = always").is_err());
+    }
+
+    #[test]
+    fn test_comments_and_strings() {
+        let config = Config::parse("warnings = [\"a#b\", \"say \\\"hi\\\"\"] # done").unwrap();
+        assert_eq!(config.warnings, ["a#b", "say \"hi\""]);
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let file = Config::parse("float_precision = 6\noptimize = true\nmax_steps = 10\nwarnings = [\"float-equality\"]").unwrap();
+
+        assert_eq!(file.clone().merge(&ConfigOverrides::default()), file);
+
+        let merged = file.merge(&ConfigOverrides {
+            float_precision: Some(2),
+            prelude: Some(false),
+            warnings: Some(vec![]),
+            ..ConfigOverrides::default()
+        });
+        assert_eq!(merged.float_precision, Some(2));
+        assert!(merged.optimize);
+        assert_eq!(merged.max_steps, Some(10));
+        assert!(!merged.prelude);
+        assert!(merged.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_include_dir_overrides_include_paths() {
+        let file = Config::parse("include_paths = [\"lib\", \"vendor\"]").unwrap();
+
+        // Without --include-dir the file's directories are searched
+        let kept = file.clone().merge(&ConfigOverrides::default());
+        assert_eq!(kept.include_paths, [PathBuf::from("lib"), PathBuf::from("vendor")]);
+
+        // --include-dir replaces them rather than adding to them
+        let merged = file.merge(&ConfigOverrides { include_paths: Some(vec![PathBuf::from("other")]), ..ConfigOverrides::default() });
+        assert_eq!(merged.include_paths, [PathBuf::from("other")]);
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let config = Config::parse("float_precision = 3\nprune_unused = true\nwarnings = [\"float-equality\"]\ninclude_paths = [\"a\\\\b\"]").unwrap();
+        let shown = config.to_string();
+        assert!(shown.starts_with("# no parlang.toml found"), "{shown}");
+        assert!(shown.contains("# max_steps is not set (unlimited)\n"), "{shown}");
+        assert_eq!(Config::parse(&shown).unwrap(), config);
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_discover_nearest_file_wins() {
+        let root = std::env::temp_dir().join(format!("parlang_config_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let nested = root.join("project").join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(CONFIG_FILE_NAME), "optimize = true\nmax_steps = 5\n").unwrap();
+        fs::write(root.join("project").join(CONFIG_FILE_NAME), "max_steps = 7\nlegacy = 1\ninclude_paths = [\"lib\"]\n").unwrap();
+
+        // The nearest file is used on its own; the outer file's optimize is not inherited
+        let config = Config::discover(&nested).unwrap();
+        assert_eq!(config.max_steps, Some(7));
+        assert!(!config.optimize);
+        let path = config.path.clone().unwrap();
+        assert!(path.ends_with(Path::new("project").join(CONFIG_FILE_NAME)), "{}", path.display());
+        assert_eq!(config.unknown, [format!("{}:2: unknown key 'legacy'", path.display())]);
+        // Relative include paths are found next to the file
+        assert_eq!(config.include_paths, [path.parent().unwrap().join("lib")]);
+
+        let outer = Config::discover(&root).unwrap();
+        assert_eq!((outer.max_steps, outer.optimize), (Some(5), true));
+
+        fs::write(root.join("project").join(CONFIG_FILE_NAME), "max_steps = lots\n").unwrap();
+        let error = Config::discover(&nested).unwrap_err();
+        assert!(error.ends_with(":1: invalid value 'lots'"), "{error}");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,300 @@
+//! Conversions between `ParLang` values and Rust values, for embedders
+//!
+//! [`FromParValue`] reads a Rust value out of a [`Value`] a program
+//! returned, and [`ToParValue`] builds the [`Value`] a Rust value stands for,
+//! to bind it in an [`Environment`](crate::Environment) with
+//! [`bind_value`](crate::Environment::bind_value). Both are implemented for
+//! the scalars, strings, units, `Vec`s and tuples of up to 8 elements of
+//! convertible types, so nested data converts without writing any impls:
+//!
+//! | Rust | `ParLang` |
+//! |------|-----------|
+//! | `i64` (`i8` to `u32` into a value) | `Int` |
+//! | `f64` | `Float` |
+//! | `bool` | `Bool` |
+//! | `char` | `Char` |
+//! | `u8` | `Byte` |
+//! | `()` | `()` |
+//! | `String` (`&str` into a value) | string (`List Char`) |
+//! | `Vec<T>` (`[T; N]` into a value) | `List T`; an array also converts to a `Vec` |
+//! | `(A, B, ...)` | tuple of the same length |
+//! | `Value` | itself |
+//!
+//! `TryFrom<Value>` is implemented for the same types and does the same
+//! conversion. A value of the wrong shape fails with a `TypeError` naming
+//! the expected type and the value.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::convert::{FromParValue, ToParValue};
+//! use parlang::Value;
+//!
+//! let value = (1, vec![true, false]).to_par_value();
+//! assert_eq!(value.to_string(), "(1, Cons(true, Cons(false, Nil)))");
+//! let (n, flags): (i64, Vec<bool>) = FromParValue::from_par_value(value).unwrap();
+//! assert_eq!((n, flags), (1, vec![true, false]));
+//! assert!(i64::try_from(Value::Bool(true)).is_err());
+//! ```
+
+use crate::builtins::string_value;
+use crate::eval::{EvalError, Value};
+
+/// A Rust value that can be read out of a `ParLang` value
+pub trait FromParValue: Sized {
+    /// Convert `value`, failing with a `TypeError` when it has the wrong shape
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TypeError` naming the expected type and the value.
+    fn from_par_value(value: Value) -> Result<Self, EvalError>;
+}
+
+/// A Rust value that stands for a `ParLang` value
+pub trait ToParValue {
+    /// The `ParLang` value this Rust value stands for
+    fn to_par_value(self) -> Value;
+}
+
+/// The error for a value that is not of the expected type
+fn expected(name: &str, value: &Value) -> EvalError {
+    EvalError::TypeError(format!("expected {name}, got {value}"))
+}
+
+/// Conversions between a scalar Rust type and a `ParLang` scalar
+macro_rules! scalar_conversion {
+    ($rust:ty, $name:literal, $variant:ident) => {
+        impl TryFrom<Value> for $rust {
+            type Error = EvalError;
+
+            fn try_from(value: Value) -> Result<Self, EvalError> {
+                match value {
+                    Value::$variant(x) => Ok(x),
+                    other => Err(expected($name, &other)),
+                }
+            }
+        }
+
+        impl FromParValue for $rust {
+            fn from_par_value(value: Value) -> Result<Self, EvalError> {
+                Self::try_from(value)
+            }
+        }
+
+        impl ToParValue for $rust {
+            fn to_par_value(self) -> Value {
+                Value::$variant(self)
+            }
+        }
+    };
+}
+
+scalar_conversion!(i64, "Int", Int);
+scalar_conversion!(f64, "Float", Float);
+scalar_conversion!(bool, "Bool", Bool);
+scalar_conversion!(char, "Char", Char);
+scalar_conversion!(u8, "Byte", Byte);
+
+/// Smaller integers become Ints, so integer literals (`i32`) can be bound directly
+macro_rules! int_to_value {
+    ($($rust:ty),+) => {
+        $(impl ToParValue for $rust {
+            fn to_par_value(self) -> Value {
+                Value::Int(i64::from(self))
+            }
+        })+
+    };
+}
+
+int_to_value!(i8, i16, i32, u16, u32);
+
+impl TryFrom<Value> for String {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, EvalError> {
+        value.as_string().ok_or_else(|| expected("a string", &value))
+    }
+}
+
+impl FromParValue for String {
+    fn from_par_value(value: Value) -> Result<Self, EvalError> {
+        Self::try_from(value)
+    }
+}
+
+impl ToParValue for String {
+    fn to_par_value(self) -> Value {
+        string_value(&self)
+    }
+}
+
+impl ToParValue for &str {
+    fn to_par_value(self) -> Value {
+        string_value(self)
+    }
+}
+
+impl FromParValue for () {
+    fn from_par_value(value: Value) -> Result<Self, EvalError> {
+        match value {
+            Value::Unit => Ok(()),
+            other => Err(expected("()", &other)),
+        }
+    }
+}
+
+impl ToParValue for () {
+    fn to_par_value(self) -> Value {
+        Value::Unit
+    }
+}
+
+impl FromParValue for Value {
+    fn from_par_value(value: Value) -> Result<Self, EvalError> {
+        Ok(value)
+    }
+}
+
+impl ToParValue for Value {
+    fn to_par_value(self) -> Value {
+        self
+    }
+}
+
+/// The elements of a list or an array, converted in order
+impl<T: FromParValue> TryFrom<Value> for Vec<T> {
+    type Error = EvalError;
+
+    fn try_from(value: Value) -> Result<Self, EvalError> {
+        if let Value::Array(_, elements) = value {
+            return elements.into_iter().map(T::from_par_value).collect();
+        }
+        // A loop rather than recursion, so long lists cannot overflow the stack
+        let mut elements = Vec::new();
+        let mut current = value;
+        loop {
+            match current {
+                Value::Variant(name, args) if name == "Nil" && args.is_empty() => return Ok(elements),
+                Value::Variant(name, args) if name == "Cons" && args.len() == 2 => {
+                    let [head, tail]: [Value; 2] = args.try_into().expect("Cons has two arguments");
+                    elements.push(T::from_par_value(head)?);
+                    current = tail;
+                }
+                other => return Err(expected("a list or array", &other)),
+            }
+        }
+    }
+}
+
+impl<T: FromParValue> FromParValue for Vec<T> {
+    fn from_par_value(value: Value) -> Result<Self, EvalError> {
+        Self::try_from(value)
+    }
+}
+
+/// A `Cons`/`Nil` list of the converted elements
+impl<T: ToParValue> ToParValue for Vec<T> {
+    fn to_par_value(self) -> Value {
+        self.into_iter().rev().fold(Value::Variant("Nil".to_string(), vec![]), |rest, element| {
+            Value::Variant("Cons".to_string(), vec![element.to_par_value(), rest])
+        })
+    }
+}
+
+/// An array of the converted elements
+impl<T: ToParValue, const N: usize> ToParValue for [T; N] {
+    fn to_par_value(self) -> Value {
+        Value::Array(N, self.into_iter().map(ToParValue::to_par_value).collect())
+    }
+}
+
+/// Conversions between Rust tuples and `ParLang` tuples of the same length
+macro_rules! tuple_conversion {
+    ($len:literal; $($name:ident $index:tt),+) => {
+        impl<$($name: FromParValue),+> TryFrom<Value> for ($($name,)+) {
+            type Error = EvalError;
+
+            fn try_from(value: Value) -> Result<Self, EvalError> {
+                match value {
+                    Value::Tuple(elements) if elements.len() == $len => {
+                        let mut elements = elements.into_iter();
+                        Ok(($($name::from_par_value(elements.next().expect("length checked"))?,)+))
+                    }
+                    other => Err(expected(concat!("a tuple of ", $len, " elements"), &other)),
+                }
+            }
+        }
+
+        impl<$($name: FromParValue),+> FromParValue for ($($name,)+) {
+            fn from_par_value(value: Value) -> Result<Self, EvalError> {
+                Self::try_from(value)
+            }
+        }
+
+        impl<$($name: ToParValue),+> ToParValue for ($($name,)+) {
+            fn to_par_value(self) -> Value {
+                Value::Tuple(vec![$(self.$index.to_par_value()),+])
+            }
+        }
+    };
+}
+
+tuple_conversion!(2; A 0, B 1);
+tuple_conversion!(3; A 0, B 1, C 2);
+tuple_conversion!(4; A 0, B 1, C 2, D 3);
+tuple_conversion!(5; A 0, B 1, C 2, D 3, E 4);
+tuple_conversion!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_conversion!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_conversion!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval, parse, Environment};
+
+    fn run(source: &str) -> Value {
+        eval(&parse(source).unwrap(), &Environment::with_builtins()).unwrap()
+    }
+
+    #[test]
+    fn test_from_program_results() {
+        assert_eq!(i64::try_from(run("6 * 7")), Ok(42));
+        assert_eq!(String::from_par_value(run("\"hi\" ^ \"!\"")), Ok("hi!".to_string()));
+        assert_eq!(Vec::<i64>::try_from(run("[1, 2, 3]")), Ok(vec![1, 2, 3]));
+        assert_eq!(Vec::<char>::try_from(run("[|'a', 'b'|]")), Ok(vec!['a', 'b']));
+        let nested: (i64, (bool, String), Vec<(i64, f64)>) = run("(1, (true, \"x\"), [(2, 0.5)])").try_into().unwrap();
+        assert_eq!(nested, (1, (true, "x".to_string()), vec![(2, 0.5)]));
+        let eight: (i64, i64, i64, i64, i64, i64, i64, i64) = run("(1, 2, 3, 4, 5, 6, 7, 8)").try_into().unwrap();
+        assert_eq!(eight.7, 8);
+    }
+
+    #[test]
+    fn test_wrong_shapes_are_type_errors() {
+        assert_eq!(bool::try_from(Value::Int(1)), Err(EvalError::TypeError("expected Bool, got 1".to_string())));
+        assert_eq!(
+            <(i64, i64)>::try_from(run("(1, 2, 3)")),
+            Err(EvalError::TypeError("expected a tuple of 2 elements, got (1, 2, 3)".to_string()))
+        );
+        assert_eq!(
+            Vec::<i64>::try_from(Value::Int(1)),
+            Err(EvalError::TypeError("expected a list or array, got 1".to_string()))
+        );
+        // An element of the wrong type fails the whole conversion
+        assert_eq!(
+            Vec::<bool>::try_from(run("[true, false, 1]")),
+            Err(EvalError::TypeError("expected Bool, got 1".to_string()))
+        );
+        assert!(<(i64, bool)>::try_from(run("(1, 2)")).is_err());
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let value = (7, "seven", vec![('a', 1u8)], [1.5, 2.5], ()).to_par_value();
+        assert_eq!(value.to_string(), "(7, \"seven\", Cons(('a', 1b), Nil), [|1.5, 2.5|] (size: 2), ())");
+        let back: (i64, String, Vec<(char, u8)>, Vec<f64>, ()) = value.try_into().unwrap();
+        assert_eq!(back, (7, "seven".to_string(), vec![('a', 1)], vec![1.5, 2.5], ()));
+        // Long lists convert without recursion
+        let long: Vec<i64> = (0..100_000).collect();
+        assert_eq!(Vec::<i64>::try_from(long.clone().to_par_value()), Ok(long));
+    }
+}
@@ -0,0 +1,552 @@
+//! The core language the evaluator runs
+//!
+//! The surface AST ([`Expr`]) keeps everything the parser read, including
+//! conveniences that add nothing at runtime. [`lower`] translates it into
+//! [`CoreExpr`], a deliberately small language in which each construct has
+//! one form:
+//!
+//! - `if c1 then e1 elif c2 then e2 else e3` becomes nested `If`s
+//! - sequential bindings `let x = e1; let y = e2; body` become nested `Let`s
+//! - a missing program body and `()` become the `Unit` literal
+//! - `ref e`, `!e`, `-e`, `r := e`, `a[i]` and `a..b` become [`PrimOp`] applications
+//! - type annotations and type aliases are dropped, since they only matter
+//!   to the type checker
+//! - sum type definitions keep only the arity of each constructor
+//!
+//! Lowering never fails: errors such as a `rec` whose body is not a function
+//! are kept in the core expression and reported when it is evaluated. The
+//! AST carries no source positions; [`lower_with_spans`] takes the spans the
+//! parser found and keeps those of the nodes that can fail in
+//! [`CoreExpr::At`] nodes.
+//!
+//! [`crate::eval::eval`] lowers its argument and evaluates the result, and
+//! closures keep their bodies in core form. New surface syntax should be
+//! added to the parser and to [`lower`] only: as long as it lowers to
+//! existing core constructs, the evaluator and the analyses over
+//! `CoreExpr` need no changes. The type checker still works on `Expr`.
+//!
+//! [`mark_int_arith`] is a separate pass over the core, run by `eval` after
+//! lowering, that marks integer arithmetic for the evaluator's fast path.
+//! Building with the `force-int-fast-path` feature sends every integer
+//! operation of every new environment down it, so
+//! `cargo test --features force-int-fast-path` runs the whole test suite
+//! through it.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::core::{lower, CoreExpr};
+//! use parlang::parse;
+//!
+//! let expr = parse("let x = 1; x").unwrap();
+//! let core = CoreExpr::Let("x".to_string(), Box::new(CoreExpr::Int(1)), Box::new(CoreExpr::Var("x".to_string())));
+//! assert_eq!(lower(&expr), core);
+//! ```
+
+use crate::ast::{BinOp, Expr, Pattern};
+use crate::span::Span;
+use crate::subst::pattern_vars;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Expression of the core language
+#[derive(Debug, Clone, PartialEq)]
+pub enum CoreExpr {
+    /// Integer literal
+    Int(i64),
+    /// Boolean literal
+    Bool(bool),
+    /// Character literal
+    Char(char),
+    /// Floating point literal
+    Float(f64),
+    /// Byte literal
+    Byte(u8),
+    /// The unit value `()`
+    Unit,
+    /// Variable reference
+    Var(String),
+    /// Function: parameter and body, shared with the closures created from it
+    Lam(String, Rc<CoreExpr>),
+    /// Function application
+    App(Box<CoreExpr>, Box<CoreExpr>),
+    /// Non-recursive binding: name, value, body
+    Let(String, Box<CoreExpr>, Box<CoreExpr>),
+    /// Recursive function: its name, bound in the body, and the body,
+    /// which must evaluate as a `Lam`
+    Rec(String, Box<CoreExpr>),
+    /// Conditional
+    If(Box<CoreExpr>, Box<CoreExpr>, Box<CoreExpr>),
+    /// Pattern matching: scrutinee and arms tried in order; an arm with a
+    /// guard is taken only when its guard evaluates to `true`
+    Match(Box<CoreExpr>, Vec<(Pattern, Option<CoreExpr>, CoreExpr)>),
+    /// Binary operation
+    BinOp(BinOp, Box<CoreExpr>, Box<CoreExpr>),
+    /// Primitive operation applied to its operands, evaluated left to right
+    Prim(PrimOp, Vec<CoreExpr>),
+    /// Tuple of two or more elements
+    Tuple(Vec<CoreExpr>),
+    /// Tuple projection
+    Proj(Box<CoreExpr>, usize),
+    /// Record construction, fields in source order
+    Record(Vec<(String, CoreExpr)>),
+    /// Record field access
+    Field(Box<CoreExpr>, String),
+    /// Constructor application
+    Constructor(String, Vec<CoreExpr>),
+    /// Array construction
+    Array(Vec<CoreExpr>),
+    /// Sum type definition: type name, constructor names with their arity, body
+    TypeDef(String, Vec<(String, usize)>, Box<CoreExpr>),
+    /// Library load: path, namespace its bindings go under (if any), and body
+    Load(String, Option<String>, Box<CoreExpr>),
+    /// Error recovery: body, name bound to the error record, handler
+    Try(Box<CoreExpr>, String, Box<CoreExpr>),
+    /// Integer arithmetic found by [`mark_int_arith`]: a `BinOp` tree of
+    /// arithmetic operators over `Int` literals and variables, optionally
+    /// under one comparison, and the number of nodes in it
+    IntArith(Box<CoreExpr>, usize),
+    /// An expression with its source span, added by [`lower_with_spans`]:
+    /// errors raised evaluating it are reported there. Evaluating it counts
+    /// no step of its own
+    At(Span, Box<CoreExpr>),
+}
+
+/// Operations on values that have no syntax of their own in the core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimOp {
+    /// `ref e`: a new reference holding the operand
+    Ref,
+    /// `!r`: the contents of a reference
+    Deref,
+    /// `-e`: the negation of a number
+    Neg,
+    /// `r := e`: replace the contents of a reference
+    Assign,
+    /// `a[i]`: element of an array
+    Index,
+    /// `a..b`: inclusive integer range
+    Range,
+}
+
+/// Translate a surface expression into the core language
+pub fn lower(expr: &Expr) -> CoreExpr {
+    lower_in(expr, &HashMap::new())
+}
+
+/// Translate a parsed program into the core language, keeping where its operations are
+///
+/// `spans` are the spans of the nodes of `expr`, in the order of
+/// [`Expr::subexpressions`], as
+/// [`parse_program_with_spans`](crate::parser::parse_program_with_spans)
+/// returns them. Each variable, operator, primitive operation, projection,
+/// field access, constructor application, conditional, match and call with
+/// a span is wrapped in [`CoreExpr::At`]; of the applications `f a b` is
+/// made of, only the outermost is. Functions and bindings are not, so
+/// function bodies keep the shape the evaluator looks for, and it follows
+/// the spanned conditionals, matches and calls in tail position.
+pub fn lower_with_spans(expr: &Expr, spans: &[Option<Span>]) -> CoreExpr {
+    let by_node = expr
+        .subexpressions()
+        .zip(spans)
+        .filter_map(|(node, span)| Some((std::ptr::from_ref(node), (*span)?)))
+        .collect();
+    lower_in(expr, &by_node)
+}
+
+/// Translate `expr`, wrapping the nodes that can fail in their span from `spans`
+fn lower_in(expr: &Expr, spans: &HashMap<*const Expr, Span>) -> CoreExpr {
+    let lower = |expr: &Expr| lower_in(expr, spans);
+    let boxed = |expr: &Expr| Box::new(lower(expr));
+    let all = |exprs: &[Expr]| exprs.iter().map(lower).collect();
+    let prim = |op, operands: &[&Expr]| CoreExpr::Prim(op, operands.iter().map(|expr| lower(expr)).collect());
+    let core = match expr {
+        Expr::Int(n) => CoreExpr::Int(*n),
+        Expr::Bool(b) => CoreExpr::Bool(*b),
+        Expr::Char(c) => CoreExpr::Char(*c),
+        Expr::Float(f) => CoreExpr::Float(*f),
+        Expr::Byte(b) => CoreExpr::Byte(*b),
+        Expr::EmptyBody => CoreExpr::Unit,
+        Expr::Tuple(elements) if elements.is_empty() => CoreExpr::Unit,
+        Expr::Var(name) => CoreExpr::Var(name.clone()),
+        Expr::Fun(param, _, body) => CoreExpr::Lam(param.clone(), Rc::new(lower(body))),
+        // The call is spanned as a whole, leaving a spine of applications
+        Expr::App(func, arg) => match lower(func) {
+            CoreExpr::At(_, func) if matches!(*func, CoreExpr::App(..)) => CoreExpr::App(func, boxed(arg)),
+            func => CoreExpr::App(Box::new(func), boxed(arg)),
+        },
+        Expr::Let(name, _, value, body) => CoreExpr::Let(name.clone(), boxed(value), boxed(body)),
+        Expr::Seq(bindings, body) => bindings.iter().rev().fold(lower(body), |body, (name, _, value)| {
+            CoreExpr::Let(name.clone(), Box::new(lower(value)), Box::new(body))
+        }),
+        Expr::Rec(name, body) => CoreExpr::Rec(name.clone(), boxed(body)),
+        Expr::If(cond, then_branch, else_branch) => CoreExpr::If(boxed(cond), boxed(then_branch), boxed(else_branch)),
+        Expr::IfChain(branches, else_branch) => branches.iter().rev().fold(lower(else_branch), |else_branch, (cond, branch)| {
+            CoreExpr::If(Box::new(lower(cond)), Box::new(lower(branch)), Box::new(else_branch))
+        }),
+        Expr::Match(scrutinee, arms) => CoreExpr::Match(
+            boxed(scrutinee),
+            arms.iter().map(|(pattern, guard, result)| (pattern.clone(), guard.as_ref().map(&lower), lower(result))).collect(),
+        ),
+        Expr::BinOp(op, left, right) => CoreExpr::BinOp(*op, boxed(left), boxed(right)),
+        Expr::Ref(value) => prim(PrimOp::Ref, &[value]),
+        Expr::Deref(reference) => prim(PrimOp::Deref, &[reference]),
+        Expr::Neg(operand) => prim(PrimOp::Neg, &[operand]),
+        Expr::RefAssign(reference, value) => prim(PrimOp::Assign, &[reference, value]),
+        Expr::ArrayIndex(array, index) => prim(PrimOp::Index, &[array, index]),
+        Expr::Range(start, end) => prim(PrimOp::Range, &[start, end]),
+        Expr::Tuple(elements) => CoreExpr::Tuple(all(elements)),
+        Expr::TupleProj(tuple, index) => CoreExpr::Proj(boxed(tuple), *index),
+        Expr::Record(fields) => CoreExpr::Record(fields.iter().map(|(name, value)| (name.clone(), lower(value))).collect()),
+        Expr::FieldAccess(record, field) => CoreExpr::Field(boxed(record), field.clone()),
+        Expr::Constructor(name, args) => CoreExpr::Constructor(name.clone(), all(args)),
+        Expr::Array(elements) => CoreExpr::Array(all(elements)),
+        Expr::TypeAlias(_, _, body) => lower(body),
+        Expr::TypeDef { name, constructors, body, .. } => CoreExpr::TypeDef(
+            name.clone(),
+            constructors.iter().map(|(ctor, payload)| (ctor.clone(), payload.len())).collect(),
+            boxed(body),
+        ),
+        Expr::Load(path, body) => CoreExpr::Load(path.clone(), None, boxed(body)),
+        Expr::LoadAs(path, namespace, body) => CoreExpr::Load(path.clone(), Some(namespace.clone()), boxed(body)),
+        Expr::Try(body, name, handler) => CoreExpr::Try(boxed(body), name.clone(), boxed(handler)),
+    };
+    let can_fail = matches!(
+        core,
+        CoreExpr::Var(_)
+            | CoreExpr::BinOp(..)
+            | CoreExpr::Prim(..)
+            | CoreExpr::Proj(..)
+            | CoreExpr::Field(..)
+            | CoreExpr::Constructor(..)
+            | CoreExpr::If(..)
+            | CoreExpr::Match(..)
+            | CoreExpr::App(..)
+    );
+    match spans.get(&std::ptr::from_ref(expr)) {
+        Some(span) if can_fail => CoreExpr::At(*span, Box::new(core)),
+        _ => core,
+    }
+}
+
+/// Fewest operators an arithmetic subtree needs for [`mark_int_arith`] to mark it
+///
+/// A single `x + 1` gains too little from the fast path to pay for the
+/// fallback when `x` turns out not to hold an `Int`.
+pub const INT_FAST_PATH_MIN_OPS: usize = 2;
+
+/// The fast path threshold of a new [`Environment`](crate::Environment): 1
+/// when built with the `force-int-fast-path` feature, as
+/// `set_int_fast_path(Some(1))` does, and [`INT_FAST_PATH_MIN_OPS`] otherwise
+pub(crate) const DEFAULT_INT_FAST_PATH: usize = if cfg!(feature = "force-int-fast-path") { 1 } else { INT_FAST_PATH_MIN_OPS };
+
+/// Mark the integer arithmetic in an expression for the evaluator's fast path
+///
+/// Wraps each maximal subtree of `+`, `-`, `*` and `/` whose leaves are
+/// `Int` literals and variables, possibly under one comparison, in
+/// [`CoreExpr::IntArith`] when it has at least `min_ops` operators (and at
+/// least one). The evaluator computes such a subtree on plain `i64`s and
+/// falls back to evaluating it node by node when a variable does not hold
+/// an `Int` or an operation fails, so marking never changes the result.
+///
+/// ```
+/// use parlang::core::{lower, mark_int_arith, CoreExpr};
+/// use parlang::parse;
+///
+/// let core = lower(&parse("f (a * b + c * d)").unwrap());
+/// let CoreExpr::App(_, arg) = mark_int_arith(core, 2) else { panic!() };
+/// assert!(matches!(*arg, CoreExpr::IntArith(_, 7)));
+/// ```
+pub fn mark_int_arith(expr: CoreExpr, min_ops: usize) -> CoreExpr {
+    if let Some(ops) = int_arith_ops(&expr) {
+        if ops >= min_ops.max(1) {
+            return CoreExpr::IntArith(Box::new(expr), 2 * ops + 1);
+        }
+    }
+    let mark = |expr: CoreExpr| mark_int_arith(expr, min_ops);
+    let boxed = |expr: Box<CoreExpr>| Box::new(mark(*expr));
+    let all = |exprs: Vec<CoreExpr>| exprs.into_iter().map(mark).collect();
+    match expr {
+        CoreExpr::Int(_)
+        | CoreExpr::Bool(_)
+        | CoreExpr::Char(_)
+        | CoreExpr::Float(_)
+        | CoreExpr::Byte(_)
+        | CoreExpr::Unit
+        | CoreExpr::Var(_)
+        | CoreExpr::IntArith(..) => expr,
+        CoreExpr::Lam(name, body) => CoreExpr::Lam(name, Rc::new(mark(Rc::unwrap_or_clone(body)))),
+        CoreExpr::App(func, arg) => CoreExpr::App(boxed(func), boxed(arg)),
+        CoreExpr::Let(name, value, body) => CoreExpr::Let(name, boxed(value), boxed(body)),
+        CoreExpr::Rec(name, body) => CoreExpr::Rec(name, boxed(body)),
+        CoreExpr::If(cond, then_branch, else_branch) => CoreExpr::If(boxed(cond), boxed(then_branch), boxed(else_branch)),
+        CoreExpr::Match(scrutinee, arms) => {
+            let arms = arms.into_iter().map(|(pattern, guard, result)| (pattern, guard.map(mark), mark(result))).collect();
+            CoreExpr::Match(boxed(scrutinee), arms)
+        }
+        CoreExpr::BinOp(op, left, right) => CoreExpr::BinOp(op, boxed(left), boxed(right)),
+        CoreExpr::Prim(op, operands) => CoreExpr::Prim(op, all(operands)),
+        CoreExpr::Tuple(elements) => CoreExpr::Tuple(all(elements)),
+        CoreExpr::Proj(tuple, index) => CoreExpr::Proj(boxed(tuple), index),
+        CoreExpr::Record(fields) => CoreExpr::Record(fields.into_iter().map(|(name, value)| (name, mark(value))).collect()),
+        CoreExpr::Field(record, field) => CoreExpr::Field(boxed(record), field),
+        CoreExpr::Constructor(name, args) => CoreExpr::Constructor(name, all(args)),
+        CoreExpr::Array(elements) => CoreExpr::Array(all(elements)),
+        CoreExpr::TypeDef(name, constructors, body) => CoreExpr::TypeDef(name, constructors, boxed(body)),
+        CoreExpr::Load(path, namespace, body) => CoreExpr::Load(path, namespace, boxed(body)),
+        CoreExpr::Try(body, name, handler) => CoreExpr::Try(boxed(body), name, boxed(handler)),
+        CoreExpr::At(span, inner) => CoreExpr::At(span, boxed(inner)),
+    }
+}
+
+/// Operators of an expression the integer fast path can evaluate, or `None` if it cannot
+fn int_arith_ops(expr: &CoreExpr) -> Option<usize> {
+    match expr {
+        CoreExpr::BinOp(BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge, left, right) => {
+            Some(arithmetic_ops(left)? + arithmetic_ops(right)? + 1)
+        }
+        // A span stays outside the marked tree, where the evaluator looks for it
+        CoreExpr::At(..) => None,
+        _ => arithmetic_ops(expr),
+    }
+}
+
+/// Operators of an arithmetic tree over `Int` literals and variables, or `None` if it is not one
+fn arithmetic_ops(expr: &CoreExpr) -> Option<usize> {
+    match expr {
+        CoreExpr::Int(_) | CoreExpr::Var(_) => Some(0),
+        CoreExpr::At(_, inner) => arithmetic_ops(inner),
+        CoreExpr::BinOp(BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div, left, right) => {
+            Some(arithmetic_ops(left)? + arithmetic_ops(right)? + 1)
+        }
+        _ => None,
+    }
+}
+
+impl CoreExpr {
+    /// Variables the expression references without binding them
+    ///
+    /// Like [`crate::subst::free_vars`], the bindings a `Load` introduces are
+    /// not known, so its body is treated as if the library bound nothing. A
+    /// namespaced `Load` binds just its namespace.
+    pub fn free_vars(&self) -> HashSet<String> {
+        let mut free = HashSet::new();
+        self.collect_free_vars(&mut Vec::new(), &mut free);
+        free
+    }
+
+    fn collect_free_vars(&self, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+        match self {
+            CoreExpr::Var(name) => {
+                if !bound.contains(name) {
+                    free.insert(name.clone());
+                }
+            }
+            CoreExpr::Lam(name, body) => body.collect_free_vars_binding(name, bound, free),
+            CoreExpr::Rec(name, body) => body.collect_free_vars_binding(name, bound, free),
+            CoreExpr::Let(name, value, body) => {
+                value.collect_free_vars(bound, free);
+                body.collect_free_vars_binding(name, bound, free);
+            }
+            CoreExpr::Try(body, name, handler) => {
+                body.collect_free_vars(bound, free);
+                handler.collect_free_vars_binding(name, bound, free);
+            }
+            CoreExpr::Load(_, Some(namespace), body) => body.collect_free_vars_binding(namespace, bound, free),
+            CoreExpr::Match(scrutinee, arms) => {
+                scrutinee.collect_free_vars(bound, free);
+                for (pattern, guard, result) in arms {
+                    let vars = pattern_vars(pattern);
+                    let count = vars.len();
+                    bound.extend(vars);
+                    if let Some(guard) = guard {
+                        guard.collect_free_vars(bound, free);
+                    }
+                    result.collect_free_vars(bound, free);
+                    bound.truncate(bound.len() - count);
+                }
+            }
+            _ => {
+                for child in self.children() {
+                    child.collect_free_vars(bound, free);
+                }
+            }
+        }
+    }
+
+    /// Collect the free variables of an expression in the scope of `name`
+    fn collect_free_vars_binding(&self, name: &str, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+        bound.push(name.to_string());
+        self.collect_free_vars(bound, free);
+        bound.pop();
+    }
+
+    /// The direct sub-expressions, in evaluation order
+    pub fn children(&self) -> Vec<&CoreExpr> {
+        match self {
+            CoreExpr::Int(_)
+            | CoreExpr::Bool(_)
+            | CoreExpr::Char(_)
+            | CoreExpr::Float(_)
+            | CoreExpr::Byte(_)
+            | CoreExpr::Unit
+            | CoreExpr::Var(_) => vec![],
+            CoreExpr::Lam(_, body) => vec![body],
+            CoreExpr::Rec(_, body)
+            | CoreExpr::Proj(body, _)
+            | CoreExpr::Field(body, _)
+            | CoreExpr::TypeDef(_, _, body)
+            | CoreExpr::Load(_, _, body)
+            | CoreExpr::IntArith(body, _)
+            | CoreExpr::At(_, body) => vec![body],
+            CoreExpr::App(left, right)
+            | CoreExpr::Let(_, left, right)
+            | CoreExpr::BinOp(_, left, right)
+            | CoreExpr::Try(left, _, right) => vec![left, right],
+            CoreExpr::If(cond, then_branch, else_branch) => vec![cond, then_branch, else_branch],
+            CoreExpr::Match(scrutinee, arms) => {
+                let arms = arms.iter().flat_map(|(_, guard, result)| guard.iter().chain(std::iter::once(result)));
+                std::iter::once(scrutinee.as_ref()).chain(arms).collect()
+            }
+            CoreExpr::Prim(_, operands)
+            | CoreExpr::Tuple(operands)
+            | CoreExpr::Constructor(_, operands)
+            | CoreExpr::Array(operands) => operands.iter().collect(),
+            CoreExpr::Record(fields) => fields.iter().map(|(_, value)| value).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::TypeAnnotation;
+    use crate::parser::parse;
+
+    fn lowered(source: &str) -> CoreExpr {
+        lower(&parse(source).expect("Parse error"))
+    }
+
+    fn var(name: &str) -> Box<CoreExpr> {
+        Box::new(CoreExpr::Var(name.to_string()))
+    }
+
+    fn int(n: i64) -> Box<CoreExpr> {
+        Box::new(CoreExpr::Int(n))
+    }
+
+    #[test]
+    fn test_lower_if_chain_to_nested_ifs() {
+        let expected = CoreExpr::If(
+            var("a"),
+            int(1),
+            Box::new(CoreExpr::If(var("b"), int(2), Box::new(CoreExpr::If(var("c"), int(3), int(4))))),
+        );
+        assert_eq!(lowered("if a then 1 elif b then 2 else if c then 3 else 4"), expected);
+    }
+
+    #[test]
+    fn test_lower_sequence_to_nested_lets() {
+        let expected = CoreExpr::Let(
+            "x".to_string(),
+            int(1),
+            Box::new(CoreExpr::Let(
+                "y".to_string(),
+                Box::new(CoreExpr::BinOp(BinOp::Add, var("x"), int(1))),
+                var("y"),
+            )),
+        );
+        assert_eq!(lowered("let x = 1; let y = x + 1; y"), expected);
+    }
+
+    #[test]
+    fn test_lower_missing_body_and_empty_tuple_to_unit() {
+        assert_eq!(lowered("let x = 1;"), CoreExpr::Let("x".to_string(), int(1), Box::new(CoreExpr::Unit)));
+        assert_eq!(lowered("()"), CoreExpr::Unit);
+        assert_eq!(lowered("(1, 2)"), CoreExpr::Tuple(vec![CoreExpr::Int(1), CoreExpr::Int(2)]));
+    }
+
+    #[test]
+    fn test_lower_references_arrays_and_ranges_to_prim_ops() {
+        let r = || CoreExpr::Var("r".to_string());
+        assert_eq!(lowered("ref 1"), CoreExpr::Prim(PrimOp::Ref, vec![CoreExpr::Int(1)]));
+        assert_eq!(lowered("!r"), CoreExpr::Prim(PrimOp::Deref, vec![r()]));
+        assert_eq!(lowered("r := 2"), CoreExpr::Prim(PrimOp::Assign, vec![r(), CoreExpr::Int(2)]));
+        assert_eq!(lowered("r[0]"), CoreExpr::Prim(PrimOp::Index, vec![r(), CoreExpr::Int(0)]));
+        assert_eq!(lowered("1..3"), CoreExpr::Prim(PrimOp::Range, vec![CoreExpr::Int(1), CoreExpr::Int(3)]));
+    }
+
+    #[test]
+    fn test_lower_drops_annotations_and_aliases() {
+        let fun = Expr::Fun("x".to_string(), Some(TypeAnnotation::Concrete("Int".to_string())), Box::new(Expr::Var("x".to_string())));
+        assert_eq!(lower(&fun), CoreExpr::Lam("x".to_string(), Rc::new(CoreExpr::Var("x".to_string()))));
+        assert_eq!(lowered("let x : Int = 1 in x"), CoreExpr::Let("x".to_string(), int(1), var("x")));
+        assert_eq!(lowered("type Step = Int -> Int in 1"), CoreExpr::Int(1));
+    }
+
+    #[test]
+    fn test_lower_type_def_keeps_constructor_arity() {
+        let expected = CoreExpr::TypeDef(
+            "Option".to_string(),
+            vec![("Some".to_string(), 1), ("None".to_string(), 0)],
+            Box::new(CoreExpr::Constructor("Some".to_string(), vec![CoreExpr::Int(1)])),
+        );
+        assert_eq!(lowered("type Option a = Some a | None in Some 1"), expected);
+    }
+
+    #[test]
+    fn test_lower_keeps_core_forms() {
+        assert_eq!(
+            lowered("rec f -> fun n -> f n"),
+            CoreExpr::Rec("f".to_string(), Box::new(CoreExpr::Lam("n".to_string(), Rc::new(CoreExpr::App(var("f"), var("n"))))))
+        );
+        assert_eq!(lowered("{ a: 1 }.a"), CoreExpr::Field(Box::new(CoreExpr::Record(vec![("a".to_string(), CoreExpr::Int(1))])), "a".to_string()));
+        assert_eq!(lowered("(1, 2).0"), CoreExpr::Proj(Box::new(CoreExpr::Tuple(vec![CoreExpr::Int(1), CoreExpr::Int(2)])), 0));
+        assert_eq!(lowered("try x catch e -> e"), CoreExpr::Try(var("x"), "e".to_string(), var("e")));
+        assert_eq!(
+            lowered("match x with | (a, _) -> a"),
+            CoreExpr::Match(var("x"), vec![(Pattern::Tuple(vec![Pattern::Var("a".to_string()), Pattern::Wildcard]), None, CoreExpr::Var("a".to_string()))])
+        );
+    }
+
+    #[test]
+    fn test_free_vars() {
+        let free = |source: &str| {
+            let mut names: Vec<String> = lowered(source).free_vars().into_iter().collect();
+            names.sort();
+            names
+        };
+        assert_eq!(free("fun x -> x + y"), ["y"]);
+        assert_eq!(free("let x = x; let y = x; y + z"), ["x", "z"]);
+        assert_eq!(free("match p with | (a, b) -> a + c"), ["c", "p"]);
+        assert_eq!(free("rec f -> fun n -> f (g n)"), ["g"]);
+        assert_eq!(free("try e catch err -> err.kind"), ["e"]);
+    }
+
+    #[test]
+    fn test_mark_int_arith_wraps_maximal_subtrees() {
+        let marked = |source: &str, min_ops| mark_int_arith(lowered(source), min_ops);
+        let sum = || CoreExpr::BinOp(BinOp::Add, var("a"), int(1));
+        assert_eq!(marked("a + 1", 2), sum());
+        assert_eq!(marked("a + 1", 1), CoreExpr::IntArith(Box::new(sum()), 3));
+        assert_eq!(marked("a + 1", 0), CoreExpr::IntArith(Box::new(sum()), 3));
+        assert!(matches!(marked("a * b + c < d - 1", 2), CoreExpr::IntArith(_, 9)));
+        assert_eq!(
+            marked("f (a + 1)", 1),
+            CoreExpr::App(var("f"), Box::new(CoreExpr::IntArith(Box::new(sum()), 3)))
+        );
+    }
+
+    #[test]
+    fn test_mark_int_arith_skips_other_operands() {
+        let marked = |source: &str| mark_int_arith(lowered(source), 1);
+        // Only the root may be a comparison
+        let CoreExpr::BinOp(BinOp::Eq, left, right) = marked("(a < b) == (c < d)") else { panic!("expected a comparison") };
+        assert!(matches!((*left, *right), (CoreExpr::IntArith(_, 3), CoreExpr::IntArith(_, 3))));
+        // A float literal or a call makes the rest of the tree general
+        let CoreExpr::BinOp(BinOp::Add, left, right) = marked("a * b + 1.0") else { panic!("expected an addition") };
+        assert!(matches!((*left, *right), (CoreExpr::IntArith(_, 3), CoreExpr::Float(_))));
+        assert!(matches!(marked("a + f x"), CoreExpr::BinOp(BinOp::Add, ..)));
+        assert_eq!(marked("fun x -> x + y").free_vars(), HashSet::from(["y".to_string()]));
+    }
+}
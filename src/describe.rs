@@ -0,0 +1,214 @@
+/// Human-readable listings of environments
+///
+/// `describe_environment` renders the bindings of an environment grouped by
+/// kind (functions, values, constructors), each group sorted alphabetically
+/// with its size in the header. It backs the REPL's `:env` command.
+use crate::eval::{closure_arity, Environment, Value};
+use crate::typechecker::{canonicalize, TypeEnv};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Longest value rendering shown before truncating with `...`
+const MAX_VALUE_WIDTH: usize = 40;
+
+/// Describe the bindings of an environment, grouped by kind
+///
+/// Functions (closures, recursive closures, built-ins) are listed with the
+/// number of arguments they still take; for closures this is a guess that
+/// counts the directly nested `fun` parameters of the body. Other values are
+/// shown with a truncated rendering. When `tenv` is given, a name it has a
+/// type for is shown with that type instead of the arity, or after the
+/// value. Constructors are taken from `tenv` when given, with their full
+/// signature, and otherwise from the runtime environment with their arity.
+/// Empty groups are omitted.
+pub fn describe_environment(env: &Environment, tenv: Option<&TypeEnv>) -> String {
+    let types: HashMap<&str, String> = tenv
+        .map(|tenv| {
+            tenv.bindings()
+                .map(|(name, scheme)| (name.as_str(), canonicalize(scheme).ty.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut functions = Vec::new();
+    let mut values = Vec::new();
+    // Names starting with `_` are made by desugaring pattern bindings
+    for (name, value) in env.visible_bindings().into_iter().filter(|(name, _)| !name.starts_with('_')) {
+        let ty = types.get(name.as_str());
+        match (function_arity(&value), ty) {
+            (Some(_), Some(ty)) => functions.push((name, ty.clone())),
+            (Some(arity), None) => functions.push((name, format!("arity {arity}"))),
+            (None, Some(ty)) => values.push((name, format!("{} : {ty}", truncate(&value.to_string())))),
+            (None, None) => values.push((name, truncate(&value.to_string()))),
+        }
+    }
+
+    let constructors: Vec<(String, String)> = match tenv {
+        Some(tenv) => tenv
+            .constructors()
+            .map(|(name, info)| {
+                let mut result = info.sum_type_name.clone();
+                for param in &info.type_params {
+                    result.push(' ');
+                    result.push_str(param);
+                }
+                let signature = info
+                    .payload_types
+                    .iter()
+                    .map(ToString::to_string)
+                    .chain(std::iter::once(result))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                (name.clone(), signature)
+            })
+            .collect(),
+        None => env
+            .constructors()
+            .map(|(name, info)| (name.clone(), format!("{} (arity {})", info.type_name, info.arity)))
+            .collect(),
+    };
+
+    let mut output = String::new();
+    write_group(&mut output, "Functions", functions, " : ");
+    write_group(&mut output, "Values", values, " = ");
+    write_group(&mut output, "Constructors", constructors, " : ");
+    if output.is_empty() {
+        output.push_str("(empty environment)\n");
+    }
+    output
+}
+
+/// Number of arguments a function value still takes, or `None` for non-functions
+fn function_arity(value: &Value) -> Option<usize> {
+    match value {
+        Value::Closure(_, body, _) | Value::RecClosure(_, _, body, _) => Some(closure_arity(body)),
+        Value::Resolved(closure) => Some(closure.arity()),
+        Value::Partial(_, applied, arity, _) => Some(arity - applied),
+        Value::Builtin(builtin) => Some(builtin.arity - builtin.args.len()),
+        _ => None,
+    }
+}
+
+/// Shorten a rendering to at most `MAX_VALUE_WIDTH` characters
+fn truncate(rendering: &str) -> String {
+    if rendering.chars().count() <= MAX_VALUE_WIDTH {
+        rendering.to_string()
+    } else {
+        let prefix: String = rendering.chars().take(MAX_VALUE_WIDTH - 3).collect();
+        format!("{prefix}...")
+    }
+}
+
+/// Append a sorted, aligned group with a counted header; empty groups are skipped
+fn write_group(output: &mut String, title: &str, mut entries: Vec<(String, String)>, separator: &str) {
+    if entries.is_empty() {
+        return;
+    }
+    entries.sort();
+    let width = entries.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let _ = writeln!(output, "{title} ({}):", entries.len());
+    for (name, detail) in entries {
+        let _ = writeln!(output, "  {name:width$}{separator}{detail}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::TypeAnnotation;
+    use crate::eval::{ConstructorInfo, Fields};
+    use crate::parser::parse;
+    use crate::typechecker::ConstructorInfo as TypeConstructorInfo;
+    use crate::types::{Type, TypeScheme};
+
+    fn closure(source: &str) -> Value {
+        crate::eval::eval(&parse(source).unwrap(), &Environment::new()).unwrap()
+    }
+
+    fn sample_environment() -> Environment {
+        let mut env = Environment::new();
+        env.bind("twice".to_string(), closure("fun f -> fun x -> f (f x)"));
+        env.bind("inc".to_string(), closure("fun x -> x + 1"));
+        env.bind("zeta".to_string(), Value::Int(26));
+        env.bind("alpha".to_string(), Value::Bool(true));
+        let mut record = Fields::new();
+        record.insert("x".to_string(), Value::Int(1));
+        env.bind("point".to_string(), Value::Record(record));
+        env
+    }
+
+    #[test]
+    fn test_groups_and_ordering() {
+        let listing = describe_environment(&sample_environment(), None);
+        assert_eq!(
+            listing,
+            "Functions (2):\n\
+             \x20 inc   : arity 1\n\
+             \x20 twice : arity 2\n\
+             Values (3):\n\
+             \x20 alpha = true\n\
+             \x20 point = {x: 1}\n\
+             \x20 zeta  = 26\n"
+        );
+    }
+
+    #[test]
+    fn test_constructors_from_type_env() {
+        let mut tenv = TypeEnv::new();
+        tenv.register_constructor(
+            "Some".to_string(),
+            TypeConstructorInfo {
+                type_params: vec!["a".to_string()],
+                payload_types: vec![TypeAnnotation::Var("a".to_string())],
+                sum_type_name: "Option".to_string(),
+            },
+        );
+        let listing = describe_environment(&sample_environment(), Some(&tenv));
+        assert!(listing.ends_with("Constructors (1):\n  Some : a -> Option a\n"));
+    }
+
+    #[test]
+    fn test_types_from_type_env() {
+        let mut tenv = TypeEnv::new();
+        let scheme = |ty| TypeScheme { vars: vec![], row_vars: vec![], ty };
+        tenv.bind("inc".to_string(), scheme(Type::Fun(Box::new(Type::Int), Box::new(Type::Int))));
+        tenv.bind("zeta".to_string(), scheme(Type::Int));
+        let listing = describe_environment(&sample_environment(), Some(&tenv));
+        assert!(listing.contains("\n  inc   : Int -> Int\n"), "{listing}");
+        assert!(listing.contains("\n  twice : arity 2\n"), "{listing}");
+        assert!(listing.contains("\n  zeta  = 26 : Int\n"), "{listing}");
+        assert!(listing.contains("\n  alpha = true\n"), "{listing}");
+    }
+
+    #[test]
+    fn test_constructors_from_runtime_environment() {
+        let mut env = Environment::new();
+        env.register_constructor(
+            "None".to_string(),
+            ConstructorInfo { type_name: "Option".to_string(), arity: 0 },
+        );
+        assert_eq!(describe_environment(&env, None), "Constructors (1):\n  None : Option (arity 0)\n");
+    }
+
+    #[test]
+    fn test_builtin_arity_and_truncation() {
+        let mut env = Environment::with_builtins();
+        let partial = match env.lookup("clamp") {
+            Some(Value::Builtin(b)) => b.clone().apply(Value::Int(0)).unwrap(),
+            _ => panic!("clamp missing"),
+        };
+        env.bind("percent".to_string(), partial);
+        env.bind("big".to_string(), Value::Tuple((0..30).map(Value::Int).collect()));
+        let listing = describe_environment(&env, None);
+        let line = |name: &str| listing.lines().find(|line| line.starts_with(&format!("  {name} "))).unwrap();
+        assert!(line("clamp").ends_with(" : arity 3"));
+        assert!(line("percent").ends_with(" : arity 2"));
+        let big = line("big");
+        assert!(big.ends_with("..."));
+        assert_eq!(big.len(), "  big = ".len() + MAX_VALUE_WIDTH);
+    }
+
+    #[test]
+    fn test_empty_environment() {
+        assert_eq!(describe_environment(&Environment::new(), None), "(empty environment)\n");
+    }
+}
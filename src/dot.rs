@@ -1,8 +1,9 @@
-/// DOT format generator for `ParLang` AST visualization
+/// DOT format generator for `ParLang` AST and value visualization
 /// 
-/// This module provides functionality to convert `ParLang` Abstract Syntax Trees
-/// into Graphviz DOT format for visualization. The generated DOT files can be
-/// rendered using tools like `dot` or `graphviz`.
+/// This module provides functionality to convert `ParLang` Abstract Syntax Trees,
+/// and the runtime values programs produce, into Graphviz DOT format for
+/// visualization. The generated DOT files can be rendered using tools like
+/// `dot` or `graphviz`.
 /// 
 /// # Example
 /// 
@@ -13,24 +14,59 @@
 /// let source = "let x = 42 in x + 1";
 /// let expr = parse(source).unwrap();
 /// let dot_output = ast_to_dot(&expr);
-/// fs::write("ast.dot", dot_output).unwrap();
+/// let path = std::env::temp_dir().join("ast.dot");
+/// fs::write(&path, dot_output).unwrap();
+/// # fs::remove_file(&path).unwrap();
 /// ```
+/// 
+/// [`typed_ast_to_dot`] draws the same tree after type inference, with the
+/// type inferred for each expression after its label (`Int\n7 :: Int`).
 use crate::ast::{Expr, BinOp, Pattern, Literal};
+use crate::eval::Value;
+use crate::typechecker::{typecheck_nodes, TypeEnv, TypeError};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs;
 use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+/// ID of the sentinel node that stands in for subtrees cut off by a node limit
+const TRUNCATED_NODE_ID: &str = "truncated";
 
 /// Counter for generating unique node IDs in the DOT graph
 /// 
 /// This helper struct ensures each AST node gets a unique identifier
 /// when converting to DOT format. Node IDs are sequential strings
 /// in the format "node0", "node1", "node2", etc.
+/// 
+/// It also tracks the node budget of a size-limited dump: once `limit`
+/// nodes have been emitted, remaining subtrees are replaced by an edge to
+/// the single truncation sentinel node.
+/// 
+/// For a typed dump it holds the type to show for each expression, and
+/// records which node IDs were given one.
 struct NodeIdGenerator {
     counter: usize,
+    limit: Option<usize>,
+    truncated: bool,
+    types: HashMap<*const Expr, String>,
+    typed_nodes: HashMap<String, String>,
 }
 
 impl NodeIdGenerator {
-    /// Create a new generator starting from node0
-    fn new() -> Self {
-        NodeIdGenerator { counter: 0 }
+    /// Create a new generator starting from node0, allowing at most `limit` nodes
+    fn new(limit: Option<usize>) -> Self {
+        NodeIdGenerator { counter: 0, limit, truncated: false, types: HashMap::new(), typed_nodes: HashMap::new() }
+    }
+
+    /// Check whether the node budget is used up, recording the truncation if so
+    fn exhausted(&mut self) -> bool {
+        let exhausted = self.limit.is_some_and(|limit| self.counter >= limit);
+        self.truncated |= exhausted;
+        exhausted
     }
 
     /// Generate the next unique node ID
@@ -54,16 +90,180 @@ impl NodeIdGenerator {
 /// 
 /// A String containing the DOT representation of the AST
 pub fn ast_to_dot(expr: &Expr) -> String {
+    ast_to_dot_with_limit(expr, None).0
+}
+
+/// Convert an expression to DOT format, emitting at most `limit` nodes
+/// 
+/// When the AST has more nodes than the limit, the remaining subtrees are
+/// replaced by edges to a single sentinel node labelled with the number of
+/// nodes shown and the number the full dump has, pattern nodes included.
+/// 
+/// # Arguments
+/// 
+/// * `expr` - The expression to convert to DOT format
+/// * `limit` - Maximum number of AST nodes to emit (`None` = unlimited)
+/// 
+/// # Returns
+/// 
+/// The DOT representation, and the number of nodes of the full dump if it was truncated
+pub fn ast_to_dot_with_limit(expr: &Expr, limit: Option<usize>) -> (String, Option<usize>) {
     let mut output = String::new();
     output.push_str("digraph AST {\n");
     output.push_str("  node [shape=box, style=rounded];\n");
     output.push_str("  edge [fontsize=10];\n\n");
     
-    let mut gen = NodeIdGenerator::new();
+    let mut gen = NodeIdGenerator::new(limit);
     expr_to_dot(expr, &mut output, &mut gen);
     
+    let total = gen.truncated.then(|| dot_node_count(expr));
+    if let Some(total) = total {
+        let _ = writeln!(
+            output,
+            "  {TRUNCATED_NODE_ID} [label=\"truncated\\n{} of {total} nodes shown\", shape=note, style=dashed];",
+            gen.counter
+        );
+    }
+    
     output.push_str("}\n");
-    output
+    (output, total)
+}
+
+/// Number of nodes the full DOT dump of an expression has, pattern nodes included
+///
+/// This is the unit `limit` counts in [`ast_to_dot_with_limit`]. Like
+/// [`Expr::node_count`] it uses an explicit stack rather than rendering the
+/// dump, so it works on trees too deep to draw.
+pub fn dot_node_count(expr: &Expr) -> usize {
+    expr.subexpressions()
+        .map(|expr| {
+            // Bindings, arms, fields and constructors get a node of their own
+            1 + match expr {
+                Expr::Seq(bindings, _) => bindings.len(),
+                Expr::Match(_, arms) => arms.iter().map(|(pattern, _, _)| 1 + pattern_node_count(pattern)).sum(),
+                Expr::Record(fields) => fields.len(),
+                Expr::TypeDef { constructors, .. } => constructors.len(),
+                Expr::TypeAlias(_, ty_expr, _) => type_expr_node_count(ty_expr),
+                _ => 0,
+            }
+        })
+        .sum()
+}
+
+/// Number of nodes [`pattern_to_dot`] draws for a pattern
+fn pattern_node_count(pattern: &Pattern) -> usize {
+    let mut stack = vec![pattern];
+    let mut count = 0;
+    while let Some(pattern) = stack.pop() {
+        count += 1;
+        match pattern {
+            Pattern::Tuple(patterns) | Pattern::Constructor(_, patterns) => stack.extend(patterns),
+            Pattern::Record(fields) => {
+                count += fields.len();
+                stack.extend(fields.iter().map(|(_, pattern)| pattern));
+            }
+            Pattern::Literal(_) | Pattern::Var(_) | Pattern::Wildcard => {}
+        }
+    }
+    count
+}
+
+/// Number of nodes [`type_expr_to_dot`] draws for a type expression
+fn type_expr_node_count(ty_expr: &crate::ast::TypeExpr) -> usize {
+    let mut stack = vec![ty_expr];
+    let mut count = 0;
+    while let Some(ty_expr) = stack.pop() {
+        count += 1;
+        if let crate::ast::TypeExpr::Fun(arg, ret) = ty_expr {
+            stack.extend([arg.as_ref(), ret.as_ref()]);
+        }
+    }
+    count
+}
+
+/// Why a typed AST dump could not be made
+#[derive(Debug)]
+pub enum TypedDotError {
+    /// The expression is not well-typed
+    Type(Box<TypeError>),
+    /// The dump could not be written
+    Io(io::Error),
+}
+
+impl fmt::Display for TypedDotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypedDotError::Type(e) => write!(f, "Type error: {e}"),
+            TypedDotError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TypedDotError {}
+
+/// Convert an expression to DOT format, labelling each expression node with its inferred type
+/// 
+/// Inference runs in a copy of `env`. Nodes read `expr :: type`, for example
+/// `BinOp\n+ :: Int`; type variables are numbered from `t0` across the
+/// whole tree, so a variable shared by two nodes has the same name in both.
+/// Bindings, arms, patterns and type expressions are drawn as in
+/// [`ast_to_dot`], without a type.
+/// 
+/// # Errors
+/// 
+/// Returns the `TypeError` when `expr` is not well-typed.
+pub fn typed_ast_to_dot(expr: &Expr, env: &TypeEnv) -> Result<String, TypeError> {
+    let (_, types) = typecheck_nodes(expr, &mut env.clone())?;
+
+    let mut output = String::new();
+    output.push_str("digraph TypedAST {\n");
+    output.push_str("  node [shape=box, style=rounded];\n");
+    output.push_str("  edge [fontsize=10];\n\n");
+
+    let mut gen = NodeIdGenerator::new(None);
+    gen.types = expr
+        .subexpressions()
+        .zip(types)
+        .filter_map(|(node, ty)| Some((std::ptr::from_ref(node), escape_label(&ty?.to_string()))))
+        .collect();
+    let mut nodes = String::new();
+    expr_to_dot(expr, &mut nodes, &mut gen);
+
+    // A node's own line is `  id [label="..."];`, and edge lines are `  id -> ...`
+    for line in nodes.lines() {
+        let (node_id, rest) = line.trim_start().split_once(' ').unwrap_or_default();
+        match gen.typed_nodes.get(node_id) {
+            Some(ty) if rest.starts_with("[label=") => {
+                let (label, end) = line.split_at(line.len() - "\"];".len());
+                output.push_str(label);
+                output.push_str(" :: ");
+                output.push_str(ty);
+                output.push_str(end);
+                output.push('\n');
+            }
+            _ => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+
+    output.push_str("}\n");
+    Ok(output)
+}
+
+/// Write the typed DOT representation of an expression to a file
+/// 
+/// See [`typed_ast_to_dot`]. Missing parent directories are created, as by
+/// [`write_dot_file`].
+/// 
+/// # Errors
+/// 
+/// Returns [`TypedDotError::Type`] when `expr` is not well-typed, in which
+/// case no file is written, and [`TypedDotError::Io`] when writing fails.
+pub fn write_typed_ast_to_dot_file(expr: &Expr, env: &TypeEnv, path: &str) -> Result<(), TypedDotError> {
+    let dot_content = typed_ast_to_dot(expr, env).map_err(|e| TypedDotError::Type(Box::new(e)))?;
+    write_dot_file(path, &dot_content).map_err(TypedDotError::Io)
 }
 
 /// Write DOT representation of an expression to a file
@@ -80,18 +280,61 @@ pub fn ast_to_dot(expr: &Expr) -> String {
 /// Result indicating success or IO error when writing to file fails
 pub fn write_ast_to_dot_file(expr: &Expr, path: &str) -> io::Result<()> {
     let dot_content = ast_to_dot(expr);
-    std::fs::write(path, dot_content)
+    write_dot_file(path, &dot_content)
+}
+
+/// Write a size-limited DOT representation of an expression to a file
+/// 
+/// See [`ast_to_dot_with_limit`] for how truncation is represented. Missing
+/// parent directories are created, as by [`write_dot_file`].
+/// 
+/// # Returns
+/// 
+/// The number of nodes of the full dump if the output was truncated
+/// 
+/// # Errors
+/// 
+/// Returns an IO error when writing to the file fails
+pub fn write_ast_to_dot_file_with_limit(expr: &Expr, path: &str, limit: Option<usize>) -> io::Result<Option<usize>> {
+    let (dot_content, total) = ast_to_dot_with_limit(expr, limit);
+    write_dot_file(path, &dot_content)?;
+    Ok(total)
+}
+
+/// Write a dump to `path`, creating its missing parent directories
+/// 
+/// # Errors
+/// 
+/// Returns an IO error when a directory cannot be created or the file cannot
+/// be written; its message starts with the absolute path, so a relative
+/// `path` is reported unambiguously.
+pub fn write_dot_file(path: &str, content: &str) -> io::Result<()> {
+    let path = Path::new(path);
+    let written = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent).and_then(|()| fs::write(path, content)),
+        _ => fs::write(path, content),
+    };
+    written.map_err(|e| {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        io::Error::new(e.kind(), format!("{}: {e}", absolute.display()))
+    })
 }
 
 fn expr_to_dot(expr: &Expr, output: &mut String, gen: &mut NodeIdGenerator) -> String {
+    if gen.exhausted() {
+        return TRUNCATED_NODE_ID.to_string();
+    }
     let node_id = gen.next();
+    if let Some(ty) = gen.types.get(&std::ptr::from_ref(expr)) {
+        gen.typed_nodes.insert(node_id.clone(), ty.clone());
+    }
     
     match expr {
         Expr::Int(n) => {
-            output.push_str(&format!("  {node_id} [label=\"Int\\n{n}\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Int\\n{n}\"];");
         }
         Expr::Bool(b) => {
-            output.push_str(&format!("  {node_id} [label=\"Bool\\n{b}\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Bool\\n{b}\"];");
         }
         Expr::Char(c) => {
             let label = match c {
@@ -102,32 +345,49 @@ fn expr_to_dot(expr: &Expr, output: &mut String, gen: &mut NodeIdGenerator) -> S
                 '\'' => "\\\\'".to_string(),
                 _ => c.to_string(),
             };
-            output.push_str(&format!("  {node_id} [label=\"Char\\n'{label}'\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Char\\n'{label}'\"];");
         }
         Expr::Float(fl) => {
-            output.push_str(&format!("  {node_id} [label=\"Float\\n{fl}\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Float\\n{fl}\"];");
         }
         Expr::Byte(b) => {
-            output.push_str(&format!("  {node_id} [label=\"Byte\\n{b}b\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Byte\\n{b}b\"];");
         }
         Expr::Var(name) => {
-            output.push_str(&format!("  {} [label=\"Var\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"Var\\n{}\"];", node_id, escape_label(name));
+        }
+        Expr::EmptyBody => {
+            let _ = writeln!(output, "  {node_id} [label=\"EmptyBody\"];");
         }
         Expr::BinOp(op, left, right) => {
-            output.push_str(&format!("  {} [label=\"BinOp\\n{}\"];\n", node_id, binop_label(*op)));
+            let _ = writeln!(output, "  {} [label=\"BinOp\\n{}\"];", node_id, binop_label(*op));
             let left_id = expr_to_dot(left, output, gen);
             let right_id = expr_to_dot(right, output, gen);
-            output.push_str(&format!("  {node_id} -> {left_id} [label=\"left\"];\n"));
-            output.push_str(&format!("  {node_id} -> {right_id} [label=\"right\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {left_id} [label=\"left\"];");
+            let _ = writeln!(output, "  {node_id} -> {right_id} [label=\"right\"];");
         }
         Expr::If(cond, then_branch, else_branch) => {
-            output.push_str(&format!("  {node_id} [label=\"If\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"If\"];");
             let cond_id = expr_to_dot(cond, output, gen);
             let then_id = expr_to_dot(then_branch, output, gen);
             let else_id = expr_to_dot(else_branch, output, gen);
-            output.push_str(&format!("  {node_id} -> {cond_id} [label=\"cond\"];\n"));
-            output.push_str(&format!("  {node_id} -> {then_id} [label=\"then\"];\n"));
-            output.push_str(&format!("  {node_id} -> {else_id} [label=\"else\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {cond_id} [label=\"cond\"];");
+            let _ = writeln!(output, "  {node_id} -> {then_id} [label=\"then\"];");
+            let _ = writeln!(output, "  {node_id} -> {else_id} [label=\"else\"];");
+        }
+        Expr::IfChain(branches, else_branch) => {
+            let _ = writeln!(output, "  {node_id} [label=\"IfChain\"];");
+            for (i, (cond, branch)) in branches.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
+                let cond_id = expr_to_dot(cond, output, gen);
+                let branch_id = expr_to_dot(branch, output, gen);
+                let _ = writeln!(output, "  {node_id} -> {cond_id} [label=\"cond{i}\"];");
+                let _ = writeln!(output, "  {node_id} -> {branch_id} [label=\"then{i}\"];");
+            }
+            let else_id = expr_to_dot(else_branch, output, gen);
+            let _ = writeln!(output, "  {node_id} -> {else_id} [label=\"else\"];");
         }
         Expr::Let(name, ty_ann, value, body) => {
             let label = if let Some(ty) = ty_ann {
@@ -135,11 +395,11 @@ fn expr_to_dot(expr: &Expr, output: &mut String, gen: &mut NodeIdGenerator) -> S
             } else {
                 format!("Let\\n{}", escape_label(name))
             };
-            output.push_str(&format!("  {} [label=\"{}\"];\n", node_id, label));
+            let _ = writeln!(output, "  {node_id} [label=\"{label}\"];");
             let value_id = expr_to_dot(value, output, gen);
             let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {value_id} [label=\"value\"];\n"));
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {value_id} [label=\"value\"];");
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
         }
         Expr::Fun(param, ty_ann, body) => {
             let label = if let Some(ty) = ty_ann {
@@ -147,151 +407,201 @@ fn expr_to_dot(expr: &Expr, output: &mut String, gen: &mut NodeIdGenerator) -> S
             } else {
                 format!("Fun\\n{}", escape_label(param))
             };
-            output.push_str(&format!("  {} [label=\"{}\"];\n", node_id, label));
+            let _ = writeln!(output, "  {node_id} [label=\"{label}\"];");
             let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
         }
         Expr::App(func, arg) => {
-            output.push_str(&format!("  {node_id} [label=\"App\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"App\"];");
             let func_id = expr_to_dot(func, output, gen);
             let arg_id = expr_to_dot(arg, output, gen);
-            output.push_str(&format!("  {node_id} -> {func_id} [label=\"func\"];\n"));
-            output.push_str(&format!("  {node_id} -> {arg_id} [label=\"arg\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {func_id} [label=\"func\"];");
+            let _ = writeln!(output, "  {node_id} -> {arg_id} [label=\"arg\"];");
         }
         Expr::Load(filepath, body) => {
-            output.push_str(&format!("  {} [label=\"Load\\n{}\"];\n", node_id, escape_label(filepath)));
-            let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {} [label=\"Load\\n{}\"];", node_id, escape_label(filepath));
+            if **body != Expr::EmptyBody {
+                let body_id = expr_to_dot(body, output, gen);
+                let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
+            }
+        }
+        Expr::LoadAs(filepath, namespace, body) => {
+            let label = escape_label(&format!("{filepath} as {namespace}"));
+            let _ = writeln!(output, "  {node_id} [label=\"LoadAs\\n{label}\"];");
+            if **body != Expr::EmptyBody {
+                let body_id = expr_to_dot(body, output, gen);
+                let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
+            }
         }
         Expr::Seq(bindings, body) => {
-            output.push_str(&format!("  {node_id} [label=\"Seq\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Seq\"];");
             for (i, (name, ty_ann, value)) in bindings.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let binding_id = gen.next();
                 let label = if let Some(ty) = ty_ann {
                     format!("Binding\\n{} : {}", escape_label(name), ty)
                 } else {
                     format!("Binding\\n{}", escape_label(name))
                 };
-                output.push_str(&format!("  {} [label=\"{}\"];\n", binding_id, label));
+                let _ = writeln!(output, "  {binding_id} [label=\"{label}\"];");
                 let value_id = expr_to_dot(value, output, gen);
-                output.push_str(&format!("  {node_id} -> {binding_id} [label=\"binding {i}\"];\n"));
-                output.push_str(&format!("  {binding_id} -> {value_id} [label=\"value\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {binding_id} [label=\"binding {i}\"];");
+                let _ = writeln!(output, "  {binding_id} -> {value_id} [label=\"value\"];");
+            }
+            // A body-less program has no body node
+            if **body != Expr::EmptyBody {
+                let body_id = expr_to_dot(body, output, gen);
+                let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
             }
-            let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
         }
         Expr::Rec(name, body) => {
-            output.push_str(&format!("  {} [label=\"Rec\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"Rec\\n{}\"];", node_id, escape_label(name));
             let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
         }
         Expr::Match(scrutinee, arms) => {
-            output.push_str(&format!("  {node_id} [label=\"Match\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Match\"];");
             let scrutinee_id = expr_to_dot(scrutinee, output, gen);
-            output.push_str(&format!("  {node_id} -> {scrutinee_id} [label=\"scrutinee\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {scrutinee_id} [label=\"scrutinee\"];");
             
-            for (i, (pattern, result)) in arms.iter().enumerate() {
+            for (i, (pattern, guard, result)) in arms.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let arm_id = gen.next();
-                output.push_str(&format!("  {arm_id} [label=\"Arm {i}\"];\n"));
+                let _ = writeln!(output, "  {arm_id} [label=\"Arm {i}\"];");
                 let pattern_id = pattern_to_dot(pattern, output, gen);
                 let result_id = expr_to_dot(result, output, gen);
-                output.push_str(&format!("  {node_id} -> {arm_id} [label=\"arm {i}\"];\n"));
-                output.push_str(&format!("  {arm_id} -> {pattern_id} [label=\"pattern\"];\n"));
-                output.push_str(&format!("  {arm_id} -> {result_id} [label=\"result\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {arm_id} [label=\"arm {i}\"];");
+                let _ = writeln!(output, "  {arm_id} -> {pattern_id} [label=\"pattern\"];");
+                if let Some(guard) = guard {
+                    let guard_id = expr_to_dot(guard, output, gen);
+                    let _ = writeln!(output, "  {arm_id} -> {guard_id} [label=\"guard\"];");
+                }
+                let _ = writeln!(output, "  {arm_id} -> {result_id} [label=\"result\"];");
             }
         }
         Expr::Tuple(elements) => {
-            output.push_str(&format!("  {node_id} [label=\"Tuple\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Tuple\"];");
             for (i, elem) in elements.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let elem_id = expr_to_dot(elem, output, gen);
-                output.push_str(&format!("  {node_id} -> {elem_id} [label=\"elem {i}\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {elem_id} [label=\"elem {i}\"];");
             }
         }
         Expr::TupleProj(tuple, index) => {
-            output.push_str(&format!("  {node_id} [label=\"TupleProj\\n{index}\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"TupleProj\\n{index}\"];");
             let tuple_id = expr_to_dot(tuple, output, gen);
-            output.push_str(&format!("  {node_id} -> {tuple_id} [label=\"tuple\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {tuple_id} [label=\"tuple\"];");
         }
         Expr::TypeAlias(name, ty_expr, body) => {
-            output.push_str(&format!("  {} [label=\"TypeAlias\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"TypeAlias\\n{}\"];", node_id, escape_label(name));
             let type_id = type_expr_to_dot(ty_expr, output, gen);
             let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {type_id} [label=\"type\"];\n"));
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {type_id} [label=\"type\"];");
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
         }
         Expr::Record(fields) => {
-            output.push_str(&format!("  {node_id} [label=\"Record\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Record\"];");
             for (i, (name, expr)) in fields.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let field_id = gen.next();
-                output.push_str(&format!("  {} [label=\"Field\\n{}\"];\n", field_id, escape_label(name)));
+                let _ = writeln!(output, "  {} [label=\"Field\\n{}\"];", field_id, escape_label(name));
                 let expr_id = expr_to_dot(expr, output, gen);
-                output.push_str(&format!("  {node_id} -> {field_id} [label=\"field {i}\"];\n"));
-                output.push_str(&format!("  {field_id} -> {expr_id} [label=\"value\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {field_id} [label=\"field {i}\"];");
+                let _ = writeln!(output, "  {field_id} -> {expr_id} [label=\"value\"];");
             }
         }
         Expr::FieldAccess(record, field) => {
-            output.push_str(&format!("  {} [label=\"FieldAccess\\n{}\"];\n", node_id, escape_label(field)));
+            let _ = writeln!(output, "  {} [label=\"FieldAccess\\n{}\"];", node_id, escape_label(field));
             let record_id = expr_to_dot(record, output, gen);
-            output.push_str(&format!("  {node_id} -> {record_id} [label=\"record\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {record_id} [label=\"record\"];");
         }
         Expr::TypeDef { name, type_params, constructors, body } => {
             let params_str = type_params.join(" ");
-            output.push_str(&format!("  {} [label=\"TypeDef\\n{}\\n{}\"];\n", node_id, escape_label(name), escape_label(&params_str)));
+            let _ = writeln!(output, "  {} [label=\"TypeDef\\n{}\\n{}\"];", node_id, escape_label(name), escape_label(&params_str));
             
             // Add constructor nodes
             for (ctor_name, _ctor_types) in constructors {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let ctor_id = gen.next();
-                output.push_str(&format!("  {} [label=\"Constructor\\n{}\"];\n", ctor_id, escape_label(ctor_name)));
-                output.push_str(&format!("  {node_id} -> {ctor_id} [label=\"ctor\"];\n"));
+                let _ = writeln!(output, "  {} [label=\"Constructor\\n{}\"];", ctor_id, escape_label(ctor_name));
+                let _ = writeln!(output, "  {node_id} -> {ctor_id} [label=\"ctor\"];");
             }
             
             let body_id = expr_to_dot(body, output, gen);
-            output.push_str(&format!("  {node_id} -> {body_id} [label=\"body\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
         }
         Expr::Constructor(name, args) => {
-            output.push_str(&format!("  {} [label=\"Constructor\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"Constructor\\n{}\"];", node_id, escape_label(name));
             for (i, arg) in args.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let arg_id = expr_to_dot(arg, output, gen);
-                output.push_str(&format!("  {node_id} -> {arg_id} [label=\"arg{}\"];\n", i));
+                let _ = writeln!(output, "  {node_id} -> {arg_id} [label=\"arg{i}\"];");
             }
         }
         Expr::Array(elements) => {
-            output.push_str(&format!("  {node_id} [label=\"Array\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Array\"];");
             for (i, elem) in elements.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let elem_id = expr_to_dot(elem, output, gen);
-                output.push_str(&format!("  {node_id} -> {elem_id} [label=\"elem{}\"];\n", i));
+                let _ = writeln!(output, "  {node_id} -> {elem_id} [label=\"elem{i}\"];");
             }
         }
         Expr::ArrayIndex(arr, index) => {
-            output.push_str(&format!("  {node_id} [label=\"ArrayIndex\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"ArrayIndex\"];");
             let arr_id = expr_to_dot(arr, output, gen);
             let index_id = expr_to_dot(index, output, gen);
-            output.push_str(&format!("  {node_id} -> {arr_id} [label=\"array\"];\n"));
-            output.push_str(&format!("  {node_id} -> {index_id} [label=\"index\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {arr_id} [label=\"array\"];");
+            let _ = writeln!(output, "  {node_id} -> {index_id} [label=\"index\"];");
         }
         Expr::Ref(expr) => {
-            output.push_str(&format!("  {node_id} [label=\"Ref\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Ref\"];");
             let expr_id = expr_to_dot(expr, output, gen);
-            output.push_str(&format!("  {node_id} -> {expr_id} [label=\"value\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {expr_id} [label=\"value\"];");
         }
         Expr::Deref(expr) => {
-            output.push_str(&format!("  {node_id} [label=\"Deref\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Deref\"];");
+            let expr_id = expr_to_dot(expr, output, gen);
+            let _ = writeln!(output, "  {node_id} -> {expr_id} [label=\"ref\"];");
+        }
+        Expr::Neg(expr) => {
+            let _ = writeln!(output, "  {node_id} [label=\"Neg\"];");
             let expr_id = expr_to_dot(expr, output, gen);
-            output.push_str(&format!("  {node_id} -> {expr_id} [label=\"ref\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {expr_id} [label=\"operand\"];");
         }
         Expr::RefAssign(ref_expr, value) => {
-            output.push_str(&format!("  {node_id} [label=\"RefAssign\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"RefAssign\"];");
             let ref_id = expr_to_dot(ref_expr, output, gen);
             let value_id = expr_to_dot(value, output, gen);
-            output.push_str(&format!("  {node_id} -> {ref_id} [label=\"ref\"];\n"));
-            output.push_str(&format!("  {node_id} -> {value_id} [label=\"value\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {ref_id} [label=\"ref\"];");
+            let _ = writeln!(output, "  {node_id} -> {value_id} [label=\"value\"];");
         }
         Expr::Range(start, end) => {
-            output.push_str(&format!("  {node_id} [label=\"Range\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Range\"];");
             let start_id = expr_to_dot(start, output, gen);
             let end_id = expr_to_dot(end, output, gen);
-            output.push_str(&format!("  {node_id} -> {start_id} [label=\"start\"];\n"));
-            output.push_str(&format!("  {node_id} -> {end_id} [label=\"end\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {start_id} [label=\"start\"];");
+            let _ = writeln!(output, "  {node_id} -> {end_id} [label=\"end\"];");
+        }
+        Expr::Try(body, name, handler) => {
+            let _ = writeln!(output, "  {} [label=\"Try\\ncatch {}\"];", node_id, escape_label(name));
+            let body_id = expr_to_dot(body, output, gen);
+            let handler_id = expr_to_dot(handler, output, gen);
+            let _ = writeln!(output, "  {node_id} -> {body_id} [label=\"body\"];");
+            let _ = writeln!(output, "  {node_id} -> {handler_id} [label=\"handler\"];");
         }
     }
     
@@ -299,24 +609,27 @@ fn expr_to_dot(expr: &Expr, output: &mut String, gen: &mut NodeIdGenerator) -> S
 }
 
 fn type_expr_to_dot(ty_expr: &crate::ast::TypeExpr, output: &mut String, gen: &mut NodeIdGenerator) -> String {
+    if gen.exhausted() {
+        return TRUNCATED_NODE_ID.to_string();
+    }
     let node_id = gen.next();
     
     match ty_expr {
         crate::ast::TypeExpr::Int => {
-            output.push_str(&format!("  {node_id} [label=\"Type\\nInt\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Type\\nInt\"];");
         }
         crate::ast::TypeExpr::Bool => {
-            output.push_str(&format!("  {node_id} [label=\"Type\\nBool\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Type\\nBool\"];");
         }
         crate::ast::TypeExpr::Fun(arg, ret) => {
-            output.push_str(&format!("  {node_id} [label=\"Type\\nFun\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Type\\nFun\"];");
             let arg_id = type_expr_to_dot(arg, output, gen);
             let ret_id = type_expr_to_dot(ret, output, gen);
-            output.push_str(&format!("  {node_id} -> {arg_id} [label=\"arg\"];\n"));
-            output.push_str(&format!("  {node_id} -> {ret_id} [label=\"ret\"];\n"));
+            let _ = writeln!(output, "  {node_id} -> {arg_id} [label=\"arg\"];");
+            let _ = writeln!(output, "  {node_id} -> {ret_id} [label=\"ret\"];");
         }
         crate::ast::TypeExpr::Alias(name) => {
-            output.push_str(&format!("  {} [label=\"TypeAlias\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"TypeAlias\\n{}\"];", node_id, escape_label(name));
         }
     }
     
@@ -324,6 +637,9 @@ fn type_expr_to_dot(ty_expr: &crate::ast::TypeExpr, output: &mut String, gen: &m
 }
 
 fn pattern_to_dot(pattern: &Pattern, output: &mut String, gen: &mut NodeIdGenerator) -> String {
+    if gen.exhausted() {
+        return TRUNCATED_NODE_ID.to_string();
+    }
     let node_id = gen.next();
     
     match pattern {
@@ -344,36 +660,45 @@ fn pattern_to_dot(pattern: &Pattern, output: &mut String, gen: &mut NodeIdGenera
                 }
                 Literal::Byte(b) => format!("Literal\\nByte {b}b"),
             };
-            output.push_str(&format!("  {node_id} [label=\"{label}\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"{label}\"];");
         }
         Pattern::Var(name) => {
-            output.push_str(&format!("  {} [label=\"Var\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"Var\\n{}\"];", node_id, escape_label(name));
         }
         Pattern::Wildcard => {
-            output.push_str(&format!("  {node_id} [label=\"Wildcard\\n_\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"Wildcard\\n_\"];");
         }
         Pattern::Tuple(patterns) => {
-            output.push_str(&format!("  {node_id} [label=\"TuplePattern\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"TuplePattern\"];");
             for (i, pat) in patterns.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let pat_id = pattern_to_dot(pat, output, gen);
-                output.push_str(&format!("  {node_id} -> {pat_id} [label=\"elem {i}\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {pat_id} [label=\"elem {i}\"];");
             }
         }
         Pattern::Record(fields) => {
-            output.push_str(&format!("  {node_id} [label=\"RecordPattern\"];\n"));
+            let _ = writeln!(output, "  {node_id} [label=\"RecordPattern\"];");
             for (i, (name, pat)) in fields.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let field_id = gen.next();
-                output.push_str(&format!("  {} [label=\"Field\\n{}\"];\n", field_id, escape_label(name)));
+                let _ = writeln!(output, "  {} [label=\"Field\\n{}\"];", field_id, escape_label(name));
                 let pat_id = pattern_to_dot(pat, output, gen);
-                output.push_str(&format!("  {node_id} -> {field_id} [label=\"field {i}\"];\n"));
-                output.push_str(&format!("  {field_id} -> {pat_id} [label=\"pattern\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {field_id} [label=\"field {i}\"];");
+                let _ = writeln!(output, "  {field_id} -> {pat_id} [label=\"pattern\"];");
             }
         }
         Pattern::Constructor(name, patterns) => {
-            output.push_str(&format!("  {} [label=\"ConstructorPattern\\n{}\"];\n", node_id, escape_label(name)));
+            let _ = writeln!(output, "  {} [label=\"ConstructorPattern\\n{}\"];", node_id, escape_label(name));
             for (i, pat) in patterns.iter().enumerate() {
+                if truncate_children(&node_id, output, gen) {
+                    break;
+                }
                 let pat_id = pattern_to_dot(pat, output, gen);
-                output.push_str(&format!("  {node_id} -> {pat_id} [label=\"arg {i}\"];\n"));
+                let _ = writeln!(output, "  {node_id} -> {pat_id} [label=\"arg {i}\"];");
             }
         }
     }
@@ -381,6 +706,133 @@ fn pattern_to_dot(pattern: &Pattern, output: &mut String, gen: &mut NodeIdGenera
     node_id
 }
 
+/// Options for rendering runtime values with [`value_to_dot_string`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Deepest level whose payload is expanded (`None` = unlimited)
+    /// 
+    /// The root value is at depth 0. A compound value below this depth is
+    /// collapsed into a single dashed summary node.
+    pub max_depth: Option<usize>,
+}
+
+/// Convert a runtime value to DOT format
+/// 
+/// Constructors, records, tuples and arrays become nodes with one edge per
+/// payload element, labelled with the field name or index. A reference
+/// becomes a node pointing to its current contents; references sharing the
+/// same cell are drawn as one node, so sharing (and cycles) show up as
+/// several edges into it. Closures and built-ins are leaves.
+/// 
+/// # Example
+/// 
+/// ```
+/// use parlang::{dot::{value_to_dot_string, DotOptions}, eval, parse, Environment};
+/// 
+/// let value = eval(&parse("(1, true)").unwrap(), &Environment::new()).unwrap();
+/// let dot = value_to_dot_string(&value, &DotOptions::default());
+/// assert!(dot.contains("label=\"Tuple\""));
+/// ```
+pub fn value_to_dot_string(value: &Value, options: &DotOptions) -> String {
+    let mut output = String::new();
+    output.push_str("digraph Value {\n");
+    output.push_str("  node [shape=box, style=rounded];\n");
+    output.push_str("  edge [fontsize=10];\n\n");
+
+    let mut renderer = ValueRenderer { options: *options, gen: NodeIdGenerator::new(None), refs: HashMap::new() };
+    renderer.value_to_dot(value, 0, &mut output);
+
+    output.push_str("}\n");
+    output
+}
+
+/// Write DOT representation of a runtime value to a file
+/// 
+/// # Errors
+/// 
+/// Returns an IO error when writing to the file fails
+pub fn write_value_to_dot_file(value: &Value, path: &str, options: &DotOptions) -> io::Result<()> {
+    write_dot_file(path, &value_to_dot_string(value, options))
+}
+
+/// State for rendering one value graph
+struct ValueRenderer {
+    options: DotOptions,
+    gen: NodeIdGenerator,
+    /// Node already emitted for each reference cell, keyed by cell address
+    refs: HashMap<*const RefCell<Value>, String>,
+}
+
+impl ValueRenderer {
+    fn value_to_dot(&mut self, value: &Value, depth: usize, output: &mut String) -> String {
+        if let Value::Reference(_, cell) = value {
+            if let Some(node_id) = self.refs.get(&Rc::as_ptr(cell)) {
+                return node_id.clone();
+            }
+        }
+
+        let node_id = self.gen.next();
+        let (label, children) = Self::describe(value);
+        let hidden = if matches!(value, Value::Reference(..)) { 1 } else { children.len() };
+        if hidden > 0 && self.options.max_depth.is_some_and(|max| depth >= max) {
+            let _ = writeln!(output,
+                "  {node_id} [label=\"{}\\n({hidden} collapsed)\", shape=note, style=dashed];",
+                escape_label(&label)
+            );
+            return node_id;
+        }
+        let _ = writeln!(output, "  {node_id} [label=\"{}\"];", escape_label(&label));
+
+        if let Value::Reference(_, cell) = value {
+            // Record the node before visiting the contents so cycles end here
+            self.refs.insert(Rc::as_ptr(cell), node_id.clone());
+            let child_id = self.value_to_dot(&cell.borrow(), depth + 1, output);
+            let _ = writeln!(output, "  {node_id} -> {child_id};");
+            return node_id;
+        }
+        for (edge_label, child) in children {
+            let child_id = self.value_to_dot(child, depth + 1, output);
+            let _ = writeln!(output, "  {node_id} -> {child_id} [label=\"{}\"];", escape_label(&edge_label));
+        }
+        node_id
+    }
+
+    /// Node label and labelled payload of a value (references excluded)
+    fn describe(value: &Value) -> (String, Vec<(String, &Value)>) {
+        fn indexed(values: &[Value]) -> Vec<(String, &Value)> {
+            values.iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect()
+        }
+        match value {
+            Value::Variant(name, payload) => (name.clone(), indexed(payload)),
+            Value::Tuple(values) => ("Tuple".to_string(), indexed(values)),
+            Value::Array(size, values) => (format!("Array[{size}]"), indexed(values)),
+            Value::Record(fields) => {
+                let mut fields: Vec<_> = fields.iter().map(|(name, v)| (name.clone(), v)).collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                ("Record".to_string(), fields)
+            }
+            // The contents are rendered separately so shared cells are drawn once
+            Value::Reference(id, _) => (format!("ref #{id}"), vec![]),
+            Value::Closure(param, _, _) => (format!("fun {param}"), vec![]),
+            Value::RecClosure(name, param, _, _) => (format!("rec {name}\nfun {param}"), vec![]),
+            other => (other.to_string(), vec![]),
+        }
+    }
+}
+
+/// Check the node budget before emitting another child of `node_id`
+/// 
+/// When the budget is used up, links the parent to the truncation sentinel
+/// once and returns `true` so the caller stops emitting further children.
+fn truncate_children(node_id: &str, output: &mut String, gen: &mut NodeIdGenerator) -> bool {
+    if gen.exhausted() {
+        let _ = writeln!(output, "  {node_id} -> {TRUNCATED_NODE_ID};");
+        true
+    } else {
+        false
+    }
+}
+
 fn binop_label(op: BinOp) -> &'static str {
     match op {
         BinOp::Add => "+",
@@ -393,6 +845,7 @@ fn binop_label(op: BinOp) -> &'static str {
         BinOp::Le => "<=",
         BinOp::Gt => ">",
         BinOp::Ge => ">=",
+        BinOp::Concat => "^",
     }
 }
 
@@ -458,6 +911,22 @@ mod tests {
         assert!(dot.contains("[label=\"else\"]"));
     }
 
+    #[test]
+    fn test_if_chain_expr() {
+        let expr = Expr::IfChain(
+            vec![
+                (Expr::Bool(false), Expr::Int(1)),
+                (Expr::Bool(true), Expr::Int(2)),
+            ],
+            Box::new(Expr::Int(3)),
+        );
+        let dot = ast_to_dot(&expr);
+        assert!(dot.contains("[label=\"IfChain\"]"));
+        assert!(dot.contains("[label=\"cond0\"]"));
+        assert!(dot.contains("[label=\"then1\"]"));
+        assert!(dot.contains("[label=\"else\"]"));
+    }
+
     #[test]
     fn test_let_expr() {
         let expr = Expr::Let(
@@ -515,8 +984,8 @@ mod tests {
     #[test]
     fn test_match_expr() {
         let arms = vec![
-            (Pattern::Literal(Literal::Int(0)), Expr::Int(1)),
-            (Pattern::Var("n".to_string()), Expr::Var("n".to_string())),
+            (Pattern::Literal(Literal::Int(0)), None, Expr::Int(1)),
+            (Pattern::Var("n".to_string()), None, Expr::Var("n".to_string())),
         ];
         let expr = Expr::Match(Box::new(Expr::Var("x".to_string())), arms);
         let dot = ast_to_dot(&expr);
@@ -546,6 +1015,18 @@ mod tests {
         assert!(dot.contains("[label=\"Binding\\ny\"]"));
     }
 
+    #[test]
+    fn test_seq_without_body() {
+        let expr = Expr::Seq(
+            vec![("x".to_string(), None, Expr::Int(42))],
+            Box::new(Expr::EmptyBody),
+        );
+        let dot = ast_to_dot(&expr);
+        assert!(!dot.contains("[label=\"body\"]"));
+        assert!(!dot.contains("EmptyBody"));
+        assert!(!dot.contains("Int\\n0"));
+    }
+
     #[test]
     fn test_load_expr() {
         let expr = Expr::Load(
@@ -600,9 +1081,9 @@ mod tests {
         let ops = vec![
             BinOp::Add, BinOp::Sub, BinOp::Mul, BinOp::Div,
             BinOp::Eq, BinOp::Neq, BinOp::Lt, BinOp::Le,
-            BinOp::Gt, BinOp::Ge,
+            BinOp::Gt, BinOp::Ge, BinOp::Concat,
         ];
-        let expected = vec!["+", "-", "*", "/", "==", "!=", "<", "<=", ">", ">="];
+        let expected = vec!["+", "-", "*", "/", "==", "!=", "<", "<=", ">", ">=", "^"];
         
         for (op, expected_label) in ops.iter().zip(expected.iter()) {
             let label = binop_label(*op);
@@ -614,7 +1095,7 @@ mod tests {
     fn test_pattern_literal() {
         let pattern = Pattern::Literal(Literal::Int(42));
         let mut output = String::new();
-        let mut gen = NodeIdGenerator::new();
+        let mut gen = NodeIdGenerator::new(None);
         let node_id = pattern_to_dot(&pattern, &mut output, &mut gen);
         assert_eq!(node_id, "node0");
         assert!(output.contains("[label=\"Literal\\nInt 42\"]"));
@@ -624,7 +1105,7 @@ mod tests {
     fn test_pattern_var() {
         let pattern = Pattern::Var("x".to_string());
         let mut output = String::new();
-        let mut gen = NodeIdGenerator::new();
+        let mut gen = NodeIdGenerator::new(None);
         pattern_to_dot(&pattern, &mut output, &mut gen);
         assert!(output.contains("[label=\"Var\\nx\"]"));
     }
@@ -633,7 +1114,7 @@ mod tests {
     fn test_pattern_wildcard() {
         let pattern = Pattern::Wildcard;
         let mut output = String::new();
-        let mut gen = NodeIdGenerator::new();
+        let mut gen = NodeIdGenerator::new(None);
         pattern_to_dot(&pattern, &mut output, &mut gen);
         assert!(output.contains("[label=\"Wildcard\\n_\"]"));
     }
@@ -645,10 +1126,207 @@ mod tests {
             Pattern::Var("x".to_string()),
         ]);
         let mut output = String::new();
-        let mut gen = NodeIdGenerator::new();
+        let mut gen = NodeIdGenerator::new(None);
         pattern_to_dot(&pattern, &mut output, &mut gen);
         assert!(output.contains("[label=\"TuplePattern\"]"));
         assert!(output.contains("[label=\"Literal\\nInt 1\"]"));
         assert!(output.contains("[label=\"Var\\nx\"]"));
     }
+
+    #[test]
+    fn test_limit_truncates_large_ast() {
+        // A flat tuple of 9_999 elements: 10_000 nodes in total
+        let expr = Expr::Tuple((0..9_999).map(Expr::Int).collect());
+        assert_eq!(expr.node_count(), 10_000);
+
+        let (dot, total) = ast_to_dot_with_limit(&expr, Some(100));
+        assert_eq!(total, Some(10_000));
+        assert!(dot.contains("truncated [label=\"truncated\\n100 of 10000 nodes shown\""));
+        let node_entries = dot.lines().filter(|line| line.starts_with("  node") && !line.contains("->")).count();
+        // 100 AST nodes plus the `node [shape=...]` default attribute line
+        assert_eq!(node_entries, 101);
+        // The elements past the budget share a single edge to the sentinel
+        assert_eq!(dot.matches("-> truncated").count(), 1);
+    }
+
+    #[test]
+    fn test_limit_not_reached() {
+        let expr = Expr::BinOp(BinOp::Add, Box::new(Expr::Int(1)), Box::new(Expr::Int(2)));
+        let (dot, total) = ast_to_dot_with_limit(&expr, Some(3));
+        assert_eq!(total, None);
+        assert_eq!(dot, ast_to_dot(&expr));
+    }
+
+    #[test]
+    fn test_limit_counts_pattern_nodes() {
+        let expr = Expr::Match(
+            Box::new(Expr::Var("p".to_string())),
+            vec![(Pattern::Wildcard, None, Expr::Int(0))],
+        );
+        let (dot, total) = ast_to_dot_with_limit(&expr, Some(2));
+        assert_eq!(total, Some(5));
+        assert!(dot.contains("-> truncated;"));
+        assert!(!dot.contains("Wildcard"));
+    }
+
+    #[test]
+    fn test_limit_sentinel_counts_pattern_nodes() {
+        let expr = crate::parser::parse("match x with | (a, b, c, d, e, f, g) -> 1").unwrap();
+        let full = ast_to_dot(&expr);
+        let total = full.lines().filter(|line| line.starts_with("  node") && !line.contains("->")).count() - 1;
+        assert_eq!(total, 12);
+        assert_eq!(dot_node_count(&expr), 12);
+        let (dot, total) = ast_to_dot_with_limit(&expr, Some(5));
+        assert_eq!(total, Some(12));
+        assert!(dot.contains("truncated\\n5 of 12 nodes shown"), "{dot}");
+        let source = "type T = A | B in let r = {a: 1, b: 2} in type F = Int -> Int in \
+                      match r with | {a: (x, _), b: Some y} -> 1";
+        let expr = crate::parser::parse(source).unwrap();
+        let mut gen = NodeIdGenerator::new(None);
+        expr_to_dot(&expr, &mut String::new(), &mut gen);
+        assert_eq!(dot_node_count(&expr), gen.counter);
+    }
+
+    fn value_dot(source: &str, options: &DotOptions) -> String {
+        let value = crate::eval::eval(&crate::parser::parse(source).unwrap(), &crate::eval::Environment::new()).unwrap();
+        value_to_dot_string(&value, options)
+    }
+
+    #[test]
+    fn test_value_payloads() {
+        let dot = value_dot(
+            "type Shape = Circle Int | Dot in (Circle 3, { x: 1, y: true }, [|'a'|], fun n -> n)",
+            &DotOptions::default(),
+        );
+        assert!(dot.starts_with("digraph Value {"));
+        assert!(dot.contains("node0 [label=\"Tuple\"]"), "{dot}");
+        assert!(dot.contains("node1 [label=\"Circle\"]"), "{dot}");
+        assert!(dot.contains("node1 -> node2 [label=\"0\"]"), "{dot}");
+        assert!(dot.contains("[label=\"Record\"]"));
+        assert!(dot.contains("[label=\"x\"]") && dot.contains("[label=\"y\"]"));
+        assert!(dot.contains("[label=\"Array[1]\"]"));
+        assert!(dot.contains("[label=\"fun n\"]"));
+    }
+
+    #[test]
+    fn test_shared_reference_is_drawn_once() {
+        let dot = value_dot("let r = ref 7 in (r, r, ref 7)", &DotOptions::default());
+        let ref_nodes: Vec<&str> = dot.lines().filter(|line| line.contains("[label=\"ref #")).collect();
+        assert_eq!(ref_nodes.len(), 2, "{dot}");
+        let shared = ref_nodes[0].trim().split(' ').next().unwrap();
+        let incoming = dot.lines().filter(|line| line.contains(&format!("-> {shared} "))).count();
+        assert_eq!(incoming, 2, "{dot}");
+        assert_eq!(dot.matches("[label=\"7\"]").count(), 2);
+    }
+
+    #[test]
+    fn test_reference_cycle_terminates() {
+        let dot = value_dot(
+            "type Node = Link (Ref Node) | End in let r = ref End in let u = r := Link r in r",
+            &DotOptions::default(),
+        );
+        assert!(dot.contains("node0 [label=\"ref #"), "{dot}");
+        assert!(dot.contains("node1 -> node0 [label=\"0\"]"), "{dot}");
+    }
+
+    #[test]
+    fn test_depth_limit_collapses_payloads() {
+        let options = DotOptions { max_depth: Some(1) };
+        let dot = value_dot("type Option a = Some a | None in Some (Some (Some 1))", &options);
+        assert!(dot.contains("node0 [label=\"Some\"]"), "{dot}");
+        assert!(dot.contains("node1 [label=\"Some\\n(1 collapsed)\", shape=note, style=dashed]"), "{dot}");
+        assert!(!dot.contains("node2"), "{dot}");
+        // Leaves are never collapsed
+        assert!(value_dot("(1, 2)", &DotOptions { max_depth: Some(1) }).contains("[label=\"2\"]"));
+    }
+
+    fn typed_dot(source: &str) -> String {
+        typed_ast_to_dot(&crate::parser::parse(source).unwrap(), &TypeEnv::new()).unwrap()
+    }
+
+    #[test]
+    fn test_typed_ast_snapshot_polymorphic_let() {
+        let expected = concat!(
+            "digraph TypedAST {\n",
+            "  node [shape=box, style=rounded];\n",
+            "  edge [fontsize=10];\n",
+            "\n",
+            "  node0 [label=\"Let\\nid :: Int\"];\n",
+            "  node1 [label=\"Fun\\nx :: t0 -> t0\"];\n",
+            "  node2 [label=\"Var\\nx :: t0\"];\n",
+            "  node1 -> node2 [label=\"body\"];\n",
+            "  node3 [label=\"App :: Int\"];\n",
+            "  node4 [label=\"Var\\nid :: Int -> Int\"];\n",
+            "  node5 [label=\"Int\\n1 :: Int\"];\n",
+            "  node3 -> node4 [label=\"func\"];\n",
+            "  node3 -> node5 [label=\"arg\"];\n",
+            "  node0 -> node1 [label=\"value\"];\n",
+            "  node0 -> node3 [label=\"body\"];\n",
+            "}\n",
+        );
+        assert_eq!(typed_dot("let id = fun x -> x in id 1"), expected);
+    }
+
+    #[test]
+    fn test_typed_ast_snapshot_records_and_annotations() {
+        let source = "let f: Int -> Int = fun n -> n * 2 in let get = fun r -> r.x in (f (get { x: 3, y: true }), get)";
+        let expected = concat!(
+            "digraph TypedAST {\n",
+            "  node [shape=box, style=rounded];\n",
+            "  edge [fontsize=10];\n",
+            "\n",
+            "  node0 [label=\"Let\\nf : (Int -> Int) :: (Int, {x: t0 | r0} -> t0)\"];\n",
+            "  node1 [label=\"Fun\\nn :: Int -> Int\"];\n",
+            "  node2 [label=\"BinOp\\n* :: Int\"];\n",
+            "  node3 [label=\"Var\\nn :: Int\"];\n",
+            "  node4 [label=\"Int\\n2 :: Int\"];\n",
+            "  node2 -> node3 [label=\"left\"];\n",
+            "  node2 -> node4 [label=\"right\"];\n",
+            "  node1 -> node2 [label=\"body\"];\n",
+            "  node5 [label=\"Let\\nget :: (Int, {x: t0 | r0} -> t0)\"];\n",
+            "  node6 [label=\"Fun\\nr :: {x: t1 | r1} -> t1\"];\n",
+            "  node7 [label=\"FieldAccess\\nx :: t1\"];\n",
+            "  node8 [label=\"Var\\nr :: {x: t1 | r1}\"];\n",
+            "  node7 -> node8 [label=\"record\"];\n",
+            "  node6 -> node7 [label=\"body\"];\n",
+            "  node9 [label=\"Tuple :: (Int, {x: t0 | r0} -> t0)\"];\n",
+            "  node10 [label=\"App :: Int\"];\n",
+            "  node11 [label=\"Var\\nf :: Int -> Int\"];\n",
+            "  node12 [label=\"App :: Int\"];\n",
+            "  node13 [label=\"Var\\nget :: {x: Int, y: Bool} -> Int\"];\n",
+            "  node14 [label=\"Record :: {x: Int, y: Bool}\"];\n",
+            "  node15 [label=\"Field\\nx\"];\n",
+            "  node16 [label=\"Int\\n3 :: Int\"];\n",
+            "  node14 -> node15 [label=\"field 0\"];\n",
+            "  node15 -> node16 [label=\"value\"];\n",
+            "  node17 [label=\"Field\\ny\"];\n",
+            "  node18 [label=\"Bool\\ntrue :: Bool\"];\n",
+            "  node14 -> node17 [label=\"field 1\"];\n",
+            "  node17 -> node18 [label=\"value\"];\n",
+            "  node12 -> node13 [label=\"func\"];\n",
+            "  node12 -> node14 [label=\"arg\"];\n",
+            "  node10 -> node11 [label=\"func\"];\n",
+            "  node10 -> node12 [label=\"arg\"];\n",
+            "  node9 -> node10 [label=\"elem 0\"];\n",
+            "  node19 [label=\"Var\\nget :: {x: t0 | r0} -> t0\"];\n",
+            "  node9 -> node19 [label=\"elem 1\"];\n",
+            "  node5 -> node6 [label=\"value\"];\n",
+            "  node5 -> node9 [label=\"body\"];\n",
+            "  node0 -> node1 [label=\"value\"];\n",
+            "  node0 -> node5 [label=\"body\"];\n",
+            "}\n",
+        );
+        assert_eq!(typed_dot(source), expected);
+    }
+
+    #[test]
+    fn test_typed_ast_rejects_ill_typed_programs() {
+        let expr = crate::parser::parse("1 + true").unwrap();
+        assert!(typed_ast_to_dot(&expr, &TypeEnv::new()).is_err());
+        let path = std::env::temp_dir().join("test_typed_ast_rejected").join("typed.dot");
+        let error = write_typed_ast_to_dot_file(&expr, &TypeEnv::new(), path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(error, TypedDotError::Type(_)));
+        assert!(error.to_string().starts_with("Type error: "));
+        assert!(!path.exists());
+    }
 }
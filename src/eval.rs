@@ -1,14 +1,28 @@
 /// Evaluator/Interpreter for the `ParLang` language
 /// This module implements the runtime evaluation of `ParLang` expressions
 use crate::ast::{BinOp, Expr, Literal, Pattern};
-use crate::exhaustiveness::{check_exhaustiveness, ExhaustivenessResult};
-use std::cell::RefCell;
+use crate::bindings::Bindings;
+use crate::core::{lower, lower_with_spans, mark_int_arith, CoreExpr, PrimOp, DEFAULT_INT_FAST_PATH};
+use crate::convert::ToParValue;
+use crate::builtins::{prelude_types, prelude_values, string_value, Builtin, DEBUG_ENV};
+use crate::profile::Profiler;
+use crate::retention::check_closure_envs;
+use crate::numeric_rules;
+use crate::prune::{prune_library, PrunePlan};
+use crate::load_path::{canonical, LoadPath, LoadStack};
+use crate::parser::{is_blank_source, BindingPosition};
+use crate::resolve::{self, resolve, ResolvedClosure};
+use crate::span::Span;
+use crate::suggest::{self, suggest, KEYWORD_LITERALS};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 /// Global counter for generating unique reference IDs
 static NEXT_REF_ID: AtomicUsize = AtomicUsize::new(0);
@@ -18,21 +32,30 @@ fn next_ref_id() -> usize {
 }
 
 /// Runtime values in the language
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
     Bool(bool),
     Char(char),
     Float(f64),
     Byte(u8),
-    Closure(String, Expr, Environment),
-    /// Recursive closure: function name, parameter name, body, environment
-    RecClosure(String, String, Expr, Environment),
+    /// The unit value `()`, the result of `:=` and of a program without a body
+    Unit,
+    /// Closure: parameter name, body shared with the `fun` it was created from, environment
+    Closure(String, Rc<CoreExpr>, Environment),
+    /// Recursive closure: function name, parameter name, shared body, environment
+    RecClosure(String, String, Rc<CoreExpr>, Environment),
+    /// Closure created while evaluating with variables resolved to slots
+    /// (see [`crate::resolve`]); never part of a value `eval` returns
+    Resolved(Rc<ResolvedClosure>),
+    /// Named function applied to fewer arguments than it takes: function
+    /// name, arguments applied, arity, and the closure taking the rest
+    /// e.g., gcd 48 -> Partial("gcd", 1, 2, <function b>)
+    Partial(String, usize, usize, Box<Value>),
     /// Tuple of values
     Tuple(Vec<Value>),
-    /// Record value: field name -> value
-    /// Uses HashMap for O(1) field access at runtime
-    Record(HashMap<String, Value>),
+    /// Record value: its fields, in the order the record was built
+    Record(Fields),
     /// Variant value (sum type instance)
     /// Variant: (constructor_name, payload_values)
     /// e.g., Some(42) -> Variant("Some", vec![Int(42)])
@@ -53,15 +76,317 @@ pub enum Value {
     /// Represents an inclusive integer range
     /// e.g., 1..10 -> Range(1, 10)
     Range(i64, i64),
+    /// Built-in function implemented in Rust, possibly partially applied
+    /// e.g., max -> Builtin(max), max 3 -> Builtin(max, args: [3])
+    Builtin(Builtin),
+    /// Host value the language cannot inspect, tagged with its host type name
+    /// e.g., a database connection handed in by an embedder
+    /// Only created from Rust with `Value::opaque`
+    Opaque(Arc<dyn Any + Send + Sync>, &'static str),
+}
+
+/// The fields of a record value, in the order the record was built
+///
+/// Fields are looked up by name. Records are displayed in this order, but
+/// two records are equal when they have the same fields with equal values,
+/// whatever order they were built in, as their types are.
+#[derive(Debug, Clone, Default)]
+pub struct Fields(Vec<(String, Value)>);
+
+/// Iterator over the name and value of each field of a record, in order
+pub type FieldsIter<'a> =
+    std::iter::Map<std::slice::Iter<'a, (String, Value)>, fn(&'a (String, Value)) -> (&'a String, &'a Value)>;
+
+impl Fields {
+    /// A record without fields
+    pub fn new() -> Self {
+        Fields(Vec::new())
+    }
+
+    /// The value of field `name`
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.iter().find(|(field, _)| field == name).map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Set field `name`, which keeps its place if the record already has it,
+    /// and return its previous value
+    pub fn insert(&mut self, name: String, value: Value) -> Option<Value> {
+        if let Some((_, old)) = self.0.iter_mut().find(|(field, _)| *field == name) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.0.push((name, value));
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The name and value of each field, in order
+    pub fn iter(&self) -> FieldsIter<'_> {
+        self.0.iter().map(|(name, value)| (name, value))
+    }
+
+    /// The field names, in order
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.0.iter().map(|(name, _)| name)
+    }
+
+    /// The field values, in order
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.0.iter().map(|(_, value)| value)
+    }
+}
+
+impl PartialEq for Fields {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, value)| other.get(name) == Some(value))
+    }
+}
+
+/// Later fields replace earlier ones with the same name, in the earlier one's place
+impl FromIterator<(String, Value)> for Fields {
+    fn from_iter<I: IntoIterator<Item = (String, Value)>>(iter: I) -> Self {
+        let mut fields = Fields::new();
+        for (name, value) in iter {
+            fields.insert(name, value);
+        }
+        fields
+    }
+}
+
+/// The value of a field, panicking if the record does not have it
+impl std::ops::Index<&str> for Fields {
+    type Output = Value;
+
+    fn index(&self, name: &str) -> &Value {
+        self.get(name).unwrap_or_else(|| panic!("record has no field '{name}'"))
+    }
+}
+
+impl<const N: usize> From<[(String, Value); N]> for Fields {
+    fn from(fields: [(String, Value); N]) -> Self {
+        fields.into_iter().collect()
+    }
+}
+
+impl IntoIterator for Fields {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Fields {
+    type Item = (&'a String, &'a Value);
+    type IntoIter = FieldsIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Options controlling how values are rendered for display
+///
+/// The default options render every value exactly; `Display` for `Value`
+/// always uses the defaults so printed values can be read back unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Number of significant digits used for floats (`None` = exact shortest form)
+    pub float_precision: Option<usize>,
+}
+
+/// A value paired with the options used to render it
+/// Created by `Value::display_with`
+pub struct ValueDisplay<'a> {
+    value: &'a Value,
+    options: DisplayOptions,
+}
+
+impl Value {
+    /// Render this value with the given display options
+    ///
+    /// # Example
+    /// ```
+    /// use parlang::{DisplayOptions, Value};
+    ///
+    /// let options = DisplayOptions { float_precision: Some(6) };
+    /// let value = Value::Float(0.1 + 0.2);
+    /// assert_eq!(value.display_with(options).to_string(), "0.3");
+    /// ```
+    pub fn display_with(&self, options: DisplayOptions) -> ValueDisplay<'_> {
+        ValueDisplay { value: self, options }
+    }
+
+    /// Name of the value's runtime kind, for error messages
+    ///
+    /// Scalars are named by their type (`Int`, `Bool`, ...); other values by
+    /// their shape (`tuple`, `function`, ...), and opaque values by their tag.
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Bool(_) => "Bool",
+            Value::Char(_) => "Char",
+            Value::Float(_) => "Float",
+            Value::Byte(_) => "Byte",
+            Value::Unit => "unit",
+            Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_) | Value::Partial(..) | Value::Builtin(_) => {
+                "function"
+            }
+            Value::Tuple(_) => "tuple",
+            Value::Record(_) => "record",
+            Value::Variant(..) => "variant",
+            Value::Array(..) => "array",
+            Value::Reference(..) => "reference",
+            Value::Range(..) => "range",
+            Value::Opaque(_, tag) => tag,
+        }
+    }
+
+    /// Wrap a host value so it can be passed through `ParLang` code
+    ///
+    /// Programs can bind, store, and pass the value along, but cannot look
+    /// inside it; host built-ins get it back with `downcast_opaque`. The tag
+    /// names the host type in error messages and in `Type::Opaque`.
+    ///
+    /// # Example
+    /// ```
+    /// use parlang::Value;
+    ///
+    /// let handle = Value::opaque("Counter", 7u32);
+    /// assert_eq!(handle.to_string(), "<opaque: Counter>");
+    /// assert_eq!(handle.downcast_opaque::<u32>("Counter").as_deref(), Some(&7));
+    /// assert!(handle.downcast_opaque::<u32>("Other").is_none());
+    /// ```
+    pub fn opaque<T: Any + Send + Sync>(tag: &'static str, data: T) -> Self {
+        Value::Opaque(Arc::new(data), tag)
+    }
+
+    /// Extract the host value of an opaque value with the given tag
+    ///
+    /// Returns `None` for non-opaque values, a different tag, or a different
+    /// Rust type.
+    pub fn downcast_opaque<T: Any + Send + Sync>(&self, tag: &str) -> Option<Arc<T>> {
+        match self {
+            Value::Opaque(data, value_tag) if *value_tag == tag => Arc::clone(data).downcast().ok(),
+            _ => None,
+        }
+    }
+
+    /// The text of a string value: a `Cons`/`Nil` list of characters
+    ///
+    /// `Nil` is the empty string. Returns `None` for any other value.
+    ///
+    /// # Example
+    /// ```
+    /// use parlang::{eval, parse, Environment};
+    ///
+    /// let program = "type List a = Nil | Cons a (List a) in \"hi\"";
+    /// let value = eval(&parse(program).unwrap(), &Environment::new()).unwrap();
+    /// assert_eq!(value.as_string().as_deref(), Some("hi"));
+    /// assert_eq!(value.to_string(), "\"hi\"");
+    /// ```
+    pub fn as_string(&self) -> Option<String> {
+        let mut text = String::new();
+        let mut current = self;
+        loop {
+            match current {
+                Value::Variant(name, args) if name == "Nil" && args.is_empty() => return Some(text),
+                Value::Variant(name, args) if name == "Cons" && args.len() == 2 => match &args[0] {
+                    Value::Char(c) => {
+                        text.push(*c);
+                        current = &args[1];
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+}
+
+/// Whether two closure bodies are equal, without comparing the trees of a shared one
+fn same_body(a: &Rc<CoreExpr>, b: &Rc<CoreExpr>) -> bool {
+    Rc::ptr_eq(a, b) || a == b
+}
+
+/// Values are compared structurally, except opaque values and references,
+/// which are equal only when they share the same host allocation or cell
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Byte(a), Value::Byte(b)) => a == b,
+            (Value::Closure(p1, b1, e1), Value::Closure(p2, b2, e2)) => p1 == p2 && same_body(b1, b2) && e1 == e2,
+            (Value::RecClosure(n1, p1, b1, e1), Value::RecClosure(n2, p2, b2, e2)) => {
+                n1 == n2 && p1 == p2 && same_body(b1, b2) && e1 == e2
+            }
+            (Value::Resolved(a), Value::Resolved(b)) => a.same_as(b),
+            (Value::Partial(n1, a1, k1, c1), Value::Partial(n2, a2, k2, c2)) => {
+                n1 == n2 && a1 == a2 && k1 == k2 && c1 == c2
+            }
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => a == b,
+            (Value::Variant(c1, a1), Value::Variant(c2, a2)) => c1 == c2 && a1 == a2,
+            (Value::Array(n1, a1), Value::Array(n2, a2)) => n1 == n2 && a1 == a2,
+            (Value::Reference(i1, c1), Value::Reference(i2, c2)) => i1 == i2 && Rc::ptr_eq(c1, c2),
+            (Value::Range(s1, e1), Value::Range(s2, e2)) => s1 == s2 && e1 == e2,
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            (Value::Opaque(a, t1), Value::Opaque(b, t2)) => Arc::ptr_eq(a, b) && t1 == t2,
+            _ => false,
+        }
+    }
+}
+
+/// Format a float, rounding to `precision` significant digits when given
+///
+/// The rounded value is printed in its shortest form, so trailing zeros are
+/// dropped (e.g. 0.30000000000000004 with 6 digits becomes `0.3`).
+fn format_float(value: f64, precision: Option<usize>) -> String {
+    let value = match precision {
+        Some(digits) if value.is_finite() => {
+            let rounded = format!("{:.*e}", digits.max(1) - 1, value);
+            rounded.parse().unwrap_or(value)
+        }
+        _ => value,
+    };
+    // Keep whole floats apart from integers: `3.0`, not `3`
+    if value.is_finite() && value.fract() == 0.0 {
+        format!("{value:.1}")
+    } else {
+        format!("{value}")
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
+        write!(f, "{}", self.display_with(DisplayOptions::default()))
+    }
+}
+
+impl fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let options = self.options;
+        match self.value {
             Value::Int(n) => write!(f, "{n}"),
             Value::Bool(b) => write!(f, "{b}"),
-            Value::Float(fl) => write!(f, "{fl}"),
+            Value::Float(fl) => write!(f, "{}", format_float(*fl, options.float_precision)),
             Value::Byte(b) => write!(f, "{}b", b),
+            Value::Unit => write!(f, "()"),
             Value::Char(c) => {
                 write!(f, "'")?;
                 match c {
@@ -74,39 +399,49 @@ impl fmt::Display for Value {
                 }
                 write!(f, "'")
             }
+            // A parameter desugaring named, for a pattern, is not shown
+            Value::Closure(param, _, _) if param.starts_with('_') => write!(f, "<function>"),
             Value::Closure(param, _, _) => write!(f, "<function {param}>"),
             Value::RecClosure(name, _, _, _) => write!(f, "<recursive function {name}>"),
+            Value::Resolved(closure) => match closure.rec_name() {
+                Some(name) => write!(f, "<recursive function {name}>"),
+                None if closure.param().starts_with('_') => write!(f, "<function>"),
+                None => write!(f, "<function {}>", closure.param()),
+            },
+            Value::Partial(name, applied, arity, _) => {
+                write!(f, "<partial: {name}, {applied} of {arity} args applied>")
+            }
             Value::Tuple(values) => {
                 write!(f, "(")?;
                 for (i, val) in values.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{val}")?;
+                    write!(f, "{}", val.display_with(options))?;
                 }
                 write!(f, ")")
             }
             Value::Record(fields) => {
                 write!(f, "{{")?;
-                // Sort fields by name for consistent display
-                let mut sorted_fields: Vec<_> = fields.iter().collect();
-                sorted_fields.sort_by_key(|(name, _)| *name);
-                
-                for (i, (name, value)) in sorted_fields.iter().enumerate() {
+                for (i, (name, value)) in fields.iter().enumerate() {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{name}: {value}")?;
+                    write!(f, "{name}: {}", value.display_with(options))?;
                 }
                 write!(f, "}}")
             }
             Value::Variant(ctor, args) => {
+                // Non-empty lists of characters print as string literals
+                if let Some(text) = (ctor == "Cons").then(|| self.value.as_string()).flatten() {
+                    return write_string_literal(f, &text);
+                }
                 write!(f, "{}", ctor)?;
                 if !args.is_empty() {
                     write!(f, "(")?;
                     for (i, arg) in args.iter().enumerate() {
                         if i > 0 { write!(f, ", ")?; }
-                        write!(f, "{}", arg)?;
+                        write!(f, "{}", arg.display_with(options))?;
                     }
                     write!(f, ")")?;
                 }
@@ -118,21 +453,42 @@ impl fmt::Display for Value {
                     if i > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{val}")?;
+                    write!(f, "{}", val.display_with(options))?;
                 }
                 write!(f, "|]")?;
                 write!(f, " (size: {size})")
             }
             Value::Reference(id, cell) => {
-                write!(f, "<ref #{id}: {}>", cell.borrow())
+                write!(f, "<ref #{id}: {}>", cell.borrow().display_with(options))
             }
             Value::Range(start, end) => {
                 write!(f, "{}..{}", start, end)
             }
+            Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Value::Opaque(_, tag) => write!(f, "<opaque: {tag}>"),
         }
     }
 }
 
+/// Write `text` as a string literal that parses back to the same string
+fn write_string_literal(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in text.chars() {
+        match c {
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            '\\' => write!(f, "\\\\")?,
+            '"' => write!(f, "\\\"")?,
+            '{' => write!(f, "{{{{")?,
+            '}' => write!(f, "}}}}")?,
+            _ => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
 /// Constructor information for sum types
 #[derive(Debug, Clone, PartialEq)]
 pub struct ConstructorInfo {
@@ -142,30 +498,507 @@ pub struct ConstructorInfo {
     pub arity: usize,
 }
 
+/// A mutable layer of top-level bindings shared between environments
+///
+/// Environments (and therefore closures) created while a shared layer is
+/// attached hold a handle to the layer rather than a snapshot of it, so they
+/// observe definitions made after they were created. Used by the REPL's
+/// dynamic top-level mode.
+#[derive(Clone, Default)]
+pub struct SharedBindings(Rc<RefCell<HashMap<String, Value>>>);
+
+impl SharedBindings {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Define or redefine a name in the shared layer
+    pub fn define(&self, name: String, value: Value) {
+        self.0.borrow_mut().insert(name, value);
+    }
+
+    /// Look up the current value of a name in the shared layer
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.0.borrow().get(name).cloned()
+    }
+
+    /// Copy out all current bindings of the shared layer
+    pub fn snapshot(&self) -> Vec<(String, Value)> {
+        self.0
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// Replace all bindings of the shared layer, e.g. with an earlier [`snapshot`](Self::snapshot)
+    pub fn restore(&self, bindings: Vec<(String, Value)>) {
+        *self.0.borrow_mut() = bindings.into_iter().collect();
+    }
+}
+
+impl fmt::Debug for SharedBindings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Closures stored in the layer point back to it, so never print the contents
+        write!(f, "SharedBindings(<{} bindings>)", self.0.borrow().len())
+    }
+}
+
+impl PartialEq for SharedBindings {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
 /// Environment for variable bindings
+///
+/// Environments are persistent: deriving one with [`extend`](Self::extend)
+/// or [`merge`](Self::merge) shares the structure of the original instead of
+/// copying it, so closures can capture their environment cheaply. With `n`
+/// local bindings:
+///
+/// - `clone`: `O(1)`
+/// - [`extend`](Self::extend) and [`bind`](Self::bind): `O(log n)`, at most 16 levels of a hash trie
+/// - [`lookup`](Self::lookup) and [`resolve`](Self::resolve): `O(log n)`
+/// - [`merge`](Self::merge): proportional to the smaller side only
+/// - [`binding_order`](Self::binding_order) and listings: `O(n log n)`
 #[derive(Debug, Clone, PartialEq)]
 pub struct Environment {
-    bindings: HashMap<String, Value>,
-    constructors: HashMap<String, ConstructorInfo>,
+    bindings: Bindings,
+    /// Shared until a constructor is registered, so extending stays cheap
+    constructors: Rc<HashMap<String, ConstructorInfo>>,
+    /// Live top-level bindings consulted for names not bound locally
+    toplevel: Option<SharedBindings>,
+    /// Prelude built-ins, consulted last; shared so extending the environment stays cheap
+    prelude: Option<Rc<HashMap<String, Value>>>,
+    /// Library bindings to skip when loading, computed ahead of evaluation
+    prune_plan: Option<Rc<PrunePlan>>,
+    /// Where `load` looks for libraries; a library's bindings look next to the library
+    load_path: Rc<LoadPath>,
+    /// Libraries being loaded, shared like the step budget to catch circular loads
+    loading: Rc<LoadStack>,
+    /// Libraries read and parsed so far, shared like the step budget
+    load_cache: Rc<LoadCache>,
+    /// Evaluation steps left, shared by every environment derived from this one
+    step_budget: Option<Rc<StepBudget>>,
+    /// Function calls in progress and how many may be, shared like the step budget
+    depth_budget: Option<Rc<DepthBudget>>,
+    /// Flag another thread sets to stop evaluation, shared like the step budget
+    interrupt: Option<Rc<Interrupt>>,
+    /// Profiler recording steps and calls, shared like the step budget
+    profiler: Option<Rc<Profiler>>,
+    /// Fewest operators for [`eval`] to send integer arithmetic down the fast path, `None` to never
+    int_fast_path: Option<usize>,
+    /// Whether `debug_env` is available, shared by every environment derived from this one
+    debug_builtins: Rc<Cell<bool>>,
+    /// Whether [`eval`] resolves variables to slots before evaluating
+    resolved: bool,
+}
+
+/// A library file as `load` parsed it, with the position of each top-level
+/// binding, or `None` for a blank file
+type ParsedLibrary = Option<(Expr, Vec<BindingPosition>)>;
+
+/// Libraries read and parsed so far, by canonical path
+#[derive(Debug, Default)]
+struct LoadCache(RefCell<HashMap<PathBuf, Rc<ParsedLibrary>>>);
+
+impl PartialEq for LoadCache {
+    fn eq(&self, _other: &Self) -> bool {
+        // What has been cached never changes what an environment binds
+        true
+    }
+}
+
+/// How many more expressions `eval` may evaluate before giving up
+#[derive(Debug, PartialEq)]
+struct StepBudget {
+    limit: u64,
+    remaining: Cell<u64>,
+}
+
+/// How many function calls may be in progress at once, and how many are
+#[derive(Debug, PartialEq)]
+struct DepthBudget {
+    limit: usize,
+    depth: Cell<usize>,
+}
+
+/// Steps between two reads of an interrupt flag, keeping the check off the hot path
+pub const INTERRUPT_CHECK_INTERVAL: u32 = 1024;
+
+/// A flag that stops evaluation once set, and the steps until it is next read
+#[derive(Debug)]
+struct Interrupt {
+    flag: Arc<AtomicBool>,
+    countdown: Cell<u32>,
+}
+
+impl PartialEq for Interrupt {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.flag, &other.flag)
+    }
+}
+
+/// A function call in progress, counted against the depth limit until it is dropped
+pub(crate) struct CallDepth(Rc<DepthBudget>);
+
+impl Drop for CallDepth {
+    fn drop(&mut self) {
+        self.0.depth.set(self.0.depth.get() - 1);
+    }
 }
 
 impl Environment {
     #[must_use]
     pub fn new() -> Self {
         Environment {
-            bindings: HashMap::new(),
-            constructors: HashMap::new(),
+            bindings: Bindings::default(),
+            constructors: Rc::default(),
+            toplevel: None,
+            prelude: None,
+            prune_plan: None,
+            load_path: Rc::default(),
+            loading: Rc::default(),
+            load_cache: Rc::default(),
+            step_budget: None,
+            depth_budget: None,
+            interrupt: None,
+            profiler: None,
+            int_fast_path: Some(DEFAULT_INT_FAST_PATH),
+            debug_builtins: Rc::default(),
+            resolved: cfg!(feature = "force-resolved"),
+        }
+    }
+
+    /// Create an environment with the prelude built-ins bound and the prelude types declared
+    #[must_use]
+    pub fn with_builtins() -> Self {
+        let mut env = Self::new();
+        env.prelude = Some(Rc::new(prelude_values().into_iter().collect()));
+        env.declare_prelude_types();
+        env
+    }
+
+    /// Register the constructors of the prelude types
+    fn declare_prelude_types(&mut self) {
+        for (name, _, constructors) in prelude_types() {
+            let constructors: Vec<_> = constructors.into_iter().map(|(ctor, payload)| (ctor, payload.len())).collect();
+            register_type_def(self, &name, &constructors);
         }
     }
 
+    /// Remove every local binding, the shared top-level layer, and every
+    /// constructor but the prelude's, keeping the prelude built-ins and the
+    /// settings (step limit, profiler, debug built-ins, ...)
+    pub fn clear(&mut self) {
+        self.bindings = Bindings::default();
+        self.toplevel = None;
+        self.constructors = Rc::default();
+        if self.prelude.is_some() {
+            self.declare_prelude_types();
+        }
+    }
+
+    /// Define a local binding; redefining a name moves it to the end of [`binding_order`](Self::binding_order)
+    ///
+    /// Takes `O(log n)` time for `n` local bindings.
     pub fn bind(&mut self, name: String, value: Value) {
         self.bindings.insert(name, value);
     }
 
+    /// Bind `name` to the `ParLang` value a Rust value stands for (see [`crate::convert`])
+    ///
+    /// # Example
+    /// ```
+    /// use parlang::{eval, parse, Environment};
+    ///
+    /// let mut env = Environment::with_builtins();
+    /// env.bind_value("config", (8080, "localhost"));
+    /// let port = eval(&parse("match config with | (port, _) -> port + 1").unwrap(), &env).unwrap();
+    /// assert_eq!(i64::try_from(port), Ok(8081));
+    /// ```
+    pub fn bind_value(&mut self, name: impl Into<String>, value: impl ToParValue) {
+        self.bind(name.into(), value.to_par_value());
+    }
+
+    /// Bind `name` to a host function, which programs call like any other function
+    ///
+    /// The function gets the argument it is applied to and its error ends
+    /// the evaluation, as a built-in's would. A host function taking several
+    /// arguments takes them as a tuple. The type checker does not know host
+    /// functions; check programs using them with a [`TypeEnv`](crate::TypeEnv)
+    /// that binds their types.
+    ///
+    /// # Example
+    /// ```
+    /// use parlang::convert::ToParValue;
+    /// use parlang::{eval, parse, Environment};
+    ///
+    /// let mut env = Environment::with_builtins();
+    /// env.bind_fn("shout", |text| Ok(String::try_from(text)?.to_uppercase().to_par_value()));
+    /// let result = eval(&parse("shout \"hi\" ^ \"!\"").unwrap(), &env).unwrap();
+    /// assert_eq!(String::try_from(result).unwrap(), "HI!");
+    /// ```
+    pub fn bind_fn(&mut self, name: impl Into<String>, func: impl Fn(Value) -> Result<Value, EvalError> + 'static) {
+        let name = name.into();
+        let builtin = Builtin::new(name.clone(), 1, Rc::new(move |args: &[Value]| func(args[0].clone())));
+        self.bind(name, Value::Builtin(builtin));
+    }
+
+    /// Names of the local bindings in the order they were introduced
+    ///
+    /// A name that was bound more than once appears once, at the position of
+    /// its latest definition, so every name comes after the bindings its
+    /// value could refer to. Prelude built-ins and the shared top-level layer
+    /// are not included.
+    ///
+    /// The order is computed on each call, in `O(n log n)` time; it is meant
+    /// for listings, not for evaluation.
+    pub fn binding_order(&self) -> Vec<String> {
+        self.bindings.order()
+    }
+
+    /// Look up a local binding, falling back to the prelude built-ins
+    ///
+    /// Takes `O(log n)` time for `n` local bindings.
     pub fn lookup(&self, name: &str) -> Option<&Value> {
+        self.bindings
+            .get(name)
+            .or_else(|| self.prelude.as_ref().and_then(|prelude| prelude.get(name)))
+    }
+
+    /// Look up a local binding only, ignoring the shared top-level layer and the prelude
+    pub fn lookup_local(&self, name: &str) -> Option<&Value> {
         self.bindings.get(name)
     }
 
+    /// Resolve a variable: local bindings first, then the shared top-level layer,
+    /// then the prelude built-ins
+    pub fn resolve(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.bindings.get(name) {
+            return Some(value.clone());
+        }
+        self.toplevel
+            .as_ref()
+            .and_then(|layer| layer.get(name))
+            .or_else(|| self.prelude.as_ref().and_then(|prelude| prelude.get(name).cloned()))
+    }
+
+    /// Whether `name` is bound locally or in the shared top-level layer, ignoring the prelude
+    fn defines(&self, name: &str) -> bool {
+        self.bindings.contains(name) || self.toplevel.as_ref().is_some_and(|layer| layer.get(name).is_some())
+    }
+
+    /// All bindings visible by name: the prelude, overridden by the shared
+    /// top-level layer, overridden by local bindings
+    pub fn visible_bindings(&self) -> Vec<(String, Value)> {
+        let mut visible: HashMap<String, Value> = self
+            .prelude
+            .as_ref()
+            .map(|prelude| (**prelude).clone())
+            .unwrap_or_default();
+        if let Some(layer) = &self.toplevel {
+            visible.extend(layer.snapshot());
+        }
+        visible.extend(self.bindings.iter().map(|(name, value)| (name.clone(), value.clone())));
+        visible.into_iter().collect()
+    }
+
+    /// Attach (or detach, with `None`) a shared top-level layer
+    pub fn set_toplevel(&mut self, layer: Option<SharedBindings>) {
+        self.toplevel = layer;
+    }
+
+    /// Attach (or detach, with `None`) a plan of library bindings to skip on `load`
+    pub fn set_prune_plan(&mut self, plan: Option<PrunePlan>) {
+        self.prune_plan = plan.map(Rc::new);
+    }
+
+    /// Look up the libraries named by `load` as `load_path` says
+    ///
+    /// Libraries keep the search path but look up their own loads next to
+    /// their file. Without a load path, `load` looks in the working directory.
+    pub fn set_load_path(&mut self, load_path: LoadPath) {
+        self.load_path = Rc::new(load_path);
+    }
+
+    /// Where `load` looks for libraries
+    pub fn load_path(&self) -> &LoadPath {
+        &self.load_path
+    }
+
+    /// Forget the libraries read so far, so the next `load` of each reads its file again
+    ///
+    /// Every environment derived from this one shares its cache: a library
+    /// is read and parsed the first time it is loaded, and later loads of the
+    /// same file, through any path, reuse that parse even if the file has
+    /// changed since. Its bindings are evaluated anew on each load.
+    pub fn clear_load_cache(&self) {
+        self.load_cache.0.borrow_mut().clear();
+    }
+
+    /// Canonical paths of the libraries read and parsed so far, sorted
+    pub fn cached_libraries(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self.load_cache.0.borrow().keys().cloned().collect();
+        paths.sort();
+        paths
+    }
+
+    /// Limit (or, with `None`, stop limiting) how many expressions evaluation may visit
+    ///
+    /// Every environment derived from this one, including closures and loaded
+    /// libraries, draws from the same budget; evaluation fails with
+    /// `EvalError::StepLimitExceeded` once it runs out.
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_budget = limit.map(|limit| Rc::new(StepBudget { limit, remaining: Cell::new(limit) }));
+    }
+
+    /// Limit (or, with `None`, stop limiting) how many function calls may be in progress at once
+    ///
+    /// Calls the evaluator turns into loops, tail calls of a `rec` function
+    /// to itself, do not count. Like the step budget, the limit is shared by
+    /// every environment derived from this one; evaluation fails with
+    /// `EvalError::RecursionLimitExceeded` instead of overflowing the stack.
+    pub fn set_depth_limit(&mut self, limit: Option<usize>) {
+        self.depth_budget = limit.map(|limit| Rc::new(DepthBudget { limit, depth: Cell::new(0) }));
+    }
+
+    /// Stop evaluation once `flag` is set, or stop watching a flag with `None`
+    ///
+    /// The flag is read every [`INTERRUPT_CHECK_INTERVAL`] steps, so a
+    /// signal handler or another thread can set it to make evaluation fail
+    /// with `EvalError::Interrupted` soon after. Like the step budget, it is
+    /// shared by every environment derived from this one. Evaluation never
+    /// clears the flag; whoever sets it clears it before evaluating again.
+    pub fn set_interrupt(&mut self, flag: Option<Arc<AtomicBool>>) {
+        self.interrupt = flag.map(|flag| Rc::new(Interrupt { flag, countdown: Cell::new(INTERRUPT_CHECK_INTERVAL) }));
+    }
+
+    /// Attach (or detach, with `None`) a profiler
+    ///
+    /// Like the step budget, the profiler is shared by every environment
+    /// derived from this one, so closures created here report to it.
+    pub fn set_profiler(&mut self, profiler: Option<Rc<Profiler>>) {
+        self.profiler = profiler;
+    }
+
+    /// The attached profiler, if any
+    pub fn profiler(&self) -> Option<&Rc<Profiler>> {
+        self.profiler.as_ref()
+    }
+
+    /// Set how many operators integer arithmetic needs for [`eval`] to use
+    /// the fast path, or turn the fast path off with `None`
+    ///
+    /// Defaults to [`INT_FAST_PATH_MIN_OPS`](crate::core::INT_FAST_PATH_MIN_OPS),
+    /// or to 1 when built with the `force-int-fast-path` feature;
+    /// `Some(1)` sends every integer operation down it. Results, errors and
+    /// step counts are the same either way. See [`mark_int_arith`].
+    pub fn set_int_fast_path(&mut self, min_ops: Option<usize>) {
+        self.int_fast_path = min_ops;
+    }
+
+    /// How many operators integer arithmetic needs for the fast path, or `None` if it is off
+    pub fn int_fast_path(&self) -> Option<usize> {
+        self.int_fast_path
+    }
+
+    /// Make [`eval`] resolve variables to slots before evaluating, or stop it
+    ///
+    /// Off by default, unless built with the `force-resolved` feature.
+    /// Results, errors and step counts are the same either way; see
+    /// [`crate::resolve`].
+    pub fn set_resolved(&mut self, enabled: bool) {
+        self.resolved = enabled;
+    }
+
+    /// Make the `debug_env` built-in available, or unavailable again
+    ///
+    /// The setting is shared by every environment derived from this one,
+    /// before or after the call, so turning it on in a REPL session also
+    /// applies to functions defined earlier.
+    pub fn set_debug_builtins(&self, enabled: bool) {
+        self.debug_builtins.set(enabled);
+    }
+
+    /// Whether the `debug_env` built-in is available
+    pub fn debug_builtins(&self) -> bool {
+        self.debug_builtins.get()
+    }
+
+    /// The bindings in scope as a record, for `debug_env`
+    ///
+    /// Covers the shared top-level layer and the local bindings, which win
+    /// over it; prelude built-ins and the names desugaring makes, starting
+    /// with `_`, are left out. Functions are replaced by their rendering
+    /// (see [`without_functions`]).
+    pub(crate) fn debug_record(&self) -> Value {
+        let layer = self.toplevel.as_ref().map(SharedBindings::snapshot).unwrap_or_default();
+        let mut fields: HashMap<String, Value> = layer.into_iter().collect();
+        fields.extend(self.bindings.iter().map(|(name, value)| (name.clone(), value.clone())));
+        fields.retain(|name, _| !name.starts_with('_'));
+        // Scopes have no order of their own, so the fields are sorted by name
+        let mut fields: Vec<(String, Value)> = fields.into_iter().collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Value::Record(fields.into_iter().map(|(name, value)| (name, without_functions(&value))).collect())
+    }
+
+    /// Take `count` evaluation steps, one at a time, as evaluating that many expressions would
+    pub(crate) fn count_steps(&self, count: usize) -> Result<(), EvalError> {
+        if self.profiler.is_none() && self.step_budget.is_none() && self.interrupt.is_none() {
+            return Ok(());
+        }
+        (0..count).try_for_each(|_| self.count_step())
+    }
+
+    /// Take one evaluation step from the budget, if there is one, and
+    /// check for an interrupt every [`INTERRUPT_CHECK_INTERVAL`] steps
+    pub(crate) fn count_step(&self) -> Result<(), EvalError> {
+        if let Some(profiler) = &self.profiler {
+            profiler.step();
+        }
+        if let Some(interrupt) = &self.interrupt {
+            match interrupt.countdown.get() {
+                0 if interrupt.flag.load(Ordering::Relaxed) => return Err(EvalError::Interrupted),
+                0 => interrupt.countdown.set(INTERRUPT_CHECK_INTERVAL),
+                steps => interrupt.countdown.set(steps - 1),
+            }
+        }
+        match &self.step_budget {
+            Some(budget) if budget.remaining.get() == 0 => Err(EvalError::StepLimitExceeded(budget.limit)),
+            Some(budget) => {
+                budget.remaining.set(budget.remaining.get() - 1);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Start a function call, failing if the depth limit allows no more
+    ///
+    /// The call ends when the returned guard is dropped.
+    pub(crate) fn enter_call(&self) -> Result<Option<CallDepth>, EvalError> {
+        let Some(budget) = &self.depth_budget else {
+            return Ok(None);
+        };
+        if budget.depth.get() >= budget.limit {
+            return Err(EvalError::RecursionLimitExceeded(budget.limit));
+        }
+        budget.depth.set(budget.depth.get() + 1);
+        Ok(Some(CallDepth(Rc::clone(budget))))
+    }
+
+    /// Remove and return all local bindings, keeping constructors, the top-level layer, and the prelude
+    pub fn take_bindings(&mut self) -> HashMap<String, Value> {
+        self.bindings.take()
+    }
+
+    /// A copy of this environment with `name` bound to `value`
+    ///
+    /// The copy shares this environment's bindings, so this takes `O(log n)`
+    /// time for `n` local bindings, not `O(n)`.
     #[must_use]
     pub fn extend(&self, name: String, value: Value) -> Self {
         let mut new_env = self.clone();
@@ -173,23 +1006,33 @@ impl Environment {
         new_env
     }
 
+    /// Add the local bindings of `other`, which win over bindings of the same
+    /// name and follow this environment's bindings in their own order
+    ///
+    /// The larger of the two sets of bindings is reused as it is and only the
+    /// smaller one is added to it, so merging `m` bindings with `n` takes
+    /// `O(min(m, n) log max(m, n))` time: loading a small library into a large
+    /// session does not depend on the size of the session.
     #[must_use]
     pub fn merge(&self, other: &Environment) -> Self {
         let mut new_env = self.clone();
-        for (name, value) in &other.bindings {
-            new_env.bind(name.clone(), value.clone());
-        }
+        new_env.bindings = self.bindings.merge(&other.bindings);
         new_env
     }
     
     pub fn register_constructor(&mut self, name: String, info: ConstructorInfo) {
-        self.constructors.insert(name, info);
+        Rc::make_mut(&mut self.constructors).insert(name, info);
     }
     
     pub fn lookup_constructor(&self, name: &str) -> Option<&ConstructorInfo> {
         self.constructors.get(name)
     }
     
+    /// All registered constructors with their information
+    pub fn constructors(&self) -> impl Iterator<Item = (&String, &ConstructorInfo)> {
+        self.constructors.iter()
+    }
+    
     /// Get constructor information by name (used by exhaustiveness checker)
     pub fn get_constructor(&self, name: &str) -> Option<&ConstructorInfo> {
         self.constructors.get(name)
@@ -203,6 +1046,32 @@ impl Environment {
             .map(|(name, _)| name.clone())
             .collect()
     }
+
+    /// Error for an unknown lowercase name, suggesting visible variables and
+    /// keyword literals that are close to it
+    fn unbound_variable(&self, name: &str) -> EvalError {
+        self.unbound_variable_among(name, &[])
+    }
+
+    /// Error for an unknown lowercase name, also suggesting the local variables `locals`
+    pub(crate) fn unbound_variable_among(&self, name: &str, locals: &[String]) -> EvalError {
+        let visible = self.visible_bindings();
+        let candidates = visible.iter().map(|(candidate, _)| candidate.as_str()).chain(locals.iter().map(String::as_str));
+        let suggestions = suggest(name, candidates.chain(KEYWORD_LITERALS.iter().copied()));
+        EvalError::UnboundVariable(name.to_string(), suggestions)
+    }
+
+    /// Error for an unknown capitalized name, suggesting registered
+    /// constructors first, then visible variables and keyword literals
+    fn unknown_constructor(&self, name: &str) -> EvalError {
+        let mut suggestions = suggest(name, self.constructors.keys().map(String::as_str));
+        if suggestions.is_empty() {
+            let visible = self.visible_bindings();
+            let candidates = visible.iter().map(|(candidate, _)| candidate.as_str());
+            suggestions = suggest(name, candidates.chain(KEYWORD_LITERALS.iter().copied()));
+        }
+        EvalError::UnknownConstructor(name.to_string(), suggestions)
+    }
 }
 
 impl Default for Environment {
@@ -214,7 +1083,8 @@ impl Default for Environment {
 /// Evaluation errors
 #[derive(Debug, Clone, PartialEq)]
 pub enum EvalError {
-    UnboundVariable(String),
+    /// Variable not in scope: name, near-miss suggestions
+    UnboundVariable(String, Vec<String>),
     TypeError(String),
     DivisionByZero,
     LoadError(String),
@@ -223,18 +1093,57 @@ pub enum EvalError {
     FieldNotFound(String, Vec<String>),
     /// Expected record but got a different type
     RecordExpected(String),
-    /// Unknown constructor
-    UnknownConstructor(String),
+    /// Constructor not registered: name, near-miss suggestions
+    UnknownConstructor(String, Vec<String>),
     /// Constructor arity mismatch: name, expected, got
     ConstructorArityMismatch(String, usize, usize),
     /// Pattern match is non-exhaustive
     PatternMatchNonExhaustive,
+    /// A pattern binding, `let p = v` or a function taking `p`, given a value
+    /// the pattern does not match: the pattern, the value's shortened rendering
+    PatternMismatch { pattern: String, value: String },
+    /// Error while evaluating a top-level binding of a loaded library:
+    /// binding name, source location (`file:line`) if known, underlying error
+    InBinding(String, Option<String>, Box<EvalError>),
+    /// Evaluation ran out of steps: the limit set with `Environment::set_step_limit`
+    StepLimitExceeded(u64),
+    /// Too many function calls were in progress at once: the limit set with
+    /// `Environment::set_depth_limit`
+    RecursionLimitExceeded(usize),
+    /// Evaluation was stopped from outside: the flag set with
+    /// `Environment::set_interrupt` was raised, e.g. by Ctrl+C in the REPL
+    Interrupted,
+    /// A closure in the result retains more captured environment than
+    /// `EvalConfig::max_returned_closure_env` allows: its heaviest captured
+    /// names, the estimated bytes it retains
+    ResultRetainsTooMuch { binding_names: Vec<String>, estimated_size: usize },
+    /// A binary operator applied to operands it does not support: the
+    /// operator, and each operand's shortened rendering and kind name
+    BinOpTypeMismatch { op: BinOp, left: String, left_kind: &'static str, right: String, right_kind: &'static str },
+    /// A named function applied to more arguments than it takes, whose
+    /// result is not a function: the function, its arity, the number of
+    /// arguments given, and the result's kind name
+    OverApplied { function: String, arity: usize, args: usize, result_kind: &'static str },
+    /// Error raised while evaluating a named call, a `let` value or a `match`
+    /// arm: where, and the underlying error. See [`EvalError::with_context`]
+    Context(String, Box<EvalError>),
+    /// Error raised in the source span of the expression that failed, for
+    /// programs evaluated with [`eval_with_spans`]
+    At(Span, Box<EvalError>),
 }
 
+/// Most frames [`EvalError::with_context`] keeps; outer frames beyond these are elided
+pub const MAX_TRACE_FRAMES: usize = 20;
+
+/// The frame standing for the elided outer frames of a trace
+const ELIDED_FRAMES: &str = "...";
+
 impl fmt::Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            EvalError::UnboundVariable(name) => write!(f, "Unbound variable: {name}"),
+            EvalError::UnboundVariable(name, suggestions) => {
+                write!(f, "Unbound variable: {name}{}", suggest::hint(suggestions))
+            }
             EvalError::TypeError(msg) => write!(f, "Type error: {msg}"),
             EvalError::DivisionByZero => write!(f, "Division by zero"),
             EvalError::LoadError(msg) => write!(f, "Load error: {msg}"),
@@ -245,8 +1154,8 @@ impl fmt::Display for EvalError {
             EvalError::RecordExpected(got) => {
                 write!(f, "Expected record, got {got}")
             }
-            EvalError::UnknownConstructor(name) => {
-                write!(f, "Unknown constructor: {}", name)
+            EvalError::UnknownConstructor(name, suggestions) => {
+                write!(f, "Unknown constructor: {name}{}", suggest::hint(suggestions))
             }
             EvalError::ConstructorArityMismatch(name, expected, got) => {
                 write!(f, "Constructor {} expects {} arguments, got {}", name, expected, got)
@@ -254,120 +1163,746 @@ impl fmt::Display for EvalError {
             EvalError::PatternMatchNonExhaustive => {
                 write!(f, "Pattern match is non-exhaustive")
             }
+            EvalError::PatternMismatch { pattern, value } => {
+                write!(f, "Value {value} does not match pattern {pattern}")
+            }
+            EvalError::InBinding(name, Some(location), err) => {
+                write!(f, "In binding '{name}' ({location}): {err}")
+            }
+            EvalError::InBinding(name, None, err) => write!(f, "In binding '{name}': {err}"),
+            EvalError::StepLimitExceeded(limit) => {
+                write!(f, "Step limit exceeded: evaluation took more than {limit} steps")
+            }
+            EvalError::RecursionLimitExceeded(limit) => {
+                write!(f, "Recursion limit exceeded: more than {limit} nested function calls")
+            }
+            EvalError::Interrupted => write!(f, "Interrupted"),
+            EvalError::ResultRetainsTooMuch { binding_names, estimated_size } => write!(
+                f,
+                "Result retains too much: a returned closure captures about {estimated_size} bytes, mostly {}",
+                binding_names.join(", ")
+            ),
+            EvalError::BinOpTypeMismatch { op, left, left_kind, right, right_kind } => {
+                write!(f, "Type error: cannot apply '{op}' to {left} ({left_kind}) and {right} ({right_kind})")
+            }
+            EvalError::OverApplied { function, arity, args, result_kind } => write!(
+                f,
+                "Type error: {function} takes {arity} argument{} but is applied to {args}, and its {result_kind} result is not a function",
+                if *arity == 1 { "" } else { "s" }
+            ),
+            EvalError::Context(..) => {
+                // Outermost frame first, each nested one indented further
+                let mut error = self;
+                let mut depth = 0;
+                while let EvalError::Context(frame, inner) = error {
+                    writeln!(f, "{:indent$}{frame}", "", indent = 2 * depth)?;
+                    error = inner;
+                    depth += 1;
+                }
+                write!(f, "{:indent$}{error}", "", indent = 2 * depth)
+            }
+            EvalError::At(_, error) => write!(f, "{error}"),
         }
     }
 }
 
 impl std::error::Error for EvalError {}
 
-/// Evaluate a recursive function body with tail call optimization (TCO)
-/// 
-/// This function implements tail call optimization for recursive functions. Instead of
-/// creating a new stack frame for each recursive call, it iteratively updates the
-/// environment and re-evaluates the body expression. This allows deep recursion without
-/// stack overflow for tail-recursive functions.
-/// 
-/// # Arguments
-/// * `body` - The body expression of the recursive function
-/// * `initial_env` - The initial environment with the argument binding
-/// * `rec_name` - The name of the recursive function
-/// * `param_name` - The name of the function parameter
-/// * `closure_env` - The environment captured in the closure
-/// 
-/// # Returns
-/// The result value of evaluating the function, or an error
-/// 
-/// # Example
-/// For a tail-recursive factorial with accumulator:
-/// ```text
-/// rec fact -> fun acc -> fun n ->
-///     if n == 0 then acc else fact (acc * n) (n - 1)
-/// ```
-/// Instead of recursing, this function updates `acc` and `n` and re-evaluates the body.
-fn eval_with_tco(
-    body: &Expr,
-    initial_env: &Environment,
-    rec_name: &str,
-    param_name: &str,
-    closure_env: &Environment,
-) -> Result<Value, EvalError> {
-    let mut current_expr = body.clone();
-    let mut current_env = initial_env.clone();
-    
-    loop {
-        // Check if the expression is a tail call to the recursive function
-        match &current_expr {
-            // Direct tail call: rec_name arg
-            Expr::App(func, arg) => {
-                // Check if this is a call to the recursive function (possibly nested in applications)
-                if is_tail_call_to(func, rec_name) {
-                    // This is a tail call - evaluate arg and loop instead of recursing
-                    let arg_val = eval(arg, &current_env)?;
-                    
-                    // Reset environment for next iteration
-                    let rec_val = Value::RecClosure(
-                        rec_name.to_string(),
-                        param_name.to_string(),
-                        body.clone(),
-                        closure_env.clone(),
-                    );
-                    current_env = closure_env.extend(rec_name.to_string(), rec_val);
-                    current_env = current_env.extend(param_name.to_string(), arg_val);
-                    current_expr = body.clone();
-                    continue;
-                }
-                // Not a tail call to self - evaluate normally and return
-                break eval(&current_expr, &current_env);
-            }
-            // Handle if expressions - evaluate condition and continue with the appropriate branch
-            Expr::If(cond, then_branch, else_branch) => {
-                let cond_val = eval(cond, &current_env)?;
-                match cond_val {
-                    Value::Bool(true) => {
-                        current_expr = (**then_branch).clone();
-                    }
-                    Value::Bool(false) => {
-                        current_expr = (**else_branch).clone();
-                    }
-                    _ => return Err(EvalError::TypeError(
-                        "if condition must evaluate to a boolean".to_string(),
-                    )),
-                }
+impl EvalError {
+    /// Whether `try ... catch` may handle this error
+    ///
+    /// Failures of the program's own logic are recoverable: division by zero,
+    /// an index out of bounds, a match with no matching arm, a pattern
+    /// binding given a value it does not match, and a failing
+    /// `load`, including an error in a loaded library's binding. Running out
+    /// of steps or calls, being interrupted, exceeding a result limit, and errors that signal a broken
+    /// program (type errors, unbound names, unknown constructors) are not,
+    /// so a handler can never hide them.
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            EvalError::DivisionByZero
+            | EvalError::IndexOutOfBounds(_)
+            | EvalError::PatternMatchNonExhaustive
+            | EvalError::PatternMismatch { .. }
+            | EvalError::LoadError(_) => true,
+            EvalError::InBinding(_, _, error) | EvalError::Context(_, error) | EvalError::At(_, error) => {
+                error.is_recoverable()
             }
-            // For other expressions, evaluate normally and return
-            _ => break eval(&current_expr, &current_env),
+            _ => false,
         }
     }
-}
 
-/// Check if an expression is ultimately a call to the recursive function
-/// 
-/// This helper function determines whether an expression is a direct or indirect call
-/// to the named recursive function. It handles nested applications like `(rec_name arg1) arg2`
-/// by recursively checking the function part of applications.
-/// 
-/// # Arguments
-/// * `expr` - The expression to check
-/// * `rec_name` - The name of the recursive function
-/// 
-/// # Returns
-/// `true` if the expression calls the recursive function, `false` otherwise
-/// 
+    /// Name of the error's kind, as `try ... catch` reports it
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::UnboundVariable(..) => "UnboundVariable",
+            EvalError::TypeError(_) | EvalError::BinOpTypeMismatch { .. } | EvalError::OverApplied { .. } => "TypeError",
+            EvalError::DivisionByZero => "DivisionByZero",
+            EvalError::LoadError(_) => "LoadError",
+            EvalError::IndexOutOfBounds(_) => "IndexOutOfBounds",
+            EvalError::FieldNotFound(..) => "FieldNotFound",
+            EvalError::RecordExpected(_) => "RecordExpected",
+            EvalError::UnknownConstructor(..) => "UnknownConstructor",
+            EvalError::ConstructorArityMismatch(..) => "ConstructorArityMismatch",
+            EvalError::PatternMatchNonExhaustive => "PatternMatchNonExhaustive",
+            EvalError::PatternMismatch { .. } => "PatternMismatch",
+            EvalError::InBinding(_, _, error) | EvalError::Context(_, error) | EvalError::At(_, error) => error.kind(),
+            EvalError::StepLimitExceeded(_) => "StepLimitExceeded",
+            EvalError::RecursionLimitExceeded(_) => "RecursionLimitExceeded",
+            EvalError::Interrupted => "Interrupted",
+            EvalError::ResultRetainsTooMuch { .. } => "ResultRetainsTooMuch",
+        }
+    }
+
+    /// The record `{kind: String, message: String}` a `catch` handler receives
+    ///
+    /// The message is the root error's, without the trace.
+    pub(crate) fn to_value(&self) -> Value {
+        Value::Record(Fields::from([
+            ("kind".to_string(), string_value(self.kind())),
+            ("message".to_string(), string_value(&self.root().to_string())),
+        ]))
+    }
+
+    /// Add a frame to the error's trace, as it propagates out of `frame`
+    ///
+    /// Only the innermost [`MAX_TRACE_FRAMES`] frames are kept, followed by
+    /// a `...` frame, so deep recursion does not build a huge trace. Running
+    /// out of steps or calls, an interrupt, or going over a result limit
+    /// concerns the whole evaluation and is never traced.
+    #[must_use]
+    pub fn with_context(self, frame: impl FnOnce() -> String) -> EvalError {
+        if matches!(
+            self,
+            EvalError::StepLimitExceeded(_)
+                | EvalError::RecursionLimitExceeded(_)
+                | EvalError::Interrupted
+                | EvalError::ResultRetainsTooMuch { .. }
+        ) {
+            return self;
+        }
+        match self.trace().len() {
+            depth if depth < MAX_TRACE_FRAMES => EvalError::Context(frame(), Box::new(self)),
+            MAX_TRACE_FRAMES => EvalError::Context(ELIDED_FRAMES.to_string(), Box::new(self)),
+            _ => self,
+        }
+    }
+
+    /// The frames of the error's trace, outermost first
+    pub fn trace(&self) -> Vec<&str> {
+        let mut frames = Vec::new();
+        let mut error = self;
+        while let EvalError::Context(frame, inner) = error {
+            frames.push(frame.as_str());
+            error = inner;
+        }
+        frames
+    }
+
+    /// The error without its trace or span
+    pub fn root(&self) -> &EvalError {
+        match self {
+            EvalError::Context(_, error) | EvalError::At(_, error) => error.root(),
+            error => error,
+        }
+    }
+
+    /// Where in the source the error was raised, if it is known
+    ///
+    /// An error in a loaded library's binding has no span: the spans are
+    /// those of the program passed to [`eval_with_spans`].
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            EvalError::At(span, _) => Some(*span),
+            EvalError::Context(_, error) => error.span(),
+            _ => None,
+        }
+    }
+
+    /// The error raised in `span`, unless it already has a span or concerns
+    /// the whole evaluation, like the errors [`EvalError::with_context`] does not trace
+    ///
+    /// The span goes under the trace, which stays readable by [`EvalError::trace`].
+    pub(crate) fn at(self, span: Span) -> EvalError {
+        if self.span().is_some()
+            || matches!(
+                self,
+                EvalError::StepLimitExceeded(_)
+                    | EvalError::RecursionLimitExceeded(_)
+                    | EvalError::Interrupted
+                    | EvalError::ResultRetainsTooMuch { .. }
+            )
+        {
+            return self;
+        }
+        match self {
+            EvalError::Context(frame, error) => EvalError::Context(frame, Box::new(error.at(span))),
+            error => EvalError::At(span, Box::new(error)),
+        }
+    }
+}
+
+/// The body of a function call, left to evaluate
+pub(crate) enum TailCall {
+    /// The body `root` of a `Value::Closure` or `Value::RecClosure`, with its
+    /// first `skip` nested `fun`s already applied, in `env`
+    Core { root: Rc<CoreExpr>, skip: usize, env: Environment },
+    /// The body of a resolved function, in a frame starting with `slots`,
+    /// after `applied` of its parameters
+    Resolved { closure: Rc<ResolvedClosure>, slots: Vec<Value>, applied: usize },
+}
+
+impl TailCall {
+    /// The environment the call counts its steps and depth in
+    fn env(&self) -> &Environment {
+        match self {
+            TailCall::Core { env, .. } => env,
+            TailCall::Resolved { closure, .. } => closure.env(),
+        }
+    }
+}
+
+/// A call to report to the profiler when it is made as a tail call
+pub(crate) struct ProfiledCall {
+    profiler: Rc<Profiler>,
+    name: String,
+    /// Whether the call starts a new call rather than continuing a curried one
+    new_call: bool,
+}
+
+/// What evaluating an expression in tail position leaves to do
+pub(crate) enum Tail {
+    /// Nothing: this is its value
+    Value(Value),
+    /// Make this call, whose value is the expression's
+    Call(TailCall, Option<ProfiledCall>),
+}
+
+/// Make a call, and then each call its body ends with in place of the last
+///
+/// This is the evaluator's tail call optimization: a call in tail position,
+/// to the same function or any other closure, neither grows the Rust stack
+/// nor counts against the depth limit, so tail-recursive and mutually
+/// tail-recursive functions run in constant stack. A tail call replaces its
+/// caller, so an error's trace shows the call the chain started with. A
+/// function first entered by a tail call stays active in the profile until
+/// the chain returns.
+///
 /// # Example
-/// - `is_tail_call_to(Var("fact"), "fact")` returns `true`
-/// - `is_tail_call_to(App(Var("fact"), Lit(5)), "fact")` returns `true`
-/// - `is_tail_call_to(Var("other"), "fact")` returns `false`
-fn is_tail_call_to(expr: &Expr, rec_name: &str) -> bool {
+/// For a tail-recursive factorial with accumulator:
+/// ```text
+/// rec fact -> fun acc -> fun n ->
+///     if n == 0 then acc else fact (acc * n) (n - 1)
+/// ```
+/// Each `fact (acc * n) (n - 1)` is left by the body as a [`TailCall`] and
+/// made here, instead of inside the call that reached it.
+fn run_tail_calls(mut call: TailCall) -> Result<Value, EvalError> {
+    let mut entered = Vec::new();
+    loop {
+        let tail = match call {
+            TailCall::Core { root, skip, env } => eval_body(&root, skip, env)?,
+            TailCall::Resolved { closure, slots, applied } => resolve::eval_body(&closure, slots, applied)?,
+        };
+        match tail {
+            Tail::Value(value) => return Ok(value),
+            Tail::Call(next, profiled) => {
+                if let Some(ProfiledCall { profiler, name, new_call }) = profiled {
+                    entered.extend(profiler.tail_call(name, new_call));
+                }
+                call = next;
+            }
+        }
+    }
+}
+
+/// Make a call that is not in tail position, counting it against the depth limit
+fn run_call(call: TailCall) -> Result<Value, EvalError> {
+    let _depth = call.env().enter_call()?;
+    run_tail_calls(call)
+}
+
+/// Evaluate a closure body up to the expression in tail position
+///
+/// `if`, `match` and `let` are followed into the branch, arm or body they
+/// continue with, and a call of a closure found there is left for
+/// [`run_tail_calls`] to make. Counts the same steps as [`eval_core`].
+fn eval_body(root: &CoreExpr, skip: usize, mut env: Environment) -> Result<Tail, EvalError> {
+    let mut current = root;
+    for _ in 0..skip {
+        let CoreExpr::Lam(_, inner) = current else { unreachable!("closure_arity counted a nested fun") };
+        current = inner;
+    }
+    // Patterns of the match arms entered, for the trace
+    let mut arms: Vec<&Pattern> = Vec::new();
+    // Span of the innermost node entered, which errors raised so far are in
+    let mut span = None;
+    let result = (|| loop {
+        match current {
+            CoreExpr::At(at, inner) if matches!(**inner, CoreExpr::If(..) | CoreExpr::Match(..) | CoreExpr::App(..)) => {
+                span = Some(*at);
+                current = inner;
+            }
+            CoreExpr::If(cond, then_branch, else_branch) => {
+                env.count_step()?;
+                current = match eval_core(cond, &env)? {
+                    Value::Bool(true) => then_branch,
+                    Value::Bool(false) => else_branch,
+                    _ => return Err(EvalError::TypeError("If condition must be a boolean".to_string())),
+                };
+            }
+            CoreExpr::Match(scrutinee, match_arms) => {
+                env.count_step()?;
+                let (pattern, arm, new_env) = select_arm(scrutinee, match_arms, &env)?;
+                arms.push(pattern);
+                current = arm;
+                env = new_env;
+            }
+            CoreExpr::Let(name, value, body) => {
+                env.count_step()?;
+                let value = eval_core(value, &env).map_err(|e| in_let(e, name))?;
+                env.bind(name.clone(), value);
+                current = body;
+            }
+            CoreExpr::App(..) => {
+                env.count_step()?;
+                let (head, args) = spine(current);
+                let func_val = eval_core(head, &env)?;
+                break apply_spine_in(func_val, var_name(head), &args, &env, |arg| eval_core(arg, &env), true);
+            }
+            _ => break eval_core(current, &env).map(Tail::Value),
+        }
+    })();
+    result.map_err(|e| {
+        let e = in_arms(e, &arms);
+        match span {
+            Some(span) => e.at(span),
+            None => e,
+        }
+    })
+}
+
+/// The function and the arguments, left to right, of an application spine `head a1 a2 ... an`
+fn spine(expr: &CoreExpr) -> (&CoreExpr, Vec<&CoreExpr>) {
+    let mut args = Vec::new();
+    let mut head = expr;
+    while let CoreExpr::App(func, arg) = head {
+        args.push(arg.as_ref());
+        head = func;
+    }
+    args.reverse();
+    (head, args)
+}
+
+/// The variable `expr` is, if it is one
+fn var_name(expr: &CoreExpr) -> Option<&str> {
     match expr {
-        Expr::Var(name) => name == rec_name,
-        Expr::App(func, _) => is_tail_call_to(func, rec_name),
+        CoreExpr::Var(name) => Some(name),
+        CoreExpr::At(_, inner) => var_name(inner),
+        _ => None,
+    }
+}
+
+/// Trace frame of a call of the function named `name`
+pub(crate) fn call_frame(name: &str) -> String {
+    format!("in application of '{name}'")
+}
+
+/// `error` raised evaluating the value of `let name = ...`, with its trace frame
+///
+/// A name desugaring made, which starts with `_`, gets no frame: it stands
+/// for a pattern, and a value the pattern does not match is reported with
+/// the pattern itself.
+pub(crate) fn in_let(error: EvalError, name: &str) -> EvalError {
+    if name.starts_with('_') {
+        error
+    } else {
+        error.with_context(|| format!("in let binding '{name}'"))
+    }
+}
+
+/// `error` raised inside the match arms with `patterns`, outermost first, with their trace frames
+pub(crate) fn in_arms(error: EvalError, patterns: &[&Pattern]) -> EvalError {
+    patterns.iter().rev().fold(error, |error, pattern| error.with_context(|| format!("in match arm '{pattern}'")))
+}
+
+/// Evaluate a match's scrutinee and pick the first arm whose pattern matches it
+/// and whose guard, if any, holds
+/// 
+/// Returns the arm's pattern and result with the environment extended by
+/// the pattern's bindings. Warns on stderr when the arms are not exhaustive.
+/// 
+/// # Errors
+/// 
+/// Returns an error if evaluating the scrutinee or a guard fails, a
+/// constructor pattern has the wrong arity, or no arm matches.
+fn select_arm<'a>(
+    scrutinee: &CoreExpr,
+    arms: &'a [(Pattern, Option<CoreExpr>, CoreExpr)],
+    env: &Environment,
+) -> Result<(&'a Pattern, &'a CoreExpr, Environment), EvalError> {
+    let patterns: Vec<Pattern> = arms.iter().map(|(p, _, _)| p.clone()).collect();
+    let guard = |index: usize, bindings: &[(String, Value)]| match &arms[index] {
+        (pattern, Some(guard), _) => eval_core(guard, &with_bindings(env, bindings.to_vec()))
+            .and_then(|value| guard_holds(&value))
+            .map_err(|e| in_arms(e, &[pattern])),
+        (_, None, _) => Ok(true),
+    };
+    let binding = binds_pattern(var_name(scrutinee), arms.iter().map(|(_, guard, _)| guard.is_some()));
+    let (index, bindings) = select_arm_index(&patterns, env, || eval_core(scrutinee, env), guard, binding)?;
+    let (pattern, _, result) = &arms[index];
+    Ok((pattern, result, with_bindings(env, bindings)))
+}
+
+/// Whether the value of a match guard lets its arm be taken
+pub(crate) fn guard_holds(value: &Value) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(EvalError::TypeError("Match guard must be a boolean".to_string())),
+    }
+}
+
+/// Pick the first of a match's patterns that matches its scrutinee
+/// 
+/// Evaluates the scrutinee with `scrutinee`; non-exhaustive matches are
+/// reported before the program runs, by [`check_matches`](crate::exhaustiveness::check_matches).
+/// A matching pattern is only picked when `guard`, given its index and the
+/// variables it binds, returns `true`. Returns the index of the picked
+/// pattern and the variables it binds, in the order of [`match_value`].
+/// When `binding`, the match is a pattern binding (see [`binds_pattern`]).
+/// 
+/// # Errors
+/// 
+/// Returns an error if evaluating the scrutinee or `guard` fails, a
+/// constructor pattern has the wrong arity, or no pattern is picked:
+/// [`EvalError::PatternMismatch`] for a pattern binding, and
+/// [`EvalError::PatternMatchNonExhaustive`] otherwise.
+pub(crate) fn select_arm_index(
+    patterns: &[Pattern],
+    env: &Environment,
+    scrutinee: impl FnOnce() -> Result<Value, EvalError>,
+    mut guard: impl FnMut(usize, &[(String, Value)]) -> Result<bool, EvalError>,
+    binding: bool,
+) -> Result<(usize, Vec<(String, Value)>), EvalError> {
+    // Evaluate the scrutinee expression
+    let val = scrutinee()?;
+    
+    for pattern in patterns {
+        check_constructor_arity(pattern, env)?;
+    }
+    
+    // Try to match against each pattern in order
+    for (index, pattern) in patterns.iter().enumerate() {
+        reject_opaque_literal(pattern, &val)?;
+        if let Some(bindings) = match_value(pattern, &val) {
+            if guard(index, &bindings)? {
+                return Ok((index, bindings));
+            }
+        }
+    }
+    
+    // No pattern matched - use the dedicated error variant
+    match patterns {
+        [pattern] if binding => Err(EvalError::PatternMismatch { pattern: pattern.to_string(), value: operand_rendering(&val) }),
+        _ => Err(EvalError::PatternMatchNonExhaustive),
+    }
+}
+
+/// Whether a match is a pattern binding: the parser's desugaring of
+/// `let p = v in e` or `fun p -> e`, which matches a variable it named,
+/// starting with `_`, against a single pattern with no guard
+///
+/// `guarded` says for each arm whether it has a guard.
+pub(crate) fn binds_pattern(scrutinee: Option<&str>, guarded: impl IntoIterator<Item = bool>) -> bool {
+    let mut guarded = guarded.into_iter();
+    scrutinee.is_some_and(|name| name.starts_with('_')) && guarded.next() == Some(false) && guarded.next().is_none()
+}
+
+/// Apply a function value to an argument
+/// 
+/// Handles closures, recursive closures (with tail call optimization), and
+/// built-in functions, which collect arguments until they are saturated.
+/// 
+/// # Errors
+/// 
+/// Returns an error if `func_val` is not a function or if evaluating the
+/// function body fails.
+pub fn apply(func_val: Value, arg_val: Value) -> Result<Value, EvalError> {
+    let _call = profiled_call(&func_val, None, &mut None).and_then(|(profiler, name)| profiler.enter(name, true));
+    match start_call(func_val, arg_val)? {
+        Tail::Value(value) => Ok(value),
+        Tail::Call(call, _) => run_call(call),
+    }
+}
+
+/// Apply a function value to an argument, leaving a closure's body to evaluate
+fn start_call(func_val: Value, arg_val: Value) -> Result<Tail, EvalError> {
+    match func_val {
+        Value::Closure(param, body, closure_env) => {
+            let env = closure_env.extend(param, arg_val);
+            Ok(Tail::Call(TailCall::Core { root: body, skip: 0, env }, None))
+        }
+        Value::RecClosure(rec_name, param, body, closure_env) => {
+            // Bind the recursive function to itself, then the argument
+            let rec_val = Value::RecClosure(
+                rec_name.clone(),
+                param.clone(),
+                Rc::clone(&body),
+                closure_env.clone(),
+            );
+            let env = closure_env.extend(rec_name, rec_val).extend(param, arg_val);
+            Ok(Tail::Call(TailCall::Core { root: body, skip: 0, env }, None))
+        }
+        Value::Resolved(closure) => Ok(Tail::Call(resolve::start_call(&closure, vec![arg_val]), None)),
+        Value::Partial(_, _, _, closure) => start_call(*closure, arg_val),
+        Value::Builtin(builtin) => builtin.apply(arg_val).map(Tail::Value),
+        _ => Err(EvalError::TypeError(
+            "Application requires a function".to_string(),
+        )),
+    }
+}
+
+/// The profiler to report a call of `func_val` to, and the name of the call
+/// 
+/// Returns `None` unless `func_val` is a closure whose environment has an
+/// enabled profiler. The name is computed once per application spine and
+/// kept in `name`, so a curried call is reported under one name throughout;
+/// `head` is the variable the function was called through, if any.
+fn profiled_call(func_val: &Value, head: Option<&str>, name: &mut Option<String>) -> Option<(Rc<Profiler>, String)> {
+    let closure_env = match func_val {
+        Value::Closure(_, _, closure_env) | Value::RecClosure(_, _, _, closure_env) => closure_env,
+        Value::Resolved(closure) => closure.env(),
+        _ => return None,
+    };
+    let profiler = closure_env.profiler.as_ref().filter(|profiler| profiler.is_enabled())?;
+    let name = name.get_or_insert_with(|| match (recursive_name(func_val), head, func_val) {
+        (Some(rec_name), _, _) => rec_name.to_string(),
+        (None, Some(var), _) => var.to_string(),
+        (None, None, Value::Closure(param, body, _)) => profiler.anonymous_name(param, body),
+        (None, None, Value::Resolved(closure)) => profiler.anonymous_name(closure.param(), closure.source_body()),
+        _ => unreachable!("only closures are profiled"),
+    });
+    Some((Rc::clone(profiler), name.clone()))
+}
+
+/// Apply a function value to the arguments of an application spine
+/// 
+/// Arguments are evaluated left to right. When the function is a closure
+/// whose body is a chain of nested `fun`s, as many arguments as the chain
+/// accepts are bound into one extended environment and the innermost body is
+/// evaluated directly, skipping the intermediate closures. This is
+/// equivalent to applying the arguments one at a time, because creating the
+/// intermediate closures has no observable effect. Any remaining arguments
+/// are evaluated only after that body has run, as with one-at-a-time
+/// application.
+/// 
+/// A named function (a recursive closure, a closure called through a
+/// variable, or a partial application of either) given fewer arguments than
+/// its arity returns a `Value::Partial` recording how many it has. Given
+/// more, and returning a non-function, it fails with
+/// `EvalError::OverApplied`. The arity counts the nested `fun`s in the
+/// function's body, so it is a syntactic approximation: a function that
+/// computes and returns another function can still be applied further.
+/// 
+/// `head` is the variable the function was called through, if any, and
+/// `eval_arg` evaluates an argument; the evaluator in [`crate::resolve`]
+/// shares this function with [`eval_core`].
+/// 
+/// # Errors
+/// 
+/// Returns an error if evaluating an argument or applying a function fails.
+pub(crate) fn apply_spine<A>(
+    func_val: Value,
+    head: Option<&str>,
+    args: &[A],
+    env: &Environment,
+    eval_arg: impl FnMut(&A) -> Result<Value, EvalError>,
+) -> Result<Value, EvalError> {
+    match apply_spine_in(func_val, head, args, env, eval_arg, false)? {
+        Tail::Value(value) => Ok(value),
+        Tail::Call(..) => unreachable!("only spines in tail position leave their call"),
+    }
+}
+
+/// [`apply_spine`], leaving the spine's last call for [`run_tail_calls`] when `tail` is set
+///
+/// The last call is left when it runs a closure's body and its result is
+/// not made a `Value::Partial`; calls before it are made here.
+pub(crate) fn apply_spine_in<A>(
+    mut func_val: Value,
+    head: Option<&str>,
+    args: &[A],
+    env: &Environment,
+    mut eval_arg: impl FnMut(&A) -> Result<Value, EvalError>,
+    tail: bool,
+) -> Result<Tail, EvalError> {
+    let (applied, arity) = match &func_val {
+        Value::Closure(_, body, _) | Value::RecClosure(_, _, body, _) => (0, closure_arity(body)),
+        Value::Resolved(closure) => (0, closure.arity()),
+        Value::Partial(_, applied, arity, _) => (*applied, *arity),
+        _ => (0, 0),
+    };
+    // Only a spine that under- or over-applies needs the function's name
+    let function = (applied + args.len() != arity).then(|| spine_function_name(&func_val, head)).flatten();
+    let partial = function.is_some() && applied + args.len() < arity;
+    // Errors raised inside the calls, but not in the arguments, are traced to the spine
+    let rec_name = if head.is_none() { recursive_name(&func_val).map(str::to_string) } else { None };
+    let frame_name = head.or(rec_name.as_deref());
+    let traced = |error: EvalError| match frame_name {
+        Some(name) => error.with_context(|| call_frame(name)),
+        None => error,
+    };
+    if let Value::Partial(_, _, _, closure) = func_val {
+        func_val = *closure;
+    }
+    let mut rest = args;
+    let mut name = None;
+    while let Some((first, remaining)) = rest.split_first() {
+        let new_call = rest.len() == args.len();
+        if let Some(function) = function.as_ref().filter(|_| func_val.kind_name() != "function") {
+            return Err(EvalError::OverApplied {
+                function: function.clone(),
+                arity,
+                args: applied + args.len(),
+                result_kind: func_val.kind_name(),
+            });
+        }
+        let call = profiled_call(&func_val, head, &mut name);
+        // Errors of built-ins and of applying a non-function are about the call itself
+        let traced_call = is_closure(&func_val);
+        let started = match func_val {
+            Value::Closure(param, body, closure_env) if !remaining.is_empty() => {
+                let count = closure_arity(&body).min(rest.len());
+                let mut new_env = closure_env;
+                new_env.bind(param, eval_arg(first)?);
+                let mut inner = body.as_ref();
+                for arg in &remaining[..count - 1] {
+                    let CoreExpr::Lam(inner_param, inner_body) = inner else {
+                        unreachable!("closure_arity counted a nested fun")
+                    };
+                    new_env.bind(inner_param.clone(), eval_arg(arg)?);
+                    inner = inner_body;
+                }
+                rest = &rest[count..];
+                Tail::Call(TailCall::Core { root: body, skip: count - 1, env: new_env }, None)
+            }
+            Value::Resolved(closure) if closure.rec_name().is_none() && !remaining.is_empty() => {
+                let count = closure.arity().min(rest.len());
+                let arg_vals = rest[..count].iter().map(&mut eval_arg).collect::<Result<Vec<_>, _>>()?;
+                rest = &rest[count..];
+                Tail::Call(resolve::start_call(&closure, arg_vals), None)
+            }
+            // Only here is the caller's environment known, so `debug_env` is answered here
+            Value::Builtin(builtin) if builtin.name == DEBUG_ENV && builtin.args.is_empty() && env.debug_builtins() => {
+                eval_arg(first)?;
+                rest = remaining;
+                Tail::Value(env.debug_record())
+            }
+            other => {
+                let arg_val = eval_arg(first)?;
+                rest = remaining;
+                start_call(other, arg_val)?
+            }
+        };
+        func_val = match started {
+            Tail::Value(value) => value,
+            Tail::Call(body, _) if tail && rest.is_empty() && !partial => {
+                let profiled = call.map(|(profiler, name)| ProfiledCall { profiler, name, new_call });
+                return Ok(Tail::Call(body, profiled));
+            }
+            Tail::Call(body, _) => {
+                let _call = call.and_then(|(profiler, name)| profiler.enter(name, new_call));
+                let result = run_call(body);
+                if traced_call { result.map_err(traced)? } else { result? }
+            }
+        };
+    }
+    match function {
+        Some(function) if partial && is_plain_closure(&func_val) => {
+            Ok(Tail::Value(Value::Partial(function, applied + args.len(), arity, Box::new(func_val))))
+        }
+        _ => Ok(Tail::Value(func_val)),
+    }
+}
+
+/// Name a function at the head of an application spine is known by, if any
+/// 
+/// Partial applications carry their own name. Closures are named by the
+/// variable they were called through, so `let gcd = rec g -> ...` is
+/// reported as `gcd`; a recursive closure not called through a variable
+/// falls back to its own name.
+fn spine_function_name(func_val: &Value, head: Option<&str>) -> Option<String> {
+    if let Value::Partial(name, ..) = func_val {
+        return Some(name.clone());
+    }
+    match (recursive_name(func_val), head) {
+        (Some(_), Some(var)) => Some(var.to_string()),
+        (Some(name), None) => Some(name.to_string()),
+        (None, Some(var)) if is_plain_closure(func_val) => Some(var.to_string()),
+        _ => None,
+    }
+}
+
+/// The name of a recursive closure
+fn recursive_name(func_val: &Value) -> Option<&str> {
+    match func_val {
+        Value::RecClosure(name, ..) => Some(name),
+        Value::Resolved(closure) => closure.rec_name(),
+        _ => None,
+    }
+}
+
+/// Whether `func_val` is a closure, whose errors are traced to its calls
+///
+/// Errors of built-ins and of applying a non-function are about the call
+/// itself rather than something inside it.
+fn is_closure(func_val: &Value) -> bool {
+    matches!(func_val, Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_))
+}
+
+/// Whether `func_val` is a closure that is not recursive
+fn is_plain_closure(func_val: &Value) -> bool {
+    match func_val {
+        Value::Closure(..) => true,
+        Value::Resolved(closure) => closure.rec_name().is_none(),
         _ => false,
     }
 }
 
+/// `value` with every function in it replaced by its rendering as a string
+///
+/// Values inside reference cells are left alone.
+fn without_functions(value: &Value) -> Value {
+    match value {
+        Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_) | Value::Partial(..) | Value::Builtin(_) => {
+            string_value(&value.to_string())
+        }
+        Value::Tuple(values) => Value::Tuple(values.iter().map(without_functions).collect()),
+        Value::Record(fields) => {
+            Value::Record(fields.iter().map(|(name, value)| (name.clone(), without_functions(value))).collect())
+        }
+        Value::Variant(ctor, values) => Value::Variant(ctor.clone(), values.iter().map(without_functions).collect()),
+        Value::Array(size, values) => Value::Array(*size, values.iter().map(without_functions).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Number of arguments a closure with this body takes before its body is evaluated
+/// 
+/// Counts the closure's own parameter plus the directly nested `fun`s.
+pub(crate) fn closure_arity(body: &CoreExpr) -> usize {
+    let mut arity = 1;
+    let mut body = body;
+    while let CoreExpr::Lam(_, inner) = body {
+        arity += 1;
+        body = inner;
+    }
+    arity
+}
+
 /// Extract bindings from nested let expressions
 /// This walks through the AST and extracts all top-level let bindings.
-/// Used by the REPL to persist function definitions and library loads across evaluations.
+/// [`eval_toplevel`] also evaluates the rest of the program, for the REPL
+/// to persist function definitions and library loads across evaluations.
 /// 
 /// # Errors
 /// 
@@ -376,177 +1911,447 @@ fn is_tail_call_to(expr: &Expr, rec_name: &str) -> bool {
 /// - Loading a library file fails (file not found or parse error)
 /// - A binding value causes a type error or other evaluation error
 pub fn extract_bindings(expr: &Expr, env: &Environment) -> Result<Environment, EvalError> {
-    match expr {
-        Expr::Let(name, _ty_ann, value, body) => {
-            // Evaluate the value in the current environment
-            let val = eval(value, env)?;
-            // Extend the environment with this binding
-            let new_env = env.extend(name.clone(), val);
-            // Continue extracting from the body
-            extract_bindings(body, &new_env)
-        }
-        Expr::Load(filepath, body) => {
-            // Handle nested load expressions
-            // Read and parse the file
-            let content = fs::read_to_string(Path::new(filepath))
-                .map_err(|e| EvalError::LoadError(format!("Failed to read file '{filepath}': {e}")))?;
-            let lib_expr = crate::parser::parse(&content)
-                .map_err(|e| EvalError::LoadError(format!("Failed to parse file '{filepath}': {e}")))?;
-            
-            // Extract bindings from the loaded library
-            // Pass current environment so type constructors are available
-            let lib_env = extract_bindings(&lib_expr, env)?;
-            // Merge with current environment
-            let new_env = env.merge(&lib_env);
-            // Continue extracting from the body
-            extract_bindings(body, &new_env)
+    bind_spine(expr, env).map(|(_, env)| env)
+}
+
+/// Evaluate a program, keeping the bindings of its top-level spine
+///
+/// Each binding [`extract_bindings`] would keep is evaluated once, and the
+/// rest of the program is evaluated in the environment they build, so the
+/// bindings' side effects happen once. Returns the program's value and
+/// that environment.
+///
+/// # Errors
+///
+/// Returns the error evaluating a binding, loading a library, or evaluating
+/// the rest of the program.
+pub fn eval_toplevel(expr: &Expr, env: &Environment) -> Result<(Value, Environment), EvalError> {
+    let (rest, env) = bind_spine(expr, env)?;
+    let value = eval(rest, &env)?;
+    Ok((value, env))
+}
+
+/// Bind the top-level lets, loads and type definitions that start `expr`
+///
+/// Returns the expression after them and the environment they build. Binding
+/// errors carry the same `let` trace frame as when the whole program is
+/// evaluated.
+fn bind_spine<'a>(mut expr: &'a Expr, env: &Environment) -> Result<(&'a Expr, Environment), EvalError> {
+    let mut env = env.clone();
+    loop {
+        expr = match expr {
+            Expr::Let(name, _ty_ann, value, body) => {
+                let val = eval(value, &env).map_err(|e| in_let(e, name))?;
+                env = env.extend(name.clone(), val);
+                body
+            }
+            Expr::Load(filepath, body) => {
+                // Handle nested load expressions the same way `eval` does
+                env = import_library(filepath, &env)?;
+                body
+            }
+            Expr::LoadAs(filepath, namespace, body) => {
+                env = env.extend(namespace.clone(), load_namespace(filepath, &env)?);
+                body
+            }
+            Expr::Seq(bindings, body) => {
+                for (name, _ty_ann, value) in bindings {
+                    let val = eval(value, &env).map_err(|e| in_let(e, name))?;
+                    env = env.extend(name.clone(), val);
+                }
+                body
+            }
+            // Type aliases don't create runtime bindings
+            Expr::TypeAlias(_name, _ty_expr, body) => body,
+            Expr::TypeDef { name, constructors, body, .. } => {
+                // Keep the constructors registered so later inputs can build and match them
+                let arities: Vec<(String, usize)> =
+                    constructors.iter().map(|(ctor_name, payload)| (ctor_name.clone(), payload.len())).collect();
+                register_type_def(&mut env, name, &arities);
+                body
+            }
+            // Anything else ends the spine
+            _ => return Ok((expr, env)),
+        };
+    }
+}
+
+/// Add the bindings of a library file underneath the bindings of `env`
+///
+/// Imports are the base layer: a name `env` already binds, locally or in the
+/// shared top-level layer, keeps its value, and the library's binding of the
+/// same name is left out with a warning on stderr. Inside the library its own
+/// bindings are used as usual, and closures created before the load keep
+/// what they captured.
+fn import_library(filepath: &str, env: &Environment) -> Result<Environment, EvalError> {
+    let lib_env = load_library(filepath, env)?;
+    let mut new_env = env.clone();
+    for name in lib_env.binding_order() {
+        if env.defines(&name) {
+            eprintln!("Warning: '{name}' imported from '{filepath}' is shadowed by the existing binding");
+        } else {
+            let value = lib_env.bindings.get(&name).cloned().expect("ordered names are bound");
+            new_env.bind(name, value);
         }
+    }
+    Ok(new_env)
+}
+
+/// Add the bindings of a library file over the bindings of `env`
+///
+/// Unlike [`import_library`], the library's bindings replace those `env`
+/// already has of the same names, so a library loaded again after its file
+/// changed takes effect.
+pub(crate) fn reimport_library(filepath: &str, env: &Environment) -> Result<Environment, EvalError> {
+    let lib_env = load_library(filepath, env)?;
+    let mut new_env = env.clone();
+    for name in lib_env.binding_order() {
+        let value = lib_env.bindings.get(&name).cloned().expect("ordered names are bound");
+        new_env.bind(name, value);
+    }
+    Ok(new_env)
+}
+
+/// Load a library file as a namespace: a record of its bindings
+///
+/// `load "path" as Name` binds `Name` to this record, so the library's
+/// bindings are reached as `Name.binding` and never clash with other names.
+fn load_namespace(filepath: &str, env: &Environment) -> Result<Value, EvalError> {
+    let lib_env = load_library(filepath, env)?;
+    let fields = lib_env
+        .binding_order()
+        .into_iter()
+        .map(|name| {
+            let value = lib_env.bindings.get(&name).cloned().expect("ordered names are bound");
+            (name, value)
+        })
+        .collect();
+    Ok(Value::Record(fields))
+}
+
+/// Find, read, parse, and evaluate a library file
+///
+/// The file is found through the environment's [`LoadPath`], and loading a
+/// library that is still being loaded fails as a circular load. The file is
+/// read and parsed once; later loads reuse the environment's cached parse.
+/// The library is evaluated in `env` so type constructors are available. The
+/// returned environment's [`binding_order`](Environment::binding_order) lists
+/// only the names the library itself defines.
+/// Errors from the library's top-level bindings are wrapped in
+/// `EvalError::InBinding` with the `file:line` where the binding starts.
+/// Bindings left out by the environment's prune plan are skipped unevaluated.
+///
+/// A blank file (see [`is_blank_source`]) loads as no bindings. Whenever the
+/// file defines no bindings or types, a warning on stderr says so, since a
+/// library that contributes nothing is usually a mistake.
+fn load_library(filepath: &str, env: &Environment) -> Result<Environment, EvalError> {
+    let path = env.load_path.resolve(filepath).map_err(EvalError::LoadError)?;
+    let _loading = env.loading.enter(&path).map_err(EvalError::LoadError)?;
+    let parsed = parse_library(&path, filepath, env)?;
+    // Start the order afresh so it records the library's own bindings
+    let mut env = env.clone();
+    env.bindings.start_order();
+    env.load_path = Rc::new(env.load_path.for_file(&path));
+    let Some((lib_expr, positions)) = parsed.as_ref() else {
+        eprintln!("Warning: {}", empty_library_notice(filepath));
+        return Ok(env);
+    };
+    if defines_nothing(lib_expr) {
+        eprintln!("Warning: {}", empty_library_notice(filepath));
+    }
+    let env = &env;
+
+    let keep = env.prune_plan.as_ref().and_then(|plan| plan.keep_set(&path.display().to_string()));
+    let pruned;
+    let lib_expr = match keep {
+        Some(keep) => {
+            pruned = match lib_expr {
+                Expr::Seq(bindings, body) => Expr::Seq(bindings.clone(), Box::new(prune_library(body, keep))),
+                other => prune_library(other, keep),
+            };
+            &pruned
+        }
+        None => lib_expr,
+    };
+
+    match lib_expr {
         Expr::Seq(bindings, body) => {
-            // Process each binding in the sequence
             let mut current_env = env.clone();
-            for (name, _ty_ann, value) in bindings {
-                let val = eval(value, &current_env)?;
+            for ((name, _ty_ann, value), position) in bindings.iter().zip(positions) {
+                if keep.is_some_and(|keep| !keep.contains(name)) {
+                    continue;
+                }
+                let val = eval(value, &current_env).map_err(|e| {
+                    if name.starts_with('_') {
+                        // A pattern binding's own name, which its errors leave out
+                        return e;
+                    }
+                    let location = format!("{filepath}:{}", position.line);
+                    EvalError::InBinding(name.clone(), Some(location), Box::new(e))
+                })?;
                 current_env = current_env.extend(name.clone(), val);
             }
-            // Continue extracting from the body
             extract_bindings(body, &current_env)
         }
-        Expr::TypeAlias(_name, _ty_expr, body) => {
-            // Type aliases don't create runtime bindings, just pass through to the body
-            extract_bindings(body, env)
-        }
-        // If we reach anything other than a Let, Load, Seq, or TypeAlias, we're done extracting
-        // Return the accumulated environment
-        _ => Ok(env.clone()),
+        _ => extract_bindings(lib_expr, env),
     }
 }
 
-/// Match a pattern against a value, returning an extended environment if successful
-/// 
-/// This function implements pattern matching by recursively checking if a pattern
-/// matches a given value. If successful, it returns an environment extended with
-/// any variable bindings from the pattern. If the match fails, it returns `None`.
-/// 
-/// # Arguments
-/// * `pattern` - The pattern to match against
-/// * `value` - The value to match
-/// * `env` - The current environment to extend with bindings
-/// 
-/// # Returns
-/// `Some(Environment)` with new bindings if the pattern matches, `None` otherwise
-/// 
-/// # Supported Patterns
-/// - `Wildcard`: Matches anything without binding
-/// - `Var(name)`: Matches anything and binds it to `name`
-/// - `Literal(lit)`: Matches only the specific literal value
-/// - `Tuple(patterns)`: Matches tuples with matching sub-patterns
-/// - `Record(fields)`: Matches records with specified fields (supports partial matching)
-/// - `Constructor(name, args)`: Matches sum type constructors
-/// 
-/// # Example
-/// ```text
-/// match_pattern(Var("x"), Int(42), env) → Some(env + {x: 42})
-/// match_pattern(Literal(Int(0)), Int(0), env) → Some(env)
-/// match_pattern(Literal(Int(0)), Int(1), env) → None
-/// ```
-fn match_pattern(pattern: &Pattern, value: &Value, env: &Environment) -> Option<Environment> {
+/// Read and parse the library file at `path`, or reuse the environment's cached parse of it
+fn parse_library(path: &Path, filepath: &str, env: &Environment) -> Result<Rc<ParsedLibrary>, EvalError> {
+    let key = canonical(path);
+    if let Some(parsed) = env.load_cache.0.borrow().get(&key) {
+        return Ok(Rc::clone(parsed));
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| EvalError::LoadError(format!("Failed to read file '{}': {e}", path.display())))?;
+    let parsed = if is_blank_source(&content) {
+        None
+    } else {
+        let parsed = crate::parser::parse_program_with_positions(&content)
+            .map_err(|e| EvalError::LoadError(format!("Failed to parse file '{filepath}': {e}")))?;
+        Some(parsed)
+    };
+    let parsed = Rc::new(parsed);
+    env.load_cache.0.borrow_mut().insert(key, Rc::clone(&parsed));
+    Ok(parsed)
+}
+
+/// Notice for a loaded library that defines nothing
+pub(crate) fn empty_library_notice(filepath: &str) -> String {
+    format!("loaded '{filepath}': 0 bindings")
+}
+
+/// Whether a parsed library defines no bindings, types, or loads
+fn defines_nothing(expr: &Expr) -> bool {
+    match expr {
+        Expr::Seq(bindings, body) => bindings.is_empty() && defines_nothing(body),
+        Expr::Let(..) | Expr::Load(..) | Expr::LoadAs(..) | Expr::TypeDef { .. } | Expr::TypeAlias(..) => false,
+        _ => true,
+    }
+}
+
+/// Register the constructors of a sum type definition with their arity
+///
+/// The constructors of an earlier type with the same name are dropped, so
+/// redefining a type (such as the prelude's `List`) replaces it.
+pub(crate) fn register_type_def(env: &mut Environment, type_name: &str, constructors: &[(String, usize)]) {
+    Rc::make_mut(&mut env.constructors).retain(|_, info| info.type_name != type_name);
+    for (ctor_name, arity) in constructors {
+        let info = ConstructorInfo { type_name: type_name.to_string(), arity: *arity };
+        env.register_constructor(ctor_name.clone(), info);
+    }
+}
+
+/// Fail when a pattern applies a registered constructor to the wrong number of arguments
+///
+/// Such a pattern could never match, so it is reported instead of silently
+/// falling through to the next arm. Constructors that are not registered,
+/// like the `Cons` and `Nil` lists built-ins return, are left alone.
+fn check_constructor_arity(pattern: &Pattern, env: &Environment) -> Result<(), EvalError> {
     match pattern {
-        Pattern::Wildcard => {
-            // Wildcard matches anything without binding
-            Some(env.clone())
-        }
-        Pattern::Literal(lit) => {
-            // Literal pattern must match exactly
-            match (lit, value) {
-                (Literal::Int(n1), Value::Int(n2)) if n1 == n2 => Some(env.clone()),
-                (Literal::Bool(b1), Value::Bool(b2)) if b1 == b2 => Some(env.clone()),
-                (Literal::Char(c1), Value::Char(c2)) if c1 == c2 => Some(env.clone()),
-                (Literal::Byte(b1), Value::Byte(b2)) if b1 == b2 => Some(env.clone()),
-                _ => None,
-            }
-        }
-        Pattern::Var(name) => {
-            // Variable pattern binds the value to the name
-            Some(env.extend(name.clone(), value.clone()))
-        }
-        Pattern::Tuple(patterns) => {
-            // Tuple pattern must match a tuple value with the same number of elements
-            match value {
-                Value::Tuple(values) => {
-                    // Check if the number of patterns matches the number of values
-                    if patterns.len() != values.len() {
-                        return None;
-                    }
-                    // Match each pattern against corresponding value
-                    let mut current_env = env.clone();
-                    for (pat, val) in patterns.iter().zip(values.iter()) {
-                        match match_pattern(pat, val, &current_env) {
-                            Some(new_env) => current_env = new_env,
-                            None => return None,
-                        }
-                    }
-                    Some(current_env)
+        Pattern::Constructor(name, args) => {
+            if let Some(info) = env.lookup_constructor(name) {
+                if info.arity != args.len() {
+                    return Err(EvalError::ConstructorArityMismatch(name.clone(), info.arity, args.len()));
                 }
-                _ => None,
             }
+            args.iter().try_for_each(|arg| check_constructor_arity(arg, env))
         }
-        Pattern::Record(pattern_fields) => {
-            // Record pattern can be partial - only matches specified fields
-            match value {
-                Value::Record(value_fields) => {
-                    let mut current_env = env.clone();
-                    
-                    // Match each field in the pattern
-                    for (field_name, field_pattern) in pattern_fields {
-                        match value_fields.get(field_name) {
-                            Some(field_value) => {
-                                // Recursively match the field
-                                match match_pattern(field_pattern, field_value, &current_env) {
-                                    Some(new_env) => current_env = new_env,
-                                    None => return None,
-                                }
-                            }
-                            None => {
-                                // Field not found in value record - pattern doesn't match
-                                return None;
-                            }
-                        }
-                    }
-                    
-                    Some(current_env)
-                }
-                _ => None,
-            }
+        Pattern::Tuple(patterns) => patterns.iter().try_for_each(|pattern| check_constructor_arity(pattern, env)),
+        Pattern::Record(fields) => fields.iter().try_for_each(|(_, pattern)| check_constructor_arity(pattern, env)),
+        Pattern::Literal(_) | Pattern::Var(_) | Pattern::Wildcard => Ok(()),
+    }
+}
+
+/// Fail when a literal pattern would be compared with an opaque value
+///
+/// Opaque values cannot be inspected, so matching one against a literal is
+/// an error rather than a silent non-match. Only the parts of the value the
+/// pattern actually reaches are checked.
+fn reject_opaque_literal(pattern: &Pattern, value: &Value) -> Result<(), EvalError> {
+    match (pattern, value) {
+        (Pattern::Literal(lit), Value::Opaque(_, tag)) => Err(EvalError::TypeError(format!(
+            "Cannot match opaque {tag} value against literal pattern {lit}"
+        ))),
+        (Pattern::Tuple(patterns), Value::Tuple(values)) | (Pattern::Constructor(_, patterns), Value::Variant(_, values)) => {
+            patterns.iter().zip(values).try_for_each(|(pattern, value)| reject_opaque_literal(pattern, value))
         }
-        Pattern::Constructor(pattern_ctor, pattern_args) => {
-            // Constructor pattern matching
-            match value {
-                Value::Variant(value_ctor, value_args) => {
-                    // Constructor names must match
-                    if pattern_ctor != value_ctor {
-                        return None;
-                    }
-                    
-                    // Argument counts must match
-                    if pattern_args.len() != value_args.len() {
-                        return None;
-                    }
-                    
-                    // Match each argument recursively
-                    let mut current_env = env.clone();
-                    for (pat, val) in pattern_args.iter().zip(value_args.iter()) {
-                        match match_pattern(pat, val, &current_env) {
-                            Some(new_env) => current_env = new_env,
-                            None => return None,
-                        }
-                    }
-                    
-                    Some(current_env)
-                }
-                _ => None,
-            }
+        (Pattern::Record(fields), Value::Record(values)) => fields.iter().try_for_each(|(name, pattern)| {
+            values.get(name).map_or(Ok(()), |value| reject_opaque_literal(pattern, value))
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Match a pattern against a value, returning the variables it binds
+///
+/// This is the pattern matching `match` uses, for hosts that want to
+/// destructure a [`Value`] themselves. On a match, the bindings come back
+/// in the order their variables appear in the pattern; `None` means the
+/// value does not match. Constructor patterns compare constructor names
+/// only, so no type needs to be registered, and record patterns may name a
+/// subset of the record's fields.
+///
+/// # Example
+///
+/// ```
+/// use parlang::{match_value, parse_pattern, Value};
+///
+/// let pattern = parse_pattern("(host, 8080)").unwrap();
+/// let config = Value::Tuple(vec![Value::Int(127), Value::Int(8080)]);
+/// assert_eq!(match_value(&pattern, &config), Some(vec![("host".to_string(), Value::Int(127))]));
+///
+/// let other = Value::Tuple(vec![Value::Int(127), Value::Int(443)]);
+/// assert_eq!(match_value(&pattern, &other), None);
+/// ```
+#[must_use]
+pub fn match_value(pattern: &Pattern, value: &Value) -> Option<Vec<(String, Value)>> {
+    let mut bindings = Vec::new();
+    collect_bindings(pattern, value, &mut bindings).then_some(bindings)
+}
+
+/// Match a pattern against a value, adding its bindings to `bindings`
+///
+/// Returns whether the value matches; on a mismatch, `bindings` may hold
+/// some of the pattern's bindings.
+fn collect_bindings(pattern: &Pattern, value: &Value, bindings: &mut Vec<(String, Value)>) -> bool {
+    let all = |patterns: &[Pattern], values: &[Value], bindings: &mut Vec<(String, Value)>| {
+        patterns.len() == values.len()
+            && patterns.iter().zip(values).all(|(pattern, value)| collect_bindings(pattern, value, bindings))
+    };
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Var(name), value) => {
+            bindings.push((name.clone(), value.clone()));
+            true
+        }
+        (Pattern::Literal(lit), value) => match (lit, value) {
+            (Literal::Int(n1), Value::Int(n2)) => n1 == n2,
+            (Literal::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            (Literal::Char(c1), Value::Char(c2)) => c1 == c2,
+            (Literal::Byte(b1), Value::Byte(b2)) => b1 == b2,
+            _ => false,
+        },
+        (Pattern::Tuple(patterns), Value::Unit) => patterns.is_empty(),
+        (Pattern::Tuple(patterns), Value::Tuple(values)) => all(patterns, values, bindings),
+        // Record patterns may be partial: only the named fields must match
+        (Pattern::Record(fields), Value::Record(values)) => fields.iter().all(|(name, pattern)| {
+            values.get(name).is_some_and(|value| collect_bindings(pattern, value, bindings))
+        }),
+        (Pattern::Constructor(ctor, patterns), Value::Variant(value_ctor, values)) => {
+            ctor == value_ctor && all(patterns, values, bindings)
         }
+        _ => false,
+    }
+}
+
+/// `env` extended with `bindings`, in order
+///
+/// A variable bound more than once keeps its last binding.
+fn with_bindings(env: &Environment, bindings: Vec<(String, Value)>) -> Environment {
+    let mut env = env.clone();
+    for (name, value) in bindings {
+        env.bind(name, value);
     }
+    env
+}
+
+/// Options `eval_with_config` applies to a program, all off by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalConfig {
+    /// Largest captured environment, in estimated bytes, a closure in the
+    /// result may retain
+    ///
+    /// Only the bindings a closure's body refers to count (see
+    /// [`crate::retention`]). Closures anywhere in the result are checked,
+    /// except inside reference cells.
+    pub max_returned_closure_env: Option<usize>,
+    /// Make the `debug_env` built-in available while evaluating the program
+    ///
+    /// The environment passed in is left as it is; see
+    /// [`Environment::set_debug_builtins`] to turn it on for an environment.
+    pub debug_builtins: bool,
+}
+
+/// Evaluate an expression like [`eval`], with the debug built-ins if
+/// `config` enables them, then check the result against `config`
+///
+/// # Errors
+///
+/// Returns any error `eval` returns, and `EvalError::ResultRetainsTooMuch`
+/// when a closure in the result captures more than
+/// `config.max_returned_closure_env` allows.
+///
+/// # Example
+///
+/// ```
+/// use parlang::{parse, eval_with_config, Environment, EvalConfig, EvalError, Value};
+///
+/// let mut env = Environment::new();
+/// env.bind("table".to_string(), Value::Array(10_000, vec![Value::Int(0); 10_000]));
+/// let expr = parse("fun i -> table[i]").unwrap();
+/// let config = EvalConfig { max_returned_closure_env: Some(4096), ..EvalConfig::default() };
+/// match eval_with_config(&expr, &env, &config) {
+///     Err(EvalError::ResultRetainsTooMuch { binding_names, .. }) => assert_eq!(binding_names, ["table"]),
+///     other => panic!("unexpected {other:?}"),
+/// }
+/// ```
+pub fn eval_with_config(expr: &Expr, env: &Environment, config: &EvalConfig) -> Result<Value, EvalError> {
+    let value = if config.debug_builtins && !env.debug_builtins() {
+        let mut env = env.clone();
+        env.debug_builtins = Rc::new(Cell::new(true));
+        eval(expr, &env)?
+    } else {
+        eval(expr, env)?
+    };
+    if let Some(limit) = config.max_returned_closure_env {
+        check_closure_envs(&value, limit)?;
+    }
+    Ok(value)
+}
+
+/// Bounds on an evaluation, none by default; see [`eval_with_limits`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvalLimits {
+    /// Most expressions evaluation may visit (see [`Environment::set_step_limit`])
+    pub max_steps: Option<u64>,
+    /// Most function calls that may be in progress at once (see
+    /// [`Environment::set_depth_limit`])
+    pub max_depth: Option<usize>,
+}
+
+/// Evaluate an expression like [`eval`], within `limits`
+///
+/// The limits replace any set on `env` for this evaluation; `env` itself is
+/// left as it is. Closures already bound in `env` count their steps and calls
+/// against the limits of the environment they were created in.
+///
+/// # Errors
+///
+/// Returns any error `eval` returns, `EvalError::StepLimitExceeded` once
+/// evaluation takes more than `limits.max_steps` steps, and
+/// `EvalError::RecursionLimitExceeded` when a call would make more than
+/// `limits.max_depth` calls in progress.
+///
+/// # Example
+///
+/// ```
+/// use parlang::{parse, eval_with_limits, Environment, EvalError, EvalLimits};
+///
+/// let looping = parse("(rec f -> fun n -> f n) 0").unwrap();
+/// let limits = EvalLimits { max_steps: Some(10_000), ..EvalLimits::default() };
+/// assert_eq!(eval_with_limits(&looping, &Environment::new(), limits), Err(EvalError::StepLimitExceeded(10_000)));
+///
+/// let deep = parse("let rec down = fun n -> if n == 0 then 0 else 1 + down (n - 1) in down 1000000").unwrap();
+/// let limits = EvalLimits { max_depth: Some(100), ..EvalLimits::default() };
+/// assert_eq!(eval_with_limits(&deep, &Environment::new(), limits), Err(EvalError::RecursionLimitExceeded(100)));
+/// ```
+pub fn eval_with_limits(expr: &Expr, env: &Environment, limits: EvalLimits) -> Result<Value, EvalError> {
+    let mut env = env.clone();
+    env.set_step_limit(limits.max_steps);
+    env.set_depth_limit(limits.max_depth);
+    eval(expr, &env)
 }
 
 /// Evaluate an expression in an environment
@@ -560,120 +2365,143 @@ fn match_pattern(pattern: &Pattern, value: &Value, env: &Environment) -> Option<
 /// - A pattern match fails (no pattern matches the scrutinee)
 /// - Loading a library file fails
 /// - A tuple projection index is out of bounds
+/// - The environment's step limit is exhausted, or its depth limit is reached
 pub fn eval(expr: &Expr, env: &Environment) -> Result<Value, EvalError> {
+    eval_lowered(lower(expr), env)
+}
+
+/// Evaluate a parsed program, reporting errors at their source spans
+///
+/// `spans` are the spans of the nodes of `expr` returned by
+/// [`parse_program_with_spans`](crate::parser::parse_program_with_spans).
+/// An error raised by a variable, operator, primitive, projection, field
+/// access, constructor, conditional, match or call with a span is wrapped in
+/// [`EvalError::At`], the innermost one's; otherwise this evaluates exactly
+/// like [`eval`], counting the same steps.
+///
+/// ```
+/// use parlang::eval::{eval_with_spans, Environment};
+/// use parlang::parser::parse_program_with_spans;
+///
+/// let source = "let half = fun n -> n / 0 in\nhalf 10";
+/// let (expr, spans) = parse_program_with_spans(source).unwrap();
+/// let error = eval_with_spans(&expr, &spans, &Environment::new()).unwrap_err();
+/// let span = error.span().unwrap();
+/// assert_eq!(&source[span.start..span.end], "n / 0");
+/// assert_eq!(error.root().to_string(), "Division by zero");
+/// ```
+///
+/// # Errors
+///
+/// Returns the same errors as [`eval`].
+pub fn eval_with_spans(expr: &Expr, spans: &[Option<Span>], env: &Environment) -> Result<Value, EvalError> {
+    eval_lowered(lower_with_spans(expr, spans), env)
+}
+
+/// Evaluate a lowered program the way the environment is configured to
+fn eval_lowered(mut core: CoreExpr, env: &Environment) -> Result<Value, EvalError> {
+    if let Some(min_ops) = env.int_fast_path {
+        core = mark_int_arith(core, min_ops);
+    }
+    if env.resolved {
+        resolve::eval_resolved(&resolve(&core), env)
+    } else {
+        eval_core(&core, env)
+    }
+}
+
+/// Evaluate an expression of the core language in an environment
+///
+/// [`eval`] lowers its argument with [`lower`] and calls this.
+///
+/// # Errors
+///
+/// Returns the same errors as [`eval`].
+pub fn eval_core(expr: &CoreExpr, env: &Environment) -> Result<Value, EvalError> {
     match expr {
-        Expr::Int(n) => Ok(Value::Int(*n)),
-        Expr::Bool(b) => Ok(Value::Bool(*b)),
-        Expr::Char(c) => Ok(Value::Char(*c)),
-        Expr::Float(f) => Ok(Value::Float(*f)),
+        CoreExpr::At(span, inner) => eval_core(inner, env).map_err(|e| e.at(*span)),
+        _ => eval_node(expr, env),
+    }
+}
+
+/// Evaluate one node of the core language, counting a step
+///
+/// Kept apart from [`eval_core`], whose frames for spans stay small.
+#[inline(never)]
+fn eval_node(expr: &CoreExpr, env: &Environment) -> Result<Value, EvalError> {
+    env.count_step()?;
+    match expr {
+        CoreExpr::Int(n) => Ok(Value::Int(*n)),
+        CoreExpr::Bool(b) => Ok(Value::Bool(*b)),
+        CoreExpr::Char(c) => Ok(Value::Char(*c)),
+        CoreExpr::Float(f) => Ok(Value::Float(*f)),
+        
+        CoreExpr::Byte(b) => Ok(Value::Byte(*b)),
         
-        Expr::Byte(b) => Ok(Value::Byte(*b)),
+        CoreExpr::Unit => Ok(Value::Unit),
         
-        Expr::Var(name) => env
-            .lookup(name)
-            .cloned()
-            .ok_or_else(|| EvalError::UnboundVariable(name.clone())),
+        CoreExpr::Var(name) => env.resolve(name).ok_or_else(|| env.unbound_variable(name)),
         
-        Expr::BinOp(op, left, right) => {
-            let left_val = eval(left, env)?;
-            let right_val = eval(right, env)?;
+        CoreExpr::BinOp(op, left, right) => {
+            let left_val = eval_core(left, env)?;
+            let right_val = eval_core(right, env)?;
             eval_binop(*op, left_val, right_val)
         }
+
+        CoreExpr::IntArith(tree, nodes) => eval_int_arith(tree, *nodes, env),
+
+        CoreExpr::At(..) => unreachable!("eval_core evaluates spans"),
         
-        Expr::If(cond, then_branch, else_branch) => {
-            let cond_val = eval(cond, env)?;
+        CoreExpr::If(cond, then_branch, else_branch) => {
+            let cond_val = eval_core(cond, env)?;
             match cond_val {
-                Value::Bool(true) => eval(then_branch, env),
-                Value::Bool(false) => eval(else_branch, env),
+                Value::Bool(true) => eval_core(then_branch, env),
+                Value::Bool(false) => eval_core(else_branch, env),
                 _ => Err(EvalError::TypeError(
                     "If condition must be a boolean".to_string(),
                 )),
             }
         }
         
-        Expr::Let(name, _ty_ann, value, body) => {
-            let val = eval(value, env)?;
+        CoreExpr::Let(name, value, body) => {
+            let val = eval_core(value, env).map_err(|e| in_let(e, name))?;
             let new_env = env.extend(name.clone(), val);
-            eval(body, &new_env)
+            eval_core(body, &new_env)
         }
         
-        Expr::Fun(param, _ty_ann, body) => Ok(Value::Closure(
+        CoreExpr::Lam(param, body) => Ok(Value::Closure(
             param.clone(),
-            (**body).clone(),
+            Rc::clone(body),
             env.clone(),
         )),
         
-        Expr::App(func, arg) => {
-            let func_val = eval(func, env)?;
-            let arg_val = eval(arg, env)?;
-            
-            match func_val {
-                Value::Closure(param, body, closure_env) => {
-                    let new_env = closure_env.extend(param, arg_val);
-                    eval(&body, &new_env)
-                }
-                Value::RecClosure(rec_name, param, body, closure_env) => {
-                    // Create an environment with the recursive function bound to itself
-                    let rec_val = Value::RecClosure(
-                        rec_name.clone(),
-                        param.clone(),
-                        body.clone(),
-                        closure_env.clone(),
-                    );
-                    let env_with_rec = closure_env.extend(rec_name.clone(), rec_val);
-                    let new_env = env_with_rec.extend(param.clone(), arg_val);
-                    
-                    // Evaluate the body - TCO happens naturally via iteration below
-                    // when the body is a tail call
-                    eval_with_tco(&body, &new_env, &rec_name, &param, &closure_env)
-                }
-                _ => Err(EvalError::TypeError(
-                    "Application requires a function".to_string(),
-                )),
-            }
+        CoreExpr::App(..) => {
+            let (head, args) = spine(expr);
+            let func_val = eval_core(head, env)?;
+            apply_spine(func_val, var_name(head), &args, env, |arg| eval_core(arg, env))
         }
         
-        Expr::Load(filepath, body) => {
-            // Read the file contents
-            let content = fs::read_to_string(Path::new(filepath))
-                .map_err(|e| EvalError::LoadError(format!("Failed to read file '{filepath}': {e}")))?;
-            
-            // Parse the file contents
-            let lib_expr = crate::parser::parse(&content)
-                .map_err(|e| EvalError::LoadError(format!("Failed to parse file '{filepath}': {e}")))?;
-            
-            // Extract bindings from the library file
-            // Pass current environment so type constructors are available
-            let lib_env = extract_bindings(&lib_expr, env)?;
-            
-            // Merge library bindings into current environment
-            let extended_env = env.merge(&lib_env);
+        CoreExpr::Load(filepath, namespace, body) => {
+            // Read, parse, and evaluate the library file; existing bindings shadow its imports
+            let extended_env = match namespace {
+                Some(namespace) => env.extend(namespace.clone(), load_namespace(filepath, env)?),
+                None => import_library(filepath, env)?,
+            };
             
             // Evaluate the body in the extended environment
-            eval(body, &extended_env)
-        }
-        
-        Expr::Seq(bindings, body) => {
-            // Process each binding in sequence, extending the environment
-            let mut current_env = env.clone();
-            for (name, _ty_ann, value) in bindings {
-                let val = eval(value, &current_env)?;
-                current_env = current_env.extend(name.clone(), val);
-            }
-            // Evaluate the body in the extended environment
-            eval(body, &current_env)
+            eval_core(body, &extended_env)
         }
         
-        Expr::Rec(name, body) => {
+        CoreExpr::Rec(name, body) => {
             // Parse the body which should be a function (fun param -> expr)
             // The recursive function can reference itself by name within its body
             match body.as_ref() {
-                Expr::Fun(param, _ty_ann, fun_body) => {
+                CoreExpr::Lam(param, fun_body) => {
                     // Create a recursive closure that captures the function name
                     Ok(Value::RecClosure(
                         name.clone(),
                         param.clone(),
-                        (**fun_body).clone(),
+                        Rc::clone(fun_body),
                         env.clone(),
                     ))
                 }
@@ -683,291 +2511,317 @@ pub fn eval(expr: &Expr, env: &Environment) -> Result<Value, EvalError> {
             }
         }
         
-        Expr::Match(scrutinee, arms) => {
-            // Check exhaustiveness of patterns
-            let patterns: Vec<Pattern> = arms.iter().map(|(p, _)| p.clone()).collect();
-            let exhaustiveness = check_exhaustiveness(&patterns, env);
-            
-            if !exhaustiveness.is_exhaustive() {
-                // Print warning to stderr for non-exhaustive patterns
-                if let ExhaustivenessResult::NonExhaustive(missing) = exhaustiveness {
-                    eprintln!("Warning: pattern match is non-exhaustive");
-                    eprintln!("  Missing cases: {}", missing.join(", "));
-                }
-            }
-            
-            // Evaluate the scrutinee expression
-            let val = eval(scrutinee, env)?;
-            
-            // Try to match against each pattern arm in order
-            for (pattern, result_expr) in arms {
-                if let Some(new_env) = match_pattern(pattern, &val, env) {
-                    // Pattern matched, evaluate the result expression with the extended environment
-                    return eval(result_expr, &new_env);
-                }
-            }
-            
-            // No pattern matched - use the dedicated error variant
-            Err(EvalError::PatternMatchNonExhaustive)
+        CoreExpr::Match(scrutinee, arms) => {
+            let (pattern, result_expr, new_env) = select_arm(scrutinee, arms, env)?;
+            eval_core(result_expr, &new_env).map_err(|e| in_arms(e, &[pattern]))
         }
         
-        Expr::Tuple(elements) => {
+        CoreExpr::Tuple(elements) => {
             // Evaluate all elements of the tuple
             let mut values = Vec::new();
             for elem in elements {
-                values.push(eval(elem, env)?);
+                values.push(eval_core(elem, env)?);
             }
             Ok(Value::Tuple(values))
         }
         
-        Expr::TupleProj(tuple_expr, index) => {
-            // Evaluate the tuple expression
-            let tuple_val = eval(tuple_expr, env)?;
-            
-            // Check that the value is a tuple
-            match tuple_val {
-                Value::Tuple(values) => {
-                    // Check bounds
-                    if *index >= values.len() {
-                        Err(EvalError::IndexOutOfBounds(format!(
-                            "Tuple index {} out of bounds for tuple of size {}",
-                            index,
-                            values.len()
-                        )))
-                    } else {
-                        Ok(values[*index].clone())
-                    }
-                }
-                _ => Err(EvalError::TypeError(
-                    "Tuple projection requires a tuple".to_string(),
-                )),
-            }
-        }
-        
-        Expr::TypeAlias(_name, _ty_expr, body) => {
-            // Type aliases are transparent at runtime - they're only used during type checking
-            // We simply evaluate the body in the current environment
-            eval(body, env)
-        }
+        CoreExpr::Proj(tuple_expr, index) => project(eval_core(tuple_expr, env)?, *index),
         
-        Expr::Record(fields) => {
+        CoreExpr::Record(fields) => {
             // Evaluate all field expressions and build the record
-            let mut record = HashMap::new();
+            let mut record = Fields::new();
             
             for (name, expr) in fields {
-                let value = eval(expr, env)?;
+                let value = eval_core(expr, env)?;
                 record.insert(name.clone(), value);
             }
             
             Ok(Value::Record(record))
         }
         
-        Expr::FieldAccess(record_expr, field_name) => {
-            // Evaluate the record expression
-            let record_value = eval(record_expr, env)?;
-            
-            // Check that the value is a record and access the field
-            match record_value {
-                Value::Record(fields) => {
-                    fields.get(field_name)
-                        .cloned()
-                        .ok_or_else(|| {
-                            let mut available: Vec<String> = fields.keys().cloned().collect();
-                            available.sort();
-                            EvalError::FieldNotFound(field_name.clone(), available)
-                        })
-                }
-                other => {
-                    Err(EvalError::RecordExpected(format!("{:?}", other)))
-                }
-            }
-        }
+        CoreExpr::Field(record_expr, field_name) => field(eval_core(record_expr, env)?, field_name),
         
-        Expr::TypeDef { name, type_params: _, constructors, body } => {
+        CoreExpr::TypeDef(name, constructors, body) => {
             // Register all constructors in the environment
             let mut new_env = env.clone();
-            
-            for (ctor_name, ctor_types) in constructors {
-                let ctor_info = ConstructorInfo {
-                    type_name: name.clone(),
-                    arity: ctor_types.len(),
-                };
-                new_env.register_constructor(ctor_name.clone(), ctor_info);
-            }
+            register_type_def(&mut new_env, name, constructors);
             
             // Evaluate body in extended environment
-            eval(body, &new_env)
+            eval_core(body, &new_env)
         }
         
-        Expr::Constructor(ctor_name, args) => {
-            // Look up constructor info
-            let ctor_info = env.lookup_constructor(ctor_name)
-                .ok_or_else(|| EvalError::UnknownConstructor(ctor_name.clone()))?;
-            
-            // Check arity
-            if args.len() != ctor_info.arity {
-                return Err(EvalError::ConstructorArityMismatch(
-                    ctor_name.clone(),
-                    ctor_info.arity,
-                    args.len()
-                ));
-            }
+        CoreExpr::Constructor(ctor_name, args) => {
+            check_constructor(ctor_name, args.len(), env)?;
             
             // Evaluate all arguments
             let mut values = Vec::new();
             for arg in args {
-                values.push(eval(arg, env)?);
+                values.push(eval_core(arg, env)?);
             }
             
             Ok(Value::Variant(ctor_name.clone(), values))
         }
         
-        Expr::Array(elements) => {
+        CoreExpr::Array(elements) => {
             // Evaluate all elements of the array
             let mut values = Vec::new();
             for elem in elements {
-                values.push(eval(elem, env)?);
+                values.push(eval_core(elem, env)?);
             }
             let size = values.len();
             Ok(Value::Array(size, values))
         }
         
-        Expr::ArrayIndex(arr_expr, index_expr) => {
-            // Evaluate the array and index expressions
-            let arr_val = eval(arr_expr, env)?;
-            let index_val = eval(index_expr, env)?;
-            
-            // Check that the index is an integer
-            let index = match index_val {
-                Value::Int(i) => i,
-                _ => return Err(EvalError::TypeError(
-                    "Array index must be an integer".to_string()
-                )),
-            };
-            
-            // Check that index is non-negative
-            if index < 0 {
-                return Err(EvalError::IndexOutOfBounds(format!(
-                    "Array index {} is negative",
-                    index
-                )));
-            }
-            
-            // Check that the value is an array
-            match arr_val {
-                Value::Array(size, values) => {
-                    let idx = index as usize;
-                    // Check bounds
-                    if idx >= size {
-                        Err(EvalError::IndexOutOfBounds(format!(
-                            "Array index {} out of bounds for array of size {}",
-                            idx, size
-                        )))
-                    } else {
-                        Ok(values[idx].clone())
-                    }
-                }
-                _ => Err(EvalError::TypeError(
-                    "Array indexing requires an array".to_string()
-                )),
+        CoreExpr::Prim(op, operands) => {
+            let mut values = Vec::new();
+            for operand in operands {
+                values.push(eval_core(operand, env)?);
             }
+            eval_prim(*op, values)
         }
         
-        Expr::Ref(expr) => {
+        CoreExpr::Try(body, name, handler) => match eval_core(body, env) {
+            Err(error) if error.is_recoverable() => eval_core(handler, &env.extend(name.clone(), error.to_value())),
+            result => result,
+        },
+    }
+}
+
+/// Component `index` of a tuple
+pub(crate) fn project(tuple_val: Value, index: usize) -> Result<Value, EvalError> {
+    match tuple_val {
+        Value::Tuple(values) => {
+            // Check bounds
+            if index >= values.len() {
+                Err(EvalError::IndexOutOfBounds(format!(
+                    "Tuple index {} out of bounds for tuple of size {}",
+                    index,
+                    values.len()
+                )))
+            } else {
+                Ok(values[index].clone())
+            }
+        }
+        // `()` is the empty tuple, so it has no components
+        Value::Unit => Err(EvalError::IndexOutOfBounds(format!(
+            "Tuple index {index} out of bounds for tuple of size 0"
+        ))),
+        _ => Err(EvalError::TypeError(
+            "Tuple projection requires a tuple".to_string(),
+        )),
+    }
+}
+
+/// Field `field_name` of a record
+pub(crate) fn field(record_value: Value, field_name: &str) -> Result<Value, EvalError> {
+    match record_value {
+        Value::Record(fields) => {
+            fields.get(field_name)
+                .cloned()
+                .ok_or_else(|| EvalError::FieldNotFound(field_name.to_string(), fields.keys().cloned().collect()))
+        }
+        other => {
+            Err(EvalError::RecordExpected(format!("{:?}", other)))
+        }
+    }
+}
+
+/// Fail unless `ctor_name` is a registered constructor taking `arg_count` arguments
+pub(crate) fn check_constructor(ctor_name: &str, arg_count: usize, env: &Environment) -> Result<(), EvalError> {
+    let ctor_info = env.lookup_constructor(ctor_name)
+        .ok_or_else(|| env.unknown_constructor(ctor_name))?;
+    if arg_count != ctor_info.arity {
+        return Err(EvalError::ConstructorArityMismatch(
+            ctor_name.to_string(),
+            ctor_info.arity,
+            arg_count
+        ));
+    }
+    Ok(())
+}
+
+/// Apply a primitive operation to its evaluated operands
+pub(crate) fn eval_prim(op: PrimOp, operands: Vec<Value>) -> Result<Value, EvalError> {
+    match (op, <[Value; 1]>::try_from(operands)) {
+        (PrimOp::Ref, Ok([value])) => {
             // Create a reference to a value
-            let val = eval(expr, env)?;
             let id = next_ref_id();
-            Ok(Value::Reference(id, Rc::new(RefCell::new(val))))
+            Ok(Value::Reference(id, Rc::new(RefCell::new(value))))
         }
-        
-        Expr::Deref(expr) => {
+        (PrimOp::Deref, Ok([reference])) => match reference {
             // Dereference a reference to get the value
-            let ref_val = eval(expr, env)?;
-            match ref_val {
-                Value::Reference(_id, cell) => {
-                    Ok(cell.borrow().clone())
-                }
-                _ => Err(EvalError::TypeError(
-                    "Dereference requires a reference".to_string()
-                )),
-            }
-        }
-        
-        Expr::RefAssign(ref_expr, value_expr) => {
-            // Assign a new value to a reference
-            let ref_val = eval(ref_expr, env)?;
-            let new_val = eval(value_expr, env)?;
-            
-            match ref_val {
+            Value::Reference(_id, cell) => Ok(cell.borrow().clone()),
+            _ => Err(EvalError::TypeError(
+                "Dereference requires a reference".to_string()
+            )),
+        },
+        (PrimOp::Neg, Ok([operand])) => match operand {
+            Value::Int(n) => n
+                .checked_neg()
+                .map(Value::Int)
+                .ok_or_else(|| EvalError::TypeError("Integer overflow in negation".to_string())),
+            Value::Float(f) => Ok(Value::Float(-f)),
+            other => Err(EvalError::TypeError(format!(
+                "Cannot negate {other} ({}): negation requires an Int or a Float",
+                other.kind_name()
+            ))),
+        },
+        (op, Err(operands)) => match (op, <[Value; 2]>::try_from(operands)) {
+            (PrimOp::Assign, Ok([reference, new_val])) => match reference {
+                // Assign a new value to a reference
                 Value::Reference(_id, cell) => {
                     *cell.borrow_mut() = new_val;
-                    // Return unit value after assignment
-                    Ok(Value::Tuple(vec![]))
+                    Ok(Value::Unit)
                 }
                 _ => Err(EvalError::TypeError(
                     "Reference assignment requires a reference".to_string()
                 )),
-            }
-        }
-        
-        Expr::Range(start_expr, end_expr) => {
-            // Evaluate start and end expressions
-            let start_val = eval(start_expr, env)?;
-            let end_val = eval(end_expr, env)?;
-            
-            // Check that both are integers
-            match (start_val, end_val) {
-                (Value::Int(start), Value::Int(end)) => {
-                    Ok(Value::Range(start, end))
-                }
+            },
+            (PrimOp::Index, Ok([array, index])) => index_array(array, &index),
+            (PrimOp::Range, Ok([start, end])) => match (start, end) {
+                (Value::Int(start), Value::Int(end)) => Ok(Value::Range(start, end)),
                 _ => Err(EvalError::TypeError(
                     "Range requires integer start and end values".to_string()
                 )),
-            }
-        }
+            },
+            (op, _) => unreachable!("lowering gives {op:?} its operands"),
+        },
+        (op, Ok(_)) => unreachable!("lowering gives {op:?} its operands"),
     }
 }
 
-/// Evaluate a binary operation
-fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError> {
-    match (op, left, right) {
-        // Arithmetic operations with overflow checking for Int
-        (BinOp::Add, Value::Int(a), Value::Int(b)) => {
-            a.checked_add(b)
-                .map(Value::Int)
-                .ok_or_else(|| EvalError::TypeError("Integer overflow in addition".to_string()))
+/// Element of an array at an index
+fn index_array(arr_val: Value, index_val: &Value) -> Result<Value, EvalError> {
+    // Check that the index is an integer
+    let index = match index_val {
+        Value::Int(i) => *i,
+        _ => return Err(EvalError::TypeError(
+            "Array index must be an integer".to_string()
+        )),
+    };
+    
+    // Check that index is non-negative
+    if index < 0 {
+        return Err(EvalError::IndexOutOfBounds(format!(
+            "Array index {} is negative",
+            index
+        )));
+    }
+    
+    // Check that the value is an array
+    match arr_val {
+        // Check bounds; an index too large for a usize is out of bounds too
+        Value::Array(size, values) => match usize::try_from(index) {
+            Ok(idx) if idx < size => Ok(values[idx].clone()),
+            _ => Err(EvalError::IndexOutOfBounds(format!("Array index {index} out of bounds for array of size {size}"))),
+        },
+        _ => Err(EvalError::TypeError(
+            "Array indexing requires an array".to_string()
+        )),
+    }
+}
+
+/// Evaluate integer arithmetic marked by [`mark_int_arith`], whose root step is already counted
+///
+/// The tree is computed on `i64`s first. If that fails, because a variable
+/// does not hold an `Int` or an operation overflows or divides by zero, it
+/// is evaluated again node by node, which reports the error exactly as if
+/// it had never been marked; the leaves are literals and variables, so
+/// evaluating them twice has no effect.
+fn eval_int_arith(tree: &CoreExpr, nodes: usize, env: &Environment) -> Result<Value, EvalError> {
+    let fast = match tree {
+        CoreExpr::BinOp(op @ (BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge), left, right) => {
+            eval_int_expr(left, env).and_then(|a| int_compare(*op, a, eval_int_expr(right, env)?)).map(Value::Bool)
         }
-        (BinOp::Sub, Value::Int(a), Value::Int(b)) => {
-            a.checked_sub(b)
-                .map(Value::Int)
-                .ok_or_else(|| EvalError::TypeError("Integer overflow in subtraction".to_string()))
+        _ => eval_int_expr(tree, env).map(Value::Int),
+    };
+    match (fast, tree) {
+        (Ok(value), _) => {
+            env.count_steps(nodes - 1)?;
+            Ok(value)
         }
-        (BinOp::Mul, Value::Int(a), Value::Int(b)) => {
-            a.checked_mul(b)
-                .map(Value::Int)
-                .ok_or_else(|| EvalError::TypeError("Integer overflow in multiplication".to_string()))
+        (Err(_), CoreExpr::BinOp(op, left, right)) => {
+            let left_val = eval_core(left, env)?;
+            let right_val = eval_core(right, env)?;
+            eval_binop(*op, left_val, right_val)
         }
-        (BinOp::Div, Value::Int(a), Value::Int(b)) => {
-            if b == 0 {
-                Err(EvalError::DivisionByZero)
-            } else {
-                a.checked_div(b)
-                    .map(Value::Int)
-                    .ok_or_else(|| EvalError::TypeError("Integer overflow in division".to_string()))
-            }
+        (Err(_), _) => eval_core(tree, env),
+    }
+}
+
+/// Evaluate an arithmetic tree over `Int` literals and variables without building values
+///
+/// Uses the same checked operations as [`eval_binop`] and counts no steps.
+///
+/// # Errors
+///
+/// Returns an error if a variable is unbound or does not hold an `Int`, or
+/// an operation overflows or divides by zero.
+pub fn eval_int_expr(expr: &CoreExpr, env: &Environment) -> Result<i64, EvalError> {
+    match expr {
+        CoreExpr::Int(n) => Ok(*n),
+        CoreExpr::Var(name) => match env.resolve(name) {
+            Some(Value::Int(n)) => Ok(n),
+            Some(value) => Err(EvalError::TypeError(format!("Expected Int in '{name}', found {}", value.kind_name()))),
+            None => Err(env.unbound_variable(name)),
+        },
+        CoreExpr::BinOp(op, left, right) => int_arith(*op, eval_int_expr(left, env)?, eval_int_expr(right, env)?),
+        CoreExpr::At(_, inner) => eval_int_expr(inner, env),
+        _ => Err(EvalError::TypeError("Expected integer arithmetic".to_string())),
+    }
+}
+
+/// Apply an arithmetic operator to two integers, checking for overflow
+pub(crate) fn int_arith(op: BinOp, a: i64, b: i64) -> Result<i64, EvalError> {
+    let overflow = |operation: &str| EvalError::TypeError(format!("Integer overflow in {operation}"));
+    match op {
+        BinOp::Add => a.checked_add(b).ok_or_else(|| overflow("addition")),
+        BinOp::Sub => a.checked_sub(b).ok_or_else(|| overflow("subtraction")),
+        BinOp::Mul => a.checked_mul(b).ok_or_else(|| overflow("multiplication")),
+        BinOp::Div if b == 0 => Err(EvalError::DivisionByZero),
+        BinOp::Div => a.checked_div(b).ok_or_else(|| overflow("division")),
+        _ => Err(binop_type_mismatch(op, &Value::Int(a), &Value::Int(b))),
+    }
+}
+
+/// Apply a comparison operator to two integers
+pub(crate) fn int_compare(op: BinOp, a: i64, b: i64) -> Result<bool, EvalError> {
+    match op {
+        BinOp::Eq => Ok(a == b),
+        BinOp::Neq => Ok(a != b),
+        BinOp::Lt => Ok(a < b),
+        BinOp::Le => Ok(a <= b),
+        BinOp::Gt => Ok(a > b),
+        BinOp::Ge => Ok(a >= b),
+        _ => Err(binop_type_mismatch(op, &Value::Int(a), &Value::Int(b))),
+    }
+}
+
+/// Evaluate a binary operation
+///
+/// Arithmetic and ordering operands are checked against
+/// [`numeric_rules`](crate::numeric_rules) first; the arms below only
+/// implement the combinations it allows.
+pub(crate) fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError> {
+    if numeric_rules::governs(op) && !numeric_rules::allows_values(op, &left, &right) {
+        return match (&left, &right) {
+            (Value::Opaque(..), _) | (_, Value::Opaque(..)) => opaque_binop(op, &left, &right),
+            _ => Err(binop_type_mismatch(op, &left, &right)),
+        };
+    }
+    match (op, left, right) {
+        // Arithmetic operations with overflow checking for Int
+        (op @ (BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div), Value::Int(a), Value::Int(b)) => {
+            int_arith(op, a, b).map(Value::Int)
         }
         
         // Arithmetic operations for Float
         (BinOp::Add, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
         (BinOp::Sub, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
         (BinOp::Mul, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
-        (BinOp::Div, Value::Float(a), Value::Float(b)) => {
-            if b == 0.0 {
-                Err(EvalError::DivisionByZero)
-            } else {
-                Ok(Value::Float(a / b))
-            }
-        }
+        // Float division follows IEEE 754: dividing by zero gives an infinity
+        // (or NaN for `0.0 / 0.0`) rather than `DivisionByZero`
+        (BinOp::Div, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
         
         // Arithmetic operations for Byte with overflow checking
         (BinOp::Add, Value::Byte(a), Value::Byte(b)) => {
@@ -996,12 +2850,9 @@ fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError>
         }
         
         // Comparison operations for Int
-        (BinOp::Eq, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a == b)),
-        (BinOp::Neq, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a != b)),
-        (BinOp::Lt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a < b)),
-        (BinOp::Le, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a <= b)),
-        (BinOp::Gt, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a > b)),
-        (BinOp::Ge, Value::Int(a), Value::Int(b)) => Ok(Value::Bool(a >= b)),
+        (op @ (BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge), Value::Int(a), Value::Int(b)) => {
+            int_compare(op, a, b).map(Value::Bool)
+        }
         
         // Comparison operations for Float
         (BinOp::Eq, Value::Float(a), Value::Float(b)) => Ok(Value::Bool(a == b)),
@@ -1031,6 +2882,14 @@ fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError>
         (BinOp::Gt, Value::Byte(a), Value::Byte(b)) => Ok(Value::Bool(a > b)),
         (BinOp::Ge, Value::Byte(a), Value::Byte(b)) => Ok(Value::Bool(a >= b)),
         
+        // Unit equality
+        (op @ (BinOp::Eq | BinOp::Neq), Value::Unit, Value::Unit) => Ok(Value::Bool(op == BinOp::Eq)),
+
+        // Reference equality is identity: two cells are equal only if they are the same cell
+        (op @ (BinOp::Eq | BinOp::Neq), Value::Reference(_, a), Value::Reference(_, b)) => {
+            Ok(Value::Bool(Rc::ptr_eq(&a, &b) == (op == BinOp::Eq)))
+        }
+
         // Comparison operations for Range
         (BinOp::Eq, Value::Range(start1, end1), Value::Range(start2, end2)) => {
             Ok(Value::Bool(start1 == start2 && end1 == end2))
@@ -1039,12 +2898,128 @@ fn eval_binop(op: BinOp, left: Value, right: Value) -> Result<Value, EvalError>
             Ok(Value::Bool(start1 != start2 || end1 != end2))
         }
         
-        (op, left, right) => Err(EvalError::TypeError(format!(
-            "Type error in binary operation {:?}: cannot apply to {:?} and {:?}", op, left, right
+        // Structural equality on constructed values, strings (List Char) included
+        (op @ (BinOp::Eq | BinOp::Neq), left @ Value::Variant(..), right @ Value::Variant(..)) => {
+            Ok(Value::Bool(variants_equal(left, right)? == (op == BinOp::Eq)))
+        }
+
+        // String (List Char) concatenation
+        (BinOp::Concat, left, right) => append_lists(&left, right).ok_or_else(|| {
+            EvalError::TypeError("String concatenation (^) requires two strings".to_string())
+        }),
+
+        (op, left @ Value::Opaque(..), right) | (op, left, right @ Value::Opaque(..)) => opaque_binop(op, &left, &right),
+
+        (op, left, right) => Err(binop_type_mismatch(op, &left, &right)),
+    }
+}
+
+/// The error for an operator applied to operands it does not support
+fn binop_type_mismatch(op: BinOp, left: &Value, right: &Value) -> EvalError {
+    EvalError::BinOpTypeMismatch {
+        op,
+        left: operand_rendering(left),
+        left_kind: left.kind_name(),
+        right: operand_rendering(right),
+        right_kind: right.kind_name(),
+    }
+}
+
+/// Longest operand rendering shown in a `BinOpTypeMismatch` or `PatternMismatch` error
+const MAX_OPERAND_WIDTH: usize = 40;
+
+/// A value's rendering, shortened to at most `MAX_OPERAND_WIDTH` characters
+fn operand_rendering(value: &Value) -> String {
+    let rendering = value.to_string();
+    if rendering.chars().count() <= MAX_OPERAND_WIDTH {
+        rendering
+    } else {
+        let prefix: String = rendering.chars().take(MAX_OPERAND_WIDTH - 3).collect();
+        format!("{prefix}...")
+    }
+}
+
+/// Binary operation with an opaque operand: only identity comparison is allowed
+fn opaque_binop(op: BinOp, left: &Value, right: &Value) -> Result<Value, EvalError> {
+    match (op, left, right) {
+        (BinOp::Eq | BinOp::Neq, Value::Opaque(..), Value::Opaque(..)) => {
+            Ok(Value::Bool((left == right) == (op == BinOp::Eq)))
+        }
+        (_, Value::Opaque(_, tag), _) | (_, _, Value::Opaque(_, tag)) => Err(EvalError::TypeError(format!(
+            "Opaque {tag} value cannot be used with operator {op}; only == and != compare opaque values"
         ))),
+        _ => unreachable!("opaque_binop called without an opaque operand"),
+    }
+}
+
+/// Append two `Cons`/`Nil` lists, or `None` if either value is not a list
+fn append_lists(left: &Value, right: Value) -> Option<Value> {
+    if !is_list(&right) {
+        return None;
+    }
+    let mut elements = Vec::new();
+    let mut current = left;
+    loop {
+        match current {
+            Value::Variant(name, args) if name == "Nil" && args.is_empty() => break,
+            Value::Variant(name, args) if name == "Cons" && args.len() == 2 => {
+                elements.push(args[0].clone());
+                current = &args[1];
+            }
+            _ => return None,
+        }
+    }
+    Some(elements.into_iter().rev().fold(right, |rest, element| {
+        Value::Variant("Cons".to_string(), vec![element, rest])
+    }))
+}
+
+/// Whether two constructed values have the same constructors and equal payloads
+///
+/// Payloads are compared left to right with `==`, so comparing payloads that
+/// have no equality (such as functions) is a type error.
+fn variants_equal(left: Value, right: Value) -> Result<bool, EvalError> {
+    // A work list rather than recursion, so long lists cannot overflow the stack
+    let mut pending = vec![(left, right)];
+    while let Some(pair) = pending.pop() {
+        let unequal = match pair {
+            (Value::Variant(name1, args1), Value::Variant(name2, args2)) => {
+                if name1 == name2 && args1.len() == args2.len() {
+                    pending.extend(args1.into_iter().zip(args2).rev());
+                    continue;
+                }
+                release(args1.into_iter().chain(args2));
+                Ok(false)
+            }
+            (left, right) => match eval_binop(BinOp::Eq, left, right) {
+                Ok(Value::Bool(true)) => continue,
+                result => result.map(|_| false),
+            },
+        };
+        // What is left unvisited may hold the rest of a long list
+        release(pending.into_iter().flat_map(|(left, right)| [left, right]));
+        return unequal;
+    }
+    Ok(true)
+}
+
+/// Drop values without recursing into the payloads of constructed values,
+/// so dropping the rest of a long list cannot overflow the stack
+fn release(values: impl IntoIterator<Item = Value>) {
+    let mut pending: Vec<Value> = values.into_iter().collect();
+    while let Some(value) = pending.pop() {
+        if let Value::Variant(_, args) = value {
+            pending.extend(args);
+        }
     }
 }
 
+/// Whether a value is a `Cons` cell or `Nil` (without walking the whole list)
+fn is_list(value: &Value) -> bool {
+    matches!(value, Value::Variant(name, args)
+        if (name == "Nil" && args.is_empty()) || (name == "Cons" && args.len() == 2))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1118,7 +3093,7 @@ mod tests {
     fn test_eval_unbound_var() {
         let env = Environment::new();
         let expr = Expr::Var("x".to_string());
-        assert!(matches!(eval(&expr, &env), Err(EvalError::UnboundVariable(_))));
+        assert!(matches!(eval(&expr, &env), Err(EvalError::UnboundVariable(..))));
     }
 
     // Test all arithmetic operations
@@ -1383,6 +3358,54 @@ mod tests {
         assert!(matches!(eval(&expr, &env), Err(EvalError::TypeError(_))));
     }
 
+    #[test]
+    fn test_eval_if_chain_five_branches() {
+        let grade = |score: i64| {
+            let expr = Expr::IfChain(
+                [90, 80, 70, 60]
+                    .iter()
+                    .zip(['A', 'B', 'C', 'D'])
+                    .map(|(bound, letter)| {
+                        (
+                            Expr::BinOp(BinOp::Ge, Box::new(Expr::Int(score)), Box::new(Expr::Int(*bound))),
+                            Expr::Char(letter),
+                        )
+                    })
+                    .collect(),
+                Box::new(Expr::Char('F')),
+            );
+            eval(&expr, &Environment::new())
+        };
+        assert_eq!(grade(95), Ok(Value::Char('A')));
+        assert_eq!(grade(85), Ok(Value::Char('B')));
+        assert_eq!(grade(75), Ok(Value::Char('C')));
+        assert_eq!(grade(65), Ok(Value::Char('D')));
+        assert_eq!(grade(10), Ok(Value::Char('F')));
+    }
+
+    #[test]
+    fn test_eval_if_chain_non_bool_condition() {
+        let env = Environment::new();
+        let expr = Expr::IfChain(
+            vec![
+                (Expr::Bool(false), Expr::Int(1)),
+                (Expr::Int(42), Expr::Int(2)),
+            ],
+            Box::new(Expr::Int(3)),
+        );
+        assert!(matches!(eval(&expr, &env), Err(EvalError::TypeError(_))));
+    }
+
+    #[test]
+    fn test_eval_if_chain_tail_call() {
+        // Tail calls inside an if chain must not grow the stack
+        let expr = crate::parser::parse(
+            "let count = rec count -> fun n -> if n == 0 then 0 elif n < 0 then 0 - 1 else count (n - 1) in count 100000",
+        )
+        .unwrap();
+        assert_eq!(eval(&expr, &Environment::new()), Ok(Value::Int(0)));
+    }
+
     // Test let bindings
     #[test]
     fn test_eval_let_simple() {
@@ -1562,7 +3585,7 @@ mod tests {
             Box::new(Expr::Int(1)),
             Box::new(Expr::Bool(true)),
         );
-        assert!(matches!(eval(&expr, &env), Err(EvalError::TypeError(_))));
+        assert!(matches!(eval(&expr, &env), Err(EvalError::BinOpTypeMismatch { op: BinOp::Add, .. })));
     }
 
     #[test]
@@ -1573,7 +3596,50 @@ mod tests {
             Box::new(Expr::Int(1)),
             Box::new(Expr::Bool(true)),
         );
-        assert!(matches!(eval(&expr, &env), Err(EvalError::TypeError(_))));
+        assert!(matches!(eval(&expr, &env), Err(EvalError::BinOpTypeMismatch { op: BinOp::Lt, .. })));
+    }
+
+    #[test]
+    fn test_binop_type_mismatch_names_operands() {
+        let env = Environment::with_builtins();
+        let message = |source: &str| eval(&crate::parser::parse(source).unwrap(), &env).unwrap_err().to_string();
+        assert_eq!(message("true < 3"), "Type error: cannot apply '<' to true (Bool) and 3 (Int)");
+        assert_eq!(message("true / false"), "Type error: cannot apply '/' to true (Bool) and false (Bool)");
+        assert_eq!(message("(1, 2) * 2.5"), "Type error: cannot apply '*' to (1, 2) (tuple) and 2.5 (Float)");
+    }
+
+    #[test]
+    fn test_binop_type_mismatch_shortens_long_operands() {
+        let long = Value::Tuple((0..30).map(Value::Int).collect());
+        let Err(error) = eval_binop(BinOp::Add, long, Value::Int(1)) else { panic!("expected an error") };
+        let EvalError::BinOpTypeMismatch { left, left_kind, .. } = &error else { panic!("unexpected error {error:?}") };
+        assert_eq!(left.chars().count(), MAX_OPERAND_WIDTH);
+        assert!(left.ends_with("..."));
+        assert_eq!(*left_kind, "tuple");
+        assert_eq!(error.kind(), "TypeError");
+    }
+
+    #[test]
+    fn test_eval_int_expr() {
+        let mut env = Environment::new();
+        env.bind("x".to_string(), Value::Int(6));
+        env.bind("f".to_string(), Value::Float(1.0));
+        let core = |source: &str| lower(&crate::parser::parse(source).unwrap());
+        assert_eq!(eval_int_expr(&core("x * x - 2 * x / 3"), &env), Ok(32));
+        assert_eq!(eval_int_expr(&core("x / (x - 6)"), &env), Err(EvalError::DivisionByZero));
+        assert!(eval_int_expr(&core("x + f"), &env).is_err());
+        assert!(matches!(eval_int_expr(&core("x + y"), &env), Err(EvalError::UnboundVariable(..))));
+    }
+
+    #[test]
+    fn test_int_fast_path_falls_back_for_non_int_variables() {
+        let mut env = Environment::new();
+        env.set_int_fast_path(Some(1));
+        env.bind("f".to_string(), Value::Float(1.5));
+        let expr = crate::parser::parse("f * 2.0 + f").unwrap();
+        assert_eq!(eval(&expr, &env), Ok(Value::Float(4.5)));
+        let expr = crate::parser::parse("f * 2 + 1").unwrap();
+        assert!(matches!(eval(&expr, &env), Err(EvalError::BinOpTypeMismatch { op: BinOp::Mul, .. })));
     }
 
     // Test Environment methods
@@ -1635,14 +3701,26 @@ mod tests {
     #[test]
     fn test_value_display_closure() {
         let env = Environment::new();
-        let closure = Value::Closure("x".to_string(), Expr::Var("x".to_string()), env);
+        let closure = Value::Closure("x".to_string(), Rc::new(CoreExpr::Var("x".to_string())), env);
         assert_eq!(format!("{closure}"), "<function x>");
     }
 
+    #[test]
+    fn test_calls_share_the_closure_body() {
+        let Ok(Value::RecClosure(_, _, body, _)) = eval(&crate::parser::parse("rec f -> fun n -> f").unwrap(), &Environment::new()) else {
+            panic!("expected a recursive closure");
+        };
+        let env = Environment::new().extend("g".to_string(), Value::RecClosure("f".to_string(), "n".to_string(), Rc::clone(&body), Environment::new()));
+        let Ok(Value::RecClosure(_, _, returned, _)) = eval(&crate::parser::parse("g 1").unwrap(), &env) else {
+            panic!("expected a recursive closure");
+        };
+        assert!(Rc::ptr_eq(&body, &returned));
+    }
+
     // Test EvalError Display implementation
     #[test]
     fn test_eval_error_display_unbound_var() {
-        let err = EvalError::UnboundVariable("x".to_string());
+        let err = EvalError::UnboundVariable("x".to_string(), vec![]);
         assert_eq!(format!("{err}"), "Unbound variable: x");
     }
 
@@ -1674,6 +3752,20 @@ mod tests {
         assert_ne!(Value::Bool(true), Value::Bool(false));
     }
 
+    #[test]
+    fn test_long_lists_compare_without_recursion() {
+        // Built, compared and dropped on the test thread's default stack
+        let cons = |n: i64, rest: Value| Value::Variant("Cons".to_string(), vec![Value::Int(n), rest]);
+        let nil = || Value::Variant("Nil".to_string(), vec![]);
+        // 100_000 cells, differing in the last one only
+        let list = |last: i64| (1..100_000).fold(cons(last, nil()), |rest, n| cons(n, rest));
+        assert_eq!(eval_binop(BinOp::Eq, list(0), list(0)), Ok(Value::Bool(true)));
+        assert_eq!(eval_binop(BinOp::Eq, list(0), list(1)), Ok(Value::Bool(false)));
+        // An early difference leaves both long tails unvisited
+        assert_eq!(eval_binop(BinOp::Neq, cons(1, list(0)), cons(2, list(0))), Ok(Value::Bool(true)));
+        assert_eq!(eval_binop(BinOp::Eq, list(0), nil()), Ok(Value::Bool(false)));
+    }
+
     // Test complex scenarios
     #[test]
     fn test_eval_complex_nested() {
@@ -1879,6 +3971,81 @@ mod tests {
         fs::remove_file(&temp_file).ok();
     }
 
+    #[test]
+    fn test_load_blank_files() {
+        use std::fs;
+
+        let blank = [("empty", ""), ("whitespace", " \n\t\r\n"), ("bom", "\u{feff}"), ("bom_newline", "\u{feff}\n")];
+        for (name, content) in blank {
+            let temp_file = std::env::temp_dir().join(format!("test_load_blank_{name}.par"));
+            fs::write(&temp_file, content).unwrap();
+            let expr = Expr::Load(temp_file.to_str().unwrap().to_string(), Box::new(Expr::Int(42)));
+
+            assert_eq!(eval(&expr, &Environment::new()), Ok(Value::Int(42)), "{name}");
+            let env = extract_bindings(&expr, &Environment::new()).unwrap();
+            assert!(env.binding_order().is_empty(), "{name}");
+            let ty = crate::typechecker::typecheck_in(&expr, &mut crate::typechecker::TypeEnv::with_builtins());
+            assert_eq!(ty, Ok(crate::types::Type::Int), "{name}");
+
+            fs::remove_file(&temp_file).ok();
+        }
+    }
+
+    #[test]
+    fn test_load_comment_only_file_is_parse_error() {
+        use std::fs;
+
+        // The language has no comment syntax, so this is not a blank file
+        let temp_file = std::env::temp_dir().join("test_load_comment_only.par");
+        fs::write(&temp_file, "# helpers go here\n").unwrap();
+        let expr = Expr::Load(temp_file.to_str().unwrap().to_string(), Box::new(Expr::Int(42)));
+        let result = eval(&expr, &Environment::new());
+        assert!(matches!(&result, Err(EvalError::LoadError(msg)) if msg.contains("Failed to parse file")), "{result:?}");
+        fs::remove_file(&temp_file).ok();
+    }
+
+    #[test]
+    fn test_library_defining_nothing() {
+        assert_eq!(empty_library_notice("lib/x.par"), "loaded 'lib/x.par': 0 bindings");
+        let parsed = |source: &str| crate::parser::parse_program_with_positions(source).unwrap().0;
+        assert!(defines_nothing(&parsed("42")));
+        assert!(!defines_nothing(&parsed("let x = 1;")));
+        assert!(!defines_nothing(&parsed("type Color = Red | Green in ()")));
+        assert!(!defines_nothing(&parsed("load \"other.par\" in ()")));
+    }
+
+    #[test]
+    fn test_load_binding_error_reports_line() {
+        use std::fs;
+        
+        // The third binding divides by zero
+        let lib_content = "let one = 1;\nlet two = one + 1;\n\nlet broken =\n  two / 0;\nlet four = 4;\n";
+        let temp_dir = std::env::temp_dir();
+        let temp_file = temp_dir.join("test_load_binding_line.par");
+        fs::write(&temp_file, lib_content).unwrap();
+        let filepath = temp_file.to_str().unwrap().to_string();
+        
+        let expr = Expr::Load(filepath.clone(), Box::new(Expr::Int(0)));
+        let result = eval(&expr, &Environment::new());
+        assert_eq!(
+            result,
+            Err(EvalError::InBinding(
+                "broken".to_string(),
+                Some(format!("{filepath}:4")),
+                Box::new(EvalError::DivisionByZero),
+            ))
+        );
+        let msg = result.unwrap_err().to_string();
+        assert!(msg.contains("test_load_binding_line.par:4"));
+        
+        // The REPL path through extract_bindings reports the same location
+        let result = extract_bindings(&expr, &Environment::new());
+        assert!(matches!(result, Err(EvalError::InBinding(name, Some(_), _)) if name == "broken"));
+        
+        // Cleanup
+        fs::remove_file(&temp_file).ok();
+    }
+
     #[test]
     fn test_load_nested_load() {
         use std::fs;
@@ -1915,13 +4082,13 @@ mod tests {
     fn test_load_preserves_outer_bindings() {
         use std::fs;
         
-        // Create a library
-        let lib_content = "let double = fun x -> x * 2 in 0";
+        // Create a library that also defines a name the environment already binds
+        let lib_content = "let double = fun x -> x * 2 in let y = 1000 in 0";
         let temp_dir = std::env::temp_dir();
         let temp_file = temp_dir.join("test_load_preserve.par");
         fs::write(&temp_file, lib_content).unwrap();
         
-        // Create an environment with existing bindings
+        // Create an environment with existing bindings; they shadow the library's
         let mut env = Environment::new();
         env.bind("y".to_string(), Value::Int(10));
         
@@ -1945,6 +4112,84 @@ mod tests {
         fs::remove_file(&temp_file).ok();
     }
 
+    #[test]
+    fn test_load_as_keeps_libraries_apart() {
+        use std::fs;
+
+        // Both libraries define `helper`; namespaces let a program use both
+        let temp_dir = std::env::temp_dir();
+        let temp_file1 = temp_dir.join("test_load_as_lib1.par");
+        let temp_file2 = temp_dir.join("test_load_as_lib2.par");
+        fs::write(&temp_file1, "let helper = fun x -> x + 1 in 0").unwrap();
+        fs::write(&temp_file2, "let helper = fun x -> x * 10 in let base = 7 in 0").unwrap();
+
+        let source = format!(
+            "load \"{}\" as A in load \"{}\" as B in A.helper B.base + B.helper 2",
+            temp_file1.display(),
+            temp_file2.display()
+        );
+        let expr = crate::parser::parse(&source).unwrap();
+        assert_eq!(eval(&expr, &Environment::new()), Ok(Value::Int(28))); // (7 + 1) + 2 * 10
+
+        // The library's bindings are only reachable through the namespace
+        let source = format!("load \"{}\" as A in helper 1", temp_file1.display());
+        let expr = crate::parser::parse(&source).unwrap();
+        assert!(matches!(eval(&expr, &Environment::new()), Err(EvalError::UnboundVariable(..))));
+
+        // extract_bindings keeps the namespace, not the library's names
+        let source = format!("load \"{}\" as A in 0", temp_file1.display());
+        let env = extract_bindings(&crate::parser::parse(&source).unwrap(), &Environment::new()).unwrap();
+        assert!(matches!(env.lookup("A"), Some(Value::Record(fields)) if fields.contains_key("helper")));
+        assert_eq!(env.lookup("helper"), None);
+
+        fs::remove_file(&temp_file1).ok();
+        fs::remove_file(&temp_file2).ok();
+    }
+
+    #[test]
+    fn test_load_relative_to_library() {
+        use std::fs;
+
+        // The library loads its neighbour by a path relative to itself
+        let dir = std::env::temp_dir().join("test_load_relative");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("inner.par"), "let inner = 20 in 0").unwrap();
+        fs::write(dir.join("outer.par"), "load \"inner.par\" in let outer = inner + 1 in 0").unwrap();
+
+        let expr = crate::parser::parse("load \"outer.par\" in outer * 2").unwrap();
+        let mut env = Environment::new();
+        assert!(matches!(eval(&expr, &env), Err(EvalError::LoadError(msg)) if msg.contains("tried 'outer.par'")));
+        env.set_load_path(LoadPath { dir: None, search_path: vec![dir.clone()] });
+        assert_eq!(eval(&expr, &env), Ok(Value::Int(42)));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_load_circular() {
+        use std::fs;
+
+        let dir = std::env::temp_dir().join("test_load_circular");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.par"), "load \"b.par\" in let a = 1 in 0").unwrap();
+        fs::write(dir.join("b.par"), "load \"a.par\" in let b = 2 in 0").unwrap();
+
+        let expr = crate::parser::parse("load \"a.par\" in a").unwrap();
+        let mut env = Environment::new();
+        env.set_load_path(LoadPath { dir: Some(dir.clone()), search_path: Vec::new() });
+        let Err(EvalError::LoadError(msg)) = eval(&expr, &env) else { panic!("expected a circular load") };
+        let (a, b) = (dir.join("a.par"), dir.join("b.par"));
+        assert_eq!(msg, format!("Circular load: {} -> {} -> {}", a.display(), b.display(), a.display()));
+
+        // A library loaded twice in a row is not circular
+        fs::write(dir.join("b.par"), "let b = 2 in 0").unwrap();
+        env.clear_load_cache();
+        let expr = crate::parser::parse("load \"b.par\" in load \"b.par\" in b").unwrap();
+        assert_eq!(eval(&expr, &env), Ok(Value::Int(2)));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
     // Test environment merge
     #[test]
     fn test_environment_merge() {
@@ -2033,6 +4278,149 @@ mod tests {
         assert_eq!(format!("{err}"), "Load error: test load error");
     }
 
+    /// Evaluate source where `tick` returns the next counter value and `log` records it
+    fn eval_with_counter(body: &str) -> Result<Value, EvalError> {
+        let source = format!(
+            "let counter = ref 0 in \
+             let tick = fun u -> let n = !counter in let t = counter := n + 1 in n in {body}"
+        );
+        eval(&crate::parser::parse(&source).unwrap(), &Environment::new())
+    }
+
+    #[test]
+    fn test_saturated_call_evaluates_arguments_left_to_right() {
+        let result = eval_with_counter("(fun a -> fun b -> fun c -> (a, b, c)) (tick 0) (tick 0) (tick 0)");
+        assert_eq!(result, Ok(Value::Tuple(vec![Value::Int(0), Value::Int(1), Value::Int(2)])));
+    }
+
+    #[test]
+    fn test_partial_application_through_spine() {
+        let result = eval_with_counter(
+            "let f = fun a -> fun b -> fun c -> a * 100 + b * 10 + c in \
+             let g = f (tick 0) (tick 0) in (g 7, g 8, f 1 2)",
+        );
+        match result {
+            Ok(Value::Tuple(values)) => {
+                assert_eq!(values[0], Value::Int(17));
+                assert_eq!(values[1], Value::Int(18));
+                assert_eq!(values[2].to_string(), "<partial: f, 2 of 3 args applied>");
+            }
+            other => panic!("Expected tuple, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_oversaturated_call_runs_body_before_later_arguments() {
+        // f's body ticks before returning a function, so it must run after `tick 0`
+        // for a but before the third argument's `tick 0` is evaluated
+        let result = eval_with_counter(
+            "let f = fun a -> fun b -> let t = tick 0 in fun c -> (a, b, t, c) in f (tick 0) 5 (tick 0)",
+        );
+        assert_eq!(
+            result,
+            Ok(Value::Tuple(vec![Value::Int(0), Value::Int(5), Value::Int(1), Value::Int(2)]))
+        );
+    }
+
+    const GCD: &str = "let gcd = rec gcd -> fun a -> fun b -> if b == 0 then a else gcd b (a - b * (a / b)) in ";
+
+    fn eval_source(source: &str) -> Result<Value, EvalError> {
+        eval(&crate::parser::parse(source).unwrap(), &Environment::new())
+    }
+
+    #[test]
+    fn test_recursive_function_applied_to_exact_arity() {
+        assert_eq!(eval_source(&format!("{GCD}gcd 48 18")), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn test_recursive_function_under_applied_is_partial() {
+        let partial = eval_source(&format!("{GCD}gcd 48")).unwrap();
+        assert_eq!(partial.to_string(), "<partial: gcd, 1 of 2 args applied>");
+        assert_eq!(partial.kind_name(), "function");
+        assert_eq!(eval_source(&format!("{GCD}let g = gcd 48 in g 18")), Ok(Value::Int(6)));
+        assert_eq!(eval_source(&format!("{GCD}let g = gcd 48 in (g 18, g 36)")).unwrap().to_string(), "(6, 12)");
+    }
+
+    #[test]
+    fn test_recursive_function_over_applied_reports_arity() {
+        let error = eval_source(&format!("{GCD}gcd 48 18 5")).unwrap_err();
+        assert_eq!(
+            error,
+            EvalError::OverApplied { function: "gcd".to_string(), arity: 2, args: 3, result_kind: "Int" }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Type error: gcd takes 2 arguments but is applied to 3, and its Int result is not a function"
+        );
+        assert_eq!(error.kind(), "TypeError");
+        let error = eval_source(&format!("{GCD}let g = gcd 48 in g 18 5")).unwrap_err();
+        assert!(matches!(error, EvalError::OverApplied { arity: 2, args: 3, .. }), "{error:?}");
+    }
+
+    #[test]
+    fn test_diagnostics_name_the_let_binding_over_the_rec_name() {
+        let source = "let gcd = rec g -> fun a -> fun b -> if b == 0 then a else g b (a - b * (a / b)) in ";
+        assert_eq!(eval_source(&format!("{source}gcd 48")).unwrap().to_string(), "<partial: gcd, 1 of 2 args applied>");
+        let error = eval_source(&format!("{source}gcd 48 18 5")).unwrap_err();
+        assert!(matches!(&error, EvalError::OverApplied { function, .. } if function == "gcd"), "{error:?}");
+        // Without a variable to go by, the closure's own name is used
+        let error = eval_source(&format!("{source}(rec g -> fun a -> a) 1 2")).unwrap_err();
+        assert!(matches!(&error, EvalError::OverApplied { function, .. } if function == "g"), "{error:?}");
+    }
+
+    #[test]
+    fn test_partial_of_partial_counts_all_arguments() {
+        let source = "let add3 = fun a -> fun b -> fun c -> a + b + c in let p = add3 1 in ";
+        assert_eq!(eval_source(&format!("{source}p 2")).unwrap().to_string(), "<partial: add3, 2 of 3 args applied>");
+        assert_eq!(eval_source(&format!("{source}p 2 3")), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn test_arity_is_syntactic() {
+        // Anonymous functions are not named, so their partial applications stay plain closures
+        assert!(matches!(eval_source("(fun a -> fun b -> a) 1"), Ok(Value::Closure(..))));
+        // A function returning a computed function can still be applied past its counted arity
+        let source = "let k = fun a -> let f = fun b -> a + b in f in k 1 2";
+        assert_eq!(eval_source(source), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn test_spine_with_shadowed_parameters() {
+        let expr = crate::parser::parse("(fun x -> fun x -> x) 1 2").unwrap();
+        assert_eq!(eval(&expr, &Environment::new()), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn test_spine_mixing_closures_and_builtins() {
+        let expr = crate::parser::parse("let pick = fun f -> f in pick max 3 9").unwrap();
+        assert_eq!(eval(&expr, &Environment::with_builtins()), Ok(Value::Int(9)));
+    }
+
+    #[test]
+    fn test_eval_empty_body_is_unit() {
+        let env = Environment::new();
+        let expr = Expr::Seq(
+            vec![("x".to_string(), None, Expr::Int(42))],
+            Box::new(Expr::EmptyBody),
+        );
+        assert_eq!(eval(&expr, &env), Ok(Value::Unit));
+        let env = extract_bindings(&expr, &env).unwrap();
+        assert_eq!(env.lookup("x"), Some(&Value::Int(42)));
+    }
+
+    #[test]
+    fn test_eval_error_display_in_binding() {
+        let err = EvalError::InBinding(
+            "f".to_string(),
+            Some("lib.par:12".to_string()),
+            Box::new(EvalError::DivisionByZero),
+        );
+        assert_eq!(format!("{err}"), "In binding 'f' (lib.par:12): Division by zero");
+        let err = EvalError::InBinding("f".to_string(), None, Box::new(EvalError::DivisionByZero));
+        assert_eq!(format!("{err}"), "In binding 'f': Division by zero");
+    }
+
     // Test Seq evaluation
     #[test]
     fn test_eval_seq_single() {
@@ -2138,7 +4526,7 @@ mod tests {
     fn test_eval_tuple_empty() {
         let env = Environment::new();
         let expr = Expr::Tuple(vec![]);
-        assert_eq!(eval(&expr, &env), Ok(Value::Tuple(vec![])));
+        assert_eq!(eval(&expr, &env), Ok(Value::Unit));
     }
 
     #[test]
@@ -2242,7 +4630,7 @@ mod tests {
         let env = Environment::new();
         let pattern = Pattern::Tuple(vec![Pattern::Var("x".to_string()), Pattern::Var("y".to_string())]);
         let value = Value::Tuple(vec![Value::Int(1), Value::Int(2)]);
-        let result = match_pattern(&pattern, &value, &env);
+        let result = match_value(&pattern, &value).map(|bindings| with_bindings(&env, bindings));
         assert!(result.is_some());
         let new_env = result.unwrap();
         assert_eq!(new_env.lookup("x"), Some(&Value::Int(1)));
@@ -2257,7 +4645,7 @@ mod tests {
             Pattern::Var("y".to_string()),
         ]);
         let value = Value::Tuple(vec![Value::Int(0), Value::Int(5)]);
-        let result = match_pattern(&pattern, &value, &env);
+        let result = match_value(&pattern, &value).map(|bindings| with_bindings(&env, bindings));
         assert!(result.is_some());
         let new_env = result.unwrap();
         assert_eq!(new_env.lookup("y"), Some(&Value::Int(5)));
@@ -2271,7 +4659,7 @@ mod tests {
             Pattern::Var("y".to_string()),
         ]);
         let value = Value::Tuple(vec![Value::Int(1), Value::Int(5)]);
-        let result = match_pattern(&pattern, &value, &env);
+        let result = match_value(&pattern, &value).map(|bindings| with_bindings(&env, bindings));
         assert!(result.is_none());
     }
 
@@ -2280,7 +4668,7 @@ mod tests {
         let env = Environment::new();
         let pattern = Pattern::Tuple(vec![Pattern::Var("x".to_string())]);
         let value = Value::Tuple(vec![Value::Int(1), Value::Int(2)]);
-        let result = match_pattern(&pattern, &value, &env);
+        let result = match_value(&pattern, &value).map(|bindings| with_bindings(&env, bindings));
         assert!(result.is_none());
     }
 
@@ -2295,7 +4683,7 @@ mod tests {
             Value::Tuple(vec![Value::Int(1), Value::Int(2)]),
             Value::Int(3),
         ]);
-        let result = match_pattern(&pattern, &value, &env);
+        let result = match_value(&pattern, &value).map(|bindings| with_bindings(&env, bindings));
         assert!(result.is_some());
         let new_env = result.unwrap();
         assert_eq!(new_env.lookup("a"), Some(&Value::Int(1)));
@@ -2315,10 +4703,12 @@ mod tests {
                         Pattern::Literal(Literal::Int(0)),
                         Pattern::Literal(Literal::Int(0)),
                     ]),
+                    None,
                     Expr::Int(0),
                 ),
                 (
                     Pattern::Tuple(vec![Pattern::Var("x".to_string()), Pattern::Var("y".to_string())]),
+                    None,
                     Expr::BinOp(
                         BinOp::Add,
                         Box::new(Expr::Var("x".to_string())),
@@ -2330,6 +4720,200 @@ mod tests {
         assert_eq!(eval(&expr, &env), Ok(Value::Int(30)));
     }
 
+    #[test]
+    fn test_binding_order() {
+        let order = |source: &str| {
+            let expr = crate::parser::parse(source).unwrap();
+            extract_bindings(&expr, &Environment::new()).unwrap().binding_order().to_vec()
+        };
+        assert_eq!(order("let a = 1; let b = a + 1; let c = b * 2; c"), ["a", "b", "c"]);
+        assert_eq!(order("let b = 1; let a = b + 1; a"), ["b", "a"]);
+        // A redefined name moves to its latest position
+        assert_eq!(order("let a = 1; let b = a; let a = b + 1; a"), ["b", "a"]);
+
+        let mut left = Environment::new();
+        left.bind("x".to_string(), Value::Int(1));
+        left.bind("y".to_string(), Value::Int(2));
+        let mut right = Environment::new();
+        right.bind("z".to_string(), Value::Int(3));
+        right.bind("x".to_string(), Value::Int(4));
+        let merged = left.merge(&right);
+        assert_eq!(merged.binding_order(), ["y", "z", "x"]);
+        assert_eq!(merged.lookup("x"), Some(&Value::Int(4)));
+    }
+
+    #[test]
+    fn test_merge_into_smaller_environment() {
+        // The larger side is reused, but order and shadowing are as usual
+        let mut left = Environment::new();
+        left.bind("x".to_string(), Value::Int(1));
+        left.bind("y".to_string(), Value::Int(2));
+        let mut right = Environment::new();
+        for name in ["a", "b", "x", "c"] {
+            right.bind(name.to_string(), Value::Int(3));
+        }
+        let merged = left.merge(&right);
+        assert_eq!(merged.binding_order(), ["y", "a", "b", "x", "c"]);
+        assert_eq!(merged.lookup("x"), Some(&Value::Int(3)));
+        assert_eq!(merged.lookup("y"), Some(&Value::Int(2)));
+        assert_eq!(left.lookup("a"), None);
+
+        let extended = merged.extend("y".to_string(), Value::Int(4));
+        assert_eq!(extended.binding_order(), ["a", "b", "x", "c", "y"]);
+        assert_eq!(merged.lookup("y"), Some(&Value::Int(2)));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let looping = crate::parser::parse("(rec loop -> fun n -> loop (n + 1)) 0").unwrap();
+        let mut env = Environment::with_builtins();
+        env.set_step_limit(Some(1000));
+        assert_eq!(eval(&looping, &env), Err(EvalError::StepLimitExceeded(1000)));
+
+        // The budget is shared, so it is used up across evaluations
+        env.set_step_limit(Some(4));
+        let sum = crate::parser::parse("1 + 2").unwrap();
+        assert_eq!(eval(&sum, &env), Ok(Value::Int(3)));
+        assert_eq!(eval(&sum, &env), Err(EvalError::StepLimitExceeded(4)));
+        env.set_step_limit(None);
+        assert_eq!(eval(&sum, &env), Ok(Value::Int(3)));
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let deep = crate::parser::parse("let rec down = fun n -> if n == 0 then 0 else 1 + down (n - 1) in down 12").unwrap();
+        let tail = crate::parser::parse("let rec loop = fun n -> if n == 0 then 0 else loop (n - 1) in loop 10000").unwrap();
+        let curried = crate::parser::parse("let add = fun a -> fun b -> a + b in add 1 (add 2 (add 3 4))").unwrap();
+        for resolved in [false, true] {
+            let mut env = Environment::with_builtins();
+            env.set_resolved(resolved);
+            env.set_depth_limit(Some(12));
+            assert_eq!(eval(&deep, &env), Err(EvalError::RecursionLimitExceeded(12)));
+            // Tail calls run as loops, and calls that returned or failed no longer count
+            assert_eq!(eval(&tail, &env), Ok(Value::Int(0)));
+            env.set_depth_limit(Some(13));
+            assert_eq!(eval(&deep, &env), Ok(Value::Int(12)));
+            env.set_depth_limit(Some(1));
+            assert_eq!(eval(&curried, &env), Ok(Value::Int(10)));
+        }
+    }
+
+    #[test]
+    fn test_eval_with_limits_leaves_env_limits_alone() {
+        let deep = crate::parser::parse("let rec down = fun n -> if n == 0 then 0 else 1 + down (n - 1) in down 12").unwrap();
+        let mut env = Environment::with_builtins();
+        env.set_depth_limit(Some(5));
+        let limits = EvalLimits { max_steps: Some(10), max_depth: None };
+        assert_eq!(eval_with_limits(&deep, &env, limits), Err(EvalError::StepLimitExceeded(10)));
+        assert_eq!(eval_with_limits(&deep, &env, EvalLimits::default()), Ok(Value::Int(12)));
+        assert_eq!(eval(&deep, &env), Err(EvalError::RecursionLimitExceeded(5)));
+        assert_eq!(EvalError::RecursionLimitExceeded(5).kind(), "RecursionLimitExceeded");
+        assert!(!EvalError::RecursionLimitExceeded(5).is_recoverable());
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let looping = crate::parser::parse("(rec f -> fun n -> f (n + 1)) 0").unwrap();
+        let caught = crate::parser::parse("try (rec f -> fun n -> f (n + 1)) 0 catch e -> 0").unwrap();
+        let sum = crate::parser::parse("1 + 2").unwrap();
+        let flag = Arc::new(AtomicBool::new(true));
+        for resolved in [false, true] {
+            let mut env = Environment::with_builtins();
+            env.set_resolved(resolved);
+            env.set_interrupt(Some(Arc::clone(&flag)));
+            // A raised flag is only read once the countdown runs out
+            assert_eq!(eval(&sum, &env), Ok(Value::Int(3)));
+            assert_eq!(eval(&looping, &env), Err(EvalError::Interrupted));
+            assert_eq!(eval(&caught, &env), Err(EvalError::Interrupted));
+            env.set_interrupt(None);
+            env.set_step_limit(Some(10_000));
+            assert_eq!(eval(&looping, &env), Err(EvalError::StepLimitExceeded(10_000)));
+        }
+        assert_eq!(EvalError::Interrupted.kind(), "Interrupted");
+        assert!(!EvalError::Interrupted.is_recoverable());
+        assert_eq!(EvalError::Interrupted.with_context(|| "in f".to_string()), EvalError::Interrupted);
+    }
+
+    #[test]
+    fn test_interrupt_from_another_thread() {
+        let looping = crate::parser::parse("(rec f -> fun n -> f (n + 1)) 0").unwrap();
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut env = Environment::with_builtins();
+        env.set_interrupt(Some(Arc::clone(&flag)));
+        let raiser = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            flag.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(eval(&looping, &env), Err(EvalError::Interrupted));
+        raiser.join().unwrap();
+    }
+
+    #[test]
+    fn test_eval_with_spans_reports_where_the_error_is_raised() {
+        let source = "let xs = (1, 2) in\nlet pick = fun i -> if i == 0 then xs.0 else {a: 1}.b in\npick 0 + pick 1";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        for resolved in [false, true] {
+            let mut env = Environment::new();
+            env.set_resolved(resolved);
+            let error = eval_with_spans(&expr, &spans, &env).unwrap_err();
+            let span = error.span().unwrap();
+            assert_eq!(&source[span.start..span.end], "{a: 1}.b");
+            assert!(matches!(error.root(), EvalError::FieldNotFound(..)), "{error:?}");
+            assert_eq!(error.trace(), ["in application of 'pick'"]);
+            // Without spans it is the same error
+            assert_eq!(error.root(), eval(&expr, &env).unwrap_err().root());
+        }
+    }
+
+    #[test]
+    fn test_eval_with_spans_locates_conditions_calls_and_matches() {
+        let cases = [
+            ("let x = 1;\nlet y = if x then 2 else 3;\ny", "if x then 2 else 3", vec!["in let binding 'y'"]),
+            ("let y = 3 4;\ny", "3 4", vec!["in let binding 'y'"]),
+            ("let y = match 3 with | 0 -> 1;\ny", "match 3 with | 0 -> 1", vec!["in let binding 'y'"]),
+            // In tail position of a function body, and at the call of one that fails on its own
+            ("let f = fun n -> if n then 1 else f 2;\nf 3", "if n then 1 else f 2", vec!["in application of 'f'"]),
+            ("let f = fun (a, b) -> a;\nf 3", "f 3", vec!["in application of 'f'"]),
+            // A pattern binding fails where its value is
+            ("let (a, b) = 1 + 2 in a", "1 + 2", vec![]),
+        ];
+        for (source, failing, trace) in cases {
+            let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+            for resolved in [false, true] {
+                let mut env = Environment::new();
+                env.set_resolved(resolved);
+                let error = eval_with_spans(&expr, &spans, &env).unwrap_err();
+                let span = error.span().unwrap_or_else(|| panic!("no span for {error:?}"));
+                assert_eq!(&source[span.start..span.end], failing, "{source}");
+                assert_eq!(error.trace(), trace, "{source}");
+                assert_eq!(error.root(), eval(&expr, &env).unwrap_err().root());
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_with_spans_counts_the_same_steps() {
+        let source = "let rec sum = fun n -> if n == 0 then 0 else n + sum (n - 1) in\nsum 10 * 2";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        let mut env = Environment::new();
+        let steps = (1..).find(|&limit| {
+            env.set_step_limit(Some(limit));
+            eval(&expr, &env).is_ok()
+        });
+        env.set_step_limit(steps);
+        assert_eq!(eval_with_spans(&expr, &spans, &env), Ok(Value::Int(110)));
+        env.set_step_limit(steps.map(|steps| steps - 1));
+        // Running out of steps concerns the whole program, not a span
+        assert_eq!(eval_with_spans(&expr, &spans, &env), Err(EvalError::StepLimitExceeded(steps.unwrap() - 1)));
+    }
+
+    #[test]
+    fn test_try_catches_spanned_errors() {
+        let source = "try 1 / 0 catch e -> e.message";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        assert_eq!(eval_with_spans(&expr, &spans, &Environment::new()), Ok(string_value("Division by zero")));
+    }
+
     // Test Value Display
     #[test]
     fn test_value_display_tuple() {
@@ -2341,6 +4925,7 @@ mod tests {
     fn test_value_display_tuple_empty() {
         let val = Value::Tuple(vec![]);
         assert_eq!(format!("{val}"), "()");
+        assert_eq!(format!("{}", Value::Unit), "()");
     }
 
     #[test]
@@ -8,7 +8,10 @@
 //!
 //! The exhaustiveness checker works by building a "usefulness" matrix that tracks which
 //! patterns cover which values. The algorithm is based on the principles described in
-//! "Warnings for pattern matching" by Luc Maranget (2007).
+//! "Warnings for pattern matching" by Luc Maranget (2007): a match is exhaustive when a
+//! wildcard row is not useful after all arms, and an arm is redundant when it is not
+//! useful after the arms before it. Following the matrix down to the missing values
+//! yields example patterns for them, such as `Some false` or `(true, _)`.
 //!
 //! # Example
 //!
@@ -25,36 +28,92 @@
 //! | Some n -> n
 //! ```
 
-use crate::ast::{Literal, Pattern};
-use crate::eval::Environment;
-use std::collections::HashSet;
+use crate::ast::{Expr, Literal, Pattern};
+use crate::eval::{register_type_def, Environment};
+use crate::lint::Lint;
+use crate::parser::match_keyword_lines;
+use std::collections::BTreeSet;
+use std::fmt;
 
 /// Result of exhaustiveness checking
 #[derive(Debug, Clone, PartialEq)]
-pub enum ExhaustivenessResult {
-    /// Patterns are exhaustive
-    Exhaustive,
-    /// Patterns are non-exhaustive, with examples of missing patterns
-    NonExhaustive(Vec<String>),
+pub struct ExhaustivenessResult {
+    /// Whether every value of the scrutinee matches some arm
+    pub is_exhaustive: bool,
+    /// Example patterns for values no arm matches; empty when exhaustive
+    pub missing: Vec<Pattern>,
+    /// Indices (0-based) of arms that can never match, as earlier arms cover them
+    pub redundant: Vec<usize>,
 }
 
 impl ExhaustivenessResult {
     /// Returns true if patterns are exhaustive
     pub fn is_exhaustive(&self) -> bool {
-        matches!(self, ExhaustivenessResult::Exhaustive)
+        self.is_exhaustive
+    }
+
+    /// The result as warnings, one per problem
+    ///
+    /// A non-exhaustive match gives a `non-exhaustive-match` warning listing
+    /// the missing patterns, and each redundant arm an `unreachable-arm`
+    /// warning naming it (counting from 1).
+    pub fn to_diagnostics(&self) -> Vec<Lint> {
+        let mut diagnostics = Vec::new();
+        if !self.is_exhaustive {
+            diagnostics.push(Lint {
+                rule: "non-exhaustive-match",
+                message: format!("pattern match is non-exhaustive; missing: {}", self.missing_list()),
+                name: None,
+                span: None,
+            });
+        }
+        diagnostics.extend(self.redundant.iter().map(|index| Lint {
+            rule: "unreachable-arm",
+            message: format!("match arm {} can never match: earlier arms cover it", index + 1),
+            name: None,
+            span: None,
+        }));
+        diagnostics
+    }
+
+    /// The missing patterns, separated by commas
+    fn missing_list(&self) -> String {
+        self.missing.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
     }
 }
 
-/// Check if a list of patterns is exhaustive
+/// A summary of the result: one line per problem
+impl fmt::Display for ExhaustivenessResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_exhaustive && self.redundant.is_empty() {
+            return write!(f, "pattern match is exhaustive");
+        }
+        if self.is_exhaustive {
+            write!(f, "pattern match has unreachable arms")?;
+        } else {
+            write!(f, "pattern match is non-exhaustive")?;
+            write!(f, "\n  Missing cases: {}", self.missing_list())?;
+        }
+        for index in &self.redundant {
+            write!(f, "\n  Unreachable arm {}: earlier arms cover it", index + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check if a list of patterns is exhaustive, and which of them are redundant
 ///
 /// This function analyzes the patterns to determine if they cover all possible values.
 /// It considers:
-/// - Literal patterns (Int, Bool)
+/// - Literal patterns (Int, Bool, Char, Byte); only Bool literals can cover their type
 /// - Variable and wildcard patterns (always match everything)
-/// - Constructor patterns (sum types)
+/// - Constructor patterns (sum types), nested to any depth
 /// - Tuple patterns
 /// - Record patterns
 ///
+/// Constructors the environment does not know are taken to make up their
+/// type on their own.
+///
 /// # Arguments
 ///
 /// * `patterns` - The list of patterns from the match arms
@@ -62,181 +121,298 @@ impl ExhaustivenessResult {
 ///
 /// # Returns
 ///
-/// An `ExhaustivenessResult` indicating whether the patterns are exhaustive
+/// An `ExhaustivenessResult` with examples of missing values and the redundant arms
 pub fn check_exhaustiveness(patterns: &[Pattern], env: &Environment) -> ExhaustivenessResult {
-    if patterns.is_empty() {
-        return ExhaustivenessResult::NonExhaustive(vec!["_".to_string()]);
-    }
-
-    // Check if there's a catch-all pattern (wildcard or variable)
-    if has_catch_all(patterns) {
-        return ExhaustivenessResult::Exhaustive;
-    }
-
-    // Analyze patterns by type
-    let mut constructors_covered: HashSet<String> = HashSet::new();
-    let mut has_bool_true = false;
-    let mut has_bool_false = false;
-    let mut int_literals: HashSet<i64> = HashSet::new();
-    let mut has_tuple_pattern = false;
-    let mut has_record_pattern = false;
-
-    for pattern in patterns {
-        analyze_pattern(
-            pattern,
-            &mut constructors_covered,
-            &mut has_bool_true,
-            &mut has_bool_false,
-            &mut int_literals,
-            &mut has_tuple_pattern,
-            &mut has_record_pattern,
-        );
+    let arms: Vec<(Pattern, bool)> = patterns.iter().map(|pattern| (pattern.clone(), false)).collect();
+    check_guarded_exhaustiveness(&arms, env)
+}
+
+/// Like [`check_exhaustiveness`], for the arms of a match given as their
+/// pattern and whether they have a guard
+///
+/// A guarded arm may be passed over even when its pattern matches, so it
+/// covers no values: a match whose only catch-all arm is guarded is not
+/// exhaustive. A guarded arm is still redundant when the unguarded arms
+/// before it cover its pattern.
+pub fn check_guarded_exhaustiveness(arms: &[(Pattern, bool)], env: &Environment) -> ExhaustivenessResult {
+    let mut rows: Vec<Vec<Pattern>> = Vec::new();
+    let mut redundant = Vec::new();
+    for (index, (pattern, guarded)) in arms.iter().enumerate() {
+        let row = vec![pattern.clone()];
+        if !is_useful(&rows, &row, env) {
+            redundant.push(index);
+        }
+        if !guarded {
+            rows.push(row);
+        }
     }
+    let missing: Vec<Pattern> = missing_rows(&rows, 1, env)
+        .into_iter()
+        .filter_map(|mut row| row.pop())
+        .collect();
+    ExhaustivenessResult { is_exhaustive: missing.is_empty(), missing, redundant }
+}
 
-    // Check for exhaustiveness based on pattern types
+/// What is wrong with a `match`
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchProblem {
+    /// No arm matches these example values
+    NonExhaustive(Vec<Pattern>),
+    /// The arm with this index (0-based) can never match, as earlier arms cover it
+    UnreachableArm(usize),
+}
 
-    // 1. Constructor patterns - need to check all constructors of the type are covered
-    if !constructors_covered.is_empty() {
-        if let Some(missing) = check_constructor_exhaustiveness(&constructors_covered, env) {
-            return ExhaustivenessResult::NonExhaustive(missing);
+/// A problem with one `match` of a program, found before running it
+///
+/// `Display` renders it as `match on line 3 is non-exhaustive, missing: None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchWarning {
+    /// Line (1-based) of the `match` or `function` keyword, when it could be found
+    pub line: Option<usize>,
+    pub problem: MatchProblem,
+}
+
+impl fmt::Display for MatchWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "match")?;
+        if let Some(line) = self.line {
+            write!(f, " on line {line}")?;
+        }
+        match &self.problem {
+            MatchProblem::NonExhaustive(missing) => {
+                let missing: Vec<String> = missing.iter().map(ToString::to_string).collect();
+                write!(f, " is non-exhaustive, missing: {}", missing.join(", "))
+            }
+            MatchProblem::UnreachableArm(index) => {
+                write!(f, " has an unreachable arm {}: earlier arms cover it", index + 1)
+            }
         }
     }
+}
 
-    // 2. Boolean patterns - need both true and false
-    if has_bool_true || has_bool_false {
-        if !has_bool_true {
-            return ExhaustivenessResult::NonExhaustive(vec!["true".to_string()]);
+/// Check every `match` in a program before running it
+///
+/// `source` is the text `expr` was parsed from, and is only used to find the
+/// line of each match. The constructors of `env` and of every type the
+/// program declares are known to the checker. Matches in `load`ed files are
+/// not checked.
+pub fn check_matches(expr: &Expr, source: &str, env: &Environment) -> Vec<MatchWarning> {
+    let mut env = env.clone();
+    for node in expr.subexpressions() {
+        if let Expr::TypeDef { name, constructors, .. } = node {
+            let arities: Vec<(String, usize)> =
+                constructors.iter().map(|(ctor_name, payload)| (ctor_name.clone(), payload.len())).collect();
+            register_type_def(&mut env, name, &arities);
         }
-        if !has_bool_false {
-            return ExhaustivenessResult::NonExhaustive(vec!["false".to_string()]);
+    }
+    let matches: Vec<&[(Pattern, Option<Expr>, Expr)]> = expr
+        .subexpressions()
+        .filter_map(|node| match node {
+            Expr::Match(_, arms) => Some(arms.as_slice()),
+            _ => None,
+        })
+        .collect();
+    // A keyword the scan miscounts would shift every later line, so give none
+    let lines = Some(match_keyword_lines(source)).filter(|lines| lines.len() == matches.len());
+    let mut warnings = Vec::new();
+    for (index, arms) in matches.into_iter().enumerate() {
+        let line = lines.as_ref().map(|lines| lines[index]);
+        let arms: Vec<(Pattern, bool)> = arms.iter().map(|(pattern, guard, _)| (pattern.clone(), guard.is_some())).collect();
+        let result = check_guarded_exhaustiveness(&arms, &env);
+        if !result.is_exhaustive {
+            warnings.push(MatchWarning { line, problem: MatchProblem::NonExhaustive(result.missing) });
         }
+        warnings.extend(
+            result.redundant.into_iter().map(|index| MatchWarning { line, problem: MatchProblem::UnreachableArm(index) }),
+        );
     }
+    warnings
+}
 
-    // 3. Integer patterns - integers are infinite, so if we only have literals, it's non-exhaustive
-    if !int_literals.is_empty() {
-        // Integer patterns alone (without a catch-all) are never exhaustive
-        return ExhaustivenessResult::NonExhaustive(vec!["<other integers>".to_string()]);
-    }
+/// The head of a pattern that is not a wildcard: what kind of value it matches
+#[derive(Debug, Clone, PartialEq)]
+enum Head {
+    Bool(bool),
+    /// An Int, Char, or Byte literal: these types have too many values to list
+    Literal(Literal),
+    Tuple(usize),
+    /// A record with these fields; patterns naming fewer fields leave the others unconstrained
+    Record(Vec<String>),
+    /// A constructor and its arity
+    Constructor(String, usize),
+}
 
-    // 4. Tuple and record patterns
-    // For tuples and records, if we don't have a catch-all, we consider them non-exhaustive
-    // (proper nested exhaustiveness checking would be more complex)
-    if has_tuple_pattern || has_record_pattern {
-        // These patterns alone without catch-all are considered non-exhaustive
-        return ExhaustivenessResult::NonExhaustive(vec!["_".to_string()]);
+impl Head {
+    /// Number of sub-patterns a pattern with this head has
+    fn arity(&self) -> usize {
+        match self {
+            Head::Bool(_) | Head::Literal(_) => 0,
+            Head::Tuple(arity) | Head::Constructor(_, arity) => *arity,
+            Head::Record(fields) => fields.len(),
+        }
     }
 
-    // If we get here, we have no patterns or only catch-all patterns
-    ExhaustivenessResult::Exhaustive
+    /// The pattern with this head and the given sub-patterns
+    fn to_pattern(&self, args: Vec<Pattern>) -> Pattern {
+        match self {
+            Head::Bool(b) => Pattern::Literal(Literal::Bool(*b)),
+            Head::Literal(literal) => Pattern::Literal(literal.clone()),
+            Head::Tuple(_) => Pattern::Tuple(args),
+            Head::Record(fields) => Pattern::Record(fields.iter().cloned().zip(args).collect()),
+            Head::Constructor(name, _) => Pattern::Constructor(name.clone(), args),
+        }
+    }
 }
 
-/// Check if patterns contain a catch-all (wildcard or variable)
-fn has_catch_all(patterns: &[Pattern]) -> bool {
-    patterns.iter().any(|p| matches!(p, Pattern::Wildcard | Pattern::Var(_)))
+/// Whether a pattern matches every value
+fn is_catch_all(pattern: &Pattern) -> bool {
+    matches!(pattern, Pattern::Wildcard | Pattern::Var(_))
 }
 
-/// Recursively analyze a pattern to collect information
-fn analyze_pattern(
-    pattern: &Pattern,
-    constructors: &mut HashSet<String>,
-    has_bool_true: &mut bool,
-    has_bool_false: &mut bool,
-    int_literals: &mut HashSet<i64>,
-    has_tuple_pattern: &mut bool,
-    has_record_pattern: &mut bool,
-) {
+/// The head of a pattern, with record fields taken from `record_fields`
+fn head(pattern: &Pattern, record_fields: &[String], env: &Environment) -> Option<Head> {
     match pattern {
-        Pattern::Literal(Literal::Bool(true)) => *has_bool_true = true,
-        Pattern::Literal(Literal::Bool(false)) => *has_bool_false = true,
-        Pattern::Literal(Literal::Int(n)) => {
-            int_literals.insert(*n);
-        }
-        Pattern::Literal(Literal::Char(_)) => {
-            // Char literals are not exhaustively checked currently
-            // (there are too many possible char values)
+        Pattern::Wildcard | Pattern::Var(_) => None,
+        Pattern::Literal(Literal::Bool(b)) => Some(Head::Bool(*b)),
+        Pattern::Literal(literal) => Some(Head::Literal(literal.clone())),
+        Pattern::Tuple(patterns) => Some(Head::Tuple(patterns.len())),
+        Pattern::Record(_) => Some(Head::Record(record_fields.to_vec())),
+        Pattern::Constructor(name, args) => {
+            let arity = env.get_constructor(name).map_or(args.len(), |info| info.arity);
+            Some(Head::Constructor(name.clone(), arity))
         }
-        Pattern::Literal(Literal::Byte(_)) => {
-            // Byte literals are not exhaustively checked currently
-            // (there are 256 possible byte values)
+    }
+}
+
+/// Every field named by a record pattern in the first column
+fn record_fields<'a>(first_patterns: impl Iterator<Item = &'a Pattern>) -> Vec<String> {
+    let mut fields = BTreeSet::new();
+    for pattern in first_patterns {
+        if let Pattern::Record(named) = pattern {
+            fields.extend(named.iter().map(|(field, _)| field.clone()));
         }
-        Pattern::Constructor(name, args) => {
-            constructors.insert(name.clone());
-            // Recursively analyze nested patterns
-            for arg in args {
-                analyze_pattern(
-                    arg,
-                    constructors,
-                    has_bool_true,
-                    has_bool_false,
-                    int_literals,
-                    has_tuple_pattern,
-                    has_record_pattern,
-                );
-            }
+    }
+    fields.into_iter().collect()
+}
+
+/// The rows of `rows` that can match a value with head `head`, with the
+/// first pattern replaced by its sub-patterns
+fn specialize(rows: &[Vec<Pattern>], head_of: &Head) -> Vec<Vec<Pattern>> {
+    rows.iter().filter_map(|row| specialize_row(row, head_of)).collect()
+}
+
+/// One row of [`specialize`], or `None` if it cannot match `head`
+fn specialize_row(row: &[Pattern], head_of: &Head) -> Option<Vec<Pattern>> {
+    let (first, rest) = row.split_first()?;
+    let args = match (first, head_of) {
+        (Pattern::Wildcard | Pattern::Var(_), _) => vec![Pattern::Wildcard; head_of.arity()],
+        (Pattern::Literal(Literal::Bool(b)), Head::Bool(expected)) if b == expected => vec![],
+        (Pattern::Literal(literal), Head::Literal(expected)) if literal == expected => vec![],
+        (Pattern::Tuple(patterns), Head::Tuple(arity)) if patterns.len() == *arity => patterns.clone(),
+        (Pattern::Record(named), Head::Record(fields)) => fields
+            .iter()
+            .map(|field| {
+                named.iter().find(|(name, _)| name == field).map_or(Pattern::Wildcard, |(_, p)| p.clone())
+            })
+            .collect(),
+        (Pattern::Constructor(name, patterns), Head::Constructor(expected, arity)) if name == expected => {
+            let mut args = patterns.clone();
+            args.resize(*arity, Pattern::Wildcard);
+            args
         }
-        Pattern::Tuple(patterns) => {
-            *has_tuple_pattern = true;
-            // Recursively analyze nested patterns
-            for p in patterns {
-                analyze_pattern(
-                    p,
-                    constructors,
-                    has_bool_true,
-                    has_bool_false,
-                    int_literals,
-                    has_tuple_pattern,
-                    has_record_pattern,
-                );
-            }
+        _ => return None,
+    };
+    Some(args.into_iter().chain(rest.iter().cloned()).collect())
+}
+
+/// The rows whose first pattern matches anything, without it
+fn default_rows(rows: &[Vec<Pattern>]) -> Vec<Vec<Pattern>> {
+    rows.iter()
+        .filter(|row| row.first().is_some_and(is_catch_all))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// The heads the first column uses, and the heads its type has that it does
+/// not use, or `None` when the type has too many values to list
+fn signature(heads: &[Head], env: &Environment) -> (Vec<Head>, Option<Vec<Head>>) {
+    let mut used: Vec<Head> = Vec::new();
+    for head_of in heads {
+        if !used.contains(head_of) {
+            used.push(head_of.clone());
         }
-        Pattern::Record(fields) => {
-            *has_record_pattern = true;
-            // Recursively analyze nested patterns
-            for (_, p) in fields {
-                analyze_pattern(
-                    p,
-                    constructors,
-                    has_bool_true,
-                    has_bool_false,
-                    int_literals,
-                    has_tuple_pattern,
-                    has_record_pattern,
-                );
+    }
+    let unused = match used.first() {
+        None | Some(Head::Literal(_)) => None,
+        Some(Head::Tuple(_) | Head::Record(_)) => Some(vec![]),
+        Some(Head::Bool(_)) => Some(
+            [true, false].into_iter().map(Head::Bool).filter(|b| !used.contains(b)).collect(),
+        ),
+        Some(Head::Constructor(name, _)) => Some(match env.get_constructor(name) {
+            Some(info) => {
+                let mut all = env.get_constructors_for_type(&info.type_name);
+                all.sort();
+                all.into_iter()
+                    .filter_map(|ctor| env.get_constructor(&ctor).map(|info| Head::Constructor(ctor, info.arity)))
+                    .filter(|ctor| !used.contains(ctor))
+                    .collect()
             }
+            None => vec![],
+        }),
+    };
+    (used, unused)
+}
+
+/// Whether a value matching `row` can fail to match every row of `rows`
+fn is_useful(rows: &[Vec<Pattern>], row: &[Pattern], env: &Environment) -> bool {
+    let Some(first) = row.first() else {
+        return rows.is_empty();
+    };
+    let fields = record_fields(rows.iter().filter_map(|r| r.first()).chain([first]));
+    let heads: Vec<Head> = rows.iter().filter_map(|r| head(&r[0], &fields, env)).collect();
+    match head(first, &fields, env) {
+        Some(head_of) => {
+            let row = specialize_row(row, &head_of).unwrap_or_default();
+            is_useful(&specialize(rows, &head_of), &row, env)
         }
-        Pattern::Wildcard | Pattern::Var(_) => {
-            // These are catch-all patterns, handled separately
-        }
+        None => match signature(&heads, env) {
+            (used, Some(unused)) if !used.is_empty() && unused.is_empty() => used.iter().any(|head_of| {
+                let row = specialize_row(row, head_of).unwrap_or_default();
+                is_useful(&specialize(rows, head_of), &row, env)
+            }),
+            _ => is_useful(&default_rows(rows), &row[1..], env),
+        },
     }
 }
 
-/// Check if all constructors of a sum type are covered
+/// Example rows of `width` patterns that no row of `rows` matches
 ///
-/// This function finds all constructors that belong to the same type as the covered
-/// constructors and checks if all are present in the set.
-fn check_constructor_exhaustiveness(
-    covered: &HashSet<String>,
-    env: &Environment,
-) -> Option<Vec<String>> {
-    // Find the type name from one of the covered constructors
-    let first_ctor = covered.iter().next()?;
-    let type_info = env.get_constructor(first_ctor)?;
-    let type_name = &type_info.type_name;
-
-    // Find all constructors for this type
-    let all_constructors = env.get_constructors_for_type(type_name);
-
-    // Check which constructors are missing
-    let missing: Vec<String> = all_constructors
-        .into_iter()
-        .filter(|ctor| !covered.contains(ctor))
-        .collect();
-
-    if missing.is_empty() {
-        None
-    } else {
-        Some(missing)
+/// When the first column's type has few enough values to list, the examples
+/// follow each head in turn, so a missing `None` and a missing `Some None`
+/// are both reported; a head no row uses gets wildcard sub-patterns.
+/// Otherwise the first pattern of each example is a wildcard.
+fn missing_rows(rows: &[Vec<Pattern>], width: usize, env: &Environment) -> Vec<Vec<Pattern>> {
+    if width == 0 {
+        return if rows.is_empty() { vec![vec![]] } else { vec![] };
+    }
+    let fields = record_fields(rows.iter().map(|row| &row[0]));
+    let heads: Vec<Head> = rows.iter().filter_map(|row| head(&row[0], &fields, env)).collect();
+    let (used, unused) = signature(&heads, env);
+    let Some(unused) = unused.filter(|_| !used.is_empty()) else {
+        return missing_rows(&default_rows(rows), width - 1, env)
+            .into_iter()
+            .map(|rest| std::iter::once(Pattern::Wildcard).chain(rest).collect())
+            .collect();
+    };
+    let mut missing = Vec::new();
+    for head_of in used.iter().chain(&unused) {
+        let arity = head_of.arity();
+        for mut row in missing_rows(&specialize(rows, head_of), arity + width - 1, env) {
+            let rest = row.split_off(arity);
+            missing.push(std::iter::once(head_of.to_pattern(row)).chain(rest).collect());
+        }
     }
+    missing
 }
 
 #[cfg(test)]
@@ -286,9 +462,7 @@ mod tests {
         let env = Environment::new();
         let result = check_exhaustiveness(&patterns, &env);
         assert!(!result.is_exhaustive());
-        if let ExhaustivenessResult::NonExhaustive(missing) = result {
-            assert!(missing.contains(&"false".to_string()));
-        }
+        assert_eq!(result.missing, vec![Pattern::Literal(Literal::Bool(false))]);
     }
 
     #[test]
@@ -297,9 +471,7 @@ mod tests {
         let env = Environment::new();
         let result = check_exhaustiveness(&patterns, &env);
         assert!(!result.is_exhaustive());
-        if let ExhaustivenessResult::NonExhaustive(missing) = result {
-            assert!(missing.contains(&"true".to_string()));
-        }
+        assert_eq!(result.missing, vec![Pattern::Literal(Literal::Bool(true))]);
     }
 
     #[test]
@@ -365,9 +537,147 @@ mod tests {
         ];
         let result = check_exhaustiveness(&patterns, &env);
         assert!(!result.is_exhaustive());
-        if let ExhaustivenessResult::NonExhaustive(missing) = result {
-            assert!(missing.contains(&"None".to_string()));
-        }
+        assert_eq!(result.missing, vec![Pattern::Constructor("None".to_string(), vec![])]);
+    }
+
+    /// An environment with `Option a = Some a | None`
+    fn option_env() -> Environment {
+        let mut env = Environment::new();
+        env.register_constructor("Some".to_string(), ConstructorInfo { type_name: "Option".to_string(), arity: 1 });
+        env.register_constructor("None".to_string(), ConstructorInfo { type_name: "Option".to_string(), arity: 0 });
+        env
+    }
+
+    fn some(pattern: Pattern) -> Pattern {
+        Pattern::Constructor("Some".to_string(), vec![pattern])
+    }
+
+    fn boolean(b: bool) -> Pattern {
+        Pattern::Literal(Literal::Bool(b))
+    }
+
+    #[test]
+    fn test_missing_int_literals_need_wildcard() {
+        let patterns = vec![Pattern::Literal(Literal::Int(0)), Pattern::Literal(Literal::Int(1))];
+        let result = check_exhaustiveness(&patterns, &Environment::new());
+        assert_eq!(result.missing, vec![Pattern::Wildcard]);
+        assert_eq!(result.to_string(), "pattern match is non-exhaustive\n  Missing cases: _");
+    }
+
+    #[test]
+    fn test_missing_tuple_examples() {
+        let patterns = vec![
+            Pattern::Tuple(vec![boolean(true), boolean(true)]),
+            Pattern::Tuple(vec![boolean(false), Pattern::Wildcard]),
+        ];
+        let result = check_exhaustiveness(&patterns, &Environment::new());
+        assert_eq!(result.missing, vec![Pattern::Tuple(vec![boolean(true), boolean(false)])]);
+        assert_eq!(result.to_string(), "pattern match is non-exhaustive\n  Missing cases: (true, false)");
+
+        let patterns = vec![
+            Pattern::Tuple(vec![boolean(true), Pattern::Var("x".to_string())]),
+            Pattern::Tuple(vec![boolean(false), Pattern::Wildcard]),
+        ];
+        assert!(check_exhaustiveness(&patterns, &Environment::new()).is_exhaustive());
+    }
+
+    #[test]
+    fn test_missing_nested_constructor_examples() {
+        let env = option_env();
+        let patterns = vec![some(boolean(true)), Pattern::Constructor("None".to_string(), vec![])];
+        let result = check_exhaustiveness(&patterns, &env);
+        assert_eq!(result.missing, vec![some(boolean(false))]);
+        assert_eq!(result.to_string(), "pattern match is non-exhaustive\n  Missing cases: Some false");
+
+        let result = check_exhaustiveness(&[some(some(Pattern::Wildcard))], &env);
+        assert_eq!(
+            result.missing,
+            vec![some(Pattern::Constructor("None".to_string(), vec![])), Pattern::Constructor("None".to_string(), vec![])]
+        );
+        assert_eq!(result.to_string(), "pattern match is non-exhaustive\n  Missing cases: Some None, None");
+
+        let result = check_exhaustiveness(&[some(some(boolean(true))), some(Pattern::Wildcard)], &env);
+        assert_eq!(result.to_string(), "pattern match is non-exhaustive\n  Missing cases: None");
+        let result = check_exhaustiveness(&[some(some(boolean(true))), Pattern::Constructor("None".to_string(), vec![])], &env);
+        assert_eq!(
+            result.to_string(),
+            "pattern match is non-exhaustive\n  Missing cases: Some (Some false), Some None"
+        );
+    }
+
+    #[test]
+    fn test_redundant_arms() {
+        let env = option_env();
+        let patterns = vec![
+            Pattern::Var("x".to_string()),
+            some(Pattern::Wildcard),
+            boolean(true),
+        ];
+        let result = check_exhaustiveness(&patterns, &env);
+        assert!(result.is_exhaustive());
+        assert_eq!(result.redundant, vec![1, 2]);
+        assert_eq!(
+            result.to_string(),
+            "pattern match has unreachable arms\n  \
+             Unreachable arm 2: earlier arms cover it\n  \
+             Unreachable arm 3: earlier arms cover it"
+        );
+
+        let patterns = vec![boolean(true), boolean(true)];
+        let result = check_exhaustiveness(&patterns, &env);
+        assert_eq!((result.missing, result.redundant), (vec![boolean(false)], vec![1]));
+    }
+
+    #[test]
+    fn test_to_diagnostics() {
+        let env = option_env();
+        assert!(check_exhaustiveness(&[Pattern::Wildcard], &env).to_diagnostics().is_empty());
+
+        let patterns = vec![some(boolean(true)), some(boolean(true))];
+        let diagnostics = check_exhaustiveness(&patterns, &env).to_diagnostics();
+        let rendered: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            vec![
+                "pattern match is non-exhaustive; missing: Some false, None [non-exhaustive-match]",
+                "match arm 2 can never match: earlier arms cover it [unreachable-arm]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_matches_reports_lines_and_declared_types() {
+        let source = "type Shape = Circle Int | Square Int | Dot in\nlet area = function\n  | Circle r -> r * r\n  | Square s -> s * s in\nmatch area (Circle 2) with\n| n -> n\n| 0 -> 0";
+        let expr = crate::parser::parse(source).unwrap();
+        let warnings = check_matches(&expr, source, &Environment::with_builtins());
+        let rendered: Vec<String> = warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            rendered,
+            ["match on line 2 is non-exhaustive, missing: Dot", "match on line 5 has an unreachable arm 2: earlier arms cover it"]
+        );
+        // Without the source the matches are still checked, just not placed
+        let warnings = check_matches(&expr, "", &Environment::with_builtins());
+        assert_eq!(warnings[0], MatchWarning { line: None, problem: MatchProblem::NonExhaustive(vec![Pattern::Constructor("Dot".to_string(), vec![])]) });
+        assert_eq!(warnings[1].to_string(), "match has an unreachable arm 2: earlier arms cover it");
+    }
+
+    #[test]
+    fn test_guarded_arms_cover_nothing() {
+        let env = Environment::new();
+        let var = || Pattern::Var("n".to_string());
+        let result = check_guarded_exhaustiveness(&[(var(), true)], &env);
+        assert!(!result.is_exhaustive());
+        assert_eq!(result.missing, [Pattern::Wildcard]);
+        assert!(check_guarded_exhaustiveness(&[(var(), true), (Pattern::Wildcard, false)], &env).is_exhaustive());
+        // A guarded arm after a catch-all is still unreachable, and one before it is not
+        let result = check_guarded_exhaustiveness(&[(var(), false), (var(), true)], &env);
+        assert_eq!(result.redundant, [1]);
+        let result = check_guarded_exhaustiveness(&[(var(), true), (var(), true), (var(), false)], &env);
+        assert!(result.is_exhaustive() && result.redundant.is_empty());
+
+        let source = "match true with\n| b when b -> 1\n| false -> 0";
+        let warnings = check_matches(&crate::parser::parse(source).unwrap(), source, &env);
+        assert_eq!(warnings.iter().map(ToString::to_string).collect::<Vec<_>>(), ["match on line 1 is non-exhaustive, missing: true"]);
     }
 
     #[test]
@@ -0,0 +1,127 @@
+//! Versions of the files parlang writes for tools and later sessions
+//!
+//! Every artifact meant to be read back — REPL transcripts and the JSON
+//! language summary — carries a top-level `"parlang_format"` field holding
+//! the [`FORMAT_VERSION`] it was written with. Readers pass the version they
+//! find to [`check_version`], which rejects artifacts written by a newer
+//! parlang, and then bring older artifacts up to date with [`migrate`].
+//! Artifacts written before versioning existed have no field and count as
+//! version 0.
+
+use crate::transcript::json_string;
+use std::fmt;
+
+/// Version of the artifact formats written by this build
+///
+/// Bump it whenever an artifact changes in a way older readers would
+/// misread, and add the matching migration to every reader.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Name of the field holding the format version of an artifact
+pub const FORMAT_FIELD: &str = "parlang_format";
+
+/// An artifact that this build cannot read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatError {
+    /// What was being read, e.g. `transcript`
+    pub artifact: String,
+    /// Format version the artifact was written with
+    pub found: u32,
+    /// Newest format version this build reads
+    pub supported: u32,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} has format version {}, but parlang {} reads format versions up to {}; upgrade parlang to read it",
+            self.artifact,
+            self.found,
+            env!("CARGO_PKG_VERSION"),
+            self.supported
+        )
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Check that an artifact's format version can be read, treating a missing version as 0
+///
+/// # Errors
+///
+/// Returns a [`FormatError`] naming both versions when the artifact was
+/// written with a newer format than [`FORMAT_VERSION`].
+pub fn check_version(artifact: &str, found: Option<u32>) -> Result<u32, FormatError> {
+    let found = found.unwrap_or(0);
+    if found > FORMAT_VERSION {
+        return Err(FormatError { artifact: artifact.to_string(), found, supported: FORMAT_VERSION });
+    }
+    Ok(found)
+}
+
+/// Bring an artifact read at version `found` up to [`FORMAT_VERSION`]
+///
+/// `steps[v]` turns an artifact of version `v` into one of version `v + 1`,
+/// so a reader needs exactly one step per version before the current one.
+/// `found` must have passed [`check_version`].
+pub fn migrate<T>(found: u32, artifact: T, steps: &[fn(T) -> T; FORMAT_VERSION as usize]) -> T {
+    steps[found as usize..].iter().fold(artifact, |artifact, step| step(artifact))
+}
+
+/// Cargo features this build was compiled with
+pub fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "config") {
+        features.push("config");
+    }
+    if cfg!(feature = "serde") {
+        features.push("serde");
+    }
+    features
+}
+
+/// Describe this build as a JSON object, for tools to detect what it supports
+///
+/// Printed by `parlang --version --json`.
+pub fn version_json() -> String {
+    let features: Vec<String> = enabled_features().into_iter().map(json_string).collect();
+    format!(
+        "{{\"version\": {}, \"{FORMAT_FIELD}\": {FORMAT_VERSION}, \"features\": [{}]}}",
+        json_string(env!("CARGO_PKG_VERSION")),
+        features.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        assert_eq!(check_version("transcript", None), Ok(0));
+        assert_eq!(check_version("transcript", Some(FORMAT_VERSION)), Ok(FORMAT_VERSION));
+        let error = check_version("transcript", Some(FORMAT_VERSION + 1)).unwrap_err();
+        assert_eq!(error, FormatError { artifact: "transcript".to_string(), found: FORMAT_VERSION + 1, supported: FORMAT_VERSION });
+        assert!(error.to_string().starts_with(&format!("transcript has format version {}, but parlang ", FORMAT_VERSION + 1)));
+    }
+
+    #[test]
+    fn test_migrate_runs_the_steps_after_the_found_version() {
+        fn step(mut seen: Vec<usize>) -> Vec<usize> {
+            seen.push(seen.len());
+            seen
+        }
+        let steps: [fn(Vec<usize>) -> Vec<usize>; FORMAT_VERSION as usize] = [step; FORMAT_VERSION as usize];
+        assert_eq!(migrate(0, Vec::new(), &steps).len(), FORMAT_VERSION as usize);
+        assert!(migrate(FORMAT_VERSION, Vec::new(), &steps).is_empty());
+    }
+
+    #[test]
+    fn test_version_json_lists_enabled_features() {
+        let json = version_json();
+        assert!(json.starts_with(&format!("{{\"version\": \"{}\", \"parlang_format\": 1, ", env!("CARGO_PKG_VERSION"))), "{json}");
+        assert_eq!(json.contains("\"config\""), cfg!(feature = "config"));
+        assert_eq!(json.contains("\"serde\""), cfg!(feature = "serde"));
+    }
+}
@@ -0,0 +1,197 @@
+//! A session of programs evaluated one after another
+//!
+//! An [`Interpreter`] owns the environment its programs run in. Top-level
+//! bindings a program defines with [`eval_str`](Interpreter::eval_str) stay
+//! visible to later programs, and every program shares the environment's
+//! library cache: a library is read and parsed the first time it is loaded
+//! and reused by later loads, in this program or another, until
+//! [`reload`](Interpreter::reload) forgets it. The REPL and `parlang <file>`
+//! both evaluate through an interpreter.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::Interpreter;
+//!
+//! let mut interpreter = Interpreter::new();
+//! interpreter.eval_str("let double = fun x -> x * 2;").unwrap();
+//! assert_eq!(interpreter.eval_str("double 21").unwrap().to_string(), "42");
+//! ```
+
+use crate::ast::Expr;
+use crate::eval::{eval, eval_toplevel, eval_with_spans, reimport_library, Environment, EvalError, Value};
+use crate::parser::{parse, ParseErrorInfo};
+use crate::span::Span;
+use std::fmt;
+
+/// Evaluates programs in an environment that persists between them
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    env: Environment,
+}
+
+/// Why [`Interpreter::eval_str`] failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpreterError {
+    /// The program does not parse
+    Parse(ParseErrorInfo),
+    /// The program failed while evaluating
+    Eval(EvalError),
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterpreterError::Parse(e) => write!(f, "{e}"),
+            InterpreterError::Eval(e) => write!(f, "Evaluation error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Create an interpreter with the prelude built-ins bound
+    pub fn new() -> Self {
+        Self::with_environment(Environment::with_builtins())
+    }
+
+    /// Create an interpreter that evaluates in `env`, keeping its bindings and settings
+    pub fn with_environment(env: Environment) -> Self {
+        Interpreter { env }
+    }
+
+    /// The environment programs are evaluated in
+    pub fn environment(&self) -> &Environment {
+        &self.env
+    }
+
+    /// The environment programs are evaluated in, for changing its settings or bindings
+    pub fn environment_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    /// Evaluate a program without keeping its bindings
+    ///
+    /// # Errors
+    ///
+    /// Returns the evaluation error.
+    pub fn eval(&self, expr: &Expr) -> Result<Value, EvalError> {
+        eval(expr, &self.env)
+    }
+
+    /// Evaluate a parsed program without keeping its bindings, reporting errors at their spans
+    ///
+    /// `spans` are those [`parse_program_with_spans`](crate::parser::parse_program_with_spans)
+    /// returned with `expr`; see [`eval_with_spans`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the evaluation error.
+    pub fn eval_with_spans(&self, expr: &Expr, spans: &[Option<Span>]) -> Result<Value, EvalError> {
+        eval_with_spans(expr, spans, &self.env)
+    }
+
+    /// Evaluate a parsed program, keeping its top-level bindings for later programs
+    ///
+    /// Each binding of the program's spine (see [`eval_toplevel`]) is
+    /// evaluated once, so its side effects happen once.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error evaluating the program or one of its bindings; the
+    /// environment is then left as it was.
+    pub fn eval_toplevel(&mut self, expr: &Expr) -> Result<Value, EvalError> {
+        let (value, env) = eval_toplevel(expr, &self.env)?;
+        self.env = env;
+        Ok(value)
+    }
+
+    /// Parse and evaluate a program, keeping its top-level bindings for later programs
+    ///
+    /// # Errors
+    ///
+    /// Returns the parse error, or the error evaluating the program or one
+    /// of its bindings; the environment is left as it was.
+    pub fn eval_str(&mut self, source: &str) -> Result<Value, InterpreterError> {
+        let expr = parse(source).map_err(InterpreterError::Parse)?;
+        self.eval_toplevel(&expr).map_err(InterpreterError::Eval)
+    }
+
+    /// Forget the cached libraries, so the next load of each reads its file again
+    pub fn reload(&mut self) {
+        self.env.clear_load_cache();
+    }
+
+    /// Forget the cached libraries and load the library at `filepath` again
+    ///
+    /// Its bindings replace the ones an earlier load of it made, which a
+    /// plain `load` would keep.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error loading the library; the environment is then left as it was.
+    pub fn reload_library(&mut self, filepath: &str) -> Result<(), EvalError> {
+        self.reload();
+        self.env = reimport_library(filepath, &self.env)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_eval_str_keeps_bindings() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_str("let x = 20; x + 1"), Ok(Value::Int(21)));
+        assert_eq!(interpreter.eval_str("x * 2"), Ok(Value::Int(40)));
+        assert!(matches!(interpreter.eval_str("let = 1"), Err(InterpreterError::Parse(_))));
+        // A failing program leaves the bindings as they were
+        assert!(matches!(interpreter.eval_str("let x = 1 / 0; x"), Err(InterpreterError::Eval(_))));
+        assert_eq!(interpreter.eval_str("x"), Ok(Value::Int(20)));
+    }
+
+    #[test]
+    fn test_bindings_are_evaluated_once() {
+        let mut interpreter = Interpreter::new();
+        interpreter.eval_str("let count = ref 0; let tick = fun u -> count := !count + 1;").unwrap();
+        assert_eq!(interpreter.eval_str("let a = tick 0; let b = tick 0; !count"), Ok(Value::Int(2)));
+        assert_eq!(interpreter.eval_str("!count"), Ok(Value::Int(2)));
+    }
+
+    #[test]
+    fn test_libraries_are_cached_until_reload() {
+        let path = std::env::temp_dir().join(format!("test_interpreter_cache_{}.par", std::process::id()));
+        fs::write(&path, "let version = 1;").unwrap();
+        let program = format!("load \"{}\" as Lib in Lib.version", path.display());
+
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_str(&program), Ok(Value::Int(1)));
+        assert_eq!(interpreter.environment().cached_libraries(), vec![fs::canonicalize(&path).unwrap()]);
+
+        // The file changes, but later loads reuse what was read the first time
+        fs::write(&path, "let version = 2;").unwrap();
+        assert_eq!(interpreter.eval_str(&program), Ok(Value::Int(1)));
+        interpreter.reload();
+        assert!(interpreter.environment().cached_libraries().is_empty());
+        assert_eq!(interpreter.eval_str(&program), Ok(Value::Int(2)));
+
+        // Reloading replaces the bindings an earlier load made
+        interpreter.eval_str(&format!("load \"{}\" in 0", path.display())).unwrap();
+        fs::write(&path, "let version = 3;").unwrap();
+        assert_eq!(interpreter.eval_str("version"), Ok(Value::Int(2)));
+        interpreter.reload_library(path.to_str().unwrap()).unwrap();
+        assert_eq!(interpreter.eval_str("version"), Ok(Value::Int(3)));
+
+        fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,216 @@
+//! Machine-readable summary of the language surface, for editor tooling
+//!
+//! [`language_spec`] lists the keywords, infix operators, prelude built-ins
+//! and literal forms of the language. Everything but the literal forms is
+//! read from the tables the implementation itself runs on: [`KEYWORDS`] and
+//! [`OPERATORS`] drive the parser, and the built-ins and their types come
+//! from the prelude registry shared by the evaluator and the type checker.
+//! The summary therefore cannot drift from the language it describes.
+//!
+//! `parlang language-spec --json` prints [`LanguageSpec::to_json`], which
+//! needs no optional features; without `--json` it prints the same
+//! information as text. With the `serde` feature the summary is also
+//! `Serialize`.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::language_spec::language_spec;
+//!
+//! let spec = language_spec();
+//! assert!(spec.keywords.contains(&"match"));
+//! let plus = spec.operators.iter().find(|op| op.symbol == "+").unwrap();
+//! let times = spec.operators.iter().find(|op| op.symbol == "*").unwrap();
+//! assert!(times.precedence > plus.precedence);
+//! ```
+
+use crate::builtins::prelude_schemes;
+use crate::format::FORMAT_VERSION;
+use crate::parser::{Associativity, KEYWORDS, OPERATORS};
+use crate::transcript::json_string;
+use crate::typechecker::canonicalize;
+use std::fmt;
+
+/// Keywords, operators, built-ins and literal forms of the language
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LanguageSpec {
+    /// Reserved words, which cannot be used as identifiers
+    pub keywords: Vec<&'static str>,
+    /// Infix operators, from lowest to highest precedence
+    pub operators: Vec<OperatorSpec>,
+    /// Precedence of function application, higher than every infix operator
+    pub application_precedence: usize,
+    /// Prelude built-ins, in registration order
+    pub builtins: Vec<BuiltinSpec>,
+    /// Literal syntax, with examples
+    pub literals: Vec<LiteralSpec>,
+}
+
+/// An infix operator
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OperatorSpec {
+    pub symbol: &'static str,
+    /// 1 for the loosest-binding operators; higher binds tighter
+    pub precedence: usize,
+    pub associativity: Associativity,
+}
+
+/// A prelude built-in function
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BuiltinSpec {
+    pub name: String,
+    /// The type as `:type` prints it, e.g. `Int -> Int -> Int`
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub ty: String,
+}
+
+/// A form of literal
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LiteralSpec {
+    pub kind: &'static str,
+    /// Source text of literals of this kind
+    pub examples: &'static [&'static str],
+}
+
+/// Literal forms accepted by the parser
+const LITERALS: &[LiteralSpec] = &[
+    LiteralSpec { kind: "Int", examples: &["42", "-7"] },
+    LiteralSpec { kind: "Float", examples: &["3.14", "-0.5"] },
+    LiteralSpec { kind: "Byte", examples: &["255b"] },
+    LiteralSpec { kind: "Bool", examples: &["true", "false"] },
+    LiteralSpec { kind: "Char", examples: &["'a'", "'\\n'"] },
+    LiteralSpec { kind: "String", examples: &["\"hello\\n\"", "\"x = {x}\""] },
+    LiteralSpec { kind: "Unit", examples: &["()"] },
+    LiteralSpec { kind: "Tuple", examples: &["(1, true)"] },
+    LiteralSpec { kind: "Record", examples: &["{ name: \"Ada\", age: 36 }"] },
+    LiteralSpec { kind: "Array", examples: &["[|1, 2, 3|]"] },
+    LiteralSpec { kind: "List", examples: &["[1, 2, 3]", "[]"] },
+];
+
+/// Collect the language surface from the parser and prelude tables
+pub fn language_spec() -> LanguageSpec {
+    let operators = OPERATORS
+        .iter()
+        .zip(1..)
+        .flat_map(|(level, precedence)| {
+            level.operators.iter().map(move |op| OperatorSpec {
+                symbol: op.symbol,
+                precedence,
+                associativity: level.associativity,
+            })
+        })
+        .collect();
+    let builtins = prelude_schemes()
+        .into_iter()
+        .map(|(name, scheme)| BuiltinSpec { name, ty: canonicalize(&scheme).ty.to_string() })
+        .collect();
+    LanguageSpec {
+        keywords: KEYWORDS.to_vec(),
+        operators,
+        application_precedence: OPERATORS.len() + 1,
+        builtins,
+        literals: LITERALS.to_vec(),
+    }
+}
+
+impl LanguageSpec {
+    /// Encode the summary as a JSON object
+    ///
+    /// The first key is the format version (see [`crate::format`]), followed
+    /// by the fields in declaration order, one per line, so the output only
+    /// changes when the language does and diffs of it stay readable.
+    pub fn to_json(&self) -> String {
+        let strings = |items: &[&str], indent| json_list(items.iter().map(|item| json_string(item)).collect(), indent);
+        let operators = self
+            .operators
+            .iter()
+            .map(|op| {
+                let associativity = match op.associativity {
+                    Associativity::Left => "left",
+                    Associativity::Right => "right",
+                    Associativity::None => "none",
+                };
+                json_object(
+                    &[
+                        ("symbol", json_string(op.symbol)),
+                        ("precedence", op.precedence.to_string()),
+                        ("associativity", json_string(associativity)),
+                    ],
+                    4,
+                )
+            })
+            .collect();
+        let builtins = self
+            .builtins
+            .iter()
+            .map(|builtin| json_object(&[("name", json_string(&builtin.name)), ("type", json_string(&builtin.ty))], 4))
+            .collect();
+        let literals = self
+            .literals
+            .iter()
+            .map(|literal| json_object(&[("kind", json_string(literal.kind)), ("examples", strings(literal.examples, 6))], 4))
+            .collect();
+        json_object(
+            &[
+                ("parlang_format", FORMAT_VERSION.to_string()),
+                ("keywords", strings(&self.keywords, 2)),
+                ("operators", json_list(operators, 2)),
+                ("application_precedence", self.application_precedence.to_string()),
+                ("builtins", json_list(builtins, 2)),
+                ("literals", json_list(literals, 2)),
+            ],
+            0,
+        )
+    }
+}
+
+/// A JSON array of already encoded items, one per line, its closing bracket
+/// indented by `indent` spaces
+fn json_list(items: Vec<String>, indent: usize) -> String {
+    if items.is_empty() {
+        return "[]".to_string();
+    }
+    let items: Vec<String> = items.into_iter().map(|item| format!("{:indent$}{item}", "", indent = indent + 2)).collect();
+    format!("[\n{}\n{:indent$}]", items.join(",\n"), "")
+}
+
+/// A JSON object of already encoded values, one field per line, its closing
+/// brace indented by `indent` spaces
+fn json_object(fields: &[(&str, String)], indent: usize) -> String {
+    let fields: Vec<String> =
+        fields.iter().map(|(key, value)| format!("{:indent$}\"{key}\": {value}", "", indent = indent + 2)).collect();
+    format!("{{\n{}\n{:indent$}}}", fields.join(",\n"), "")
+}
+
+/// Renders the summary as plain text, one section per table
+impl fmt::Display for LanguageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Keywords: {}", self.keywords.join(" "))?;
+        writeln!(f)?;
+        writeln!(f, "Operators (lowest precedence first):")?;
+        for op in &self.operators {
+            let grouping = match op.associativity {
+                Associativity::Left => "left-associative",
+                Associativity::Right => "right-associative",
+                Associativity::None => "non-associative",
+            };
+            writeln!(f, "  {:<3} precedence {}, {grouping}", op.symbol, op.precedence)?;
+        }
+        writeln!(f, "  function application: precedence {}", self.application_precedence)?;
+        writeln!(f)?;
+        writeln!(f, "Built-ins:")?;
+        for builtin in &self.builtins {
+            writeln!(f, "  {} : {}", builtin.name, builtin.ty)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Literals:")?;
+        for literal in &self.literals {
+            writeln!(f, "  {:<7} {}", literal.kind, literal.examples.join("  "))?;
+        }
+        Ok(())
+    }
+}
@@ -10,6 +10,12 @@
 /// 
 /// # Example
 /// 
+/// ```parlang
+/// let x = 42 in x + 1 -- => 43
+/// ```
+/// 
+/// evaluated from Rust:
+/// 
 /// ```
 /// use parlang::{parse, eval, Environment};
 /// 
@@ -17,31 +23,105 @@
 /// let expr = parse(program).expect("Parse error");
 /// let env = Environment::new();
 /// let result = eval(&expr, &env).expect("Evaluation error");
-/// println!("Result: {}", result); // prints "Result: 43"
+/// assert_eq!(result.to_string(), "43");
 /// ```
 /// 
 /// # Type Checking Example
 /// 
+/// ```parlang
+/// fun x -> x + 1 -- : Int -> Int
+/// ```
+/// 
+/// checked from Rust:
+/// 
 /// ```
 /// use parlang::{parse, typecheck};
 /// 
 /// let program = "fun x -> x + 1";
 /// let expr = parse(program).expect("Parse error");
 /// let ty = typecheck(&expr).expect("Type error");
-/// println!("Type: {}", ty); // prints "Type: Int -> Int"
+/// assert_eq!(ty.to_string(), "Int -> Int");
+/// ```
+/// 
+/// # Embedding Example
+/// 
+/// Rust values are bound with `bind_value` and host functions with
+/// `bind_fn`; results are read back with `TryFrom` (see [`convert`]):
+/// 
 /// ```
+/// use parlang::{eval, parse, Environment, EvalError, Value};
+/// 
+/// let mut env = Environment::with_builtins();
+/// env.bind_value("prices", vec![120, 80, 45]);
+/// env.bind_fn("discount", |args| {
+///     let (price, percent): (i64, i64) = args.try_into()?;
+///     Ok(Value::Int(price - price * percent / 100))
+/// });
+/// let script = "let sum = rec sum -> fun xs -> match xs with | [] -> 0 | x :: rest -> x + sum rest in \
+///               let total = sum prices in (total, discount (total, 10))";
+/// let result = eval(&parse(script).unwrap(), &env).unwrap();
+/// let (total, discounted): (i64, i64) = result.try_into().unwrap();
+/// assert_eq!((total, discounted), (245, 221));
+/// 
+/// // Errors from host functions end the evaluation
+/// let error = eval(&parse("discount 5").unwrap(), &env).unwrap_err();
+/// assert!(matches!(error.root(), EvalError::TypeError(_)));
+/// ```
+/// 
+/// `parlang` snippets in doc comments are checked by `tests/doc_snippet_tests.rs`
+/// (see [`crate::snippets`]).
 pub mod ast;
 pub mod parser;
+pub mod core;
 pub mod eval;
+pub mod span;
+pub mod convert;
+mod bindings;
 pub mod dot;
 pub mod types;
 pub mod typechecker;
 pub mod exhaustiveness;
+pub mod subst;
+pub mod repl;
+pub mod interpreter;
+pub mod builtins;
+pub mod describe;
+pub mod suggest;
+pub mod prune;
+pub mod load_path;
+pub mod optimize;
+pub mod transcript;
+pub mod check;
+pub mod snippets;
+pub mod lint;
+pub mod config;
+pub mod stream;
+pub mod language_spec;
+pub mod profile;
+pub mod retention;
+pub mod numeric_rules;
+pub mod resolve;
+pub mod format;
+pub mod pretty;
+#[cfg(feature = "serde")]
+pub mod ast_json;
 
 // Re-export commonly used types and functions
 pub use ast::{Expr, BinOp};
-pub use parser::parse;
-pub use eval::{eval, extract_bindings, Value, Environment, EvalError};
+pub use parser::{is_blank_source, parse, parse_pattern, parse_program_with_options, parse_program_with_positions, parse_program_with_spans, BindingPosition, ParseErrorInfo, ParseOptions};
+pub use span::Span;
+pub use eval::{eval, eval_with_config, eval_with_limits, eval_with_spans, eval_toplevel, extract_bindings, match_value, Fields, Value, Environment, EvalConfig, EvalError, EvalLimits, DisplayOptions, MAX_TRACE_FRAMES};
 pub use types::{Type, TypeScheme, TypeVar, RowVar};
-pub use typechecker::{typecheck, TypeError, TypeEnv};
-pub use exhaustiveness::{check_exhaustiveness, ExhaustivenessResult};
+pub use typechecker::{typecheck, typecheck_equalities, typecheck_equalities_with_spans, typecheck_in, typecheck_nodes, typecheck_toplevel, typecheck_with_spans, EqualityCheck, TypeError, TypeEnv};
+pub use exhaustiveness::{check_exhaustiveness, check_guarded_exhaustiveness, check_matches, ExhaustivenessResult, MatchProblem, MatchWarning};
+pub use subst::{substitute, free_vars, fresh_name};
+pub use repl::{ReplSession, TypeCheckMode};
+pub use interpreter::{Interpreter, InterpreterError};
+pub use convert::{FromParValue, ToParValue};
+pub use describe::describe_environment;
+pub use prune::{plan_pruning, plan_pruning_from, PrunePlan};
+pub use load_path::LoadPath;
+pub use language_spec::{language_spec, LanguageSpec};
+pub use format::{FormatError, FORMAT_VERSION};
+#[cfg(feature = "serde")]
+pub use ast_json::{expr_from_json, expr_to_json, expr_to_json_with_limit, parse_to_json};
@@ -0,0 +1,418 @@
+//! Lints: warnings about code that is probably a mistake
+//!
+//! [`lint`] runs on the results of type inference; [`check`] only looks at
+//! which names are bound where, so it works on programs that do not type
+//! check. Neither rejects a program. Each [`Lint`] names the rule that
+//! produced it.
+//!
+//! # Rules
+//!
+//! - `float-equality`: `==` or `!=` on operands inferred to be Float. Rounding
+//!   makes exact float comparison fragile (`0.1 + 0.2 == 0.3` is false), so
+//!   the warning suggests the `approx_eq` built-in or comparing against a
+//!   tolerance instead.
+//! - `unbound-variable`: a variable no enclosing `let`, `fun`, `rec`, `match`
+//!   arm or `catch` binds, and that is not a built-in. Names used in the body
+//!   of a `load` without `as` may come from the library and are not reported.
+//! - `unused-binding`: a `let`, `fun` parameter or `match` pattern variable
+//!   the code in its scope never uses. Names starting with `_` are exempt, as
+//!   are the top-level bindings of a program without a body, which is a
+//!   library.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::{lint, parse, TypeEnv};
+//!
+//! let expr = parse("let x = 0.1 + 0.2 in x == 0.3").unwrap();
+//! let lints = lint::lint(&expr, &mut TypeEnv::with_builtins()).unwrap();
+//! assert_eq!(lints[0].rule, "float-equality");
+//!
+//! let expr = parse("let lenght = fun xs -> 0 in length [1]").unwrap();
+//! let lints: Vec<String> = lint::check(&expr).iter().map(ToString::to_string).collect();
+//! assert_eq!(lints, [
+//!     "Unused parameter 'xs'; prefix it with '_' if that is intended [unused-binding]",
+//!     "Unbound variable: length (did you mean `lenght`?) [unbound-variable]",
+//!     "Unused let binding 'lenght'; prefix it with '_' if that is intended [unused-binding]",
+//! ]);
+//! ```
+
+use crate::ast::Expr;
+use crate::builtins::prelude_values;
+use crate::span::Span;
+use crate::subst::pattern_vars;
+use crate::suggest::{hint, suggest};
+use crate::typechecker::{typecheck_equalities_with_spans, EqualityCheck, TypeEnv, TypeError};
+use crate::types::Type;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// Names of all lint rules
+pub const RULES: &[&str] = &["float-equality", "unbound-variable", "unused-binding"];
+
+/// A warning produced by a lint rule
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    /// Name of the rule, e.g. `float-equality`
+    pub rule: &'static str,
+    /// What is suspicious and what to do instead
+    pub message: String,
+    /// The variable the warning is about, for the rules about names
+    pub name: Option<String>,
+    /// Where the warning applies, when [`check_with`] was given the spans
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+/// Type check `expr` and run every lint rule over it
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed; lints need
+/// inferred types, so nothing is reported for ill-typed programs.
+pub fn lint(expr: &Expr, env: &mut TypeEnv) -> Result<Vec<Lint>, Box<TypeError>> {
+    lint_with(expr, &[], env)
+}
+
+/// [`lint`], reporting each warning at its node
+///
+/// `spans` are the spans of the nodes of `expr`, as for [`check_with`]. A
+/// float comparison is reported at the `==` or `!=` expression.
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn lint_with(expr: &Expr, spans: &[Option<Span>], env: &mut TypeEnv) -> Result<Vec<Lint>, Box<TypeError>> {
+    let (_, equalities) = typecheck_equalities_with_spans(expr, spans, env).map_err(Box::new)?;
+    Ok(float_equality(&equalities))
+}
+
+/// `float-equality`: comparisons of Float operands with `==` or `!=`
+pub fn float_equality(equalities: &[EqualityCheck]) -> Vec<Lint> {
+    equalities
+        .iter()
+        .filter(|check| check.operand == Type::Float)
+        .map(|check| Lint {
+            rule: "float-equality",
+            message: format!(
+                "{} compares Floats exactly; consider approx_eq a b epsilon or comparing against a tolerance",
+                check.expr
+            ),
+            name: None,
+            span: check.span,
+        })
+        .collect()
+}
+
+/// What [`check_with`] takes as bound and which rules it runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckOptions {
+    /// Names bound around the program, such as the built-ins
+    pub globals: HashSet<String>,
+    /// Report `unbound-variable`
+    pub unbound_variables: bool,
+    /// Report `unused-binding`
+    pub unused_bindings: bool,
+}
+
+impl Default for CheckOptions {
+    /// Both rules, with the prelude built-ins bound
+    fn default() -> Self {
+        CheckOptions {
+            globals: prelude_values().into_iter().map(|(name, _)| name).collect(),
+            unbound_variables: true,
+            unused_bindings: true,
+        }
+    }
+}
+
+/// `unbound-variable` and `unused-binding` for `expr`, with the prelude built-ins bound
+///
+/// No types are needed, so this works on programs that do not type check.
+pub fn check(expr: &Expr) -> Vec<Lint> {
+    check_with(expr, &[], &CheckOptions::default())
+}
+
+/// The name-based rules `options` enables for `expr`
+///
+/// `spans` are the spans of the nodes of `expr`, as
+/// [`parse_program_with_spans`](crate::parser::parse_program_with_spans)
+/// returns them, or empty. An unbound variable is reported at the variable,
+/// an unused parameter at its function, an unused `let` at the bound value
+/// and an unused pattern variable at its arm. Warnings come in the
+/// order the walk finds them: an unbound variable where it is used, an
+/// unused name when its scope ends.
+pub fn check_with(expr: &Expr, spans: &[Option<Span>], options: &CheckOptions) -> Vec<Lint> {
+    let mut checker = Checker {
+        options,
+        spans: expr.subexpressions().zip(spans).filter_map(|(node, span)| Some((std::ptr::from_ref(node), (*span)?))).collect(),
+        scope: Vec::new(),
+        opaque: 0,
+        lints: Vec::new(),
+    };
+    match expr {
+        // The top-level bindings of a library are what it exports
+        Expr::Seq(bindings, body) if matches!(**body, Expr::EmptyBody) => {
+            for (name, _, value) in bindings {
+                checker.expr(value);
+                checker.bind(name, BindingKind::Exempt, value);
+            }
+        }
+        _ => checker.expr(expr),
+    }
+    checker.lints
+}
+
+/// What introduced a name, as `unused-binding` calls it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    Let,
+    Parameter,
+    PatternVariable,
+    /// Names `unused-binding` does not report: `rec` and `catch` names,
+    /// namespaces and a library's top-level bindings
+    Exempt,
+}
+
+/// A name in scope during [`check_with`]'s walk
+struct Binding<'a> {
+    name: String,
+    kind: BindingKind,
+    /// Where an unused name is reported: the function that takes a
+    /// parameter, the value of a `let`, or the arm of a pattern variable
+    site: &'a Expr,
+    used: bool,
+}
+
+struct Checker<'a> {
+    options: &'a CheckOptions,
+    spans: HashMap<*const Expr, Span>,
+    /// Innermost binding last
+    scope: Vec<Binding<'a>>,
+    /// How many `load`s without `as` the walk is inside; their libraries may bind any name
+    opaque: usize,
+    lints: Vec<Lint>,
+}
+
+impl<'a> Checker<'a> {
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            Expr::Var(name) => self.use_name(name, expr),
+            Expr::Let(name, _, value, body) => {
+                self.expr(value);
+                self.scoped(&[(name, BindingKind::Let)], value, body);
+            }
+            Expr::Fun(name, _, body) => self.scoped(&[(name, BindingKind::Parameter)], expr, body),
+            Expr::Rec(name, body) | Expr::LoadAs(_, name, body) => self.scoped(&[(name, BindingKind::Exempt)], expr, body),
+            Expr::Try(body, name, handler) => {
+                self.expr(body);
+                self.scoped(&[(name, BindingKind::Exempt)], expr, handler);
+            }
+            Expr::Seq(bindings, body) => {
+                for (name, _, value) in bindings {
+                    self.expr(value);
+                    self.bind(name, BindingKind::Let, value);
+                }
+                self.expr(body);
+                self.unbind(bindings.len());
+            }
+            Expr::Match(scrutinee, arms) => {
+                self.expr(scrutinee);
+                for (pattern, guard, result) in arms {
+                    let vars = pattern_vars(pattern);
+                    for name in &vars {
+                        self.bind(name, BindingKind::PatternVariable, result);
+                    }
+                    if let Some(guard) = guard {
+                        self.expr(guard);
+                    }
+                    self.expr(result);
+                    self.unbind(vars.len());
+                }
+            }
+            Expr::Load(_, body) => {
+                self.opaque += 1;
+                self.expr(body);
+                self.opaque -= 1;
+            }
+            _ => {
+                for child in expr.children() {
+                    self.expr(child);
+                }
+            }
+        }
+    }
+
+    /// Walk `body` with `names` bound, reporting unused ones at `site`
+    fn scoped(&mut self, names: &[(&str, BindingKind)], site: &'a Expr, body: &'a Expr) {
+        for (name, kind) in names {
+            self.bind(name, *kind, site);
+        }
+        self.expr(body);
+        self.unbind(names.len());
+    }
+
+    fn bind(&mut self, name: &str, kind: BindingKind, site: &'a Expr) {
+        self.scope.push(Binding { name: name.to_string(), kind, site, used: false });
+    }
+
+    /// End the scope of the `count` innermost bindings, reporting those never used
+    fn unbind(&mut self, count: usize) {
+        let ended = self.scope.split_off(self.scope.len() - count);
+        if !self.options.unused_bindings {
+            return;
+        }
+        for binding in ended.into_iter().rev() {
+            let what = match binding.kind {
+                BindingKind::Let => "let binding",
+                BindingKind::Parameter => "parameter",
+                BindingKind::PatternVariable => "pattern variable",
+                BindingKind::Exempt => continue,
+            };
+            if binding.used || binding.name.starts_with('_') {
+                continue;
+            }
+            self.lints.push(Lint {
+                rule: "unused-binding",
+                message: format!("Unused {what} '{}'; prefix it with '_' if that is intended", binding.name),
+                name: Some(binding.name),
+                span: self.spans.get(&std::ptr::from_ref(binding.site)).copied(),
+            });
+        }
+    }
+
+    fn use_name(&mut self, name: &str, var: &Expr) {
+        if let Some(binding) = self.scope.iter_mut().rev().find(|binding| binding.name == name) {
+            binding.used = true;
+            return;
+        }
+        if self.opaque > 0 || !self.options.unbound_variables || self.options.globals.contains(name) {
+            return;
+        }
+        let in_scope = self.scope.iter().map(|binding| binding.name.as_str()).chain(self.options.globals.iter().map(String::as_str));
+        self.lints.push(Lint {
+            rule: "unbound-variable",
+            message: format!("Unbound variable: {name}{}", hint(&suggest(name, in_scope))),
+            name: Some(name.to_string()),
+            span: self.spans.get(&std::ptr::from_ref(var)).copied(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn lints(source: &str) -> Vec<Lint> {
+        lint(&parse(source).unwrap(), &mut TypeEnv::with_builtins()).unwrap()
+    }
+
+    #[test]
+    fn test_float_comparisons_are_flagged() {
+        let found = lints("0.1 + 0.2 == 0.3");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].rule, "float-equality");
+        assert_eq!(
+            found[0].message,
+            "((0.1 + 0.2) == 0.3) compares Floats exactly; consider approx_eq a b epsilon or comparing against a tolerance"
+        );
+        assert_eq!(lints("fun x -> x == 1.0")[0].message.split(" compares").next(), Some("(x == 1.0)"));
+
+        // Operands only later known to be Float, and `!=`
+        assert_eq!(lints("let y = 2.5 in fun z -> z != y").len(), 1);
+        assert_eq!(lints("fun x -> if x == x then x * 2.0 else 0.0").len(), 1);
+        assert_eq!(lints("if 1.0 == 2.0 then 3.0 != 4.0 else false").len(), 2);
+
+        let source = "let x = 0.5 in\nif x == 1.0 then 1 else 2";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        let span = lint_with(&expr, &spans, &mut TypeEnv::with_builtins()).unwrap()[0].span.unwrap();
+        assert_eq!(&source[span.start..span.end], "x == 1.0");
+    }
+
+    #[test]
+    fn test_other_comparisons_are_not_flagged() {
+        assert_eq!(lints("1 + 2 == 3"), vec![]);
+        assert_eq!(lints("if 'a' == 'b' then true != false else false"), vec![]);
+        assert_eq!(lints("1.0 < 2.0"), vec![]);
+        assert_eq!(lints("approx_eq (0.1 + 0.2) 0.3 0.000001"), vec![]);
+        // A polymorphic comparison is not known to be on Floats
+        assert_eq!(lints("let same = fun a -> fun b -> a == b in same 1.5 2.5"), vec![]);
+    }
+
+    #[test]
+    fn test_ill_typed_programs_are_not_linted() {
+        assert!(lint(&parse("1 == true").unwrap(), &mut TypeEnv::with_builtins()).is_err());
+    }
+
+    /// The rule and name of each warning `check` gives for `source`
+    fn checked(source: &str) -> Vec<(&'static str, String)> {
+        check(&parse(source).unwrap()).into_iter().map(|lint| (lint.rule, lint.name.unwrap())).collect()
+    }
+
+    fn unused(name: &str) -> (&'static str, String) {
+        ("unused-binding", name.to_string())
+    }
+
+    fn unbound(name: &str) -> (&'static str, String) {
+        ("unbound-variable", name.to_string())
+    }
+
+    #[test]
+    fn test_unbound_variables() {
+        assert_eq!(checked("let lenght = 1 in length + lenght"), [unbound("length")]);
+        assert_eq!(checked("fun x -> x + y"), [unbound("y")]);
+        // A value is not in scope of its own `let`, but a `rec` name is in its body
+        assert_eq!(checked("let f = fun n -> f n in f 1"), [unbound("f")]);
+        assert_eq!(checked("let rec f = fun n -> f n in f 1"), []);
+        // Built-ins, `catch` names and namespaces are bound
+        assert_eq!(checked("try show 1 catch e -> e.message"), []);
+        assert_eq!(checked("load \"m.par\" as M in M.f 1"), []);
+        // A library loaded without `as` may bind anything
+        assert_eq!(checked("load \"m.par\" in f 1"), []);
+        let found = check(&parse("let value = 1 in valeu").unwrap());
+        assert_eq!(found[0].to_string(), "Unbound variable: valeu (did you mean `value`?) [unbound-variable]");
+    }
+
+    #[test]
+    fn test_unused_bindings() {
+        assert_eq!(checked("let x = 1 in 2"), [unused("x")]);
+        assert_eq!(checked("fun x -> fun y -> x"), [unused("y")]);
+        assert_eq!(checked("match (1, 2) with | (a, b) -> a | c -> 0"), [unused("b"), unused("c")]);
+        // A shadowed binding is unused if only the inner one is used
+        assert_eq!(checked("let x = 1 in let x = 2 in x"), [unused("x")]);
+        assert_eq!(checked("let x = 1 in let y = x in let x = y in x"), []);
+        // Top-level bindings are used by the bindings after them and the body
+        assert_eq!(checked("let a = 1;\nlet b = a;\nlet c = 3;\nb"), [unused("c")]);
+        // ... unless there is no body: the bindings of a library are its exports
+        assert_eq!(checked("let a = 1;\nlet b = fun x -> 2;"), [unused("x")]);
+        // Names starting with `_`, which only desugaring makes, are exempt,
+        // as are `rec` and `catch` names
+        let ignored = Expr::Let("_x".to_string(), None, Box::new(Expr::Int(1)), Box::new(Expr::Int(2)));
+        assert_eq!(check(&ignored), vec![]);
+        assert_eq!(checked("let f = rec go -> fun n -> n in try f 1 catch e -> 0"), []);
+        assert_eq!(checked("function | Some x -> 1 | None -> 0"), [unused("x")]);
+    }
+
+    #[test]
+    fn test_check_options_and_spans() {
+        let source = "let unused = 1 in\nmissing";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        let found = check_with(&expr, &spans, &CheckOptions::default());
+        let at: Vec<&str> = found.iter().map(|lint| &source[lint.span.unwrap().start..lint.span.unwrap().end]).collect();
+        assert_eq!(at, ["missing", "1"]);
+
+        let options = CheckOptions { unused_bindings: false, ..CheckOptions::default() };
+        assert_eq!(check_with(&expr, &[], &options).len(), 1);
+        let options = CheckOptions { globals: HashSet::from(["missing".to_string()]), ..CheckOptions::default() };
+        assert_eq!(check_with(&expr, &[], &options)[0].rule, "unused-binding");
+        // Without the prelude the built-ins are unbound too
+        let options = CheckOptions { globals: HashSet::new(), unused_bindings: false, ..CheckOptions::default() };
+        assert_eq!(check_with(&parse("show 1").unwrap(), &[], &options)[0].name.as_deref(), Some("show"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Where `load` finds the libraries it names
+//!
+//! A relative path in `load "path"` is looked up next to the file doing the
+//! loading first, then in each directory of the search path, and finally in
+//! the working directory. A program that was not read from a file, such as
+//! REPL input, loads from the working directory first. The search path is
+//! given with the repeatable `--include-dir` flag and the `PARLANG_PATH`
+//! environment variable, whose directories are separated as in `PATH`. An
+//! absolute path is used as written.
+//!
+//! A library loaded again while it is still being loaded (`a.par` loads
+//! `b.par`, which loads `a.par`) would be loaded forever, so it is reported
+//! as a circular load instead.
+
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Environment variable holding the directories `load` searches
+pub const SEARCH_PATH_VAR: &str = "PARLANG_PATH";
+
+/// Where relative `load` paths are looked up
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadPath {
+    /// Directory of the file doing the loading, `None` for the working directory
+    pub dir: Option<PathBuf>,
+    /// Directories searched after `dir`, in order
+    pub search_path: Vec<PathBuf>,
+}
+
+impl LoadPath {
+    /// Load from the working directory, then from the directories in `PARLANG_PATH`
+    pub fn from_env() -> Self {
+        LoadPath { dir: None, search_path: search_path_from_env() }
+    }
+
+    /// The same search path, for loads made by `file`
+    #[must_use]
+    pub fn for_file(&self, file: &Path) -> Self {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).map(Path::to_path_buf);
+        LoadPath { dir, search_path: self.search_path.clone() }
+    }
+
+    /// The files `load "filepath"` tries, in the order it tries them
+    pub fn candidates(&self, filepath: &str) -> Vec<PathBuf> {
+        let path = Path::new(filepath);
+        if path.is_absolute() {
+            return vec![path.to_path_buf()];
+        }
+        let first = self.dir.as_ref().map_or_else(|| path.to_path_buf(), |dir| dir.join(path));
+        let searched = self.search_path.iter().map(|dir| dir.join(path));
+        let mut candidates: Vec<PathBuf> = Vec::new();
+        for candidate in std::iter::once(first).chain(searched).chain(std::iter::once(path.to_path_buf())) {
+            if !candidates.contains(&candidate) {
+                candidates.push(candidate);
+            }
+        }
+        candidates
+    }
+
+    /// Find the library `load "filepath"` names: the first candidate that is a file
+    ///
+    /// # Errors
+    ///
+    /// Returns a message listing every path tried when none of them is a file.
+    pub fn resolve(&self, filepath: &str) -> Result<PathBuf, String> {
+        let candidates = self.candidates(filepath);
+        if let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) {
+            return Ok(found.clone());
+        }
+        let tried: Vec<String> = candidates.iter().map(|candidate| format!("'{}'", candidate.display())).collect();
+        Err(format!("Failed to read file '{filepath}': no such file, tried {}", tried.join(", ")))
+    }
+}
+
+/// The directories listed in `PARLANG_PATH`, or none when it is not set
+pub fn search_path_from_env() -> Vec<PathBuf> {
+    env::var_os(SEARCH_PATH_VAR)
+        .map(|value| env::split_paths(&value).filter(|dir| !dir.as_os_str().is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// The path identifying the file at `path`, however it was reached
+///
+/// Falls back to `path` itself when it cannot be resolved.
+pub(crate) fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The libraries being loaded, outermost first
+///
+/// Shared by every environment derived from the one a program runs in, so
+/// loads made while a library is loading see it.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct LoadStack(RefCell<Vec<(PathBuf, PathBuf)>>);
+
+impl LoadStack {
+    /// Record that the library at `path` is being loaded until the returned guard is dropped
+    ///
+    /// Fails with a message showing the chain of loads when the library is
+    /// already being loaded.
+    pub(crate) fn enter(self: &Rc<Self>, path: &Path) -> Result<Loading, String> {
+        // The same file may be reached through different relative paths
+        let key = canonical(path);
+        let mut stack = self.0.borrow_mut();
+        if let Some(start) = stack.iter().position(|(loading, _)| *loading == key) {
+            let chain: Vec<String> =
+                stack[start..].iter().map(|(_, shown)| shown.as_path()).chain([path]).map(|p| p.display().to_string()).collect();
+            return Err(format!("Circular load: {}", chain.join(" -> ")));
+        }
+        stack.push((key, path.to_path_buf()));
+        Ok(Loading(Rc::clone(self)))
+    }
+}
+
+/// A library being loaded, on the [`LoadStack`] until it is dropped
+pub(crate) struct Loading(Rc<LoadStack>);
+
+impl Drop for Loading {
+    fn drop(&mut self) {
+        self.0 .0.borrow_mut().pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_candidates_order() {
+        let load_path = LoadPath { dir: Some("lib".into()), search_path: vec!["/usr/share/parlang".into(), "lib".into()] };
+        let expected: Vec<PathBuf> = vec!["lib/util.par".into(), "/usr/share/parlang/util.par".into(), "util.par".into()];
+        assert_eq!(load_path.candidates("util.par"), expected);
+        assert_eq!(load_path.candidates("/tmp/util.par"), vec![PathBuf::from("/tmp/util.par")]);
+        // Without a file doing the loading, the working directory comes first
+        let expected: Vec<PathBuf> = vec!["util.par".into(), "/usr/share/parlang/util.par".into()];
+        assert_eq!(LoadPath { dir: None, search_path: vec!["/usr/share/parlang".into()] }.candidates("util.par"), expected);
+    }
+
+    #[test]
+    fn test_for_file_keeps_the_search_path() {
+        let load_path = LoadPath { dir: None, search_path: vec!["std".into()] };
+        assert_eq!(load_path.for_file(Path::new("src/main.par")).dir, Some(PathBuf::from("src")));
+        assert_eq!(load_path.for_file(Path::new("main.par")).dir, None);
+        assert_eq!(load_path.for_file(Path::new("src/main.par")).search_path, load_path.search_path);
+    }
+
+    #[test]
+    fn test_resolve_lists_the_paths_tried() {
+        let load_path = LoadPath { dir: Some("nowhere".into()), search_path: vec!["also_nowhere".into()] };
+        let message = load_path.resolve("missing.par").unwrap_err();
+        assert_eq!(
+            message,
+            "Failed to read file 'missing.par': no such file, tried 'nowhere/missing.par', 'also_nowhere/missing.par', 'missing.par'"
+        );
+        assert_eq!(LoadPath::default().resolve("examples/stdlib.par"), Ok(PathBuf::from("examples/stdlib.par")));
+    }
+
+    #[test]
+    fn test_load_stack_reports_cycles() {
+        let stack = Rc::new(LoadStack::default());
+        let outer = stack.enter(Path::new("a.par")).unwrap();
+        let inner = stack.enter(Path::new("b.par")).unwrap();
+        assert_eq!(stack.enter(Path::new("a.par")).err(), Some("Circular load: a.par -> b.par -> a.par".to_string()));
+        drop(inner);
+        drop(outer);
+        // Loading a library again once it has finished is fine
+        assert!(stack.enter(Path::new("a.par")).is_ok());
+    }
+}
@@ -5,16 +5,30 @@
 /// - File execution mode for running .par files
 /// - AST dumping to DOT format for visualization
 use clap::{Parser, Subcommand};
-use parlang::{parse, eval, extract_bindings, dot, Environment, typecheck};
+use parlang::check::{self, CheckOptions};
+use parlang::config::{Config, ConfigOverrides};
+use parlang::profile::Profiler;
+use parlang::repl::default_history_path;
+use parlang::load_path;
+use parlang::{parse, parse_program_with_spans, check_matches, dot, format, language_spec, lint, optimize, plan_pruning_from, pretty, typecheck_with_spans, DisplayOptions, Environment, Interpreter, LoadPath, ReplSession, Span, TypeCheckMode, TypeEnv};
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
 use std::fs;
+use std::io;
+use std::path::PathBuf;
 use std::process;
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
+use std::thread;
 use std::env;
+use std::path::Path;
 
 #[derive(Parser)]
+// Each flag is an independent switch
+#[allow(clippy::struct_excessive_bools)]
 #[command(name = "parlang")]
 #[command(author, version, about = "A small ML-alike functional language", long_about = None)]
+#[command(disable_version_flag = true)]
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
@@ -22,62 +36,363 @@ struct Cli {
     /// Input file to execute (.par file)
     file: Option<String>,
 
-    /// Dump AST to DOT file (Graphviz format)
+    /// Dump AST to DOT file (Graphviz format); `-` writes it to stdout instead of running the program
     #[arg(short, long, value_name = "FILE")]
     dump_ast: Option<String>,
+
+    /// Dump AST to JSON file, for other tools; `-` writes it to stdout instead of running the program
+    #[cfg(feature = "serde")]
+    #[arg(long, value_name = "FILE", requires = "file")]
+    dump_ast_json: Option<String>,
+
+    /// Dump AST to DOT file with the type inferred for each expression; `-` writes it to stdout instead of running the program
+    #[arg(long, value_name = "FILE", requires = "file")]
+    dump_typed_ast: Option<String>,
+
+    /// Maximum number of AST nodes to write with --dump-ast or --dump-ast-json (default: unlimited)
+    #[arg(long, value_name = "N")]
+    dump_limit: Option<usize>,
+
+    /// Number of significant digits used when printing floats (default: exact)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    float_precision: Option<u16>,
+
+    /// Apply source-level optimizations (such as unboxing local references and folding constants) and resolve variables to slots before evaluating
+    #[arg(long)]
+    optimize: bool,
+
+    /// Type check the program before evaluating it, and stop if it is not well-typed
+    #[arg(long)]
+    typecheck: bool,
+
+    /// Stop before running the program if a match is non-exhaustive or has unreachable arms
+    #[arg(long)]
+    strict_matches: bool,
+
+    /// Stop before running the program if there are any warnings
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Skip library bindings the program never references before evaluating
+    #[arg(long)]
+    prune_unused: bool,
+
+    /// Report library bindings skipped by --prune-unused
+    #[arg(long)]
+    verbose_load: bool,
+
+    /// Directory to look for loaded libraries in, after the loading file's own directory (repeatable; searched before PARLANG_PATH; replaces include_paths from parlang.toml)
+    #[arg(long, value_name = "DIR")]
+    include_dir: Vec<PathBuf>,
+
+    /// Maximum number of evaluation steps before giving up (default: unlimited)
+    #[arg(long, value_name = "N")]
+    max_steps: Option<u64>,
+
+    /// Maximum number of nested function calls before giving up (default: unlimited)
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Print how often each function was called and how many steps it took
+    #[arg(long)]
+    profile: bool,
+
+    /// Number of functions to list with --profile (default: all)
+    #[arg(long, value_name = "N", requires = "profile")]
+    profile_top: Option<usize>,
+
+    /// Do not bind the built-in functions
+    #[arg(long)]
+    no_prelude: bool,
+
+    /// Make the debug_env built-in available, for inspecting bindings from within a program
+    #[arg(long)]
+    debug_builtins: bool,
+
+    /// File the REPL keeps its input history in (default: $XDG_DATA_HOME/parlang/history or ~/.parlang_history)
+    #[arg(long, value_name = "FILE")]
+    history_file: Option<String>,
+
+    /// Report warnings of a lint rule, e.g. float-equality (repeatable)
+    #[arg(long, value_name = "RULE")]
+    warn: Vec<String>,
+
+    /// Ignore parlang.toml files
+    #[arg(long)]
+    no_config: bool,
+
+    /// Print the effective configuration and exit
+    #[arg(long)]
+    show_config: bool,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// With --version, print the version, artifact format version and enabled features as JSON
+    #[arg(long, requires = "version")]
+    json: bool,
+}
+
+impl Cli {
+    /// The settings given as flags, which take precedence over parlang.toml
+    fn overrides(&self) -> ConfigOverrides {
+        ConfigOverrides {
+            float_precision: self.float_precision.map(usize::from),
+            optimize: self.optimize.then_some(true),
+            typecheck: self.typecheck.then_some(true),
+            strict_matches: self.strict_matches.then_some(true),
+            deny_warnings: self.deny_warnings.then_some(true),
+            prune_unused: self.prune_unused.then_some(true),
+            verbose_load: self.verbose_load.then_some(true),
+            dump_limit: self.dump_limit,
+            max_steps: self.max_steps,
+            max_depth: self.max_depth,
+            prelude: self.no_prelude.then_some(false),
+            warnings: (!self.warn.is_empty()).then(|| self.warn.clone()),
+            include_paths: (!self.include_dir.is_empty()).then(|| self.include_dir.clone()),
+        }
+    }
+}
+
+/// Where `load` looks for libraries: the --include-dir directories (or the
+/// `include_paths` of parlang.toml), then those in PARLANG_PATH
+fn load_path(config: &Config) -> LoadPath {
+    let mut search_path = config.include_paths.clone();
+    search_path.extend(load_path::search_path_from_env());
+    LoadPath { dir: None, search_path }
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Start interactive REPL
     Repl,
+    /// Parse, type check, and evaluate every .par file under a directory
+    CheckExamples {
+        /// Directory to search recursively for .par files
+        dir: String,
+
+        /// Evaluation steps allowed per file before it counts as looping
+        /// (default: the step limit set in parlang.toml, or 1000000)
+        #[arg(long, value_name = "N")]
+        step_limit: Option<u64>,
+    },
+    /// Print the keywords, operators, built-ins and literal forms of the language
+    LanguageSpec {
+        /// Print JSON, for editor tooling
+        #[arg(long)]
+        json: bool,
+    },
+    /// Reformat a .par file in place
+    Fmt {
+        /// File to format
+        file: String,
+
+        /// Print the formatted program instead of rewriting the file, and
+        /// exit with status 1 if the file is not formatted
+        #[arg(long)]
+        check: bool,
+
+        /// Only reformat the top-level bindings and body that are not already
+        /// formatted, keeping the layout of the others
+        #[arg(long)]
+        minimal: bool,
+
+        /// Line width to fit the program in
+        #[arg(long, value_name = "N", default_value_t = pretty::DEFAULT_WIDTH)]
+        width: usize,
+    },
 }
 
+/// Stack size of the thread parlang runs on
+///
+/// Evaluation recurses on the Rust stack, so this leaves room for the REPL's
+/// `DEFAULT_MAX_DEPTH` calls, and for a `--max-depth` as large, to end in an
+/// error rather than a crash.
+const STACK_SIZE: usize = 1024 * 1024 * 1024;
+
 fn main() {
+    let main = thread::Builder::new().stack_size(STACK_SIZE).spawn(run).expect("Failed to start the main thread");
+    if main.join().is_err() {
+        // The panic message has been printed already
+        process::exit(101);
+    }
+}
+
+fn run() {
     let cli = Cli::parse();
 
+    if cli.version {
+        if cli.json {
+            println!("{}", format::version_json());
+        } else {
+            println!("parlang {}", env!("CARGO_PKG_VERSION"));
+        }
+        return;
+    }
+    let config = discover_config(&cli).merge(&cli.overrides());
+
+    if cli.show_config {
+        print!("{config}");
+        return;
+    }
+
+    if let Some(Commands::CheckExamples { dir, step_limit }) = &cli.command {
+        check_examples(dir, step_limit.or(config.max_steps).unwrap_or(check::DEFAULT_STEP_LIMIT));
+        return;
+    }
+
+    if let Some(Commands::LanguageSpec { json }) = &cli.command {
+        let spec = language_spec();
+        if *json {
+            println!("{}", spec.to_json());
+        } else {
+            print!("{spec}");
+        }
+        return;
+    }
+
+    if let Some(Commands::Fmt { file, check, minimal, width }) = &cli.command {
+        fmt_file(file, *check, *minimal, *width);
+        return;
+    }
+
     // Handle REPL command or no arguments
     if cli.command.is_some() || (cli.file.is_none() && cli.dump_ast.is_none()) {
         // REPL mode
         println!("ParLang v{} - A small ML-alike functional language", env!("CARGO_PKG_VERSION"));
-        println!("Type expressions to evaluate them. Press Ctrl+C to exit.");
+        println!("Type expressions to evaluate them, :help for commands, :quit to exit.");
         println!();
-        repl();
+        repl(cli.debug_builtins, cli.history_file.as_deref(), config.max_depth, load_path(&config));
         return;
     }
 
     // File execution mode
     if let Some(filename) = &cli.file {
+        let load_path = load_path(&config).for_file(Path::new(filename));
         match fs::read_to_string(filename) {
             Ok(contents) => {
-                // Parse the file
-                match parse(&contents) {
-                    Ok(expr) => {
-                        // Dump AST if requested
+                // Parse the file, keeping where each expression is for error messages
+                match parse_program_with_spans(&contents) {
+                    Ok((expr, spans)) => {
+                        let optimized = config.optimize.then(|| optimize::optimize_with_spans(&expr, &spans));
+
+                        // Dump AST if requested, as it will run
                         if let Some(dot_file) = &cli.dump_ast {
-                            match dot::write_ast_to_dot_file(&expr, dot_file) {
-                                Ok(()) => {
-                                    eprintln!("AST dumped to: {dot_file}");
+                            let dumped_expr = optimized.as_ref().map_or(&expr, |(optimized, _)| optimized);
+                            let to_stdout = dot_file == "-";
+                            let dumped = if to_stdout {
+                                let (dot_content, total) = dot::ast_to_dot_with_limit(dumped_expr, config.dump_limit);
+                                print!("{dot_content}");
+                                Ok(total)
+                            } else {
+                                dot::write_ast_to_dot_file_with_limit(dumped_expr, dot_file, config.dump_limit)
+                            };
+                            match dumped {
+                                Ok(total) => {
+                                    if !to_stdout {
+                                        eprintln!("AST dumped to: {dot_file}");
+                                    }
+                                    if let Some(total) = total {
+                                        eprintln!(
+                                            "Warning: AST dump truncated to {} of {total} nodes (--dump-limit)",
+                                            config.dump_limit.unwrap_or_default()
+                                        );
+                                    }
                                 }
                                 Err(e) => {
                                     eprintln!("Failed to write DOT file '{dot_file}': {e}");
                                     process::exit(1);
                                 }
                             }
+                            // Nothing else may reach stdout after a DOT dump there
+                            if to_stdout {
+                                return;
+                            }
+                        }
+
+                        #[cfg(feature = "serde")]
+                        if let Some(json_file) = &cli.dump_ast_json {
+                            // Nor after a JSON dump there
+                            if dump_ast_json(optimized.as_ref().map_or(&expr, |(optimized, _)| optimized), json_file, config.dump_limit) {
+                                return;
+                            }
+                        }
+
+                        if let Some(dot_file) = &cli.dump_typed_ast {
+                            // Nor after a typed dump there
+                            if dump_typed_ast(&expr, dot_file, &config, &load_path) {
+                                return;
+                            }
+                        }
+
+                        if config.typecheck {
+                            let mut type_env = if config.prelude { TypeEnv::with_builtins() } else { TypeEnv::new() };
+                            type_env.set_load_path(load_path.clone());
+                            if let Err(e) = typecheck_with_spans(&expr, &spans, &mut type_env) {
+                                eprintln!("{}", located("Type error", &e, e.span(), &contents));
+                                process::exit(1);
+                            }
                         }
 
+                        let unbound = report_lints(&expr, &spans, &contents, &config, &load_path);
+                        report_matches(&expr, &contents, &config);
+
                         // Execute the program
-                        let env = Environment::new();
-                        match eval(&expr, &env).map_err(|e| e.to_string()) {
-                            Ok(value) => println!("{value}"),
+                        let mut env = if config.prelude { Environment::with_builtins() } else { Environment::new() };
+                        env.set_step_limit(config.max_steps);
+                        env.set_depth_limit(config.max_depth);
+                        env.set_debug_builtins(cli.debug_builtins);
+                        env.set_resolved(config.optimize);
+                        env.set_load_path(load_path.clone());
+                        if config.prune_unused {
+                            let plan = plan_pruning_from(&expr, &load_path);
+                            if config.verbose_load {
+                                for (library, names) in plan.pruned() {
+                                    eprintln!("Pruned from {library}: {}", names.join(", "));
+                                }
+                            }
+                            env.set_prune_plan(Some(plan));
+                        }
+                        let profiler = cli.profile.then(|| Rc::new(Profiler::new()));
+                        env.set_profiler(profiler.clone());
+                        let options = DisplayOptions { float_precision: config.float_precision };
+                        let interpreter = Interpreter::with_environment(env);
+                        let result = match &optimized {
+                            Some((optimized, optimized_spans)) => interpreter.eval_with_spans(optimized, optimized_spans),
+                            None => interpreter.eval_with_spans(&expr, &spans),
+                        };
+                        if let Some(profiler) = profiler {
+                            eprint!("{}", profiler.report().render(cli.profile_top));
+                        }
+                        // An unbound name evaluation ran into is reported once, as the error
+                        let reported = |lint: &&lint::Lint| match (result.as_ref().map_err(|e| (e.root(), e.span())), &lint.name) {
+                            (Err((parlang::EvalError::UnboundVariable(name, _), span)), Some(unbound)) => {
+                                name == unbound && span == lint.span
+                            }
+                            _ => false,
+                        };
+                        for lint in unbound.iter().filter(|lint| !reported(lint)) {
+                            eprintln!("{}", located("Warning", lint, lint.span, &contents));
+                        }
+                        match result {
+                            Ok(value) => println!("{}", value.display_with(options)),
                             Err(e) => {
-                                eprintln!("Error: {e}");
+                                if e.span().is_some() {
+                                    // The trace follows the source line, innermost call first
+                                    eprintln!("{}", located("Error", e.root(), e.span(), &contents));
+                                    for frame in e.trace().iter().rev() {
+                                        eprintln!("  {frame}");
+                                    }
+                                } else {
+                                    eprintln!("Error: {e}");
+                                }
                                 process::exit(1);
                             }
                         }
                     }
                     Err(e) => {
-                        eprintln!("Parse error: {e}");
+                        eprintln!("{e}");
                         process::exit(1);
                     }
                 }
@@ -93,14 +408,260 @@ fn main() {
     }
 }
 
-fn repl() {
-    let mut env = Environment::new();
+/// Write the AST as JSON to `path`, or to stdout for `-`, with at most
+/// `limit` expression nodes, exiting with status 1 if it cannot be written
+///
+/// Returns whether the JSON went to stdout.
+#[cfg(feature = "serde")]
+fn dump_ast_json(expr: &parlang::Expr, path: &str, limit: Option<usize>) -> bool {
+    let to_stdout = path == "-";
+    let written = parlang::expr_to_json_with_limit(expr, limit).and_then(|(json, truncated)| {
+        if to_stdout {
+            println!("{json}");
+        } else {
+            dot::write_dot_file(path, &json).map_err(|e| e.to_string())?;
+        }
+        Ok(truncated)
+    });
+    match written {
+        Ok(truncated) => {
+            if !to_stdout {
+                eprintln!("AST JSON dumped to: {path}");
+            }
+            if truncated {
+                eprintln!(
+                    "Warning: AST JSON dump truncated to {} of {} nodes (--dump-limit)",
+                    limit.unwrap_or_default(),
+                    expr.node_count()
+                );
+            }
+            to_stdout
+        }
+        Err(e) => {
+            eprintln!("Failed to write AST JSON file '{path}': {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Write the AST with inferred types as DOT to `path`, or to stdout for `-`,
+/// exiting with status 1 if the program is not well-typed or the file cannot
+/// be written
+///
+/// The program is checked as written, before any --optimize rewriting.
+/// Returns whether the DOT went to stdout.
+fn dump_typed_ast(expr: &parlang::Expr, path: &str, config: &Config, load_path: &LoadPath) -> bool {
+    let mut type_env = if config.prelude { TypeEnv::with_builtins() } else { TypeEnv::new() };
+    type_env.set_load_path(load_path.clone());
+    let to_stdout = path == "-";
+    let dumped = if to_stdout {
+        dot::typed_ast_to_dot(expr, &type_env).map(|dot_content| print!("{dot_content}")).map_err(|e| dot::TypedDotError::Type(Box::new(e)))
+    } else {
+        dot::write_typed_ast_to_dot_file(expr, &type_env, path)
+    };
+    match dumped {
+        Ok(()) => {
+            if !to_stdout {
+                eprintln!("Typed AST dumped to: {path}");
+            }
+            to_stdout
+        }
+        Err(e @ dot::TypedDotError::Type(_)) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Failed to write DOT file '{path}': {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Read the nearest parlang.toml for the input, unless --no-config is given
+///
+/// The search starts in the directory of the input file (or the
+/// `check-examples` directory, or the current directory). Unknown keys are
+/// reported as warnings; an unreadable or malformed file is an error.
+#[cfg(feature = "config")]
+fn discover_config(cli: &Cli) -> Config {
+    if cli.no_config {
+        return Config::default();
+    }
+    let start = match (&cli.command, &cli.file) {
+        (Some(Commands::CheckExamples { dir, .. }), _) => Path::new(dir),
+        (_, Some(file)) => Path::new(file).parent().unwrap_or(Path::new("")),
+        _ => Path::new(""),
+    };
+    let start = if start.as_os_str().is_empty() { Path::new(".") } else { start };
+    match Config::discover(start) {
+        Ok(config) => {
+            for message in &config.unknown {
+                eprintln!("Warning: {message}");
+            }
+            config
+        }
+        Err(e) => {
+            eprintln!("Config error: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Without the `config` feature only the command-line flags apply
+#[cfg(not(feature = "config"))]
+fn discover_config(_cli: &Cli) -> Config {
+    Config::default()
+}
+
+/// `message` labelled with where `span` is in `source`, as [`Span::render`]
+/// shows it, or just labelled when the span is not known
+fn located(label: &str, message: impl std::fmt::Display, span: Option<Span>, source: &str) -> String {
+    match span {
+        Some(span) => span.render(source, label, message),
+        None => format!("{label}: {message}"),
+    }
+}
+
+/// Print the warnings of the enabled lint rules, exiting with status 1 if
+/// there are any and `deny_warnings` is set
+///
+/// `unbound-variable` is always checked, `unused-binding` and the rules
+/// needing types only when enabled. Programs that do not type check are run
+/// without warnings of the latter, as file execution does not require type
+/// checking.
+///
+/// Unless `deny_warnings` is set, the `unbound-variable` warnings are
+/// returned instead of printed, to be printed after evaluation unless it
+/// fails on the same name at the same place.
+fn report_lints(expr: &parlang::Expr, spans: &[Option<Span>], source: &str, config: &Config, load_path: &LoadPath) -> Vec<lint::Lint> {
+    let enabled = |rule: &str| config.warnings.iter().any(|enabled| enabled == rule);
+    let mut options = lint::CheckOptions { unused_bindings: enabled("unused-binding"), ..lint::CheckOptions::default() };
+    if !config.prelude {
+        options.globals.clear();
+    }
+    let mut lints = lint::check_with(expr, spans, &options);
+    if config.warnings.iter().any(|rule| rule != "unused-binding" && rule != "unbound-variable") {
+        let mut type_env = TypeEnv::with_builtins();
+        type_env.set_load_path(load_path.clone());
+        if let Ok(typed) = lint::lint_with(expr, spans, &mut type_env) {
+            lints.extend(typed.into_iter().filter(|lint| enabled(lint.rule)));
+        }
+    }
+    let label = if config.deny_warnings { "Error" } else { "Warning" };
+    let (unbound, lints): (Vec<lint::Lint>, Vec<lint::Lint>) =
+        lints.into_iter().partition(|lint| !config.deny_warnings && lint.rule == "unbound-variable");
+    for lint in &lints {
+        eprintln!("{}", located(label, lint, lint.span, source));
+    }
+    if config.deny_warnings && !lints.is_empty() {
+        process::exit(1);
+    }
+    unbound
+}
+
+/// Print the problems with the program's matches, exiting with status 1 if
+/// there are any and `strict_matches` or `deny_warnings` is set
+fn report_matches(expr: &parlang::Expr, source: &str, config: &Config) {
+    let env = if config.prelude { Environment::with_builtins() } else { Environment::new() };
+    let warnings = check_matches(expr, source, &env);
+    let strict = config.strict_matches || config.deny_warnings;
+    let label = if strict { "Error" } else { "Warning" };
+    for warning in &warnings {
+        eprintln!("{label}: {warning}");
+    }
+    if strict && !warnings.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Run `check-examples`, exiting with status 1 if any file fails
+fn check_examples(dir: &str, step_limit: u64) {
+    match check::check_examples(dir.as_ref(), CheckOptions { step_limit }) {
+        Ok(results) => {
+            print!("{}", check::render_table(&results));
+            if !results.iter().all(check::FileCheck::passed) {
+                process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Rewrite `file` as `parlang::pretty` prints it, or with `check` print the
+/// formatted program and exit with status 1 if the file differs from it
+///
+/// With `minimal`, only the top-level items that are not already formatted
+/// are printed again (see `parlang::pretty::pretty_minimal`).
+fn fmt_file(file: &str, check: bool, minimal: bool, width: usize) {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Failed to read file '{file}': {e}");
+            process::exit(1);
+        }
+    };
+    // A `-- no-typecheck` marker is not part of the program, so it is kept as written
+    let first_line = source.lines().next().unwrap_or_default();
+    let (marker, program) = if first_line.trim() == check::NO_TYPECHECK_MARKER {
+        (format!("{first_line}\n"), &source[first_line.len()..])
+    } else {
+        (String::new(), source.as_str())
+    };
+    let printed = if minimal {
+        pretty::pretty_minimal(program, width)
+    } else {
+        parse(program).map(|expr| pretty::pretty(&expr, width))
+    };
+    let printed = match printed {
+        Ok(printed) => printed,
+        Err(e) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+    };
+    let formatted = if printed.is_empty() { marker } else { format!("{marker}{printed}\n") };
+    if check {
+        print!("{formatted}");
+        if formatted != source {
+            process::exit(1);
+        }
+    } else if formatted != source {
+        if let Err(e) = fs::write(file, &formatted) {
+            eprintln!("Failed to write file '{file}': {e}");
+            process::exit(1);
+        }
+        eprintln!("Formatted: {file}");
+    }
+}
+
+/// Run the interactive REPL, keeping its input history in `history_file`
+/// (or the default history file) across sessions
+///
+/// Inputs may have `max_depth` calls in progress, or the REPL's default, and
+/// load libraries through `load_path`.
+fn repl(debug_builtins: bool, history_file: Option<&str>, max_depth: Option<usize>, load_path: LoadPath) {
+    let mut session = ReplSession::new();
+    session.environment().set_debug_builtins(debug_builtins);
+    session.set_load_path(load_path);
+    if max_depth.is_some() {
+        session.set_depth_limit(max_depth);
+    }
+    // The line editor turns Ctrl+C at the prompt into `Interrupted`; during
+    // evaluation it raises the signal, which stops the running input
+    let interrupt = session.interrupt_flag();
+    if let Err(e) = ctrlc::set_handler(move || interrupt.store(true, Ordering::Relaxed)) {
+        eprintln!("Warning: Ctrl+C will not interrupt evaluation: {e}");
+    }
     let mut rl = DefaultEditor::new().expect("Failed to initialize line editor");
+    let mut history = History::open(&mut rl, history_file);
     
-    // Check if type checking is enabled
-    let type_check_enabled = env::var("PARLANG_TYPECHECK").is_ok();
-    if type_check_enabled {
-        println!("Type checking enabled (PARLANG_TYPECHECK is set)");
+    // Type errors are warnings unless PARLANG_TYPECHECK asks for strict checking
+    if env::var("PARLANG_TYPECHECK").is_ok() {
+        println!("Strict type checking enabled (PARLANG_TYPECHECK is set)");
+        session.set_type_mode(TypeCheckMode::Strict);
     }
 
     loop {
@@ -127,23 +688,20 @@ fn repl() {
                         continue;
                     }
                     
-                    // Add the line to history if it's the first line
-                    if is_first_line {
-                        if let Err(e) = rl.add_history_entry(line.as_str()) {
-                            eprintln!("Warning: Failed to add entry to history: {e}");
-                        }
+                    // Commands are single-line and submitted immediately
+                    if is_first_line && ReplSession::is_command(trimmed) {
+                        lines.push(line);
+                        break;
                     }
-                    
+
                     // Add the line to our accumulator (with newline to match old behavior)
                     lines.push(line + "\n");
                     is_first_line = false;
                     
                     // Try to parse the accumulated input after each line
-                    // If it's parseable, auto-submit without requiring a blank line
-                    let accumulated = lines.concat();
-                    let accumulated_trimmed = accumulated.trim();
-                    
-                    if parse(accumulated_trimmed).is_ok() {
+                    // If it's parseable, auto-submit without requiring a blank line,
+                    // unless the line ends in `\` to continue on the next one
+                    if ReplSession::is_complete(&lines.concat()) {
                         // Input is complete and parseable, submit it
                         break;
                     }
@@ -151,15 +709,18 @@ fn repl() {
                 Err(ReadlineError::Interrupted) => {
                     // Ctrl+C - reset the multiline input state and start fresh
                     println!("^C");
+                    lines.clear();
                     break;
                 }
                 Err(ReadlineError::Eof) => {
                     // Ctrl+D
                     println!("\nGoodbye!");
+                    history.save(&mut rl);
                     return;
                 }
                 Err(err) => {
                     eprintln!("Error reading input: {err}");
+                    history.save(&mut rl);
                     return;
                 }
             }
@@ -168,39 +729,62 @@ fn repl() {
         // Join all lines and try to parse/evaluate
         if !lines.is_empty() {
             let input = lines.concat();  // Preserves newlines
-            let input = input.trim();
-
-            match parse(input) {
-                Ok(expr) => {
-                    // Type check if enabled
-                    if type_check_enabled {
-                        match typecheck(&expr) {
-                            Ok(ty) => println!("Type: {ty}"),
-                            Err(e) => {
-                                eprintln!("Type error: {e}");
-                                continue;
-                            }
-                        }
-                    }
-                    
-                    match eval(&expr, &env) {
-                        Ok(value) => {
-                            println!("{value}");
-                            // Extract bindings from the expression and merge into environment
-                            match extract_bindings(&expr, &env) {
-                                Ok(new_env) => {
-                                    env = new_env;
-                                }
-                                Err(e) => {
-                                    // If binding extraction fails, report it but continue with the old environment
-                                    eprintln!("Warning: Failed to persist bindings: {e}");
-                                }
-                            }
-                        }
-                        Err(e) => eprintln!("Evaluation error: {e}"),
-                    }
-                },
-                Err(e) => eprintln!("Parse error: {e}"),
+            // A multi-line input is one history entry, so it can be recalled whole
+            history.add(&mut rl, input.trim_end());
+            match session.execute(&input) {
+                Ok(output) => println!("{output}"),
+                Err(e) => eprintln!("{e}"),
+            }
+            if session.is_finished() {
+                history.save(&mut rl);
+                return;
+            }
+        }
+    }
+}
+
+/// The REPL's history file, written as inputs are entered
+///
+/// A missing file is created on the first save. When the file cannot be
+/// read or written, the REPL warns once and keeps its history in memory only.
+struct History {
+    path: Option<PathBuf>,
+}
+
+impl History {
+    /// Load the history from `history_file`, or from the default history file
+    fn open(rl: &mut DefaultEditor, history_file: Option<&str>) -> Self {
+        let path = history_file
+            .map(PathBuf::from)
+            .or_else(|| default_history_path(env::var_os("XDG_DATA_HOME"), env::var_os("HOME")));
+        let Some(path) = path else { return History { path: None } };
+        match rl.load_history(&path) {
+            Err(ReadlineError::Io(e)) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    // A failure shows up as a warning on the first save
+                    let _ = fs::create_dir_all(dir);
+                }
+            }
+            Err(e) => eprintln!("Warning: Failed to read history from '{}': {e}", path.display()),
+            Ok(()) => {}
+        }
+        History { path: Some(path) }
+    }
+
+    /// Add an input to the history and append it to the file
+    fn add(&mut self, rl: &mut DefaultEditor, input: &str) {
+        if let Err(e) = rl.add_history_entry(input) {
+            eprintln!("Warning: Failed to add entry to history: {e}");
+        }
+        self.save(rl);
+    }
+
+    /// Append the entries not yet in the file
+    fn save(&mut self, rl: &mut DefaultEditor) {
+        if let Some(path) = &self.path {
+            if let Err(e) = rl.append_history(path) {
+                eprintln!("Warning: Failed to save history to '{}': {e}; history will not be saved", path.display());
+                self.path = None;
             }
         }
     }
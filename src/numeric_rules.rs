@@ -0,0 +1,196 @@
+//! Which scalar kinds the arithmetic and ordering operators combine
+//!
+//! [`NUMERIC_RULES`] is the single description of what `+ - * /` and
+//! `< <= > >=` accept: one entry per allowed `(operator, left kind, right
+//! kind)` combination, with the kind of the result. Anything not listed is
+//! rejected. The evaluator checks runtime operands against the table before
+//! applying an operator, and the type checker infers these operators from
+//! it, so the two cannot disagree about which combinations are legal.
+//!
+//! There are no cross-kind entries: `1 + 1.0`, `1b + 1` and `'a' < 1` are
+//! errors in both layers. When neither operand's type is known, the type
+//! checker assumes [`DEFAULT_KIND`].
+//!
+//! Equality (`==`, `!=`) works on any pair of values of the same type and
+//! string concatenation (`^`) on strings only; neither is governed by the
+//! table.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::BinOp;
+//! use parlang::numeric_rules::{result_kind, ScalarKind};
+//!
+//! assert_eq!(result_kind(BinOp::Add, ScalarKind::Byte, ScalarKind::Byte), Some(ScalarKind::Byte));
+//! assert_eq!(result_kind(BinOp::Lt, ScalarKind::Char, ScalarKind::Char), Some(ScalarKind::Bool));
+//! assert_eq!(result_kind(BinOp::Add, ScalarKind::Int, ScalarKind::Float), None);
+//! ```
+
+use crate::ast::BinOp;
+use crate::eval::Value;
+use crate::types::Type;
+use BinOp::{Add, Div, Ge, Gt, Le, Lt, Mul, Sub};
+use ScalarKind::{Bool, Byte, Char, Float, Int};
+
+/// A scalar kind of value, as seen by the operators in [`NUMERIC_RULES`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarKind {
+    Int,
+    Float,
+    Byte,
+    Char,
+    Bool,
+}
+
+impl ScalarKind {
+    /// Every scalar kind
+    pub const ALL: [ScalarKind; 5] = [
+        ScalarKind::Int,
+        ScalarKind::Float,
+        ScalarKind::Byte,
+        ScalarKind::Char,
+        ScalarKind::Bool,
+    ];
+
+    /// The kind of a runtime value, or `None` if it is not a scalar
+    pub fn of_value(value: &Value) -> Option<ScalarKind> {
+        match value {
+            Value::Int(_) => Some(ScalarKind::Int),
+            Value::Float(_) => Some(ScalarKind::Float),
+            Value::Byte(_) => Some(ScalarKind::Byte),
+            Value::Char(_) => Some(ScalarKind::Char),
+            Value::Bool(_) => Some(ScalarKind::Bool),
+            _ => None,
+        }
+    }
+
+    /// The kind of a static type, or `None` if it is not a scalar type
+    pub fn of_type(ty: &Type) -> Option<ScalarKind> {
+        match ty {
+            Type::Int => Some(ScalarKind::Int),
+            Type::Float => Some(ScalarKind::Float),
+            Type::Byte => Some(ScalarKind::Byte),
+            Type::Char => Some(ScalarKind::Char),
+            Type::Bool => Some(ScalarKind::Bool),
+            _ => None,
+        }
+    }
+
+    /// The static type of values of this kind
+    pub fn to_type(self) -> Type {
+        match self {
+            ScalarKind::Int => Type::Int,
+            ScalarKind::Float => Type::Float,
+            ScalarKind::Byte => Type::Byte,
+            ScalarKind::Char => Type::Char,
+            ScalarKind::Bool => Type::Bool,
+        }
+    }
+}
+
+/// One allowed combination of an operator and its operand kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumericRule {
+    pub op: BinOp,
+    pub left: ScalarKind,
+    pub right: ScalarKind,
+    pub result: ScalarKind,
+}
+
+const fn rule(op: BinOp, left: ScalarKind, right: ScalarKind, result: ScalarKind) -> NumericRule {
+    NumericRule { op, left, right, result }
+}
+
+/// Every allowed combination of an arithmetic or ordering operator
+pub const NUMERIC_RULES: &[NumericRule] = &[
+    rule(Add, Int, Int, Int),
+    rule(Sub, Int, Int, Int),
+    rule(Mul, Int, Int, Int),
+    rule(Div, Int, Int, Int),
+    rule(Add, Float, Float, Float),
+    rule(Sub, Float, Float, Float),
+    rule(Mul, Float, Float, Float),
+    rule(Div, Float, Float, Float),
+    rule(Add, Byte, Byte, Byte),
+    rule(Sub, Byte, Byte, Byte),
+    rule(Mul, Byte, Byte, Byte),
+    rule(Div, Byte, Byte, Byte),
+    rule(Lt, Int, Int, Bool),
+    rule(Le, Int, Int, Bool),
+    rule(Gt, Int, Int, Bool),
+    rule(Ge, Int, Int, Bool),
+    rule(Lt, Float, Float, Bool),
+    rule(Le, Float, Float, Bool),
+    rule(Gt, Float, Float, Bool),
+    rule(Ge, Float, Float, Bool),
+    rule(Lt, Byte, Byte, Bool),
+    rule(Le, Byte, Byte, Bool),
+    rule(Gt, Byte, Byte, Bool),
+    rule(Ge, Byte, Byte, Bool),
+    rule(Lt, Char, Char, Bool),
+    rule(Le, Char, Char, Bool),
+    rule(Gt, Char, Char, Bool),
+    rule(Ge, Char, Char, Bool),
+];
+
+/// The operand kind the type checker assumes when neither side is known
+pub const DEFAULT_KIND: ScalarKind = ScalarKind::Int;
+
+/// Whether `op` is one of the operators [`NUMERIC_RULES`] governs
+pub fn governs(op: BinOp) -> bool {
+    matches!(op, Add | Sub | Mul | Div | Lt | Le | Gt | Ge)
+}
+
+/// The result kind of `left op right`, or `None` if the table rejects it
+pub fn result_kind(op: BinOp, left: ScalarKind, right: ScalarKind) -> Option<ScalarKind> {
+    NUMERIC_RULES
+        .iter()
+        .find(|rule| rule.op == op && rule.left == left && rule.right == right)
+        .map(|rule| rule.result)
+}
+
+/// The allowed combinations of `op` with a left operand of kind `left`
+pub fn rules_for_left(op: BinOp, left: ScalarKind) -> impl Iterator<Item = &'static NumericRule> {
+    NUMERIC_RULES.iter().filter(move |rule| rule.op == op && rule.left == left)
+}
+
+/// Whether the runtime operands of a governed operator are allowed
+pub fn allows_values(op: BinOp, left: &Value, right: &Value) -> bool {
+    match (ScalarKind::of_value(left), ScalarKind::of_value(right)) {
+        (Some(left), Some(right)) => result_kind(op, left, right).is_some(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules_are_unique_and_governed() {
+        for (i, rule) in NUMERIC_RULES.iter().enumerate() {
+            assert!(governs(rule.op), "{rule:?}");
+            assert!(
+                NUMERIC_RULES[i + 1..]
+                    .iter()
+                    .all(|other| (other.op, other.left, other.right) != (rule.op, rule.left, rule.right)),
+                "duplicate {rule:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_governed_operator_accepts_the_default_kind() {
+        for op in [Add, Sub, Mul, Div, Lt, Le, Gt, Ge] {
+            assert!(result_kind(op, DEFAULT_KIND, DEFAULT_KIND).is_some(), "{op}");
+        }
+    }
+
+    #[test]
+    fn test_no_cross_kind_rules() {
+        assert!(NUMERIC_RULES.iter().all(|rule| rule.left == rule.right));
+        assert_eq!(result_kind(Add, Int, Byte), None);
+        assert_eq!(result_kind(Lt, Char, Int), None);
+        assert_eq!(result_kind(Add, Bool, Bool), None);
+    }
+}
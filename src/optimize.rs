@@ -0,0 +1,654 @@
+//! Source-to-source optimizations applied before evaluation
+//!
+//! [`optimize`] runs every pass in this module; the CLI applies it when
+//! `--optimize` is given. The passes preserve the result of evaluation for
+//! programs that evaluate without error. [`optimize_with_spans`] optimizes a
+//! program parsed with its spans and keeps them, so that the CLI reports a
+//! runtime error at the same place with and without `--optimize`.
+//!
+//! # Local references
+//!
+//! A reference that is created, updated and read within a single `let`
+//! and never escapes it does not need a shared mutable cell:
+//!
+//! ```parlang
+//! let r = ref 0 in let d = r := !r + 1 in !r -- => 1
+//! ```
+//!
+//! is rewritten to thread the current contents through shadowing bindings:
+//!
+//! ```parlang
+//! let r = 0 in let r = r + 1 in r -- => 1
+//! ```
+//!
+//! (the unit result of the assignment is still bound when the body uses it).
+//!
+//! The rewrite fires only when, within the body of the binding, `r`:
+//! - occurs only as `!r` or as the target of `r := e`,
+//! - is assigned only as the value of a `let` (or sequential binding) on the
+//!   straight-line spine of the body, not inside a branch or sub-expression,
+//! - is not mentioned inside a `fun` or `rec` (a closure would observe later
+//!   assignments), and
+//! - is not shadowed by a binder or pattern, and no `load` occurs in the body.
+//!
+//! Passing `r` to a function, storing it in a structure, returning it or
+//! aliasing it are bare uses and keep the reference as is.
+//!
+//! # Constant folding
+//!
+//! [`fold_constants`] evaluates what does not depend on run time:
+//!
+//! ```parlang
+//! let x = 5 in if x > 3 then x * 2 + 1 else 0 -- => 11
+//! ```
+//!
+//! Operators applied to literals are computed with the evaluator's own
+//! rules, conditionals on a literal condition keep only the branch taken,
+//! projections from tuples of literals pick the element, and `let` bindings
+//! of literals are inlined into their body unless it loads a library. The
+//! binding itself is only dropped once the body refers to no other names, so
+//! an unbound variable is reported with the same suggestions as before. An
+//! operation that would raise, such as `1 / 0` or an overflowing addition,
+//! is left in place so the error still happens when the program runs. Only
+//! literals are substituted, so closures capture the same values as before.
+
+use std::collections::HashMap;
+
+use crate::ast::{BinOp, Expr};
+use crate::core::PrimOp;
+use crate::eval::{eval_binop, eval_prim, Value};
+use crate::span::Span;
+use crate::subst::{free_vars, pattern_vars, substitute};
+
+/// Apply all optimization passes to an expression
+pub fn optimize(expr: &Expr) -> Expr {
+    fold_constants(localize_refs(expr))
+}
+
+/// Apply all optimization passes to a parsed program, keeping its spans
+///
+/// `spans` are the spans of the nodes of `expr` in the order of
+/// [`Expr::subexpressions`], as
+/// [`parse_program_with_spans`](crate::parser::parse_program_with_spans)
+/// returns them; the spans returned are those of the optimized program, in
+/// the same order, for [`eval_with_spans`](crate::eval::eval_with_spans).
+/// A node that can fail keeps its span wherever the passes move it, and a
+/// node that takes the place of one, such as the branch kept from a
+/// conditional on a literal, has that node's span unless it has its own.
+///
+/// ```
+/// use parlang::optimize::optimize_with_spans;
+/// use parlang::parser::parse_program_with_spans;
+///
+/// let source = "let x = 1 in if x > 0 then x + true else 0";
+/// let (expr, spans) = parse_program_with_spans(source).unwrap();
+/// let (optimized, spans) = optimize_with_spans(&expr, &spans);
+/// assert_eq!(optimized, parlang::parse("1 + true").unwrap());
+/// let span = spans[0].unwrap();
+/// assert_eq!(&source[span.start..span.end], "x + true");
+/// ```
+pub fn optimize_with_spans(expr: &Expr, spans: &[Option<Span>]) -> (Expr, Vec<Option<Span>>) {
+    let by_node = expr
+        .subexpressions()
+        .zip(spans)
+        .filter_map(|(node, span)| Some((std::ptr::from_ref(node), (*span)?)))
+        .collect();
+    let mut marked_spans = Vec::new();
+    let marked = mark_spans(expr, &by_node, &mut marked_spans);
+    let mut spans = Vec::new();
+    let optimized = strip_spans(optimize(&marked), &marked_spans, &mut spans);
+    (optimized, spans)
+}
+
+/// Constructor name of the markers [`optimize_with_spans`] wraps nodes in
+/// while the passes run; user constructors cannot start with `#`
+const SPAN_MARKER: &str = "#span";
+
+/// Wrap each node of `expr` that can fail and has a span in a marker holding
+/// the index of the span in `marked_spans`
+///
+/// These are the nodes [`lower_with_spans`](crate::core::lower_with_spans)
+/// wraps in their span; literals and bindings stay bare, so the passes see
+/// them as they are.
+fn mark_spans(expr: &Expr, spans: &HashMap<*const Expr, Span>, marked_spans: &mut Vec<Span>) -> Expr {
+    let marked = map_children(expr, &mut |child| mark_spans(child, spans, marked_spans));
+    let can_fail = matches!(
+        expr,
+        Expr::Var(_)
+            | Expr::BinOp(..)
+            | Expr::Neg(_)
+            | Expr::Ref(_)
+            | Expr::Deref(_)
+            | Expr::RefAssign(..)
+            | Expr::ArrayIndex(..)
+            | Expr::Range(..)
+            | Expr::TupleProj(..)
+            | Expr::FieldAccess(..)
+            | Expr::Constructor(..)
+            | Expr::If(..)
+            | Expr::IfChain(..)
+            | Expr::Match(..)
+            | Expr::App(..)
+    );
+    match spans.get(&std::ptr::from_ref(expr)) {
+        Some(span) if can_fail => {
+            let index = i64::try_from(marked_spans.len()).expect("fewer spans than nodes");
+            marked_spans.push(*span);
+            Expr::Constructor(SPAN_MARKER.to_string(), vec![Expr::Int(index), marked])
+        }
+        _ => marked,
+    }
+}
+
+/// The node inside any span markers around `expr`
+fn unmarked(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Constructor(name, parts) if name == SPAN_MARKER => unmarked(&parts[1]),
+        expr => expr,
+    }
+}
+
+/// Remove the span markers from `expr`, pushing the span of each remaining
+/// node onto `spans` in the order of [`Expr::subexpressions`]; of the
+/// markers around a node, the innermost gives its span
+fn strip_spans(mut expr: Expr, marked_spans: &[Span], spans: &mut Vec<Option<Span>>) -> Expr {
+    let mut span = None;
+    loop {
+        match expr {
+            Expr::Constructor(name, mut parts) if name == SPAN_MARKER => {
+                let node = parts.pop().expect("span markers hold a node");
+                if let Some(Expr::Int(index)) = parts.first() {
+                    span = usize::try_from(*index).ok().and_then(|index| marked_spans.get(index)).copied();
+                }
+                expr = node;
+            }
+            node => {
+                spans.push(span);
+                return node.map_children(&mut |child| strip_spans(child, marked_spans, spans));
+            }
+        }
+    }
+}
+
+/// Evaluate the constant parts of an expression ahead of time
+pub fn fold_constants(expr: Expr) -> Expr {
+    expr.transform(&mut fold_node)
+}
+
+/// Fold one node whose children are already folded
+fn fold_node(expr: Expr) -> Expr {
+    match expr {
+        // A constant cannot fail, so it needs no span
+        Expr::Constructor(name, mut parts) if name == SPAN_MARKER && is_constant(&parts[1]) => {
+            parts.pop().expect("span markers hold a node")
+        }
+        Expr::BinOp(op, left, right) => fold_binop(op, left, right),
+        Expr::Neg(inner) => literal_value(&inner)
+            .and_then(|value| eval_prim(PrimOp::Neg, vec![value]).ok())
+            .and_then(|value| literal_expr(&value))
+            .unwrap_or(Expr::Neg(inner)),
+        Expr::If(cond, then_branch, else_branch) => match *cond {
+            Expr::Bool(true) => *then_branch,
+            Expr::Bool(false) => *else_branch,
+            cond => Expr::If(Box::new(cond), then_branch, else_branch),
+        },
+        Expr::IfChain(branches, else_branch) => fold_if_chain(branches, *else_branch),
+        Expr::TupleProj(tuple, index) => match *tuple {
+            Expr::Tuple(elements) if index < elements.len() && elements.iter().all(is_constant) => {
+                elements.into_iter().nth(index).unwrap_or(Expr::Tuple(vec![]))
+            }
+            tuple => Expr::TupleProj(Box::new(tuple), index),
+        },
+        // An annotated binding is kept, as the annotation is checked, and so is
+        // one whose body loads a library, which might bind the same name, and
+        // a pattern binding's value, which its mismatch error shows
+        Expr::Let(name, None, value, body) if literal_value(&value).is_some() && !loads(&body) && !name.starts_with('_') => {
+            let body = fold_constants(substitute(&body, &name, &value));
+            // A body that still refers to other names might fail on an unbound
+            // one, whose error suggests the names in scope, so the binding stays
+            if free_vars(&body).is_empty() {
+                body
+            } else {
+                Expr::Let(name, None, value, Box::new(body))
+            }
+        }
+        expr => expr,
+    }
+}
+
+/// Compute an operator applied to two literals, unless doing so raises
+fn fold_binop(op: BinOp, left: Box<Expr>, right: Box<Expr>) -> Expr {
+    literal_value(&left)
+        .zip(literal_value(&right))
+        .and_then(|(a, b)| eval_binop(op, a, b).ok())
+        .and_then(|value| literal_expr(&value))
+        .unwrap_or(Expr::BinOp(op, left, right))
+}
+
+/// Drop the branches of a chain whose condition is `false`, and everything
+/// after the first whose condition is `true`
+fn fold_if_chain(branches: Vec<(Expr, Expr)>, else_branch: Expr) -> Expr {
+    let mut kept = Vec::new();
+    let mut otherwise = else_branch;
+    for (cond, branch) in branches {
+        match cond {
+            Expr::Bool(false) => {}
+            Expr::Bool(true) => {
+                otherwise = branch;
+                break;
+            }
+            cond => kept.push((cond, branch)),
+        }
+    }
+    match kept.len() {
+        0 => otherwise,
+        1 => {
+            let (cond, branch) = kept.remove(0);
+            Expr::If(Box::new(cond), Box::new(branch), Box::new(otherwise))
+        }
+        _ => Expr::IfChain(kept, Box::new(otherwise)),
+    }
+}
+
+/// The value of a literal expression
+fn literal_value(expr: &Expr) -> Option<Value> {
+    match expr {
+        Expr::Int(n) => Some(Value::Int(*n)),
+        Expr::Bool(b) => Some(Value::Bool(*b)),
+        Expr::Char(c) => Some(Value::Char(*c)),
+        Expr::Float(f) => Some(Value::Float(*f)),
+        Expr::Byte(b) => Some(Value::Byte(*b)),
+        _ => None,
+    }
+}
+
+/// The literal expression for a value, if it has one
+fn literal_expr(value: &Value) -> Option<Expr> {
+    match value {
+        Value::Int(n) => Some(Expr::Int(*n)),
+        Value::Bool(b) => Some(Expr::Bool(*b)),
+        Value::Char(c) => Some(Expr::Char(*c)),
+        Value::Float(f) => Some(Expr::Float(*f)),
+        Value::Byte(b) => Some(Expr::Byte(*b)),
+        _ => None,
+    }
+}
+
+/// Whether a `load` occurs anywhere in `expr`
+fn loads(expr: &Expr) -> bool {
+    expr.subexpressions().any(|node| matches!(node, Expr::Load(..) | Expr::LoadAs(..)))
+}
+
+/// Whether evaluating `expr` can neither fail nor have an effect: a literal
+/// or a tuple of them
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Tuple(elements) => elements.iter().all(is_constant),
+        _ => literal_value(expr).is_some(),
+    }
+}
+
+/// Replace references that never escape their binding with plain bindings
+pub fn localize_refs(expr: &Expr) -> Expr {
+    match expr {
+        Expr::Let(name, ty_ann, value, body) => match unmarked(value) {
+            Expr::Ref(init) if stays_local(name, body, true) => Expr::Let(
+                name.clone(),
+                None,
+                Box::new(localize_refs(init)),
+                Box::new(localize_refs(&thread_uses(name, body))),
+            ),
+            _ => Expr::Let(
+                name.clone(),
+                ty_ann.clone(),
+                Box::new(localize_refs(value)),
+                Box::new(localize_refs(body)),
+            ),
+        },
+        Expr::Seq(bindings, body) => {
+            let local = bindings.iter().enumerate().position(|(index, (name, _, value))| {
+                matches!(unmarked(value), Expr::Ref(_))
+                    && stays_local(name, &seq_rest(&bindings[index + 1..], body), true)
+            });
+            match local {
+                Some(index) => {
+                    let (name, _, value) = &bindings[index];
+                    let rest = seq_rest(&bindings[index + 1..], body);
+                    let scope = Expr::Let(name.clone(), None, Box::new(value.clone()), Box::new(rest));
+                    let scope = localize_refs(&scope);
+                    if index == 0 {
+                        scope
+                    } else {
+                        let before = bindings[..index]
+                            .iter()
+                            .map(|(name, ty_ann, value)| (name.clone(), ty_ann.clone(), localize_refs(value)))
+                            .collect();
+                        Expr::Seq(before, Box::new(scope))
+                    }
+                }
+                None => map_children(expr, &mut localize_refs),
+            }
+        }
+        _ => map_children(expr, &mut localize_refs),
+    }
+}
+
+/// The scope of a sequential binding: the bindings after it and the body
+fn seq_rest(rest: &[(String, Option<crate::ast::TypeAnnotation>, Expr)], body: &Expr) -> Expr {
+    if rest.is_empty() {
+        body.clone()
+    } else {
+        Expr::Seq(rest.to_vec(), Box::new(body.clone()))
+    }
+}
+
+/// Whether the reference bound to `name` is used only in ways the rewrite
+/// supports within `expr`; `on_spine` is true while `expr` is reached from the
+/// binding through `let` bodies alone
+fn stays_local(name: &str, expr: &Expr, on_spine: bool) -> bool {
+    let expr = unmarked(expr);
+    let is_ref = |e: &Expr| matches!(unmarked(e), Expr::Var(var) if var == name);
+    let binding_stays_local = |value: &Expr| match unmarked(value) {
+        Expr::RefAssign(target, assigned) if on_spine && is_ref(target) => {
+            stays_local(name, assigned, false)
+        }
+        _ => stays_local(name, value, false),
+    };
+    match expr {
+        Expr::Var(var) => var != name,
+        Expr::Deref(inner) if is_ref(inner) => true,
+        Expr::Let(binder, _, value, body) => {
+            binder != name && binding_stays_local(value) && stays_local(name, body, on_spine)
+        }
+        Expr::Seq(bindings, body) => {
+            bindings
+                .iter()
+                .all(|(binder, _, value)| binder != name && binding_stays_local(value))
+                && stays_local(name, body, on_spine)
+        }
+        Expr::Fun(..) | Expr::Rec(..) => !free_vars(expr).contains(name),
+        Expr::Match(scrutinee, arms) => {
+            stays_local(name, scrutinee, false)
+                && arms.iter().all(|(pattern, guard, result)| {
+                    !pattern_vars(pattern).iter().any(|var| var == name)
+                        && guard.iter().all(|guard| stays_local(name, guard, false))
+                        && stays_local(name, result, false)
+                })
+        }
+        Expr::Load(..) | Expr::LoadAs(..) => false,
+        _ => expr.children().into_iter().all(|child| stays_local(name, child, false)),
+    }
+}
+
+/// Rewrite the uses of a local reference: `!r` becomes `r`, and an
+/// assignment bound by `let x = r := e` becomes `let r = e in let x = ()`,
+/// dropping `let x = ()` when `x` is unused
+fn thread_uses(name: &str, expr: &Expr) -> Expr {
+    let unit = || Expr::Tuple(vec![]);
+    let assigned = |value: &Expr| match unmarked(value) {
+        Expr::RefAssign(target, assigned) if matches!(unmarked(target), Expr::Var(var) if var == name) => {
+            Some(thread_uses(name, assigned))
+        }
+        _ => None,
+    };
+    match expr {
+        Expr::Deref(inner) if matches!(unmarked(inner), Expr::Var(var) if var == name) => {
+            Expr::Var(name.to_string())
+        }
+        Expr::Let(binder, ty_ann, value, body) => {
+            let body = Box::new(thread_uses(name, body));
+            match assigned(value) {
+                // The unit result of the assignment is usually bound to an unused name
+                Some(new_value) if !free_vars(&body).contains(binder) => {
+                    Expr::Let(name.to_string(), None, Box::new(new_value), body)
+                }
+                Some(new_value) => Expr::Let(
+                    name.to_string(),
+                    None,
+                    Box::new(new_value),
+                    Box::new(Expr::Let(binder.clone(), ty_ann.clone(), Box::new(unit()), body)),
+                ),
+                None => Expr::Let(binder.clone(), ty_ann.clone(), Box::new(thread_uses(name, value)), body),
+            }
+        }
+        Expr::Seq(bindings, body) => {
+            let mut threaded = Vec::new();
+            for (binder, ty_ann, value) in bindings {
+                match assigned(value) {
+                    Some(new_value) => {
+                        threaded.push((name.to_string(), None, new_value));
+                        threaded.push((binder.clone(), ty_ann.clone(), unit()));
+                    }
+                    None => threaded.push((binder.clone(), ty_ann.clone(), thread_uses(name, value))),
+                }
+            }
+            Expr::Seq(threaded, Box::new(thread_uses(name, body)))
+        }
+        _ => map_children(expr, &mut |child| thread_uses(name, child)),
+    }
+}
+
+/// Rebuild a node with `f` applied to each direct sub-expression
+fn map_children(expr: &Expr, f: &mut impl FnMut(&Expr) -> Expr) -> Expr {
+    let mut go_box = |e: &Expr| Box::new(f(e));
+    match expr {
+        Expr::Int(_)
+        | Expr::Bool(_)
+        | Expr::Char(_)
+        | Expr::Float(_)
+        | Expr::Byte(_)
+        | Expr::Var(_)
+        | Expr::EmptyBody => expr.clone(),
+        Expr::Let(name, ty_ann, value, body) => {
+            Expr::Let(name.clone(), ty_ann.clone(), go_box(value), go_box(body))
+        }
+        Expr::Fun(param, ty_ann, body) => Expr::Fun(param.clone(), ty_ann.clone(), go_box(body)),
+        Expr::Rec(name, body) => Expr::Rec(name.clone(), go_box(body)),
+        Expr::Seq(bindings, body) => Expr::Seq(
+            bindings
+                .iter()
+                .map(|(name, ty_ann, value)| (name.clone(), ty_ann.clone(), f(value)))
+                .collect(),
+            Box::new(f(body)),
+        ),
+        Expr::Match(scrutinee, arms) => Expr::Match(
+            Box::new(f(scrutinee)),
+            arms.iter().map(|(pattern, guard, result)| (pattern.clone(), guard.as_ref().map(&mut *f), f(result))).collect(),
+        ),
+        Expr::BinOp(op, left, right) => Expr::BinOp(*op, go_box(left), go_box(right)),
+        Expr::If(cond, then_branch, else_branch) => {
+            Expr::If(go_box(cond), go_box(then_branch), go_box(else_branch))
+        }
+        Expr::IfChain(branches, else_branch) => Expr::IfChain(
+            branches.iter().map(|(cond, branch)| (f(cond), f(branch))).collect(),
+            Box::new(f(else_branch)),
+        ),
+        Expr::App(func, arg) => Expr::App(go_box(func), go_box(arg)),
+        Expr::Load(filepath, body) => Expr::Load(filepath.clone(), go_box(body)),
+        Expr::LoadAs(filepath, namespace, body) => Expr::LoadAs(filepath.clone(), namespace.clone(), go_box(body)),
+        Expr::Tuple(elements) => Expr::Tuple(elements.iter().map(f).collect()),
+        Expr::TupleProj(tuple, index) => Expr::TupleProj(go_box(tuple), *index),
+        Expr::TypeAlias(alias, ty_expr, body) => Expr::TypeAlias(alias.clone(), ty_expr.clone(), go_box(body)),
+        Expr::Record(fields) => Expr::Record(fields.iter().map(|(field, e)| (field.clone(), f(e))).collect()),
+        Expr::FieldAccess(record, field) => Expr::FieldAccess(go_box(record), field.clone()),
+        Expr::TypeDef { name, type_params, constructors, body } => Expr::TypeDef {
+            name: name.clone(),
+            type_params: type_params.clone(),
+            constructors: constructors.clone(),
+            body: go_box(body),
+        },
+        Expr::Constructor(ctor, args) => Expr::Constructor(ctor.clone(), args.iter().map(f).collect()),
+        Expr::Array(elements) => Expr::Array(elements.iter().map(f).collect()),
+        Expr::ArrayIndex(arr, index) => Expr::ArrayIndex(go_box(arr), go_box(index)),
+        Expr::Ref(inner) => Expr::Ref(go_box(inner)),
+        Expr::Deref(inner) => Expr::Deref(go_box(inner)),
+        Expr::Neg(inner) => Expr::Neg(go_box(inner)),
+        Expr::RefAssign(target, value) => Expr::RefAssign(go_box(target), go_box(value)),
+        Expr::Range(start, end) => Expr::Range(go_box(start), go_box(end)),
+        Expr::Try(body, err, handler) => Expr::Try(go_box(body), err.clone(), go_box(handler)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, Environment};
+    use crate::parser::parse;
+
+    /// Localize the references of a program, check it evaluates to the same
+    /// value, and return the rewritten source
+    fn optimized(source: &str) -> String {
+        let expr = parse(source).unwrap();
+        let result = localize_refs(&expr);
+        assert_eq!(
+            eval(&result, &Environment::new()).map(|v| v.to_string()),
+            eval(&expr, &Environment::new()).map(|v| v.to_string()),
+            "optimization changed the result of {source}"
+        );
+        result.to_string()
+    }
+
+    fn assert_unchanged(source: &str) {
+        assert_eq!(optimized(source), parse(source).unwrap().to_string(), "rewrote {source}");
+    }
+
+    #[test]
+    fn test_straight_line_ref_is_localized() {
+        let result = optimized("let r = ref 1 in let d = r := !r + 1 in let e = r := !r * 10 in !r");
+        assert!(!result.contains("ref") && !result.contains(":=") && !result.contains('!'), "{result}");
+    }
+
+    #[test]
+    fn test_used_assignment_result_is_kept() {
+        let result = optimized("let r = ref 1 in let d = r := 2 in (d, !r)");
+        assert_eq!(result, "(let r = 1 in (let r = 2 in (let d = () in (d, r))))");
+    }
+
+    #[test]
+    fn test_sequential_bindings_are_localized() {
+        let result = optimized("let total = ref 0; let a = total := !total + 5; let b = total := !total + 6; !total");
+        assert!(!result.contains("ref"), "{result}");
+    }
+
+    #[test]
+    fn test_ref_inside_loop_body_is_localized() {
+        let result = optimized(
+            "let step = fun n -> let r = ref n in let d = r := !r - 1 in !r in \
+             (rec loop -> fun n -> if n == 0 then 0 else loop (step n)) 50",
+        );
+        assert!(result.contains("(fun n -> (let r = n in (let r = (r - 1) in r)))"), "{result}");
+    }
+
+    #[test]
+    fn test_reads_in_nested_expressions_are_localized() {
+        let result = optimized("let r = ref 3 in let d = r := 4 in if !r > 3 then (!r, !r + 1) else (0, 0)");
+        assert!(!result.contains("ref"), "{result}");
+    }
+
+    #[test]
+    fn test_escaping_refs_are_kept() {
+        // Passed to a function
+        assert_unchanged("let f = fun c -> !c in let r = ref 1 in f r");
+        // Stored in a structure
+        assert_unchanged("let r = ref 1 in let p = (r, 2) in !(p.0)");
+        assert_unchanged("let r = ref 1 in let s = { cell: r } in !(s.cell)");
+        // Returned
+        assert_unchanged("!(let r = ref 1 in r)");
+        // Aliased
+        assert_unchanged("let r = ref 5 in let alias = r in let d = r := 10 in !alias");
+        // Captured by a closure
+        assert_unchanged("let r = ref 0 in let inc = fun x -> r := !r + x in let d = inc 2 in !r");
+        assert_unchanged("let r = ref 0 in let get = fun x -> !r in let d = r := 1 in get 0");
+    }
+
+    #[test]
+    fn test_non_straight_line_assignments_are_kept() {
+        // Assignment in a branch
+        assert_unchanged("let r = ref 5 in let d = if !r > 0 then r := !r * 2 else r := 0 in !r");
+        // Assignment in a nested let that is not on the spine
+        assert_unchanged("let r = ref 1 in let a = (let d = r := 2 in 0) in !r + a");
+        // Assignment as a bare expression
+        assert_unchanged("let r = ref 1 in r := 2");
+        // Assignment inside a match arm
+        assert_unchanged("let r = ref 1 in let d = match 0 with | 0 -> r := 2 | _ -> r := 3 in !r");
+    }
+
+    #[test]
+    fn test_shadowing_keeps_ref() {
+        assert_unchanged("let r = ref 1 in let r = 2 in r");
+        assert_unchanged("let r = ref 1 in match 5 with | r -> r");
+    }
+
+    /// Fold the constants of a program, check it evaluates to the same
+    /// value or error, and return the folded program
+    fn folded(source: &str) -> Expr {
+        let expr = parse(source).unwrap();
+        let result = fold_constants(expr.clone());
+        let env = Environment::with_builtins();
+        assert_eq!(
+            eval(&result, &env).map(|v| v.to_string()).map_err(|e| e.to_string()),
+            eval(&expr, &env).map(|v| v.to_string()).map_err(|e| e.to_string()),
+            "folding changed the result of {source}"
+        );
+        result
+    }
+
+    fn assert_folds_to(source: &str, expected: &str) {
+        assert_eq!(folded(source), parse(expected).unwrap(), "folding {source}");
+    }
+
+    #[test]
+    fn test_operators_on_literals_are_folded() {
+        assert_folds_to("1 + 2 * 3", "7");
+        assert_folds_to("-(4 - 6) * 2", "4");
+        assert_folds_to("(10 > 3, 'a' == 'b', 1.5 * 2.0, 3b + 4b)", "(true, false, 3.0, 7b)");
+        assert_folds_to("x + 2 * 3", "x + 6");
+    }
+
+    #[test]
+    fn test_conditionals_on_literals_keep_the_branch_taken() {
+        assert_folds_to("if 1 < 2 then 10 else 1 / 0", "10");
+        assert_folds_to("if false then missing else 2", "2");
+        assert_folds_to("if a then 1 elif false then 2 elif true then 3 else 4", "if a then 1 else 3");
+        assert_folds_to("if a then 1 elif b then 2 elif 1 == 2 then 3 else 4", "if a then 1 elif b then 2 else 4");
+    }
+
+    #[test]
+    fn test_literal_lets_and_projections_are_folded() {
+        assert_folds_to("let x = 5 in x + 1", "6");
+        assert_folds_to("let x = 5 in x + y", "let x = 5 in 5 + y");
+        assert_folds_to("let x = 5 in let f = fun y -> x * 2 + y in f 1", "let f = fun y -> 10 + y in f 1");
+        assert_folds_to("(1, (2, 3)).1.0", "2");
+        assert_folds_to("let x = 5 in let x = x + 1 in x", "6");
+    }
+
+    #[test]
+    fn test_operations_that_raise_are_kept() {
+        for source in [
+            "1 / 0",
+            "9223372036854775807 + 1",
+            "255b + 1b",
+            "1 + true",
+            "if 1 then 2 else 3",
+            "(1, 2).5",
+            "(missing, 2).1",
+            "let x: Int = 5 in x",
+        ] {
+            assert_eq!(folded(source), parse(source).unwrap(), "folded {source}");
+        }
+        assert_folds_to("let d = 7 in 1 / (d - 7)", "1 / 0");
+        assert_folds_to("let x = 1 in load \"lib.par\" in x + 1", "let x = 1 in load \"lib.par\" in x + 1");
+        assert_folds_to("let (a, b) = 1 in a", "let (a, b) = 1 in a");
+    }
+
+    #[test]
+    fn test_spans_follow_the_rewritten_nodes() {
+        let source = "let r = ref (f 0) in let d = r := !r + 1 in if 2 > 1 then !r + true else 0";
+        let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+        let (optimized, spans) = optimize_with_spans(&expr, &spans);
+        assert_eq!(optimized, parse("let r = f 0 in let r = r + 1 in r + true").unwrap());
+        let located: Vec<_> = spans.iter().flatten().map(|span| &source[span.start..span.end]).collect();
+        assert_eq!(located, ["f 0", "f", "!r + 1", "!r", "!r + true", "!r"]);
+        assert_eq!(optimize_with_spans(&expr, &[]).0, optimize(&expr));
+    }
+}
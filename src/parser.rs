@@ -1,13 +1,19 @@
 /// Parser for the `ParLang` language using the combine parser combinator library
 /// This implements a parser for ML-alike functional language syntax
 use crate::ast::{BinOp, Expr, Literal, Pattern, TypeAnnotation};
+use crate::span::Span;
+use crate::subst::{free_vars, fresh_name, pattern_vars};
 use combine::error::StreamError;
-use combine::parser::char::{alpha_num, letter, spaces, string};
+use combine::parser::char::{alpha_num, spaces, string};
+use combine::stream::position::{Positioner, RangePositioner, SourcePosition};
 use combine::stream::StreamErrorFor;
 use combine::{
-    attempt, between, choice, many, many1, optional, parser, token, EasyParser, Parser,
+    attempt, between, choice, count_min_max, look_ahead, many, many1, optional, parser, token, EasyParser, Parser,
     ParseError, Stream,
 };
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::ops::Range;
 
 /// Helper function to check if a string starts with an uppercase ASCII character.
 /// Used to distinguish concrete types (Int, Bool) from type variables (a, b).
@@ -18,7 +24,7 @@ fn starts_with_uppercase(s: &str) -> bool {
 /// Parse an integer literal
 fn int<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     // Parse digits and convert to i64
@@ -40,7 +46,7 @@ where
 /// Parse a floating point literal
 fn float<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     // Parse optional sign, digits, then check for ".digit" pattern
@@ -70,7 +76,7 @@ where
 /// Parse a byte literal (unsigned 8-bit integer with 'b' suffix)
 fn byte<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     // Parse digits followed by 'b' suffix
@@ -88,7 +94,7 @@ where
 /// Parse a boolean literal
 fn bool_literal<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     choice((
@@ -100,7 +106,7 @@ where
 /// Parse a character literal
 fn char_literal<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
@@ -124,7 +130,7 @@ where
 /// Parse a single character inside a string literal, handling escape sequences
 fn string_char<Input>() -> impl Parser<Input, Output = char>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     choice((
@@ -138,8 +144,39 @@ where
             token('\'').map(|_| '\''),
             token('0').map(|_| '\0'),
         )))),
-        // Regular character (not quote or backslash)
-        combine::satisfy(|c: char| c != '"' && c != '\\'),
+        // Regular character (not quote, backslash, or an interpolation brace)
+        combine::satisfy(|c: char| !matches!(c, '"' | '\\' | '{' | '}')),
+    ))
+}
+
+/// A piece of a string literal: a character or an interpolated expression
+enum StringPiece {
+    Char(char),
+    Hole(Expr),
+}
+
+/// Parse a piece of a string literal
+///
+/// `{{` and `}}` stand for literal braces; `{ expr }` interpolates an
+/// expression; a lone `}` is an error.
+fn string_piece<Input>() -> impl Parser<Input, Output = StringPiece>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    choice((
+        attempt(string("{{")).map(|_| StringPiece::Char('{')),
+        attempt(string("}}")).map(|_| StringPiece::Char('}')),
+        token('{')
+            .skip(spaces())
+            .with(expr())
+            .skip(spaces())
+            .skip(token('}').expected("`}` to close the interpolation started with `{`"))
+            .map(StringPiece::Hole),
+        token('}')
+            .with(combine::unexpected_any("lone `}` in string literal (write `}}` for a literal brace)"))
+            .map(|()| StringPiece::Char('}')),
+        string_char().map(StringPiece::Char),
     ))
 }
 
@@ -154,7 +191,7 @@ where
 /// to `List Char`.
 fn raw_string<Input>() -> impl Parser<Input, Output = String>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
@@ -170,25 +207,82 @@ where
 /// - "abc" desugars to: Cons 'a' (Cons 'b' (Cons 'c' Nil))
 /// - "" desugars to: Nil
 ///
+/// Braces interpolate expressions, which are rendered with the `show`
+/// built-in and joined with `^`:
+/// - "x = {x}!" desugars to: "x = " ^ (show x ^ "!")
+/// - "{{}}" is the two-character string `{}`
+///
 /// This function parses the string and builds the appropriate nested Constructor/App structure.
 fn string_literal<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
         token('"'),
         token('"'),
-        many(string_char()),
+        many(string_piece()),
     )
-    .map(|chars: Vec<char>| {
-        // Desugar string to List Char
-        // Empty string becomes Nil
-        // "abc" becomes Cons 'a' (Cons 'b' (Cons 'c' Nil))
-        desugar_string_to_list(chars)
+    .map(desugar_interpolation)
+}
+
+/// Join the pieces of a string literal into one `List Char` expression
+///
+/// Runs of characters become string literals and holes become `show expr`,
+/// concatenated right-associatively with `^`. Without holes this is exactly
+/// `desugar_string_to_list`.
+fn desugar_interpolation(pieces: Vec<StringPiece>) -> Expr {
+    let mut parts = Vec::new();
+    let mut text = Vec::new();
+    for piece in pieces {
+        match piece {
+            StringPiece::Char(c) => text.push(c),
+            StringPiece::Hole(expr) => {
+                if !text.is_empty() {
+                    parts.push(desugar_string_to_list(std::mem::take(&mut text)));
+                }
+                parts.push(Expr::App(Box::new(Expr::Var("show".to_string())), Box::new(expr)));
+            }
+        }
+    }
+    if parts.is_empty() || !text.is_empty() {
+        parts.push(desugar_string_to_list(text));
+    }
+    let last = parts.pop().expect("at least one part");
+    parts.into_iter().rev().fold(last, |rest, part| {
+        Expr::BinOp(BinOp::Concat, Box::new(part), Box::new(rest))
     })
 }
 
+/// Parse a string literal pattern and desugar it to Cons/Nil constructor patterns
+///
+/// `"ab"` matches exactly the string `ab`: `Cons 'a' (Cons 'b' Nil)`, and
+/// `""` matches `Nil`. Escapes and `{{`/`}}` work as in string literals;
+/// interpolation holes are not allowed.
+fn string_pattern<Input>() -> impl Parser<Input, Output = Pattern>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    between(token('"'), token('"'), many(string_piece()))
+        .and_then(|pieces: Vec<StringPiece>| {
+            pieces
+                .into_iter()
+                .map(|piece| match piece {
+                    StringPiece::Char(c) => Ok(c),
+                    StringPiece::Hole(_) => Err(StreamErrorFor::<Input>::unexpected_static_message(
+                        "interpolation in a string pattern",
+                    )),
+                })
+                .collect::<Result<Vec<char>, _>>()
+        })
+        .map(|chars| {
+            chars.into_iter().rev().fold(Pattern::Constructor("Nil".to_string(), vec![]), |rest, c| {
+                Pattern::Constructor("Cons".to_string(), vec![Pattern::Literal(Literal::Char(c)), rest])
+            })
+        })
+}
+
 /// Helper function to desugar a vector of characters into a List Char expression
 /// 
 /// Builds nested Cons and Nil constructors:
@@ -210,47 +304,98 @@ fn desugar_string_to_list(chars: Vec<char>) -> Expr {
     )
 }
 
+/// Whether `c` can follow the first letter of an identifier or constructor name
+///
+/// Names are ASCII: a letter followed by letters, digits and `_`.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Why `word`, found where an identifier was expected, is not one, if it
+/// is a mistake worth explaining
+///
+/// `word` is the run of Unicode letters, digits and `_` ahead; `None`
+/// means either a valid name or something that is simply not a name, such
+/// as a number.
+fn name_problem(word: &str) -> Option<String> {
+    if word == "_" {
+        Some("wildcard `_` is only valid in patterns".to_string())
+    } else if word.starts_with('_') {
+        Some(format!("identifier `{word}` must start with a letter"))
+    } else {
+        word.chars().find(|c| !c.is_ascii()).map(|c| {
+            format!(
+                "identifier `{word}` contains the non-ASCII character '{c}'; \
+                 identifiers use ASCII letters, digits and `_`"
+            )
+        })
+    }
+}
+
 /// Parse a raw identifier string (including keywords)
+///
+/// An identifier is an ASCII letter followed by ASCII letters, digits and
+/// `_`. A lone `_`, a non-ASCII letter or a trailing `'` (as in OCaml's
+/// `x'`) is recorded as the deferred error; `f'a'` is still `f` applied to
+/// a character literal.
 fn raw_identifier<Input>() -> impl Parser<Input, Output = String>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    (
-        letter(),
-        many1(alpha_num().or(token('_'))).or(combine::value(String::new())),
-    )
-        .map(|(first, rest): (char, String)| format!("{first}{rest}"))
-        .skip(combine::not_followed_by(alpha_num().or(token('_'))))
+    look_ahead(many::<String, _, _>(combine::satisfy(|c: char| c.is_alphanumeric() || c == '_'))).then(|word| {
+        if let Some(problem) = name_problem(&word) {
+            record_deferred_error(|| problem);
+        }
+        (
+            combine::satisfy(|c: char| c.is_ascii_alphabetic()),
+            many::<String, _, _>(combine::satisfy(is_name_char)),
+            look_ahead(many::<String, _, _>(token('\''))),
+            look_ahead(optional(attempt(char_literal()))),
+        )
+            .then(|(first, rest, primes, literal): (char, String, String, Option<Expr>)| {
+                let name = format!("{first}{rest}");
+                if primes.is_empty() || literal.is_some() {
+                    combine::value(name).left()
+                } else {
+                    record_deferred_error(|| {
+                        format!("identifier `{name}{primes}` cannot contain `'`; identifiers use ASCII letters, digits and `_`")
+                    });
+                    combine::unexpected("'").map(move |()| name.clone()).right()
+                }
+            })
+    })
 }
 
 /// Reserved keywords that cannot be used as identifiers
-const KEYWORDS: &[&str] = &[
+pub const KEYWORDS: &[&str] = &[
     "let", "in", "if", "then", "else", "fun", "true", "false", 
-    "load", "rec", "match", "with", "type", "ref"
+    "load", "rec", "match", "with", "type", "ref", "function", "elif",
+    "try", "catch", "when"
 ];
 
 /// Parse an identifier (variable name) - ensures it's not a keyword
 fn identifier<Input>() -> impl Parser<Input, Output = String>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
-    raw_identifier().then(|name: String| {
-        // Reject keywords by returning a failing parser
-        if KEYWORDS.contains(&name.as_str()) {
+    // Look at the word before consuming it, so a keyword is reported where it starts
+    look_ahead(many::<String, _, _>(combine::satisfy(is_name_char))).then(|word: String| {
+        if KEYWORDS.contains(&word.as_str()) {
             // Use a parser that will never succeed to reject keywords
-            combine::unexpected("keyword").map(move |()| name.clone()).right()
+            combine::unexpected("keyword").map(|()| String::new()).right()
         } else {
-            combine::value(name).left()
+            raw_identifier().left()
         }
     })
+    .expected("an identifier")
 }
 
 /// Parse a variable reference
 fn variable<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     identifier().map(Expr::Var)
@@ -259,12 +404,12 @@ where
 /// Parse a constructor name (starts with uppercase)
 fn constructor_name<Input>() -> impl Parser<Input, Output = String>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     (
-        combine::parser::char::upper(),
-        many::<String, _, _>(alpha_num().or(token('_'))),
+        combine::satisfy(|c: char| c.is_ascii_uppercase()),
+        many::<String, _, _>(combine::satisfy(is_name_char)),
     )
         .map(|(first, rest): (char, String)| format!("{first}{rest}"))
         .skip(combine::not_followed_by(alpha_num().or(token('_'))))
@@ -273,7 +418,7 @@ where
 /// Parse a constructor as an expression (without arguments)
 fn constructor<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     constructor_name().map(|name| Expr::Constructor(name, vec![]))
@@ -286,7 +431,7 @@ where
 /// - (expr, expr, ...) -> tuple with 2+ elements
 fn tuple_or_paren<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
@@ -323,7 +468,7 @@ where
 /// Parse a record literal: { field1: expr1, field2: expr2 }
 fn record<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
@@ -344,7 +489,7 @@ where
 /// Parse an array literal: [|e1, e2, e3|]
 fn array<Input>() -> impl Parser<Input, Output = Expr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     between(
@@ -358,9 +503,32 @@ where
     .map(Expr::Array)
 }
 
+/// Parse a list literal and desugar it to Cons/Nil constructors: [e1, e2, e3]
+///
+/// `[1, 2]` desugars to `Cons 1 (Cons 2 Nil)` and `[]` to `Nil`.
+fn list<Input>() -> impl Parser<Input, Output = Expr>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    between(
+        (token('['), combine::not_followed_by(token('|'))).skip(spaces()),
+        token(']'),
+        combine::sep_by(
+            expr().skip(spaces()),
+            token(',').skip(spaces())
+        )
+    )
+    .map(|elements: Vec<Expr>| {
+        elements.into_iter().rev().fold(Expr::Constructor("Nil".to_string(), vec![]), |rest, element| {
+            Expr::Constructor("Cons".to_string(), vec![element, rest])
+        })
+    })
+}
+
 parser! {
     fn atom[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
             attempt(bool_literal()),
@@ -370,6 +538,7 @@ parser! {
             attempt(byte()),
             attempt(int()),
             attempt(array()),
+            attempt(list()),
             attempt(record()),
             attempt(constructor()),  // Try constructor before variable
             attempt(variable()),
@@ -380,25 +549,112 @@ parser! {
 
 parser! {
     fn fun_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
+        // In `fun x : T -> body` the annotation is a single type, as its own
+        // arrows could not be told apart from the one before the body; wrap an
+        // arrow type in parentheses, or write `fun (x : T1 -> T2) -> body`
+        //
+        // The parameter may also be a pattern, as in `fun (x, y) -> x + y`; a
+        // pattern starting with a name must be parenthesized: `fun (Some x) -> x`
+        let param = choice((
+            attempt(annotated_param()).skip(spaces()).map(|(name, ty_ann)| Binder::Name(name, ty_ann)),
+            binding_pattern().skip(spaces()).map(Binder::Pattern),
+            (
+                identifier().skip(spaces()),
+                optional(annotation(type_annotation_atom().skip(spaces()).skip(look_ahead(string("->"))))),
+            )
+                .map(|(name, ty_ann)| Binder::Name(name, ty_ann)),
+        ));
         (
             string("fun").skip(spaces()),
-            identifier().skip(spaces()),
-            optional(
-                token(':').skip(spaces())
-                    .with(type_annotation().skip(spaces()))
-            ),
+            param,
             string("->").skip(spaces()),
             expr(),
         )
-            .map(|(_, param, ty_ann, _, body)| Expr::Fun(param, ty_ann, Box::new(body)))
+            .map(|(_, param, _, body)| desugar_fun(param, body))
+    }
+}
+
+/// What a `fun` parameter or a `let` binds: a name with an optional type
+/// annotation, or a pattern
+#[derive(Debug, Clone)]
+enum Binder {
+    Name(String, Option<TypeAnnotation>),
+    Pattern(Pattern),
+}
+
+parser! {
+    /// Parse a pattern in binding position, which does not start with a name
+    ///
+    /// A parenthesized pattern is the pattern itself rather than a
+    /// one-element tuple, so `fun (Some x) -> x` takes an option.
+    fn binding_pattern[Input]()(Input) -> Pattern
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        look_ahead(combine::satisfy(|c: char| !c.is_ascii_alphabetic()))
+            .with(linear_pattern())
+            .map(|pattern| match pattern {
+                Pattern::Tuple(mut patterns) if patterns.len() == 1 => patterns.remove(0),
+                pattern => pattern,
+            })
+    }
+}
+
+/// Build `fun param -> body`
+///
+/// A pattern parameter becomes a fresh parameter name matched against the
+/// pattern: `fun (x, y) -> e` is `fun p -> match p with | (x, y) -> e`.
+fn desugar_fun(param: Binder, body: Expr) -> Expr {
+    match param {
+        Binder::Name(name, ty_ann) => Expr::Fun(name, ty_ann, Box::new(body)),
+        Binder::Pattern(pattern) => {
+            let param = fresh_name("__param", &free_vars(&body));
+            let body = match pattern {
+                Pattern::Wildcard => body,
+                pattern => Expr::Match(Box::new(Expr::Var(param.clone())), vec![(pattern, None, body)]),
+            };
+            Expr::Fun(param, None, Box::new(body))
+        }
+    }
+}
+
+/// Build `let pattern = value in body`
+///
+/// The value gets a fresh name that is matched against the pattern:
+/// `let (x, y) = v in e` is `let v1 = v in match v1 with | (x, y) -> e`, so
+/// a value the pattern does not match is reported as such, where the value is.
+fn desugar_let(pattern: Pattern, value: Expr, body: Expr) -> Expr {
+    let mut taken = free_vars(&body);
+    taken.extend(free_vars(&value));
+    let bound = fresh_name("__value", &taken);
+    let body = Expr::Match(Box::new(Expr::Var(bound.clone())), vec![(pattern, None, body)]);
+    let body = match marked_span(&value) {
+        Some((start, end)) => mark_span(start, end, body),
+        None => body,
+    };
+    Expr::Let(bound, None, Box::new(value), Box::new(body))
+}
+
+parser! {
+    /// Parse a parenthesized parameter with its type: `(x : T)`
+    fn annotated_param[Input]()(Input) -> (String, Option<TypeAnnotation>)
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        between(
+            token('(').skip(spaces()),
+            token(')'),
+            (
+                identifier().skip(spaces()),
+                annotation(type_annotation().skip(spaces()).skip(look_ahead(token(')')))).map(Some),
+            ),
+        )
     }
 }
 
 parser! {
     fn rec_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("rec").skip(spaces()),
@@ -413,7 +669,7 @@ parser! {
 /// Parse a type expression atom (Int, Bool, or type alias reference)
 fn type_atom<Input>() -> impl Parser<Input, Output = crate::ast::TypeExpr>
 where
-    Input: Stream<Token = char>,
+    Input: Stream<Token = char, Position = SourcePosition>,
     Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
 {
     choice((
@@ -431,7 +687,7 @@ where
 
 parser! {
     fn type_expr[Input]()(Input) -> crate::ast::TypeExpr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         // Parse left-associative function types: T1 -> T2 -> T3 is (T1 -> (T2 -> T3))
         // We parse the first type, then optionally parse "-> type_expr"
@@ -453,7 +709,7 @@ parser! {
 
 parser! {
     fn type_alias_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("type").skip(spaces()),
@@ -472,7 +728,7 @@ parser! {
 // Parse type annotations for sum type definitions
 parser! {
     fn type_annotation[Input]()(Input) -> TypeAnnotation
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         // Parse function types: a -> b
         (
@@ -494,7 +750,7 @@ parser! {
 // Parse atomic type annotation (concrete type, type variable, or applied type)
 parser! {
     fn type_annotation_atom[Input]()(Input) -> TypeAnnotation
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
             // Try applied type first: List a, Option Int
@@ -507,8 +763,20 @@ parser! {
                         combine::value(name).left()
                     }
                 }).skip(spaces()),
-                many1(type_annotation_atom().skip(spaces()))
+                many1(type_annotation_arg().skip(spaces()))
             ).map(|(name, args)| TypeAnnotation::App(name, args))),
+            type_annotation_arg(),
+        ))
+    }
+}
+
+// Parse a type annotation that can be an argument without parentheses: a name
+// or a parenthesized type, so `Node k v (Tree k v)` has four payload types
+parser! {
+    fn type_annotation_arg[Input]()(Input) -> TypeAnnotation
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        choice((
             // Parenthesized type annotation
             attempt(between(
                 token('(').skip(spaces()),
@@ -534,50 +802,58 @@ parser! {
     }
 }
 
+/// Built-in types a type alias can name directly, as in `type Age = Int in ...`
+const BUILTIN_TYPE_NAMES: &[&str] = &["Int", "Bool"];
+
+/// A constructor of a type definition: name and payload types
+type ConstructorDef = (String, Vec<TypeAnnotation>);
+
 // Parse type definitions: type Name a b = Constructor1 T1 T2 | Constructor2 T3 | ...
+// A definition with a single constructor that has no payload and is named
+// like a built-in type is left to `type_alias_expr`
 parser! {
     fn type_def_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("type").skip(spaces()),
             raw_identifier().skip(spaces()),  // type name
             // Type parameters: lowercase identifiers
             many(attempt((
-                combine::parser::char::lower(),
-                many::<String, _, _>(alpha_num().or(token('_')))
+                combine::satisfy(|c: char| c.is_ascii_lowercase()),
+                many::<String, _, _>(combine::satisfy(is_name_char))
             ).map(|(first, rest)| format!("{}{}", first, rest))
              .skip(combine::not_followed_by(alpha_num().or(token('_'))))
              .skip(spaces()))),
             token('=').skip(spaces()),
-            // First constructor (without |)
             (
-                // Constructor name (must start with uppercase)
-                (
-                    combine::parser::char::upper(),
-                    many::<String, _, _>(alpha_num().or(token('_')))
-                ).map(|(first, rest)| format!("{}{}", first, rest))
-                 .skip(combine::not_followed_by(alpha_num().or(token('_'))))
-                 .skip(spaces()),
-                // Constructor argument types
-                many(attempt(type_annotation_atom().skip(spaces())))
-            ),
-            // Additional constructors (each starting with |)
-            many(attempt((
-                token('|').skip(spaces()),
+                // First constructor (without |)
                 (
-                    combine::parser::char::upper(),
-                    many::<String, _, _>(alpha_num().or(token('_')))
-                ).map(|(first, rest)| format!("{}{}", first, rest))
-                 .skip(combine::not_followed_by(alpha_num().or(token('_'))))
-                 .skip(spaces()),
-                many(attempt(type_annotation_atom().skip(spaces())))
-            ))),
+                    // Constructor name (must start with uppercase)
+                    constructor_name().skip(spaces()),
+                    // Constructor argument types
+                    many(attempt(type_annotation_arg().skip(spaces())))
+                ),
+                // Additional constructors (each starting with |)
+                many(attempt((
+                    token('|').skip(spaces()),
+                    constructor_name().skip(spaces()),
+                    many(attempt(type_annotation_arg().skip(spaces())))
+                ))),
+            )
+                .and_then(|(first_ctor, additional_ctors): (ConstructorDef, Vec<(char, String, Vec<TypeAnnotation>)>)| {
+                    // `type Age = Int in ...` is an alias for a built-in type, not a
+                    // sum type with one constructor named `Int`
+                    if additional_ctors.is_empty() && first_ctor.1.is_empty() && BUILTIN_TYPE_NAMES.contains(&first_ctor.0.as_str()) {
+                        return Err(StreamErrorFor::<Input>::unexpected_static_message("built-in type name"));
+                    }
+                    Ok((first_ctor, additional_ctors))
+                }),
             string("in").skip(spaces()),
             expr()
         )
-            .map(|tuple: (_, String, Vec<String>, _, (String, Vec<TypeAnnotation>), Vec<(char, String, Vec<TypeAnnotation>)>, _, Expr)| {
-                let (_, name, type_params, _, first_ctor, additional_ctors, _, body) = tuple;
+            .map(|tuple: (_, String, Vec<String>, _, (ConstructorDef, Vec<(char, String, Vec<TypeAnnotation>)>), _, Expr)| {
+                let (_, name, type_params, _, (first_ctor, additional_ctors), _, body) = tuple;
                 // Combine first constructor with additional constructors
                 let mut constructors = vec![first_ctor];
                 for (_, ctor_name, ctor_types) in additional_ctors {
@@ -594,70 +870,256 @@ parser! {
     }
 }
 
+/// A `let` binding: what it binds and the value
+type LetBinding = (Binder, Expr);
+
 parser! {
-    fn let_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    /// Parse `let name = value` or `let rec name = value`, without what follows
+    ///
+    /// `let rec f = e` binds `f` to `rec f -> e`, so `e` can refer to `f`:
+    /// `let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1)`.
+    ///
+    /// Parameters after the name define a function: `let add x (y : Int) = e`
+    /// is `let add = fun x -> fun (y : Int) -> e`, and may be combined with
+    /// `rec`. The name of a function defined this way has no annotation.
+    /// Without `rec`, a pattern may take the place of the name, as in
+    /// `let (a, b) = pair`, and parameters may be patterns as in `fun`.
+    fn let_binding[Input]()(Input) -> LetBinding
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
+        let param = choice((
+            attempt(annotated_param()).map(|(name, ty_ann)| Binder::Name(name, ty_ann)),
+            binding_pattern().map(Binder::Pattern),
+            identifier().map(|name| Binder::Name(name, None)),
+        ));
+        let named = (identifier().skip(spaces()), many(param.skip(spaces())))
+            .map(|(name, params)| (Binder::Name(name, None), params));
         (
             string("let").skip(spaces()),
-            identifier().skip(spaces()),
-            optional(
-                token(':').skip(spaces())
-                    .with(type_annotation().skip(spaces()))
-            ),
-            token('=').skip(spaces()),
-            expr().skip(spaces()),
-            string("in").skip(spaces()),
+            optional(attempt(keyword("rec").skip(spaces()))),
+            choice((binding_pattern().skip(spaces()).map(|pattern| (Binder::Pattern(pattern), Vec::new())), named)),
+        )
+            .and_then(|(_, rec, (binder, params)): (_, _, (Binder, Vec<Binder>))| {
+                if rec.is_some() && matches!(binder, Binder::Pattern(_)) {
+                    Err(StreamErrorFor::<Input>::message_static_message("`let rec` must bind a name, not a pattern"))
+                } else {
+                    Ok((rec.is_some(), binder, params))
+                }
+            })
+            .then(|(rec, binder, params): (bool, Binder, Vec<Binder>)| {
+                let ty_ann = if matches!(binder, Binder::Name(..)) && params.is_empty() {
+                    optional(annotation(type_annotation().skip(spaces()).skip(look_ahead(token('='))))).left()
+                } else {
+                    combine::value(None).right()
+                };
+                (
+                    ty_ann,
+                    token('=').skip(spaces()),
+                    expr().skip(spaces()),
+                )
+                    .map(move |(ty_ann, _, body)| {
+                        let value = params.iter().rev().fold(body, |body, param| desugar_fun(param.clone(), body));
+                        match &binder {
+                            Binder::Name(name, _) => {
+                                let value = if rec { Expr::Rec(name.clone(), Box::new(value)) } else { value };
+                                (Binder::Name(name.clone(), ty_ann), value)
+                            }
+                            Binder::Pattern(_) => (binder.clone(), value),
+                        }
+                    })
+            })
+    }
+}
+
+parser! {
+    /// Parse `let name = value in body`
+    ///
+    /// A chain of `let ... in` headers is collected in a loop before the
+    /// final body, so programs made of many nested bindings do not need a
+    /// parser call stack as deep as the chain is long. A pattern binding
+    /// `let p = value in body` is `match value with | p -> body`.
+    fn let_expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        let header = || (let_binding(), string("in").skip(spaces())).map(|(binding, _)| binding);
+        (
+            header(),
+            many(attempt(header())),
             expr(),
         )
-            .map(|(_, name, ty_ann, _, value, _, body)| {
-                Expr::Let(name, ty_ann, Box::new(value), Box::new(body))
+            .map(|(first, rest, body): (LetBinding, Vec<LetBinding>, Expr)| {
+                std::iter::once(first).chain(rest).rev().fold(body, |body, (binder, value)| match binder {
+                    Binder::Name(name, ty_ann) => Expr::Let(name, ty_ann, Box::new(value), Box::new(body)),
+                    Binder::Pattern(pattern) => desugar_let(pattern, value, body),
+                })
             })
     }
 }
 
+/// The named bindings of an [`Expr::Seq`] for a top-level `let`
+///
+/// A pattern binding `let p = value;` binds its variables from a single
+/// match: with one variable `x` it is `let x = let p = value in x;`, and
+/// otherwise a fresh name holds the tuple of the variables, which are then
+/// projected from it.
+fn seq_bindings(binder: Binder, value: Expr) -> Vec<(String, Option<TypeAnnotation>, Expr)> {
+    let pattern = match binder {
+        Binder::Name(name, ty_ann) => return vec![(name, ty_ann, value)],
+        Binder::Pattern(pattern) => pattern,
+    };
+    let vars = pattern_vars(&pattern);
+    if let [var] = vars.as_slice() {
+        let value = desugar_let(pattern, value, Expr::Var(var.clone()));
+        return vec![(var.clone(), None, value)];
+    }
+    let bound = fresh_name("__bound", &free_vars(&value));
+    let tuple = Expr::Tuple(vars.iter().cloned().map(Expr::Var).collect());
+    let mut bindings = vec![(bound.clone(), None, desugar_let(pattern, value, tuple))];
+    bindings.extend(vars.into_iter().enumerate().map(|(index, var)| {
+        (var, None, Expr::TupleProj(Box::new(Expr::Var(bound.clone())), index))
+    }));
+    bindings
+}
+
+/// Parse a keyword, making sure it is not just the prefix of an identifier
+fn keyword<Input>(word: &'static str) -> impl Parser<Input, Output = &'static str>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    string(word).skip(combine::not_followed_by(alpha_num().or(token('_'))))
+}
+
+/// Parse `: type` after a bound name, with `ty` parsing the type
+///
+/// `ty` should also check, without consuming it, what must follow the type.
+/// When it fails, the annotation is recorded as the deferred error, so the
+/// parse error quotes the annotation rather than the whole expression.
+fn annotation<Input, P>(ty: P) -> impl Parser<Input, Output = TypeAnnotation>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    P: Parser<Input, Output = TypeAnnotation>,
+{
+    token(':')
+        .skip(spaces())
+        .with((look_ahead(many::<String, _, _>(combine::satisfy(|c| c != '\n'))), optional(attempt(ty))))
+        .then(|(text, ty)| {
+            if let Some(ty) = ty {
+                combine::value(ty).left()
+            } else {
+                record_deferred_error(|| format!("malformed type annotation at '{}'", text.trim_end()));
+                combine::unexpected("type annotation").map(|()| TypeAnnotation::Var(String::new())).right()
+            }
+        })
+}
+
 parser! {
     fn if_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("if").skip(spaces()),
             expr().skip(spaces()),
             string("then").skip(spaces()),
             expr().skip(spaces()),
+            // Further branches: `elif c then e` or `else if c then e`
+            many(attempt((
+                choice((
+                    attempt(keyword("elif")).map(|_| ()),
+                    attempt(keyword("else").skip(spaces()).with(keyword("if"))).map(|_| ()),
+                )).skip(spaces()),
+                expr().skip(spaces()),
+                string("then").skip(spaces()),
+                expr().skip(spaces()),
+            ))),
             string("else").skip(spaces()),
             expr(),
         )
-            .map(|(_, cond, _, then_branch, _, else_branch)| {
-                Expr::If(
-                    Box::new(cond),
-                    Box::new(then_branch),
-                    Box::new(else_branch),
-                )
+            .map(|(_, cond, _, then_branch, more, _, else_branch): (_, Expr, _, Expr, Vec<((), Expr, _, Expr)>, _, Expr)| {
+                if more.is_empty() {
+                    Expr::If(
+                        Box::new(cond),
+                        Box::new(then_branch),
+                        Box::new(else_branch),
+                    )
+                } else {
+                    // Flatten the chain into a single multi-branch node
+                    let mut branches = vec![(cond, then_branch)];
+                    branches.extend(more.into_iter().map(|((), c, _, e)| (c, e)));
+                    Expr::IfChain(branches, Box::new(else_branch))
+                }
             })
     }
 }
 
 parser! {
     fn load_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("load").skip(spaces()),
             raw_string().skip(spaces()),
+            // `as` only has a meaning here, so it is not a reserved keyword
+            optional(
+                attempt(keyword("as").skip(spaces()))
+                    .with(constructor_name().expected("a namespace name").skip(spaces()))
+            ),
             optional((string("in").skip(spaces()), expr())),
         )
-            .map(|(_, filepath, body_opt)| {
+            .map(|(_, filepath, namespace, body_opt)| {
                 let body = body_opt
-                    .map_or(Expr::Int(0), |(_, b)| b);
-                Expr::Load(filepath, Box::new(body))
+                    .map_or(Expr::EmptyBody, |(_, b)| b);
+                match namespace {
+                    Some(namespace) => Expr::LoadAs(filepath, namespace, Box::new(body)),
+                    None => Expr::Load(filepath, Box::new(body)),
+                }
             })
     }
 }
 
 parser! {
+    /// Parse a pattern, possibly a cons pattern `head :: tail`
+    ///
+    /// `::` is right-associative: `a :: b :: rest` is `a :: (b :: rest)`.
     fn pattern[Input]()(Input) -> Pattern
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        (
+            simple_pattern(),
+            optional(attempt((spaces(), string("::"), spaces()).with(pattern()))),
+        )
+            .map(|(head, tail)| match tail {
+                Some(tail) => Pattern::Constructor("Cons".to_string(), vec![head, tail]),
+                None => head,
+            })
+    }
+}
+
+/// Parse a list pattern and desugar it to Cons/Nil constructor patterns: [p1, p2]
+///
+/// `[a, b]` matches exactly the two-element lists: `Cons a (Cons b Nil)`,
+/// and `[]` matches `Nil`.
+fn list_pattern<Input>() -> impl Parser<Input, Output = Pattern>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+{
+    between(
+        (token('['), combine::not_followed_by(token('|'))).skip(spaces()),
+        token(']'),
+        combine::sep_by(pattern().skip(spaces()), token(',').skip(spaces())),
+    )
+    .map(|patterns: Vec<Pattern>| {
+        patterns.into_iter().rev().fold(Pattern::Constructor("Nil".to_string(), vec![]), |rest, pattern| {
+            Pattern::Constructor("Cons".to_string(), vec![pattern, rest])
+        })
+    })
+}
+
+parser! {
+    fn simple_pattern[Input]()(Input) -> Pattern
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
             // Record pattern: { field1: pattern1, field2: pattern2, ... }
@@ -723,6 +1185,10 @@ parser! {
                 )
                 .map(|c| Pattern::Literal(Literal::Char(c)))
             ),
+            // String literal pattern: "yes" (sugar for Cons/Nil patterns)
+            attempt(string_pattern()),
+            // List pattern: [a, b] (sugar for Cons/Nil patterns)
+            attempt(list_pattern()),
             // Byte literal pattern: 0b, 255b (must come before integer)
             attempt({
                 (
@@ -759,11 +1225,32 @@ parser! {
     }
 }
 
+parser! {
+    /// Parse a pattern that binds each variable at most once
+    ///
+    /// A repeated variable is recorded as the deferred error, so the parse
+    /// error names it rather than quoting the whole `match`.
+    fn linear_pattern[Input]()(Input) -> Pattern
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        pattern().then(|pattern| {
+            let vars = pattern_vars(&pattern);
+            let repeated = vars.iter().enumerate().find(|(i, name)| vars[..*i].contains(name)).map(|(_, name)| name);
+            if let Some(name) = repeated {
+                record_deferred_error(|| format!("variable '{name}' is bound more than once in pattern '{pattern}'"));
+                combine::unexpected("repeated pattern variable").map(|()| Pattern::Wildcard).right()
+            } else {
+                combine::value(pattern).left()
+            }
+        })
+    }
+}
+
 // Parse atomic patterns for use in constructor patterns
 // This prevents infinite recursion by not allowing full pattern expressions
 parser! {
     fn pattern_atom[Input]()(Input) -> Pattern
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
             // Wildcard
@@ -790,6 +1277,10 @@ parser! {
                 )
                 .map(|c| Pattern::Literal(Literal::Char(c)))
             ),
+            // String literals
+            attempt(string_pattern()),
+            // List patterns
+            attempt(list_pattern()),
             // Byte literals (must come before integers)
             attempt({
                 (
@@ -847,47 +1338,103 @@ parser! {
     }
 }
 
+parser! {
+    fn match_arms[Input]()(Input) -> Vec<(Pattern, Option<Expr>, Expr)>
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        // Parse arms: many1 of (| pattern [when guard] -> expr)
+        many1((
+            token('|').skip(spaces()),
+            linear_pattern().skip(spaces()),
+            optional(keyword("when").skip(spaces()).with(expr().skip(spaces()))),
+            string("->").skip(spaces()),
+            expr().skip(spaces()),
+        ))
+            .map(|arms: Vec<(char, Pattern, Option<Expr>, _, Expr)>| {
+                arms.into_iter()
+                    .map(|(_, pat, guard, _, result)| (pat, guard, result))
+                    .collect()
+            })
+    }
+}
+
 parser! {
     fn match_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
             string("match").skip(spaces()),
             expr().skip(spaces()),
             string("with").skip(spaces()),
-            // Parse arms: many1 of (| pattern -> expr)
-            many1((
-                token('|').skip(spaces()),
-                pattern().skip(spaces()),
-                string("->").skip(spaces()),
-                expr().skip(spaces()),
-            ))
+            match_arms(),
         )
-            .map(|(_, scrutinee, _, arms): (_, Expr, _, Vec<(char, Pattern, _, Expr)>)| {
-                let parsed_arms: Vec<(Pattern, Expr)> = arms
-                    .into_iter()
-                    .map(|(_, pat, _, result)| (pat, result))
-                    .collect();
-                Expr::Match(Box::new(scrutinee), parsed_arms)
-            })
+            .map(|(_, scrutinee, _, arms)| Expr::Match(Box::new(scrutinee), arms))
     }
 }
 
+parser! {
+    fn function_expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        (
+            string("function")
+                .skip(combine::not_followed_by(alpha_num().or(token('_'))))
+                .skip(spaces()),
+            match_arms(),
+        )
+            .map(|(_, arms)| desugar_function(arms))
+    }
+}
+
+/// Desugar `function | p1 -> e1 | ...` into `fun x -> match x with | p1 -> e1 | ...`
+///
+/// The parameter name is chosen so it cannot capture a variable used in the arms.
+fn desugar_function(arms: Vec<(Pattern, Option<Expr>, Expr)>) -> Expr {
+    let body = Expr::Match(Box::new(Expr::Int(0)), arms);
+    let param = fresh_name("__scrut", &free_vars(&body));
+    let Expr::Match(_, arms) = body else {
+        unreachable!("body was built as a match");
+    };
+    Expr::Fun(
+        param.clone(),
+        None,
+        Box::new(Expr::Match(Box::new(Expr::Var(param)), arms)),
+    )
+}
+
 parser! {
     fn ref_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
-            string("ref").skip(spaces()),
+            keyword("ref").skip(spaces()),
             app_expr(),
         )
             .map(|(_, expr)| Expr::Ref(Box::new(expr)))
     }
 }
 
+parser! {
+    fn try_expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        (
+            keyword("try").skip(spaces()),
+            expr().skip(spaces()),
+            keyword("catch").skip(spaces()),
+            identifier().skip(spaces()),
+            string("->").skip(spaces()),
+            expr(),
+        )
+            .map(|(_, body, _, name, _, handler)| {
+                Expr::Try(Box::new(body), name, Box::new(handler))
+            })
+    }
+}
+
 parser! {
     fn primary[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
             attempt(type_def_expr()),  // Try type def before type alias
@@ -896,7 +1443,9 @@ parser! {
             attempt(load_expr()),
             attempt(if_expr()),
             attempt(match_expr()),
+            attempt(try_expr()),
             attempt(rec_expr()),
+            attempt(function_expr()),  // Try function before fun (shared prefix)
             attempt(fun_expr()),
             attempt(ref_expr()),  // Add ref expression
             attempt(atom()),
@@ -906,13 +1455,13 @@ parser! {
 
 parser! {
     fn proj_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         (
-            primary().skip(spaces()),
+            primary(),
             // Parse projections and array indexing
             many(choice((
-                // Array indexing: [expr]
+                // Array indexing: [expr], directly after the array so `f [x]` applies f to a list
                 attempt(between(
                     token('[').skip(spaces()),
                     token(']'),
@@ -921,7 +1470,7 @@ parser! {
                 // Tuple/field access: .number or .identifier
                 // But not ".." which is the range operator
                 attempt((
-                    token('.'),
+                    spaces().with(token('.')),
                     combine::parser::combinator::not_followed_by(token('.')),
                 ).with(choice((
                     // Try to parse a number first (tuple projection)
@@ -940,7 +1489,14 @@ parser! {
                     .fold(base, |expr, (proj_type, index, field, index_expr)| {
                         match proj_type {
                             0 => Expr::TupleProj(Box::new(expr), index),
-                            1 => Expr::FieldAccess(Box::new(expr), field),
+                            // `Name.field` reaches a binding of the namespace a
+                            // `load ... as Name` bound, as constructors have no fields
+                            1 => match unmarked_ref(&expr) {
+                                Expr::Constructor(name, args) if args.is_empty() => {
+                                    Expr::FieldAccess(Box::new(Expr::Var(name.clone())), field)
+                                }
+                                _ => Expr::FieldAccess(Box::new(expr), field),
+                            },
                             2 => Expr::ArrayIndex(Box::new(expr), Box::new(index_expr.unwrap())),
                             _ => unreachable!("Invalid projection type: {}", proj_type),
                         }
@@ -951,11 +1507,11 @@ parser! {
 
 parser! {
     fn deref_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
         choice((
-            // Parse dereference: !expr
-            attempt((token('!').skip(spaces()), proj_expr())
+            // Parse dereference: !expr, where expr may itself be a dereference
+            attempt((token('!').skip(spaces()), deref_expr())
                 .map(|(_, expr)| Expr::Deref(Box::new(expr)))),
             // Otherwise just parse projection expression
             proj_expr()
@@ -965,13 +1521,18 @@ parser! {
 
 parser! {
     fn app_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
-        (deref_expr().skip(spaces()), many(deref_expr().skip(spaces())))
-            .map(|(func, args): (Expr, Vec<Expr>)| {
+        // An argument cannot start with `-`, so `f -1` and `n -1` subtract
+        let arg = combine::not_followed_by(token('-')).with(spanned(deref_expr()).skip(spaces()));
+        (combine::position(), spanned(deref_expr()).skip(spaces()), many(arg))
+            .map(|(start, func, args): (SourcePosition, Expr, Vec<Expr>)| {
                 // Special handling for constructor applications
                 // If func is a constructor, combine it with all arguments
-                if let Expr::Constructor(name, mut ctor_args) = func {
+                if matches!(unmarked_ref(&func), Expr::Constructor(..)) {
+                    let Expr::Constructor(name, mut ctor_args) = unmarked(func) else {
+                        unreachable!("checked to be a constructor");
+                    };
                     // If the constructor already has arguments (shouldn't happen in our parser),
                     // extend them. Otherwise, just use the provided args.
                     if ctor_args.is_empty() {
@@ -981,247 +1542,1065 @@ parser! {
                         Expr::Constructor(name, ctor_args)
                     }
                 } else {
-                    // Regular function application
-                    args.into_iter()
-                        .fold(func, |f, arg| Expr::App(Box::new(f), Box::new(arg)))
+                    // Regular function application, each call marked from the function to its last argument
+                    args.into_iter().fold(func, |f, arg| {
+                        let end = marked_span(&arg).map(|(_, end)| end);
+                        let app = Expr::App(Box::new(f), Box::new(arg));
+                        match end {
+                            Some(end) => mark_span(start, end, app),
+                            None => app,
+                        }
+                    })
                 }
             })
     }
 }
 
-/// Parse multiplication and division expressions.
-///
-/// This parser implements left-associative binary operations with equal precedence:
-/// - `*` (multiplication)
-/// - `/` (division)
-///
-/// # Precedence
-/// Higher precedence than addition/subtraction, lower than function application.
+parser! {
+    /// Parse a function application, possibly negated
+    ///
+    /// Negation binds looser than application and tighter than any infix
+    /// operator: `-f 3` is `-(f 3)` and `-x * y` is `(-x) * y`. A `-`
+    /// directly before a digit is part of a negative literal instead.
+    fn unary_expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        let minus = (token('-'), combine::not_followed_by(combine::parser::char::digit().or(token('>'))), spaces());
+        choice((
+            attempt(minus).with(unary_expr()).map(|operand| Expr::Neg(Box::new(operand))),
+            app_expr(),
+        ))
+    }
+}
+
+/// How a chain of operators of one precedence level groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(rename_all = "lowercase"))]
+pub enum Associativity {
+    /// `a - b - c` parses as `(a - b) - c`
+    Left,
+    /// `a ^ b ^ c` parses as `a ^ (b ^ c)`
+    Right,
+    /// The operators cannot be chained: `1 < 2 < 3` is a parse error
+    None,
+}
+
+/// The expression an infix operator builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfixOp {
+    /// `Expr::BinOp`
+    Binary(BinOp),
+    /// `Expr::Range`
+    Range,
+    /// `Expr::RefAssign`
+    Assign,
+    /// A `Cons` constructor: `x :: xs` is `Cons x xs`
+    Cons,
+}
+
+impl InfixOp {
+    /// The expression `left op right`
+    fn build(self, left: Expr, right: Expr) -> Expr {
+        match self {
+            InfixOp::Binary(op) => Expr::BinOp(op, Box::new(left), Box::new(right)),
+            InfixOp::Range => Expr::Range(Box::new(left), Box::new(right)),
+            InfixOp::Assign => Expr::RefAssign(Box::new(left), Box::new(right)),
+            InfixOp::Cons => Expr::Constructor("Cons".to_string(), vec![left, right]),
+        }
+    }
+}
+
+/// An infix operator and how it is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Operator {
+    pub symbol: &'static str,
+    pub op: InfixOp,
+}
+
+/// Infix operators that share a precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrecedenceLevel {
+    pub associativity: Associativity,
+    /// Tried in order, so a symbol comes before any symbol that is its prefix
+    pub operators: &'static [Operator],
+}
+
+const fn binary(symbol: &'static str, op: BinOp) -> Operator {
+    Operator { symbol, op: InfixOp::Binary(op) }
+}
+
+/// Infix operators from lowest to highest precedence
 ///
-/// # Associativity
-/// Left-associative: `a * b * c` parses as `(a * b) * c`
+/// The expression parser is generated from this table: each level parses
+/// chains of its operators with the next level as operands, and the last
+/// level's operands are function applications, possibly negated, which bind
+/// tighter than any infix operator.
 ///
 /// # Examples
-/// - `2 * 3` -> `BinOp(Mul, 2, 3)`
-/// - `10 / 2 / 5` -> `BinOp(Div, BinOp(Div, 10, 2), 5)` = `1`
+/// - `1 + 2 * 3` parses as `1 + (2 * 3)` = `7`
+/// - `f x + 1` parses as `(f x) + 1`
+/// - `-x * y` parses as `(-x) * y`, and `n -1` as `n - 1`
+/// - `r := !r + 1` parses as `r := ((!r) + 1)`
+/// - `a := b := c` parses as `a := (b := c)`
+/// - `x + 1 :: xs` parses as `(x + 1) :: xs`, and `1 :: 2 :: []` as `1 :: (2 :: [])`
+pub const OPERATORS: &[PrecedenceLevel] = &[
+    PrecedenceLevel {
+        associativity: Associativity::Right,
+        operators: &[Operator { symbol: ":=", op: InfixOp::Assign }],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::None,
+        operators: &[
+            binary("==", BinOp::Eq),
+            binary("!=", BinOp::Neq),
+            binary("<=", BinOp::Le),
+            binary(">=", BinOp::Ge),
+            binary("<", BinOp::Lt),
+            binary(">", BinOp::Gt),
+        ],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::None,
+        operators: &[Operator { symbol: "..", op: InfixOp::Range }],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::Right,
+        operators: &[binary("^", BinOp::Concat)],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::Right,
+        operators: &[Operator { symbol: "::", op: InfixOp::Cons }],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::Left,
+        operators: &[binary("+", BinOp::Add), binary("-", BinOp::Sub)],
+    },
+    PrecedenceLevel {
+        associativity: Associativity::Left,
+        operators: &[binary("*", BinOp::Mul), binary("/", BinOp::Div)],
+    },
+];
+
 parser! {
-    fn mul_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    fn infix_op[Input](operators: &'static [Operator])(Input) -> InfixOp
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
-        let op = choice((
-            token('*').map(|_| BinOp::Mul),
-            token('/').map(|_| BinOp::Div),
-        ));
+        let (first, rest) = (*operators).split_first().expect("precedence level without operators");
+        // The arrow in `| n when n > 0 -> e` is not a subtraction
+        let first = attempt(string(first.symbol).skip(combine::not_followed_by(token('>')))).map(move |_| first.op);
+        if rest.is_empty() {
+            first.left()
+        } else {
+            first.or(infix_op(rest)).right()
+        }
+    }
+}
 
-        (
-            app_expr().skip(spaces()),
-            many((op.skip(spaces()), app_expr().skip(spaces()))),
-        )
-            .map(|(first, rest): (Expr, Vec<(BinOp, Expr)>)| {
-                rest.into_iter()
-                    .fold(first, |left, (op, right)| {
-                        Expr::BinOp(op, Box::new(left), Box::new(right))
-                    })
-            })
+parser! {
+    fn operand[Input](level: usize)(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        if *level < OPERATORS.len() {
+            infix_expr(*level).left()
+        } else {
+            spanned(unary_expr()).right()
+        }
     }
 }
 
-/// Parse addition and subtraction expressions.
+thread_local! {
+    /// Explanation of the first misplaced construct the current parse ran into
+    ///
+    /// Some mistakes are not errors where they are found, and the eventual
+    /// failure backtracks far away from them. A chain such as `a < b < c`:
+    /// the comparison level parses `a < b` and leaves `< c` to whatever
+    /// follows. A malformed annotation such as `let x : Int -> = 1`: the
+    /// failed `let` is retried as other expressions. The parser records the
+    /// first such mistake here instead, and [`parse`] and
+    /// [`parse_program_with_options`] report it when parsing fails.
+    static DEFERRED_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Take the recorded deferred error, leaving none
+fn take_deferred_error() -> Option<String> {
+    DEFERRED_ERROR.with(RefCell::take)
+}
+
+/// Record `message` as the deferred error, unless one is already recorded
+fn record_deferred_error(message: impl FnOnce() -> String) {
+    DEFERRED_ERROR.with(|deferred| {
+        deferred.borrow_mut().get_or_insert_with(message);
+    });
+}
+
+thread_local! {
+    /// Whether the current parse marks the nodes it builds with their positions
+    ///
+    /// Only [`parse_program_with_spans`] turns this on, so every other parse
+    /// builds exactly the tree it always has.
+    static RECORD_SPANS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Turns [`RECORD_SPANS`] on while it lives
 ///
-/// This parser implements left-associative binary operations with equal precedence:
-/// - `+` (addition)
-/// - `-` (subtraction)
+/// Dropping it puts the flag back as it was, so a parse that returns early
+/// or panics does not leave every later parse on the thread marking nodes.
+struct RecordingSpans {
+    was_recording: bool,
+}
+
+impl RecordingSpans {
+    fn start() -> Self {
+        RecordingSpans { was_recording: RECORD_SPANS.with(|record| record.replace(true)) }
+    }
+}
+
+impl Drop for RecordingSpans {
+    fn drop(&mut self) {
+        RECORD_SPANS.with(|record| record.set(self.was_recording));
+    }
+}
+
+/// Name of the constructor that holds a node and its position while spans are recorded
 ///
-/// # Precedence
-/// Lower precedence than multiplication/division, higher than comparisons.
+/// A marked node is `Constructor(SPAN_MARKER, [start line, start column,
+/// end line, end column, node])`. No constructor can have this name, and
+/// [`strip_spans`] removes every marker before the expression leaves the
+/// parser. Parsers that look inside a sub-expression see through markers
+/// with [`unmarked`].
+const SPAN_MARKER: &str = "#span";
+
+/// `expr` marked as running from `start` to `end`, if spans are being recorded
+fn mark_span(start: SourcePosition, end: SourcePosition, expr: Expr) -> Expr {
+    if !RECORD_SPANS.with(Cell::get) {
+        return expr;
+    }
+    let mut parts: Vec<Expr> = [start.line, start.column, end.line, end.column].map(|n| Expr::Int(i64::from(n))).into();
+    parts.push(expr);
+    Expr::Constructor(SPAN_MARKER.to_string(), parts)
+}
+
+/// The start and end positions of a marked node, or `None` if `expr` is not marked
+fn marked_span(expr: &Expr) -> Option<(SourcePosition, SourcePosition)> {
+    let Expr::Constructor(name, parts) = expr else { return None };
+    if name != SPAN_MARKER {
+        return None;
+    }
+    let coordinate = |i: usize| match parts[i] {
+        Expr::Int(n) => i32::try_from(n).unwrap_or(i32::MAX),
+        _ => unreachable!("span markers start with four coordinates"),
+    };
+    Some((
+        SourcePosition { line: coordinate(0), column: coordinate(1) },
+        SourcePosition { line: coordinate(2), column: coordinate(3) },
+    ))
+}
+
+/// `expr` without the markers around it
+fn unmarked(expr: Expr) -> Expr {
+    match expr {
+        Expr::Constructor(name, mut parts) if name == SPAN_MARKER => unmarked(parts.pop().expect("span markers hold a node")),
+        expr => expr,
+    }
+}
+
+/// The node under the markers around `expr`
+fn unmarked_ref(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Constructor(name, parts) if name == SPAN_MARKER => unmarked_ref(&parts[4]),
+        expr => expr,
+    }
+}
+
+/// The expression `left op right`, marked as running from the start of `left` to the end of `right`
+fn build_marked(op: InfixOp, left: Expr, right: Expr) -> Expr {
+    let start = marked_span(&left).map(|(start, _)| start);
+    let end = marked_span(&right).map(|(_, end)| end);
+    let expr = op.build(left, right);
+    match start.zip(end) {
+        Some((start, end)) => mark_span(start, end, expr),
+        None => expr,
+    }
+}
+
+/// Remove the markers from a tree, collecting the spans they held
 ///
-/// # Associativity
-/// Left-associative: `a + b - c` parses as `(a + b) - c`
+/// `spans` gets one entry per node, in the order of
+/// [`Expr::subexpressions`] of the result: the positions of the innermost
+/// marker around the node, or `None` for an unmarked node.
+fn strip_spans(expr: Expr, spans: &mut Vec<Option<(SourcePosition, SourcePosition)>>) -> Expr {
+    let mut span = None;
+    let mut expr = expr;
+    while let Some(positions) = marked_span(&expr) {
+        span = Some(positions);
+        let Expr::Constructor(_, mut parts) = expr else { unreachable!("marked nodes are constructors") };
+        expr = parts.pop().expect("span markers hold a node");
+    }
+    spans.push(span);
+    expr.map_children(&mut |child| strip_spans(child, spans))
+}
+
+/// Parse `parser`, marking its result with where it starts and ends
+fn spanned<Input, P>(parser: P) -> impl Parser<Input, Output = Expr>
+where
+    Input: Stream<Token = char, Position = SourcePosition>,
+    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    P: Parser<Input, Output = Expr>,
+{
+    (combine::position(), parser, combine::position()).map(|(start, expr, end)| mark_span(start, end, expr))
+}
+
+/// Record a comparison `left op right` followed by another comparison `next_op next`
+fn record_chained_comparison(left: &Expr, op: BinOp, right: &Expr, next_op: BinOp, next: &Expr) {
+    let [left, right, next] = [left, right, next].map(|expr| strip_spans(expr.clone(), &mut Vec::new()));
+    record_deferred_error(|| {
+        format!(
+            "comparison operators cannot be chained; use parentheses or `if` to combine \
+             `{left} {op} {right}` and `{right} {next_op} {next}`"
+        )
+    });
+}
+
+parser! {
+    /// Parse the expressions of precedence level `level` of [`OPERATORS`] and above
+    fn infix_expr[Input](level: usize)(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        let PrecedenceLevel { associativity, operators } = OPERATORS[*level];
+        let max_ops = if associativity == Associativity::None { 1 } else { usize::MAX };
+        // Peek past a non-associative operator for a second one, to explain the chain
+        let chained = if associativity == Associativity::None {
+            look_ahead(optional(attempt((infix_op(operators).skip(spaces()), operand(*level + 1))))).left()
+        } else {
+            combine::value(None).right()
+        };
+        (
+            operand(*level + 1).skip(spaces()),
+            count_min_max(0, max_ops, (infix_op(operators).skip(spaces()), operand(*level + 1).skip(spaces()))),
+            combine::position(),
+            chained,
+        )
+            .flat_map(|(first, rest, position, chained): (Expr, Vec<(InfixOp, Expr)>, _, Option<_>)| {
+                // A chain fails at its second operator, even where that comparison
+                // could apply to an enclosing `let`, `fun` or `if`
+                if let ([(InfixOp::Binary(op), right)], Some((InfixOp::Binary(next_op), next))) = (rest.as_slice(), &chained) {
+                    record_chained_comparison(&first, *op, right, *next_op, next);
+                    let error = StreamErrorFor::<Input>::message_static_message("chained comparison");
+                    return Err(Input::Error::from_error(position, error));
+                }
+                Ok((first, rest))
+            })
+            .map(move |(first, rest): (Expr, Vec<(InfixOp, Expr)>)| match associativity {
+                Associativity::Right => {
+                    let (ops, mut operands): (Vec<InfixOp>, Vec<Expr>) = rest.into_iter().unzip();
+                    operands.insert(0, first);
+                    let last = operands.pop().expect("at least one operand");
+                    ops.into_iter().zip(operands).rev().fold(last, |right, (op, left)| build_marked(op, left, right))
+                }
+                Associativity::Left | Associativity::None => {
+                    rest.into_iter().fold(first, |left, (op, right)| build_marked(op, left, right))
+                }
+            })
+    }
+}
+
+/// Parse a complete expression.
 ///
-/// # Examples
-/// - `1 + 2` -> `BinOp(Add, 1, 2)`
-/// - `10 - 3 + 2` -> `BinOp(Add, BinOp(Sub, 10, 3), 2)` = `9`
+/// This is the top-level expression parser that handles all expression types,
+/// starting with the lowest precedence infix operators (see [`OPERATORS`]).
 parser! {
-    fn add_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
+    fn expr[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
     {
-        let op = choice((
-            token('+').map(|_| BinOp::Add),
-            token('-').map(|_| BinOp::Sub),
-        ));
+        infix_expr(0)
+    }
+}
+
+/// A top-level `let name = value;` binding, with the positions where it starts and ends
+type PositionedBinding<P> = ((P, P), String, Option<TypeAnnotation>, Expr);
 
+parser! {
+    fn program_parts[Input]()(Input) -> (Vec<PositionedBinding<Input::Position>>, Expr)
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
         (
-            mul_expr().skip(spaces()),
-            many((op.skip(spaces()), mul_expr().skip(spaces()))),
-        )
-            .map(|(first, rest): (Expr, Vec<(BinOp, Expr)>)| {
-                rest.into_iter()
-                    .fold(first, |left, (op, right)| {
-                        Expr::BinOp(op, Box::new(left), Box::new(right))
+            spaces(),
+            many(attempt((
+                combine::position(),
+                let_binding(),
+                token(';'),
+                combine::position().skip(spaces()),
+            ))).map(|bindings: Vec<(Input::Position, LetBinding, _, Input::Position)>| {
+                bindings
+                    .into_iter()
+                    .flat_map(|(start, (binder, value), _, end)| {
+                        seq_bindings(binder, value)
+                            .into_iter()
+                            .map(move |(name, ty_ann, value)| ((start, end), name, ty_ann, value))
                     })
-            })
+                    .collect::<Vec<PositionedBinding<Input::Position>>>()
+            }),
+            optional(expr()).skip(spaces())
+        )
+            .map(|((), bindings, body)| (bindings, body.unwrap_or(Expr::EmptyBody)))
+    }
+}
+
+parser! {
+    /// Like [`program_parts`], but requiring a body and the end of the input
+    ///
+    /// It only runs on input that failed to parse: without the optional
+    /// body to fall back on, its error is where parsing got furthest.
+    fn strict_program[Input]()(Input) -> ()
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        (
+            spaces(),
+            many::<Vec<_>, _, _>(attempt((let_binding(), token(';'), spaces()))),
+            expr(),
+            spaces(),
+            combine::eof(),
+        ).map(|_| ())
+    }
+}
+
+/// Assemble a program from its top-level bindings and body
+fn build_program<P>(bindings: Vec<PositionedBinding<P>>, body: Expr) -> Expr {
+    if bindings.is_empty() {
+        body
+    } else {
+        let bindings = bindings
+            .into_iter()
+            .map(|(_, name, ty_ann, value)| (name, ty_ann, value))
+            .collect();
+        Expr::Seq(bindings, Box::new(body))
+    }
+}
+
+parser! {
+    pub fn program[Input]()(Input) -> Expr
+    where [Input: Stream<Token = char, Position = SourcePosition>]
+    {
+        program_parts().map(|(bindings, body)| build_program(bindings, body))
+    }
+}
+
+/// Options for [`parse_program_with_options`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Number of columns a tab advances in reported positions
+    pub tab_width: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions { tab_width: 1 }
+    }
+}
+
+/// Line and column tracking for detailed parse errors
+///
+/// Like combine's `SourcePosition`, but a `\r\n` pair ends a line without
+/// the `\r` taking a column, and a tab advances `tab_width` columns.
+#[derive(Debug, Clone, Copy)]
+struct SourceColumns {
+    position: SourcePosition,
+    tab_width: i32,
+}
+
+impl SourceColumns {
+    fn new(options: ParseOptions) -> Self {
+        SourceColumns {
+            position: SourcePosition::default(),
+            tab_width: i32::try_from(options.tab_width).unwrap_or(i32::MAX),
+        }
+    }
+}
+
+impl Positioner<char> for SourceColumns {
+    type Position = SourcePosition;
+    type Checkpoint = Self;
+
+    fn position(&self) -> SourcePosition {
+        self.position
+    }
+
+    fn update(&mut self, token: &char) {
+        match token {
+            '\n' => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            '\r' => {}
+            '\t' => self.position.column += self.tab_width,
+            _ => self.position.column += 1,
+        }
+    }
+
+    fn checkpoint(&self) -> Self {
+        *self
+    }
+
+    fn reset(&mut self, checkpoint: Self) {
+        *self = checkpoint;
+    }
+}
+
+impl<'a> RangePositioner<char, &'a str> for SourceColumns {
+    fn update_range(&mut self, range: &&'a str) {
+        for c in range.chars() {
+            self.update(&c);
+        }
+    }
+}
+
+/// The line and column of byte `offset` in `input`
+fn position_of(input: &str, options: ParseOptions, offset: usize) -> SourcePosition {
+    let mut positioner = SourceColumns::new(options);
+    for c in input[..offset].chars() {
+        positioner.update(&c);
+    }
+    positioner.position()
+}
+
+/// Byte offsets in `input` of `positions`, which must be in source order
+fn byte_offsets(input: &str, options: ParseOptions, positions: &[SourcePosition]) -> Vec<usize> {
+    let mut positioner = SourceColumns::new(options);
+    let mut chars = input.char_indices();
+    let mut offset = 0;
+    positions
+        .iter()
+        .map(|target| {
+            while positioner.position() != *target {
+                match chars.next() {
+                    Some((i, c)) => {
+                        positioner.update(&c);
+                        offset = i + c.len_utf8();
+                    }
+                    None => break,
+                }
+            }
+            offset
+        })
+        .collect()
+}
+
+/// Drop the byte order mark some editors put at the start of UTF-8 files
+pub(crate) fn strip_bom(input: &str) -> &str {
+    input.strip_prefix('\u{feff}').unwrap_or(input)
+}
+
+/// Whether a source file is blank: nothing but whitespace, after an optional byte order mark
+///
+/// A blank file is a valid program with no bindings, so `load` accepts it
+/// without parsing it.
+pub fn is_blank_source(input: &str) -> bool {
+    strip_bom(input).trim().is_empty()
+}
+
+/// Lines (1-based) of the `match` and `function` keywords in `input`, in source order
+///
+/// Each keyword starts one `Match` expression, so for a program that
+/// parsed, the lines pair up with its matches in pre-order. String and
+/// character literals are skipped, but the expressions interpolated into
+/// strings are scanned.
+pub(crate) fn match_keyword_lines(input: &str) -> Vec<usize> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut in_string = false;
+    // Brace depth outside each interpolation being scanned, innermost last
+    let mut interpolations: Vec<usize> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line += 1;
+        }
+        if in_string {
+            match (c, next) {
+                ('\\', Some('\n')) => {
+                    line += 1;
+                    i += 1;
+                }
+                ('\\', _) | ('{', Some('{')) | ('}', Some('}')) => i += 1,
+                ('{', _) => {
+                    in_string = false;
+                    interpolations.push(depth);
+                }
+                ('"', _) => in_string = false,
+                _ => {}
+            }
+        } else {
+            match c {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' if interpolations.last() == Some(&depth) => {
+                    interpolations.pop();
+                    in_string = true;
+                }
+                '}' => depth = depth.saturating_sub(1),
+                '\'' => {
+                    let close = if next == Some('\\') { i + 3 } else { i + 2 };
+                    if chars.get(close) == Some(&'\'') {
+                        i = close;
+                    }
+                }
+                c if is_name_char(c) => {
+                    let end = (i..chars.len()).find(|&j| !is_name_char(chars[j])).unwrap_or(chars.len());
+                    let word: String = chars[i..end].iter().collect();
+                    if word == "match" || word == "function" {
+                        lines.push(line);
+                    }
+                    i = end;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    lines
+}
+
+/// Source position of a top-level binding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingPosition {
+    /// Name of the bound variable
+    pub name: String,
+    /// Line (1-based) on which the binding's `let` starts
+    pub line: usize,
+    /// Byte range of the binding in the source, from `let` through the closing `;`
+    pub span: Range<usize>,
+}
+
+/// A failed parse: where it failed, and what was expected there
+///
+/// `Display` renders it as `parse error at line 3, column 14: expected 'in',
+/// found 'then'`, followed by the source line with a caret under the column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseErrorInfo {
+    /// Line (1-based) where parsing failed
+    pub line: usize,
+    /// Column (1-based) where parsing failed, a tab advancing `tab_width` columns
+    pub column: usize,
+    /// What the parser would have accepted there, such as `in` or `an expression`
+    pub expected: Vec<String>,
+    /// The token found there, or `None` at the end of the input
+    pub found: Option<String>,
+    /// The source line containing the error
+    pub snippet: String,
+    /// An explanation of a mistake the parser recognised, which replaces
+    /// `expected` and `found` in the description
+    pub message: Option<String>,
+    /// Number of characters of `snippet` before the error
+    snippet_offset: usize,
+}
+
+impl ParseErrorInfo {
+    /// An error at `position` in `input`
+    ///
+    /// An error at the end of the input is moved back to just after the last
+    /// token, so it does not point at trailing blank lines.
+    fn at(input: &str, options: ParseOptions, position: SourcePosition, expected: Vec<String>, message: Option<String>) -> Self {
+        let mut offset = byte_offsets(input, options, &[position])[0];
+        let mut position = position;
+        if input[offset..].trim().is_empty() && offset > input.trim_end().len() {
+            offset = input.trim_end().len();
+            position = position_of(input, options, offset);
+        }
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[offset..].find('\n').map_or(input.len(), |i| offset + i);
+        ParseErrorInfo {
+            line: usize::try_from(position.line).unwrap_or(0),
+            column: usize::try_from(position.column).unwrap_or(0),
+            expected,
+            found: token_at(&input[offset..]),
+            snippet: input[line_start..line_end].trim_end_matches('\r').to_string(),
+            message,
+            snippet_offset: input[line_start..offset].chars().count(),
+        }
+    }
+
+    /// The error without its position: the explanation, or what was expected and what was found
+    pub fn description(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let quote = |item: &String| {
+            if item.starts_with("an ") || item == "end of input" {
+                item.clone()
+            } else {
+                format!("'{item}'")
+            }
+        };
+        let found = self.found.as_ref().map_or_else(|| "end of input".to_string(), |found| format!("'{found}'"));
+        match self.expected.split_last() {
+            None => format!("unexpected {found}"),
+            Some((last, [])) => format!("expected {}, found {found}", quote(last)),
+            Some((last, rest)) => {
+                let rest: Vec<String> = rest.iter().map(quote).collect();
+                format!("expected {} or {}, found {found}", rest.join(", "), quote(last))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "parse error at line {}, column {}: {}", self.line, self.column, self.description())?;
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Tabs are kept so the caret lines up however the terminal expands them
+        let indent: String = self.snippet.chars().take(self.snippet_offset).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{number} | {}", self.snippet)?;
+        write!(f, "{gutter} | {indent}^")
+    }
+}
+
+impl std::error::Error for ParseErrorInfo {}
+
+impl From<ParseErrorInfo> for String {
+    fn from(error: ParseErrorInfo) -> String {
+        error.to_string()
+    }
+}
+
+/// Parse a string into an expression
+/// 
+/// # Errors
+/// 
+/// Returns an error if:
+/// - The input contains invalid syntax
+/// - There is unexpected input after a valid expression
+///
+/// The error gives the line and column where parsing failed; its
+/// `Display` form quotes the source line with a caret under that column.
+pub fn parse(input: &str) -> Result<Expr, ParseErrorInfo> {
+    parse_program_with_options(input, ParseOptions::default()).map(|(expr, _)| expr)
+}
+
+/// Parse a string into a pattern, as written in a `match` arm
+///
+/// Surrounding whitespace is allowed. Use [`crate::eval::match_value`] to
+/// match the result against a value.
+///
+/// # Errors
+///
+/// Returns an error if the input is not a single pattern, or if the
+/// pattern binds a variable more than once.
+///
+/// # Example
+///
+/// ```
+/// use parlang::ast::Pattern;
+/// use parlang::parse_pattern;
+///
+/// let pattern = parse_pattern("Some (x, _)").unwrap();
+/// assert_eq!(pattern.to_string(), "Some (x, _)");
+/// assert!(parse_pattern("(x, x)").is_err());
+/// assert!(matches!(parse_pattern(" n "), Ok(Pattern::Var(_))));
+/// ```
+pub fn parse_pattern(input: &str) -> Result<Pattern, String> {
+    let input = strip_bom(input);
+    take_deferred_error();
+    let stream = combine::stream::position::Stream::with_positioner(input, SourceColumns::new(ParseOptions::default()));
+    let result = spaces().with(linear_pattern()).skip(spaces()).easy_parse(stream);
+    let deferred = take_deferred_error();
+    match result {
+        Ok((pattern, rest)) if rest.input.is_empty() => Ok(pattern),
+        Ok((_, rest)) => Err(deferred.map_or_else(
+            || format!("Unexpected input after pattern: '{}'", rest.input),
+            |message| format!("Parse error: {message}"),
+        )),
+        Err(err) => Err(format!("Parse error: {}", deferred.unwrap_or_else(|| err.to_string()))),
+    }
+}
+
+/// Explain a failed parse caused by misplaced braces in a string literal
+///
+/// Parse failures inside a string literal backtrack to the start of the
+/// enclosing expression, so the parser's own message does not point at the
+/// string. This scans the source for an interpolation `{` that is never
+/// closed, a lone `}`, or a hole whose contents do not parse, returning
+/// `None` when every string literal is well formed, or else the line,
+/// column and explanation of the first problem. Columns are counted as in
+/// other errors, with a tab advancing `tab_width` columns.
+fn interpolation_error(input: &str, tab_width: usize) -> Option<(usize, usize, String)> {
+    enum Context {
+        Code,
+        Str,
+        /// Inside `{ ... }` of an interpolation: line and column of the `{`,
+        /// byte offset of the hole's contents, and the depth of record braces
+        /// opened within it
+        Hole(usize, usize, usize, usize),
+    }
+    let mut stack = vec![Context::Code];
+    let (mut line, mut column) = (1, 0);
+    let mut bad_hole = None;
+    let mut chars = input.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '\n' => {
+                line += 1;
+                column = 0;
+            }
+            '\r' => {}
+            '\t' => column += tab_width,
+            _ => column += 1,
+        }
+        match stack.last_mut()? {
+            Context::Str => match c {
+                '\\' => {
+                    chars.next();
+                    column += 1;
+                }
+                '"' => {
+                    stack.pop();
+                }
+                '{' | '}' if chars.peek().map(|&(_, next)| next) == Some(c) => {
+                    chars.next();
+                    column += 1;
+                }
+                '{' => stack.push(Context::Hole(line, column, offset + 1, 0)),
+                '}' => {
+                    return Some((line, column, "lone `}` in string literal (write `}}` for a literal brace)".to_string()))
+                }
+                _ => {}
+            },
+            Context::Code | Context::Hole(..) => match c {
+                '"' => stack.push(Context::Str),
+                '\'' => {
+                    // Skip a character literal so `'"'` and `'{'` are not misread
+                    let mut escaped = false;
+                    for (_, next) in chars.by_ref() {
+                        column += 1;
+                        if next == '\'' && !escaped {
+                            break;
+                        }
+                        escaped = next == '\\' && !escaped;
+                    }
+                }
+                '{' => {
+                    if let Some(Context::Hole(_, _, _, depth)) = stack.last_mut() {
+                        *depth += 1;
+                    }
+                }
+                '}' => match stack.last_mut() {
+                    Some(&mut Context::Hole(hole_line, hole_column, start, 0)) => {
+                        stack.pop();
+                        if bad_hole.is_none() {
+                            let options = ParseOptions { tab_width };
+                            if let Err(err) = parse_program_with_options(&input[start..offset], options) {
+                                let message = format!("invalid interpolation in string literal: {}", err.description());
+                                bad_hole = Some((hole_line, hole_column, message));
+                            }
+                        }
+                    }
+                    Some(Context::Hole(_, _, _, depth)) => *depth -= 1,
+                    _ => {}
+                },
+                _ => {}
+            },
+        }
     }
+    stack
+        .into_iter()
+        .find_map(|context| match context {
+            Context::Hole(line, column, ..) => Some((
+                line,
+                column,
+                "unterminated interpolation in string literal: expected `}` to close the `{` \
+                 (write `{{` for a literal brace)"
+                    .to_string(),
+            )),
+            _ => None,
+        })
+        .or(bad_hole)
 }
 
-/// Parse range expressions.
+/// Parse a program, also returning the position of each top-level binding
 ///
-/// This parser implements range creation with the `..` operator:
-/// - `a..b` creates an inclusive range from a to b
+/// The positions are listed in the same order as the bindings of the
+/// resulting `Expr::Seq`; the list is empty when the program has no
+/// top-level `let name = value;` bindings.
 ///
-/// # Precedence
-/// Lower precedence than addition/subtraction, higher than comparisons.
+/// # Errors
 ///
-/// # Examples
-/// - `1..10` -> `Range(1, 10)`
-/// - `0..100` -> `Range(0, 100)`
-parser! {
-    fn range_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
-    {
-        (
-            add_expr().skip(spaces()),
-            optional(attempt(string("..")).skip(spaces()).with(add_expr().skip(spaces())))
-        )
-            .map(|(left, rest)| {
-                if let Some(right) = rest {
-                    Expr::Range(Box::new(left), Box::new(right))
-                } else {
-                    left
-                }
-            })
-    }
+/// Returns an error under the same conditions as [`parse`]; the error
+/// message reports the failing line and column.
+pub fn parse_program_with_positions(input: &str) -> Result<(Expr, Vec<BindingPosition>), ParseErrorInfo> {
+    parse_program_with_options(input, ParseOptions::default())
 }
 
-/// Parse comparison expressions.
+/// Like [`parse_program_with_positions`], with control over how columns are counted
 ///
-/// This parser implements comparison operations:
-/// - `==` (equality)
-/// - `!=` (inequality)
-/// - `<` (less than)
-/// - `<=` (less than or equal)
-/// - `>` (greater than)
-/// - `>=` (greater than or equal)
+/// A leading byte order mark is ignored, `\r\n` counts as a single line
+/// terminator, and a tab advances `options.tab_width` columns, so reported
+/// positions match what an editor shows.
 ///
-/// # Precedence
-/// Lowest precedence - comparisons are evaluated last.
-///
-/// # Associativity
-/// Non-associative: comparison operators cannot be chained.
-/// `1 < 2 < 3` is not allowed (unlike in Python).
+/// # Errors
 ///
-/// # Examples
-/// - `5 > 3` -> `BinOp(Gt, 5, 3)` -> `true`
-/// - `1 == 1` -> `BinOp(Eq, 1, 1)` -> `true`
-/// - `1 < 2 < 3` -> Parse error (comparisons don't chain)
-parser! {
-    fn cmp_expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
-    {
-        let op = choice((
-            attempt(string("==")).map(|_| BinOp::Eq),
-            attempt(string("!=")).map(|_| BinOp::Neq),
-            attempt(string("<=")).map(|_| BinOp::Le),
-            attempt(string(">=")).map(|_| BinOp::Ge),
-            attempt(token('<')).map(|_| BinOp::Lt),
-            attempt(token('>')).map(|_| BinOp::Gt),
-        ));
-
-        (range_expr().skip(spaces()), optional(op.skip(spaces()).and(range_expr())))
-            .map(|(left, rest)| {
-                if let Some((op, right)) = rest {
-                    Expr::BinOp(op, Box::new(left), Box::new(right))
-                } else {
-                    left
-                }
-            })
+/// Returns an error under the same conditions as [`parse`], with its
+/// column counted as `options` says.
+pub fn parse_program_with_options(
+    input: &str,
+    options: ParseOptions,
+) -> Result<(Expr, Vec<BindingPosition>), ParseErrorInfo> {
+    let original = input;
+    let input = strip_bom(input);
+    let stream = combine::stream::position::Stream::with_positioner(input, SourceColumns::new(options));
+    take_deferred_error();
+    let result = program_parts().easy_parse(stream);
+    let deferred = take_deferred_error();
+    match result {
+        Ok(((bindings, body), rest)) => {
+            if !rest.input.is_empty() {
+                return Err(parse_failure(input, options, rest.positioner.position, deferred));
+            }
+            let bom = original.len() - input.len();
+            // The bindings a pattern binding stands for share its span, which is looked up once
+            let mut spans: Vec<(SourcePosition, SourcePosition)> = bindings.iter().map(|(span, ..)| *span).collect();
+            spans.dedup();
+            let ends: Vec<SourcePosition> = spans.iter().flat_map(|(start, end)| [*start, *end]).collect();
+            let offsets = byte_offsets(input, options, &ends);
+            let mut item = 0;
+            let positions = bindings
+                .iter()
+                .map(|(span, name, _, _)| {
+                    if spans[item] != *span {
+                        item += 1;
+                    }
+                    BindingPosition {
+                        name: name.clone(),
+                        line: usize::try_from(span.0.line).unwrap_or(0),
+                        span: bom + offsets[2 * item]..bom + offsets[2 * item + 1],
+                    }
+                })
+                .collect();
+            Ok((build_program(bindings, body), positions))
+        }
+        Err(err) => Err(parse_failure(input, options, err.position, deferred)),
     }
 }
 
-/// Parse a complete expression.
+/// Parse a program, also returning the span of each of its nodes
 ///
-/// This is the top-level expression parser that handles all expression types.
-/// It starts with the lowest precedence operator (assignment) and works up.
+/// The spans line up with [`Expr::subexpressions`] of the expression, which
+/// is the one [`parse`] returns; a node the parser made up while desugaring
+/// has none. See [`crate::span`].
 ///
-/// # Operator Precedence (lowest to highest)
-/// 1. Assignment: `:=`
-/// 2. Comparisons: `==`, `!=`, `<`, `<=`, `>`, `>=`
-/// 3. Addition/Subtraction: `+`, `-`
-/// 4. Multiplication/Division: `*`, `/`
-/// 5. Function Application: `f x y`
-/// 6. Atomic expressions: literals, variables, parenthesized expressions
+/// # Errors
 ///
-/// # Examples
-/// - `1 + 2 * 3` parses as `1 + (2 * 3)` = `7`
-/// - `f x + 1` parses as `(f x) + 1`
-parser! {
-    fn expr[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
-    {
-        // Parse assignment: ref_expr := value_expr
-        // Right-associative to support chained assignments
-        (cmp_expr().skip(spaces()), optional(string(":=").skip(spaces()).with(cmp_expr())))
-            .map(|(left, rest)| {
-                if let Some(right) = rest {
-                    Expr::RefAssign(Box::new(left), Box::new(right))
-                } else {
-                    left
-                }
+/// Returns an error under the same conditions as [`parse`].
+pub fn parse_program_with_spans(input: &str) -> Result<(Expr, Vec<Option<Span>>), ParseErrorInfo> {
+    let (expr, _) = {
+        let _recording = RecordingSpans::start();
+        parse_program_with_options(input, ParseOptions::default())?
+    };
+    let mut marked = Vec::new();
+    let expr = strip_spans(expr, &mut marked);
+
+    let source = strip_bom(input);
+    let bom = input.len() - source.len();
+    let mut positions: Vec<SourcePosition> = marked.iter().flatten().flat_map(|&(start, end)| [start, end]).collect();
+    positions.sort_unstable();
+    positions.dedup();
+    let offsets = byte_offsets(source, ParseOptions::default(), &positions);
+    let offset = |position: &SourcePosition| offsets[positions.binary_search(position).unwrap_or_else(|index| index)];
+    let spans = marked
+        .iter()
+        .map(|span| {
+            span.map(|(start, end)| {
+                let (start, end) = (offset(&start), offset(&end).max(offset(&start)));
+                // Parsers skip the whitespace after what they parse, which is not part of it
+                Span::new(bom + start, bom + start + source[start..end].trim_end().len())
             })
+        })
+        .collect();
+    Ok((expr, spans))
+}
+
+/// Describe why `input` failed to parse
+///
+/// A malformed string literal is reported where it is; otherwise the
+/// input is parsed again with [`strict_program`] to find where parsing got
+/// furthest, falling back to `position`, where the first attempt stopped.
+/// A `deferred` explanation replaces what was expected there.
+fn parse_failure(input: &str, options: ParseOptions, position: SourcePosition, deferred: Option<String>) -> ParseErrorInfo {
+    if let Some((line, column, message)) = interpolation_error(input, options.tab_width) {
+        let position = SourcePosition {
+            line: i32::try_from(line).unwrap_or(i32::MAX),
+            column: i32::try_from(column).unwrap_or(i32::MAX),
+        };
+        return ParseErrorInfo::at(input, options, position, Vec::new(), Some(message));
+    }
+    let stream = combine::stream::position::Stream::with_positioner(input, SourceColumns::new(options));
+    take_deferred_error();
+    let strict = strict_program().easy_parse(stream);
+    take_deferred_error();
+    let Err(err) = strict else {
+        return ParseErrorInfo::at(input, options, position, Vec::new(), deferred);
+    };
+    let mut expected: Vec<String> = Vec::new();
+    for error in &err.errors {
+        if let combine::easy::Error::Expected(info) = error {
+            let item = match info {
+                combine::easy::Info::Token(c) => c.to_string(),
+                combine::easy::Info::Range(range) => (*range).to_string(),
+                combine::easy::Info::Owned(text) => text.clone(),
+                combine::easy::Info::Static(text) => (*text).to_string(),
+            };
+            if !item.starts_with("whitespace") && !expected.contains(&item) {
+                expected.push(item);
+            }
+        }
     }
+    ParseErrorInfo::at(input, options, err.position, summarize_expected(expected), deferred)
 }
 
-parser! {
-    pub fn program[Input]()(Input) -> Expr
-    where [Input: Stream<Token = char>]
-    {
-        (
-            spaces(),
-            many(attempt((
-                string("let").skip(spaces()),
-                identifier().skip(spaces()),
-                optional(
-                    token(':').skip(spaces())
-                        .with(type_annotation().skip(spaces()))
-                ),
-                token('=').skip(spaces()),
-                expr().skip(spaces()),
-                token(';').skip(spaces()),
-            ))).map(|bindings: Vec<(_, String, Option<TypeAnnotation>, _, Expr, _)>| {
-                bindings
-                    .into_iter()
-                    .map(|(_, name, ty_ann, _, value, _)| (name, ty_ann, value))
-                    .collect::<Vec<(String, Option<TypeAnnotation>, Expr)>>()
-            }),
-            optional(expr()).skip(spaces())
-        )
-            .map(|((), bindings, body): ((), Vec<(String, Option<TypeAnnotation>, Expr)>, Option<Expr>)| {
-                let body_expr = body.unwrap_or(Expr::Int(0));
-                if bindings.is_empty() {
-                    body_expr
-                } else {
-                    Expr::Seq(bindings, Box::new(body_expr))
-                }
-            })
+/// Tokens that continue or close a construct, as opposed to starting an expression
+const CLOSING_TOKENS: &[&str] = &[
+    "in", "then", "else", "elif", "with", "catch", "when", "->", ")", "]", "}", "|]", ",", "=", ":", ";", "|",
+    "end of input",
+];
+
+/// Tokens that can start an expression, besides keywords
+const EXPRESSION_STARTS: &[&str] = &["digit", "!", "-", "(", "[", "{", "\"", "'"];
+
+/// Reduce the parser's list of expected tokens to the ones worth reporting
+///
+/// After backtracking, the list also holds every token that could have
+/// started the enclosing expression. When it contains closing tokens such as
+/// `in` or `)` those are the useful ones, then a missing name; otherwise
+/// the tokens that can start an expression are summarized as "an expression".
+fn summarize_expected(expected: Vec<String>) -> Vec<String> {
+    let closing: Vec<String> = expected.iter().filter(|item| CLOSING_TOKENS.contains(&item.as_str())).cloned().collect();
+    let starts_expression = |item: &String| EXPRESSION_STARTS.contains(&item.as_str()) || KEYWORDS.contains(&item.as_str());
+    if !closing.is_empty() {
+        closing
+    } else if expected.iter().any(|item| item == "an identifier") && !expected.iter().any(|item| item == "digit") {
+        vec!["an identifier".to_string()]
+    } else if expected.iter().any(|item| item == "digit") || (!expected.is_empty() && expected.iter().all(starts_expression)) {
+        vec!["an expression".to_string()]
+    } else {
+        expected
     }
 }
 
-/// Parse a string into an expression
-/// 
-/// # Errors
-/// 
-/// Returns an error if:
-/// - The input contains invalid syntax
-/// - There is unexpected input after a valid expression
-pub fn parse(input: &str) -> Result<Expr, String> {
-    match program().easy_parse(input) {
-        Ok((expr, rest)) => {
-            if rest.is_empty() {
-                Ok(expr)
-            } else {
-                Err(format!("Unexpected input after expression: '{rest}'"))
-            }
-        }
-        Err(err) => Err(format!("Parse error: {err}")),
+/// The token at the start of `rest`, after whitespace, or `None` at the end of the input
+fn token_at(rest: &str) -> Option<String> {
+    let rest = rest.trim_start();
+    let first = rest.chars().next()?;
+    if first.is_alphanumeric() || first == '_' {
+        Some(rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect())
+    } else if let Some(op) = ["->", "==", "!=", "<=", ">=", ":=", "::", "..", "[|", "|]"].iter().find(|op| rest.starts_with(**op)) {
+        Some((*op).to_string())
+    } else {
+        Some(first.to_string())
     }
 }
 
@@ -1268,6 +2647,85 @@ mod tests {
         assert_eq!(parse("let x = 42 in x"), Ok(expected));
     }
 
+    #[test]
+    fn test_parse_let_rec() {
+        let body = parse("fun n -> if n == 0 then 1 else n * fact (n - 1)").unwrap();
+        let rec_fact = Expr::Rec("fact".to_string(), Box::new(body));
+        assert_eq!(
+            parse("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1) in fact 5"),
+            Ok(Expr::Let(
+                "fact".to_string(),
+                None,
+                Box::new(rec_fact.clone()),
+                Box::new(parse("fact 5").unwrap())
+            ))
+        );
+        assert_eq!(
+            parse("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1);"),
+            Ok(Expr::Seq(vec![("fact".to_string(), None, rec_fact)], Box::new(Expr::EmptyBody)))
+        );
+        // `rec` only starts a recursive binding as a whole word
+        assert_eq!(
+            parse("let recur = 1 in recur"),
+            Ok(Expr::Let("recur".to_string(), None, Box::new(Expr::Int(1)), Box::new(Expr::Var("recur".to_string()))))
+        );
+        assert!(parse("let rec = 1 in 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_binding_patterns() {
+        let pvar = |name: &str| Pattern::Var(name.to_string());
+        let matching_param = |pattern: Pattern, body: &str| {
+            let param = Box::new(Expr::Var("__param".to_string()));
+            Expr::Fun("__param".to_string(), None, Box::new(Expr::Match(param, vec![(pattern, None, parse(body).unwrap())])))
+        };
+        assert_eq!(parse("fun (x, y) -> x + y"), Ok(matching_param(Pattern::Tuple(vec![pvar("x"), pvar("y")]), "x + y")));
+        assert_eq!(parse("fun _ -> 1"), Ok(Expr::Fun("__param".to_string(), None, Box::new(Expr::Int(1)))));
+        assert_eq!(
+            parse("fun (Some x) -> x"),
+            Ok(matching_param(Pattern::Constructor("Some".to_string(), vec![pvar("x")]), "x"))
+        );
+        // A `let` matches a fresh name bound to the value against the pattern
+        let value = Box::new(Expr::Var("__value".to_string()));
+        let body = Expr::Match(value, vec![(Pattern::Tuple(vec![pvar("a"), pvar("b")]), None, parse("a * b").unwrap())]);
+        assert_eq!(
+            parse("let (a, b) = pair in a * b"),
+            Ok(Expr::Let("__value".to_string(), None, Box::new(Expr::Var("pair".to_string())), Box::new(body)))
+        );
+        assert_eq!(parse("let swap (a, b) = (b, a) in swap"), parse("let swap = fun (a, b) -> (b, a) in swap"));
+        // At the top level each variable is bound, from one match of the value
+        assert_eq!(parse("let (a, _) = p;"), parse("let a = let (a, _) = p in a;"));
+        let Ok(Expr::Seq(bindings, _)) = parse("let (a, b) = p;") else { panic!("expected bindings") };
+        let names: Vec<&str> = bindings.iter().map(|(name, _, _)| name.as_str()).collect();
+        assert_eq!(names, ["__bound", "a", "b"]);
+        assert_eq!(bindings[2].2, Expr::TupleProj(Box::new(Expr::Var("__bound".to_string())), 1));
+        // Variables are bound once, `let rec` binds a name, and a name pattern needs parentheses
+        assert!(parse("fun (x, x) -> x").unwrap_err().description().contains("bound more than once"));
+        assert!(parse("let rec (a, b) = p in a").is_err());
+        assert!(parse("fun Some x -> x").is_err());
+    }
+
+    #[test]
+    fn test_parse_let_function_sugar() {
+        assert_eq!(
+            parse("let add x y = x + y in add 1 2"),
+            parse("let add = fun x -> fun y -> x + y in add 1 2")
+        );
+        assert_eq!(parse("let add x y = x + y; add 1 2"), parse("let add = fun x -> fun y -> x + y; add 1 2"));
+        assert_eq!(
+            parse("let rec fact n = if n == 0 then 1 else n * fact (n - 1) in fact 5"),
+            parse("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1) in fact 5")
+        );
+        assert_eq!(
+            parse("let add (x : Int) (y : Int) = x + y in add"),
+            parse("let add = fun (x : Int) -> fun (y : Int) -> x + y in add")
+        );
+        assert_eq!(parse("let pick (x : Int) y = y;"), parse("let pick = fun (x : Int) -> fun y -> y;"));
+        // The name of a function defined with parameters cannot be annotated
+        assert!(parse("let f x : Int = x in f").is_err());
+        assert!(parse("let f (x : Int = x in f").is_err());
+    }
+
     #[test]
     fn test_parse_if() {
         let expected = Expr::If(
@@ -1278,6 +2736,41 @@ mod tests {
         assert_eq!(parse("if true then 1 else 2"), Ok(expected));
     }
 
+    #[test]
+    fn test_parse_elif_chain() {
+        let expected = Expr::IfChain(
+            vec![
+                (Expr::Var("a".to_string()), Expr::Int(1)),
+                (Expr::Var("b".to_string()), Expr::Int(2)),
+            ],
+            Box::new(Expr::Int(3)),
+        );
+        assert_eq!(parse("if a then 1 elif b then 2 else 3"), Ok(expected));
+    }
+
+    #[test]
+    fn test_parse_else_if_chain_is_flattened() {
+        // `else if` and `elif` produce the same flat node, and may be mixed
+        let with_else_if = parse("if a then 1 else if b then 2 else if c then 3 else 4");
+        let with_elif = parse("if a then 1 elif b then 2 elif c then 3 else 4");
+        let mixed = parse("if a then 1 elif b then 2 else if c then 3 else 4");
+        assert!(matches!(&with_elif, Ok(Expr::IfChain(branches, _)) if branches.len() == 3));
+        assert_eq!(with_else_if, with_elif);
+        assert_eq!(mixed, with_elif);
+    }
+
+    #[test]
+    fn test_parse_parenthesized_else_if_stays_nested() {
+        let result = parse("if a then 1 else (if b then 2 else 3)").unwrap();
+        assert!(matches!(result, Expr::If(_, _, _)));
+    }
+
+    #[test]
+    fn test_parse_elif_is_keyword() {
+        assert!(parse("let elif = 1 in elif").is_err());
+        assert!(parse("let elifant = 1 in elifant").is_ok());
+    }
+
     #[test]
     fn test_parse_fun() {
         let expected = Expr::Fun("x".to_string(), None, Box::new(Expr::Var("x".to_string())));
@@ -1617,6 +3110,23 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_parse_load_as() {
+        let expected = Expr::LoadAs(
+            "math.par".to_string(),
+            "Math".to_string(),
+            Box::new(Expr::App(
+                Box::new(Expr::FieldAccess(Box::new(Expr::Var("Math".to_string())), "add".to_string())),
+                Box::new(Expr::Int(1)),
+            )),
+        );
+        assert_eq!(parse("load \"math.par\" as Math in Math.add 1"), Ok(expected));
+        // A namespace names a module, so it is capitalized like a constructor
+        assert!(parse("load \"math.par\" as math in math.add 1").is_err());
+        // `as` stays an ordinary identifier everywhere else
+        assert!(parse("let as = 1 in as").is_ok());
+    }
+
     // Test sequential let bindings
     #[test]
     fn test_parse_seq_single() {
@@ -1660,6 +3170,22 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_seq_without_body() {
+        let result = parse("let x = 42;").unwrap();
+        match result {
+            Expr::Seq(bindings, body) => {
+                assert_eq!(bindings.len(), 1);
+                assert_eq!(*body, Expr::EmptyBody);
+            }
+            _ => panic!("Expected Seq expression"),
+        }
+        assert_eq!(
+            parse("load \"lib.par\""),
+            Ok(Expr::Load("lib.par".to_string(), Box::new(Expr::EmptyBody)))
+        );
+    }
+
     // Test variable names with underscores
     #[test]
     fn test_var_with_underscore() {
@@ -1706,12 +3232,111 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_identifier_grammar() {
+        // Each candidate is parsed on its own and as a `let` name; invalid
+        // ones carry a piece of the expected error message
+        let candidates: &[(&str, Result<(), &str>)] = &[
+            ("x", Ok(())),
+            ("x1", Ok(())),
+            ("foo_bar", Ok(())),
+            ("camelCase", Ok(())),
+            ("x_", Ok(())),
+            ("x__1", Ok(())),
+            ("letx", Ok(())),
+            ("letters", Ok(())),
+            ("ABC", Ok(())),
+            ("_", Err("wildcard `_` is only valid in patterns")),
+            ("_x", Err("identifier `_x` must start with a letter")),
+            ("x'", Err("identifier `x'` cannot contain `'`")),
+            ("x''", Err("identifier `x''` cannot contain `'`")),
+            ("é", Err("non-ASCII character 'é'")),
+            ("café", Err("identifier `café` contains the non-ASCII character 'é'")),
+            ("naïve", Err("non-ASCII character 'ï'")),
+            ("Ωmega", Err("non-ASCII character 'Ω'")),
+            ("x1é", Err("identifier `x1é`")),
+            ("let", Err("")),
+            ("in", Err("")),
+        ];
+        for (name, expected) in candidates {
+            let lowercase = name.starts_with(|c: char| c.is_ascii_lowercase());
+            for source in [name.to_string(), format!("let {name} = 1 in {name}")] {
+                match (parse(&source), expected) {
+                    (Ok(_), Ok(())) => {}
+                    (Err(e), Err(message)) => assert!(e.description().contains(message), "{source}: {e}"),
+                    (result, _) => panic!("{source}: {result:?}"),
+                }
+            }
+            if lowercase && expected.is_ok() {
+                assert_eq!(parse(name), Ok(Expr::Var(name.to_string())));
+            }
+        }
+        // A character literal right after a name is an argument, not part of the name
+        assert_eq!(parse("f'a'"), parse("f 'a'"));
+        assert!(parse("x'y").unwrap_err().description().contains("cannot contain `'`"));
+        assert!(parse("type T = É | B in B").unwrap_err().description().contains("non-ASCII character 'É'"));
+        assert!(parse("match 1 with | _ -> _").unwrap_err().description().contains("wildcard"));
+        assert_eq!(parse("match 1 with | _ -> 0"), Ok(Expr::Match(Box::new(Expr::Int(1)), vec![(Pattern::Wildcard, None, Expr::Int(0))])));
+    }
+
     #[test]
     fn test_parse_error_unmatched_paren() {
         let result = parse("(1 + 2");
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_error_chained_comparison() {
+        let message = |source: &str| parse(source).unwrap_err().description();
+        let chained = "comparison operators cannot be chained; use parentheses or `if` to combine";
+        assert_eq!(message("a < b < c"), format!("{chained} `a < b` and `b < c`"));
+        assert_eq!(message("a == b == c"), format!("{chained} `a == b` and `b == c`"));
+        assert_eq!(message("1 + 2 == 3 + 4 < 5"), format!("{chained} `(1 + 2) == (3 + 4)` and `(3 + 4) < 5`"));
+        // Also where the failure backtracks out of the enclosing expression
+        assert_eq!(message("let x = (a <= b > c) in x"), format!("{chained} `a <= b` and `b > c`"));
+        let err = parse_program_with_positions("let y = 1;\nif a != b != c then 1 else 2").unwrap_err();
+        assert_eq!(err.description(), format!("{chained} `a != b` and `b != c`"));
+        assert_eq!((err.line, err.column), (2, 11));
+        // Also where the chain ends a `let`, `fun` or `if` body, whose value
+        // the second comparison would otherwise apply to
+        let err = parse("let c = 1 in c == c == c").unwrap_err();
+        assert_eq!(err.description(), format!("{chained} `c == c` and `c == c`"));
+        assert_eq!((err.line, err.column), (1, 21));
+        assert_eq!(message("fun c -> c < c < c"), format!("{chained} `c < c` and `c < c`"));
+        assert_eq!(message("if b then c else c >= 1 != d"), format!("{chained} `c >= 1` and `1 != d`"));
+        assert_eq!(message("let f = fun x -> x < 1 in f 0 < 2 < 3"), format!("{chained} `(f 0) < 2` and `2 < 3`"));
+
+        // Grouped comparisons are fine
+        assert!(parse("(a < b) == (c < d)").is_ok());
+        // Other trailing input keeps its message
+        assert_eq!(message("1 2 )"), "expected end of input, found ')'");
+        assert_eq!(message("a < b )"), "expected end of input, found ')'");
+    }
+
+    #[test]
+    fn test_parse_type_def_or_alias() {
+        let Ok(Expr::TypeDef { name, type_params, constructors, body }) =
+            parse("type Option a = Some a | None in match Some 1 with | Some x -> x | None -> 0")
+        else {
+            panic!("expected a type definition")
+        };
+        assert_eq!((name.as_str(), type_params), ("Option", vec!["a".to_string()]));
+        assert_eq!(
+            constructors,
+            vec![("Some".to_string(), vec![TypeAnnotation::Var("a".to_string())]), ("None".to_string(), vec![])]
+        );
+        let Expr::Match(scrutinee, arms) = *body else { panic!("expected a match") };
+        assert_eq!(*scrutinee, Expr::Constructor("Some".to_string(), vec![Expr::Int(1)]));
+        assert_eq!(arms[0].0, Pattern::Constructor("Some".to_string(), vec![Pattern::Var("x".to_string())]));
+
+        // A built-in type on its own is an alias; any other name is a constructor
+        assert_eq!(parse("type Age = Int in 1"), Ok(Expr::TypeAlias("Age".to_string(), crate::ast::TypeExpr::Int, Box::new(Expr::Int(1)))));
+        assert_eq!(parse("type Flag = Bool in 1"), Ok(Expr::TypeAlias("Flag".to_string(), crate::ast::TypeExpr::Bool, Box::new(Expr::Int(1)))));
+        assert!(matches!(parse("type Unit = Unit in Unit"), Ok(Expr::TypeDef { .. })));
+        assert!(matches!(parse("type Boxed = Int Int in 1"), Ok(Expr::TypeDef { .. })));
+        assert!(matches!(parse("type Step = Int -> Int in 1"), Ok(Expr::TypeAlias(..))));
+    }
+
     // Test complex realistic expressions
     #[test]
     fn test_factorial_like() {
@@ -1927,6 +3552,113 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_array_literal_with_whitespace() {
+        let expected = Expr::Array(vec![Expr::Int(1), Expr::Int(2)]);
+        assert_eq!(parse("[| 1 , 2 |]"), Ok(expected.clone()));
+        assert_eq!(parse("[|\n  1,\n  2\n|]"), Ok(expected));
+        assert_eq!(parse("[||]"), Ok(Expr::Array(vec![])));
+        assert_eq!(
+            parse("[|[|1|], [||]|]"),
+            Ok(Expr::Array(vec![Expr::Array(vec![Expr::Int(1)]), Expr::Array(vec![])]))
+        );
+    }
+
+    #[test]
+    fn test_parse_array_index_chains_with_projection() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert_eq!(
+            parse("pairs[0].1"),
+            Ok(Expr::TupleProj(Box::new(Expr::ArrayIndex(var("pairs"), Box::new(Expr::Int(0)))), 1))
+        );
+        assert_eq!(
+            parse("grid[ i ][j]"),
+            Ok(Expr::ArrayIndex(Box::new(Expr::ArrayIndex(var("grid"), var("i"))), var("j")))
+        );
+    }
+
+    #[test]
+    fn test_parse_array_index_binds_tighter_than_application() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert_eq!(
+            parse("f arr[0]"),
+            Ok(Expr::App(var("f"), Box::new(Expr::ArrayIndex(var("arr"), Box::new(Expr::Int(0))))))
+        );
+    }
+
+    #[test]
+    fn test_parse_ref_counter() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert_eq!(
+            parse("let counter = ref 0 in counter := !counter + 1"),
+            Ok(Expr::Let(
+                "counter".to_string(),
+                None,
+                Box::new(Expr::Ref(Box::new(Expr::Int(0)))),
+                Box::new(Expr::RefAssign(
+                    var("counter"),
+                    Box::new(Expr::BinOp(BinOp::Add, Box::new(Expr::Deref(var("counter"))), Box::new(Expr::Int(1)))),
+                )),
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_ref_operator_precedence() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert_eq!(
+            parse("!r + 1"),
+            Ok(Expr::BinOp(BinOp::Add, Box::new(Expr::Deref(var("r"))), Box::new(Expr::Int(1))))
+        );
+        assert_eq!(
+            parse("!r != 1"),
+            Ok(Expr::BinOp(BinOp::Neq, Box::new(Expr::Deref(var("r"))), Box::new(Expr::Int(1))))
+        );
+        assert_eq!(parse("!!r"), Ok(Expr::Deref(Box::new(Expr::Deref(var("r"))))));
+        assert_eq!(
+            parse("a := b := 1"),
+            Ok(Expr::RefAssign(var("a"), Box::new(Expr::RefAssign(var("b"), Box::new(Expr::Int(1))))))
+        );
+        assert_eq!(
+            parse("r := 1 < 2"),
+            Ok(Expr::RefAssign(var("r"), Box::new(Expr::BinOp(BinOp::Lt, Box::new(Expr::Int(1)), Box::new(Expr::Int(2))))))
+        );
+    }
+
+    #[test]
+    fn test_parse_ref_is_a_keyword() {
+        assert_eq!(parse("refx"), Ok(Expr::Var("refx".to_string())));
+        assert!(parse("let ref = 1 in ref").is_err());
+    }
+
+    #[test]
+    fn test_parse_unary_minus() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        let neg = |expr: Expr| Expr::Neg(Box::new(expr));
+        assert_eq!(parse("-x"), Ok(neg(Expr::Var("x".to_string()))));
+        assert_eq!(parse("- x"), Ok(neg(Expr::Var("x".to_string()))));
+        assert_eq!(parse("--x"), Ok(neg(neg(Expr::Var("x".to_string())))));
+        assert_eq!(parse("-(1 + 2)"), Ok(neg(Expr::BinOp(BinOp::Add, Box::new(Expr::Int(1)), Box::new(Expr::Int(2))))));
+        assert_eq!(parse("-f 3"), Ok(neg(Expr::App(var("f"), Box::new(Expr::Int(3))))));
+        assert_eq!(parse("-x * y"), Ok(Expr::BinOp(BinOp::Mul, Box::new(neg(Expr::Var("x".to_string()))), var("y"))));
+        assert_eq!(parse("a - -b"), Ok(Expr::BinOp(BinOp::Sub, var("a"), Box::new(neg(Expr::Var("b".to_string()))))));
+        // A minus before a digit stays part of the literal
+        assert_eq!(parse("-5"), Ok(Expr::Int(-5)));
+        assert_eq!(parse("-2.5"), Ok(Expr::Float(-2.5)));
+    }
+
+    #[test]
+    fn test_parse_minus_never_starts_an_argument() {
+        let sub = |left: Expr, right: Expr| Ok(Expr::BinOp(BinOp::Sub, Box::new(left), Box::new(right)));
+        assert_eq!(parse("1 -2"), sub(Expr::Int(1), Expr::Int(2)));
+        assert_eq!(parse("1 - 2"), sub(Expr::Int(1), Expr::Int(2)));
+        assert_eq!(parse("1-2"), sub(Expr::Int(1), Expr::Int(2)));
+        assert_eq!(parse("f -x"), sub(Expr::Var("f".to_string()), Expr::Var("x".to_string())));
+        assert_eq!(parse("f (-1)"), Ok(Expr::App(Box::new(Expr::Var("f".to_string())), Box::new(Expr::Int(-1)))));
+        assert!(parse("fun x -> -x").is_ok());
+        assert!(parse("match n with | 0 -> -1 | _ -> n").is_ok());
+    }
+
     #[test]
     fn test_parse_tuple_proj_chained() {
         let result = parse("t.0.1");
@@ -2016,6 +3748,36 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    // List tests
+    #[test]
+    fn test_parse_list_literal() {
+        let cons = |head, tail| Expr::Constructor("Cons".to_string(), vec![head, tail]);
+        let nil = Expr::Constructor("Nil".to_string(), vec![]);
+        assert_eq!(parse("[1, 2]"), Ok(cons(Expr::Int(1), cons(Expr::Int(2), nil.clone()))));
+        assert_eq!(parse("[ ]"), Ok(nil.clone()));
+        assert_eq!(parse("0 :: [1]"), Ok(cons(Expr::Int(0), cons(Expr::Int(1), nil))));
+        assert!(matches!(parse("[|1|]"), Ok(Expr::Array(_))));
+    }
+
+    #[test]
+    fn test_parse_list_argument_and_index() {
+        let var = |name: &str| Box::new(Expr::Var(name.to_string()));
+        assert!(matches!(parse("f [x]"), Ok(Expr::App(_, arg)) if matches!(*arg, Expr::Constructor(..))));
+        assert_eq!(parse("a[i]"), Ok(Expr::ArrayIndex(var("a"), var("i"))));
+    }
+
+    #[test]
+    fn test_parse_list_patterns() {
+        let cons = |head, tail| Pattern::Constructor("Cons".to_string(), vec![head, tail]);
+        let nil = || Pattern::Constructor("Nil".to_string(), vec![]);
+        let var = |name: &str| Pattern::Var(name.to_string());
+        assert_eq!(parse_pattern("[]"), Ok(nil()));
+        assert_eq!(parse_pattern("x :: y :: rest"), Ok(cons(var("x"), cons(var("y"), var("rest")))));
+        assert_eq!(parse_pattern("[a, _]"), Ok(cons(var("a"), cons(Pattern::Wildcard, nil()))));
+        assert_eq!(parse_pattern("Some (x :: _)"), Ok(Pattern::Constructor("Some".to_string(), vec![cons(var("x"), Pattern::Wildcard)])));
+        assert!(parse("match xs with | [x, x] -> x").unwrap_err().description().contains("variable 'x' is bound more than once"));
+    }
+
     // String literal tests
     #[test]
     fn test_parse_string_literal() {
@@ -2134,5 +3896,267 @@ mod tests {
         let result = parse(r#""emoji: 🎉""#);
         assert!(result.is_ok());
     }
-}
 
+    #[test]
+    fn test_function_desugars_to_fun_match() {
+        let sugar = parse("function | 0 -> 0 | n -> 1").unwrap();
+        // `__scrut` is not a valid source identifier, so build the explicit form by hand
+        let Expr::Match(_, arms) = parse("match y with | 0 -> 0 | n -> 1").unwrap() else {
+            panic!("Expected Match");
+        };
+        let scrut = "__scrut".to_string();
+        let explicit = Expr::Fun(
+            scrut.clone(),
+            None,
+            Box::new(Expr::Match(Box::new(Expr::Var(scrut)), arms)),
+        );
+        assert_eq!(sugar, explicit);
+    }
+
+    #[test]
+    fn test_function_is_keyword() {
+        assert!(parse("let function = 1 in function").is_err());
+        // Identifiers that merely start with the keyword are fine
+        assert!(parse("let functional = 1 in functional").is_ok());
+    }
+
+    #[test]
+    fn test_function_nested_in_application() {
+        let result = parse("(function | (a, b) -> a + b) (1, 2)").unwrap();
+        match result {
+            Expr::App(func, _) => assert!(matches!(*func, Expr::Fun(_, _, _))),
+            _ => panic!("Expected App"),
+        }
+    }
+
+    #[test]
+    fn test_function_as_rec_body() {
+        let result = parse("rec len -> function | Nil -> 0 | Cons _ rest -> 1 + len rest").unwrap();
+        match result {
+            Expr::Rec(name, body) => {
+                assert_eq!(name, "len");
+                match *body {
+                    Expr::Fun(param, _, match_body) => match *match_body {
+                        Expr::Match(scrutinee, arms) => {
+                            assert_eq!(*scrutinee, Expr::Var(param));
+                            assert_eq!(arms.len(), 2);
+                        }
+                        _ => panic!("Expected Match"),
+                    },
+                    _ => panic!("Expected Fun"),
+                }
+            }
+            _ => panic!("Expected Rec"),
+        }
+    }
+
+    #[test]
+    fn test_function_eval_through_let() {
+        use crate::eval::{eval, Environment, Value};
+        let expr = parse("let classify = function | 0 -> 0 | n -> 1; (classify 0, classify 5)").unwrap();
+        let result = eval(&expr, &Environment::new()).unwrap();
+        assert_eq!(result, Value::Tuple(vec![Value::Int(0), Value::Int(1)]));
+    }
+
+    #[test]
+    fn test_parse_program_with_positions() {
+        let source = "let a = 1;\n\n\nlet b =\n  a + 1;\n  let c = b;\nc";
+        let (expr, positions) = parse_program_with_positions(source).unwrap();
+        assert_eq!(Ok(expr), parse(source));
+        let lines: Vec<(&str, usize)> = positions.iter().map(|p| (p.name.as_str(), p.line)).collect();
+        assert_eq!(lines, vec![("a", 1), ("b", 4), ("c", 6)]);
+    }
+
+    #[test]
+    fn test_binding_spans_cover_the_source_text() {
+        let source = "let a = 1;\n\nlet b =\n  a + 1 ;\r\n\tlet c = \"é\";\nc";
+        let (_, positions) = parse_program_with_positions(source).unwrap();
+        let texts: Vec<&str> = positions.iter().map(|p| &source[p.span.clone()]).collect();
+        assert_eq!(texts, vec!["let a = 1;", "let b =\n  a + 1 ;", "let c = \"é\";"]);
+        let (_, positions) = parse_program_with_options(&format!("\u{feff}{source}"), ParseOptions { tab_width: 4 }).unwrap();
+        assert_eq!(positions[2].span, 3 + source.find("let c").unwrap()..3 + source.rfind(';').unwrap() + 1);
+        let source = "let (a, b) = (1, 2);\nlet c = a;\nc";
+        let (_, positions) = parse_program_with_positions(source).unwrap();
+        let texts: Vec<(&str, &str)> = positions.iter().map(|p| (p.name.as_str(), &source[p.span.clone()])).collect();
+        assert_eq!(
+            texts,
+            vec![("__bound", "let (a, b) = (1, 2);"), ("a", "let (a, b) = (1, 2);"), ("b", "let (a, b) = (1, 2);"), ("c", "let c = a;")]
+        );
+    }
+
+    #[test]
+    fn test_parse_program_with_spans() {
+        let source = "\u{feff}let y = 1;\nf (g y) [1, 2] + Some 3 * -y  \n";
+        let (expr, spans) = parse_program_with_spans(source).unwrap();
+        // Recording spans does not change what is parsed
+        assert_eq!(Ok(expr.clone()), parse(source));
+        assert_eq!(expr.subexpressions().count(), spans.len());
+        let text = |node: &str| {
+            let (_, span) = expr.subexpressions().zip(&spans).find(|(e, _)| e.to_string() == node).unwrap();
+            span.map(|span| &source[span.start..span.end])
+        };
+        // The body, without the whitespace after it
+        assert_eq!(text("(((f (g y)) Cons 1 Cons 2 Nil) + (Some 3 * (-y)))"), Some("f (g y) [1, 2] + Some 3 * -y"));
+        assert_eq!(text("(f (g y))"), Some("f (g y)"));
+        assert_eq!(text("Cons 1 Cons 2 Nil"), Some("[1, 2]"));
+        assert_eq!(text("(Some 3 * (-y))"), Some("Some 3 * -y"));
+        assert_eq!(text("(-y)"), Some("-y"));
+        // The tail of a list literal is made up while desugaring
+        assert_eq!(text("Cons 2 Nil"), None);
+    }
+
+    #[test]
+    fn test_failed_span_parse_stops_recording_spans() {
+        assert!(parse_program_with_spans("let x = in x").is_err());
+        // A later plain parse builds the tree without span markers
+        assert_eq!(parse("f 1 + 2").unwrap().to_string(), "((f 1) + 2)");
+        assert!(!RECORD_SPANS.with(Cell::get));
+    }
+
+    #[test]
+    fn test_parse_program_with_positions_no_bindings() {
+        let (expr, positions) = parse_program_with_positions("let x = 1 in x").unwrap();
+        assert!(matches!(expr, Expr::Let(..)));
+        assert!(positions.is_empty());
+        assert!(parse_program_with_positions("let x = ;").is_err());
+    }
+
+    #[test]
+    fn test_match_keyword_lines() {
+        let source = "let f = function\n  | 0 -> \"match\"\n  | n -> 'm' in\nlet matches = 1 in\n\"{match f 1 with | s -> s}\" ^ \"{{match}}\"";
+        assert_eq!(match_keyword_lines(source), [1, 5]);
+        let expr = parse(source).unwrap();
+        let matches = expr.subexpressions().filter(|e| matches!(e, Expr::Match(..))).count();
+        assert_eq!(matches, 2);
+    }
+
+    #[test]
+    fn test_string_interpolation_desugaring() {
+        assert_eq!(parse(r#""x = {x}!""#), parse(r#""x = " ^ show x ^ "!""#));
+        assert_eq!(parse(r#""{f "a"}""#), parse(r#"show (f "a")"#));
+        // Escaped braces leave the literal unchanged
+        assert_eq!(parse(r#""{{a}}""#), Ok(desugar_string_to_list("{a}".chars().collect())));
+    }
+
+    #[test]
+    fn test_concat_is_right_associative() {
+        let expected = Expr::BinOp(
+            BinOp::Concat,
+            Box::new(Expr::Var("a".to_string())),
+            Box::new(Expr::BinOp(
+                BinOp::Concat,
+                Box::new(Expr::Var("b".to_string())),
+                Box::new(Expr::BinOp(
+                    BinOp::Add,
+                    Box::new(Expr::Var("c".to_string())),
+                    Box::new(Expr::Int(1)),
+                )),
+            )),
+        );
+        assert_eq!(parse("a ^ b ^ c + 1"), Ok(expected));
+    }
+
+    #[test]
+    fn test_malformed_interpolation_errors() {
+        let err = parse("let s = \"total: {x\" in s").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 17));
+        assert!(err.description().starts_with("unterminated interpolation in string literal"), "{err}");
+        let err = parse("\"a\" ^\n\"b }\"").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 4));
+        assert!(err.description().starts_with("lone `}` in string literal"), "{err}");
+        let err = parse("\"x { 1 + }\"").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 4));
+        assert_eq!(err.description(), "invalid interpolation in string literal: expected an expression, found end of input");
+        // Braces in character literals and records are not string braces
+        assert!(parse("('{', {a: \"{1}\"}.a)").is_ok());
+    }
+
+    #[test]
+    fn test_is_blank_source() {
+        for blank in ["", " \n\t\r\n", "\u{feff}", "\u{feff}  \r\n"] {
+            assert!(is_blank_source(blank), "{blank:?}");
+            assert!(parse(blank).is_ok(), "{blank:?}");
+        }
+        for not_blank in ["0", "\u{feff}x", "# comment", "\u{feff}\u{feff}"] {
+            assert!(!is_blank_source(not_blank), "{not_blank:?}");
+        }
+    }
+
+    #[test]
+    fn test_line_endings_bom_and_tabs_parse_identically() {
+        let lf = "let inc = fun x -> x + 1;\nlet s = \"a{inc 1}\";\nmatch inc 2 with\n| 3 -> s\n| _ -> \"b\"\n";
+        let variants = [
+            lf.replace('\n', "\r\n"),
+            format!("\u{feff}{}", lf.replace('\n', "\r\n")),
+            lf.replace('\n', "\n\t").replace(' ', "\t"),
+        ];
+        let expected = parse(lf).unwrap();
+        for source in &variants {
+            assert_eq!(parse(source).as_ref(), Ok(&expected), "{source:?}");
+            let (expr, positions) = parse_program_with_positions(source).unwrap();
+            assert_eq!(expr, expected, "{source:?}");
+            assert_eq!(positions.iter().map(|p| p.line).collect::<Vec<_>>(), vec![1, 2]);
+        }
+    }
+
+    #[test]
+    fn test_crlf_error_position() {
+        let source = "let x = 1;\r\nlet y = 2;\r\nx + y )\r\n";
+        let err = parse_program_with_positions(source).unwrap_err();
+        assert_eq!((err.line, err.column, err.snippet.as_str()), (3, 7, "x + y )"));
+        let err = parse_program_with_positions(&format!("\u{feff}{source}")).unwrap_err();
+        assert_eq!((err.line, err.column), (3, 7));
+        let err = parse("let x = 1;\r\nlet y = 2;\r\nlet s = \"{x +}\";\r\ns").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 10));
+    }
+
+    #[test]
+    fn test_tab_width_in_error_columns() {
+        let source = "let x = 1;\n\tx )";
+        let err = parse_program_with_positions(source).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 4));
+        let err = parse_program_with_options(source, ParseOptions { tab_width: 4 }).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 7));
+        // The caret keeps the tab, so it lines up however the tab is shown
+        assert!(err.to_string().ends_with("2 | \tx )\n  | \t  ^"), "{err}");
+    }
+
+    #[test]
+    fn test_parse_error_positions_and_rendering() {
+        let cases = [
+            ("let x = 1 then 2", 1, 11, "expected 'in', found 'then'"),
+            ("let x = 1 in\nlet y = 2 in\nif x then y 1 else", 3, 19, "expected an expression, found end of input"),
+            ("1 +", 1, 4, "expected an expression, found end of input"),
+            ("(1, 2", 1, 6, "expected ',' or ')', found end of input"),
+            ("match x with", 1, 13, "expected '|', found end of input"),
+            ("let x = 1;\nlet y = ;\ny", 2, 9, "expected an expression, found ';'"),
+            ("fun x x", 1, 7, "expected '->', found 'x'"),
+            ("if 1 then 2", 1, 12, "expected 'elif' or 'else', found end of input"),
+            ("{a: 1, b}", 1, 9, "expected ':', found '}'"),
+        ];
+        for (source, line, column, description) in cases {
+            let err = parse(source).unwrap_err();
+            assert_eq!((err.line, err.column, err.description().as_str()), (line, column, description), "{source}");
+        }
+        let err = parse("let f = fun x -> x in\nlet y = 1 then f y").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "parse error at line 2, column 11: expected 'in', found 'then'\n  |\n2 | let y = 1 then f y\n  |           ^"
+        );
+        assert_eq!(err.found.as_deref(), Some("then"));
+        assert_eq!(err.expected, vec!["in"]);
+        assert_eq!(String::from(err.clone()), err.to_string());
+    }
+
+    #[test]
+    fn test_long_let_chains_parse_without_deep_recursion() {
+        let source: String = (0..300).map(|i| format!("let x{i} = {i} in ")).collect::<String>() + "x299";
+        let mut expr = parse(&source).unwrap();
+        for i in 0..300 {
+            let Expr::Let(name, None, value, body) = expr else { panic!("expected a let") };
+            assert_eq!((name, *value), (format!("x{i}"), Expr::Int(i)));
+            expr = *body;
+        }
+        assert_eq!(expr, Expr::Var("x299".to_string()));
+    }
+}
@@ -0,0 +1,1010 @@
+//! Pretty-printer producing indented, re-parseable source
+//!
+//! [`pretty`] lays a program out to fit a line width: `let ... in` bindings
+//! are each on their own line, every `match` arm starts a line, and
+//! applications, operator chains, tuples, records and lists break across
+//! lines only when they do not fit. Parentheses are written only where the
+//! precedence of the operators requires them.
+//!
+//! Sugar the parser removes is written back: `Cons`/`Nil` chains become
+//! lists, `::` chains and string literals, `let x = rec x -> e` becomes
+//! `let rec`, the desugared `function` is printed as `function`, and so is
+//! a `let` of a pattern as `let (x, y) = v in e`. For
+//! every tree the parser produces, parsing the output gives the same tree:
+//! `parse(&pretty(&parse(src)?, width)) == parse(src)`.
+//!
+//! [`pretty_minimal`] prints only the top-level items that are not already
+//! formatted, and keeps the others as written.
+
+use crate::ast::{Expr, Literal, Pattern, TypeAnnotation, TypeExpr};
+use crate::parser::{parse, parse_program_with_positions, strip_bom, Associativity, InfixOp, ParseErrorInfo, OPERATORS};
+
+/// Line width `parlang fmt` formats to unless told otherwise
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Columns each nested block is indented by
+const INDENT: usize = 2;
+
+/// A layout of text and optional line breaks, after Wadler's "A prettier printer"
+enum Doc {
+    Text(String),
+    /// A space, or a newline if the enclosing group is broken
+    Line,
+    /// Nothing, or a newline if the enclosing group is broken
+    Break,
+    /// A newline that breaks every group around it
+    HardLine,
+    Concat(Vec<Doc>),
+    /// Indent the lines started inside by more columns
+    Nest(usize, Box<Doc>),
+    /// Print on one line if it fits, and otherwise break all its lines
+    Group(Box<Doc>),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn concat(docs: Vec<Doc>) -> Doc {
+    Doc::Concat(docs)
+}
+
+fn nest(doc: Doc) -> Doc {
+    Doc::Nest(INDENT, Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+fn parens(doc: Doc) -> Doc {
+    concat(vec![text("("), doc, text(")")])
+}
+
+/// `open`, the items separated by `,` and `close`, wrapped one item per line if they do not fit
+fn bracketed(open: &str, items: Vec<Doc>, close: &str, padded: bool) -> Doc {
+    if items.is_empty() {
+        return text(format!("{open}{close}"));
+    }
+    let mut inner = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            inner.push(text(","));
+            inner.push(Doc::Line);
+        }
+        inner.push(item);
+    }
+    let edge = || if padded { Doc::Line } else { Doc::Break };
+    inner.insert(0, edge());
+    group(concat(vec![text(open), nest(concat(inner)), edge(), text(close)]))
+}
+
+/// Lay `doc` out within `width` columns
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    // Indentation is written with the first text of a line, so blank lines have no trailing spaces
+    let mut pending_indent = None;
+    let mut stack = vec![(0, true, doc)];
+    while let Some((indent, broken, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                if let Some(indent) = pending_indent.take() {
+                    out.extend(std::iter::repeat_n(' ', indent));
+                }
+                out.push_str(s);
+                column += s.chars().count();
+            }
+            Doc::Line if !broken => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Break if !broken => {}
+            Doc::Line | Doc::Break | Doc::HardLine => {
+                out.push('\n');
+                pending_indent = Some(indent);
+                column = indent;
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, broken, doc))),
+            Doc::Nest(extra, doc) => stack.push((indent + extra, broken, doc)),
+            Doc::Group(doc) => {
+                let flat = !broken || fits(width.saturating_sub(column), doc, &stack);
+                stack.push((indent, !flat, doc));
+            }
+        }
+    }
+    out
+}
+
+/// Whether `doc` laid out flat, and what follows it up to the next line break, fits in `remaining` columns
+fn fits(mut remaining: usize, doc: &Doc, rest: &[(usize, bool, &Doc)]) -> bool {
+    let mut work = vec![(false, doc)];
+    let mut rest = rest.iter().rev();
+    loop {
+        let Some((broken, doc)) = work.pop().or_else(|| rest.next().map(|&(_, broken, doc)| (broken, doc))) else {
+            return true;
+        };
+        match doc {
+            Doc::Text(s) => match remaining.checked_sub(s.chars().count()) {
+                Some(left) => remaining = left,
+                None => return false,
+            },
+            Doc::Line if !broken => match remaining.checked_sub(1) {
+                Some(left) => remaining = left,
+                None => return false,
+            },
+            Doc::Break if !broken => {}
+            // A hard line inside the group itself can never be laid out flat
+            Doc::HardLine => return broken,
+            Doc::Line | Doc::Break => return true,
+            Doc::Concat(docs) => work.extend(docs.iter().rev().map(|doc| (broken, doc))),
+            Doc::Nest(_, doc) | Doc::Group(doc) => work.push((broken, doc)),
+        }
+    }
+}
+
+/// Print a program as indented source that parses back to the same tree
+///
+/// Lines are kept within `width` columns where the program allows it; long
+/// names and literals can still overflow. A top-level sequence of
+/// `let x = e;` bindings is printed the same way. The result has no trailing
+/// newline.
+pub fn pretty(expr: &Expr, width: usize) -> String {
+    let doc = match expr {
+        Expr::Seq(bindings, body) => {
+            let mut docs = Vec::new();
+            for (i, (name, ann, value)) in bindings.iter().enumerate() {
+                if i > 0 {
+                    docs.push(Doc::HardLine);
+                }
+                docs.push(group(concat(vec![binding(name, ann.as_ref(), value, Follows::Nothing), text(";")])));
+            }
+            if **body != Expr::EmptyBody {
+                docs.push(Doc::HardLine);
+                docs.push(child(body, Prec::Open, Follows::Nothing));
+            }
+            concat(docs)
+        }
+        Expr::EmptyBody => text(""),
+        _ => child(expr, Prec::Open, Follows::Nothing),
+    };
+    render(&doc, width)
+}
+
+/// Print a program, keeping the top-level items that are already formatted as written
+///
+/// Each top-level `let x = e;` binding, and the body, is compared with what
+/// [`pretty`] prints for it. An item that differs from it only in whitespace
+/// keeps its layout, hand alignment included; the others are printed again.
+/// Items each start a line, blank lines between them are kept, and the result
+/// has no trailing newline, like [`pretty`].
+///
+/// # Errors
+///
+/// Returns the parse error if `source` does not parse.
+pub fn pretty_minimal(source: &str, width: usize) -> Result<String, ParseErrorInfo> {
+    let (expr, positions) = parse_program_with_positions(source)?;
+    let (bindings, body) = match expr {
+        Expr::Seq(bindings, body) => (bindings, *body),
+        body => (Vec::new(), body),
+    };
+    let mut out = String::new();
+    let mut end = source.len() - strip_bom(source).len();
+    let mut rest = bindings.as_slice();
+    // A pattern binding is several bindings sharing one span
+    for item in positions.chunk_by(|a, b| a.span == b.span) {
+        let (bound, after) = rest.split_at(item.len());
+        rest = after;
+        let span = item[0].span.clone();
+        push_gap(&mut out, &source[end..span.start]);
+        let printed = pretty(&Expr::Seq(bound.to_vec(), Box::new(Expr::EmptyBody)), width);
+        out.push_str(keep_or_reprint(&source[span.clone()], &printed));
+        end = span.end;
+    }
+    let tail = &source[end..];
+    let written = tail.trim();
+    if !written.is_empty() {
+        push_gap(&mut out, &tail[..tail.len() - tail.trim_start().len()]);
+        out.push_str(keep_or_reprint(written, &pretty(&body, width)));
+    }
+    Ok(out)
+}
+
+/// Separate the next top-level item by the newlines of `gap`, or one if it has none
+fn push_gap(out: &mut String, gap: &str) {
+    if !out.is_empty() {
+        out.push_str(&"\n".repeat(gap.matches('\n').count().max(1)));
+    }
+}
+
+/// `written` if it differs from `printed` only in whitespace, or `printed`
+/// does not parse back to the same tree, and `printed` otherwise
+fn keep_or_reprint<'a>(written: &'a str, printed: &'a str) -> &'a str {
+    let visible = |s: &'a str| s.chars().filter(|c| !c.is_whitespace());
+    if visible(written).eq(visible(printed)) || parse(printed) != parse(written) {
+        written
+    } else {
+        printed
+    }
+}
+
+/// How tightly an expression binds, from the loosest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    /// `let`, `fun`, `if`, `match` and the other forms that extend as far right as they can
+    Open,
+    /// An infix operator of `OPERATORS[level]`
+    Infix(usize),
+    /// Negation
+    Unary,
+    /// Function and constructor application, and `ref`
+    App,
+    /// Dereference
+    Deref,
+    /// Tuple projection, field access and indexing
+    Proj,
+    Atom,
+}
+
+/// The precedence of operands of the infix operators at `level`
+fn infix_prec(level: usize) -> Prec {
+    if level < OPERATORS.len() {
+        Prec::Infix(level)
+    } else {
+        Prec::Unary
+    }
+}
+
+/// What is written right after an expression, which a trailing open form could swallow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Follows {
+    Nothing,
+    /// The `in` of a `let`, which a bodiless `load` would take as its own
+    In,
+    /// Another `match` arm, which a trailing `match` would take as its own
+    Arm,
+}
+
+fn prec(expr: &Expr) -> Prec {
+    match expr {
+        Expr::Int(n) if *n < 0 => Prec::Unary,
+        Expr::Float(f) if f.is_sign_negative() => Prec::Unary,
+        Expr::Let(..)
+        | Expr::Fun(..)
+        | Expr::If(..)
+        | Expr::IfChain(..)
+        | Expr::Match(..)
+        | Expr::Try(..)
+        | Expr::Rec(..)
+        | Expr::TypeDef { .. }
+        | Expr::TypeAlias(..)
+        | Expr::Load(..)
+        | Expr::LoadAs(..)
+        | Expr::Seq(..) => Prec::Open,
+        Expr::Neg(_) => Prec::Unary,
+        Expr::App(..) | Expr::Ref(_) => Prec::App,
+        Expr::Deref(_) => Prec::Deref,
+        Expr::TupleProj(..) | Expr::FieldAccess(..) | Expr::ArrayIndex(..) => Prec::Proj,
+        Expr::Constructor(_, args) if !args.is_empty() && list_items(expr).is_none() && infix_parts(expr).is_none() => {
+            Prec::App
+        }
+        _ => infix_parts(expr).map_or(Prec::Atom, |(op, _, _)| infix_prec(level_of(op).0)),
+    }
+}
+
+/// The operator and operands of an infix expression; a `Nil`-terminated `Cons` chain is a list instead
+fn infix_parts(expr: &Expr) -> Option<(InfixOp, &Expr, &Expr)> {
+    match expr {
+        Expr::BinOp(op, left, right) => Some((InfixOp::Binary(*op), left, right)),
+        Expr::Range(left, right) => Some((InfixOp::Range, left, right)),
+        Expr::RefAssign(left, right) => Some((InfixOp::Assign, left, right)),
+        Expr::Constructor(name, args) if name == "Cons" && args.len() == 2 && list_items(expr).is_none() => {
+            Some((InfixOp::Cons, &args[0], &args[1]))
+        }
+        _ => None,
+    }
+}
+
+/// The precedence level, associativity and symbol of an infix operator
+fn level_of(op: InfixOp) -> (usize, Associativity, &'static str) {
+    OPERATORS
+        .iter()
+        .enumerate()
+        .find_map(|(level, ops)| {
+            ops.operators.iter().find(|o| o.op == op).map(|o| (level, ops.associativity, o.symbol))
+        })
+        .expect("every infix operator is in OPERATORS")
+}
+
+/// The items of a `Nil`-terminated `Cons` chain
+fn list_items(expr: &Expr) -> Option<Vec<&Expr>> {
+    let mut items = Vec::new();
+    let mut rest = expr;
+    loop {
+        match rest {
+            Expr::Constructor(name, args) if name == "Nil" && args.is_empty() => return Some(items),
+            Expr::Constructor(name, args) if name == "Cons" && args.len() == 2 => {
+                items.push(&args[0]);
+                rest = &args[1];
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// The expression an open form ends with, which extends as far right as the form does
+fn tail(expr: &Expr) -> Option<&Expr> {
+    match expr {
+        Expr::Let(_, _, _, body)
+        | Expr::Fun(_, _, body)
+        | Expr::Rec(_, body)
+        | Expr::TypeAlias(_, _, body)
+        | Expr::TypeDef { body, .. }
+        | Expr::Load(_, body)
+        | Expr::LoadAs(_, _, body)
+        | Expr::Seq(_, body)
+        | Expr::Try(_, _, body)
+        | Expr::If(_, _, body)
+        | Expr::IfChain(_, body) => Some(body),
+        Expr::Match(_, arms) => arms.last().map(|(_, _, result)| result),
+        _ => None,
+    }
+}
+
+/// Whether `expr`, written before `follows`, would take it as its own
+fn swallows(expr: &Expr, follows: Follows) -> bool {
+    let mut trailing = std::iter::successors(Some(expr), |expr| tail(expr));
+    match follows {
+        Follows::Nothing => false,
+        Follows::In => trailing.any(|expr| {
+            matches!(expr, Expr::Load(_, body) | Expr::LoadAs(_, _, body) if **body == Expr::EmptyBody)
+        }),
+        Follows::Arm => trailing.any(|expr| matches!(expr, Expr::Match(..))),
+    }
+}
+
+/// Print `expr` in a position that needs precedence `min`, adding parentheses if it binds looser
+fn child(expr: &Expr, min: Prec, follows: Follows) -> Doc {
+    if prec(expr) < min || swallows(expr, follows) {
+        parens(doc(expr, Follows::Nothing))
+    } else {
+        doc(expr, follows)
+    }
+}
+
+/// Whether `expr` is printed starting with a digit or `-`, which would fuse with a `-` before it
+fn starts_with_number(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int(_) | Expr::Float(_) | Expr::Byte(_) | Expr::Neg(_) => true,
+        Expr::App(function, _) => starts_with_number(function),
+        _ => false,
+    }
+}
+
+/// The parameter and arms of a `fun` the parser built from `function | ...`
+///
+/// The desugared parameter starts with `_`, which no source identifier does.
+fn function_arms(expr: &Expr) -> Option<&[(Pattern, Option<Expr>, Expr)]> {
+    let Expr::Fun(param, None, body) = expr else {
+        return None;
+    };
+    match &**body {
+        Expr::Match(scrutinee, arms) if param.starts_with('_') && **scrutinee == Expr::Var(param.clone()) => Some(arms),
+        _ => None,
+    }
+}
+
+/// The pattern, value and body of a `let` the parser built from `let pattern = value in body`
+///
+/// The desugared name starts with `_`, which no source identifier does.
+fn pattern_let(expr: &Expr) -> Option<(&Pattern, &Expr, &Expr)> {
+    let Expr::Let(name, None, value, body) = expr else {
+        return None;
+    };
+    match &**body {
+        Expr::Match(scrutinee, arms) if name.starts_with('_') && **scrutinee == Expr::Var(name.clone()) => match arms.as_slice() {
+            [(pattern, None, body)] => Some((pattern, value, body)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn doc(expr: &Expr, follows: Follows) -> Doc {
+    match expr {
+        Expr::Int(n) => text(n.to_string()),
+        Expr::Bool(b) => text(b.to_string()),
+        Expr::Char(c) => text(char_literal(*c)),
+        Expr::Float(f) => text(float_literal(*f)),
+        Expr::Byte(b) => text(format!("{b}b")),
+        Expr::Var(name) => text(name.clone()),
+        Expr::EmptyBody => text(""),
+        Expr::Let(..)
+        | Expr::Seq(..)
+        | Expr::Fun(..)
+        | Expr::Rec(..)
+        | Expr::If(..)
+        | Expr::IfChain(..)
+        | Expr::Match(..)
+        | Expr::Try(..)
+        | Expr::TypeAlias(..)
+        | Expr::TypeDef { .. }
+        | Expr::Load(..)
+        | Expr::LoadAs(..) => open_doc(expr, follows),
+        Expr::Tuple(items) => {
+            bracketed("(", items.iter().map(|item| child(item, Prec::Open, Follows::Nothing)).collect(), ")", false)
+        }
+        Expr::Record(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(name, value)| concat(vec![text(format!("{name}: ")), child(value, Prec::Open, Follows::Nothing)]))
+                .collect();
+            bracketed("{", fields, "}", true)
+        }
+        Expr::Array(items) => {
+            bracketed("[|", items.iter().map(|item| child(item, Prec::Open, Follows::Nothing)).collect(), "|]", false)
+        }
+        Expr::TupleProj(base, index) => concat(vec![proj_base(base), text(format!(".{index}"))]),
+        Expr::FieldAccess(base, field) => concat(vec![proj_base(base), text(format!(".{field}"))]),
+        Expr::ArrayIndex(base, index) => {
+            concat(vec![proj_base(base), text("["), child(index, Prec::Open, Follows::Nothing), text("]")])
+        }
+        Expr::App(..) => {
+            let mut args = Vec::new();
+            let mut function = expr;
+            while let Expr::App(inner, arg) = function {
+                args.push(child(arg, Prec::Deref, Follows::Nothing));
+                function = inner;
+            }
+            args.reverse();
+            // `ref` and constructor applications take no further arguments
+            let function = match function {
+                Expr::Ref(_) => parens(doc(function, Follows::Nothing)),
+                Expr::Constructor(_, payload) if !payload.is_empty() => parens(doc(function, Follows::Nothing)),
+                _ => child(function, Prec::App, Follows::Nothing),
+            };
+            applied(function, args)
+        }
+        Expr::Ref(value) => concat(vec![text("ref "), child(value, Prec::App, Follows::Nothing)]),
+        Expr::Deref(value) => concat(vec![text("!"), child(value, Prec::Deref, Follows::Nothing)]),
+        Expr::Neg(value) => {
+            if starts_with_number(value) {
+                concat(vec![text("-"), parens(doc(value, Follows::Nothing))])
+            } else {
+                concat(vec![text("-"), child(value, Prec::Unary, Follows::Nothing)])
+            }
+        }
+        Expr::Constructor(name, args) => {
+            if let Some(items) = list_items(expr) {
+                return list_doc(&items);
+            }
+            if infix_parts(expr).is_some() {
+                return infix_doc(expr);
+            }
+            applied(text(name.clone()), args.iter().map(|arg| child(arg, Prec::Deref, Follows::Nothing)).collect())
+        }
+        Expr::BinOp(..) | Expr::RefAssign(..) | Expr::Range(..) => infix_doc(expr),
+    }
+}
+
+/// Print one of the forms that extend as far right as they can
+fn open_doc(expr: &Expr, follows: Follows) -> Doc {
+    match expr {
+        Expr::Let(name, ann, value, body) => {
+            let (binding, body) = match pattern_let(expr) {
+                Some((pattern, value, body)) => (binding(&binder_text(pattern), None, value, Follows::In), body),
+                None => (binding(name, ann.as_ref(), value, Follows::In), &**body),
+            };
+            concat(vec![
+                group(concat(vec![binding, Doc::Line, text("in")])),
+                Doc::HardLine,
+                child(body, Prec::Open, follows),
+            ])
+        }
+        Expr::Seq(bindings, body) => {
+            let mut docs = Vec::new();
+            for (name, ann, value) in bindings {
+                docs.push(group(concat(vec![binding(name, ann.as_ref(), value, Follows::In), Doc::Line, text("in")])));
+                docs.push(Doc::HardLine);
+            }
+            docs.push(child(body, Prec::Open, follows));
+            concat(docs)
+        }
+        Expr::Fun(..) | Expr::Rec(..) => {
+            if let Some(arms) = function_arms(expr) {
+                return concat(vec![text("function"), arms_doc(arms, follows)]);
+            }
+            let (heads, body) = lambda_heads(expr);
+            group(concat(vec![text(heads), nest(concat(vec![Doc::Line, child(body, Prec::Open, follows)]))]))
+        }
+        Expr::If(cond, then_branch, else_branch) => group(concat(vec![
+            text("if "),
+            child(cond, Prec::Infix(0), Follows::Nothing),
+            text(" then"),
+            nest(concat(vec![Doc::Line, child(then_branch, Prec::Open, Follows::Nothing)])),
+            else_doc(else_branch, follows),
+        ])),
+        Expr::IfChain(branches, else_branch) => if_chain_doc(branches, else_branch, follows),
+        Expr::Match(scrutinee, arms) => concat(vec![
+            text("match "),
+            child(scrutinee, Prec::Infix(0), Follows::Nothing),
+            text(" with"),
+            arms_doc(arms, follows),
+        ]),
+        Expr::Try(body, name, handler) => group(concat(vec![
+            text("try"),
+            nest(concat(vec![Doc::Line, child(body, Prec::Open, Follows::Nothing)])),
+            Doc::Line,
+            text(format!("catch {name} ->")),
+            nest(concat(vec![Doc::Line, child(handler, Prec::Open, follows)])),
+        ])),
+        Expr::TypeAlias(name, ty, body) => {
+            // A bare constructor name would define a sum type instead
+            let ty = match ty {
+                TypeExpr::Alias(alias) if alias.starts_with(char::is_uppercase) => format!("({ty})"),
+                _ => ty.to_string(),
+            };
+            concat(vec![text(format!("type {name} = {ty} in")), Doc::HardLine, child(body, Prec::Open, follows)])
+        }
+        Expr::TypeDef { name, type_params, constructors, body } => {
+            concat(vec![type_def_head(name, type_params, constructors), Doc::HardLine, child(body, Prec::Open, follows)])
+        }
+        Expr::Load(path, body) => {
+            if **body == Expr::EmptyBody {
+                text(format!("load \"{path}\""))
+            } else {
+                concat(vec![text(format!("load \"{path}\" in")), Doc::HardLine, child(body, Prec::Open, follows)])
+            }
+        }
+        Expr::LoadAs(path, namespace, body) => {
+            if **body == Expr::EmptyBody {
+                text(format!("load \"{path}\" as {namespace}"))
+            } else {
+                concat(vec![
+                    text(format!("load \"{path}\" as {namespace} in")),
+                    Doc::HardLine,
+                    child(body, Prec::Open, follows),
+                ])
+            }
+        }
+        _ => unreachable!("not an open form: {expr:?}"),
+    }
+}
+
+/// `let name = value`, with any `fun` and `rec` heads of the value kept on the first line
+fn binding(name: &str, ann: Option<&TypeAnnotation>, value: &Expr, follows: Follows) -> Doc {
+    let (mut head, value) = match value {
+        Expr::Rec(rec_name, value) if rec_name == name => (format!("let rec {name}"), &**value),
+        _ => (format!("let {name}"), value),
+    };
+    if let Some(ann) = ann {
+        head.push_str(" : ");
+        head.push_str(&annotation(ann));
+    }
+    head.push_str(" =");
+    if swallows(value, follows) {
+        return concat(vec![text(head), nest(concat(vec![Doc::Line, parens(doc(value, Follows::Nothing))]))]);
+    }
+    if let Some(arms) = function_arms(value) {
+        return concat(vec![text(head + " function"), nest(arms_doc(arms, follows))]);
+    }
+    if matches!(value, Expr::Fun(..) | Expr::Rec(..)) {
+        let (heads, body) = lambda_heads(value);
+        return concat(vec![text(format!("{head} {heads}")), nest(concat(vec![Doc::Line, child(body, Prec::Open, follows)]))]);
+    }
+    concat(vec![text(head), nest(concat(vec![Doc::Line, child(value, Prec::Open, follows)]))])
+}
+
+/// The `fun x ->` and `rec f ->` heads at the start of `expr`, and the body after them
+fn lambda_heads(expr: &Expr) -> (String, &Expr) {
+    let mut heads = Vec::new();
+    let mut body = expr;
+    loop {
+        match body {
+            Expr::Fun(param, ann, inner) if function_arms(body).is_none() => {
+                heads.push(match ann {
+                    Some(ann @ TypeAnnotation::Fun(..)) => format!("fun ({param} : {}) ->", annotation(ann)),
+                    Some(ann) => format!("fun {param} : {} ->", annotation(ann)),
+                    None => format!("fun {param} ->"),
+                });
+                body = inner;
+            }
+            Expr::Rec(name, inner) => {
+                heads.push(format!("rec {name} ->"));
+                body = inner;
+            }
+            _ => return (heads.join(" "), body),
+        }
+    }
+}
+
+/// An `if`/`elif` chain, each condition with the branch it selects
+fn if_chain_doc(branches: &[(Expr, Expr)], else_branch: &Expr, follows: Follows) -> Doc {
+    let mut docs = Vec::new();
+    for (i, (cond, then_branch)) in branches.iter().enumerate() {
+        if i > 0 {
+            docs.push(Doc::Line);
+        }
+        docs.push(text(if i == 0 { "if " } else { "elif " }));
+        docs.push(child(cond, Prec::Infix(0), Follows::Nothing));
+        docs.push(text(" then"));
+        docs.push(nest(concat(vec![Doc::Line, child(then_branch, Prec::Open, Follows::Nothing)])));
+    }
+    docs.push(else_doc(else_branch, follows));
+    group(concat(docs))
+}
+
+/// `type Name params = C1 payload | C2 payload in`, the alternatives on their own lines if they do not fit
+fn type_def_head(name: &str, type_params: &[String], constructors: &[(String, Vec<TypeAnnotation>)]) -> Doc {
+    let mut head = format!("type {name}");
+    for param in type_params {
+        head.push(' ');
+        head.push_str(param);
+    }
+    head.push_str(" =");
+    let mut alternatives = Vec::new();
+    for (i, (constructor, payload)) in constructors.iter().enumerate() {
+        let mut alternative = if i == 0 { String::new() } else { "| ".to_string() };
+        alternative.push_str(constructor);
+        for ty in payload {
+            alternative.push(' ');
+            alternative.push_str(&annotation_arg(ty));
+        }
+        alternatives.push(Doc::Line);
+        alternatives.push(text(alternative));
+    }
+    group(concat(vec![text(head), nest(concat(alternatives)), Doc::Line, text("in")]))
+}
+
+/// The `else` branch of an `if`
+///
+/// An `if` there is parenthesized, as `else if` would join the chain of an `IfChain`.
+fn else_doc(else_branch: &Expr, follows: Follows) -> Doc {
+    let branch = if matches!(else_branch, Expr::If(..) | Expr::IfChain(..)) {
+        parens(doc(else_branch, Follows::Nothing))
+    } else {
+        child(else_branch, Prec::Open, follows)
+    };
+    concat(vec![Doc::Line, text("else"), nest(concat(vec![Doc::Line, branch]))])
+}
+
+/// The arms of a `match` or `function`, each on its own line
+fn arms_doc(arms: &[(Pattern, Option<Expr>, Expr)], follows: Follows) -> Doc {
+    let mut docs = Vec::new();
+    for (i, (pattern, guard, result)) in arms.iter().enumerate() {
+        let follows = if i + 1 == arms.len() { follows } else { Follows::Arm };
+        let mut head = vec![text(format!("| {}", pattern_text(pattern)))];
+        if let Some(guard) = guard {
+            head.push(text(" when "));
+            head.push(child(guard, Prec::Infix(0), Follows::Nothing));
+        }
+        head.push(text(" ->"));
+        docs.push(Doc::HardLine);
+        docs.push(group(concat(vec![
+            concat(head),
+            Doc::Nest(2 * INDENT, Box::new(concat(vec![Doc::Line, child(result, Prec::Open, follows)]))),
+        ])));
+    }
+    concat(docs)
+}
+
+/// `function` followed by its arguments, which move to their own lines if they do not fit
+fn applied(function: Doc, args: Vec<Doc>) -> Doc {
+    let mut docs = vec![function];
+    let mut rest = Vec::new();
+    for arg in args {
+        rest.push(Doc::Line);
+        rest.push(arg);
+    }
+    docs.push(nest(concat(rest)));
+    group(concat(docs))
+}
+
+/// The base of a projection, parenthesized unless it binds as tightly
+fn proj_base(base: &Expr) -> Doc {
+    // `1.0` would read as a float and `-1.0` as a negative one
+    if matches!(base, Expr::Int(_) | Expr::Float(_) | Expr::Byte(_)) {
+        parens(doc(base, Follows::Nothing))
+    } else {
+        child(base, Prec::Proj, Follows::Nothing)
+    }
+}
+
+/// A chain of infix operators of one precedence level, breaking before each operator
+fn infix_doc(expr: &Expr) -> Doc {
+    let (op, left, right) = infix_parts(expr).expect("an infix expression");
+    let (level, associativity, symbol) = level_of(op);
+    if op == InfixOp::Range {
+        return concat(vec![
+            child(left, infix_prec(level + 1), Follows::Nothing),
+            text(symbol),
+            child(right, infix_prec(level + 1), Follows::Nothing),
+        ]);
+    }
+    // Operands in order, each with the operator before it
+    let mut operands: Vec<(Option<&'static str>, &Expr, Prec)> = Vec::new();
+    match associativity {
+        Associativity::Left => {
+            let mut first = expr;
+            while let Some((op, left, right)) = same_level(first, level) {
+                operands.push((Some(level_of(op).2), right, infix_prec(level + 1)));
+                first = left;
+            }
+            operands.push((None, first, infix_prec(level)));
+            operands.reverse();
+        }
+        Associativity::Right => {
+            let mut last = expr;
+            let mut before = None;
+            while let Some((op, left, right)) = same_level(last, level) {
+                operands.push((before, left, infix_prec(level + 1)));
+                before = Some(level_of(op).2);
+                last = right;
+            }
+            operands.push((before, last, infix_prec(level)));
+        }
+        Associativity::None => {
+            operands.push((None, left, infix_prec(level + 1)));
+            operands.push((Some(symbol), right, infix_prec(level + 1)));
+        }
+    }
+    let mut operands = operands.into_iter();
+    let (_, first, min) = operands.next().expect("an infix expression has operands");
+    let mut rest = Vec::new();
+    for (symbol, operand, min) in operands {
+        rest.push(Doc::Line);
+        rest.push(text(format!("{} ", symbol.unwrap_or_default())));
+        rest.push(child(operand, min, Follows::Nothing));
+    }
+    group(concat(vec![child(first, min, Follows::Nothing), nest(concat(rest))]))
+}
+
+/// The operator and operands of `expr` if it is an infix expression at `level`
+fn same_level(expr: &Expr, level: usize) -> Option<(InfixOp, &Expr, &Expr)> {
+    infix_parts(expr).filter(|(op, _, _)| level_of(*op).0 == level)
+}
+
+/// A list literal, or a string literal if every item is a character
+fn list_doc(items: &[&Expr]) -> Doc {
+    let chars: Option<String> = items.iter().map(|item| if let Expr::Char(c) = item { Some(*c) } else { None }).collect();
+    match chars {
+        Some(chars) if !chars.is_empty() => text(string_literal(&chars)),
+        _ => bracketed("[", items.iter().map(|item| child(item, Prec::Open, Follows::Nothing)).collect(), "]", false),
+    }
+}
+
+fn char_literal(c: char) -> String {
+    match c {
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\'' => "'\\''".to_string(),
+        _ => format!("'{c}'"),
+    }
+}
+
+fn string_literal(chars: &str) -> String {
+    let mut out = String::from("\"");
+    for c in chars.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn float_literal(f: f64) -> String {
+    let mut s = f.to_string();
+    if !s.contains('.') {
+        s.push_str(".0");
+    }
+    s
+}
+
+/// A type annotation, with function types on the left of an arrow parenthesized
+fn annotation(ty: &TypeAnnotation) -> String {
+    match ty {
+        TypeAnnotation::Fun(arg, ret) => {
+            let arg = match **arg {
+                TypeAnnotation::Fun(..) => format!("({})", annotation(arg)),
+                _ => annotation(arg),
+            };
+            format!("{arg} -> {}", annotation(ret))
+        }
+        TypeAnnotation::App(name, args) => {
+            let mut s = name.clone();
+            for arg in args {
+                s.push(' ');
+                s.push_str(&annotation_arg(arg));
+            }
+            s
+        }
+        TypeAnnotation::Concrete(name) | TypeAnnotation::Var(name) => name.clone(),
+    }
+}
+
+/// A type annotation as the argument of an applied type or a constructor
+fn annotation_arg(ty: &TypeAnnotation) -> String {
+    match ty {
+        TypeAnnotation::Fun(..) => format!("({})", annotation(ty)),
+        TypeAnnotation::App(_, args) if !args.is_empty() => format!("({})", annotation(ty)),
+        _ => annotation(ty),
+    }
+}
+
+fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Literal(literal) => literal_text(literal),
+        Pattern::Var(name) => name.clone(),
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Tuple(items) => format!("({})", items.iter().map(pattern_text).collect::<Vec<_>>().join(", ")),
+        Pattern::Record(fields) => {
+            if fields.is_empty() {
+                return "{}".to_string();
+            }
+            let fields: Vec<String> = fields.iter().map(|(name, pattern)| format!("{name}: {}", pattern_text(pattern))).collect();
+            format!("{{ {} }}", fields.join(", "))
+        }
+        Pattern::Constructor(name, args) => {
+            if let Some(items) = pattern_list_items(pattern) {
+                let chars: Option<String> = items
+                    .iter()
+                    .map(|item| if let Pattern::Literal(Literal::Char(c)) = item { Some(*c) } else { None })
+                    .collect();
+                return match chars {
+                    Some(chars) if !chars.is_empty() => string_literal(&chars),
+                    _ => format!("[{}]", items.iter().map(|item| pattern_text(item)).collect::<Vec<_>>().join(", ")),
+                };
+            }
+            // The head of `::` cannot itself be a `::` pattern, nor be wrapped in
+            // parentheses, which make a one-element tuple pattern
+            if name == "Cons" && args.len() == 2 && !matches!(&args[0], Pattern::Constructor(head, head_args) if head == "Cons" && head_args.len() == 2) {
+                return format!("{} :: {}", pattern_text(&args[0]), pattern_text(&args[1]));
+            }
+            let mut s = name.clone();
+            for arg in args {
+                s.push(' ');
+                s.push_str(&pattern_atom(arg));
+            }
+            s
+        }
+    }
+}
+
+/// A pattern as a constructor argument
+/// A pattern bound by `let`, parenthesized when it starts with a name
+fn binder_text(pattern: &Pattern) -> String {
+    let text = pattern_text(pattern);
+    if text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        format!("({text})")
+    } else {
+        text
+    }
+}
+
+fn pattern_atom(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Constructor(_, args) if !args.is_empty() && pattern_list_items(pattern).is_none() => {
+            format!("({})", pattern_text(pattern))
+        }
+        // Parentheses alone are dropped here, and a record is no atom
+        Pattern::Tuple(items) if items.len() == 1 => format!("({})", pattern_text(pattern)),
+        Pattern::Record(_) => format!("({})", pattern_text(pattern)),
+        _ => pattern_text(pattern),
+    }
+}
+
+/// The items of a `Nil`-terminated `Cons` pattern chain
+fn pattern_list_items(pattern: &Pattern) -> Option<Vec<&Pattern>> {
+    let mut items = Vec::new();
+    let mut rest = pattern;
+    loop {
+        match rest {
+            Pattern::Constructor(name, args) if name == "Nil" && args.is_empty() => return Some(items),
+            Pattern::Constructor(name, args) if name == "Cons" && args.len() == 2 => {
+                items.push(&args[0]);
+                rest = &args[1];
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn literal_text(literal: &Literal) -> String {
+    match literal {
+        Literal::Int(n) => n.to_string(),
+        Literal::Bool(b) => b.to_string(),
+        Literal::Char(c) => char_literal(*c),
+        Literal::Byte(b) => format!("{b}b"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pretty-print `source` at `width`, checking that the output parses back to the same tree
+    fn round_trip(source: &str, width: usize) -> String {
+        let expr = parse(source).unwrap();
+        let printed = pretty(&expr, width);
+        assert_eq!(parse(&printed), Ok(expr), "{printed}");
+        printed
+    }
+
+    #[test]
+    fn test_minimal_parentheses() {
+        assert_eq!(round_trip("(1 + 2) * 3 - (4 - 5) + f (g x) y", 80), "(1 + 2) * 3 - (4 - 5) + f (g x) y");
+        assert_eq!(round_trip("((a :: b) :: c) :: (d :: e)", 80), "((a :: b) :: c) :: d :: e");
+        assert_eq!(round_trip("(1 - 2) - 3 - (4 * 5)", 80), "1 - 2 - 3 - 4 * 5");
+        assert_eq!(round_trip("-(f 3) * -x", 80), "-f 3 * -x");
+        assert_eq!(round_trip("- (5) + (-2).0 + !(ref 1) + (f x).y", 80), "-(5) + (-2).0 + !(ref 1) + (f x).y");
+        assert_eq!(round_trip("f (fun x -> x) (if a then b else c)", 80), "f (fun x -> x) (if a then b else c)");
+    }
+
+    #[test]
+    fn test_lets_and_matches_are_indented() {
+        let source = "let f = fun x -> match x with | Some n -> n + 1 | None -> 0 in let y = f (Some 1) in y";
+        assert_eq!(
+            round_trip(source, 80),
+            "let f = fun x ->\n  match x with\n  | Some n -> n + 1\n  | None -> 0\nin\nlet y = f (Some 1) in\ny"
+        );
+    }
+
+    #[test]
+    fn test_collections_wrap_when_too_wide() {
+        let source = "let r = { name: \"parlang\", version: (1, 2, 3), tags: [\"a\", \"b\"] } in r";
+        assert_eq!(round_trip(source, 80), "let r = { name: \"parlang\", version: (1, 2, 3), tags: [\"a\", \"b\"] } in\nr");
+        assert_eq!(
+            round_trip(source, 30),
+            "let r =\n  {\n    name: \"parlang\",\n    version: (1, 2, 3),\n    tags: [\"a\", \"b\"]\n  }\nin\nr"
+        );
+    }
+
+    #[test]
+    fn test_sugar_is_written_back() {
+        assert_eq!(round_trip("let rec f = function | 0 -> 1 | n -> n * f (n - 1) in f 5", 80), "let rec f = function\n  | 0 -> 1\n  | n -> n * f (n - 1)\nin\nf 5");
+        assert_eq!(round_trip("Cons 1 (Cons 2 Nil)", 80), "[1, 2]");
+        assert_eq!(round_trip("Cons 1 (Cons 2 rest)", 80), "1 :: 2 :: rest");
+        assert_eq!(round_trip("\"a\\n{{x}}\\\"\"", 80), "\"a\\n{{x}}\\\"\"");
+        assert_eq!(round_trip("let a = 1;\nlet b = a + 1;\nb", 80), "let a = 1;\nlet b = a + 1;\nb");
+        assert_eq!(round_trip("let (a, Some b) = p in let (Wrap n) = w in let _ = x in a", 80), "let (a, Some b) = p in\nlet (Wrap n) = w in\nlet _ = x in\na");
+    }
+
+    #[test]
+    fn test_trailing_forms_that_would_swallow_what_follows() {
+        round_trip("match a with | 1 -> (match b with | _ -> 2) | _ -> 3", 80);
+        round_trip("let x = (load \"lib.par\") in x", 80);
+        round_trip("if a then b else (if c then d else e)", 80);
+        round_trip("if a then b elif c then d else e", 80);
+        round_trip("match p with | (x :: y) :: z -> 1 | Some (Some x) -> 2 | Some ({a: x}) -> 3 | ((x)) -> 4", 80);
+    }
+
+    #[test]
+    fn test_types_and_annotations() {
+        round_trip("type Tree a = Leaf | Node (Tree a) a (Tree a) in Leaf", 80);
+        round_trip("type F = Int -> Int in type G = (F) in 1", 80);
+        round_trip("let f : (Int -> Int) -> List Int = fun (g : Int -> Int) -> fun x : Int -> [g x] in f", 80);
+    }
+
+    #[test]
+    fn test_minimal_reprints_only_unformatted_items() {
+        let aligned = "let origin = { x:   0,\n              y:   0 };";
+        let source = format!("\n{aligned}\n\n\nlet   total = ((origin.x)) + (origin.y);  let (a, b) = (1, 2);\n  total");
+        assert_eq!(
+            pretty_minimal(&source, 80).unwrap(),
+            format!("{aligned}\n\n\nlet total = origin.x + origin.y;\nlet (a, b) = (1, 2);\ntotal")
+        );
+        assert_eq!(pretty_minimal("f  (x)", 80).unwrap(), "f x");
+        assert_eq!(pretty_minimal("  \n", 80).unwrap(), "");
+    }
+}
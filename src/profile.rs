@@ -0,0 +1,264 @@
+//! Deterministic profiling of function calls
+//!
+//! A [`Profiler`] attached to an environment with
+//! [`Environment::set_profiler`](crate::eval::Environment::set_profiler)
+//! counts evaluation steps (one per expression `eval` visits, the unit of
+//! `--max-steps`) and, for every function, how often it is called and how
+//! many steps are spent inside it. The numbers depend only on the program,
+//! never on timing, so two runs give the same report and tests can assert it.
+//!
+//! # Functions and Calls
+//!
+//! - A recursive closure (`rec f -> ...` or `let rec f = ...`) is named `f`.
+//! - Any other closure is named after the variable it is called through, so
+//!   `helper x` is a call of `helper`.
+//! - A closure called some other way, e.g. `(fun x -> x) 1` or through a
+//!   built-in, is `<anonymous@N>`, numbering the distinct anonymous
+//!   functions in the order they are first called.
+//!
+//! An application `f a b` is one call of `f`, whether `f` takes both
+//! arguments at once or returns a function that takes the second. Each
+//! iteration of a tail-recursive loop is a call as well. Built-ins are not
+//! listed; closures they call (`memo`, `min_by`) are.
+//!
+//! Steps are inclusive: a function's steps include those of the functions it
+//! calls, but not the evaluation of its arguments at the call site. A
+//! recursive function's nested calls are not counted twice, so its steps are
+//! the steps spent anywhere below its outermost call.
+//!
+//! Without a profiler, evaluation only pays for checking whether one is
+//! attached.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::{parse, eval, Environment};
+//! use parlang::profile::Profiler;
+//! use std::rc::Rc;
+//!
+//! let profiler = Rc::new(Profiler::new());
+//! let mut env = Environment::new();
+//! env.set_profiler(Some(Rc::clone(&profiler)));
+//! let expr = parse("let double = fun x -> x + x in double (double 1)").unwrap();
+//! eval(&expr, &env).unwrap();
+//!
+//! let report = profiler.report();
+//! assert_eq!(report.functions[0].name, "double");
+//! assert_eq!(report.functions[0].calls, 2);
+//! ```
+
+use crate::core::CoreExpr;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Collects call counts and steps while a program runs
+#[derive(Debug)]
+pub struct Profiler {
+    enabled: Cell<bool>,
+    state: RefCell<ProfileState>,
+}
+
+#[derive(Debug, Default)]
+struct ProfileState {
+    steps: u64,
+    functions: HashMap<String, FunctionStats>,
+    /// Number of each anonymous function, by its parameter and core body
+    anonymous: HashMap<String, usize>,
+}
+
+#[derive(Debug, Default)]
+struct FunctionStats {
+    calls: u64,
+    steps: u64,
+    /// Calls of the function currently being evaluated
+    active: usize,
+    /// Step count when the outermost active call started
+    entered_at: u64,
+}
+
+/// Profilers compare by identity, like the environments sharing them
+impl PartialEq for Profiler {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// Create an enabled profiler with no data
+    pub fn new() -> Self {
+        Profiler { enabled: Cell::new(true), state: RefCell::new(ProfileState::default()) }
+    }
+
+    /// Whether steps and calls are being recorded
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    /// Start or stop recording, keeping the data collected so far
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+
+    /// Discard the data collected so far
+    ///
+    /// Anonymous functions keep their numbers.
+    pub fn reset(&self) {
+        let mut state = self.state.borrow_mut();
+        state.steps = 0;
+        state.functions.retain(|_, stats| stats.active > 0);
+        for stats in state.functions.values_mut() {
+            *stats = FunctionStats { active: stats.active, ..FunctionStats::default() };
+        }
+    }
+
+    /// The data collected so far, busiest functions first
+    pub fn report(&self) -> ProfileReport {
+        let state = self.state.borrow();
+        let mut functions: Vec<FunctionProfile> = state
+            .functions
+            .iter()
+            .filter(|(_, stats)| stats.calls > 0)
+            .map(|(name, stats)| FunctionProfile { name: name.clone(), calls: stats.calls, steps: stats.steps })
+            .collect();
+        functions.sort_by(|a, b| b.steps.cmp(&a.steps).then_with(|| b.calls.cmp(&a.calls)).then_with(|| a.name.cmp(&b.name)));
+        ProfileReport { total_steps: state.steps, functions }
+    }
+
+    /// Record one evaluation step
+    pub(crate) fn step(&self) {
+        if self.enabled.get() {
+            self.state.borrow_mut().steps += 1;
+        }
+    }
+
+    /// Record that evaluation entered the body of function `name`
+    ///
+    /// `new_call` is false when the body belongs to a call already counted,
+    /// such as the function returned by a curried function in the same
+    /// application. The call ends when the returned guard is dropped.
+    pub(crate) fn enter(self: &Rc<Self>, name: String, new_call: bool) -> Option<ActiveCall> {
+        if !self.enabled.get() {
+            return None;
+        }
+        let mut state = self.state.borrow_mut();
+        let now = state.steps;
+        let function = state.functions.entry(name.clone()).or_default();
+        if new_call {
+            function.calls += 1;
+        }
+        if function.active == 0 {
+            function.entered_at = now;
+        }
+        function.active += 1;
+        Some(ActiveCall { profiler: Rc::clone(self), name })
+    }
+
+    /// Record a tail call of `name`, which runs in place of the call that made it
+    ///
+    /// A function already active only has the call counted; otherwise it is
+    /// entered, and the returned guard keeps it active for the rest of the
+    /// chain of tail calls.
+    pub(crate) fn tail_call(self: &Rc<Self>, name: String, new_call: bool) -> Option<ActiveCall> {
+        if !self.enabled.get() {
+            return None;
+        }
+        if let Some(stats) = self.state.borrow_mut().functions.get_mut(&name).filter(|stats| stats.active > 0) {
+            if new_call {
+                stats.calls += 1;
+            }
+            return None;
+        }
+        self.enter(name, new_call)
+    }
+
+    /// The name of an anonymous function with parameter `param` and body `body`
+    pub(crate) fn anonymous_name(&self, param: &str, body: &CoreExpr) -> String {
+        let mut state = self.state.borrow_mut();
+        let next = state.anonymous.len() + 1;
+        let number = *state.anonymous.entry(format!("fun {param} -> {body:?}")).or_insert(next);
+        format!("<anonymous@{number}>")
+    }
+
+    fn exit(&self, name: &str) {
+        let mut state = self.state.borrow_mut();
+        let now = state.steps;
+        if let Some(function) = state.functions.get_mut(name) {
+            function.active -= 1;
+            if function.active == 0 {
+                function.steps += now - function.entered_at;
+            }
+        }
+    }
+}
+
+/// A function call in progress; ends when dropped, including on errors
+#[derive(Debug)]
+pub(crate) struct ActiveCall {
+    profiler: Rc<Profiler>,
+    name: String,
+}
+
+impl Drop for ActiveCall {
+    fn drop(&mut self) {
+        self.profiler.exit(&self.name);
+    }
+}
+
+/// Calls and inclusive steps of one function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    pub steps: u64,
+}
+
+/// What a profiler recorded, sorted by steps (most first)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileReport {
+    /// Steps of the whole run, inside functions or not
+    pub total_steps: u64,
+    pub functions: Vec<FunctionProfile>,
+}
+
+impl ProfileReport {
+    /// The report as a table, limited to the `top` busiest functions if given
+    pub fn render(&self, top: Option<usize>) -> String {
+        let shown = &self.functions[..top.unwrap_or(usize::MAX).min(self.functions.len())];
+        let width = shown.iter().map(|function| function.name.len()).chain([8]).max().unwrap_or(8);
+        let mut out = format!("{:<width$}  {:>10}  {:>12}  {:>6}\n", "function", "calls", "steps", "share");
+        for function in shown {
+            let _ = writeln!(
+                out,
+                "{:<width$}  {:>10}  {:>12}  {:>5.1}%",
+                function.name,
+                function.calls,
+                function.steps,
+                share(function.steps, self.total_steps)
+            );
+        }
+        let hidden = self.functions.len() - shown.len();
+        if hidden > 0 {
+            let _ = writeln!(out, "({hidden} more function{} not shown)", if hidden == 1 { "" } else { "s" });
+        }
+        let _ = writeln!(out, "total: {} steps", self.total_steps);
+        out
+    }
+}
+
+/// `part` as a percentage of `total`
+#[allow(clippy::cast_precision_loss)]
+fn share(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
@@ -0,0 +1,290 @@
+//! Dead binding elimination for loaded libraries
+//!
+//! Loading a whole library for a one-line program evaluates and retains every
+//! top-level binding it defines. [`plan_pruning`] looks at a program before it
+//! runs, reads the libraries it loads, and works out which of their top-level
+//! bindings the program can reach: starting from the program's free variables,
+//! a library binding is kept when its name is referenced, and the free
+//! variables of a kept binding's value are followed in turn. Attaching the
+//! resulting [`PrunePlan`] to an environment with
+//! [`Environment::set_prune_plan`](crate::Environment::set_prune_plan) makes
+//! `load` skip the other bindings.
+//!
+//! The analysis works on names only, so it errs on the side of keeping: a
+//! name bound in several places keeps all of them. A library that itself
+//! loads another library is kept whole, as are libraries that cannot be read
+//! or parsed (evaluation reports those errors as usual) and libraries loaded
+//! under a namespace with `load ... as`, whose namespace is a value the
+//! program can pass around. Bindings that are
+//! pruned are never evaluated, so an error in an unused binding no longer
+//! stops the program.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use parlang::{eval, parse, plan_pruning, Environment};
+//!
+//! let program = parse("load \"examples/stdlib.par\" in abs (-3)").unwrap();
+//! let plan = plan_pruning(&program);
+//! let mut env = Environment::with_builtins();
+//! env.set_prune_plan(Some(plan));
+//! let value = eval(&program, &env).unwrap();
+//! ```
+
+use crate::ast::Expr;
+use crate::load_path::LoadPath;
+use crate::subst::free_vars;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// Which top-level bindings of each loaded library a program needs
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PrunePlan {
+    /// Library path (as found by `load`) to the names to keep
+    keep: HashMap<String, HashSet<String>>,
+    /// Library path and the names that will be skipped, in source order
+    pruned: Vec<(String, Vec<String>)>,
+}
+
+impl PrunePlan {
+    /// Libraries with bindings that will be skipped, each with the skipped
+    /// names in source order; libraries that lose nothing are omitted
+    pub fn pruned(&self) -> &[(String, Vec<String>)] {
+        &self.pruned
+    }
+
+    /// Names to keep from the library at `path`, or `None` to keep everything
+    pub(crate) fn keep_set(&self, path: &str) -> Option<&HashSet<String>> {
+        self.keep.get(path)
+    }
+}
+
+/// Work out which bindings of the libraries loaded by `program` it uses
+///
+/// Every `load` in the program is considered, wherever it appears. Libraries
+/// are looked up from the working directory; use [`plan_pruning_from`] for a
+/// program that loads them from elsewhere.
+pub fn plan_pruning(program: &Expr) -> PrunePlan {
+    plan_pruning_from(program, &LoadPath::default())
+}
+
+/// Work out which bindings of the libraries loaded by `program` it uses,
+/// finding them through `load_path` exactly as `load` does during evaluation
+pub fn plan_pruning_from(program: &Expr, load_path: &LoadPath) -> PrunePlan {
+    let mut libraries: Vec<(String, Vec<(String, Expr)>)> = Vec::new();
+    let (paths, namespaced) = loaded_paths(program);
+    for path in paths.into_iter().filter(|path| !namespaced.contains(path)) {
+        let Ok(path) = load_path.resolve(&path) else { continue };
+        let Ok(content) = fs::read_to_string(&path) else { continue };
+        let path = path.display().to_string();
+        let Ok(library) = crate::parser::parse(&content) else { continue };
+        if let Some(bindings) = library_bindings(&library) {
+            libraries.push((path, bindings));
+        }
+    }
+
+    let mut kept: HashSet<(usize, usize)> = HashSet::new();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = free_vars(program).into_iter().collect();
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        for (lib_index, (_, bindings)) in libraries.iter().enumerate() {
+            for (binding_index, (binding_name, value)) in bindings.iter().enumerate() {
+                if *binding_name == name && kept.insert((lib_index, binding_index)) {
+                    pending.extend(free_vars(value));
+                }
+            }
+        }
+    }
+
+    let mut plan = PrunePlan::default();
+    for (lib_index, (path, bindings)) in libraries.into_iter().enumerate() {
+        let mut keep = HashSet::new();
+        let mut pruned = Vec::new();
+        for (binding_index, (name, _)) in bindings.into_iter().enumerate() {
+            if kept.contains(&(lib_index, binding_index)) {
+                keep.insert(name);
+            } else {
+                pruned.push(name);
+            }
+        }
+        if !pruned.is_empty() {
+            plan.pruned.push((path.clone(), pruned));
+        }
+        plan.keep.insert(path, keep);
+    }
+    plan
+}
+
+/// Drop the top-level bindings of a library that are not in `keep`
+///
+/// Only the binding spine (`let ... in`, sequential bindings, type aliases) is
+/// rewritten; whatever follows it is left as is.
+pub(crate) fn prune_library(expr: &Expr, keep: &HashSet<String>) -> Expr {
+    match expr {
+        Expr::Let(name, _, _, body) if !keep.contains(name) => prune_library(body, keep),
+        Expr::Let(name, ty_ann, value, body) => Expr::Let(
+            name.clone(),
+            ty_ann.clone(),
+            value.clone(),
+            Box::new(prune_library(body, keep)),
+        ),
+        Expr::Seq(bindings, body) => Expr::Seq(
+            bindings.iter().filter(|(name, _, _)| keep.contains(name)).cloned().collect(),
+            Box::new(prune_library(body, keep)),
+        ),
+        Expr::TypeAlias(name, ty_expr, body) => {
+            Expr::TypeAlias(name.clone(), ty_expr.clone(), Box::new(prune_library(body, keep)))
+        }
+        _ => expr.clone(),
+    }
+}
+
+/// Distinct paths of every `load` in an expression, in first-seen order, and
+/// the paths loaded under a namespace
+fn loaded_paths(expr: &Expr) -> (Vec<String>, HashSet<String>) {
+    let mut paths = Vec::new();
+    let mut namespaced = HashSet::new();
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        if let Expr::Load(path, _) = expr {
+            if !paths.contains(path) {
+                paths.push(path.clone());
+            }
+        }
+        if let Expr::LoadAs(path, _, _) = expr {
+            namespaced.insert(path.clone());
+        }
+        stack.extend(expr.children());
+    }
+    (paths, namespaced)
+}
+
+/// Top-level bindings of a library in source order, or `None` when the
+/// library loads another one and its bindings are therefore not all known
+fn library_bindings(expr: &Expr) -> Option<Vec<(String, Expr)>> {
+    let mut bindings = Vec::new();
+    let mut current = expr;
+    loop {
+        match current {
+            Expr::Let(name, _, value, body) => {
+                bindings.push((name.clone(), (**value).clone()));
+                current = body;
+            }
+            Expr::Seq(seq, body) => {
+                bindings.extend(seq.iter().map(|(name, _, value)| (name.clone(), value.clone())));
+                current = body;
+            }
+            Expr::TypeAlias(_, _, body) => current = body,
+            Expr::Load(..) | Expr::LoadAs(..) => return None,
+            _ => return Some(bindings),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{eval, extract_bindings, Environment, Value};
+    use crate::parser::parse;
+    use std::path::PathBuf;
+
+    const LIBRARY: &str = "
+        let helper = fun x -> x * 10;
+        let used = fun x -> helper x + 1;
+        let other = fun x -> x - 1;
+        let broken = 1 / 0;
+        let unrelated = fun x -> other x;
+    ";
+
+    fn write_library(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_keeps_only_reachable_bindings() {
+        let path = write_library("test_prune_reachable.par", LIBRARY);
+        let source = format!("load \"{}\" in used 4", path.display());
+        let program = parse(&source).unwrap();
+
+        let plan = plan_pruning(&program);
+        let path_name = path.to_str().unwrap().to_string();
+        let pruned = vec!["other".to_string(), "broken".to_string(), "unrelated".to_string()];
+        assert_eq!(plan.pruned(), &[(path_name, pruned)]);
+
+        let mut env = Environment::new();
+        env.set_prune_plan(Some(plan));
+        let mut names: Vec<String> = extract_bindings(&program, &env)
+            .unwrap()
+            .visible_bindings()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["helper", "used"]);
+        // The failing binding is skipped along with the rest
+        assert_eq!(eval(&program, &env), Ok(Value::Int(41)));
+        assert!(eval(&program, &Environment::new()).is_err());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_let_chain_library_and_shadowing() {
+        let content = "let f = fun x -> x in let g = fun x -> f x in let h = 3 in 0";
+        let path = write_library("test_prune_let_chain.par", content);
+        // `h` is bound by the program itself, so the library's `h` is not needed
+        let source = format!("load \"{}\" in let h = 1 in g h", path.display());
+        let program = parse(&source).unwrap();
+
+        let plan = plan_pruning(&program);
+        assert_eq!(plan.pruned().len(), 1);
+        assert_eq!(plan.pruned()[0].1, vec!["h".to_string()]);
+
+        let mut env = Environment::new();
+        env.set_prune_plan(Some(plan));
+        assert_eq!(eval(&program, &env), Ok(Value::Int(1)));
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_namespaced_load_keeps_library_whole() {
+        let path = write_library("test_prune_namespaced.par", LIBRARY);
+        // The namespace record holds every binding, even where the plain load prunes
+        let source = format!("load \"{0}\" in load \"{0}\" as Lib in used 4", path.display());
+        let program = parse(&source).unwrap();
+
+        let plan = plan_pruning(&program);
+        assert!(plan.pruned().is_empty());
+        assert_eq!(plan.keep_set(path.to_str().unwrap()), None);
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_nested_load_keeps_library_whole() {
+        let inner = write_library("test_prune_inner.par", "let a = 1; let b = 2;");
+        let outer_content = format!("let c = 3; load \"{}\"", inner.display());
+        let outer = write_library("test_prune_outer.par", &outer_content);
+        let program = parse(&format!("load \"{}\" in c", outer.display())).unwrap();
+
+        let plan = plan_pruning(&program);
+        assert!(plan.pruned().is_empty());
+        assert_eq!(plan.keep_set(outer.to_str().unwrap()), None);
+
+        fs::remove_file(&inner).ok();
+        fs::remove_file(&outer).ok();
+    }
+
+    #[test]
+    fn test_prune_library_rewrites_spine_only() {
+        let library = parse("let a = 1; let b = 2; let c = a in c").unwrap();
+        let keep: HashSet<String> = ["a".to_string(), "c".to_string()].into_iter().collect();
+        assert_eq!(prune_library(&library, &keep).to_string(), parse("let a = 1; let c = a in c").unwrap().to_string());
+    }
+}
@@ -0,0 +1,1553 @@
+/// Interactive session state for the `ParLang` REPL
+///
+/// `ReplSession` owns everything that persists between REPL inputs: the
+/// environment of top-level bindings, the type checking mode and display
+/// settings changed with `:set`, and the transcript being
+/// recorded with `:record`. The binary only handles line editing and
+/// printing, which keeps the session itself testable.
+use crate::ast::Expr;
+use crate::describe::describe_environment;
+use crate::exhaustiveness::check_matches;
+use crate::dot::{write_value_to_dot_file, DotOptions};
+use crate::eval::{eval, DisplayOptions, Environment, EvalError, SharedBindings, Value};
+use crate::interpreter::Interpreter;
+use crate::parser::{parse, strip_bom};
+use crate::profile::Profiler;
+use crate::transcript::{parse_transcript, render_response, transcript_header, FileStore, TranscriptEntry, TranscriptStore};
+use crate::lint;
+use crate::load_path::LoadPath;
+use crate::typechecker::{canonicalize, typecheck_equalities, typecheck_in, typecheck_toplevel, TypeEnv, TypeError};
+use std::collections::{BTreeSet, VecDeque};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Number of inputs `:undo` can revert
+pub const UNDO_HISTORY_LIMIT: usize = 50;
+
+/// Function calls an input may have in progress at once, so runaway
+/// recursion ends in an error instead of a stack overflow
+///
+/// Evaluating takes up to about 70 KB of stack per call in debug builds, so
+/// the REPL should run on a thread with room for this many calls.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// Ending a line with this character (outside a literal) continues the input on the next line
+pub const LINE_CONTINUATION: char = '\\';
+
+/// Name of the history file in the home directory, when `XDG_DATA_HOME` is not set
+pub const HISTORY_FILE_NAME: &str = ".parlang_history";
+
+/// Where the REPL keeps its input history, unless `--history-file` names a file
+///
+/// `$XDG_DATA_HOME/parlang/history` when `XDG_DATA_HOME` is an absolute
+/// path, otherwise [`HISTORY_FILE_NAME`] in `home`, or `None` when neither
+/// is set. The variables' values are passed in so the choice is testable.
+pub fn default_history_path(xdg_data_home: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    match xdg_data_home.map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+        Some(dir) => Some(dir.join("parlang").join("history")),
+        None => home.filter(|home| !home.is_empty()).map(|home| PathBuf::from(home).join(HISTORY_FILE_NAME)),
+    }
+}
+
+/// The REPL commands with what they do, as `:help` lists them
+pub const COMMANDS: &[(&str, &str)] = &[
+    (":help", "List these commands"),
+    (":env", "List the current bindings with their values and types"),
+    (":type EXPR, :t EXPR", "Print the inferred type of EXPR without evaluating it"),
+    (":load FILE", "Load the bindings of a .par file, like `load \"FILE\"`"),
+    (":reload [FILE]", "Read loaded libraries from disk again, replacing the bindings FILE made"),
+    (":clear", "Remove all bindings and type definitions, keeping the settings"),
+    (":undo [N]", "Revert the bindings made by the last N inputs (default 1)"),
+    (":set float-precision N|exact", "Print floats with N significant digits, or exactly"),
+    (":set typecheck off|warn|strict", "Choose how inputs are type checked"),
+    (":set dynamic-toplevel on|off", "Resolve top-level names at call time, or when closures are created"),
+    (":set debug on|off", "Make the debug_env built-in available, or not"),
+    (":profile on|off", "Print calls and steps per function after each result"),
+    (":dotval NAME FILE", "Write the value bound to NAME as a Graphviz graph"),
+    (":record FILE, :record off", "Start or stop recording a transcript"),
+    (":replay FILE", "Run a transcript in a fresh session and report the first changed response"),
+    (":quit, :q", "Leave the REPL"),
+];
+
+/// How the REPL type checks inputs, set with `:set typecheck off|warn|strict`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypeCheckMode {
+    /// Inputs are evaluated without being checked
+    Off,
+    /// Type errors and lints are printed as warnings, and the input is
+    /// evaluated anyway
+    #[default]
+    Warn,
+    /// The inferred type is printed, and inputs with type errors are not evaluated
+    Strict,
+}
+
+impl fmt::Display for TypeCheckMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeCheckMode::Off => write!(f, "off"),
+            TypeCheckMode::Warn => write!(f, "warn"),
+            TypeCheckMode::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+/// State of an interactive REPL session
+///
+/// # Type Checking
+///
+/// Each input is handled according to the [`TypeCheckMode`], `warn` by
+/// default: a type error is printed as `Warning: Type error: ...` before the
+/// result, so code the checker rejects can still be explored. `strict`
+/// refuses to evaluate such inputs and prints the type of the others; `off`
+/// evaluates inputs without checking them. In every mode, a `match` that
+/// is non-exhaustive or has unreachable arms is reported as a warning.
+///
+/// # Top-level Binding Modes
+///
+/// By default closures capture the values of the top-level names they use
+/// when they are created (ordinary lexical scoping), so redefining a name
+/// does not change functions defined earlier.
+///
+/// With `:set dynamic-toplevel on`, top-level definitions are stored in a
+/// shared layer and closures look up top-level names in that layer at call
+/// time. Redefining `helper` then changes every function that calls
+/// `helper`. Local bindings (`let ... in`, function parameters, match
+/// variables) are still captured lexically and always take precedence.
+/// The mode only affects closures created while it is on: a function
+/// defined before `:set dynamic-toplevel on` keeps the values it captured
+/// and does not see later redefinitions.
+///
+/// # Transcripts
+///
+/// `:record <path>` starts a transcript: every later input is appended to it
+/// together with its response, until `:record off`. `:replay <path>` runs the
+/// inputs of a transcript through a fresh session (with the same type
+/// checking setting) and reports the first input whose response differs
+/// from the recorded one. Transcripts are read and written through a
+/// [`TranscriptStore`], files by default.
+///
+/// # Undo
+///
+/// Before each input that succeeds, the session keeps a snapshot of its
+/// bindings (at most [`UNDO_HISTORY_LIMIT`], dropping the oldest). `:undo`
+/// restores the snapshot taken before the most recent input, and `:undo N`
+/// goes back `N` inputs. Only bindings and type definitions are reverted:
+/// values written through references (`r := v`) keep their new contents.
+///
+/// # Profiling
+///
+/// `:profile on` prints, after the result of each input, how often each
+/// function was called while evaluating it and how many steps it took (see
+/// [`crate::profile`]). `:profile off` stops profiling.
+///
+/// # Session Commands
+///
+/// `:help` lists the [`COMMANDS`]. `:load <file>` adds a library's bindings
+/// to the session as `load "<file>"` would and names them; `:clear` removes
+/// every binding and type definition but keeps the settings. Libraries are
+/// read and parsed once per session, so after editing one, `:reload <file>`
+/// reads it again and replaces the bindings its earlier load made, and
+/// `:reload` alone makes every later load read its file again. `:load`,
+/// `:clear` and `:reload <file>` can be undone with `:undo`. `:quit` only marks the session as finished
+/// ([`is_finished`](Self::is_finished)); leaving is up to the caller.
+///
+/// # Inspecting Values
+///
+/// `:set debug on` makes the `debug_env` built-in available: `debug_env ()`
+/// returns a record of the bindings in scope where it is called (see
+/// [`crate::builtins`]). It applies to functions defined earlier too, and
+/// `:set debug off` makes `debug_env` fail again.
+///
+/// `:dotval <name> <file.dot>` writes the value bound to `name` as a
+/// Graphviz graph (see [`crate::dot::value_to_dot_string`]).
+///
+/// `:type <expr>` (or `:t <expr>`) prints the inferred type of an expression
+/// without evaluating it. The session keeps the types of its top-level
+/// definitions in every type checking mode, so `:t double` works after
+/// `let double = fun x -> x + x;`. A definition that does not type check is
+/// still evaluated in `warn` and `off` mode, but no type is kept for its
+/// names: the type checker and `:type` treat them as unbound, noting that
+/// their definition did not type check.
+#[derive(Debug, Clone)]
+pub struct ReplSession {
+    /// Evaluates inputs, holding the top-level bindings accumulated from previous inputs
+    interpreter: Interpreter,
+    /// Types of the top-level bindings, for type checking and `:type`
+    type_env: TypeEnv,
+    /// How inputs are type checked before evaluation
+    type_mode: TypeCheckMode,
+    /// Rendering options for values printed at the top level
+    display: DisplayOptions,
+    /// Live top-level bindings, present when dynamic top-level mode is on
+    toplevel: Option<SharedBindings>,
+    /// Where `:record` writes and `:replay` reads transcripts
+    transcripts: Rc<dyn TranscriptStore>,
+    /// Path of the transcript being recorded, if any
+    recording: Option<String>,
+    /// Bindings before each of the latest successful inputs, oldest first
+    history: VecDeque<Snapshot>,
+    /// Profiler attached to the environment, enabled by `:profile on`
+    profiler: Rc<Profiler>,
+    /// Raised to interrupt the input being evaluated, cleared before each input
+    interrupt: Arc<AtomicBool>,
+    /// Set by `:quit`
+    finished: bool,
+}
+
+/// The binding state of a session, as restored by `:undo`
+#[derive(Debug, Clone)]
+struct Snapshot {
+    env: Environment,
+    type_env: TypeEnv,
+    toplevel: Option<SharedBindings>,
+    /// Contents of the shared top-level layer at the time of the snapshot
+    toplevel_bindings: Vec<(String, Value)>,
+}
+
+impl Default for ReplSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplSession {
+    /// Create a session with the prelude built-ins and default settings
+    ///
+    /// Inputs may have at most [`DEFAULT_MAX_DEPTH`] calls in progress, and
+    /// stop early when [`interrupt_flag`](Self::interrupt_flag) is set.
+    pub fn new() -> Self {
+        let profiler = Rc::new(Profiler::new());
+        profiler.set_enabled(false);
+        let mut env = Environment::with_builtins();
+        env.set_profiler(Some(Rc::clone(&profiler)));
+        env.set_depth_limit(Some(DEFAULT_MAX_DEPTH));
+        let interrupt = Arc::new(AtomicBool::new(false));
+        env.set_interrupt(Some(Arc::clone(&interrupt)));
+        ReplSession {
+            interpreter: Interpreter::with_environment(env),
+            type_env: TypeEnv::with_builtins(),
+            type_mode: TypeCheckMode::default(),
+            display: DisplayOptions::default(),
+            toplevel: None,
+            transcripts: Rc::new(FileStore),
+            recording: None,
+            history: VecDeque::new(),
+            profiler,
+            interrupt,
+            finished: false,
+        }
+    }
+
+    /// Use `store` for transcripts instead of the filesystem
+    pub fn set_transcript_store(&mut self, store: Rc<dyn TranscriptStore>) {
+        self.transcripts = store;
+    }
+
+    /// Enable strict type checking of inputs, or turn it off
+    pub fn set_type_check(&mut self, enabled: bool) {
+        self.type_mode = if enabled { TypeCheckMode::Strict } else { TypeCheckMode::Off };
+    }
+
+    /// Set how inputs are type checked
+    pub fn set_type_mode(&mut self, mode: TypeCheckMode) {
+        self.type_mode = mode;
+    }
+
+    /// Limit how many function calls an input may have in progress at once,
+    /// or stop limiting them with `None`
+    pub fn set_depth_limit(&mut self, limit: Option<usize>) {
+        self.interpreter.environment_mut().set_depth_limit(limit);
+    }
+
+    /// Look up the libraries named by `load` and `:load` as `load_path` says,
+    /// both when type checking and evaluating
+    pub fn set_load_path(&mut self, load_path: LoadPath) {
+        self.interpreter.environment_mut().set_load_path(load_path.clone());
+        self.type_env.set_load_path(load_path);
+    }
+
+    /// The flag that interrupts the input being evaluated when set
+    ///
+    /// Setting it from a Ctrl+C handler or another thread makes the current
+    /// input fail with `Interrupted` and leaves the bindings as they were
+    /// before it. The session clears the flag before each input.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    /// How inputs are type checked
+    pub fn type_mode(&self) -> TypeCheckMode {
+        self.type_mode
+    }
+
+    /// The display options used to print results
+    pub fn display_options(&self) -> DisplayOptions {
+        self.display
+    }
+
+    /// The environment of top-level bindings
+    pub fn environment(&self) -> &Environment {
+        self.interpreter.environment()
+    }
+
+    /// Whether `:quit` was given, so the REPL should stop reading input
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Check whether an input line is a REPL command (`:set ...`) rather than code
+    pub fn is_command(input: &str) -> bool {
+        strip_bom(input).trim_start().starts_with(':')
+    }
+
+    /// Whether the lines typed so far form an input the REPL should submit
+    ///
+    /// Input is complete once it parses, unless its last line ends in a
+    /// [`LINE_CONTINUATION`], which asks for another line whether or not the
+    /// input would parse. A backslash inside a string or character literal
+    /// is an escape, not a continuation.
+    pub fn is_complete(input: &str) -> bool {
+        let input = input.trim_end();
+        let continued = continuation_marks(input).last().is_some_and(|&at| at + 1 == input.len());
+        !continued && parse(join_continued_lines(input).trim()).is_ok()
+    }
+
+    /// Execute one REPL input, returning the text to print
+    ///
+    /// Code is parsed, optionally type checked, and evaluated; its bindings are
+    /// persisted for later inputs. Lines starting with `:` are commands. While
+    /// a transcript is being recorded, the input and response are appended to
+    /// it (`:record` commands themselves are not recorded).
+    ///
+    /// # Errors
+    ///
+    /// Returns a message prefixed with the failing stage (`parse error at`
+    /// a line and column, `Type error`, `Evaluation error`) or describing an
+    /// invalid command.
+    pub fn execute(&mut self, input: &str) -> Result<String, String> {
+        let input = strip_bom(input).trim();
+        let response = self.execute_input(input);
+        if let Some(path) = &self.recording {
+            if !input.starts_with(":record") {
+                let entry = TranscriptEntry { input: input.to_string(), response: response.clone() };
+                if let Err(e) = self.transcripts.append(path, &entry.to_json()) {
+                    eprintln!("Warning: {e}; recording stopped");
+                    self.recording = None;
+                }
+            }
+        }
+        response
+    }
+
+    /// Execute one input without recording it
+    fn execute_input(&mut self, input: &str) -> Result<String, String> {
+        self.interrupt.store(false, Ordering::Relaxed);
+        if Self::is_command(input) {
+            return self.execute_command(input);
+        }
+
+        let before = self.snapshot();
+        let output = self.execute_code(&join_continued_lines(input))?;
+        self.remember(before);
+        Ok(output)
+    }
+
+    /// Keep `before` as the state `:undo` returns to, dropping the oldest snapshot if full
+    fn remember(&mut self, before: Snapshot) {
+        if self.history.len() == UNDO_HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(before);
+    }
+
+    /// Parse, check, and evaluate code, persisting its bindings
+    fn execute_code(&mut self, input: &str) -> Result<String, String> {
+        let expr = parse(input)?;
+        self.execute_expr(&expr, input)
+    }
+
+    /// Check and evaluate an input parsed from `source`, persisting its bindings
+    fn execute_expr(&mut self, expr: &Expr, source: &str) -> Result<String, String> {
+        let mut output = Vec::new();
+        if self.type_mode != TypeCheckMode::Off {
+            match typecheck_equalities(expr, &mut self.type_env.clone()) {
+                Ok((ty, equalities)) => {
+                    if self.type_mode == TypeCheckMode::Strict {
+                        output.push(format!("Type: {ty}"));
+                    }
+                    output.extend(lint::float_equality(&equalities).iter().map(|lint| format!("Warning: {lint}")));
+                }
+                Err(e) if self.type_mode == TypeCheckMode::Strict => return Err(self.type_error(&e)),
+                Err(e) => output.push(format!("Warning: {}", self.type_error(&e))),
+            }
+        }
+        output.extend(check_matches(expr, source, self.interpreter.environment()).iter().map(|warning| format!("Warning: {warning}")));
+
+        let profiling = self.profiler.is_enabled();
+        self.profiler.reset();
+
+        // Evaluate the input once, keeping its top-level bindings; in dynamic
+        // mode, top-level sequential bindings are committed one by one and
+        // only the body's bindings are kept as usual
+        let value = match (&self.toplevel, expr) {
+            (Some(layer), Expr::Seq(bindings, body)) => {
+                let layer = layer.clone();
+                self.execute_dynamic_seq(&layer, bindings, body)
+            }
+            _ => self.interpreter.eval_toplevel(expr),
+        };
+        let value = value.map_err(|e| format!("Evaluation error: {e}"))?;
+        self.hoist_bindings();
+        output.push(value.display_with(self.display).to_string());
+        if profiling {
+            output.push(self.profiler.report().render(None).trim_end().to_string());
+        }
+        self.record_types(expr);
+
+        Ok(output.join("\n"))
+    }
+
+    /// Remember the types of the names an evaluated input binds
+    ///
+    /// Names whose definitions do not type check are forgotten, so `:type`
+    /// never reports the type of an earlier definition they replaced.
+    fn record_types(&mut self, expr: &Expr) {
+        let mut type_env = self.type_env.clone();
+        if typecheck_toplevel(expr, &mut type_env).is_ok() {
+            self.type_env = type_env;
+        } else {
+            for name in spine_names(expr) {
+                self.type_env.unbind(name);
+            }
+        }
+    }
+
+    /// The inferred type of an expression, without evaluating it
+    fn type_of(&self, input: &str) -> Result<String, String> {
+        let expr = parse(input)?;
+        let mut type_env = self.type_env.clone();
+        let ty = typecheck_in(&expr, &mut type_env).map_err(|e| self.type_error(&e))?;
+        Ok(canonicalize(&self.type_env.generalize(&ty)).ty.to_string())
+    }
+
+    /// Message for a type error, noting when an unbound name is one whose
+    /// definition was evaluated without a type
+    fn type_error(&self, error: &TypeError) -> String {
+        match error {
+            TypeError::UnboundVariable(name, _) if self.interpreter.environment().resolve(name).is_some() => {
+                format!("Type error: {error} (its definition did not type check)")
+            }
+            _ => format!("Type error: {error}"),
+        }
+    }
+
+    /// Evaluate top-level sequential bindings in dynamic mode
+    ///
+    /// Each binding is defined in the shared layer before the next one is
+    /// evaluated, so functions defined in the same input refer to each other
+    /// through the layer instead of capturing one another. If any binding or
+    /// the body fails, the layer is put back as it was before the input, as
+    /// a failing input in static mode leaves the environment unchanged.
+    fn execute_dynamic_seq(
+        &mut self,
+        layer: &SharedBindings,
+        bindings: &[(String, Option<crate::ast::TypeAnnotation>, Expr)],
+        body: &Expr,
+    ) -> Result<Value, EvalError> {
+        let before = layer.snapshot();
+        let result = bindings
+            .iter()
+            .try_for_each(|(name, _ty_ann, value)| {
+                let value = eval(value, self.interpreter.environment()).map_err(|e| {
+                    if name.starts_with('_') {
+                        e
+                    } else {
+                        EvalError::InBinding(name.clone(), None, Box::new(e))
+                    }
+                })?;
+                layer.define(name.clone(), value);
+                Ok(())
+            })
+            .and_then(|()| self.interpreter.eval_toplevel(body));
+        if result.is_err() {
+            layer.restore(before);
+        }
+        result
+    }
+
+    /// In dynamic mode, move newly persisted bindings into the shared layer
+    fn hoist_bindings(&mut self) {
+        if let Some(layer) = &self.toplevel {
+            for (name, value) in self.interpreter.environment_mut().take_bindings() {
+                layer.define(name, value);
+            }
+        }
+    }
+
+    /// Execute a `:`-prefixed command
+    fn execute_command(&mut self, input: &str) -> Result<String, String> {
+        let mut words = input.split_whitespace();
+        match words.next() {
+            Some(":set") => {
+                let option = words.next().ok_or("Usage: :set <option> <value>")?;
+                let value = words.next().ok_or("Usage: :set <option> <value>")?;
+                self.set_option(option, value)
+            }
+            Some(":type" | ":t") => match input.split_once(char::is_whitespace) {
+                Some((_, code)) if !code.trim().is_empty() => self.type_of(code),
+                _ => Err("Usage: :type <expr>".to_string()),
+            },
+            Some(":profile") => match (words.next(), words.next()) {
+                (Some(setting @ ("on" | "off")), None) => {
+                    self.profiler.set_enabled(setting == "on");
+                    Ok(format!("Profiling {setting}"))
+                }
+                _ => Err("Usage: :profile on|off".to_string()),
+            },
+            Some(":help") => Ok(help_text()),
+            Some(":env") => Ok(describe_environment(self.interpreter.environment(), Some(&self.type_env)).trim_end().to_string()),
+            Some(":load") => match input.split_once(char::is_whitespace) {
+                Some((_, path)) if !path.trim().is_empty() => {
+                    let before = self.snapshot();
+                    let output = self.load(path.trim())?;
+                    self.remember(before);
+                    Ok(output)
+                }
+                _ => Err("Usage: :load <file>".to_string()),
+            },
+            Some(":reload") => match input.split_once(char::is_whitespace) {
+                Some((_, path)) if !path.trim().is_empty() => {
+                    let before = self.snapshot();
+                    let output = self.reload(path.trim())?;
+                    self.remember(before);
+                    Ok(output)
+                }
+                _ => {
+                    self.interpreter.reload();
+                    Ok("Libraries will be read from disk again on their next load".to_string())
+                }
+            },
+            Some(":clear") => {
+                let before = self.snapshot();
+                self.clear();
+                self.remember(before);
+                Ok("Cleared all bindings".to_string())
+            }
+            Some(":quit" | ":q") => {
+                self.finished = true;
+                Ok("Goodbye!".to_string())
+            }
+            Some(":record") => match words.collect::<Vec<_>>().join(" ").as_str() {
+                "" => Err("Usage: :record <file> | :record off".to_string()),
+                "off" => match self.recording.take() {
+                    Some(path) => Ok(format!("Stopped recording to {path}")),
+                    None => Err("Not recording".to_string()),
+                },
+                path => {
+                    self.transcripts.create(path)?;
+                    self.transcripts.append(path, &transcript_header())?;
+                    self.recording = Some(path.to_string());
+                    Ok(format!("Recording to {path}"))
+                }
+            },
+            Some(":dotval") => match (words.next(), words.next(), words.next()) {
+                (Some(name), Some(path), None) => {
+                    let value = self.interpreter.environment().resolve(name).ok_or_else(|| format!("Unbound variable: {name}"))?;
+                    write_value_to_dot_file(&value, path, &DotOptions::default())
+                        .map_err(|e| format!("Failed to write '{path}': {e}"))?;
+                    Ok(format!("Wrote value of {name} to {path}"))
+                }
+                _ => Err("Usage: :dotval <name> <file.dot>".to_string()),
+            },
+            Some(":undo") => {
+                let count = match (words.next(), words.next()) {
+                    (None, _) => 1,
+                    (Some(n), None) => n
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid undo count '{n}': expected a positive number"))?,
+                    _ => return Err("Usage: :undo [N]".to_string()),
+                };
+                self.undo(count)
+            }
+            Some(":replay") => match words.collect::<Vec<_>>().join(" ").as_str() {
+                "" => Err("Usage: :replay <file>".to_string()),
+                path => self.replay(path),
+            },
+            Some(command) => Err(format!("Unknown command: {command}")),
+            None => Err("Empty command".to_string()),
+        }
+    }
+
+    /// Load a library's bindings into the session, as `load "path"` would
+    ///
+    /// Returns the names the library bound, in the order they were bound.
+    fn load(&mut self, path: &str) -> Result<String, String> {
+        let before = self.snapshot();
+        self.execute_expr(&Expr::Load(path.to_string(), Box::new(Expr::EmptyBody)), "")?;
+        let after = self.snapshot();
+        let names: Vec<String> = visible_names(&after)
+            .filter(|name| lookup(&before, name) != lookup(&after, name))
+            .collect();
+        Ok(match names.len() {
+            0 => format!("Loaded {path}: no bindings"),
+            _ => format!("Loaded {path}: {}", names.join(", ")),
+        })
+    }
+
+    /// Read a library from disk again, replacing the bindings an earlier load of it made
+    ///
+    /// Libraries are cached after their first load, so a plain `:load`
+    /// would neither see changes to the file nor replace its bindings.
+    /// Returns the names whose values changed.
+    fn reload(&mut self, path: &str) -> Result<String, String> {
+        let before = self.snapshot();
+        self.interpreter.reload_library(path).map_err(|e| format!("Evaluation error: {e}"))?;
+        self.hoist_bindings();
+        self.record_types(&Expr::Load(path.to_string(), Box::new(Expr::EmptyBody)));
+        let after = self.snapshot();
+        let names: Vec<String> = visible_names(&after)
+            .filter(|name| lookup(&before, name) != lookup(&after, name))
+            .collect();
+        Ok(match names.len() {
+            0 => format!("Reloaded {path}: no changes"),
+            _ => format!("Reloaded {path}: {}", names.join(", ")),
+        })
+    }
+
+    /// Remove every binding and type definition, keeping the settings
+    fn clear(&mut self) {
+        self.interpreter.environment_mut().clear();
+        self.type_env = TypeEnv::with_builtins();
+        self.type_env.set_load_path(self.interpreter.environment().load_path().clone());
+        if self.toplevel.is_some() {
+            let layer = SharedBindings::new();
+            self.interpreter.environment_mut().set_toplevel(Some(layer.clone()));
+            self.toplevel = Some(layer);
+        }
+    }
+
+    /// The current binding state
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            env: self.interpreter.environment().clone(),
+            type_env: self.type_env.clone(),
+            toplevel: self.toplevel.clone(),
+            toplevel_bindings: self.toplevel.as_ref().map(SharedBindings::snapshot).unwrap_or_default(),
+        }
+    }
+
+    /// Revert the bindings of the last `count` inputs (or as many as were kept)
+    ///
+    /// Returns which names the undone inputs removed or restored.
+    fn undo(&mut self, count: usize) -> Result<String, String> {
+        if self.history.is_empty() {
+            return Err("Nothing to undo".to_string());
+        }
+        let count = count.min(self.history.len());
+        let start = self.history.len() - count;
+        let Some(snapshot) = self.history.drain(start..).next() else { unreachable!() };
+        let current = self.snapshot();
+        if let Some(layer) = &snapshot.toplevel {
+            layer.restore(snapshot.toplevel_bindings.clone());
+        }
+        *self.interpreter.environment_mut() = snapshot.env;
+        self.type_env = snapshot.type_env;
+        self.toplevel = snapshot.toplevel;
+
+        let after = self.snapshot();
+        let (mut removed, mut restored) = (Vec::new(), Vec::new());
+        let names: BTreeSet<String> = visible_names(&current).chain(visible_names(&after)).collect();
+        for name in &names {
+            match (lookup(&current, name), lookup(&after, name)) {
+                (Some(_), None) => removed.push(name.as_str()),
+                (now, before) if now != before => restored.push(name.as_str()),
+                _ => {}
+            }
+        }
+        let mut message = vec![format!("Undid {count} input{}", if count == 1 { "" } else { "s" })];
+        if !removed.is_empty() {
+            message.push(format!("removed {}", removed.join(", ")));
+        }
+        if !restored.is_empty() {
+            message.push(format!("restored {}", restored.join(", ")));
+        }
+        Ok(message.join("; "))
+    }
+
+    /// Replay a transcript in a fresh session and compare the responses
+    ///
+    /// Returns a summary when every response matches, and otherwise an
+    /// error naming the first diverging input (1-based) with both responses.
+    fn replay(&self, path: &str) -> Result<String, String> {
+        let entries = parse_transcript(&self.transcripts.read(path)?)?;
+        let mut session = ReplSession::new();
+        session.type_mode = self.type_mode;
+        session.transcripts = Rc::clone(&self.transcripts);
+        for (index, entry) in entries.iter().enumerate() {
+            let response = session.execute(&entry.input);
+            if response != entry.response {
+                return Err(format!(
+                    "Replay of {path} diverged at input {} `{}`\n  recorded: {}\n  current:  {}",
+                    index + 1,
+                    entry.input,
+                    render_response(&entry.response),
+                    render_response(&response)
+                ));
+            }
+        }
+        Ok(format!("Replayed {} inputs from {path}: no divergence", entries.len()))
+    }
+
+    /// Change a session setting
+    fn set_option(&mut self, option: &str, value: &str) -> Result<String, String> {
+        match option {
+            "float-precision" => {
+                self.display.float_precision = if value == "exact" {
+                    None
+                } else {
+                    // Same bound as --float-precision; wider precisions overflow the formatter
+                    match value.parse::<u16>() {
+                        Ok(digits) if digits > 0 => Some(usize::from(digits)),
+                        _ => {
+                            return Err(format!(
+                                "Invalid float-precision '{value}': expected a positive number or 'exact'"
+                            ))
+                        }
+                    }
+                };
+                Ok(format!("float-precision = {value}"))
+            }
+            "typecheck" => {
+                self.type_mode = match value {
+                    "off" => TypeCheckMode::Off,
+                    "warn" => TypeCheckMode::Warn,
+                    "strict" => TypeCheckMode::Strict,
+                    _ => return Err(format!("Invalid typecheck '{value}': expected 'off', 'warn' or 'strict'")),
+                };
+                Ok(format!("typecheck = {value}"))
+            }
+            "dynamic-toplevel" => {
+                match value {
+                    "on" => {
+                        if self.toplevel.is_none() {
+                            let layer = SharedBindings::new();
+                            self.interpreter.environment_mut().set_toplevel(Some(layer.clone()));
+                            self.toplevel = Some(layer);
+                            self.hoist_bindings();
+                        }
+                    }
+                    "off" => {
+                        if let Some(layer) = self.toplevel.take() {
+                            // Closures created in dynamic mode keep their handle to the layer;
+                            // new inputs see a snapshot of it as ordinary bindings
+                            for (name, value) in layer.snapshot() {
+                                self.interpreter.environment_mut().bind(name, value);
+                            }
+                            self.interpreter.environment_mut().set_toplevel(None);
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid dynamic-toplevel '{value}': expected 'on' or 'off'"
+                        ))
+                    }
+                }
+                Ok(format!("dynamic-toplevel = {value}"))
+            }
+            "debug" => {
+                match value {
+                    "on" | "off" => self.interpreter.environment().set_debug_builtins(value == "on"),
+                    _ => return Err(format!("Invalid debug '{value}': expected 'on' or 'off'")),
+                }
+                Ok(format!("debug = {value}"))
+            }
+            _ => Err(format!("Unknown option: {option}")),
+        }
+    }
+}
+
+/// The `:help` listing of [`COMMANDS`]
+fn help_text() -> String {
+    let width = COMMANDS.iter().map(|(usage, _)| usage.len()).max().unwrap_or(0);
+    let lines = COMMANDS.iter().map(|(usage, effect)| format!("  {usage:width$}  {effect}"));
+    std::iter::once("Commands:".to_string()).chain(lines).collect::<Vec<_>>().join("\n")
+}
+
+/// Remove the line continuations from `input`, keeping the line breaks after them
+///
+/// Inputs are recorded and kept in history as typed, backslashes included,
+/// and joined only when executed.
+pub fn join_continued_lines(input: &str) -> String {
+    let mut joined = input.to_string();
+    for at in continuation_marks(input).into_iter().rev() {
+        joined.remove(at);
+    }
+    joined
+}
+
+/// Byte offsets of the [`LINE_CONTINUATION`]s in `input`: those outside
+/// string and character literals followed by nothing but blanks on their line
+fn continuation_marks(input: &str) -> Vec<usize> {
+    let mut marks = Vec::new();
+    // The closing quote of the literal being scanned, if any
+    let mut literal: Option<char> = None;
+    let mut chars = input.char_indices();
+    while let Some((at, c)) = chars.next() {
+        match (literal, c) {
+            (Some(_), '\\') => {
+                chars.next();
+            }
+            (Some(quote), c) if c == quote => literal = None,
+            (None, '"' | '\'') => literal = Some(c),
+            (None, LINE_CONTINUATION) => {
+                let rest = &input[at + 1..];
+                if rest[..rest.find('\n').unwrap_or(rest.len())].trim().is_empty() {
+                    marks.push(at);
+                }
+            }
+            _ => {}
+        }
+    }
+    marks
+}
+
+/// Names bound by the `let`s along the spine of a top-level input
+fn spine_names(expr: &Expr) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut current = expr;
+    loop {
+        match current {
+            Expr::Let(name, _, _, body) => {
+                names.push(name.as_str());
+                current = body;
+            }
+            Expr::Seq(bindings, body) => {
+                names.extend(bindings.iter().map(|(name, _, _)| name.as_str()));
+                current = body;
+            }
+            Expr::LoadAs(_, namespace, body) => {
+                names.push(namespace.as_str());
+                current = body;
+            }
+            Expr::TypeAlias(_, _, body) | Expr::TypeDef { body, .. } | Expr::Load(_, body) => current = body,
+            _ => return names,
+        }
+    }
+}
+
+/// Names bound by the session in a snapshot (local bindings and the shared layer)
+fn visible_names(snapshot: &Snapshot) -> impl Iterator<Item = String> + '_ {
+    snapshot.env.binding_order().into_iter().chain(snapshot.toplevel_bindings.iter().map(|(name, _)| name.clone()))
+}
+
+/// The value bound to `name` by the session in a snapshot
+fn lookup<'a>(snapshot: &'a Snapshot, name: &str) -> Option<&'a Value> {
+    if let Some(value) = snapshot.env.lookup_local(name) {
+        return Some(value);
+    }
+    snapshot.toplevel_bindings.iter().find(|(bound, _)| bound == name).map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcript::MemoryStore;
+
+    #[test]
+    fn test_execute_expression() {
+        let mut session = ReplSession::new();
+        assert_eq!(session.execute("1 + 2"), Ok("3".to_string()));
+    }
+
+    #[test]
+    fn test_bindings_persist() {
+        let mut session = ReplSession::new();
+        session.execute("let x = 40;").unwrap();
+        assert_eq!(session.execute("x + 2"), Ok("42".to_string()));
+    }
+
+    #[test]
+    fn test_constructors_persist() {
+        let mut session = ReplSession::new();
+        session.execute("type Option a = Some a | None in None").unwrap();
+        assert_eq!(
+            session.execute("Some 1 2"),
+            Err("Evaluation error: Constructor Some expects 1 arguments, got 2".to_string())
+        );
+        assert!(session.execute("Som 1").unwrap_err().contains("did you mean `Some`?"));
+        session.execute("let o = Some 3;").unwrap();
+        assert_eq!(session.execute("match o with | Some x -> x | None -> 0"), Ok("3".to_string()));
+        assert_eq!(
+            session.execute("match o with | Some x y -> x | None -> 0"),
+            Err("Evaluation error: Constructor Some expects 1 arguments, got 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_errors_are_prefixed() {
+        let mut session = ReplSession::new();
+        assert!(session.execute("let x = in y").unwrap_err().starts_with("parse error at line 1, column 9"));
+        assert!(session.execute("undefined_var").unwrap_err().starts_with("Evaluation error"));
+    }
+
+    #[test]
+    fn test_bom_crlf_and_tabs_in_input() {
+        let mut session = ReplSession::new();
+        assert!(ReplSession::is_command("\u{feff}:env\r\n"));
+        session.execute("\u{feff}let x =\t40;\r\n").unwrap();
+        assert_eq!(session.execute("x\r\n\t+ 2\r\n"), Ok("42".to_string()));
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        assert!(ReplSession::is_complete("1\n"));
+        assert!(!ReplSession::is_complete("1 \\\n"));
+        assert!(!ReplSession::is_complete("1 \\  \r\n"));
+        assert!(ReplSession::is_complete("1 \\\n+ 2\n"));
+        assert_eq!(join_continued_lines("1 \\\n+ 2\n"), "1 \n+ 2\n");
+
+        let mut session = ReplSession::new();
+        assert_eq!(session.execute("1 \\\n+ 2\n"), Ok("3".to_string()));
+        assert_eq!(session.execute("let x = \\\n  4;\nx \\\n* x"), Ok("16".to_string()));
+    }
+
+    #[test]
+    fn test_backslash_in_literal_is_not_a_continuation() {
+        assert_eq!(join_continued_lines("'\\\\' \\\n"), "'\\\\' \n");
+        assert_eq!(join_continued_lines("\"a\\\n"), "\"a\\\n");
+        assert_eq!(join_continued_lines("\"a\\\\\" \\\n"), "\"a\\\\\" \n");
+        assert!(!ReplSession::is_complete("\"a\\\n"));
+        // A backslash in the middle of a line is left to the parser
+        assert_eq!(join_continued_lines("1 \\ 2"), "1 \\ 2");
+    }
+
+    #[test]
+    fn test_continued_input_is_recorded_as_typed() {
+        let store = MemoryStore::new();
+        let mut session = recording_session(&store);
+        session.execute(":record t.json").unwrap();
+        session.execute("1 \\\n+ 2").unwrap();
+        session.execute(":record off").unwrap();
+
+        let entries = parse_transcript(&store.contents("t.json").unwrap()).unwrap();
+        assert_eq!(entries[0].input, "1 \\\n+ 2");
+        assert_eq!(entries[0].response, Ok("3".to_string()));
+        assert_eq!(
+            session.execute(":replay t.json"),
+            Ok("Replayed 1 inputs from t.json: no divergence".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_check_output() {
+        let mut session = ReplSession::new();
+        session.set_type_check(true);
+        assert_eq!(session.execute("1 + 2"), Ok("Type: Int\n3".to_string()));
+        assert!(session.execute("1 + true").unwrap_err().starts_with("Type error"));
+    }
+
+    const ILL_TYPED: &str = "let pick = fun b -> if b then 1 else true;";
+
+    #[test]
+    fn test_typecheck_warn_is_default() {
+        let mut session = ReplSession::new();
+        assert_eq!(session.type_mode(), TypeCheckMode::Warn);
+        assert_eq!(
+            session.execute(ILL_TYPED),
+            Ok("Warning: Type error: Cannot unify types: Int and Bool\n()".to_string())
+        );
+        // The value is kept, but no type for it
+        let unbound = "Type error: Unbound variable: pick (its definition did not type check)";
+        assert_eq!(session.execute("pick true"), Ok(format!("Warning: {unbound}\n1")));
+        assert_eq!(session.execute(":t pick"), Err(unbound.to_string()));
+        assert_eq!(session.execute("1 + 2"), Ok("3".to_string()));
+        // An input that fails to evaluate reports the evaluation error
+        assert!(session.execute("1 + true").unwrap_err().starts_with("Evaluation error"));
+    }
+
+    #[test]
+    fn test_typecheck_strict_refuses_ill_typed_input() {
+        let mut session = ReplSession::new();
+        assert_eq!(session.execute(":set typecheck strict"), Ok("typecheck = strict".to_string()));
+        assert_eq!(session.execute(ILL_TYPED), Err("Type error: Cannot unify types: Int and Bool".to_string()));
+        assert_eq!(session.execute("pick true"), Err("Type error: Unbound variable: pick".to_string()));
+        assert_eq!(session.execute(":t pick"), Err("Type error: Unbound variable: pick".to_string()));
+        assert_eq!(session.execute("let one = 1;"), Ok("Type: ()\n()".to_string()));
+        assert_eq!(session.execute(":t one"), Ok("Int".to_string()));
+    }
+
+    #[test]
+    fn test_typecheck_off_evaluates_without_warnings() {
+        let mut session = ReplSession::new();
+        assert_eq!(session.execute(":set typecheck off"), Ok("typecheck = off".to_string()));
+        assert_eq!(session.execute(ILL_TYPED), Ok("()".to_string()));
+        assert_eq!(session.execute("pick true"), Ok("1".to_string()));
+        assert_eq!(
+            session.execute(":t pick"),
+            Err("Type error: Unbound variable: pick (its definition did not type check)".to_string())
+        );
+        session.execute("let one = 1;").unwrap();
+        assert_eq!(session.execute(":t one"), Ok("Int".to_string()));
+
+        // Switching to strict afterwards refuses uses of the untyped binding
+        session.execute(":set typecheck strict").unwrap();
+        assert!(session.execute("pick true").unwrap_err().ends_with("(its definition did not type check)"));
+        assert!(session.execute(":set typecheck sometimes").is_err());
+        assert_eq!(session.type_mode(), TypeCheckMode::Strict);
+    }
+
+    #[test]
+    fn test_match_problems_are_warnings() {
+        let mut session = ReplSession::new();
+        session.execute("type Color = Red | Green | Blue in 0").unwrap();
+        assert_eq!(
+            session.execute("match Red with\n| Red -> 1\n| Green -> 2\n| Red -> 3"),
+            Ok("Warning: match on line 1 is non-exhaustive, missing: Blue\nWarning: match on line 1 has an unreachable arm 3: earlier arms cover it\n1".to_string())
+        );
+        assert_eq!(session.execute("match Red with | Red -> 1 | _ -> 2"), Ok("1".to_string()));
+    }
+
+    #[test]
+    fn test_type_check_reports_lints() {
+        let mut session = ReplSession::new();
+        session.set_type_check(true);
+        let output = session.execute("0.1 + 0.2 == 0.3").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "Type: Bool");
+        assert!(lines[1].starts_with("Warning: ((0.1 + 0.2) == 0.3) compares Floats exactly"), "{output}");
+        assert!(lines[1].ends_with("[float-equality]"), "{output}");
+        assert_eq!(lines[2], "false");
+        assert_eq!(session.execute("1 == 1"), Ok("Type: Bool\ntrue".to_string()));
+    }
+
+    #[test]
+    fn test_float_default_is_exact() {
+        let mut session = ReplSession::new();
+        let output = session.execute("let rate = 0.1; rate * 3.0").unwrap();
+        assert_eq!(output, "0.30000000000000004");
+    }
+
+    #[test]
+    fn test_float_precision_rounds_top_level() {
+        let mut session = ReplSession::new();
+        session.execute(":set float-precision 6").unwrap();
+        assert_eq!(session.execute("let rate = 0.1; rate * 3.0"), Ok("0.3".to_string()));
+    }
+
+    #[test]
+    fn test_float_precision_nested_in_tuple() {
+        let mut session = ReplSession::new();
+        session.execute(":set float-precision 6").unwrap();
+        assert_eq!(
+            session.execute("(0.1 * 3.0, (1.0 / 3.0, 7))"),
+            Ok("(0.3, (0.333333, 7))".to_string())
+        );
+    }
+
+    #[test]
+    fn test_float_precision_does_not_affect_computation() {
+        let mut session = ReplSession::new();
+        session.execute(":set float-precision 2").unwrap();
+        session.execute("let x = 0.1 * 3.0;").unwrap();
+        session.execute(":set float-precision exact").unwrap();
+        assert_eq!(session.execute("x"), Ok("0.30000000000000004".to_string()));
+    }
+
+    #[test]
+    fn test_float_precision_is_bounded() {
+        let mut session = ReplSession::new();
+        assert!(session.execute(":set float-precision 100000").unwrap_err().contains("Invalid float-precision"));
+        session.execute(":set float-precision 65535").unwrap();
+        assert!(session.execute("0.1").unwrap().starts_with("0.1"));
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let mut session = ReplSession::new();
+        assert!(session.execute(":set float-precision lots").is_err());
+        assert!(session.execute(":set float-precision 0").is_err());
+        assert!(session.execute(":set colour on").is_err());
+        assert!(session.execute(":quux").is_err());
+        assert_eq!(session.display_options(), DisplayOptions::default());
+    }
+
+    #[test]
+    fn test_env_command_lists_bindings() {
+        let mut session = ReplSession::new();
+        session.execute("let double = fun x -> x * 2; let answer = 42;").unwrap();
+        let listing = session.execute(":env").unwrap();
+        assert!(listing.contains("\n  double         : Int -> Int\n"), "{listing}");
+        assert!(listing.contains("Values (1):\n  answer = 42 : Int\n"));
+        assert!(listing.ends_with("Constructors (2):\n  Cons : a -> List a -> List a\n  Nil  : List a"));
+
+        // The names pattern bindings are desugared with are not listed
+        session.execute("let (a, b) = (1, 2); let first = fun (x, _) -> x;").unwrap();
+        let listing = session.execute(":env").unwrap();
+        assert!(listing.contains("Values (3):\n  a      = 1 : Int\n  answer = 42 : Int\n  b      = 2 : Int\n"), "{listing}");
+        assert!(!listing.contains("__"), "{listing}");
+        assert_eq!(session.execute("first").unwrap(), "<function>");
+    }
+
+    #[test]
+    fn test_default_history_path() {
+        let os = |s: &str| Some(OsString::from(s));
+        assert_eq!(default_history_path(os("/data"), os("/home/ada")), Some(PathBuf::from("/data/parlang/history")));
+        assert_eq!(default_history_path(None, os("/home/ada")), Some(PathBuf::from("/home/ada/.parlang_history")));
+        // The XDG specification says relative paths are to be ignored
+        assert_eq!(default_history_path(os("data"), os("/home/ada")), Some(PathBuf::from("/home/ada/.parlang_history")));
+        assert_eq!(default_history_path(os(""), None), None);
+        assert_eq!(default_history_path(None, os("")), None);
+    }
+
+    #[test]
+    fn test_help_lists_every_command() {
+        let help = ReplSession::new().execute(":help").unwrap();
+        assert!(help.starts_with("Commands:\n"));
+        for (usage, _) in COMMANDS {
+            assert!(help.contains(usage), "{usage}");
+        }
+    }
+
+    #[test]
+    fn test_load_command_adds_library_bindings() {
+        let path = std::env::temp_dir().join("repl_load_command.par");
+        std::fs::write(&path, "let double = fun x -> x * 2; let ten = 10;").unwrap();
+        let mut session = ReplSession::new();
+        let loaded = session.execute(&format!(":load {}", path.display()));
+        let ty = session.execute(":t double");
+        let result = session.execute("double ten");
+        session.execute(":undo").unwrap();
+        let undone = session.execute(":undo");
+        let missing = session.execute(":load /nonexistent/library.par");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Ok(format!("Loaded {}: double, ten", path.display())));
+        assert_eq!(result, Ok("20".to_string()));
+        assert_eq!(ty, Ok("Int -> Int".to_string()));
+        assert_eq!(undone, Ok("Undid 1 input; removed double, ten".to_string()));
+        assert!(missing.unwrap_err().starts_with("Evaluation error:"));
+        assert!(session.execute(":load").is_err());
+    }
+
+    #[test]
+    fn test_reload_command_reads_the_library_again() {
+        let path = std::env::temp_dir().join(format!("repl_reload_command_{}.par", std::process::id()));
+        std::fs::write(&path, "let answer = 41; let name = \"lib\";").unwrap();
+        let mut session = ReplSession::new();
+        session.execute(&format!(":load {}", path.display())).unwrap();
+        std::fs::write(&path, "let answer = 42.0; let name = \"lib\";").unwrap();
+        // The cached parse is used, and the earlier bindings are kept
+        let loaded_again = session.execute(&format!(":load {}", path.display()));
+        let reloaded = session.execute(&format!(":reload {}", path.display()));
+        let value = session.execute("answer");
+        let ty = session.execute(":t answer");
+        let undone = session.execute(":undo 2");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded_again, Ok(format!("Loaded {}: no bindings", path.display())));
+        assert_eq!(reloaded, Ok(format!("Reloaded {}: answer", path.display())));
+        assert_eq!(value, Ok("42.0".to_string()));
+        assert_eq!(ty, Ok("Float".to_string()));
+        assert_eq!(undone, Ok("Undid 2 inputs; restored answer".to_string()));
+        assert_eq!(session.execute(":reload"), Ok("Libraries will be read from disk again on their next load".to_string()));
+        assert!(session.execute(":reload /nonexistent/library.par").unwrap_err().starts_with("Evaluation error:"));
+    }
+
+    #[test]
+    fn test_clear_removes_bindings_and_keeps_settings() {
+        let mut session = ReplSession::new();
+        session.execute(":set float-precision 2").unwrap();
+        session.execute("type Color = Red | Green in Red").unwrap();
+        session.execute("let x = 1;").unwrap();
+        assert_eq!(session.execute(":clear"), Ok("Cleared all bindings".to_string()));
+        assert!(session.execute("x").is_err());
+        assert!(session.execute("Red").is_err());
+        assert!(session.execute(":t x").is_err());
+        assert_eq!(session.execute("[1.234]"), Ok("Cons(1.2, Nil)".to_string()));
+        assert_eq!(session.execute("max 1 2"), Ok("2".to_string()));
+        session.execute(":undo 3").unwrap();
+        assert_eq!(session.execute("x"), Ok("1".to_string()));
+        assert_eq!(session.execute("Red"), Ok("Red".to_string()));
+    }
+
+    #[test]
+    fn test_quit_finishes_the_session() {
+        let mut session = ReplSession::new();
+        assert!(!session.is_finished());
+        session.execute("let x =").unwrap_err();
+        assert!(!session.is_finished());
+        assert_eq!(session.execute(":quit"), Ok("Goodbye!".to_string()));
+        assert!(session.is_finished());
+        assert!(ReplSession::new().execute(":q").is_ok());
+    }
+
+    const REDEFINITION: [&str; 3] = [
+        "let helper = fun x -> x + 1; let use = fun x -> helper x;",
+        "let helper = fun x -> x * 100;",
+        "use 2",
+    ];
+
+    fn run_all(session: &mut ReplSession, inputs: &[&str]) -> String {
+        let mut last = String::new();
+        for input in inputs {
+            last = session.execute(input).unwrap();
+        }
+        last
+    }
+
+    #[test]
+    fn test_runaway_recursion_is_an_error() {
+        let mut session = ReplSession::new();
+        session.set_depth_limit(Some(20));
+        session.execute("let x = 1;").unwrap();
+        let error = session.execute("(rec f -> fun n -> 1 + f n) 0").unwrap_err();
+        assert!(error.contains("Recursion limit exceeded: more than 20 nested function calls"), "{error}");
+        assert_eq!(session.execute("x + 1"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn test_interrupted_input_keeps_bindings() {
+        let mut session = ReplSession::new();
+        session.execute("let x = 1;").unwrap();
+        let flag = session.interrupt_flag();
+        let raiser = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            flag.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(session.execute("let y = (rec f -> fun n -> f (n + 1)) 0;"), Err("Evaluation error: Interrupted".to_string()));
+        raiser.join().unwrap();
+        // The flag is still raised, but each input clears it first
+        assert_eq!(session.execute("let z = 50000 in (rec f -> fun n -> if n == z then x else f (n + 1)) 0"), Ok("1".to_string()));
+        assert!(session.execute("y").unwrap_err().contains("Unbound variable: y"));
+    }
+
+    #[test]
+    fn test_set_debug_enables_debug_env() {
+        let mut session = ReplSession::new();
+        session.execute("let peek = fun n -> (debug_env ()).n;").unwrap();
+        let error = session.execute("peek 3").unwrap_err();
+        assert!(error.contains("--debug-builtins") && error.contains(":set debug on"), "{error}");
+        assert_eq!(session.execute(":set debug on"), Ok("debug = on".to_string()));
+        assert_eq!(session.execute("peek 3"), Ok("3".to_string()));
+        session.execute(":set debug off").unwrap();
+        assert!(session.execute("peek 3").is_err());
+        assert!(session.execute(":set debug maybe").is_err());
+    }
+
+    #[test]
+    fn test_static_toplevel_keeps_captured_definition() {
+        let mut session = ReplSession::new();
+        assert_eq!(run_all(&mut session, &REDEFINITION), "3");
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_sees_redefinition() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        assert_eq!(run_all(&mut session, &REDEFINITION), "200");
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_across_separate_inputs() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        let result = run_all(
+            &mut session,
+            &["let helper = fun x -> x + 1;", "let use = fun x -> helper x;", "let helper = fun x -> x - 1;", "use 2"],
+        );
+        assert_eq!(result, "1");
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_leaves_earlier_closures_lexical() {
+        let mut session = ReplSession::new();
+        session.execute("let helper = fun x -> x + 1; let use = fun x -> helper x;").unwrap();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        session.execute("let helper = fun x -> x * 100;").unwrap();
+        // use was created before the switch and keeps the helper it captured
+        assert_eq!(session.execute("use 2"), Ok("3".to_string()));
+        session.execute("let use = fun x -> helper x;").unwrap();
+        assert_eq!(session.execute("use 2"), Ok("200".to_string()));
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_keeps_lexical_captures() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        // n is bound locally by let-in, and make's parameter shadows any top-level n
+        let result = run_all(
+            &mut session,
+            &[
+                "let n = 1; let f = let n = 10 in fun x -> x + n;",
+                "let make = fun n -> fun x -> x + n; let add1 = make 1;",
+                "let n = 5; let make = fun n -> fun x -> x * n;",
+                "(f 1, add1 1)",
+            ],
+        );
+        assert_eq!(result, "(11, 2)");
+    }
+
+    #[test]
+    fn test_bindings_run_once() {
+        for mode in ["off", "on"] {
+            let mut session = ReplSession::new();
+            session.execute(&format!(":set dynamic-toplevel {mode}")).unwrap();
+            session.execute("let count = ref 0;").unwrap();
+            session.execute("let x = count := !count + 1;").unwrap();
+            session.execute("let y = count := !count + 1; let z = count := !count + 1 in !count").unwrap();
+            assert_eq!(session.execute("!count"), Ok("3".to_string()), "dynamic-toplevel {mode}");
+        }
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_failed_input_defines_nothing() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        session.execute("let a = 1;").unwrap();
+        let err = session.execute("let a = 5; let b = 1 / 0;").unwrap_err();
+        assert!(err.contains("In binding 'b'"), "{err}");
+        assert!(err.contains("Division by zero"), "{err}");
+        assert_eq!(session.execute("a"), Ok("1".to_string()));
+        assert!(session.execute("b").is_err());
+        // A failing body rolls the bindings back too
+        assert!(session.execute("let c = 3; c / 0").is_err());
+        assert!(session.execute("c").is_err());
+    }
+
+    #[test]
+    fn test_dynamic_toplevel_can_be_turned_off() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        session.execute("let k = 1; let getk = fun u -> k;").unwrap();
+        session.execute(":set dynamic-toplevel off").unwrap();
+        // Existing bindings remain available; new closures capture lexically again
+        session.execute("let getk2 = fun u -> k;").unwrap();
+        session.execute("let k = 2;").unwrap();
+        assert_eq!(session.execute("getk2 0"), Ok("1".to_string()));
+        assert!(session.execute(":set dynamic-toplevel maybe").is_err());
+    }
+
+    fn recording_session(store: &MemoryStore) -> ReplSession {
+        let mut session = ReplSession::new();
+        session.set_transcript_store(Rc::new(store.clone()));
+        session
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let store = MemoryStore::new();
+        let mut session = recording_session(&store);
+        session.execute("let before = 0;").unwrap();
+        assert_eq!(session.execute(":record t.json"), Ok("Recording to t.json".to_string()));
+        run_all(&mut session, &["let x = 20;", "x + 1"]);
+        assert!(session.execute("x / 0").is_err());
+        session.execute(":record off").unwrap();
+        session.execute("x").unwrap();
+
+        let entries = parse_transcript(&store.contents("t.json").unwrap()).unwrap();
+        let inputs: Vec<&str> = entries.iter().map(|e| e.input.as_str()).collect();
+        assert_eq!(inputs, vec!["let x = 20;", "x + 1", "x / 0"]);
+        assert_eq!(entries[1].response, Ok("21".to_string()));
+        assert!(entries[2].response.is_err());
+
+        assert_eq!(
+            session.execute(":replay t.json"),
+            Ok("Replayed 3 inputs from t.json: no divergence".to_string())
+        );
+    }
+
+    #[test]
+    fn test_replay_reports_first_divergence() {
+        let store = MemoryStore::new();
+        let mut session = recording_session(&store);
+        session.execute(":record t.json").unwrap();
+        run_all(&mut session, &["let x = 20;", "x + 1", "x * 2"]);
+        session.execute(":record off").unwrap();
+
+        // Simulate a behavior change by editing the recorded output of input 2
+        let edited = store.contents("t.json").unwrap().replace("\"21\"", "\"22\"");
+        store.insert("t.json", edited);
+        let report = session.execute(":replay t.json").unwrap_err();
+        assert_eq!(
+            report,
+            "Replay of t.json diverged at input 2 `x + 1`\n  recorded: 22\n  current:  21"
+        );
+    }
+
+    #[test]
+    fn test_record_and_replay_errors() {
+        let store = MemoryStore::new();
+        let mut session = recording_session(&store);
+        assert!(session.execute(":record").is_err());
+        assert_eq!(session.execute(":record off"), Err("Not recording".to_string()));
+        assert!(session.execute(":replay missing.json").unwrap_err().contains("not found"));
+        store.insert("bad.json", "{\"input\": 1}\n");
+        assert!(session.execute(":replay bad.json").unwrap_err().contains("line 1"));
+        store.insert("new.json", "{\"parlang_format\": 99}\n");
+        assert!(session.execute(":replay new.json").unwrap_err().starts_with("transcript has format version 99"));
+    }
+
+    #[test]
+    fn test_dotval_writes_binding() {
+        let path = std::env::temp_dir().join(format!("parlang_dotval_{}.dot", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut session = ReplSession::new();
+        session.execute("let pair = (1, true);").unwrap();
+        assert_eq!(session.execute(&format!(":dotval pair {path}")), Ok(format!("Wrote value of pair to {path}")));
+        let dot = std::fs::read_to_string(path).unwrap();
+        let _ = std::fs::remove_file(path);
+        assert!(dot.contains("[label=\"Tuple\"]"), "{dot}");
+
+        assert_eq!(session.execute(&format!(":dotval missing {path}")), Err("Unbound variable: missing".to_string()));
+        assert!(session.execute(":dotval pair").unwrap_err().starts_with("Usage"));
+    }
+
+    #[test]
+    fn test_type_command_uses_earlier_definitions() {
+        let mut session = ReplSession::new();
+        session.execute("let double = fun x -> x + x;").unwrap();
+        assert_eq!(session.execute(":t double"), Ok("Int -> Int".to_string()));
+        assert_eq!(session.execute(":type fun x -> (x, double 1)"), Ok("t0 -> (t0, Int)".to_string()));
+        run_all(&mut session, &["type Box a = Box a in Box 0", "let wrap = fun x -> Box x;"]);
+        assert_eq!(session.execute(":t wrap"), Ok("t0 -> Box t0".to_string()));
+
+        // The expression is not evaluated
+        assert_eq!(session.execute(":t 1 / 0"), Ok("Int".to_string()));
+        assert_eq!(session.execute(":t double true"), Err("Type error: Cannot unify types: Int and Bool".to_string()));
+        assert!(session.execute(":t let").unwrap_err().starts_with("parse error at line 1"));
+        assert!(session.execute(":type").unwrap_err().starts_with("Usage"));
+
+        // A redefinition that does not type check is forgotten, not kept at its old type
+        session.execute("let double = fun x -> if x then x else 1;").unwrap();
+        assert_eq!(
+            session.execute(":t double"),
+            Err("Type error: Unbound variable: double (its definition did not type check)".to_string())
+        );
+        session.execute(":undo").unwrap();
+        assert_eq!(session.execute(":t double"), Ok("Int -> Int".to_string()));
+    }
+
+    #[test]
+    fn test_let_rec_persists() {
+        let mut session = ReplSession::new();
+        session.execute("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1);").unwrap();
+        assert_eq!(session.execute("fact 5"), Ok("120".to_string()));
+        assert_eq!(session.execute(":t fact"), Ok("Int -> Int".to_string()));
+    }
+
+    #[test]
+    fn test_profile_command() {
+        let mut session = ReplSession::new();
+        session.execute("let sq = fun x -> x * x;").unwrap();
+        assert_eq!(session.execute(":profile on"), Ok("Profiling on".to_string()));
+        let output = session.execute("sq 3 + sq 4").unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "25");
+        assert!(lines[1].starts_with("function"));
+        assert!(lines[2].starts_with("sq "));
+        assert_eq!(lines[2].split_whitespace().nth(1), Some("2"));
+        // Each input is profiled on its own
+        let output = session.execute("sq 5").unwrap();
+        assert_eq!(output.lines().nth(2).and_then(|line| line.split_whitespace().nth(1)), Some("1"));
+        session.execute(":profile off").unwrap();
+        assert_eq!(session.execute("sq 6"), Ok("36".to_string()));
+        assert!(session.execute(":profile").unwrap_err().starts_with("Usage"));
+    }
+
+    #[test]
+    fn test_type_check_sees_earlier_definitions() {
+        let mut session = ReplSession::new();
+        session.set_type_check(true);
+        session.execute("let inc = fun x -> x + 1;").unwrap();
+        assert_eq!(session.execute("inc 1"), Ok("Type: Int\n2".to_string()));
+        assert!(session.execute("inc true").unwrap_err().starts_with("Type error"));
+    }
+
+    #[test]
+    fn test_undo_restores_previous_definition() {
+        let mut session = ReplSession::new();
+        run_all(&mut session, &["let double = fun x -> x * 2;", "let double = fun x -> x * 3;"]);
+        assert_eq!(session.execute("double 5"), Ok("15".to_string()));
+        session.execute("let extra = 1;").unwrap();
+
+        assert_eq!(session.execute(":undo 2"), Ok("Undid 2 inputs; removed extra".to_string()));
+        assert_eq!(session.execute(":undo"), Ok("Undid 1 input; restored double".to_string()));
+        assert_eq!(session.execute("double 5"), Ok("10".to_string()));
+        assert!(session.execute("extra").is_err());
+    }
+
+    #[test]
+    fn test_undo_does_not_revert_references() {
+        let mut session = ReplSession::new();
+        session.execute("let r = ref 1;").unwrap();
+        session.execute("let u = r := 2;").unwrap();
+        assert_eq!(session.execute(":undo"), Ok("Undid 1 input; removed u".to_string()));
+        assert_eq!(session.execute("!r"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn test_undo_in_dynamic_toplevel_mode() {
+        let mut session = ReplSession::new();
+        session.execute(":set dynamic-toplevel on").unwrap();
+        run_all(&mut session, &["let helper = fun x -> x + 1;", "let f = fun x -> helper x;", "let helper = fun x -> x + 100;"]);
+        assert_eq!(session.execute(":undo"), Ok("Undid 1 input; restored helper".to_string()));
+        assert_eq!(session.execute("f 1"), Ok("2".to_string()));
+    }
+
+    #[test]
+    fn test_undo_history_is_bounded() {
+        let mut session = ReplSession::new();
+        assert_eq!(session.execute(":undo"), Err("Nothing to undo".to_string()));
+        for i in 0..=UNDO_HISTORY_LIMIT {
+            session.execute(&format!("let x{i} = {i};")).unwrap();
+        }
+        assert!(session.execute("1 / 0").is_err());
+        assert_eq!(
+            session.execute(&format!(":undo {}", UNDO_HISTORY_LIMIT + 10)),
+            Ok(format!("Undid {UNDO_HISTORY_LIMIT} inputs; removed {}", {
+                let mut names: Vec<String> = (1..=UNDO_HISTORY_LIMIT).map(|i| format!("x{i}")).collect();
+                names.sort();
+                names.join(", ")
+            }))
+        );
+        // The snapshot taken before the first input was dropped
+        assert_eq!(session.execute("x0"), Ok("0".to_string()));
+        assert_eq!(session.execute(":undo"), Ok("Undid 1 input".to_string()));
+        assert!(session.execute(":undo 0").is_err());
+    }
+
+    #[test]
+    fn test_local_library_and_redefined_precedence() {
+        let lib = std::env::temp_dir().join(format!("parlang_repl_precedence_{}.par", std::process::id()));
+        std::fs::write(&lib, "let double = fun x -> x * 2;\nlet quad = fun x -> double (double x);\n0\n").unwrap();
+        let load = |body: &str| format!("load \"{}\" in {body}", lib.display());
+        let mut session = ReplSession::new();
+
+        // Local binding and a closure capturing it, defined before the load
+        run_all(&mut session, &["let double = fun x -> x + 1;", "let before = fun n -> double n;"]);
+        // The local double shadows the library's, while the library keeps its own
+        assert_eq!(session.execute(&load("double 2")), Ok("3".to_string()));
+        assert_eq!(session.execute(&load("quad 1")), Ok("4".to_string()));
+
+        // Persisting the library's bindings keeps the same lookup order
+        session.execute(&load("0")).unwrap();
+        assert_eq!(session.execute("double 2"), Ok("3".to_string()));
+        assert_eq!(session.execute("quad 1"), Ok("4".to_string()));
+        assert_eq!(session.execute("before 2"), Ok("3".to_string()));
+
+        // A REPL redefinition wins over both, including on a later load
+        session.execute("let double = fun x -> x * 10;").unwrap();
+        assert_eq!(session.execute("double 2"), Ok("20".to_string()));
+        assert_eq!(session.execute(&load("double 2")), Ok("20".to_string()));
+        assert_eq!(session.execute("quad 1"), Ok("4".to_string()));
+        assert_eq!(session.execute("before 2"), Ok("3".to_string()));
+        std::fs::remove_file(&lib).ok();
+    }
+}
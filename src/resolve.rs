@@ -0,0 +1,826 @@
+//! Resolving variables to slots ahead of evaluation
+//!
+//! [`eval_core`] looks every variable up by name in a persistent
+//! [`Environment`], which dominates the time of tight loops. [`resolve`] is a
+//! pass over the core language that works out from the static scoping where
+//! each variable lives, producing a [`ResolvedExpr`] in which a variable
+//! bound inside the program is a [`Slot`]:
+//!
+//! - `Local(i)`: slot `i` of the current call's frame, which holds the
+//!   function itself (for `rec`), its parameters, and the variables bound by
+//!   `let`, `match` arms and `try` handlers while they are in scope
+//! - `Captured(i)`: the `i`th value the closure captured when it was created
+//! - `Global`: a name bound outside the program, in the environment passed
+//!   in, the REPL's shared top-level layer or the prelude; these are still
+//!   looked up by name when used, so a top-level definition made after the
+//!   function is found as it is by `eval_core`
+//!
+//! [`eval_resolved`] evaluates the result with each call's variables in a
+//! `Vec`, so reading one is an index operation. Closures are flat: they
+//! capture only the variables their body uses from enclosing functions. A
+//! chain of nested `fun`s (`fun a -> fun b -> ...`) is one function with
+//! several parameters, and all of them go into the same frame.
+//!
+//! Names are kept next to the slots. Unbound-variable errors suggest from
+//! the names in scope exactly as `eval_core`'s do, and the two constructs
+//! that need the environment by name, `load` and calls of `debug_env`, are
+//! evaluated by `eval_core` in an environment rebuilt from the frame. Any
+//! function around them captures every variable in scope for that purpose.
+//!
+//! Evaluation gives the same result and the same errors and counts the same
+//! steps as `eval_core`; profiles report the same calls. [`eval`] takes this
+//! path when [`Environment::set_resolved`] is on, which the CLI does for
+//! `--optimize`. Building with the `force-resolved` feature turns it on for
+//! every new environment, so `cargo test --features force-resolved` runs the
+//! whole test suite through it.
+//!
+//! Closures created by this evaluator are [`Value::Resolved`] while it runs.
+//! `eval_resolved` turns those in its result back into ordinary
+//! `Value::Closure`s and `Value::RecClosure`s, so hosts never see them; a
+//! function returned by one evaluation and called by a later one (as in the
+//! REPL) is called by name.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::core::lower;
+//! use parlang::resolve::{resolve, ResolvedExpr, Slot};
+//! use parlang::parse;
+//!
+//! let resolved = resolve(&lower(&parse("let x = 1 in let y = 2 in x").unwrap()));
+//! let ResolvedExpr::Let(_, _, body) = resolved else { panic!() };
+//! let ResolvedExpr::Let(_, _, body) = *body else { panic!() };
+//! assert_eq!(*body, ResolvedExpr::Var("x".to_string(), Slot::Local(0)));
+//! ```
+//!
+//! [`eval`]: crate::eval::eval
+
+use crate::ast::{BinOp, Pattern};
+use crate::builtins::DEBUG_ENV;
+use crate::core::{CoreExpr, PrimOp};
+use crate::eval::{
+    apply_spine, apply_spine_in, binds_pattern, check_constructor, eval_binop, eval_core, eval_prim, field, guard_holds,
+    int_arith, int_compare, project, in_arms, in_let, register_type_def, select_arm_index, Environment, EvalError, Fields, Tail, TailCall, Value,
+};
+use crate::span::Span;
+use crate::subst::pattern_vars;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// Where the value of a variable is found
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    /// A slot of the current call's frame
+    Local(usize),
+    /// A value captured by the current closure
+    Captured(usize),
+    /// Bound outside the program and looked up by name; holds the names in
+    /// scope where it occurs, for the suggestions of an unbound-variable error
+    Global(Rc<[String]>),
+}
+
+/// An expression of the core language with its variables resolved
+///
+/// Mirrors [`CoreExpr`]. Binders are implicit: `Let`, the arms of `Match`
+/// and the handler of `Try` put their values in the next free slots of the
+/// frame, in the order of [`pattern_vars`] for patterns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolvedExpr {
+    Int(i64),
+    Bool(bool),
+    Char(char),
+    Float(f64),
+    Byte(u8),
+    Unit,
+    Var(String, Slot),
+    /// A chain of `fun`s
+    Lam(Rc<Function>),
+    /// `rec name -> fun ...`
+    Rec(Rc<Function>),
+    /// A `rec` whose body is not a function, which fails when evaluated
+    RecNotFunction,
+    /// An application spine: the function and its arguments, left to right
+    App(Box<ResolvedExpr>, Vec<ResolvedExpr>),
+    /// The bound name, kept for error traces, the value and the body
+    Let(String, Box<ResolvedExpr>, Box<ResolvedExpr>),
+    If(Box<ResolvedExpr>, Box<ResolvedExpr>, Box<ResolvedExpr>),
+    /// Scrutinee, the patterns of the arms, their guards, and the arms' expressions
+    Match(Box<ResolvedExpr>, Vec<Pattern>, Vec<Option<ResolvedExpr>>, Vec<ResolvedExpr>),
+    BinOp(BinOp, Box<ResolvedExpr>, Box<ResolvedExpr>),
+    Prim(PrimOp, Vec<ResolvedExpr>),
+    Tuple(Vec<ResolvedExpr>),
+    Proj(Box<ResolvedExpr>, usize),
+    Record(Vec<(String, ResolvedExpr)>),
+    Field(Box<ResolvedExpr>, String),
+    Constructor(String, Vec<ResolvedExpr>),
+    Array(Vec<ResolvedExpr>),
+    TypeDef(String, Vec<(String, usize)>, Box<ResolvedExpr>),
+    Try(Box<ResolvedExpr>, Box<ResolvedExpr>),
+    IntArith(Box<ResolvedExpr>, usize),
+    At(Span, Box<ResolvedExpr>),
+    /// An expression `eval_core` evaluates by name, with the variables in
+    /// scope and where to find them
+    ByName(CoreExpr, Vec<(String, Slot)>),
+}
+
+/// A function of the resolved language: a chain of nested `fun`s, possibly recursive
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The name a `rec` function calls itself by, in slot 0 of its frame
+    pub rec_name: Option<String>,
+    /// The parameters of the chain, outermost first, in the slots after the name
+    pub params: Vec<String>,
+    /// Names of the variables captured from enclosing functions, and where
+    /// the creating call finds them
+    pub captures: Vec<(String, Slot)>,
+    /// The body of the innermost `fun`
+    pub body: ResolvedExpr,
+    /// The body of the outermost `fun` as written, for rendering and profiling
+    pub source: Rc<CoreExpr>,
+}
+
+/// A closure of a [`Function`], applied to some of its parameters
+#[derive(Debug)]
+pub struct ResolvedClosure {
+    function: Rc<Function>,
+    captured: Vec<Value>,
+    /// The start of the frame: the function itself for `rec`, then the parameters applied so far
+    bound: Vec<Value>,
+    applied: usize,
+    env: Environment,
+}
+
+impl ResolvedClosure {
+    /// The parameter the closure takes next
+    pub fn param(&self) -> &str {
+        &self.function.params[self.applied]
+    }
+
+    /// The recursive function's name if the closure is a `rec` function not yet applied
+    pub fn rec_name(&self) -> Option<&str> {
+        self.function.rec_name.as_deref().filter(|_| self.applied == 0)
+    }
+
+    /// Number of arguments the closure takes before its body is evaluated
+    pub fn arity(&self) -> usize {
+        self.function.params.len() - self.applied
+    }
+
+    /// The environment the closure was created in, without its local variables
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    /// The variables the closure holds: the ones it captured, then the
+    /// function itself (for `rec`) and the parameters applied so far
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &Value)> {
+        let captured = self.function.captures.iter().map(|(name, _)| name.as_str()).zip(&self.captured);
+        let names = self.function.rec_name.iter().chain(&self.function.params).map(String::as_str);
+        captured.chain(names.zip(&self.bound))
+    }
+
+    /// The body after the parameter the closure takes next, as written
+    pub fn source_body(&self) -> &Rc<CoreExpr> {
+        let mut body = &self.function.source;
+        for _ in 0..self.applied {
+            let CoreExpr::Lam(_, inner) = body.as_ref() else { unreachable!("a function's parameters are nested funs") };
+            body = inner;
+        }
+        body
+    }
+
+    /// Whether both are the same closure of the same function
+    pub(crate) fn same_as(&self, other: &ResolvedClosure) -> bool {
+        Rc::ptr_eq(&self.function, &other.function)
+            && self.applied == other.applied
+            && self.captured == other.captured
+            && self.bound == other.bound
+            && self.env == other.env
+    }
+}
+
+/// Resolve the variables of a core expression to slots
+#[must_use]
+pub fn resolve(expr: &CoreExpr) -> ResolvedExpr {
+    let mut resolver = Resolver { scopes: vec![Scope::default()], visible: None };
+    resolver.expr(expr)
+}
+
+/// The variables of one function being resolved
+#[derive(Default)]
+struct Scope {
+    /// Names of the frame's slots in use, innermost binding last
+    names: Vec<String>,
+    captures: Vec<(String, Slot)>,
+}
+
+struct Resolver {
+    /// The program, then the functions being resolved, innermost last
+    scopes: Vec<Scope>,
+    /// Names in scope, computed when first needed after a change
+    visible: Option<Rc<[String]>>,
+}
+
+impl Resolver {
+    fn bind(&mut self, name: &str) {
+        self.visible = None;
+        self.innermost().names.push(name.to_string());
+    }
+
+    fn unbind(&mut self, count: usize) {
+        self.visible = None;
+        let names = &mut self.innermost().names;
+        names.truncate(names.len() - count);
+    }
+
+    fn innermost(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("the program's scope is never popped")
+    }
+
+    /// Where function `depth` finds `name`, capturing it from enclosing functions if needed
+    fn slot(&mut self, depth: usize, name: &str) -> Option<Slot> {
+        let scope = &self.scopes[depth];
+        if let Some(index) = scope.names.iter().rposition(|bound| bound == name) {
+            return Some(Slot::Local(index));
+        }
+        if let Some(index) = scope.captures.iter().position(|(captured, _)| captured == name) {
+            return Some(Slot::Captured(index));
+        }
+        let outer = self.slot(depth.checked_sub(1)?, name)?;
+        let captures = &mut self.scopes[depth].captures;
+        captures.push((name.to_string(), outer));
+        Some(Slot::Captured(captures.len() - 1))
+    }
+
+    fn var(&mut self, name: &str) -> ResolvedExpr {
+        let slot = match self.slot(self.scopes.len() - 1, name) {
+            Some(slot) => slot,
+            None => Slot::Global(self.visible_names()),
+        };
+        ResolvedExpr::Var(name.to_string(), slot)
+    }
+
+    /// Every name in scope, outermost first
+    fn visible_names(&mut self) -> Rc<[String]> {
+        let scopes = &self.scopes;
+        let visible = self
+            .visible
+            .get_or_insert_with(|| scopes.iter().flat_map(|scope| scope.names.iter().cloned()).collect());
+        Rc::clone(visible)
+    }
+
+    /// Every name in scope once, with where its innermost binding is found
+    fn by_name(&mut self) -> Vec<(String, Slot)> {
+        let mut seen = HashSet::new();
+        let names: Vec<String> = self.visible_names().iter().filter(|name| seen.insert(*name)).cloned().collect();
+        let depth = self.scopes.len() - 1;
+        names
+            .into_iter()
+            .map(|name| {
+                let slot = self.slot(depth, &name).expect("names in scope resolve");
+                (name, slot)
+            })
+            .collect()
+    }
+
+    fn function(&mut self, rec_name: Option<&String>, param: &str, source: &Rc<CoreExpr>) -> Rc<Function> {
+        let mut params = vec![param.to_string()];
+        let mut body: &CoreExpr = source;
+        while let CoreExpr::Lam(param, inner) = body {
+            params.push(param.clone());
+            body = inner;
+        }
+        let names = rec_name.into_iter().chain(&params).cloned().collect();
+        self.visible = None;
+        self.scopes.push(Scope { names, captures: Vec::new() });
+        let body = self.expr(body);
+        let scope = self.scopes.pop().expect("pushed above");
+        self.visible = None;
+        Rc::new(Function { rec_name: rec_name.cloned(), params, captures: scope.captures, body, source: Rc::clone(source) })
+    }
+
+    fn all(&mut self, exprs: &[CoreExpr]) -> Vec<ResolvedExpr> {
+        exprs.iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    fn boxed(&mut self, expr: &CoreExpr) -> Box<ResolvedExpr> {
+        Box::new(self.expr(expr))
+    }
+
+    /// Resolve `expr` with `names` bound in the next slots
+    fn scoped(&mut self, names: &[String], expr: &CoreExpr) -> ResolvedExpr {
+        for name in names {
+            self.bind(name);
+        }
+        let resolved = self.expr(expr);
+        self.unbind(names.len());
+        resolved
+    }
+
+    fn expr(&mut self, expr: &CoreExpr) -> ResolvedExpr {
+        match expr {
+            CoreExpr::Int(n) => ResolvedExpr::Int(*n),
+            CoreExpr::Bool(b) => ResolvedExpr::Bool(*b),
+            CoreExpr::Char(c) => ResolvedExpr::Char(*c),
+            CoreExpr::Float(f) => ResolvedExpr::Float(*f),
+            CoreExpr::Byte(b) => ResolvedExpr::Byte(*b),
+            CoreExpr::Unit => ResolvedExpr::Unit,
+            CoreExpr::Var(name) => self.var(name),
+            CoreExpr::Lam(param, body) => ResolvedExpr::Lam(self.function(None, param, body)),
+            CoreExpr::Rec(name, body) => match body.as_ref() {
+                CoreExpr::Lam(param, body) => ResolvedExpr::Rec(self.function(Some(name), param, body)),
+                _ => ResolvedExpr::RecNotFunction,
+            },
+            CoreExpr::App(..) => {
+                let mut args = Vec::new();
+                let mut head = expr;
+                while let CoreExpr::App(func, arg) = head {
+                    args.push(arg.as_ref());
+                    head = func;
+                }
+                let unspanned = match head {
+                    CoreExpr::At(_, inner) => inner.as_ref(),
+                    head => head,
+                };
+                if matches!(unspanned, CoreExpr::Var(name) if name == DEBUG_ENV && self.slot(self.scopes.len() - 1, name).is_none()) {
+                    return ResolvedExpr::ByName(expr.clone(), self.by_name());
+                }
+                let head = self.boxed(head);
+                ResolvedExpr::App(head, args.into_iter().rev().map(|arg| self.expr(arg)).collect())
+            }
+            CoreExpr::Let(name, value, body) => {
+                let value = self.boxed(value);
+                ResolvedExpr::Let(name.clone(), value, Box::new(self.scoped(std::slice::from_ref(name), body)))
+            }
+            CoreExpr::If(cond, then_branch, else_branch) => {
+                ResolvedExpr::If(self.boxed(cond), self.boxed(then_branch), self.boxed(else_branch))
+            }
+            CoreExpr::Match(scrutinee, arms) => {
+                let scrutinee = self.boxed(scrutinee);
+                let patterns = arms.iter().map(|(pattern, _, _)| pattern.clone()).collect();
+                let guards = arms
+                    .iter()
+                    .map(|(pattern, guard, _)| guard.as_ref().map(|guard| self.scoped(&pattern_vars(pattern), guard)))
+                    .collect();
+                let arms = arms.iter().map(|(pattern, _, arm)| self.scoped(&pattern_vars(pattern), arm)).collect();
+                ResolvedExpr::Match(scrutinee, patterns, guards, arms)
+            }
+            CoreExpr::BinOp(op, left, right) => ResolvedExpr::BinOp(*op, self.boxed(left), self.boxed(right)),
+            CoreExpr::Prim(op, operands) => ResolvedExpr::Prim(*op, self.all(operands)),
+            CoreExpr::Tuple(elements) => ResolvedExpr::Tuple(self.all(elements)),
+            CoreExpr::Proj(tuple, index) => ResolvedExpr::Proj(self.boxed(tuple), *index),
+            CoreExpr::Record(fields) => {
+                ResolvedExpr::Record(fields.iter().map(|(name, value)| (name.clone(), self.expr(value))).collect())
+            }
+            CoreExpr::Field(record, name) => ResolvedExpr::Field(self.boxed(record), name.clone()),
+            CoreExpr::Constructor(name, args) => ResolvedExpr::Constructor(name.clone(), self.all(args)),
+            CoreExpr::Array(elements) => ResolvedExpr::Array(self.all(elements)),
+            CoreExpr::TypeDef(name, constructors, body) => {
+                ResolvedExpr::TypeDef(name.clone(), constructors.clone(), self.boxed(body))
+            }
+            CoreExpr::Load(..) => ResolvedExpr::ByName(expr.clone(), self.by_name()),
+            CoreExpr::Try(body, name, handler) => {
+                let body = self.boxed(body);
+                ResolvedExpr::Try(body, Box::new(self.scoped(std::slice::from_ref(name), handler)))
+            }
+            CoreExpr::IntArith(tree, nodes) => ResolvedExpr::IntArith(self.boxed(tree), *nodes),
+            CoreExpr::At(span, inner) => ResolvedExpr::At(*span, self.boxed(inner)),
+        }
+    }
+}
+
+/// The variables of one call: its frame, and the values its closure captured
+struct Frame<'a> {
+    slots: Vec<Value>,
+    captured: &'a [Value],
+}
+
+impl Frame<'_> {
+    fn get(&self, slot: &Slot) -> &Value {
+        match slot {
+            Slot::Local(index) => &self.slots[*index],
+            Slot::Captured(index) => &self.captured[*index],
+            Slot::Global(_) => unreachable!("globals are looked up by name"),
+        }
+    }
+
+    /// Evaluate `expr` with `values` in the next slots, freeing them afterwards
+    fn scoped(
+        &mut self,
+        values: impl IntoIterator<Item = Value>,
+        expr: &ResolvedExpr,
+        env: &Environment,
+    ) -> Result<Value, EvalError> {
+        let depth = self.slots.len();
+        self.slots.extend(values);
+        let result = eval_in(expr, self, env);
+        self.slots.truncate(depth);
+        result
+    }
+}
+
+/// Evaluate a match's scrutinee and pick its arm, as [`select_arm_index`]
+/// does, evaluating guards with the arm's bindings in the next slots
+fn select_arm(
+    scrutinee: &ResolvedExpr,
+    patterns: &[Pattern],
+    guards: &[Option<ResolvedExpr>],
+    frame: &mut Frame,
+    env: &Environment,
+) -> Result<(usize, Vec<(String, Value)>), EvalError> {
+    let value = eval_in(scrutinee, frame, env)?;
+    let binding = binds_pattern(var_name(scrutinee), guards.iter().map(Option::is_some));
+    select_arm_index(
+        patterns,
+        env,
+        || Ok(value),
+        |index, bindings| match &guards[index] {
+            Some(guard) => frame
+                .scoped(bindings.iter().map(|(_, value)| value.clone()), guard, env)
+                .and_then(|value| guard_holds(&value))
+                .map_err(|e| in_arms(e, &[&patterns[index]])),
+            None => Ok(true),
+        },
+        binding,
+    )
+}
+
+/// Evaluate a resolved expression in an environment
+///
+/// Closures in the result are turned back into `Value::Closure` and
+/// `Value::RecClosure`, bound to the variables they captured.
+///
+/// # Errors
+///
+/// Returns the same errors as [`eval_core`].
+pub fn eval_resolved(expr: &ResolvedExpr, env: &Environment) -> Result<Value, EvalError> {
+    let mut frame = Frame { slots: Vec::new(), captured: &[] };
+    let value = eval_in(expr, &mut frame, env)?;
+    Ok(unresolved(&value, &mut HashSet::new()))
+}
+
+fn eval_in(expr: &ResolvedExpr, frame: &mut Frame, env: &Environment) -> Result<Value, EvalError> {
+    match expr {
+        ResolvedExpr::At(span, inner) => eval_in(inner, frame, env).map_err(|e| e.at(*span)),
+        _ => eval_node(expr, frame, env),
+    }
+}
+
+/// Evaluate one node of a resolved expression, kept apart from [`eval_in`]
+/// as `eval_core` does, so the frames for spans stay small
+#[inline(never)]
+fn eval_node(expr: &ResolvedExpr, frame: &mut Frame, env: &Environment) -> Result<Value, EvalError> {
+    if let ResolvedExpr::ByName(expr, in_scope) = expr {
+        // `eval_core` counts the step itself
+        let mut env = env.clone();
+        for (name, slot) in in_scope {
+            env.bind(name.clone(), frame.get(slot).clone());
+        }
+        return eval_core(expr, &env);
+    }
+    env.count_step()?;
+    match expr {
+        ResolvedExpr::Int(n) => Ok(Value::Int(*n)),
+        ResolvedExpr::Bool(b) => Ok(Value::Bool(*b)),
+        ResolvedExpr::Char(c) => Ok(Value::Char(*c)),
+        ResolvedExpr::Float(f) => Ok(Value::Float(*f)),
+        ResolvedExpr::Byte(b) => Ok(Value::Byte(*b)),
+        ResolvedExpr::Unit => Ok(Value::Unit),
+        ResolvedExpr::Var(name, Slot::Global(in_scope)) => {
+            env.resolve(name).ok_or_else(|| env.unbound_variable_among(name, in_scope))
+        }
+        ResolvedExpr::Var(_, slot) => Ok(frame.get(slot).clone()),
+        ResolvedExpr::Lam(function) | ResolvedExpr::Rec(function) => Ok(closure(function, frame, env)),
+        ResolvedExpr::RecNotFunction => Err(EvalError::TypeError("rec expression body must be a function".to_string())),
+        ResolvedExpr::App(head, args) => {
+            let func_val = eval_in(head, frame, env)?;
+            apply_spine(func_val, var_name(head), args, env, |arg| eval_in(arg, frame, env))
+        }
+        ResolvedExpr::Let(name, value, body) => {
+            let value = eval_in(value, frame, env).map_err(|e| in_let(e, name))?;
+            frame.scoped([value], body, env)
+        }
+        ResolvedExpr::If(cond, then_branch, else_branch) => match eval_in(cond, frame, env)? {
+            Value::Bool(true) => eval_in(then_branch, frame, env),
+            Value::Bool(false) => eval_in(else_branch, frame, env),
+            _ => Err(EvalError::TypeError("If condition must be a boolean".to_string())),
+        },
+        ResolvedExpr::Match(scrutinee, patterns, guards, arms) => {
+            let (index, bindings) = select_arm(scrutinee, patterns, guards, frame, env)?;
+            frame
+                .scoped(bindings.into_iter().map(|(_, value)| value), &arms[index], env)
+                .map_err(|e| in_arms(e, &[&patterns[index]]))
+        }
+        ResolvedExpr::BinOp(op, left, right) => {
+            let left_val = eval_in(left, frame, env)?;
+            let right_val = eval_in(right, frame, env)?;
+            eval_binop(*op, left_val, right_val)
+        }
+        ResolvedExpr::IntArith(tree, nodes) => eval_int_arith(tree, *nodes, frame, env),
+        ResolvedExpr::Prim(op, operands) => eval_prim(*op, eval_all(operands, frame, env)?),
+        ResolvedExpr::Tuple(elements) => Ok(Value::Tuple(eval_all(elements, frame, env)?)),
+        ResolvedExpr::Proj(tuple, index) => project(eval_in(tuple, frame, env)?, *index),
+        ResolvedExpr::Record(fields) => {
+            let mut record = Fields::new();
+            for (name, value) in fields {
+                record.insert(name.clone(), eval_in(value, frame, env)?);
+            }
+            Ok(Value::Record(record))
+        }
+        ResolvedExpr::Field(record, name) => field(eval_in(record, frame, env)?, name),
+        ResolvedExpr::Constructor(name, args) => {
+            check_constructor(name, args.len(), env)?;
+            Ok(Value::Variant(name.clone(), eval_all(args, frame, env)?))
+        }
+        ResolvedExpr::Array(elements) => {
+            let values = eval_all(elements, frame, env)?;
+            Ok(Value::Array(values.len(), values))
+        }
+        ResolvedExpr::TypeDef(name, constructors, body) => {
+            let mut new_env = env.clone();
+            register_type_def(&mut new_env, name, constructors);
+            eval_in(body, frame, &new_env)
+        }
+        ResolvedExpr::Try(body, handler) => match eval_in(body, frame, env) {
+            Err(error) if error.is_recoverable() => frame.scoped([error.to_value()], handler, env),
+            result => result,
+        },
+        ResolvedExpr::ByName(..) => unreachable!("evaluated above"),
+        ResolvedExpr::At(..) => unreachable!("eval_in evaluates spans"),
+    }
+}
+
+fn eval_all(exprs: &[ResolvedExpr], frame: &mut Frame, env: &Environment) -> Result<Vec<Value>, EvalError> {
+    exprs.iter().map(|expr| eval_in(expr, frame, env)).collect()
+}
+
+/// Create a closure of `function`, capturing its variables from `frame`
+fn closure(function: &Rc<Function>, frame: &Frame, env: &Environment) -> Value {
+    Value::Resolved(Rc::new(ResolvedClosure {
+        function: Rc::clone(function),
+        captured: function.captures.iter().map(|(_, slot)| frame.get(slot).clone()).collect(),
+        bound: Vec::new(),
+        applied: 0,
+        env: env.clone(),
+    }))
+}
+
+/// Apply a closure to as many arguments as it takes, or fewer, leaving the call to make
+///
+/// A `rec` function takes one argument at a time.
+pub(crate) fn start_call(closure: &Rc<ResolvedClosure>, args: Vec<Value>) -> TailCall {
+    let mut slots = closure.bound.clone();
+    if closure.rec_name().is_some() {
+        slots.push(Value::Resolved(Rc::clone(closure)));
+    }
+    let applied = closure.applied + args.len();
+    slots.extend(args);
+    TailCall::Resolved { closure: Rc::clone(closure), slots, applied }
+}
+
+/// Evaluate the body of a closure's call up to the expression in tail position
+///
+/// Mirrors `eval_core`'s loop over a closure body: `if`, `match` and `let`
+/// are followed into what they continue with, and a call of a closure found
+/// there is left for the caller to make. With `applied` arguments leaving
+/// parameters over, the body is the inner `fun`: this counts its step and
+/// gives the closure taking the rest, as applying a `Value::Closure` does.
+pub(crate) fn eval_body(closure: &Rc<ResolvedClosure>, slots: Vec<Value>, applied: usize) -> Result<Tail, EvalError> {
+    let env = &closure.env;
+    if applied < closure.function.params.len() {
+        env.count_step()?;
+        return Ok(Tail::Value(Value::Resolved(Rc::new(ResolvedClosure {
+            function: Rc::clone(&closure.function),
+            captured: closure.captured.clone(),
+            bound: slots,
+            applied,
+            env: env.clone(),
+        }))));
+    }
+    let mut frame = Frame { slots, captured: &closure.captured };
+    let mut current = &closure.function.body;
+    // Patterns of the match arms entered, for the trace
+    let mut arms: Vec<&Pattern> = Vec::new();
+    // Span of the innermost node entered, which errors raised so far are in
+    let mut span = None;
+    let result = (|| loop {
+        match current {
+            ResolvedExpr::At(at, inner)
+                if matches!(**inner, ResolvedExpr::If(..) | ResolvedExpr::Match(..) | ResolvedExpr::App(..)) =>
+            {
+                span = Some(*at);
+                current = inner;
+            }
+            ResolvedExpr::If(cond, then_branch, else_branch) => {
+                env.count_step()?;
+                current = match eval_in(cond, &mut frame, env)? {
+                    Value::Bool(true) => then_branch,
+                    Value::Bool(false) => else_branch,
+                    _ => return Err(EvalError::TypeError("If condition must be a boolean".to_string())),
+                };
+            }
+            ResolvedExpr::Match(scrutinee, patterns, guards, match_arms) => {
+                env.count_step()?;
+                let (index, bindings) = select_arm(scrutinee, patterns, guards, &mut frame, env)?;
+                frame.slots.extend(bindings.into_iter().map(|(_, value)| value));
+                arms.push(&patterns[index]);
+                current = &match_arms[index];
+            }
+            ResolvedExpr::Let(name, value, body) => {
+                env.count_step()?;
+                let value = eval_in(value, &mut frame, env).map_err(|e| in_let(e, name))?;
+                frame.slots.push(value);
+                current = body;
+            }
+            ResolvedExpr::App(head, args) => {
+                env.count_step()?;
+                let func_val = eval_in(head, &mut frame, env)?;
+                break apply_spine_in(func_val, var_name(head), args, env, |arg| eval_in(arg, &mut frame, env), true);
+            }
+            _ => break eval_in(current, &mut frame, env).map(Tail::Value),
+        }
+    })();
+    result.map_err(|e| {
+        let e = in_arms(e, &arms);
+        match span {
+            Some(span) => e.at(span),
+            None => e,
+        }
+    })
+}
+
+/// The variable `expr` is, if it is one
+fn var_name(expr: &ResolvedExpr) -> Option<&str> {
+    match expr {
+        ResolvedExpr::Var(name, _) => Some(name),
+        ResolvedExpr::At(_, inner) => var_name(inner),
+        _ => None,
+    }
+}
+
+/// Evaluate integer arithmetic marked by `mark_int_arith`, whose root step is already counted
+///
+/// Computed on `i64`s first and evaluated again node by node if that fails,
+/// as `eval_core` does.
+fn eval_int_arith(tree: &ResolvedExpr, nodes: usize, frame: &mut Frame, env: &Environment) -> Result<Value, EvalError> {
+    let fast = match tree {
+        ResolvedExpr::BinOp(op @ (BinOp::Eq | BinOp::Neq | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge), left, right) => {
+            int_value(left, frame, env).and_then(|a| int_compare(*op, a, int_value(right, frame, env)?).ok().map(Value::Bool))
+        }
+        _ => int_value(tree, frame, env).map(Value::Int),
+    };
+    match (fast, tree) {
+        (Some(value), _) => {
+            env.count_steps(nodes - 1)?;
+            Ok(value)
+        }
+        (None, ResolvedExpr::BinOp(op, left, right)) => {
+            let left_val = eval_in(left, frame, env)?;
+            let right_val = eval_in(right, frame, env)?;
+            eval_binop(*op, left_val, right_val)
+        }
+        (None, _) => eval_in(tree, frame, env),
+    }
+}
+
+/// The value of an arithmetic tree over `Int` literals and variables, if it has one
+fn int_value(expr: &ResolvedExpr, frame: &Frame, env: &Environment) -> Option<i64> {
+    match expr {
+        ResolvedExpr::Int(n) => Some(*n),
+        ResolvedExpr::Var(name, Slot::Global(_)) => match env.resolve(name) {
+            Some(Value::Int(n)) => Some(n),
+            _ => None,
+        },
+        ResolvedExpr::Var(_, slot) => match frame.get(slot) {
+            Value::Int(n) => Some(*n),
+            _ => None,
+        },
+        ResolvedExpr::BinOp(op, left, right) => {
+            int_arith(*op, int_value(left, frame, env)?, int_value(right, frame, env)?).ok()
+        }
+        ResolvedExpr::At(_, inner) => int_value(inner, frame, env),
+        _ => None,
+    }
+}
+
+/// `value` with every resolved closure in it turned into a `Value::Closure` or `Value::RecClosure`
+///
+/// The environment of such a closure is the one it was created in, extended
+/// with the variables it captured and the parameters applied so far.
+/// Reference cells are converted in place, once each.
+fn unresolved(value: &Value, seen: &mut HashSet<usize>) -> Value {
+    let all = |values: &[Value], seen: &mut HashSet<usize>| values.iter().map(|value| unresolved(value, seen)).collect();
+    match value {
+        Value::Resolved(closure) => {
+            let mut env = closure.env.clone();
+            for (name, value) in closure.variables() {
+                env.bind(name.to_string(), unresolved(value, seen));
+            }
+            match closure.rec_name() {
+                Some(name) => {
+                    Value::RecClosure(name.to_string(), closure.param().to_string(), Rc::clone(&closure.function.source), env)
+                }
+                None => Value::Closure(closure.param().to_string(), Rc::clone(closure.source_body()), env),
+            }
+        }
+        Value::Partial(name, applied, arity, closure) => {
+            Value::Partial(name.clone(), *applied, *arity, Box::new(unresolved(closure, seen)))
+        }
+        Value::Tuple(values) => Value::Tuple(all(values, seen)),
+        Value::Record(fields) => {
+            Value::Record(fields.iter().map(|(name, value)| (name.clone(), unresolved(value, seen))).collect())
+        }
+        Value::Variant(name, values) => Value::Variant(name.clone(), all(values, seen)),
+        Value::Array(size, values) => Value::Array(*size, all(values, seen)),
+        Value::Reference(id, cell) => {
+            if seen.insert(*id) {
+                let contents = unresolved(&cell.borrow(), seen);
+                *cell.borrow_mut() = contents;
+            }
+            value.clone()
+        }
+        Value::Builtin(builtin) => {
+            let mut builtin = builtin.clone();
+            builtin.args = all(&builtin.args, seen);
+            Value::Builtin(builtin)
+        }
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::lower;
+    use crate::parser::parse;
+
+    fn resolved(source: &str) -> ResolvedExpr {
+        resolve(&lower(&parse(source).unwrap()))
+    }
+
+    fn var(name: &str, slot: Slot) -> ResolvedExpr {
+        ResolvedExpr::Var(name.to_string(), slot)
+    }
+
+    #[test]
+    fn test_function_chain_shares_one_frame() {
+        let ResolvedExpr::Rec(function) = resolved("rec f -> fun a -> fun b -> f b a") else { panic!() };
+        assert_eq!(function.params, ["a", "b"]);
+        assert!(function.captures.is_empty());
+        let ResolvedExpr::App(head, args) = &function.body else { panic!() };
+        assert_eq!(**head, var("f", Slot::Local(0)));
+        assert_eq!(args, &[var("b", Slot::Local(2)), var("a", Slot::Local(1))]);
+    }
+
+    #[test]
+    fn test_closures_capture_what_they_use() {
+        let ResolvedExpr::Let(_, _, body) = resolved("let k = 1 in let unused = 2 in fun x -> fun y -> x + k") else { panic!() };
+        let ResolvedExpr::Let(_, _, body) = *body else { panic!() };
+        let ResolvedExpr::Lam(function) = *body else { panic!() };
+        assert_eq!(function.captures, [("k".to_string(), Slot::Local(0))]);
+        assert_eq!(
+            function.body,
+            ResolvedExpr::BinOp(BinOp::Add, Box::new(var("x", Slot::Local(0))), Box::new(var("k", Slot::Captured(0))))
+        );
+    }
+
+    #[test]
+    fn test_nested_captures_go_through_each_function() {
+        let source = "fun a -> let f = fun b -> let g = fun c -> a in g in f";
+        let ResolvedExpr::Lam(outer) = resolved(source) else { panic!() };
+        let ResolvedExpr::Let(_, middle, _) = &outer.body else { panic!() };
+        let ResolvedExpr::Lam(middle) = middle.as_ref() else { panic!() };
+        assert_eq!(middle.captures, [("a".to_string(), Slot::Local(0))]);
+        let ResolvedExpr::Let(_, inner, _) = &middle.body else { panic!() };
+        let ResolvedExpr::Lam(inner) = inner.as_ref() else { panic!() };
+        assert_eq!(inner.captures, [("a".to_string(), Slot::Captured(0))]);
+        assert_eq!(inner.body, var("a", Slot::Captured(0)));
+    }
+
+    #[test]
+    fn test_slots_follow_shadowing_and_patterns() {
+        let source = "let x = 0 in match (1, 2) with | (x, y) -> y - x | _ -> x";
+        let ResolvedExpr::Let(_, _, body) = resolved(source) else { panic!() };
+        let ResolvedExpr::Match(_, _, _, arms) = *body else { panic!() };
+        let difference = ResolvedExpr::BinOp(BinOp::Sub, Box::new(var("y", Slot::Local(2))), Box::new(var("x", Slot::Local(1))));
+        assert_eq!(arms, [difference, var("x", Slot::Local(0))]);
+    }
+
+    #[test]
+    fn test_globals_keep_the_names_in_scope() {
+        let ResolvedExpr::Let(_, _, body) = resolved("let total = 1 in fun n -> totl") else { panic!() };
+        let ResolvedExpr::Lam(function) = *body else { panic!() };
+        let ResolvedExpr::Var(_, Slot::Global(in_scope)) = &function.body else { panic!() };
+        assert_eq!(in_scope.as_ref(), ["total", "n"]);
+        assert!(function.captures.is_empty());
+    }
+
+    #[test]
+    fn test_load_captures_everything_in_scope() {
+        let ResolvedExpr::Let(_, _, body) = resolved("let a = 1 in fun b -> load \"lib.par\" in a") else { panic!() };
+        let ResolvedExpr::Lam(function) = *body else { panic!() };
+        assert_eq!(function.captures, [("a".to_string(), Slot::Local(0))]);
+        let ResolvedExpr::ByName(_, in_scope) = &function.body else { panic!() };
+        assert_eq!(in_scope, &[("a".to_string(), Slot::Captured(0)), ("b".to_string(), Slot::Local(0))]);
+    }
+}
@@ -0,0 +1,168 @@
+//! Estimating how much memory a value keeps alive
+//!
+//! A closure holds on to the environment it was created in, which may be far
+//! more than it needs: a function defined after loading a library captures
+//! every binding of the library, even if it only uses one. An embedder that
+//! caches returned values keeps all of that alive.
+//!
+//! This module looks at what a closure actually retains. Its *trimmed*
+//! environment is the part of the captured environment its body refers to,
+//! i.e. the values of its free variables (see [`captured_bindings`]).
+//! [`estimated_size`] gives a rough byte count for a value, counting only
+//! the trimmed environment of each closure inside it.
+//!
+//! [`EvalConfig::max_returned_closure_env`](crate::eval::EvalConfig::max_returned_closure_env)
+//! uses both to reject results whose closures retain more than a limit.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::{parse, eval, Environment, Value};
+//! use parlang::retention::captured_bindings;
+//!
+//! let mut env = Environment::new();
+//! env.bind("big".to_string(), Value::Array(1000, vec![Value::Int(0); 1000]));
+//! env.bind("small".to_string(), Value::Int(1));
+//! let f = eval(&parse("fun i -> big[i]").unwrap(), &env).unwrap();
+//!
+//! let captured = captured_bindings(&f);
+//! assert_eq!(captured.len(), 1);
+//! assert_eq!(captured[0].0, "big");
+//! ```
+
+use crate::eval::{EvalError, Value};
+use std::collections::HashSet;
+use std::mem::size_of;
+
+/// How many of the heaviest captured names `ResultRetainsTooMuch` lists
+const HEAVIEST_NAMES: usize = 3;
+
+/// Rough number of bytes `value` keeps alive
+///
+/// Every value counts as `size_of::<Value>()`, plus its elements, fields,
+/// payload, reference contents, applied built-in arguments, and the trimmed
+/// environment of closures. Constructor and field names count their length. The
+/// contents of opaque host values are unknown and not counted. Reference
+/// cells reachable more than once are counted once.
+pub fn estimated_size(value: &Value) -> usize {
+    size_in(value, &mut HashSet::new())
+}
+
+/// The bindings a closure's body refers to, with their estimated sizes,
+/// heaviest first
+///
+/// Names bound in neither the closure's local bindings nor the prelude (for
+/// example names resolved through the REPL's shared top-level layer) are
+/// left out. Returns nothing for values other than closures.
+pub fn captured_bindings(value: &Value) -> Vec<(String, usize)> {
+    captures(value, &mut HashSet::new())
+}
+
+/// Fail when a closure in `value` retains more than `limit` bytes of captured environment
+pub(crate) fn check_closure_envs(value: &Value, limit: usize) -> Result<(), EvalError> {
+    let mut closures = Vec::new();
+    collect_closures(value, &mut closures);
+    for closure in closures {
+        let captured = captured_bindings(closure);
+        let estimated_size = captured.iter().map(|(_, size)| size).sum();
+        if estimated_size > limit {
+            let binding_names = captured.into_iter().take(HEAVIEST_NAMES).map(|(name, _)| name).collect();
+            return Err(EvalError::ResultRetainsTooMuch { binding_names, estimated_size });
+        }
+    }
+    Ok(())
+}
+
+fn captures(value: &Value, seen: &mut HashSet<usize>) -> Vec<(String, usize)> {
+    let (free, env) = match value {
+        Value::Closure(param, body, env) => {
+            let mut free = body.free_vars();
+            free.remove(param);
+            (free, env)
+        }
+        Value::RecClosure(name, param, body, env) => {
+            let mut free = body.free_vars();
+            free.remove(param);
+            free.remove(name);
+            (free, env)
+        }
+        Value::Resolved(closure) => {
+            let mut captured: Vec<(String, usize)> =
+                closure.variables().map(|(name, value)| (name.to_string(), size_in(value, seen))).collect();
+            captured.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            return captured;
+        }
+        _ => return Vec::new(),
+    };
+    let mut captured: Vec<(String, usize)> = free
+        .into_iter()
+        .filter_map(|name| {
+            let size = size_in(env.lookup(&name)?, seen);
+            Some((name, size))
+        })
+        .collect();
+    captured.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    captured
+}
+
+fn size_in(value: &Value, seen: &mut HashSet<usize>) -> usize {
+    let contents: usize = match value {
+        Value::Int(_)
+        | Value::Bool(_)
+        | Value::Char(_)
+        | Value::Float(_)
+        | Value::Byte(_)
+        | Value::Unit
+        | Value::Range(..)
+        | Value::Opaque(..) => 0,
+        Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_) => {
+            captures(value, seen).iter().map(|(_, size)| size).sum()
+        }
+        Value::Partial(_, _, _, closure) => size_in(closure, seen),
+        Value::Tuple(values) | Value::Array(_, values) => values.iter().map(|value| size_in(value, seen)).sum(),
+        Value::Variant(name, values) => name.len() + values.iter().map(|value| size_in(value, seen)).sum::<usize>(),
+        Value::Record(fields) => fields.iter().map(|(name, value)| name.len() + size_in(value, seen)).sum(),
+        Value::Reference(id, cell) => {
+            if seen.insert(*id) {
+                size_in(&cell.borrow(), seen)
+            } else {
+                0
+            }
+        }
+        Value::Builtin(builtin) => builtin.args.iter().map(|value| size_in(value, seen)).sum(),
+    };
+    size_of::<Value>() + contents
+}
+
+/// Every closure reachable from `value` without entering other closures' environments
+fn collect_closures<'a>(value: &'a Value, closures: &mut Vec<&'a Value>) {
+    match value {
+        Value::Closure(..) | Value::RecClosure(..) | Value::Resolved(_) => closures.push(value),
+        Value::Partial(_, _, _, closure) => collect_closures(closure, closures),
+        Value::Tuple(values) | Value::Array(_, values) | Value::Variant(_, values) => {
+            for value in values {
+                collect_closures(value, closures);
+            }
+        }
+        Value::Record(fields) => {
+            for value in fields.values() {
+                collect_closures(value, closures);
+            }
+        }
+        Value::Builtin(builtin) => {
+            for value in &builtin.args {
+                collect_closures(value, closures);
+            }
+        }
+        // The cell may change later, so what it retains now is not checked
+        Value::Reference(..)
+        | Value::Int(_)
+        | Value::Bool(_)
+        | Value::Char(_)
+        | Value::Float(_)
+        | Value::Byte(_)
+        | Value::Unit
+        | Value::Range(..)
+        | Value::Opaque(..) => {}
+    }
+}
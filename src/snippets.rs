@@ -0,0 +1,174 @@
+//! Checking `ParLang` snippets embedded in documentation
+//!
+//! Doc comments show `ParLang` programs that nothing runs, so they silently
+//! go stale when the language changes. Snippets in a fence marked
+//! `parlang` are extracted with [`extract_doc_snippets`] and checked with
+//! [`run_snippet`]: each must parse and evaluate without error. Fences
+//! marked anything else (`text`, `rust`, ...) are left alone.
+//!
+//! The language has no comment syntax, so expectations are written as
+//! annotations that the extractor removes before parsing:
+//!
+//! - `-- => value`: the snippet evaluates to `value`, as the REPL prints it
+//! - `-- : type`: the snippet type checks with type `type`
+//!
+//! An annotation runs to the end of its line and may follow code or stand
+//! on a line of its own. When a snippet has several of the same kind, the
+//! last one counts. Type checking only runs for snippets with a `-- :`
+//! annotation.
+//!
+//! ```text
+//! /// ```parlang
+//! /// let x = 42 in x + 1 -- => 43
+//! /// ```
+//! ```
+//!
+//! The `tests/doc_snippet_tests.rs` test runs every snippet in `src/`.
+
+use crate::check::DEFAULT_STEP_LIMIT;
+use crate::eval::{eval, Environment};
+use crate::parser::parse;
+use crate::typechecker::{typecheck_in, TypeEnv};
+use std::fmt;
+
+/// Info string of the fences whose contents are checked
+pub const FENCE_LANGUAGE: &str = "parlang";
+
+/// Annotation for the value a snippet evaluates to
+pub const VALUE_ANNOTATION: &str = "-- =>";
+
+/// Annotation for the type a snippet checks with
+pub const TYPE_ANNOTATION: &str = "-- :";
+
+/// A `ParLang` snippet found in a fenced block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// File the snippet was found in, as given to the extractor
+    pub file: String,
+    /// Line of the snippet's first line of code (1-based)
+    pub line: usize,
+    /// The program, with annotations removed
+    pub source: String,
+    /// Expected value from a `-- =>` annotation
+    pub expected_value: Option<String>,
+    /// Expected type from a `-- :` annotation
+    pub expected_type: Option<String>,
+}
+
+/// Why a snippet failed, and where it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnippetFailure {
+    /// File the snippet was found in
+    pub file: String,
+    /// Line of the snippet's first line of code (1-based)
+    pub line: usize,
+    /// What went wrong
+    pub message: String,
+}
+
+impl fmt::Display for SnippetFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for SnippetFailure {}
+
+/// Every `parlang` snippet in the doc comments (`///` and `//!`) of a Rust source file
+///
+/// A fence must open and close within consecutive doc comment lines; a
+/// fence left open when the doc comment ends is dropped.
+pub fn extract_doc_snippets(file: &str, contents: &str) -> Vec<Snippet> {
+    let lines = contents.lines().map(|line| {
+        let trimmed = line.trim_start();
+        let doc = trimmed.strip_prefix("///").or_else(|| trimmed.strip_prefix("//!"));
+        doc.map(|text| text.strip_prefix(' ').unwrap_or(text))
+    });
+    extract_fenced(file, lines)
+}
+
+/// Snippets in `lines`, where `None` marks a line that cannot be part of a fence
+fn extract_fenced<'a>(file: &str, lines: impl Iterator<Item = Option<&'a str>>) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+    // Line of the first code line and the code so far, inside a parlang fence
+    let mut open: Option<(usize, Vec<&str>)> = None;
+    // Inside a fence of another language
+    let mut skipping = false;
+    for (index, text) in lines.enumerate() {
+        let Some(text) = text else {
+            open = None;
+            skipping = false;
+            continue;
+        };
+        let fence = text.trim();
+        if let Some((line, code)) = &mut open {
+            if fence == "```" {
+                snippets.push(snippet(file, *line, code));
+                open = None;
+            } else {
+                code.push(text);
+            }
+        } else if skipping {
+            skipping = fence != "```";
+        } else if let Some(info) = fence.strip_prefix("```") {
+            if info.trim() == FENCE_LANGUAGE {
+                open = Some((index + 2, Vec::new()));
+            } else {
+                skipping = true;
+            }
+        }
+    }
+    snippets
+}
+
+/// Build a snippet from its lines, splitting off annotations
+fn snippet(file: &str, line: usize, lines: &[&str]) -> Snippet {
+    let mut source = Vec::new();
+    let mut expected_value = None;
+    let mut expected_type = None;
+    for text in lines {
+        let value = text.find(VALUE_ANNOTATION).map(|at| (at, VALUE_ANNOTATION.len(), &mut expected_value));
+        let ty = text.find(TYPE_ANNOTATION).map(|at| (at, TYPE_ANNOTATION.len(), &mut expected_type));
+        // Whichever annotation comes first starts the annotated part of the line
+        let annotation = match (value, ty) {
+            (Some(value), Some(ty)) => Some(if value.0 <= ty.0 { value } else { ty }),
+            (value, ty) => value.or(ty),
+        };
+        match annotation {
+            Some((at, len, expected)) => {
+                *expected = Some(text[at + len..].trim().to_string());
+                source.push(text[..at].trim_end());
+            }
+            None => source.push(text),
+        }
+    }
+    Snippet { file: file.to_string(), line, source: source.join("\n"), expected_value, expected_type }
+}
+
+/// Parse, evaluate, and (when annotated with a type) type check a snippet
+///
+/// Evaluation starts from the prelude and runs under
+/// [`DEFAULT_STEP_LIMIT`], so a snippet that loops fails instead of hanging.
+///
+/// # Errors
+///
+/// Returns a failure locating the snippet when a stage fails or its result
+/// differs from the annotation.
+pub fn run_snippet(snippet: &Snippet) -> Result<(), SnippetFailure> {
+    let fail = |message: String| SnippetFailure { file: snippet.file.clone(), line: snippet.line, message };
+
+    let expr = parse(&snippet.source).map_err(|e| fail(format!("parse error: {e}")))?;
+    if let Some(expected) = &snippet.expected_type {
+        let ty = typecheck_in(&expr, &mut TypeEnv::with_builtins()).map_err(|e| fail(format!("type error: {e}")))?;
+        if ty.to_string() != *expected {
+            return Err(fail(format!("expected type {expected}, got {ty}")));
+        }
+    }
+    let mut env = Environment::with_builtins();
+    env.set_step_limit(Some(DEFAULT_STEP_LIMIT));
+    let value = eval(&expr, &env).map_err(|e| fail(format!("evaluation error: {e}")))?;
+    match &snippet.expected_value {
+        Some(expected) if value.to_string() != *expected => Err(fail(format!("expected {expected}, got {value}"))),
+        _ => Ok(()),
+    }
+}
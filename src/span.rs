@@ -0,0 +1,110 @@
+//! Source locations of expressions
+//!
+//! [`parse_program_with_spans`](crate::parser::parse_program_with_spans)
+//! returns, next to the parsed expression, the [`Span`] of each of its nodes
+//! in the order of [`Expr::subexpressions`](crate::Expr::subexpressions).
+//! The AST itself carries no positions, so expressions built by hand or by
+//! [`parse`](crate::parse) are unchanged; a node the parser made up while
+//! desugaring, such as the `Cons` cells of a list literal, has no span.
+//!
+//! [`typecheck_with_spans`](crate::typechecker::typecheck_with_spans) and
+//! [`eval_with_spans`](crate::eval::eval_with_spans) take the spans along
+//! and report an error at the innermost node with a span around where it
+//! was raised: `TypeError::At` and `EvalError::At` hold the span, and
+//! [`Span::render`] shows it as the CLI does.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::parser::parse_program_with_spans;
+//! use parlang::typechecker::typecheck_with_spans;
+//! use parlang::TypeEnv;
+//!
+//! let source = "let x = 1 in\nif x then 2 else 3";
+//! let (expr, spans) = parse_program_with_spans(source).unwrap();
+//! let error = typecheck_with_spans(&expr, &spans, &mut TypeEnv::new()).unwrap_err();
+//! let span = error.span().unwrap();
+//! assert_eq!(&source[span.start..span.end], "if x then 2 else 3");
+//! assert_eq!(span.line_col(source), (2, 1));
+//! ```
+
+use std::fmt;
+
+/// A range of byte offsets in the source, from `start` up to `end`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Line and column (both 1-based) where the span starts in `source`
+    ///
+    /// Columns count characters, and a `\r` before a line break takes none.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (line, before[line_start..].chars().filter(|&c| c != '\r').count() + 1)
+    }
+
+    /// `message` labelled with where the span starts, followed by its first
+    /// source line with the span underlined
+    ///
+    /// ```
+    /// use parlang::span::Span;
+    ///
+    /// let text = Span::new(8, 12).render("let x = true + 1", "Type error", "Cannot unify types: Int and Bool");
+    /// assert_eq!(text, "Type error at 1:9: Cannot unify types: Int and Bool\n  |\n1 | let x = true + 1\n  |         ^^^^");
+    /// ```
+    pub fn render(&self, source: &str, label: &str, message: impl fmt::Display) -> String {
+        let (line, column) = self.line_col(source);
+        let start = self.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let snippet = source[line_start..line_end].trim_end_matches('\r');
+        let underlined = source[start..self.end.clamp(start, line_end)].trim_end_matches('\r').chars().count();
+        // Tabs are kept so the underline lines up however the terminal expands them
+        let indent: String = source[line_start..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let number = line.to_string();
+        let gutter = " ".repeat(number.len());
+        format!(
+            "{label} at {line}:{column}: {message}\n{gutter} |\n{number} | {snippet}\n{gutter} | {indent}{}",
+            "^".repeat(underlined.max(1))
+        )
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_col() {
+        let source = "let x = 1 in\r\n  x +\tλ";
+        assert_eq!(Span::new(0, 3).line_col(source), (1, 1));
+        assert_eq!(Span::new(4, 5).line_col(source), (1, 5));
+        assert_eq!(Span::new(16, 17).line_col(source), (2, 3));
+        // Columns count characters, not bytes
+        assert_eq!(Span::new(source.len(), source.len()).line_col(source), (2, 8));
+    }
+
+    #[test]
+    fn test_render_underlines_the_first_line_of_the_span() {
+        let source = "let f = fun x ->\n\tx + true\nin f";
+        let text = Span::new(18, source.len()).render(source, "Error", "boom");
+        assert_eq!(text, "Error at 2:2: boom\n  |\n2 | \tx + true\n  | \t^^^^^^^^");
+        // An empty span at the end of the input still gets a caret
+        assert!(Span::new(source.len(), source.len()).render(source, "Error", "boom").ends_with("  |     ^"));
+    }
+}
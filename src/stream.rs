@@ -0,0 +1,135 @@
+//! Streaming large results to embedders
+//!
+//! [`eval_stream`] evaluates a program whose result is an array, tuple, or
+//! record and hands out its top-level elements one at a time. The result is
+//! still built by `eval`, but the stream owns it and moves each element out
+//! as it is consumed, so a host converting the elements (for example with
+//! [`ValueStream::map_items`]) never holds a second full-size copy of the
+//! result next to its own data. Each element is dropped as soon as the host
+//! is done with it.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::{parse, Environment};
+//! use parlang::stream::eval_stream;
+//!
+//! let expr = parse("[|1, 2, 3|]").unwrap();
+//! let numbers: Vec<i64> = eval_stream(&expr, &Environment::new())
+//!     .unwrap()
+//!     .map_items::<i64>()
+//!     .collect::<Result<_, _>>()
+//!     .unwrap();
+//! assert_eq!(numbers, [1, 2, 3]);
+//! ```
+
+use crate::ast::Expr;
+use crate::eval::{eval, Environment, EvalError, Value};
+use std::vec;
+
+/// Evaluate `expr` and stream the elements of its result
+///
+/// Arrays and tuples yield their elements in order; records yield their
+/// field values ordered by field name (see [`ValueStream::field_names`]).
+///
+/// # Errors
+///
+/// Returns the evaluation error, or a `TypeError` when the result is not an
+/// array, tuple, or record.
+pub fn eval_stream(expr: &Expr, env: &Environment) -> Result<ValueStream, EvalError> {
+    ValueStream::new(eval(expr, env)?)
+}
+
+/// The top-level elements of an array, tuple, or record, consumed one at a time
+#[derive(Debug)]
+pub struct ValueStream {
+    items: vec::IntoIter<Value>,
+    /// Field names in the order their values are yielded; `None` unless the result is a record
+    field_names: Option<Vec<String>>,
+}
+
+impl ValueStream {
+    /// Stream the elements of an array, tuple, or record value
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TypeError` for any other value.
+    pub fn new(value: Value) -> Result<ValueStream, EvalError> {
+        match value {
+            Value::Array(_, items) | Value::Tuple(items) => Ok(ValueStream { items: items.into_iter(), field_names: None }),
+            Value::Record(fields) => {
+                let mut fields: Vec<(String, Value)> = fields.into_iter().collect();
+                fields.sort_by(|a, b| a.0.cmp(&b.0));
+                let (names, items) = fields.into_iter().unzip();
+                Ok(ValueStream { items: Vec::into_iter(items), field_names: Some(names) })
+            }
+            other => Err(EvalError::TypeError(format!(
+                "Streaming requires an array, tuple, or record result, got {other}"
+            ))),
+        }
+    }
+
+    /// Names of all fields, in the order their values are yielded, when the result is a record
+    pub fn field_names(&self) -> Option<&[String]> {
+        self.field_names.as_deref()
+    }
+
+    /// Convert each remaining element to `T` as it is consumed
+    ///
+    /// Elements that cannot be converted yield the conversion error; the
+    /// stream itself carries on with the next element.
+    pub fn map_items<T: TryFrom<Value>>(self) -> impl Iterator<Item = Result<T, T::Error>> {
+        self.map(T::try_from)
+    }
+}
+
+impl Iterator for ValueStream {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        self.items.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+impl ExactSizeIterator for ValueStream {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn stream(source: &str) -> Result<ValueStream, EvalError> {
+        eval_stream(&parse(source).unwrap(), &Environment::new())
+    }
+
+    #[test]
+    fn test_stream_shapes() {
+        let tuple = stream("(1, true, 'c')").unwrap();
+        assert_eq!(tuple.len(), 3);
+        assert_eq!(tuple.field_names(), None);
+        assert_eq!(tuple.collect::<Vec<_>>(), [Value::Int(1), Value::Bool(true), Value::Char('c')]);
+
+        let record = stream("{ y: 2, x: 1 }").unwrap();
+        assert_eq!(record.field_names(), Some(&["x".to_string(), "y".to_string()][..]));
+        assert_eq!(record.map_items::<i64>().collect::<Result<Vec<_>, _>>(), Ok(vec![1, 2]));
+
+        assert_eq!(stream("[||]").unwrap().count(), 0);
+        assert!(matches!(stream("42"), Err(EvalError::TypeError(_))));
+        assert!(matches!(stream("1 / 0"), Err(EvalError::DivisionByZero)));
+    }
+
+    #[test]
+    fn test_map_items_reports_bad_elements() {
+        let converted: Vec<Result<i64, EvalError>> = stream("(1, 2.5, 3)").unwrap().map_items().collect();
+        assert_eq!(converted[0], Ok(1));
+        assert_eq!(converted[1], Err(EvalError::TypeError("expected Int, got 2.5".to_string())));
+        assert_eq!(converted[2], Ok(3));
+
+        let floats: Result<Vec<f64>, _> = stream("[|0.5, 1.5|]").unwrap().map_items().collect();
+        assert_eq!(floats, Ok(vec![0.5, 1.5]));
+    }
+}
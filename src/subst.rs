@@ -0,0 +1,571 @@
+//! Capture-avoiding substitution over the `ParLang` AST
+//!
+//! This module provides the small set of scoping utilities that program
+//! transformations need:
+//!
+//! - [`free_vars`]: the set of variables an expression references without binding
+//! - [`fresh_name`]: generate a name that does not clash with a set of names
+//! - [`substitute`]: replace the free occurrences of a variable with an expression
+//!
+//! # Scoping Rules
+//!
+//! The binders of the language are:
+//! - `Let(name, _, value, body)`: `name` is bound in `body` only (not in `value`)
+//! - `Fun(param, _, body)`: `param` is bound in `body`
+//! - `Rec(name, body)`: `name` is bound in `body`
+//! - `Seq(bindings, body)`: each binding is in scope for the following bindings and the body
+//! - `Match(_, arms)`: the variables of each arm's pattern are bound in that arm's result
+//! - `LoadAs(_, namespace, body)`: `namespace` is bound in `body`
+//!
+//! The bindings a `Load` introduces are not known statically, so the body of a
+//! `Load` is treated as if the library bound nothing.
+//!
+//! # Example
+//!
+//! ```
+//! use parlang::{parse, substitute};
+//!
+//! // Substituting y for x must not let the inner binder capture it
+//! let expr = parse("fun y -> x + y").unwrap();
+//! let replaced = substitute(&expr, "x", &parse("y").unwrap());
+//! assert_eq!(format!("{replaced}"), "(fun y_1 -> (y + y_1))");
+//! ```
+
+use crate::ast::{Expr, Pattern};
+use std::collections::HashSet;
+use std::hash::BuildHasher;
+
+/// Collect the free variables of an expression
+///
+/// A variable is free if it is referenced without being bound by an enclosing
+/// `let`, `fun`, `rec`, sequential binding, or match pattern.
+///
+/// # Example
+/// - `x + 1` → `{x}`
+/// - `fun x -> x + y` → `{y}`
+/// - `let x = x in x` → `{x}` (the value is not in scope of its own binding)
+pub fn free_vars(expr: &Expr) -> HashSet<String> {
+    let mut free = HashSet::new();
+    collect_free_vars(expr, &mut Vec::new(), &mut free);
+    free
+}
+
+/// Walk an expression, recording every variable not found in `bound`
+fn collect_free_vars(expr: &Expr, bound: &mut Vec<String>, free: &mut HashSet<String>) {
+    match expr {
+        Expr::Int(_)
+        | Expr::Bool(_)
+        | Expr::Char(_)
+        | Expr::Float(_)
+        | Expr::Byte(_)
+        | Expr::EmptyBody => {}
+        Expr::Var(name) => {
+            if !bound.contains(name) {
+                free.insert(name.clone());
+            }
+        }
+        Expr::Let(name, _, value, body) => {
+            collect_free_vars(value, bound, free);
+            bound.push(name.clone());
+            collect_free_vars(body, bound, free);
+            bound.pop();
+        }
+        Expr::Fun(name, _, body) | Expr::Rec(name, body) | Expr::LoadAs(_, name, body) => {
+            bound.push(name.clone());
+            collect_free_vars(body, bound, free);
+            bound.pop();
+        }
+        Expr::Try(body, name, handler) => {
+            collect_free_vars(body, bound, free);
+            bound.push(name.clone());
+            collect_free_vars(handler, bound, free);
+            bound.pop();
+        }
+        Expr::Seq(bindings, body) => {
+            for (name, _, value) in bindings {
+                collect_free_vars(value, bound, free);
+                bound.push(name.clone());
+            }
+            collect_free_vars(body, bound, free);
+            bound.truncate(bound.len() - bindings.len());
+        }
+        Expr::Match(scrutinee, arms) => {
+            collect_free_vars(scrutinee, bound, free);
+            for (pattern, guard, result) in arms {
+                let vars = pattern_vars(pattern);
+                let count = vars.len();
+                bound.extend(vars);
+                if let Some(guard) = guard {
+                    collect_free_vars(guard, bound, free);
+                }
+                collect_free_vars(result, bound, free);
+                bound.truncate(bound.len() - count);
+            }
+        }
+        _ => {
+            for child in children(expr) {
+                collect_free_vars(child, bound, free);
+            }
+        }
+    }
+}
+
+/// The direct sub-expressions of a node that does not bind any variables
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::BinOp(_, left, right)
+        | Expr::App(left, right)
+        | Expr::ArrayIndex(left, right)
+        | Expr::RefAssign(left, right)
+        | Expr::Range(left, right) => vec![left, right],
+        Expr::If(cond, then_branch, else_branch) => vec![cond, then_branch, else_branch],
+        Expr::IfChain(branches, else_branch) => branches
+            .iter()
+            .flat_map(|(cond, branch)| [cond, branch])
+            .chain(std::iter::once(else_branch.as_ref()))
+            .collect(),
+        Expr::Load(_, body)
+        | Expr::TupleProj(body, _)
+        | Expr::TypeAlias(_, _, body)
+        | Expr::FieldAccess(body, _)
+        | Expr::TypeDef { body, .. }
+        | Expr::Ref(body)
+        | Expr::Deref(body)
+        | Expr::Neg(body) => vec![body],
+        Expr::Tuple(elements) | Expr::Constructor(_, elements) | Expr::Array(elements) => {
+            elements.iter().collect()
+        }
+        Expr::Record(fields) => fields.iter().map(|(_, e)| e).collect(),
+        _ => vec![],
+    }
+}
+
+/// Collect the variables bound by a pattern, in left-to-right order
+pub fn pattern_vars(pattern: &Pattern) -> Vec<String> {
+    let mut vars = Vec::new();
+    collect_pattern_vars(pattern, &mut vars);
+    vars
+}
+
+fn collect_pattern_vars(pattern: &Pattern, vars: &mut Vec<String>) {
+    match pattern {
+        Pattern::Var(name) => vars.push(name.clone()),
+        Pattern::Literal(_) | Pattern::Wildcard => {}
+        Pattern::Tuple(patterns) | Pattern::Constructor(_, patterns) => {
+            for p in patterns {
+                collect_pattern_vars(p, vars);
+            }
+        }
+        Pattern::Record(fields) => {
+            for (_, p) in fields {
+                collect_pattern_vars(p, vars);
+            }
+        }
+    }
+}
+
+/// Generate a variable name based on `base` that does not appear in `avoid`
+///
+/// Returns `base` itself when it is not taken, otherwise the first of
+/// `base_1`, `base_2`, ... that is free.
+pub fn fresh_name<S: BuildHasher>(base: &str, avoid: &HashSet<String, S>) -> String {
+    if !avoid.contains(base) {
+        return base.to_string();
+    }
+    let mut n = 1;
+    loop {
+        let candidate = format!("{base}_{n}");
+        if !avoid.contains(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Replace the free occurrences of `name` in `expr` with `replacement`
+///
+/// Substitution stops at binders that shadow `name`. When a binder would
+/// capture one of the free variables of `replacement`, the binder is renamed
+/// (together with its uses) to a fresh name first, so the meaning of both
+/// expressions is preserved.
+///
+/// # Example
+/// - `substitute(x + 1, "x", 2)` → `2 + 1`
+/// - `substitute(fun x -> x, "x", 2)` → `fun x -> x` (shadowed)
+/// - `substitute(fun y -> x + y, "x", y)` → `fun y_1 -> y + y_1` (renamed)
+pub fn substitute(expr: &Expr, name: &str, replacement: &Expr) -> Expr {
+    let replacement_fv = free_vars(replacement);
+    subst(expr, name, replacement, &replacement_fv)
+}
+
+fn subst(expr: &Expr, name: &str, replacement: &Expr, replacement_fv: &HashSet<String>) -> Expr {
+    let go = |e: &Expr| subst(e, name, replacement, replacement_fv);
+    let go_box = |e: &Expr| Box::new(subst(e, name, replacement, replacement_fv));
+
+    match expr {
+        Expr::Int(_)
+        | Expr::Bool(_)
+        | Expr::Char(_)
+        | Expr::Float(_)
+        | Expr::Byte(_)
+        | Expr::EmptyBody => expr.clone(),
+        Expr::Var(var) => {
+            if var == name {
+                replacement.clone()
+            } else {
+                expr.clone()
+            }
+        }
+        Expr::Let(binder, ty_ann, value, body) => {
+            let value = go_box(value);
+            let (binder, body) = subst_under_binder(binder, body, name, replacement, replacement_fv);
+            Expr::Let(binder, ty_ann.clone(), value, Box::new(body))
+        }
+        Expr::Fun(param, ty_ann, body) => {
+            let (param, body) = subst_under_binder(param, body, name, replacement, replacement_fv);
+            Expr::Fun(param, ty_ann.clone(), Box::new(body))
+        }
+        Expr::Rec(rec_name, body) => {
+            let (rec_name, body) = subst_under_binder(rec_name, body, name, replacement, replacement_fv);
+            Expr::Rec(rec_name, Box::new(body))
+        }
+        Expr::Seq(bindings, body) => {
+            let (bindings, body) = subst_seq(bindings, body, name, replacement, replacement_fv);
+            Expr::Seq(bindings, Box::new(body))
+        }
+        Expr::Match(scrutinee, arms) => {
+            let arms = arms
+                .iter()
+                .map(|(pattern, guard, result)| subst_arm(pattern, guard.as_ref(), result, name, replacement, replacement_fv))
+                .collect();
+            Expr::Match(go_box(scrutinee), arms)
+        }
+        Expr::BinOp(op, left, right) => Expr::BinOp(*op, go_box(left), go_box(right)),
+        Expr::If(cond, then_branch, else_branch) => {
+            Expr::If(go_box(cond), go_box(then_branch), go_box(else_branch))
+        }
+        Expr::IfChain(branches, else_branch) => Expr::IfChain(
+            branches.iter().map(|(cond, branch)| (go(cond), go(branch))).collect(),
+            go_box(else_branch),
+        ),
+        Expr::App(func, arg) => Expr::App(go_box(func), go_box(arg)),
+        Expr::Load(filepath, body) => Expr::Load(filepath.clone(), go_box(body)),
+        Expr::LoadAs(filepath, namespace, body) => {
+            let (namespace, body) = subst_under_binder(namespace, body, name, replacement, replacement_fv);
+            Expr::LoadAs(filepath.clone(), namespace, Box::new(body))
+        }
+        Expr::Tuple(elements) => Expr::Tuple(elements.iter().map(go).collect()),
+        Expr::TupleProj(tuple, index) => Expr::TupleProj(go_box(tuple), *index),
+        Expr::TypeAlias(alias, ty_expr, body) => {
+            Expr::TypeAlias(alias.clone(), ty_expr.clone(), go_box(body))
+        }
+        Expr::Record(fields) => Expr::Record(
+            fields.iter().map(|(field, e)| (field.clone(), go(e))).collect(),
+        ),
+        Expr::FieldAccess(record, field) => Expr::FieldAccess(go_box(record), field.clone()),
+        Expr::TypeDef { name: type_name, type_params, constructors, body } => Expr::TypeDef {
+            name: type_name.clone(),
+            type_params: type_params.clone(),
+            constructors: constructors.clone(),
+            body: go_box(body),
+        },
+        Expr::Constructor(ctor, args) => Expr::Constructor(ctor.clone(), args.iter().map(go).collect()),
+        Expr::Array(elements) => Expr::Array(elements.iter().map(go).collect()),
+        Expr::ArrayIndex(arr, index) => Expr::ArrayIndex(go_box(arr), go_box(index)),
+        Expr::Ref(inner) => Expr::Ref(go_box(inner)),
+        Expr::Deref(inner) => Expr::Deref(go_box(inner)),
+        Expr::Neg(inner) => Expr::Neg(go_box(inner)),
+        Expr::RefAssign(target, value) => Expr::RefAssign(go_box(target), go_box(value)),
+        Expr::Range(start, end) => Expr::Range(go_box(start), go_box(end)),
+        Expr::Try(body, err, handler) => {
+            let body = go_box(body);
+            let (err, handler) = subst_under_binder(err, handler, name, replacement, replacement_fv);
+            Expr::Try(body, err, Box::new(handler))
+        }
+    }
+}
+
+/// Substitute inside the scope of a single binder, renaming the binder if it
+/// would capture a free variable of the replacement
+fn subst_under_binder(
+    binder: &str,
+    scope: &Expr,
+    name: &str,
+    replacement: &Expr,
+    replacement_fv: &HashSet<String>,
+) -> (String, Expr) {
+    if binder == name {
+        // Shadowed: occurrences of `name` in the scope refer to this binder
+        return (binder.to_string(), scope.clone());
+    }
+    let scope_fv = free_vars(scope);
+    if !scope_fv.contains(name) {
+        return (binder.to_string(), scope.clone());
+    }
+    if replacement_fv.contains(binder) {
+        let renamed = fresh_binder(binder, name, replacement_fv, &scope_fv);
+        let scope = substitute(scope, binder, &Expr::Var(renamed.clone()));
+        let scope = subst(&scope, name, replacement, replacement_fv);
+        (renamed, scope)
+    } else {
+        (binder.to_string(), subst(scope, name, replacement, replacement_fv))
+    }
+}
+
+/// Substitute through sequential bindings, where each binding scopes over the rest
+fn subst_seq(
+    bindings: &[(String, Option<crate::ast::TypeAnnotation>, Expr)],
+    body: &Expr,
+    name: &str,
+    replacement: &Expr,
+    replacement_fv: &HashSet<String>,
+) -> (Vec<(String, Option<crate::ast::TypeAnnotation>, Expr)>, Expr) {
+    let Some(((binder, ty_ann, value), rest)) = bindings.split_first() else {
+        return (Vec::new(), subst(body, name, replacement, replacement_fv));
+    };
+    let value = subst(value, name, replacement, replacement_fv);
+
+    // The remaining bindings and the body form the scope of this binder
+    let scope = Expr::Seq(rest.to_vec(), Box::new(body.clone()));
+    let (binder, scope) = subst_under_binder(binder, &scope, name, replacement, replacement_fv);
+    let Expr::Seq(rest, body) = scope else {
+        unreachable!("substitution preserves the Seq node");
+    };
+
+    let mut result = vec![(binder, ty_ann.clone(), value)];
+    result.extend(rest);
+    (result, *body)
+}
+
+/// Substitute inside a match arm, renaming pattern variables that would capture
+fn subst_arm(
+    pattern: &Pattern,
+    guard: Option<&Expr>,
+    result: &Expr,
+    name: &str,
+    replacement: &Expr,
+    replacement_fv: &HashSet<String>,
+) -> (Pattern, Option<Expr>, Expr) {
+    let vars = pattern_vars(pattern);
+    let arm_fv = |guard: Option<&Expr>, result: &Expr| {
+        let mut fv = free_vars(result);
+        fv.extend(guard.map(free_vars).unwrap_or_default());
+        fv
+    };
+    if vars.iter().any(|v| v == name) || !arm_fv(guard, result).contains(name) {
+        return (pattern.clone(), guard.cloned(), result.clone());
+    }
+
+    let mut pattern = pattern.clone();
+    let mut guard = guard.cloned();
+    let mut result = result.clone();
+    for var in vars.iter().filter(|v| replacement_fv.contains(*v)) {
+        let mut avoid = arm_fv(guard.as_ref(), &result);
+        avoid.extend(vars.iter().cloned());
+        let renamed = fresh_binder(var, name, replacement_fv, &avoid);
+        guard = guard.map(|guard| substitute(&guard, var, &Expr::Var(renamed.clone())));
+        result = substitute(&result, var, &Expr::Var(renamed.clone()));
+        pattern = rename_pattern_var(&pattern, var, &renamed);
+    }
+    let guard = guard.map(|guard| subst(&guard, name, replacement, replacement_fv));
+    let result = subst(&result, name, replacement, replacement_fv);
+    (pattern, guard, result)
+}
+
+/// Pick a new name for a binder that avoids every name relevant to the substitution
+fn fresh_binder(
+    binder: &str,
+    name: &str,
+    replacement_fv: &HashSet<String>,
+    scope_fv: &HashSet<String>,
+) -> String {
+    let mut avoid: HashSet<String> = replacement_fv.union(scope_fv).cloned().collect();
+    avoid.insert(name.to_string());
+    avoid.insert(binder.to_string());
+    fresh_name(binder, &avoid)
+}
+
+fn rename_pattern_var(pattern: &Pattern, from: &str, to: &str) -> Pattern {
+    match pattern {
+        Pattern::Var(v) if v == from => Pattern::Var(to.to_string()),
+        Pattern::Literal(_) | Pattern::Wildcard | Pattern::Var(_) => pattern.clone(),
+        Pattern::Tuple(patterns) => Pattern::Tuple(
+            patterns.iter().map(|p| rename_pattern_var(p, from, to)).collect(),
+        ),
+        Pattern::Constructor(ctor, patterns) => Pattern::Constructor(
+            ctor.clone(),
+            patterns.iter().map(|p| rename_pattern_var(p, from, to)).collect(),
+        ),
+        Pattern::Record(fields) => Pattern::Record(
+            fields
+                .iter()
+                .map(|(field, p)| (field.clone(), rename_pattern_var(p, from, to)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn var(name: &str) -> Expr {
+        Expr::Var(name.to_string())
+    }
+
+    fn set(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    #[test]
+    fn test_free_vars_simple() {
+        assert_eq!(free_vars(&parse("x + y").unwrap()), set(&["x", "y"]));
+    }
+
+    #[test]
+    fn test_free_vars_fun_binds_param() {
+        assert_eq!(free_vars(&parse("fun x -> x + y").unwrap()), set(&["y"]));
+    }
+
+    #[test]
+    fn test_free_vars_let_value_not_in_scope() {
+        assert_eq!(free_vars(&parse("let x = x in x").unwrap()), set(&["x"]));
+    }
+
+    #[test]
+    fn test_free_vars_rec_binds_name() {
+        assert_eq!(free_vars(&parse("rec f -> fun n -> f (n - k)").unwrap()), set(&["k"]));
+    }
+
+    #[test]
+    fn test_free_vars_seq() {
+        let expr = parse("let a = b; let c = a + d; c").unwrap();
+        assert_eq!(free_vars(&expr), set(&["b", "d"]));
+    }
+
+    #[test]
+    fn test_free_vars_match_patterns() {
+        let expr = parse("match p with | (a, _) -> a + b | z -> z").unwrap();
+        assert_eq!(free_vars(&expr), set(&["p", "b"]));
+    }
+
+    #[test]
+    fn test_fresh_name_unused_base() {
+        assert_eq!(fresh_name("x", &set(&["y"])), "x");
+    }
+
+    #[test]
+    fn test_fresh_name_skips_taken() {
+        assert_eq!(fresh_name("x", &set(&["x", "x_1"])), "x_2");
+    }
+
+    #[test]
+    fn test_substitute_simple() {
+        let expr = parse("x + 1").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(41));
+        assert_eq!(result, parse("41 + 1").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_multiple_occurrences() {
+        let expr = parse("if x then x else y").unwrap();
+        let result = substitute(&expr, "x", &Expr::Bool(true));
+        assert_eq!(result, parse("if true then true else y").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_shadowed_by_fun() {
+        let expr = parse("fun x -> x + 1").unwrap();
+        assert_eq!(substitute(&expr, "x", &Expr::Int(5)), expr);
+    }
+
+    #[test]
+    fn test_substitute_shadowed_by_let_body_only() {
+        // The value of the let still sees the outer x
+        let expr = parse("let x = x + 1 in x").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(5));
+        assert_eq!(result, parse("let x = 5 + 1 in x").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_shadowed_by_rec() {
+        let expr = parse("rec f -> fun n -> f n").unwrap();
+        assert_eq!(substitute(&expr, "f", &Expr::Int(0)), expr);
+    }
+
+    #[test]
+    fn test_substitute_shadowed_in_seq() {
+        let expr = parse("let a = x; let x = 2; a + x").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(1));
+        assert_eq!(result, parse("let a = 1; let x = 2; a + x").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_avoids_capture() {
+        // Substituting y for x in fun y -> x + y must rename the binder
+        let expr = parse("fun y -> x + y").unwrap();
+        let result = substitute(&expr, "x", &var("y"));
+        assert_eq!(result, parse("fun y_1 -> y + y_1").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_avoids_capture_in_let() {
+        let expr = parse("let y = 1 in x + y").unwrap();
+        let result = substitute(&expr, "x", &parse("y * 2").unwrap());
+        assert_eq!(result, parse("let y_1 = 1 in y * 2 + y_1").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_renamed_binder_avoids_existing_names() {
+        // y_1 is already used in the body, so the binder becomes y_2
+        let expr = parse("fun y -> x + y + y_1").unwrap();
+        let result = substitute(&expr, "x", &var("y"));
+        assert_eq!(result, parse("fun y_2 -> y + y_2 + y_1").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_no_rename_when_name_absent_in_scope() {
+        let expr = parse("fun y -> y").unwrap();
+        assert_eq!(substitute(&expr, "x", &var("y")), expr);
+    }
+
+    #[test]
+    fn test_substitute_under_match_arms() {
+        let expr = parse("match p with | (a, b) -> a + x | x -> x").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(7));
+        assert_eq!(
+            result,
+            parse("match p with | (a, b) -> a + 7 | x -> x").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_substitute_match_scrutinee() {
+        let expr = parse("match x with | n -> n").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(3));
+        assert_eq!(result, parse("match 3 with | n -> n").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_renames_pattern_binder() {
+        let expr = parse("match p with | (a, b) -> a + x").unwrap();
+        let result = substitute(&expr, "x", &var("a"));
+        assert_eq!(result, parse("match p with | (a_1, b) -> a_1 + a").unwrap());
+    }
+
+    #[test]
+    fn test_substitute_idempotent_when_name_absent() {
+        let expr = parse("let f = fun a -> a * 2 in f 21").unwrap();
+        let result = substitute(&expr, "missing", &Expr::Int(0));
+        assert_eq!(result, expr);
+        assert_eq!(substitute(&result, "missing", &Expr::Int(0)), expr);
+    }
+
+    #[test]
+    fn test_substitute_inside_compound_expressions() {
+        let expr = parse("(x, { a: x }, [|x|], Some x)").unwrap();
+        let result = substitute(&expr, "x", &Expr::Int(1));
+        assert_eq!(result, parse("(1, { a: 1 }, [|1|], Some 1)").unwrap());
+    }
+}
@@ -0,0 +1,135 @@
+//! Near-miss suggestions for misspelled names
+//!
+//! Unknown variables and constructors are usually typos of a name that is in
+//! scope (`lenght` for `length`, `Noen` for `None`) or of one of the keyword
+//! literals (`flase` for `false`). `suggest` ranks candidate names by edit
+//! distance and `hint` renders the result as a "did you mean" clause for error
+//! messages.
+
+/// Keywords that denote values; they can never be bound, so a near miss of one
+/// is reported as a literal rather than as a name
+pub const KEYWORD_LITERALS: &[&str] = &["true", "false"];
+
+/// Most suggestions returned for a single unknown name
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Rank `candidates` by similarity to `name`, closest first
+///
+/// Comparison is case-insensitive and counts insertions, deletions,
+/// substitutions and transpositions of adjacent characters. A candidate
+/// qualifies when its distance is at most a third of the name's length (and at
+/// least 1). Ties are broken alphabetically; duplicates and `name` itself are
+/// dropped.
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let target: Vec<char> = name.to_lowercase().chars().collect();
+    let threshold = (target.len() / 3).max(1);
+    let mut ranked: Vec<(usize, &str)> = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .filter_map(|candidate| {
+            let chars: Vec<char> = candidate.to_lowercase().chars().collect();
+            let distance = edit_distance(&target, &chars);
+            (distance <= threshold).then_some((distance, candidate))
+        })
+        .collect();
+    ranked.sort_unstable();
+    ranked.dedup_by(|a, b| a.1 == b.1);
+    ranked
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Render suggestions as a trailing hint, e.g. `` (did you mean `None`?)``
+///
+/// Keyword literals are called out as such. Returns an empty string when there
+/// is nothing to suggest, so the result can be appended unconditionally.
+pub fn hint(suggestions: &[String]) -> String {
+    let rendered: Vec<String> = suggestions
+        .iter()
+        .map(|suggestion| {
+            if KEYWORD_LITERALS.contains(&suggestion.as_str()) {
+                format!("the literal `{suggestion}`")
+            } else {
+                format!("`{suggestion}`")
+            }
+        })
+        .collect();
+    match rendered.as_slice() {
+        [] => String::new(),
+        [only] => format!(" (did you mean {only}?)"),
+        [init @ .., last] => format!(" (did you mean {} or {last}?)", init.join(", ")),
+    }
+}
+
+/// Optimal string alignment distance between two character sequences
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        edit_distance(&a, &b)
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("flase", "false"), 1);
+        assert_eq!(distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn test_suggest_ranks_and_filters() {
+        let names = ["length", "lengths", "map", "filter", "len"];
+        assert_eq!(suggest("lenght", names), vec!["length", "lengths"]);
+        assert_eq!(suggest("fitler", names), vec!["filter"]);
+        assert!(suggest("zzz", names).is_empty());
+    }
+
+    #[test]
+    fn test_suggest_is_case_insensitive() {
+        assert_eq!(suggest("Flase", KEYWORD_LITERALS.iter().copied()), vec!["false"]);
+        assert_eq!(suggest("none", ["None", "Some"]), vec!["None"]);
+    }
+
+    #[test]
+    fn test_hint_rendering() {
+        assert_eq!(hint(&[]), "");
+        assert_eq!(hint(&["None".to_string()]), " (did you mean `None`?)");
+        assert_eq!(hint(&["false".to_string()]), " (did you mean the literal `false`?)");
+        assert_eq!(
+            hint(&["a".to_string(), "b".to_string(), "c".to_string()]),
+            " (did you mean `a`, `b` or `c`?)"
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Recorded REPL sessions
+//!
+//! A transcript is a JSON-lines file that starts with a header line holding
+//! its format version (see [`crate::format`]), followed by one object per
+//! submitted input: the input text, whether it succeeded, and the response
+//! the REPL printed.
+//! `ReplSession` writes transcripts with `:record` and checks them with
+//! `:replay`. Where transcripts are stored is decided by a
+//! [`TranscriptStore`]: [`FileStore`] uses the filesystem and
+//! [`MemoryStore`] keeps everything in memory, which is what tests use.
+
+use crate::format::{check_version, migrate, FORMAT_FIELD, FORMAT_VERSION};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::rc::Rc;
+
+/// One submitted input and the response it produced
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    /// The input as submitted (trimmed)
+    pub input: String,
+    /// The printed response: `Ok` for results, `Err` for error messages
+    pub response: Result<String, String>,
+}
+
+impl TranscriptEntry {
+    /// Encode the entry as a single JSON object (no trailing newline)
+    pub fn to_json(&self) -> String {
+        let (ok, output) = match &self.response {
+            Ok(output) => (true, output),
+            Err(message) => (false, message),
+        };
+        format!(
+            "{{\"input\":{},\"ok\":{ok},\"output\":{}}}",
+            json_string(&self.input),
+            json_string(output)
+        )
+    }
+
+    /// Decode an entry written by `to_json`
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the line is not a JSON object with a string
+    /// `input`, a boolean `ok`, and a string `output`.
+    pub fn from_json(line: &str) -> Result<Self, String> {
+        let fields = parse_object(line)?;
+        let string = |name: &str| match fields.get(name) {
+            Some(JsonValue::String(s)) => Ok(s.clone()),
+            _ => Err(format!("missing string field `{name}`")),
+        };
+        let input = string("input")?;
+        let output = string("output")?;
+        let response = match fields.get("ok") {
+            Some(JsonValue::Bool(true)) => Ok(output),
+            Some(JsonValue::Bool(false)) => Err(output),
+            _ => return Err("missing boolean field `ok`".to_string()),
+        };
+        Ok(TranscriptEntry { input, response })
+    }
+}
+
+/// The first line of every transcript, recording the format it is written in
+pub fn transcript_header() -> String {
+    format!("{{\"{FORMAT_FIELD}\":{FORMAT_VERSION}}}")
+}
+
+/// Steps bringing the entries of an older transcript up to the current format
+///
+/// Transcripts written before versioning (version 0) have no header but
+/// otherwise the same entries.
+const MIGRATIONS: [fn(Vec<TranscriptEntry>) -> Vec<TranscriptEntry>; FORMAT_VERSION as usize] = [from_unversioned];
+
+/// Migrate version 0 entries, which need no change
+fn from_unversioned(entries: Vec<TranscriptEntry>) -> Vec<TranscriptEntry> {
+    entries
+}
+
+/// Render a response the way the REPL prints it
+pub(crate) fn render_response(response: &Result<String, String>) -> String {
+    match response {
+        Ok(output) => output.clone(),
+        Err(message) => format!("error: {message}"),
+    }
+}
+
+/// Where transcripts are written to and read from
+pub trait TranscriptStore: fmt::Debug {
+    /// Start an empty transcript at `path`, replacing any existing one
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the transcript cannot be created.
+    fn create(&self, path: &str) -> Result<(), String>;
+
+    /// Append one line to the transcript at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the line cannot be written.
+    fn append(&self, path: &str, line: &str) -> Result<(), String>;
+
+    /// Read the whole transcript at `path`
+    ///
+    /// # Errors
+    ///
+    /// Returns a message when the transcript does not exist or cannot be read.
+    fn read(&self, path: &str) -> Result<String, String>;
+}
+
+/// Transcripts stored as files, with paths relative to the current directory
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FileStore;
+
+impl TranscriptStore for FileStore {
+    fn create(&self, path: &str) -> Result<(), String> {
+        fs::write(path, "").map_err(|e| format!("Cannot create transcript '{path}': {e}"))
+    }
+
+    fn append(&self, path: &str, line: &str) -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open transcript '{path}': {e}"))?;
+        writeln!(file, "{line}").map_err(|e| format!("Cannot write transcript '{path}': {e}"))
+    }
+
+    fn read(&self, path: &str) -> Result<String, String> {
+        fs::read_to_string(path).map_err(|e| format!("Cannot read transcript '{path}': {e}"))
+    }
+}
+
+/// Transcripts kept in memory; clones share the same contents
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    files: Rc<RefCell<HashMap<String, String>>>,
+}
+
+impl MemoryStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contents of the transcript at `path`, if any
+    pub fn contents(&self, path: &str) -> Option<String> {
+        self.files.borrow().get(path).cloned()
+    }
+
+    /// Replace the transcript at `path`, e.g. with a hand-edited one
+    pub fn insert(&self, path: &str, contents: impl Into<String>) {
+        self.files.borrow_mut().insert(path.to_string(), contents.into());
+    }
+}
+
+impl TranscriptStore for MemoryStore {
+    fn create(&self, path: &str) -> Result<(), String> {
+        self.insert(path, "");
+        Ok(())
+    }
+
+    fn append(&self, path: &str, line: &str) -> Result<(), String> {
+        let mut files = self.files.borrow_mut();
+        let contents = files.entry(path.to_string()).or_default();
+        contents.push_str(line);
+        contents.push('\n');
+        Ok(())
+    }
+
+    fn read(&self, path: &str) -> Result<String, String> {
+        self.contents(path).ok_or_else(|| format!("Cannot read transcript '{path}': not found"))
+    }
+}
+
+/// Parse a whole transcript, skipping blank lines
+///
+/// The header line is optional, so transcripts recorded before versioning
+/// still load; their entries are migrated to the current format.
+///
+/// # Errors
+///
+/// Returns a message naming the first malformed line (1-based), or a
+/// [`FormatError`](crate::format::FormatError) message when the transcript was
+/// written by a newer parlang.
+pub fn parse_transcript(contents: &str) -> Result<Vec<TranscriptEntry>, String> {
+    let mut lines = contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).peekable();
+    let header = lines.peek().and_then(|(_, line)| parse_object(line).ok()).and_then(|mut fields| fields.remove(FORMAT_FIELD));
+    let version = match header {
+        Some(JsonValue::Number(version)) => {
+            lines.next();
+            Some(u32::try_from(version).unwrap_or(u32::MAX))
+        }
+        Some(_) => return Err(format!("Invalid transcript line 1: field `{FORMAT_FIELD}` must be a number")),
+        None => None,
+    };
+    let version = check_version("transcript", version).map_err(|e| e.to_string())?;
+    let entries = lines
+        .map(|(index, line)| {
+            TranscriptEntry::from_json(line).map_err(|e| format!("Invalid transcript line {}: {e}", index + 1))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(migrate(version, entries, &MIGRATIONS))
+}
+
+/// Encode a string as a JSON string literal
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Field values that can appear in a transcript line
+enum JsonValue {
+    String(String),
+    Bool(bool),
+    Number(u64),
+}
+
+/// Parse a flat JSON object whose values are strings, booleans or natural numbers
+fn parse_object(line: &str) -> Result<HashMap<String, JsonValue>, String> {
+    let mut chars = line.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    let skip_spaces = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    };
+    if chars.next() != Some('{') {
+        return Err("expected `{`".to_string());
+    }
+    skip_spaces(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_spaces(&mut chars);
+            let name = parse_string(&mut chars)?;
+            skip_spaces(&mut chars);
+            if chars.next() != Some(':') {
+                return Err(format!("expected `:` after field `{name}`"));
+            }
+            skip_spaces(&mut chars);
+            let value = match chars.peek() {
+                Some('"') => JsonValue::String(parse_string(&mut chars)?),
+                Some('t' | 'f') => {
+                    let word: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_alphabetic)).collect();
+                    match word.as_str() {
+                        "true" => JsonValue::Bool(true),
+                        "false" => JsonValue::Bool(false),
+                        _ => return Err(format!("unexpected value `{word}` for field `{name}`")),
+                    }
+                }
+                Some('0'..='9') => {
+                    let digits: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect();
+                    JsonValue::Number(digits.parse().map_err(|_| format!("number too large for field `{name}`"))?)
+                }
+                _ => return Err(format!("unsupported value for field `{name}`")),
+            };
+            fields.insert(name, value);
+            skip_spaces(&mut chars);
+            match chars.next() {
+                Some(',') => {}
+                Some('}') => break,
+                _ => return Err("expected `,` or `}`".to_string()),
+            }
+        }
+    }
+    skip_spaces(&mut chars);
+    match chars.next() {
+        None => Ok(fields),
+        Some(c) => Err(format!("unexpected `{c}` after object")),
+    }
+}
+
+/// Parse a JSON string literal, starting at its opening quote
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    if chars.next() != Some('"') {
+        return Err("expected a string".to_string());
+    }
+    let mut out = String::new();
+    loop {
+        match chars.next().ok_or("unterminated string")? {
+            '"' => return Ok(out),
+            '\\' => match chars.next().ok_or("unterminated escape")? {
+                '"' => out.push('"'),
+                '\\' => out.push('\\'),
+                '/' => out.push('/'),
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'b' => out.push('\u{8}'),
+                'f' => out.push('\u{c}'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape `\\u{hex}`"))?;
+                    out.push(char::from_u32(code).ok_or_else(|| format!("invalid escape `\\u{hex}`"))?);
+                }
+                c => return Err(format!("invalid escape `\\{c}`")),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_round_trip() {
+        let entries = [
+            TranscriptEntry { input: "let x = \"a\\b\";".to_string(), response: Ok("x = Cons('a', Nil)".to_string()) },
+            TranscriptEntry { input: "1 +".to_string(), response: Err("Parse error: line 1\n\tend".to_string()) },
+            TranscriptEntry { input: "'\u{1}' é".to_string(), response: Ok(String::new()) },
+        ];
+        for entry in entries {
+            let line = entry.to_json();
+            assert!(!line.contains('\n'));
+            assert_eq!(TranscriptEntry::from_json(&line), Ok(entry));
+        }
+    }
+
+    #[test]
+    fn test_from_json_accepts_reordered_fields_and_spacing() {
+        let entry = TranscriptEntry::from_json(r#" { "output" : "3", "ok": true , "input":"1 + 2" } "#).unwrap();
+        assert_eq!(entry, TranscriptEntry { input: "1 + 2".to_string(), response: Ok("3".to_string()) });
+    }
+
+    #[test]
+    fn test_malformed_lines() {
+        assert!(TranscriptEntry::from_json("{\"input\":\"x\",\"ok\":true}").is_err());
+        assert!(TranscriptEntry::from_json("{\"input\":\"x\",\"ok\":1,\"output\":\"\"}").is_err());
+        assert!(TranscriptEntry::from_json("[1]").is_err());
+        let err = parse_transcript("{\"input\":\"1\",\"ok\":true,\"output\":\"1\"}\n\nnot json\n").unwrap_err();
+        assert!(err.contains("line 3"), "{err}");
+    }
+
+    #[test]
+    fn test_current_version_round_trips() {
+        let entry = TranscriptEntry { input: "1 + 2".to_string(), response: Ok("3".to_string()) };
+        let contents = format!("{}\n{}\n", transcript_header(), entry.to_json());
+        assert_eq!(contents.lines().next(), Some("{\"parlang_format\":1}"));
+        assert_eq!(parse_transcript(&contents), Ok(vec![entry.clone()]));
+        // Transcripts recorded before the header existed load as version 0
+        assert_eq!(parse_transcript(&format!("{}\n", entry.to_json())), Ok(vec![entry]));
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let contents = format!("{{\"parlang_format\":{}}}\n{{\"input\":\"1\",\"ok\":true,\"output\":\"1\"}}\n", FORMAT_VERSION + 1);
+        let err = parse_transcript(&contents).unwrap_err();
+        assert!(err.starts_with(&format!("transcript has format version {}", FORMAT_VERSION + 1)), "{err}");
+        assert!(err.contains(&format!("up to {FORMAT_VERSION}")), "{err}");
+        assert!(parse_transcript("{\"parlang_format\":\"1\"}\n").unwrap_err().contains("must be a number"));
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let store = MemoryStore::new();
+        assert!(store.read("t.json").is_err());
+        store.append("t.json", "a").unwrap();
+        store.append("t.json", "b").unwrap();
+        assert_eq!(store.read("t.json"), Ok("a\nb\n".to_string()));
+        store.create("t.json").unwrap();
+        assert_eq!(store.contents("t.json"), Some(String::new()));
+    }
+}
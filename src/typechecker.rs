@@ -1,11 +1,22 @@
 /// Hindley-Milner type inference implementation
-use crate::ast::{BinOp, Expr};
+use crate::ast::{BinOp, Expr, Literal, Pattern};
+use crate::builtins::string_type;
+use crate::load_path::{LoadPath, LoadStack};
+use crate::numeric_rules::{self, ScalarKind};
+use crate::suggest::{self, suggest, KEYWORD_LITERALS};
+use crate::span::Span;
 use crate::types::{Type, TypeScheme, TypeVar, RowVar};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::rc::Rc;
 
 /// Sum type constructor information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ConstructorInfo {
     /// Type parameters (e.g., ["a", "b"] for Either a b)
     pub type_params: Vec<String>,
@@ -19,29 +30,179 @@ pub struct ConstructorInfo {
 #[derive(Debug, Clone)]
 pub struct TypeEnv {
     bindings: HashMap<String, TypeScheme>,
-    next_var: usize,
-    next_row_var: usize,
+    /// Fresh variable counters, shared by clones so that inference in a
+    /// cloned environment never hands out a number twice
+    next_var: Rc<Cell<usize>>,
+    next_row_var: Rc<Cell<usize>>,
     type_aliases: HashMap<String, Type>,
     /// Constructor information: maps constructor name to its type info
     constructors: HashMap<String, ConstructorInfo>,
+    /// Types of libraries already checked, shared by every environment
+    /// derived from this one (including library contexts)
+    libraries: Rc<RefCell<LibraryCache>>,
+    /// Where `load` looks for libraries; a library's context looks next to the library
+    load_path: Rc<LoadPath>,
+    /// Libraries being checked, shared like the cache to catch circular loads
+    loading: Rc<LoadStack>,
+    /// Where to log `==` and `!=` operand types, while `typecheck_equalities` runs
+    equalities: Option<Rc<RefCell<Vec<EqualityCheck>>>>,
+    /// Where to log the type inferred for each node, while `typecheck_nodes` runs
+    node_types: Option<Rc<RefCell<HashMap<*const Expr, Type>>>>,
+    /// Source spans of the nodes, while `typecheck_with_spans` runs
+    node_spans: Option<Rc<HashMap<*const Expr, Span>>>,
+}
+
+/// An `==` or `!=` comparison and the type of its operands
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqualityCheck {
+    /// The comparison expression
+    pub expr: Expr,
+    /// The type both operands were unified to
+    pub operand: Type,
+    /// Where the comparison is, when [`typecheck_equalities_with_spans`] was given the spans
+    pub span: Option<Span>,
+}
+
+/// What a loaded library adds to the program that loads it
+///
+/// Schemes are in canonical form, so the same library always yields the same
+/// `LibraryTypes` no matter which program loads it or what was checked before.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryTypes {
+    /// Top-level bindings with their generalized schemes, in binding order
+    pub schemes: Vec<(String, TypeScheme)>,
+    /// Constructors of the sum types the library defines or loads
+    pub constructors: Vec<(String, ConstructorInfo)>,
+    /// Type aliases the library defines or loads
+    pub aliases: Vec<(String, Type)>,
+}
+
+/// Library types keyed by path and a hash of the file contents
+#[derive(Debug, Default)]
+struct LibraryCache {
+    entries: HashMap<(String, u64), Rc<LibraryTypes>>,
+    hits: usize,
 }
 
 impl TypeEnv {
     pub fn new() -> Self {
         TypeEnv {
             bindings: HashMap::new(),
-            next_var: 0,
-            next_row_var: 0,
+            next_var: Rc::default(),
+            next_row_var: Rc::default(),
             type_aliases: HashMap::new(),
             constructors: HashMap::new(),
+            libraries: Rc::default(),
+            load_path: Rc::default(),
+            loading: Rc::default(),
+            equalities: None,
+            node_types: None,
+            node_spans: None,
+        }
+    }
+
+    /// Create a type environment with the schemes of the prelude built-ins and the prelude types
+    pub fn with_builtins() -> Self {
+        let mut env = Self::new();
+        for (name, scheme) in crate::builtins::prelude_schemes() {
+            env.bind(name, scheme);
+        }
+        for (name, type_params, constructors) in crate::builtins::prelude_types() {
+            register_type_def(&name, &type_params, &constructors, &mut env);
         }
+        // Built-in schemes quantify over low-numbered variables; start fresh ones above them
+        let next_var = env
+            .bindings
+            .values()
+            .flat_map(|scheme| scheme.vars.iter().map(|var| var.0 + 1))
+            .max()
+            .unwrap_or(0);
+        let next_row_var = env
+            .bindings
+            .values()
+            .flat_map(|scheme| scheme.row_vars.iter().map(|var| var.0 + 1))
+            .max()
+            .unwrap_or(0);
+        env.next_var.set(next_var);
+        env.next_row_var.set(next_row_var);
+        env
+    }
+
+    /// Build an environment from its parts, e.g. one taken apart with
+    /// [`TypeEnv::bindings`], [`TypeEnv::type_aliases`],
+    /// [`TypeEnv::constructors`] and the counter accessors
+    ///
+    /// The counters are raised above any variable that occurs in `bindings`
+    /// or `aliases`, so fresh variables never collide with the given types.
+    pub fn from_parts(
+        bindings: HashMap<String, TypeScheme>,
+        aliases: HashMap<String, Type>,
+        constructors: HashMap<String, ConstructorInfo>,
+        next_var: usize,
+        next_row_var: usize,
+    ) -> Self {
+        let types = || bindings.values().map(|scheme| &scheme.ty).chain(aliases.values());
+        let next_var = types()
+            .flat_map(free_type_vars)
+            .map(|var| var.0 + 1)
+            .fold(next_var, usize::max);
+        let next_row_var = types()
+            .flat_map(free_row_vars)
+            .map(|var| var.0 + 1)
+            .fold(next_row_var, usize::max);
+        let env = TypeEnv {
+            bindings,
+            type_aliases: aliases,
+            constructors,
+            ..Self::new()
+        };
+        env.next_var.set(next_var);
+        env.next_row_var.set(next_row_var);
+        env
+    }
+
+    /// Number of the next fresh type variable
+    pub fn next_var(&self) -> usize {
+        self.next_var.get()
+    }
+
+    /// Number of the next fresh row variable
+    pub fn next_row_var(&self) -> usize {
+        self.next_row_var.get()
     }
 
     /// Generate a fresh type variable
     pub fn fresh_var(&mut self) -> Type {
-        let var = Type::Var(TypeVar(self.next_var));
-        self.next_var += 1;
-        var
+        Type::Var(self.fresh_type_var())
+    }
+
+    /// Generate a fresh type variable, both as a type and as its identifier
+    pub fn fresh_var_pair(&mut self) -> (Type, TypeVar) {
+        let var = self.fresh_type_var();
+        (Type::Var(var.clone()), var)
+    }
+
+    /// Allocate `n` type variable numbers at once
+    ///
+    /// The environment never hands out a number in the returned range, so
+    /// callers can build `TypeVar`s from it themselves.
+    pub fn reserve_vars(&mut self, n: usize) -> Range<usize> {
+        let start = self.next_var.get();
+        self.next_var.set(start + n);
+        start..start + n
+    }
+
+    /// Allocate `n` row variable numbers at once, like [`TypeEnv::reserve_vars`]
+    pub fn reserve_row_vars(&mut self, n: usize) -> Range<usize> {
+        let start = self.next_row_var.get();
+        self.next_row_var.set(start + n);
+        start..start + n
+    }
+
+    fn fresh_type_var(&mut self) -> TypeVar {
+        let var = self.next_var.get();
+        self.next_var.set(var + 1);
+        TypeVar(var)
     }
 
     /// Generate a fresh row variable
@@ -57,9 +218,9 @@ impl TypeEnv {
     /// // Type: { age: t0 | r0 } -> t0
     /// ```
     pub fn fresh_row_var(&mut self) -> RowVar {
-        let row_var = RowVar(self.next_row_var);
-        self.next_row_var += 1;
-        row_var
+        let row_var = self.next_row_var.get();
+        self.next_row_var.set(row_var + 1);
+        RowVar(row_var)
     }
 
     /// Look up a variable and instantiate its type scheme
@@ -73,6 +234,11 @@ impl TypeEnv {
         self.bindings.insert(name, scheme);
     }
 
+    /// Remove the binding of a variable, if any
+    pub fn unbind(&mut self, name: &str) {
+        self.bindings.remove(name);
+    }
+
     /// Extend environment with a monomorphic binding
     pub fn extend(&self, name: String, ty: Type) -> Self {
         let mut new_env = self.clone();
@@ -86,21 +252,61 @@ impl TypeEnv {
             return scheme.ty.clone();
         }
 
-        let mut subst = HashMap::new();
+        // Rename simultaneously: a quantified variable may share its number
+        // with a fresh one, so the substitution must not be applied in chains
+        let mut var_map = HashMap::new();
         for var in &scheme.vars {
-            subst.insert(var.clone(), self.fresh_var());
+            var_map.insert(var.clone(), self.fresh_type_var());
         }
-        
-        let mut row_subst = HashMap::new();
+
+        let mut row_map = HashMap::new();
         for row_var in &scheme.row_vars {
-            row_subst.insert(row_var.clone(), Type::Row(self.fresh_row_var()));
+            row_map.insert(row_var.clone(), self.fresh_row_var());
+        }
+
+        rename_vars(&scheme.ty, &var_map, &row_map)
+    }
+
+    /// Bind already generalized schemes, e.g. the exports of a library
+    ///
+    /// Quantified variables are replaced by fresh ones on every lookup, so
+    /// schemes in canonical form can be imported into any environment.
+    pub fn import_schemes(&mut self, schemes: Vec<(String, TypeScheme)>) {
+        for (name, scheme) in schemes {
+            self.bind(name, scheme);
         }
-        
-        let ty_after_subst = apply_subst(&subst, &scheme.ty);
-        apply_row_subst(&row_subst, &ty_after_subst)
+    }
+
+    /// Look up the libraries named by `load` as `load_path` says, as
+    /// [`Environment::set_load_path`](crate::Environment::set_load_path) does at runtime
+    pub fn set_load_path(&mut self, load_path: LoadPath) {
+        self.load_path = Rc::new(load_path);
+    }
+
+    /// Where `load` looks for libraries
+    pub fn load_path(&self) -> &LoadPath {
+        &self.load_path
+    }
+
+    /// Number of `load`s whose library types came from the cache
+    ///
+    /// The cache is shared by every environment cloned or derived from this
+    /// one, so a library loaded several times in one run is checked once.
+    pub fn library_cache_hits(&self) -> usize {
+        self.libraries.borrow().hits
+    }
+
+    /// Types of the most recently checked version of the library `load` found at `path`
+    pub fn loaded_library(&self, path: &str) -> Option<Rc<LibraryTypes>> {
+        let cache = self.libraries.borrow();
+        cache.entries.iter().find(|((key, _), _)| key == path).map(|(_, types)| Rc::clone(types))
     }
 
     /// Generalize a type by quantifying free type variables and row variables
+    ///
+    /// The quantified variables are renumbered canonically (see
+    /// [`canonicalize`]), so the resulting scheme does not depend on how many
+    /// fresh variables were created before.
     pub fn generalize(&self, ty: &Type) -> TypeScheme {
         let free_in_env = self.free_vars();
         let free_in_type = free_type_vars(ty);
@@ -120,11 +326,11 @@ impl TypeEnv {
             .collect();
         quantified_rows.sort();
 
-        TypeScheme {
+        canonicalize(&TypeScheme {
             vars: quantified,
             row_vars: quantified_rows,
             ty: ty.clone(),
-        }
+        })
     }
 
     /// Get free type variables in the environment
@@ -160,6 +366,15 @@ impl TypeEnv {
         self.type_aliases.insert(name, ty);
     }
 
+    /// Declare an opaque host type, the static side of `Value::opaque`
+    ///
+    /// Afterwards `tag` can be used in type annotations, and schemes bound
+    /// with `bind` can mention `Type::Opaque(tag)` for host built-ins that
+    /// create or consume such values.
+    pub fn declare_opaque(&mut self, tag: &str) {
+        self.define_type_alias(tag.to_string(), Type::Opaque(tag.to_string()));
+    }
+
     /// Resolve a type alias by name
     pub fn resolve_type_alias(&self, name: &str) -> Option<Type> {
         self.type_aliases.get(name).cloned()
@@ -178,6 +393,48 @@ impl TypeEnv {
     pub fn lookup_constructor(&self, name: &str) -> Option<&ConstructorInfo> {
         self.constructors.get(name)
     }
+
+    /// Number of type parameters a registered sum type declares
+    pub fn sum_type_arity(&self, name: &str) -> Option<usize> {
+        self.constructors
+            .values()
+            .find(|info| info.sum_type_name == name)
+            .map(|info| info.type_params.len())
+    }
+
+    /// All registered constructors with their information
+    pub fn constructors(&self) -> impl Iterator<Item = (&String, &ConstructorInfo)> {
+        self.constructors.iter()
+    }
+
+    /// All bound names with their schemes
+    pub fn bindings(&self) -> impl Iterator<Item = (&String, &TypeScheme)> {
+        self.bindings.iter()
+    }
+
+    /// All defined type aliases
+    pub fn type_aliases(&self) -> impl Iterator<Item = (&String, &Type)> {
+        self.type_aliases.iter()
+    }
+
+    /// Error for an unknown lowercase name, suggesting bound variables and
+    /// keyword literals that are close to it
+    fn unbound_variable(&self, name: &str) -> TypeError {
+        let candidates = self.bindings.keys().map(String::as_str);
+        let suggestions = suggest(name, candidates.chain(KEYWORD_LITERALS.iter().copied()));
+        TypeError::UnboundVariable(name.to_string(), suggestions)
+    }
+
+    /// Error for an unknown capitalized name, suggesting registered
+    /// constructors first, then bound variables and keyword literals
+    fn unknown_constructor(&self, name: &str) -> TypeError {
+        let mut suggestions = suggest(name, self.constructors.keys().map(String::as_str));
+        if suggestions.is_empty() {
+            let candidates = self.bindings.keys().map(String::as_str);
+            suggestions = suggest(name, candidates.chain(KEYWORD_LITERALS.iter().copied()));
+        }
+        TypeError::UnknownConstructor(name.to_string(), suggestions)
+    }
 }
 
 impl Default for TypeEnv {
@@ -186,8 +443,33 @@ impl Default for TypeEnv {
     }
 }
 
-/// Substitution map from type variables to types
-type Substitution = HashMap<TypeVar, Type>;
+/// Substitution of types for type variables and of rows for row variables
+///
+/// A row variable is bound to the fields it stands for: a `Type::Record`
+/// when no further fields can be added, or a `Type::RecordRow` ending in
+/// another row variable when they can.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Substitution {
+    types: HashMap<TypeVar, Type>,
+    rows: HashMap<RowVar, Type>,
+}
+
+impl Substitution {
+    /// The empty substitution
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Substitute `ty` for `var`
+    fn of_type(var: TypeVar, ty: Type) -> Self {
+        Substitution { types: HashMap::from([(var, ty)]), rows: HashMap::new() }
+    }
+
+    /// Substitute the fields of `row` for `var`
+    fn of_row(var: RowVar, row: Type) -> Self {
+        Substitution { types: HashMap::new(), rows: HashMap::from([(var, row)]) }
+    }
+}
 
 /// Apply type substitution to a type
 /// 
@@ -202,7 +484,7 @@ type Substitution = HashMap<TypeVar, Type>;
 /// # Returns
 /// A new type with all substitutable type variables replaced
 fn apply_subst(subst: &Substitution, ty: &Type) -> Type {
-    apply_subst_with_visited(subst, ty, &mut HashSet::new())
+    apply_subst_with_visited(subst, ty, &mut HashSet::new(), &mut HashSet::new())
 }
 
 /// Apply substitution to a type with cycle detection
@@ -210,17 +492,18 @@ fn apply_subst_with_visited(
     subst: &Substitution,
     ty: &Type,
     visited: &mut HashSet<TypeVar>,
+    visited_rows: &mut HashSet<RowVar>,
 ) -> Type {
     match ty {
-        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range => ty.clone(),
+        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range | Type::Opaque(_) => ty.clone(),
         Type::Var(v) => {
             if visited.contains(v) {
                 // Cycle detected, return the variable as-is
                 return ty.clone();
             }
-            if let Some(t) = subst.get(v) {
+            if let Some(t) = subst.types.get(v) {
                 visited.insert(v.clone());
-                let result = apply_subst_with_visited(subst, t, visited);
+                let result = apply_subst_with_visited(subst, t, visited, visited_rows);
                 visited.remove(v);
                 result
             } else {
@@ -228,15 +511,15 @@ fn apply_subst_with_visited(
             }
         }
         Type::Fun(arg, ret) => Type::Fun(
-            Box::new(apply_subst_with_visited(subst, arg, visited)),
-            Box::new(apply_subst_with_visited(subst, ret, visited)),
+            Box::new(apply_subst_with_visited(subst, arg, visited, visited_rows)),
+            Box::new(apply_subst_with_visited(subst, ret, visited, visited_rows)),
         ),
         Type::Record(fields) => {
             let mut new_fields = HashMap::new();
             for (name, ty) in fields {
                 new_fields.insert(
                     name.clone(),
-                    apply_subst_with_visited(subst, ty, visited),
+                    apply_subst_with_visited(subst, ty, visited, visited_rows),
                 );
             }
             Type::Record(new_fields)
@@ -246,114 +529,68 @@ fn apply_subst_with_visited(
             for (name, ty) in fields {
                 new_fields.insert(
                     name.clone(),
-                    apply_subst_with_visited(subst, ty, visited),
+                    apply_subst_with_visited(subst, ty, visited, visited_rows),
                 );
             }
-            Type::RecordRow(new_fields, row_var.clone())
+            // Add the fields the row variable stands for
+            match apply_row_var(subst, row_var, visited, visited_rows) {
+                Type::Record(rest) => {
+                    new_fields.extend(rest);
+                    Type::Record(new_fields)
+                }
+                Type::RecordRow(rest, tail) => {
+                    new_fields.extend(rest);
+                    Type::RecordRow(new_fields, tail)
+                }
+                _ => Type::RecordRow(new_fields, row_var.clone()),
+            }
         }
-        Type::Row(row_var) => Type::Row(row_var.clone()),
+        Type::Row(row_var) => match apply_row_var(subst, row_var, visited, visited_rows) {
+            Type::RecordRow(rest, tail) if rest.is_empty() => Type::Row(tail),
+            row => row,
+        },
         Type::SumType(name, args) => {
             let new_args = args
                 .iter()
-                .map(|arg| apply_subst_with_visited(subst, arg, visited))
+                .map(|arg| apply_subst_with_visited(subst, arg, visited, visited_rows))
                 .collect();
             Type::SumType(name.clone(), new_args)
         }
+        Type::Tuple(elements) => Type::Tuple(
+            elements
+                .iter()
+                .map(|element| apply_subst_with_visited(subst, element, visited, visited_rows))
+                .collect(),
+        ),
         Type::Array(elem_ty, size) => {
-            let new_elem_ty = apply_subst_with_visited(subst, elem_ty, visited);
+            let new_elem_ty = apply_subst_with_visited(subst, elem_ty, visited, visited_rows);
             Type::Array(Box::new(new_elem_ty), *size)
         }
         Type::Ref(inner_ty) => {
-            let new_inner_ty = apply_subst_with_visited(subst, inner_ty, visited);
+            let new_inner_ty = apply_subst_with_visited(subst, inner_ty, visited, visited_rows);
             Type::Ref(Box::new(new_inner_ty))
         }
     }
 }
 
-/// Row substitution (maps RowVar to Type)
-/// 
-/// Row substitutions map row variables to concrete types, allowing us to
-/// resolve row polymorphic types to concrete record types during unification.
-type RowSubstitution = HashMap<RowVar, Type>;
-
-/// Apply row substitution to a type
-/// 
-/// This function applies row variable substitutions to types, which is essential
-/// for row polymorphism. When we have a type like `{ age: Int | r0 }` and a
-/// substitution `r0 -> { name: Int }`, we can merge them to get
-/// `{ age: Int, name: Int }`.
-/// 
-/// # Arguments
-/// * `subst` - The row substitution mapping row variables to types
-/// * `ty` - The type to apply the substitution to
-/// 
-/// # Returns
-/// The type with row variables substituted
-fn apply_row_subst(subst: &RowSubstitution, ty: &Type) -> Type {
-    match ty {
-        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Var(_) | Type::Range => ty.clone(),
-        Type::Fun(arg, ret) => Type::Fun(
-            Box::new(apply_row_subst(subst, arg)),
-            Box::new(apply_row_subst(subst, ret)),
-        ),
-        Type::Record(fields) => {
-            let mut new_fields = HashMap::new();
-            for (name, field_ty) in fields {
-                new_fields.insert(name.clone(), apply_row_subst(subst, field_ty));
-            }
-            Type::Record(new_fields)
-        }
-        Type::RecordRow(fields, row_var) => {
-            let mut new_fields = HashMap::new();
-            for (name, field_ty) in fields {
-                new_fields.insert(name.clone(), apply_row_subst(subst, field_ty));
-            }
-            // If there's a substitution for this row variable, apply it
-            if let Some(row_ty) = subst.get(row_var) {
-                // Merge fields with the substituted row
-                match row_ty {
-                    Type::Record(row_fields) => {
-                        // Merge new_fields with row_fields
-                        let mut merged = row_fields.clone();
-                        merged.extend(new_fields);
-                        Type::Record(merged)
-                    }
-                    Type::RecordRow(row_fields, new_row_var) => {
-                        // Merge new_fields with row_fields, keeping the new row variable
-                        let mut merged = row_fields.clone();
-                        merged.extend(new_fields);
-                        Type::RecordRow(merged, new_row_var.clone())
-                    }
-                    Type::Row(new_row_var) => {
-                        // Keep the fields, replace the row variable
-                        Type::RecordRow(new_fields, new_row_var.clone())
-                    }
-                    _ => Type::RecordRow(new_fields, row_var.clone()),
-                }
-            } else {
-                Type::RecordRow(new_fields, row_var.clone())
-            }
-        }
-        Type::Row(row_var) => {
-            // If there's a substitution for this row variable, use it
-            if let Some(row_ty) = subst.get(row_var) {
-                row_ty.clone()
-            } else {
-                ty.clone()
-            }
-        }
-        Type::SumType(name, args) => {
-            let new_args = args.iter().map(|arg| apply_row_subst(subst, arg)).collect();
-            Type::SumType(name.clone(), new_args)
-        }
-        Type::Array(elem_ty, size) => {
-            let new_elem_ty = apply_row_subst(subst, elem_ty);
-            Type::Array(Box::new(new_elem_ty), *size)
-        }
-        Type::Ref(inner_ty) => {
-            let new_inner_ty = apply_row_subst(subst, inner_ty);
-            Type::Ref(Box::new(new_inner_ty))
-        }
+/// The fields a row variable stands for under `subst`
+///
+/// Returns `Type::RecordRow` with no fields and the variable itself when it
+/// is unbound.
+fn apply_row_var(
+    subst: &Substitution,
+    row_var: &RowVar,
+    visited: &mut HashSet<TypeVar>,
+    visited_rows: &mut HashSet<RowVar>,
+) -> Type {
+    match subst.rows.get(row_var) {
+        Some(row) if !visited_rows.contains(row_var) => {
+            visited_rows.insert(row_var.clone());
+            let result = apply_subst_with_visited(subst, row, visited, visited_rows);
+            visited_rows.remove(row_var);
+            result
+        }
+        _ => Type::RecordRow(HashMap::new(), row_var.clone()),
     }
 }
 
@@ -375,7 +612,7 @@ fn apply_row_subst(subst: &RowSubstitution, ty: &Type) -> Type {
 /// - For `{ age: t0 }`: returns `{t0}`
 fn free_type_vars(ty: &Type) -> HashSet<TypeVar> {
     match ty {
-        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range => HashSet::new(),
+        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range | Type::Opaque(_) => HashSet::new(),
         Type::Var(v) => {
             let mut set = HashSet::new();
             set.insert(v.clone());
@@ -401,7 +638,7 @@ fn free_type_vars(ty: &Type) -> HashSet<TypeVar> {
             set
         }
         Type::Row(_) => HashSet::new(),
-        Type::SumType(_name, args) => {
+        Type::SumType(_, args) | Type::Tuple(args) => {
             let mut set = HashSet::new();
             for arg in args {
                 set.extend(free_type_vars(arg));
@@ -427,7 +664,7 @@ fn free_type_vars(ty: &Type) -> HashSet<TypeVar> {
 /// For the type `forall r0. { age: Int | r0 }`, after instantiation r0 is bound.
 fn free_row_vars(ty: &Type) -> HashSet<RowVar> {
     match ty {
-        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Var(_) | Type::Record(_) | Type::Range => HashSet::new(),
+        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Var(_) | Type::Record(_) | Type::Range | Type::Opaque(_) => HashSet::new(),
         Type::RecordRow(fields, row_var) => {
             let mut set = HashSet::new();
             set.insert(row_var.clone());
@@ -446,7 +683,7 @@ fn free_row_vars(ty: &Type) -> HashSet<RowVar> {
             set.extend(free_row_vars(ret));
             set
         }
-        Type::SumType(_name, args) => {
+        Type::SumType(_, args) | Type::Tuple(args) => {
             let mut set = HashSet::new();
             for arg in args {
                 set.extend(free_row_vars(arg));
@@ -510,10 +747,12 @@ fn type_annotation_to_type(
 /// Type checking errors
 #[derive(Debug, Clone, PartialEq)]
 pub enum TypeError {
-    UnboundVariable(String),
-    UnificationError(Type, Type),
+    /// Variable not in scope: name, near-miss suggestions
+    UnboundVariable(String, Vec<String>),
+    UnificationError(Box<Type>, Box<Type>),
     OccursCheckFailed(TypeVar, Type),
-    RecursionRequiresAnnotation,
+    /// Recursive function calls itself at types other than its own: function name
+    RecursionRequiresAnnotation(String),
     /// Field not found in record type: field name, available fields
     FieldNotFound(String, Vec<String>),
     /// Expected record type but got something else
@@ -522,13 +761,35 @@ pub enum TypeError {
     RecordFieldMismatch,
     /// Constructor applied with wrong number of arguments: constructor name, expected, actual
     ConstructorArityMismatch(String, usize, usize),
+    /// Type applied to the wrong number of arguments in an annotation: type name, expected, actual
+    TypeArityMismatch(String, usize, usize),
+    /// Constructor not registered: name, near-miss suggestions
+    UnknownConstructor(String, Vec<String>),
+    /// Library of a `load` could not be read or parsed: message
+    LoadError(String),
+    /// Type error inside a loaded library: library path, error
+    InLibrary(String, Box<TypeError>),
+    /// Integer literal where a Float is required: the literal
+    IntLiteralForFloat(i64),
+    /// Tuple types of different lengths during unification: left arity, right arity
+    TupleArityMismatch(usize, usize),
+    /// Tuple projection past the last element: index, tuple arity
+    TupleIndexOutOfRange(usize, usize),
+    /// Expected tuple type but got something else
+    TupleExpected(String),
+    /// Type error in an arm of a `match`: arm number (from 1), pattern, error
+    InMatchArm(usize, String, Box<TypeError>),
+    /// Type error raised within a node with a known source span: the span,
+    /// error. Only [`typecheck_with_spans`] adds it, around the innermost
+    /// such node
+    At(Span, Box<TypeError>),
 }
 
 impl fmt::Display for TypeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            TypeError::UnboundVariable(name) => {
-                write!(f, "Unbound variable: {name}")
+            TypeError::UnboundVariable(name, suggestions) => {
+                write!(f, "Unbound variable: {name}{}", suggest::hint(suggestions))
             }
             TypeError::UnificationError(t1, t2) => {
                 write!(f, "Cannot unify types: {t1} and {t2}")
@@ -536,9 +797,11 @@ impl fmt::Display for TypeError {
             TypeError::OccursCheckFailed(var, ty) => {
                 write!(f, "Occurs check failed: t{} occurs in {ty}", var.0)
             }
-            TypeError::RecursionRequiresAnnotation => {
-                write!(f, "Recursive functions require type annotations")
-            }
+            TypeError::RecursionRequiresAnnotation(name) => write!(
+                f,
+                "Recursive function '{name}' calls itself at a type other than its own, which needs a type annotation: \
+                 let {name} : <type> = rec {name} -> ..."
+            ),
             TypeError::FieldNotFound(field, available) => {
                 write!(f, "Field '{field}' not found. Available fields: {available:?}")
             }
@@ -551,19 +814,63 @@ impl fmt::Display for TypeError {
             TypeError::ConstructorArityMismatch(name, expected, actual) => {
                 write!(f, "Constructor '{name}' expects {expected} arguments, but got {actual}")
             }
+            TypeError::TypeArityMismatch(name, expected, actual) => {
+                write!(f, "Type '{name}' expects {expected} type arguments, but got {actual}")
+            }
+            TypeError::UnknownConstructor(name, suggestions) => {
+                write!(f, "Unknown constructor: {name}{}", suggest::hint(suggestions))
+            }
+            TypeError::LoadError(message) => write!(f, "Load error: {message}"),
+            TypeError::InLibrary(path, error) => write!(f, "In library '{path}': {error}"),
+            TypeError::IntLiteralForFloat(n) => {
+                write!(f, "integer literal {n} used where Float is expected; write {n}.0")
+            }
+            TypeError::TupleArityMismatch(left, right) => {
+                write!(f, "Cannot unify a tuple of {left} elements with a tuple of {right} elements")
+            }
+            TypeError::TupleIndexOutOfRange(index, arity) => {
+                write!(f, "Tuple index {index} out of range for a tuple of {arity} elements")
+            }
+            TypeError::TupleExpected(got) => write!(f, "Expected tuple type, got {got}"),
+            TypeError::InMatchArm(arm, pattern, error) => write!(f, "In match arm {arm} ({pattern}): {error}"),
+            TypeError::At(_, error) => write!(f, "{error}"),
         }
     }
 }
 
 impl std::error::Error for TypeError {}
 
+impl TypeError {
+    /// Where in the source of the checked program the error was raised, if known
+    ///
+    /// An error inside a loaded library has no span of its own; it is
+    /// reported at the `load`.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            TypeError::At(span, _) => Some(*span),
+            TypeError::InMatchArm(_, _, error) => error.span(),
+            _ => None,
+        }
+    }
+
+    /// The error without the span added around it
+    pub fn without_span(&self) -> &TypeError {
+        match self {
+            TypeError::At(_, error) => error.without_span(),
+            error => error,
+        }
+    }
+}
+
 /// Unification algorithm
 fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
     match (t1, t2) {
-        (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Char, Type::Char) | (Type::Float, Type::Float) | (Type::Byte, Type::Byte) | (Type::Unit, Type::Unit) | (Type::Range, Type::Range) => Ok(HashMap::new()),
+        (Type::Int, Type::Int) | (Type::Bool, Type::Bool) | (Type::Char, Type::Char) | (Type::Float, Type::Float) | (Type::Byte, Type::Byte) | (Type::Unit, Type::Unit) | (Type::Range, Type::Range) => Ok(Substitution::new()),
 
         (Type::Var(v), t) | (t, Type::Var(v)) => bind_var(v.clone(), t.clone()),
 
+        (Type::Opaque(tag1), Type::Opaque(tag2)) if tag1 == tag2 => Ok(Substitution::new()),
+
         (Type::Fun(a1, r1), Type::Fun(a2, r2)) => {
             let s1 = unify(a1, a2)?;
             let r1_subst = apply_subst(&s1, r1);
@@ -578,7 +885,7 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
                 return Err(TypeError::RecordFieldMismatch);
             }
             
-            let mut subst = HashMap::new();
+            let mut subst = Substitution::new();
             
             for (name, ty1) in fields1 {
                 match fields2.get(name) {
@@ -603,7 +910,7 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
         (Type::Record(fields), Type::RecordRow(row_fields, row_var))
         | (Type::RecordRow(row_fields, row_var), Type::Record(fields)) => {
             // The closed record must have at least the fields in row_fields
-            let mut subst = HashMap::new();
+            let mut subst = Substitution::new();
             
             // Unify the common fields
             for (name, row_ty) in row_fields {
@@ -621,45 +928,22 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
                 }
             }
             
-            // The row variable should represent the remaining fields
-            let mut remaining = fields.clone();
-            for name in row_fields.keys() {
-                remaining.remove(name);
-            }
-            
-            // Bind the row variable to the remaining fields
-            let mut row_subst = HashMap::new();
-            row_subst.insert(row_var.clone(), Type::Record(remaining));
-            
-            // Compose the substitutions - we need to convert row_subst to regular subst
-            // For now, we'll just return the type substitution
-            Ok(subst)
+            // The row variable stands for the remaining fields, and no others
+            let remaining = fields
+                .iter()
+                .filter(|(name, _)| !row_fields.contains_key(*name))
+                .map(|(name, ty)| (name.clone(), apply_subst(&subst, ty)))
+                .collect();
+            let s = bind_row_var(row_var.clone(), Type::Record(remaining)).ok_or(TypeError::RecordFieldMismatch)?;
+            Ok(compose_subst(&s, &subst))
         }
 
         // Unify two row-polymorphic records
         // This handles cases like: { x: Int | r0 } ~ { y: Int | r1 }
         // We need to unify common fields and handle the row variables appropriately
         (Type::RecordRow(fields1, row1), Type::RecordRow(fields2, row2)) => {
-            // Find common fields and unify them
-            let mut subst = HashMap::new();
-            let mut fields1_only = HashMap::new();
-            let mut fields2_only = HashMap::new();
-            
-            // Collect fields only in fields1
-            for (name, ty) in fields1 {
-                if !fields2.contains_key(name) {
-                    fields1_only.insert(name.clone(), ty.clone());
-                }
-            }
-            
-            // Collect fields only in fields2
-            for (name, ty) in fields2 {
-                if !fields1.contains_key(name) {
-                    fields2_only.insert(name.clone(), ty.clone());
-                }
-            }
-            
             // Unify common fields
+            let mut subst = Substitution::new();
             for (name, ty1) in fields1 {
                 if let Some(ty2) = fields2.get(name) {
                     let ty1 = apply_subst(&subst, ty1);
@@ -668,68 +952,58 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
                     subst = compose_subst(&s, &subst);
                 }
             }
-            
-            // Now we need to handle the row variables
-            // row1 should contain fields2_only + some rest
-            // row2 should contain fields1_only + some rest
-            // For simplicity, if both have the same row variable, they unify
-            if row1 == row2 {
-                Ok(subst)
-            } else {
-                // More complex case: bind one row variable to include the other's unique fields
-                // For now, we'll bind row1 to contain fields2_only and row2
-                if !fields2_only.is_empty() {
-                    // Can't easily represent this with current substitution system
-                    // This would require row variable constraints which is complex
-                    // For now, require exact match
-                    Err(TypeError::RecordFieldMismatch)
-                } else if !fields1_only.is_empty() {
-                    Err(TypeError::RecordFieldMismatch)
-                } else {
-                    // No unique fields on either side, just unify the row variables
-                    // by binding row1 to row2
-                    Ok(subst)
-                }
-            }
-        }
 
-        // Unify Row with Row
-        (Type::Row(r1), Type::Row(r2)) => {
-            if r1 == r2 {
-                Ok(HashMap::new())
-            } else {
-                // Row variables can be unified, but we don't have row substitution in our
-                // regular substitution. For now, we'll accept them as compatible.
-                Ok(HashMap::new())
-            }
-        }
+            // Fields only one side names must come from the other side's row variable
+            let only = |fields: &HashMap<String, Type>, other: &HashMap<String, Type>| -> HashMap<String, Type> {
+                fields
+                    .iter()
+                    .filter(|(name, _)| !other.contains_key(*name))
+                    .map(|(name, ty)| (name.clone(), apply_subst(&subst, ty)))
+                    .collect()
+            };
+            let fields1_only = only(fields1, fields2);
+            let fields2_only = only(fields2, fields1);
 
-        // Unify Row with Record or RecordRow
-        (Type::Row(_row), Type::Record(_fields)) |
-        (Type::Record(_fields), Type::Row(_row)) => {
-            // A row variable can unify with a closed record
-            // This would bind the row variable to that record
-            Ok(HashMap::new())
+            let s = if row1 == row2 {
+                // The same rest of the record: both must name the same fields
+                if !fields1_only.is_empty() || !fields2_only.is_empty() {
+                    return Err(TypeError::RecordFieldMismatch);
+                }
+                Substitution::new()
+            } else if fields2_only.is_empty() {
+                Substitution::of_row(row2.clone(), Type::RecordRow(fields1_only, row1.clone()))
+            } else if fields1_only.is_empty() {
+                Substitution::of_row(row1.clone(), Type::RecordRow(fields2_only, row2.clone()))
+            } else {
+                // Both rows would need a fresh row variable for their common
+                // rest, which unification has no way to create
+                return Err(TypeError::RecordFieldMismatch);
+            };
+            Ok(compose_subst(&s, &subst))
         }
 
-        (Type::Row(_row), Type::RecordRow(_fields, _row_var)) |
-        (Type::RecordRow(_fields, _row_var), Type::Row(_row)) => {
-            // A row variable can unify with a row-polymorphic record
-            Ok(HashMap::new())
+        // A bare row variable stands for the fields of a record
+        (Type::Row(row), other @ (Type::Row(_) | Type::Record(_) | Type::RecordRow(_, _)))
+        | (other @ (Type::Record(_) | Type::RecordRow(_, _)), Type::Row(row)) => {
+            let fields = match other {
+                Type::Row(other_row) => Type::RecordRow(HashMap::new(), other_row.clone()),
+                fields => fields.clone(),
+            };
+            bind_row_var(row.clone(), fields).ok_or(TypeError::RecordFieldMismatch)
         }
 
         (Type::SumType(name1, args1), Type::SumType(name2, args2)) => {
             // Sum types must have the same name and same number of type arguments
             if name1 != name2 {
-                return Err(TypeError::UnificationError(t1.clone(), t2.clone()));
+                return Err(TypeError::UnificationError(Box::new(t1.clone()), Box::new(t2.clone())));
             }
             
             if args1.len() != args2.len() {
-                return Err(TypeError::UnificationError(t1.clone(), t2.clone()));
+                return Err(TypeError::UnificationError(Box::new(t1.clone()), Box::new(t2.clone())));
             }
             
             // Unify all type arguments
-            let mut subst = HashMap::new();
+            let mut subst = Substitution::new();
             for (type_arg1, type_arg2) in args1.iter().zip(args2.iter()) {
                 let type_arg1 = apply_subst(&subst, type_arg1);
                 let type_arg2 = apply_subst(&subst, type_arg2);
@@ -740,7 +1014,27 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
             Ok(subst)
         }
 
-        _ => Err(TypeError::UnificationError(t1.clone(), t2.clone())),
+        (Type::Ref(a), Type::Ref(b)) => unify(a, b),
+
+        // Size 0 stands for an unknown size, as for the array an index expects
+        (Type::Array(elem1, size1), Type::Array(elem2, size2)) if size1 == size2 || *size1 == 0 || *size2 == 0 => {
+            unify(elem1, elem2)
+        }
+
+        (Type::Tuple(elements1), Type::Tuple(elements2)) => {
+            if elements1.len() != elements2.len() {
+                return Err(TypeError::TupleArityMismatch(elements1.len(), elements2.len()));
+            }
+
+            let mut subst = Substitution::new();
+            for (element1, element2) in elements1.iter().zip(elements2) {
+                let s = unify(&apply_subst(&subst, element1), &apply_subst(&subst, element2))?;
+                subst = compose_subst(&s, &subst);
+            }
+            Ok(subst)
+        }
+
+        _ => Err(TypeError::UnificationError(Box::new(t1.clone()), Box::new(t2.clone()))),
     }
 }
 
@@ -748,7 +1042,7 @@ fn unify(t1: &Type, t2: &Type) -> Result<Substitution, TypeError> {
 fn bind_var(var: TypeVar, ty: Type) -> Result<Substitution, TypeError> {
     if let Type::Var(v) = &ty {
         if v == &var {
-            return Ok(HashMap::new());
+            return Ok(Substitution::new());
         }
     }
 
@@ -757,24 +1051,54 @@ fn bind_var(var: TypeVar, ty: Type) -> Result<Substitution, TypeError> {
         return Err(TypeError::OccursCheckFailed(var, ty));
     }
 
-    let mut subst = HashMap::new();
-    subst.insert(var, ty);
-    Ok(subst)
+    Ok(Substitution::of_type(var, ty))
+}
+
+/// Bind a row variable to the fields it stands for
+///
+/// A row cannot contain itself: binding `r0` to `{ x: Int | r0 }` gives
+/// `None`, while binding it to `{ | r0 }` binds nothing.
+fn bind_row_var(var: RowVar, row: Type) -> Option<Substitution> {
+    if let Type::RecordRow(fields, tail) = &row {
+        if *tail == var {
+            return fields.is_empty().then(Substitution::new);
+        }
+    }
+    Some(Substitution::of_row(var, row))
 }
 
 /// Compose two substitutions
 fn compose_subst(s1: &Substitution, s2: &Substitution) -> Substitution {
     let mut result = s2.clone();
-    for (var, ty) in s1 {
-        result.insert(var.clone(), apply_subst(s1, ty));
+    for (var, ty) in &s1.types {
+        result.types.insert(var.clone(), apply_subst(s1, ty));
+    }
+    for (var, row) in &s1.rows {
+        result.rows.insert(var.clone(), apply_subst(s1, row));
     }
     result
 }
 
 /// Apply substitution to type environment
+///
+/// Quantified variables are bound by their scheme, so they are left alone even
+/// when an imported scheme happens to share their numbers with the substitution.
 fn apply_subst_env(subst: &Substitution, env: &mut TypeEnv) {
     for scheme in env.bindings.values_mut() {
-        scheme.ty = apply_subst(subst, &scheme.ty);
+        if scheme.vars.iter().any(|var| subst.types.contains_key(var))
+            || scheme.row_vars.iter().any(|var| subst.rows.contains_key(var))
+        {
+            let mut free = subst.clone();
+            for var in &scheme.vars {
+                free.types.remove(var);
+            }
+            for var in &scheme.row_vars {
+                free.rows.remove(var);
+            }
+            scheme.ty = apply_subst(&free, &scheme.ty);
+        } else {
+            scheme.ty = apply_subst(subst, &scheme.ty);
+        }
     }
 }
 
@@ -790,13 +1114,25 @@ fn resolve_type_expr(ty_expr: &crate::ast::TypeExpr, env: &TypeEnv) -> Result<Ty
         }
         crate::ast::TypeExpr::Alias(name) => {
             env.resolve_type_alias(name)
-                .ok_or_else(|| TypeError::UnboundVariable(name.clone()))
+                .ok_or_else(|| TypeError::UnboundVariable(name.clone(), Vec::new()))
         }
     }
 }
 
 /// Convert a TypeAnnotation to a Type, resolving names to concrete types
+///
+/// Each type variable name stands for one fresh type variable, so `a -> a`
+/// is the type of a function returning its argument's type.
 fn resolve_type_annotation(ty_ann: &crate::ast::TypeAnnotation, env: &mut TypeEnv) -> Result<Type, TypeError> {
+    resolve_annotation_with(ty_ann, &mut HashMap::new(), env)
+}
+
+/// Convert a `TypeAnnotation` to a `Type`, with the variables named so far
+fn resolve_annotation_with(
+    ty_ann: &crate::ast::TypeAnnotation,
+    vars: &mut HashMap<String, Type>,
+    env: &mut TypeEnv,
+) -> Result<Type, TypeError> {
     match ty_ann {
         crate::ast::TypeAnnotation::Concrete(name) => {
             // Check if it's a basic type
@@ -806,48 +1142,97 @@ fn resolve_type_annotation(ty_ann: &crate::ast::TypeAnnotation, env: &mut TypeEn
                 "Char" => Ok(Type::Char),
                 "Float" => Ok(Type::Float),
                 _ => {
-                    // Try to resolve as type alias
-                    env.resolve_type_alias(name)
-                        .ok_or_else(|| TypeError::UnboundVariable(name.clone()))
+                    // Try to resolve as type alias, then as a sum type without arguments
+                    if let Some(ty) = env.resolve_type_alias(name) {
+                        return Ok(ty);
+                    }
+                    resolve_applied_type(name, Vec::new(), env)
                 }
             }
         }
-        crate::ast::TypeAnnotation::Var(_name) => {
+        crate::ast::TypeAnnotation::Var(name) => {
             // Type variables in annotations become fresh type variables
             // This allows polymorphic annotations like: fun (x : a) -> x
-            Ok(env.fresh_var())
+            if let Some(ty) = vars.get(name) {
+                return Ok(ty.clone());
+            }
+            let ty = env.fresh_var();
+            vars.insert(name.clone(), ty.clone());
+            Ok(ty)
         }
         crate::ast::TypeAnnotation::Fun(arg, ret) => {
-            let arg_ty = resolve_type_annotation(arg, env)?;
-            let ret_ty = resolve_type_annotation(ret, env)?;
+            let arg_ty = resolve_annotation_with(arg, vars, env)?;
+            let ret_ty = resolve_annotation_with(ret, vars, env)?;
             Ok(Type::Fun(Box::new(arg_ty), Box::new(ret_ty)))
         }
-        crate::ast::TypeAnnotation::App(name, _args) => {
-            // For now, we don't support applied types in annotations
-            // This would require tracking type constructors
-            Err(TypeError::UnboundVariable(format!("Applied type not yet supported in annotations: {}", name)))
+        crate::ast::TypeAnnotation::App(name, args) => {
+            let mut arg_types = Vec::with_capacity(args.len());
+            for arg in args {
+                arg_types.push(resolve_annotation_with(arg, vars, env)?);
+            }
+            resolve_applied_type(name, arg_types, env)
         }
     }
 }
 
+/// Apply a registered sum type to resolved arguments, checking the argument count
+fn resolve_applied_type(name: &str, args: Vec<Type>, env: &TypeEnv) -> Result<Type, TypeError> {
+    let expected = match env.sum_type_arity(name) {
+        Some(arity) => arity,
+        // Built-in types and aliases take no arguments
+        None if matches!(name, "Int" | "Bool" | "Char" | "Float") || env.resolve_type_alias(name).is_some() => 0,
+        None => return Err(TypeError::UnboundVariable(name.to_string(), Vec::new())),
+    };
+    if args.len() != expected {
+        return Err(TypeError::TypeArityMismatch(name.to_string(), expected, args.len()));
+    }
+    Ok(Type::SumType(name.to_string(), args))
+}
+
 /// Type inference for expressions
 pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), TypeError> {
+    let (ty, subst) = infer_node(expr, env).map_err(|error| locate_error(expr, error, env))?;
+    record_node_type(expr, &ty, env);
+    Ok((ty, subst))
+}
+
+/// Put `error`, raised within `expr`, at the span of `expr` while `typecheck_with_spans` runs
+///
+/// An error that already has a span was raised in a node inside `expr`, whose span is kept.
+fn locate_error(expr: &Expr, error: TypeError, env: &TypeEnv) -> TypeError {
+    match env.node_spans.as_ref().and_then(|spans| spans.get(&std::ptr::from_ref(expr))) {
+        Some(span) if error.span().is_none() => TypeError::At(*span, Box::new(error)),
+        _ => error,
+    }
+}
+
+/// Log the type inferred for a node, while `typecheck_nodes` runs
+fn record_node_type(expr: &Expr, ty: &Type, env: &TypeEnv) {
+    if let Some(log) = &env.node_types {
+        log.borrow_mut().insert(std::ptr::from_ref(expr), ty.clone());
+    }
+}
+
+/// Infer the type of one node, recursing through `infer` for its children
+fn infer_node(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), TypeError> {
     match expr {
-        Expr::Int(_) => Ok((Type::Int, HashMap::new())),
+        Expr::Int(_) => Ok((Type::Int, Substitution::new())),
 
-        Expr::Bool(_) => Ok((Type::Bool, HashMap::new())),
+        Expr::Bool(_) => Ok((Type::Bool, Substitution::new())),
 
-        Expr::Char(_) => Ok((Type::Char, HashMap::new())),
+        Expr::Char(_) => Ok((Type::Char, Substitution::new())),
 
-        Expr::Float(_) => Ok((Type::Float, HashMap::new())),
+        Expr::Float(_) => Ok((Type::Float, Substitution::new())),
 
-        Expr::Byte(_) => Ok((Type::Byte, HashMap::new())),
+        Expr::Byte(_) => Ok((Type::Byte, Substitution::new())),
+
+        Expr::EmptyBody => Ok((Type::Unit, Substitution::new())),
 
         Expr::Var(name) => {
             let ty = env
                 .lookup(name)
-                .ok_or_else(|| TypeError::UnboundVariable(name.clone()))?;
-            Ok((ty, HashMap::new()))
+                .ok_or_else(|| env.unbound_variable(name))?;
+            Ok((ty, Substitution::new()))
         }
 
         Expr::BinOp(op, left, right) => {
@@ -859,121 +1244,35 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             let left_ty = apply_subst(&s2, &left_ty);
 
             match op {
-                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div => {
-                    // Arithmetic operations work on Int, Float, and Byte
-                    // Check if left type is Int, Float, or Byte
-                    match &left_ty {
-                        Type::Int => {
-                            let s3 = unify(&right_ty, &Type::Int)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Int, subst));
-                        }
-                        Type::Float => {
-                            let s3 = unify(&right_ty, &Type::Float)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Float, subst));
-                        }
-                        Type::Byte => {
-                            let s3 = unify(&right_ty, &Type::Byte)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Byte, subst));
-                        }
-                        Type::Var(_) => {
-                            // Try to unify with right type first
-                            let s3 = unify(&left_ty, &right_ty)?;
-                            let unified_ty = apply_subst(&s3, &left_ty);
-                            
-                            // Now check if unified type is Int, Float, or Byte
-                            match &unified_ty {
-                                Type::Int | Type::Float | Type::Byte => {
-                                    let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                                    return Ok((unified_ty, subst));
-                                }
-                                Type::Var(_) => {
-                                    // Still a type variable, default to Int for arithmetic operations
-                                    let s4 = unify(&unified_ty, &Type::Int)?;
-                                    let subst = compose_subst(&s4, &compose_subst(&s3, &compose_subst(&s2, &s1)));
-                                    return Ok((Type::Int, subst));
-                                }
-                                _ => {
-                                    return Err(TypeError::UnificationError(
-                                        unified_ty,
-                                        Type::Int,
-                                    ));
-                                }
-                            }
-                        }
-                        _ => {
-                            return Err(TypeError::UnificationError(
-                                left_ty,
-                                Type::Int,
-                            ));
-                        }
-                    }
-                }
-                BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
-                    // Ordering comparisons work for Int, Char, Float, and Byte
-                    // Check if left type is Int, Char, Float, or Byte
-                    match &left_ty {
-                        Type::Int => {
-                            let s3 = unify(&right_ty, &Type::Int)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Bool, subst));
-                        }
-                        Type::Char => {
-                            let s3 = unify(&right_ty, &Type::Char)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Bool, subst));
-                        }
-                        Type::Float => {
-                            let s3 = unify(&right_ty, &Type::Float)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Bool, subst));
-                        }
-                        Type::Byte => {
-                            let s3 = unify(&right_ty, &Type::Byte)?;
-                            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                            return Ok((Type::Bool, subst));
-                        }
-                        Type::Var(_) => {
-                            // Try to unify with right type first
-                            let s3 = unify(&left_ty, &right_ty)?;
-                            let unified_ty = apply_subst(&s3, &left_ty);
-                            
-                            // Now check if unified type is Int, Char, Float, or Byte
-                            match &unified_ty {
-                                Type::Int | Type::Char | Type::Float | Type::Byte => {
-                                    let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
-                                    return Ok((Type::Bool, subst));
-                                }
-                                Type::Var(_) => {
-                                    // Still a type variable, default to Int for ordering operations
-                                    let s4 = unify(&unified_ty, &Type::Int)?;
-                                    let subst = compose_subst(&s4, &compose_subst(&s3, &compose_subst(&s2, &s1)));
-                                    return Ok((Type::Bool, subst));
-                                }
-                                _ => {
-                                    return Err(TypeError::UnificationError(
-                                        unified_ty,
-                                        Type::Int,
-                                    ));
-                                }
-                            }
-                        }
-                        _ => {
-                            return Err(TypeError::UnificationError(
-                                left_ty,
-                                Type::Int,
-                            ));
-                        }
-                    }
+                BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div
+                | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+                    // Arithmetic and ordering follow the table in `numeric_rules`
+                    let (result_ty, s3) = infer_numeric_binop(*op, left, right, &left_ty, &right_ty)?;
+                    let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
+                    return Ok((result_ty, subst));
                 }
                 BinOp::Eq | BinOp::Neq => {
                     // Equality works on any type, but both sides must match
                     let s3 = unify(&left_ty, &right_ty)?;
+                    if let Some(log) = &env.equalities {
+                        log.borrow_mut().push(EqualityCheck {
+                            expr: expr.clone(),
+                            operand: apply_subst(&s3, &left_ty),
+                            span: env.node_spans.as_ref().and_then(|spans| spans.get(&std::ptr::from_ref(expr)).copied()),
+                        });
+                    }
                     let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
                     return Ok((Type::Bool, subst));
                 }
+                BinOp::Concat => {
+                    // Concatenation works on strings (List Char) only
+                    let string_ty = Type::SumType("List".to_string(), vec![Type::Char]);
+                    let s3 = unify(&left_ty, &string_ty)?;
+                    let right_ty = apply_subst(&s3, &right_ty);
+                    let s4 = unify(&right_ty, &string_ty)?;
+                    let subst = compose_subst(&s4, &compose_subst(&s3, &compose_subst(&s2, &s1)));
+                    return Ok((string_ty, subst));
+                }
             }
         }
 
@@ -1003,39 +1302,60 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             Ok((result_ty, subst))
         }
 
-        Expr::Let(name, ty_ann_opt, value, body) => {
-            let (value_ty, s1) = infer(value, env)?;
+        Expr::IfChain(branches, else_br) => {
+            // Every condition must be Bool and every branch must share one result type
+            let result_ty = env.fresh_var();
+            let mut subst = Substitution::new();
+            let mut env1 = env.clone();
 
-            // If there's a type annotation, check it matches the inferred type
-            if let Some(ty_ann) = ty_ann_opt {
-                let annotated_ty = resolve_type_annotation(ty_ann, env)?;
-                let s_ann = unify(&value_ty, &annotated_ty)?;
-                let s1 = compose_subst(&s_ann, &s1);
-                
-                let mut env1 = env.clone();
-                apply_subst_env(&s1, &mut env1);
-                
-                let unified_ty = apply_subst(&s1, &value_ty);
-                let scheme = env1.generalize(&unified_ty);
-                env1.bind(name.clone(), scheme);
+            for (cond, branch) in branches {
+                let (cond_ty, s1) = infer(cond, &mut env1)?;
+                let s2 = unify(&cond_ty, &Type::Bool)?;
+                subst = compose_subst(&s2, &compose_subst(&s1, &subst));
+                apply_subst_env(&subst, &mut env1);
+
+                let (branch_ty, s3) = infer(branch, &mut env1)?;
+                subst = compose_subst(&s3, &subst);
+                let s4 = unify(&apply_subst(&subst, &result_ty), &branch_ty)?;
+                subst = compose_subst(&s4, &subst);
+                apply_subst_env(&subst, &mut env1);
+            }
 
-                let (body_ty, s2) = infer(body, &mut env1)?;
+            let (else_ty, s5) = infer(else_br, &mut env1)?;
+            subst = compose_subst(&s5, &subst);
+            let s6 = unify(&apply_subst(&subst, &result_ty), &else_ty)?;
+            subst = compose_subst(&s6, &subst);
 
-                let subst = compose_subst(&s2, &s1);
-                Ok((body_ty, subst))
-            } else {
-                let mut env1 = env.clone();
-                apply_subst_env(&s1, &mut env1);
+            Ok((apply_subst(&subst, &result_ty), subst))
+        }
 
-                // Generalize the type (let-polymorphism)
-                let scheme = env1.generalize(&value_ty);
-                env1.bind(name.clone(), scheme);
+        Expr::Try(body, name, handler) => {
+            // The handler receives the error as {kind: String, message: String}
+            // and must produce a value of the body's type
+            let (body_ty, s1) = infer(body, env)?;
 
-                let (body_ty, s2) = infer(body, &mut env1)?;
+            let error_ty = Type::Record(HashMap::from([
+                ("kind".to_string(), string_type()),
+                ("message".to_string(), string_type()),
+            ]));
+            let mut env1 = env.clone();
+            apply_subst_env(&s1, &mut env1);
+            env1 = env1.extend(name.clone(), error_ty);
 
-                let subst = compose_subst(&s2, &s1);
-                Ok((body_ty, subst))
-            }
+            let (handler_ty, s2) = infer(handler, &mut env1)?;
+            let body_ty = apply_subst(&s2, &body_ty);
+            let s3 = unify(&body_ty, &handler_ty)?;
+
+            let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
+            Ok((apply_subst(&s3, &handler_ty), subst))
+        }
+
+        Expr::Let(name, ty_ann_opt, value, body) => {
+            let (mut env1, s1) = infer_let_binding(name, ty_ann_opt.as_ref(), value, env)?;
+            let (body_ty, s2) = infer(body, &mut env1)?;
+
+            let subst = compose_subst(&s2, &s1);
+            Ok((body_ty, subst))
         }
 
         Expr::Fun(param, ty_ann_opt, body) => {
@@ -1069,7 +1389,11 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             let s3 = unify(
                 &func_ty,
                 &Type::Fun(Box::new(arg_ty), Box::new(result_ty.clone())),
-            )?;
+            )
+            .map_err(|e| match &func_ty {
+                Type::Fun(param_ty, _) => int_literal_error(arg, param_ty, e),
+                _ => e,
+            })?;
 
             let result_ty = apply_subst(&s3, &result_ty);
             let subst = compose_subst(&s3, &compose_subst(&s2, &s1));
@@ -1077,57 +1401,73 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             Ok((result_ty, subst))
         }
 
-        Expr::Rec(name, body) => {
-            // For recursive functions, we use fixpoint typing:
-            // 1. Generate a fresh type variable for the recursive function
-            // 2. Add it to the environment before checking the body
-            // 3. Infer the type of the body with the recursive name bound
-            // 4. Unify the inferred type with the assumed type
-            
-            let rec_ty = env.fresh_var();
-            let mut extended_env = env.extend(name.clone(), rec_ty.clone());
-            
-            let (body_ty, subst) = infer(body, &mut extended_env)?;
-            
-            // The body type should be the same as the recursive function type
-            // (after applying the substitution from inferring the body)
-            let rec_ty = apply_subst(&subst, &rec_ty);
-            let s2 = unify(&rec_ty, &body_ty)?;
-            
-            let final_ty = apply_subst(&s2, &body_ty);
-            let final_subst = compose_subst(&s2, &subst);
-            
-            Ok((final_ty, final_subst))
-        }
+        Expr::Rec(name, body) => match infer_rec(name, body, env) {
+            // An infinite type can come from the function calling itself at
+            // other types, which inference cannot do without an annotation
+            Err(error) if matches!(error.without_span(), TypeError::OccursCheckFailed(..)) && needs_polymorphic_recursion(name, body, env) => {
+                Err(TypeError::RecursionRequiresAnnotation(name.clone()))
+            }
+            result => result,
+        },
 
         Expr::Tuple(elements) => {
             // Empty tuple is the unit type ()
             if elements.is_empty() {
-                return Ok((Type::Unit, HashMap::new()));
+                return Ok((Type::Unit, Substitution::new()));
+            }
+            let mut element_types = Vec::new();
+            let mut subst = Substitution::new();
+            for element in elements {
+                let (ty, s) = infer(element, env)?;
+                subst = compose_subst(&s, &subst);
+                apply_subst_env(&s, env);
+                element_types.push(ty);
+            }
+            // Later elements can refine the types of earlier ones
+            let element_types = element_types.iter().map(|ty| apply_subst(&subst, ty)).collect();
+            Ok((Type::Tuple(element_types), subst))
+        }
+
+        Expr::TupleProj(tuple_expr, index) => {
+            let (tuple_ty, subst) = infer(tuple_expr, env)?;
+            match apply_subst(&subst, &tuple_ty) {
+                Type::Tuple(elements) => match elements.get(*index) {
+                    Some(element_ty) => Ok((element_ty.clone(), subst)),
+                    None => Err(TypeError::TupleIndexOutOfRange(*index, elements.len())),
+                },
+                // The arity of an unknown tuple cannot be expressed, so the element stays unconstrained
+                Type::Var(_) => Ok((env.fresh_var(), subst)),
+                other => Err(TypeError::TupleExpected(other.to_string())),
             }
-            // For non-empty tuples, return a type variable for now
-            // (full tuple type implementation is planned)
-            Ok((env.fresh_var(), HashMap::new()))
         }
 
-        Expr::TupleProj(_, _) => {
-            // For now, return a type variable for tuple projection
-            Ok((env.fresh_var(), HashMap::new()))
-        }
+        Expr::Match(scrutinee, arms) => infer_match(scrutinee, arms, env),
 
-        Expr::Match(_, _) => {
-            // For now, return a type variable for pattern matching
-            Ok((env.fresh_var(), HashMap::new()))
+        Expr::Load(path, body) => {
+            let library = library_types(path, env)?;
+            let mut env1 = env.clone();
+            env1.import_library(&library);
+            infer(body, &mut env1)
         }
 
-        Expr::Load(_, _) => {
-            // For now, return a type variable for load expressions
-            Ok((env.fresh_var(), HashMap::new()))
+        Expr::LoadAs(path, namespace, body) => {
+            let library = library_types(path, env)?;
+            let mut env1 = env.clone();
+            env1.import_namespace(namespace, &library);
+            infer(body, &mut env1)
         }
 
-        Expr::Seq(_, _) => {
-            // For now, return a type variable for sequential expressions
-            Ok((env.fresh_var(), HashMap::new()))
+        Expr::Seq(bindings, body) => {
+            // `let x = e1; let y = e2; body` is typed like `let x = e1 in let y = e2 in body`
+            let mut env1 = env.clone();
+            let mut subst = Substitution::new();
+            for (name, ty_ann, value) in bindings {
+                let (next_env, s) = infer_let_binding(name, ty_ann.as_ref(), value, &mut env1)?;
+                env1 = next_env;
+                subst = compose_subst(&s, &subst);
+            }
+            let (body_ty, s) = infer(body, &mut env1)?;
+            Ok((body_ty, compose_subst(&s, &subst)))
         }
 
         Expr::TypeAlias(name, ty_expr, body) => {
@@ -1145,7 +1485,7 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
         Expr::Record(fields) => {
             // Infer types for all field expressions
             let mut field_types = HashMap::new();
-            let mut subst = HashMap::new();
+            let mut subst = Substitution::new();
             
             for (name, expr) in fields {
                 let (ty, s) = infer(expr, env)?;
@@ -1183,15 +1523,19 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                         }
                     }
                 }
-                Type::RecordRow(fields, _) => {
+                Type::RecordRow(fields, row_var) => {
                     // Look up the field type in the known fields
                     match fields.get(field_name) {
                         Some(field_ty) => Ok((field_ty.clone(), s1)),
                         None => {
-                            // Field might be in the row variable, but we can't know for sure
-                            // For now, report an error with available fields
-                            let available: Vec<String> = fields.keys().cloned().collect();
-                            Err(TypeError::FieldNotFound(field_name.clone(), available))
+                            // The field must be among the fields the row variable stands for
+                            let field_ty = env.fresh_var();
+                            let rest = Type::RecordRow(
+                                HashMap::from([(field_name.clone(), field_ty.clone())]),
+                                env.fresh_row_var(),
+                            );
+                            let s2 = unify(&Type::Row(row_var), &rest)?;
+                            Ok((field_ty, compose_subst(&s2, &s1)))
                         }
                     }
                 }
@@ -1238,15 +1582,8 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
         
         Expr::TypeDef { name, type_params, constructors, body } => {
             // Register constructors in the environment
-            for (ctor_name, _payload_types) in constructors {
-                let info = ConstructorInfo {
-                    type_params: type_params.clone(),
-                    payload_types: _payload_types.clone(),
-                    sum_type_name: name.clone(),
-                };
-                env.register_constructor(ctor_name.clone(), info);
-            }
-            
+            register_type_def(name, type_params, constructors, env);
+
             // Type check the body with constructors available
             infer(body, env)
         }
@@ -1261,7 +1598,7 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 }
                 
                 // Type check each argument
-                let mut subst = HashMap::new();
+                let mut subst = Substitution::new();
                 let mut arg_types = Vec::new();
                 
                 for arg in args {
@@ -1281,9 +1618,10 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 }
                 
                 // Unify each argument with its expected type
+                // under the substitution so far, so conflicting payloads (Cons 1 [true]) fail
                 for (arg_ty, expected_annotation) in arg_types.iter().zip(&info.payload_types) {
                     let expected_ty = type_annotation_to_type(expected_annotation, &type_param_map, env);
-                    let s = unify(arg_ty, &expected_ty)?;
+                    let s = unify(&apply_subst(&subst, arg_ty), &apply_subst(&subst, &expected_ty))?;
                     subst = compose_subst(&s, &subst);
                 }
                 
@@ -1298,9 +1636,7 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 let result_ty = Type::SumType(info.sum_type_name.clone(), type_args);
                 Ok((result_ty, subst))
             } else {
-                // Constructor not registered - return a fresh type variable
-                // This maintains backward compatibility
-                Ok((env.fresh_var(), HashMap::new()))
+                Err(env.unknown_constructor(name))
             }
         }
         
@@ -1308,7 +1644,7 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             if elements.is_empty() {
                 // Empty array - use fresh type variable for element type
                 let elem_ty = env.fresh_var();
-                Ok((Type::Array(Box::new(elem_ty), 0), HashMap::new()))
+                Ok((Type::Array(Box::new(elem_ty), 0), Substitution::new()))
             } else {
                 // Infer type of first element
                 let (first_ty, mut subst) = infer(&elements[0], env)?;
@@ -1361,8 +1697,8 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 }
                 _ => {
                     Err(TypeError::UnificationError(
-                        arr_ty_subst,
-                        expected_arr_ty
+                        Box::new(arr_ty_subst),
+                        Box::new(expected_arr_ty),
                     ))
                 }
             }
@@ -1374,6 +1710,19 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
             Ok((Type::Ref(Box::new(ty)), subst))
         }
         
+        Expr::Neg(expr) => {
+            // Negation works on Int and Float; an unconstrained operand defaults to Int
+            let (ty, subst) = infer(expr, env)?;
+            match apply_subst(&subst, &ty) {
+                ty @ (Type::Int | Type::Float) => Ok((ty, subst)),
+                ty @ Type::Var(_) => {
+                    let s2 = unify(&ty, &Type::Int)?;
+                    Ok((Type::Int, compose_subst(&s2, &subst)))
+                }
+                ty => Err(TypeError::UnificationError(Box::new(ty), Box::new(Type::Int))),
+            }
+        }
+
         Expr::Deref(expr) => {
             // Type of !ref_expr is T where ref_expr has type Ref T
             let (ref_ty, subst) = infer(expr, env)?;
@@ -1393,8 +1742,8 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 }
                 _ => {
                     return Err(TypeError::UnificationError(
-                        ref_ty_subst,
-                        expected_ref_ty
+                        Box::new(ref_ty_subst),
+                        Box::new(expected_ref_ty),
                     ));
                 }
             };
@@ -1424,8 +1773,8 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
                 }
                 _ => {
                     return Err(TypeError::UnificationError(
-                        ref_ty_subst,
-                        Type::Ref(Box::new(env.fresh_var()))
+                        Box::new(ref_ty_subst),
+                        Box::new(Type::Ref(Box::new(env.fresh_var()))),
                     ));
                 }
             };
@@ -1455,13 +1804,730 @@ pub fn infer(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), Typ
     }
 }
 
+/// Infer a `match`: every pattern must fit the scrutinee's type and every arm
+/// must have the same type, which is the type of the whole expression
+///
+/// A guard must be a Bool, with the pattern's variables in scope. Failures
+/// in an arm's pattern, guard or body are wrapped in `TypeError::InMatchArm`.
+fn infer_match(
+    scrutinee: &Expr,
+    arms: &[(Pattern, Option<Expr>, Expr)],
+    env: &mut TypeEnv,
+) -> Result<(Type, Substitution), TypeError> {
+    let (scrutinee_ty, mut subst) = infer(scrutinee, env)?;
+    let result_ty = env.fresh_var();
+
+    for (index, (pattern, guard, body)) in arms.iter().enumerate() {
+        let in_arm = |error| TypeError::InMatchArm(index + 1, pattern.to_string(), Box::new(error));
+
+        let mut bindings = Vec::new();
+        let s = infer_pattern(pattern, &apply_subst(&subst, &scrutinee_ty), env, &mut bindings).map_err(in_arm)?;
+        subst = compose_subst(&s, &subst);
+
+        let mut arm_env = env.clone();
+        apply_subst_env(&subst, &mut arm_env);
+        for (name, ty) in bindings {
+            arm_env = arm_env.extend(name, apply_subst(&subst, &ty));
+        }
+        if let Some(guard) = guard {
+            let (guard_ty, s1) = infer(guard, &mut arm_env).map_err(in_arm)?;
+            let s2 = unify(&guard_ty, &Type::Bool).map_err(in_arm)?;
+            let s = compose_subst(&s2, &s1);
+            apply_subst_env(&s, &mut arm_env);
+            subst = compose_subst(&s, &subst);
+        }
+        let (body_ty, s) = infer(body, &mut arm_env).map_err(in_arm)?;
+        subst = compose_subst(&s, &subst);
+
+        let s = unify(&apply_subst(&subst, &result_ty), &apply_subst(&subst, &body_ty)).map_err(in_arm)?;
+        subst = compose_subst(&s, &subst);
+    }
+
+    Ok((apply_subst(&subst, &result_ty), subst))
+}
+
+/// Check `pattern` against the type of the value it matches
+///
+/// Adds the variables the pattern binds, with their types, to `bindings` and
+/// returns the substitution that makes the pattern fit `expected`.
+fn infer_pattern(
+    pattern: &Pattern,
+    expected: &Type,
+    env: &mut TypeEnv,
+    bindings: &mut Vec<(String, Type)>,
+) -> Result<Substitution, TypeError> {
+    match pattern {
+        Pattern::Wildcard => Ok(Substitution::new()),
+        Pattern::Var(name) => {
+            bindings.push((name.clone(), expected.clone()));
+            Ok(Substitution::new())
+        }
+        Pattern::Literal(literal) => {
+            let literal_ty = match literal {
+                Literal::Int(_) => Type::Int,
+                Literal::Bool(_) => Type::Bool,
+                Literal::Char(_) => Type::Char,
+                Literal::Byte(_) => Type::Byte,
+            };
+            unify(expected, &literal_ty)
+        }
+        Pattern::Tuple(elements) if elements.is_empty() => unify(expected, &Type::Unit),
+        Pattern::Tuple(elements) => {
+            let element_tys: Vec<Type> = elements.iter().map(|_| env.fresh_var()).collect();
+            let subst = unify(expected, &Type::Tuple(element_tys.clone()))?;
+            infer_sub_patterns(elements.iter().zip(element_tys), subst, env, bindings)
+        }
+        Pattern::Record(fields) => {
+            // Record patterns may leave fields out, so the record can have more
+            let field_tys: Vec<(&Pattern, Type)> = fields.iter().map(|(_, pattern)| (pattern, env.fresh_var())).collect();
+            let row: HashMap<String, Type> =
+                fields.iter().zip(&field_tys).map(|((name, _), (_, ty))| (name.clone(), ty.clone())).collect();
+            let subst = unify(expected, &Type::RecordRow(row, env.fresh_row_var()))?;
+            infer_sub_patterns(field_tys, subst, env, bindings)
+        }
+        Pattern::Constructor(name, payload) => {
+            let info = env.lookup_constructor(name).cloned().ok_or_else(|| env.unknown_constructor(name))?;
+            if payload.len() != info.payload_types.len() {
+                return Err(TypeError::ConstructorArityMismatch(name.clone(), info.payload_types.len(), payload.len()));
+            }
+            let type_param_map: HashMap<String, Type> =
+                info.type_params.iter().map(|param| (param.clone(), env.fresh_var())).collect();
+            let type_args = info.type_params.iter().map(|param| type_param_map[param].clone()).collect();
+            let subst = unify(expected, &Type::SumType(info.sum_type_name.clone(), type_args))?;
+            let payload_tys: Vec<Type> = info
+                .payload_types
+                .iter()
+                .map(|annotation| type_annotation_to_type(annotation, &type_param_map, env))
+                .collect();
+            infer_sub_patterns(payload.iter().zip(payload_tys), subst, env, bindings)
+        }
+    }
+}
+
+/// Check nested patterns against their types, continuing from `subst`
+fn infer_sub_patterns<'a>(
+    patterns: impl IntoIterator<Item = (&'a Pattern, Type)>,
+    mut subst: Substitution,
+    env: &mut TypeEnv,
+    bindings: &mut Vec<(String, Type)>,
+) -> Result<Substitution, TypeError> {
+    for (pattern, ty) in patterns {
+        let s = infer_pattern(pattern, &apply_subst(&subst, &ty), env, bindings)?;
+        subst = compose_subst(&s, &subst);
+    }
+    Ok(subst)
+}
+
+/// Infer the type of `rec name -> body`, with `name` monomorphic in `body`
+///
+/// For recursive functions, we use fixpoint typing:
+/// 1. Generate a fresh type variable for the recursive function
+/// 2. Add it to the environment before checking the body
+/// 3. Infer the type of the body with the recursive name bound
+/// 4. Unify the inferred type with the assumed type
+fn infer_rec(name: &str, body: &Expr, env: &mut TypeEnv) -> Result<(Type, Substitution), TypeError> {
+    let rec_ty = env.fresh_var();
+    let mut extended_env = env.extend(name.to_string(), rec_ty.clone());
+
+    let (body_ty, subst) = infer(body, &mut extended_env)?;
+
+    // The body type should be the same as the recursive function type
+    // (after applying the substitution from inferring the body)
+    let rec_ty = apply_subst(&subst, &rec_ty);
+    let s2 = unify(&rec_ty, &body_ty)?;
+
+    let final_subst = compose_subst(&s2, &subst);
+    Ok((apply_subst(&final_subst, &body_ty), final_subst))
+}
+
+/// Rounds of refinement `needs_polymorphic_recursion` tries before giving up
+const POLYMORPHIC_RECURSION_ROUNDS: usize = 4;
+
+/// Whether `rec name -> body` would type check if `name` were polymorphic in `body`
+///
+/// Starts by assuming nothing about `name`'s type, then assumes the type the
+/// body was inferred to have, until the assumption stops changing. A body
+/// whose type grows every round has a genuinely infinite type instead.
+fn needs_polymorphic_recursion(name: &str, body: &Expr, env: &mut TypeEnv) -> bool {
+    let anything = env.fresh_var();
+    let mut assumed = env.generalize(&anything);
+    for _ in 0..POLYMORPHIC_RECURSION_ROUNDS {
+        let mut probe_env = env.clone();
+        probe_env.bind(name.to_string(), assumed.clone());
+        let Ok((body_ty, subst)) = infer(body, &mut probe_env) else {
+            return false;
+        };
+        let mut env1 = env.clone();
+        apply_subst_env(&subst, &mut env1);
+        let inferred = env1.generalize(&body_ty);
+        if inferred == assumed {
+            return true;
+        }
+        assumed = inferred;
+    }
+    false
+}
+
+/// Infer the type of `rec name -> body` annotated with `annotated_ty`, with
+/// `name` polymorphic in `body`
+///
+/// The variables of the annotation are quantified inside the body, so the
+/// function may call itself at any instance of its annotation. The body must
+/// then be as general as the annotation: its variables have to stay distinct
+/// variables rather than being unified with other types.
+fn infer_annotated_rec(
+    name: &str,
+    body: &Expr,
+    annotated_ty: &Type,
+    env: &mut TypeEnv,
+) -> Result<(Type, Substitution), TypeError> {
+    let mut quantified: Vec<TypeVar> = free_type_vars(annotated_ty).difference(&env.free_vars()).cloned().collect();
+    quantified.sort();
+    let mut extended_env = env.clone();
+    extended_env.bind(
+        name.to_string(),
+        TypeScheme { vars: quantified.clone(), row_vars: vec![], ty: annotated_ty.clone() },
+    );
+
+    let (body_ty, s1) = infer(body, &mut extended_env)?;
+    let s2 = unify(&apply_subst(&s1, annotated_ty), &body_ty)?;
+    let subst = compose_subst(&s2, &s1);
+
+    let mut env1 = env.clone();
+    apply_subst_env(&subst, &mut env1);
+    let env_vars = env1.free_vars();
+    let mut seen = HashSet::new();
+    for var in quantified {
+        match apply_subst(&subst, &Type::Var(var)) {
+            Type::Var(v) if !env_vars.contains(&v) && seen.insert(v.clone()) => {}
+            _ => return Err(TypeError::UnificationError(Box::new(annotated_ty.clone()), Box::new(apply_subst(&subst, &body_ty)))),
+        }
+    }
+    Ok((apply_subst(&subst, annotated_ty), subst))
+}
+
+/// Infer a let-bound value and bind its generalized scheme in a copy of `env`
+///
+/// An annotation, when present, must unify with the inferred type. Returns
+/// the extended environment and the substitution found for the value.
+fn infer_let_binding(
+    name: &str,
+    ty_ann_opt: Option<&crate::ast::TypeAnnotation>,
+    value: &Expr,
+    env: &mut TypeEnv,
+) -> Result<(TypeEnv, Substitution), TypeError> {
+    let (value_ty, s1) = match (infer(value, env), value, ty_ann_opt) {
+        // A recursive function calling itself at other types is checked
+        // against its annotation instead
+        (Err(error), Expr::Rec(rec_name, body), Some(ty_ann)) if matches!(error.without_span(), TypeError::RecursionRequiresAnnotation(_)) => {
+            let annotated_ty = resolve_type_annotation(ty_ann, env)?;
+            let (ty, subst) = infer_annotated_rec(rec_name, body, &annotated_ty, env)?;
+            record_node_type(value, &ty, env);
+            (ty, subst)
+        }
+        (result, ..) => result?,
+    };
+
+    // If there's a type annotation, check it matches the inferred type
+    let (value_ty, s1) = match ty_ann_opt {
+        Some(ty_ann) => {
+            let annotated_ty = resolve_type_annotation(ty_ann, env)?;
+            // A mismatch is reported at the annotated value, not the whole `let`
+            let s_ann = unify(&value_ty, &annotated_ty)
+                .map_err(|e| locate_error(value, int_literal_error(value, &annotated_ty, e), env))?;
+            let s1 = compose_subst(&s_ann, &s1);
+            (apply_subst(&s1, &value_ty), s1)
+        }
+        None => (value_ty, s1),
+    };
+
+    let mut env1 = env.clone();
+    apply_subst_env(&s1, &mut env1);
+
+    // Generalize the type (let-polymorphism)
+    let scheme = env1.generalize(&value_ty);
+    env1.bind(name.to_string(), scheme);
+    Ok((env1, s1))
+}
+
+/// Explain a failed unification of an integer literal against Float
+///
+/// Literals are never converted implicitly, so `let x : Float = 3 in x`
+/// stays an error, but one that says how to write the literal instead of
+/// reporting `Cannot unify types`. Any other failure is returned unchanged.
+fn int_literal_error(expr: &Expr, expected: &Type, error: TypeError) -> TypeError {
+    match (expr, expected) {
+        (Expr::Int(n), Type::Float) => TypeError::IntLiteralForFloat(*n),
+        _ => error,
+    }
+}
+
+/// Infer an arithmetic or ordering operation from [`numeric_rules::NUMERIC_RULES`]
+///
+/// Returns the result type and the substitution that makes the operand
+/// types an allowed combination. An operand of unknown type takes the other
+/// operand's type, or [`numeric_rules::DEFAULT_KIND`] if both are unknown.
+fn infer_numeric_binop(
+    op: BinOp,
+    left: &Expr,
+    right: &Expr,
+    left_ty: &Type,
+    right_ty: &Type,
+) -> Result<(Type, Substitution), TypeError> {
+    let default_ty = numeric_rules::DEFAULT_KIND.to_type();
+    if let Type::Var(_) = left_ty {
+        let s1 = unify(left_ty, right_ty)?;
+        let unified_ty = apply_subst(&s1, left_ty);
+        if let Type::Var(_) = unified_ty {
+            let s2 = unify(&unified_ty, &default_ty)?;
+            let result = numeric_rules::result_kind(op, numeric_rules::DEFAULT_KIND, numeric_rules::DEFAULT_KIND)
+                .expect("every governed operator accepts the default kind");
+            return Ok((result.to_type(), compose_subst(&s2, &s1)));
+        }
+        return match ScalarKind::of_type(&unified_ty).and_then(|kind| numeric_rules::result_kind(op, kind, kind)) {
+            Some(result) => Ok((result.to_type(), s1)),
+            None => Err(TypeError::UnificationError(Box::new(unified_ty), Box::new(default_ty))),
+        };
+    }
+
+    let rules: Vec<_> = ScalarKind::of_type(left_ty)
+        .map(|kind| numeric_rules::rules_for_left(op, kind).collect())
+        .unwrap_or_default();
+    if let Some(rule) = rules.iter().find(|rule| ScalarKind::of_type(right_ty) == Some(rule.right)) {
+        return Ok((rule.result.to_type(), Substitution::new()));
+    }
+    let Some(rule) = rules.first() else {
+        return Err(TypeError::UnificationError(Box::new(left_ty.clone()), Box::new(default_ty)));
+    };
+    let expected = rule.right.to_type();
+    let subst = unify(right_ty, &expected)
+        .map_err(|e| int_literal_error(right, &expected, int_literal_error(left, right_ty, e)))?;
+    Ok((rule.result.to_type(), subst))
+}
+
+/// Register the constructors of a sum type definition
+fn register_type_def(
+    name: &str,
+    type_params: &[String],
+    constructors: &[(String, Vec<crate::ast::TypeAnnotation>)],
+    env: &mut TypeEnv,
+) {
+    for (ctor_name, payload_types) in constructors {
+        let info = ConstructorInfo {
+            type_params: type_params.to_vec(),
+            payload_types: payload_types.clone(),
+            sum_type_name: name.to_string(),
+        };
+        env.register_constructor(ctor_name.clone(), info);
+    }
+}
+
+/// Types a `load` of `path` brings into scope, checking the library if needed
+///
+/// The library is found through the environment's [`LoadPath`], as at
+/// runtime. Each library is checked in its own context holding the prelude
+/// built-ins and, as at runtime, the sum types and aliases defined where it
+/// is loaded, but none of the loader's bindings, so its schemes are numbered
+/// independently of the loading program. The result is cached by path,
+/// content hash and those type definitions for the rest of the run.
+fn library_types(path: &str, env: &TypeEnv) -> Result<Rc<LibraryTypes>, TypeError> {
+    let file = env.load_path.resolve(path).map_err(TypeError::LoadError)?;
+    let _loading = env.loading.enter(&file).map_err(TypeError::LoadError)?;
+    let content = fs::read_to_string(&file)
+        .map_err(|e| TypeError::LoadError(format!("Failed to read file '{}': {e}", file.display())))?;
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    env.hash_type_definitions(&mut hasher);
+    let key = (file.display().to_string(), hasher.finish());
+
+    {
+        let mut cache = env.libraries.borrow_mut();
+        if let Some(types) = cache.entries.get(&key).cloned() {
+            cache.hits += 1;
+            return Ok(types);
+        }
+    }
+
+    // A blank file is an empty library, as at runtime
+    let library = if crate::parser::is_blank_source(&content) {
+        Expr::EmptyBody
+    } else {
+        crate::parser::parse(&content).map_err(|e| TypeError::LoadError(format!("Failed to parse file '{path}': {e}")))?
+    };
+    let mut lib_env = TypeEnv::with_builtins();
+    lib_env.constructors.extend(env.constructors.iter().map(|(name, info)| (name.clone(), info.clone())));
+    lib_env.type_aliases.extend(env.type_aliases.iter().map(|(name, ty)| (name.clone(), ty.clone())));
+    lib_env.libraries = Rc::clone(&env.libraries);
+    lib_env.load_path = Rc::new(env.load_path.for_file(&file));
+    lib_env.loading = Rc::clone(&env.loading);
+    let mut exported = Vec::new();
+    check_spine(&library, &mut lib_env, &mut exported)
+        .map_err(|e| TypeError::InLibrary(path.to_string(), Box::new(e)))?;
+
+    let types = Rc::new(lib_env.library_exports(&exported));
+    env.libraries.borrow_mut().entries.insert(key, Rc::clone(&types));
+    Ok(types)
+}
+
+/// Check a binding spine, leaving its bindings in `env` and collecting their names
+///
+/// Mirrors what `load` makes visible at runtime: `let` bindings (sequential
+/// or nested), type definitions and aliases, and everything the spine itself
+/// loads. The expression ending the spine is checked but binds nothing; its
+/// type is returned.
+fn check_spine(expr: &Expr, env: &mut TypeEnv, exported: &mut Vec<String>) -> Result<Type, TypeError> {
+    let mut current = expr;
+    loop {
+        match current {
+            Expr::Let(name, ty_ann, value, body) => {
+                *env = infer_let_binding(name, ty_ann.as_ref(), value, env)?.0;
+                exported.push(name.clone());
+                current = body;
+            }
+            Expr::Seq(bindings, body) => {
+                for (name, ty_ann, value) in bindings {
+                    *env = infer_let_binding(name, ty_ann.as_ref(), value, env)?.0;
+                    exported.push(name.clone());
+                }
+                current = body;
+            }
+            Expr::TypeAlias(name, ty_expr, body) => {
+                let ty = resolve_type_expr(ty_expr, env)?;
+                env.define_type_alias(name.clone(), ty);
+                current = body;
+            }
+            Expr::TypeDef { name, type_params, constructors, body } => {
+                register_type_def(name, type_params, constructors, env);
+                current = body;
+            }
+            Expr::Load(path, body) => {
+                let library = library_types(path, env)?;
+                env.import_library(&library);
+                exported.extend(library.schemes.iter().map(|(name, _)| name.clone()));
+                current = body;
+            }
+            Expr::LoadAs(path, namespace, body) => {
+                let library = library_types(path, env)?;
+                env.import_namespace(namespace, &library);
+                exported.push(namespace.clone());
+                current = body;
+            }
+            other => return typecheck_in(other, env),
+        }
+    }
+}
+
+impl TypeEnv {
+    /// Add the bindings, constructors, and aliases of a checked library
+    fn import_library(&mut self, library: &LibraryTypes) {
+        self.import_library_types(library);
+        self.import_schemes(library.schemes.clone());
+    }
+
+    /// Add the constructors and aliases of a checked library
+    fn import_library_types(&mut self, library: &LibraryTypes) {
+        for (name, info) in &library.constructors {
+            self.register_constructor(name.clone(), info.clone());
+        }
+        for (name, ty) in &library.aliases {
+            self.define_type_alias(name.clone(), ty.clone());
+        }
+    }
+
+    /// Bind `namespace` to a record of a checked library's bindings, as
+    /// `load "path" as Namespace` does, and add its constructors and aliases
+    ///
+    /// Each binding's scheme is instantiated afresh and the record as a whole
+    /// generalized, so every use of `Namespace.name` stays polymorphic.
+    fn import_namespace(&mut self, namespace: &str, library: &LibraryTypes) {
+        self.import_library_types(library);
+        let fields = library
+            .schemes
+            .iter()
+            .map(|(name, scheme)| (name.clone(), self.instantiate(scheme)))
+            .collect();
+        let scheme = TypeEnv::new().generalize(&Type::Record(fields));
+        self.bind(namespace.to_string(), scheme);
+    }
+
+    /// What a library checked in this environment exports
+    ///
+    /// A name bound more than once is exported once, at its last binding.
+    /// Constructors and aliases are sorted by name so the result is stable.
+    /// Feed the sum types and aliases in scope to `hasher`, in name order
+    fn hash_type_definitions(&self, hasher: &mut impl Hasher) {
+        let mut constructors: Vec<_> = self.constructors.iter().collect();
+        constructors.sort_by_key(|(name, _)| *name);
+        for (name, info) in constructors {
+            (name, format!("{info:?}")).hash(hasher);
+        }
+        let mut aliases: Vec<_> = self.type_aliases.iter().collect();
+        aliases.sort_by_key(|(name, _)| *name);
+        for (name, ty) in aliases {
+            (name, ty.to_string()).hash(hasher);
+        }
+    }
+
+    fn library_exports(&self, names: &[String]) -> LibraryTypes {
+        let mut seen = HashSet::new();
+        let mut schemes: Vec<(String, TypeScheme)> = names
+            .iter()
+            .rev()
+            .filter(|name| seen.insert(name.as_str()))
+            .filter_map(|name| self.bindings.get(name).map(|scheme| (name.clone(), canonicalize(scheme))))
+            .collect();
+        schemes.reverse();
+        let mut constructors: Vec<(String, ConstructorInfo)> =
+            self.constructors.iter().map(|(name, info)| (name.clone(), info.clone())).collect();
+        constructors.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut aliases: Vec<(String, Type)> =
+            self.type_aliases.iter().map(|(name, ty)| (name.clone(), ty.clone())).collect();
+        aliases.sort_by(|a, b| a.0.cmp(&b.0));
+        LibraryTypes { schemes, constructors, aliases }
+    }
+}
+
+/// Rename the quantified variables of a scheme to `t0, t1, ...` and its row
+/// variables to `r0, r1, ...`, in order of first occurrence in the type
+///
+/// Numbers already used by free variables of the type are skipped, so the
+/// renaming never captures them. Two schemes that differ only in variable
+/// numbering have the same canonical form.
+pub fn canonicalize(scheme: &TypeScheme) -> TypeScheme {
+    let mut vars = Vec::new();
+    let mut rows = Vec::new();
+    vars_in_order(&scheme.ty, &mut vars, &mut rows);
+    for var in &scheme.vars {
+        if !vars.contains(var) {
+            vars.push(var.clone());
+        }
+    }
+    for row in &scheme.row_vars {
+        if !rows.contains(row) {
+            rows.push(row.clone());
+        }
+    }
+
+    let free_vars: HashSet<usize> = vars.iter().filter(|v| !scheme.vars.contains(v)).map(|v| v.0).collect();
+    let free_rows: HashSet<usize> = rows.iter().filter(|r| !scheme.row_vars.contains(r)).map(|r| r.0).collect();
+    let mut var_numbers = (0..).filter(|n| !free_vars.contains(n));
+    let mut row_numbers = (0..).filter(|n| !free_rows.contains(n));
+    let var_map: HashMap<TypeVar, TypeVar> = vars
+        .iter()
+        .filter(|v| scheme.vars.contains(v))
+        .map(|v| (v.clone(), TypeVar(var_numbers.next().unwrap_or_default())))
+        .collect();
+    let row_map: HashMap<RowVar, RowVar> = rows
+        .iter()
+        .filter(|r| scheme.row_vars.contains(r))
+        .map(|r| (r.clone(), RowVar(row_numbers.next().unwrap_or_default())))
+        .collect();
+
+    let mut new_vars: Vec<TypeVar> = var_map.values().cloned().collect();
+    new_vars.sort();
+    let mut new_rows: Vec<RowVar> = row_map.values().cloned().collect();
+    new_rows.sort();
+    TypeScheme { vars: new_vars, row_vars: new_rows, ty: rename_vars(&scheme.ty, &var_map, &row_map) }
+}
+
+/// Collect type and row variables in order of first occurrence
+///
+/// Record fields are visited in name order so the order is deterministic.
+fn vars_in_order(ty: &Type, vars: &mut Vec<TypeVar>, rows: &mut Vec<RowVar>) {
+    let visit_row = |row: &RowVar, rows: &mut Vec<RowVar>| {
+        if !rows.contains(row) {
+            rows.push(row.clone());
+        }
+    };
+    match ty {
+        Type::Var(var) => {
+            if !vars.contains(var) {
+                vars.push(var.clone());
+            }
+        }
+        Type::Fun(arg, ret) => {
+            vars_in_order(arg, vars, rows);
+            vars_in_order(ret, vars, rows);
+        }
+        Type::Record(fields) | Type::RecordRow(fields, _) => {
+            let mut names: Vec<&String> = fields.keys().collect();
+            names.sort();
+            for name in names {
+                vars_in_order(&fields[name], vars, rows);
+            }
+            if let Type::RecordRow(_, row) = ty {
+                visit_row(row, rows);
+            }
+        }
+        Type::Row(row) => visit_row(row, rows),
+        Type::SumType(_, args) | Type::Tuple(args) => args.iter().for_each(|arg| vars_in_order(arg, vars, rows)),
+        Type::Array(elem, _) | Type::Ref(elem) => vars_in_order(elem, vars, rows),
+        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range | Type::Opaque(_) => {}
+    }
+}
+
+/// Rename variables simultaneously; variables not in the maps are kept
+fn rename_vars(ty: &Type, var_map: &HashMap<TypeVar, TypeVar>, row_map: &HashMap<RowVar, RowVar>) -> Type {
+    let rename_row = |row: &RowVar| row_map.get(row).cloned().unwrap_or_else(|| row.clone());
+    let rename_fields = |fields: &HashMap<String, Type>| {
+        fields.iter().map(|(name, ty)| (name.clone(), rename_vars(ty, var_map, row_map))).collect()
+    };
+    match ty {
+        Type::Var(var) => Type::Var(var_map.get(var).cloned().unwrap_or_else(|| var.clone())),
+        Type::Fun(arg, ret) => Type::Fun(
+            Box::new(rename_vars(arg, var_map, row_map)),
+            Box::new(rename_vars(ret, var_map, row_map)),
+        ),
+        Type::Record(fields) => Type::Record(rename_fields(fields)),
+        Type::RecordRow(fields, row) => Type::RecordRow(rename_fields(fields), rename_row(row)),
+        Type::Row(row) => Type::Row(rename_row(row)),
+        Type::SumType(name, args) => {
+            Type::SumType(name.clone(), args.iter().map(|arg| rename_vars(arg, var_map, row_map)).collect())
+        }
+        Type::Tuple(elements) => Type::Tuple(elements.iter().map(|element| rename_vars(element, var_map, row_map)).collect()),
+        Type::Array(elem, size) => Type::Array(Box::new(rename_vars(elem, var_map, row_map)), *size),
+        Type::Ref(inner) => Type::Ref(Box::new(rename_vars(inner, var_map, row_map))),
+        Type::Int | Type::Bool | Type::Char | Type::Float | Type::Byte | Type::Unit | Type::Range | Type::Opaque(_) => {
+            ty.clone()
+        }
+    }
+}
+
 /// Public API for type checking
 pub fn typecheck(expr: &Expr) -> Result<Type, TypeError> {
-    let mut env = TypeEnv::new();
-    let (ty, subst) = infer(expr, &mut env)?;
+    typecheck_in(expr, &mut TypeEnv::new())
+}
+
+/// Infer the type of an expression in a given type environment
+/// 
+/// Use with `TypeEnv::with_builtins()` to typecheck programs that call
+/// prelude built-ins.
+/// 
+/// # Errors
+/// 
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_in(expr: &Expr, env: &mut TypeEnv) -> Result<Type, TypeError> {
+    let (ty, subst) = infer(expr, env)?;
     Ok(apply_subst(&subst, &ty))
 }
 
+/// Infer the type of a top-level input, keeping the names it binds in `env`
+///
+/// This is what the REPL uses to remember the types of earlier definitions:
+/// the `let` bindings, type definitions, aliases and loads along the spine of
+/// `expr` stay visible in `env` for later calls. On error `env` may hold some
+/// of the input's bindings, so callers should check a clone.
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_toplevel(expr: &Expr, env: &mut TypeEnv) -> Result<Type, TypeError> {
+    check_spine(expr, env, &mut Vec::new())
+}
+
+/// Infer the type of a parsed program like [`typecheck_in`], reporting errors where they are
+///
+/// `spans` are the spans of the nodes of `expr`, in the order of
+/// [`Expr::subexpressions`], as [`parse_program_with_spans`] returns them.
+/// An error is wrapped in `TypeError::At` with the span of the innermost
+/// node around where it was raised that has one.
+///
+/// [`parse_program_with_spans`]: crate::parser::parse_program_with_spans
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_with_spans(expr: &Expr, spans: &[Option<Span>], env: &mut TypeEnv) -> Result<Type, TypeError> {
+    let outer = env.node_spans.replace(Rc::new(spans_by_node(expr, spans)));
+    let result = typecheck_in(expr, env);
+    env.node_spans = outer;
+    result
+}
+
+/// The spans of the nodes of `expr` that have one, keyed by node
+fn spans_by_node(expr: &Expr, spans: &[Option<Span>]) -> HashMap<*const Expr, Span> {
+    expr.subexpressions().zip(spans).filter_map(|(node, span)| Some((std::ptr::from_ref(node), (*span)?))).collect()
+}
+
+/// Infer the type of an expression, also returning every `==` and `!=` in it
+///
+/// Operand types are resolved against the whole expression, so a comparison
+/// of a variable only later found to be a Float is reported as comparing
+/// Floats. Comparisons inside loaded libraries are not included.
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_equalities(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Vec<EqualityCheck>), TypeError> {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let outer = env.equalities.replace(Rc::clone(&log));
+    let result = infer(expr, env);
+    env.equalities = outer;
+    let (ty, subst) = result?;
+    let checks = log
+        .take()
+        .into_iter()
+        .map(|check| EqualityCheck { operand: apply_subst(&subst, &check.operand), ..check })
+        .collect();
+    Ok((apply_subst(&subst, &ty), checks))
+}
+
+/// Like [`typecheck_equalities`], also giving each comparison its span
+///
+/// `spans` are the spans of the nodes of `expr`, as for [`typecheck_with_spans`].
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_equalities_with_spans(
+    expr: &Expr,
+    spans: &[Option<Span>],
+    env: &mut TypeEnv,
+) -> Result<(Type, Vec<EqualityCheck>), TypeError> {
+    let outer = env.node_spans.replace(Rc::new(spans_by_node(expr, spans)));
+    let result = typecheck_equalities(expr, env);
+    env.node_spans = outer;
+    result
+}
+
+/// Infer the type of an expression, also returning the type of every node in it
+///
+/// The node types line up with [`Expr::subexpressions`]; a node has `None`
+/// when inference never visited it, such as the missing body of a `load`.
+/// Types are resolved against the whole expression, and their variables are
+/// renumbered `t0, t1, ...` (rows `r0, r1, ...`) in order of first
+/// occurrence, so the same program always gets the same types.
+///
+/// # Errors
+///
+/// Returns a `TypeError` if the expression is not well-typed.
+pub fn typecheck_nodes(expr: &Expr, env: &mut TypeEnv) -> Result<(Type, Vec<Option<Type>>), TypeError> {
+    let log = Rc::new(RefCell::new(HashMap::new()));
+    let outer = env.node_types.replace(Rc::clone(&log));
+    let result = infer(expr, env);
+    env.node_types = outer;
+    let (ty, subst) = result?;
+    let mut log = log.take();
+    let types: Vec<Option<Type>> = expr
+        .subexpressions()
+        .map(|node| log.remove(&std::ptr::from_ref(node)).map(|ty| apply_subst(&subst, &ty)))
+        .collect();
+
+    let ty = apply_subst(&subst, &ty);
+    let mut vars = Vec::new();
+    let mut rows = Vec::new();
+    for node_ty in types.iter().flatten().chain([&ty]) {
+        vars_in_order(node_ty, &mut vars, &mut rows);
+    }
+    let var_map: HashMap<TypeVar, TypeVar> = vars.into_iter().zip(0..).map(|(var, n)| (var, TypeVar(n))).collect();
+    let row_map: HashMap<RowVar, RowVar> = rows.into_iter().zip(0..).map(|(row, n)| (row, RowVar(n))).collect();
+    let types = types
+        .iter()
+        .map(|node_ty| node_ty.as_ref().map(|node_ty| rename_vars(node_ty, &var_map, &row_map)))
+        .collect();
+    Ok((rename_vars(&ty, &var_map, &row_map), types))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1507,6 +2573,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_infer_if_chain() {
+        assert_eq!(
+            check("if 1 > 2 then 1 elif 2 > 3 then 2 else if true then 3 else 4").unwrap(),
+            Type::Int
+        );
+        // Branch types must agree
+        assert!(check("if true then 1 elif false then true else 3").is_err());
+        // Every condition must be a boolean
+        assert!(check("if true then 1 elif 5 then 2 else 3").is_err());
+    }
+
     #[test]
     fn test_infer_function() {
         let ty = check("fun x -> x + 1").unwrap();
@@ -1560,9 +2638,9 @@ mod tests {
     fn test_error_unbound_variable() {
         assert!(matches!(
             check("x + 1"),
-            Err(TypeError::UnboundVariable(_))
+            Err(TypeError::UnboundVariable(..))
         ));
-        assert!(matches!(check("y"), Err(TypeError::UnboundVariable(_))));
+        assert!(matches!(check("y"), Err(TypeError::UnboundVariable(..))));
     }
 
     #[test]
@@ -1641,6 +2719,18 @@ mod tests {
         assert_eq!(ty, Type::Int);
     }
 
+    #[test]
+    fn test_seq_is_typed_like_nested_lets() {
+        assert_eq!(check("let x = 10; let y = x + 1; y * 2").unwrap(), Type::Int);
+        assert!(check("let x = 10; x + true").is_err());
+    }
+
+    #[test]
+    fn test_program_without_body_is_unit() {
+        assert_eq!(check("let double = fun x -> x * 2;").unwrap(), Type::Unit);
+        assert_eq!(check("").unwrap(), Type::Unit);
+    }
+
     #[test]
     fn test_equality_polymorphic() {
         // Equality should work on Int
@@ -1662,4 +2752,125 @@ mod tests {
         let ty = check("rec f -> fun n -> if n == 0 then 1 else n * f (n - 1)").unwrap();
         assert_eq!(ty, Type::Fun(Box::new(Type::Int), Box::new(Type::Int)));
     }
+
+    /// Type variables of a type, by number
+    fn var_numbers(ty: &Type) -> HashSet<usize> {
+        free_type_vars(ty).into_iter().map(|var| var.0).collect()
+    }
+
+    #[test]
+    fn test_reserved_vars_are_not_reused() {
+        let mut env = TypeEnv::new();
+        let (first, var) = env.fresh_var_pair();
+        assert_eq!((first, var), (Type::Var(TypeVar(0)), TypeVar(0)));
+
+        let reserved = env.reserve_vars(3);
+        assert_eq!(reserved, 1..4);
+        assert_eq!(env.next_var(), 4);
+        let rows = env.reserve_row_vars(2);
+        assert_eq!((rows, env.next_row_var()), (0..2, 2));
+
+        // An external solver builds a monomorphic binding from its own variables
+        let [a, b, _] = [1, 2, 3].map(|n| Type::Var(TypeVar(n)));
+        assert!(reserved.contains(&1) && reserved.contains(&2));
+        env.bind("ext".to_string(), TypeScheme { vars: vec![], row_vars: vec![], ty: Type::Fun(Box::new(a.clone()), Box::new(b.clone())) });
+
+        let ty = typecheck_in(&parse("fun y -> (ext, y)").unwrap(), &mut env).unwrap();
+        let Type::Fun(arg, _) = &ty else { panic!("expected a function type, got {ty}") };
+        assert!(var_numbers(arg).iter().all(|n| !reserved.contains(n)), "{ty}");
+        assert_eq!(env.fresh_var_pair().1 .0, env.next_var() - 1);
+    }
+
+    #[test]
+    fn test_from_parts_round_trip() {
+        let mut env = TypeEnv::with_builtins();
+        typecheck_in(&parse("type Pair a = Pair a a in 0").unwrap(), &mut env).unwrap();
+        env.define_type_alias("Point".to_string(), Type::Record(HashMap::from([("x".to_string(), Type::Int)])));
+        let bindings: HashMap<_, _> = env.bindings().map(|(name, scheme)| (name.clone(), scheme.clone())).collect();
+        let aliases: HashMap<_, _> = env.type_aliases().map(|(name, ty)| (name.clone(), ty.clone())).collect();
+        let constructors: HashMap<_, _> = env.constructors().map(|(name, info)| (name.clone(), info.clone())).collect();
+
+        let mut copy = TypeEnv::from_parts(bindings.clone(), aliases, constructors, env.next_var(), env.next_row_var());
+        assert_eq!((copy.next_var(), copy.next_row_var()), (env.next_var(), env.next_row_var()));
+        assert_eq!(copy.bindings().count(), bindings.len());
+        assert_eq!(copy.lookup_constructor("Pair"), env.lookup_constructor("Pair"));
+        assert_eq!(copy.resolve_type_alias("Point"), env.resolve_type_alias("Point"));
+        assert!(typecheck_in(&parse("Pair (min 1 2) 2").unwrap(), &mut copy).is_ok());
+    }
+
+    #[test]
+    fn test_from_parts_raises_counters_above_given_types() {
+        let ty = Type::RecordRow(HashMap::from([("x".to_string(), Type::Var(TypeVar(7)))]), RowVar(4));
+        let bindings = HashMap::from([("r".to_string(), TypeScheme { vars: vec![], row_vars: vec![], ty })]);
+        let mut env = TypeEnv::from_parts(bindings, HashMap::new(), HashMap::new(), 0, 0);
+        assert_eq!((env.next_var(), env.next_row_var()), (8, 5));
+        assert_eq!(env.fresh_var_pair().1, TypeVar(8));
+        assert_eq!(env.fresh_row_var(), RowVar(5));
+    }
+
+    #[test]
+    fn test_unify_arrays_of_unknown_size() {
+        let array = |elem: Type, size| Type::Array(Box::new(elem), size);
+        let var = Type::Var(TypeVar(0));
+        let s = unify(&array(var.clone(), 0), &array(Type::Int, 3)).unwrap();
+        assert_eq!(apply_subst(&s, &var), Type::Int);
+        assert!(unify(&array(Type::Int, 2), &array(Type::Int, 2)).is_ok());
+        assert!(unify(&array(Type::Int, 2), &array(Type::Int, 3)).is_err());
+        assert!(unify(&array(Type::Int, 0), &array(Type::Bool, 0)).is_err());
+    }
+
+    #[test]
+    fn test_unify_binds_row_variables() {
+        let fields = |names: &[&str]| -> HashMap<String, Type> { names.iter().map(|name| (name.to_string(), Type::Int)).collect() };
+        let open = Type::RecordRow(fields(&["x"]), RowVar(0));
+
+        let subst = unify(&open, &Type::Record(fields(&["x", "y"]))).unwrap();
+        assert_eq!(subst.rows, HashMap::from([(RowVar(0), Type::Record(fields(&["y"])))]));
+        assert_eq!(apply_subst(&subst, &open), Type::Record(fields(&["x", "y"])));
+
+        let wider = Type::RecordRow(fields(&["x", "z"]), RowVar(1));
+        let subst = unify(&open, &wider).unwrap();
+        assert_eq!(apply_subst(&subst, &open), apply_subst(&subst, &wider));
+        assert_eq!(apply_subst(&subst, &Type::Row(RowVar(0))), Type::RecordRow(fields(&["z"]), RowVar(1)));
+
+        assert!(unify(&Type::Row(RowVar(0)), &open).is_err());
+        assert_eq!(unify(&Type::Row(RowVar(0)), &Type::Row(RowVar(0))), Ok(Substitution::new()));
+    }
+
+    #[test]
+    fn test_typecheck_nodes_types_every_node() {
+        let expr = parse("let id = fun x -> x in id 1 + 2").unwrap();
+        let (ty, types) = typecheck_nodes(&expr, &mut TypeEnv::new()).unwrap();
+        assert_eq!(ty, Type::Int);
+        let shown: Vec<String> = types.iter().map(|ty| ty.as_ref().unwrap().to_string()).collect();
+        assert_eq!(shown, ["Int", "t0 -> t0", "t0", "Int", "Int", "Int -> Int", "Int", "Int"]);
+    }
+
+    #[test]
+    fn test_typecheck_with_spans_reports_the_innermost_spanned_node() {
+        let located = |source: &str| {
+            let (expr, spans) = crate::parser::parse_program_with_spans(source).unwrap();
+            let error = typecheck_with_spans(&expr, &spans, &mut TypeEnv::new()).unwrap_err();
+            let span = error.span().unwrap();
+            (source[span.start..span.end].to_string(), error.without_span().clone())
+        };
+        let (text, error) = located("let f = fun x -> x + 1 in\nlet g = fun y -> f y in\ng true");
+        assert_eq!(text, "g true");
+        assert!(matches!(error, TypeError::UnificationError(..)), "{error:?}");
+        let (text, _) = located("match 1 with\n| 0 -> 1\n| n -> n + 'c'");
+        assert_eq!(text, "n + 'c'");
+        // Errors the checker recognises keep their meaning under a span
+        let (_, error) = located("let rec f = fun x -> f in f");
+        assert!(matches!(error, TypeError::OccursCheckFailed(..)), "{error:?}");
+        // The span is only known through the side table
+        assert_eq!(check("g true").unwrap_err().span(), None);
+    }
+
+    #[test]
+    fn test_typecheck_nodes_leaves_missing_bodies_untyped() {
+        let expr = Expr::Seq(vec![("x".to_string(), None, Expr::Int(1))], Box::new(Expr::EmptyBody));
+        let (_, types) = typecheck_nodes(&expr, &mut TypeEnv::new()).unwrap();
+        assert_eq!(types.len(), expr.subexpressions().count());
+        assert_eq!(types[1], Some(Type::Int));
+    }
 }
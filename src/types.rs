@@ -3,6 +3,7 @@ use std::fmt;
 
 /// Type representations for the type system
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     /// Integer type
     Int,
@@ -17,6 +18,9 @@ pub enum Type {
     /// Unit type: ()
     /// Represents the type of the empty tuple, used for side effects
     Unit,
+    /// Tuple type: (T1, T2, ...)
+    /// Has at least one element; the empty tuple has type `Unit`
+    Tuple(Vec<Type>),
     /// Function type: T1 -> T2
     Fun(Box<Type>, Box<Type>),
     /// Type variable (for polymorphism): α, β, γ
@@ -45,19 +49,25 @@ pub enum Type {
     /// Range type: Range
     /// Represents an inclusive integer range from start to end
     Range,
+    /// Opaque host type, identified by the tag its values carry
+    /// Declared by an embedder with `TypeEnv::declare_opaque`
+    Opaque(String),
 }
 
 /// Type variable identifier
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeVar(pub usize);
 
 /// Row variable identifier for row polymorphism
 /// Represents "the rest of the fields" in a record type
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RowVar(pub usize);
 
 /// Type scheme for polymorphic types: ∀α.τ
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TypeScheme {
     /// Quantified type variables
     pub vars: Vec<TypeVar>,
@@ -76,6 +86,10 @@ impl fmt::Display for Type {
             Type::Float => write!(f, "Float"),
             Type::Byte => write!(f, "Byte"),
             Type::Unit => write!(f, "()"),
+            Type::Tuple(elements) => {
+                let elements: Vec<String> = elements.iter().map(ToString::to_string).collect();
+                write!(f, "({})", elements.join(", "))
+            }
             Type::Fun(arg, ret) => {
                 // Add parentheses around function arguments if they are also functions
                 match arg.as_ref() {
@@ -83,7 +97,7 @@ impl fmt::Display for Type {
                     _ => write!(f, "{arg} -> {ret}"),
                 }
             }
-            Type::Var(var) => write!(f, "t{}", var.0),
+            Type::Var(var) => write!(f, "{var}"),
             Type::Record(fields) => {
                 write!(f, "{{")?;
                 // Sort fields by name for consistent display
@@ -113,14 +127,18 @@ impl fmt::Display for Type {
                 if !fields.is_empty() {
                     write!(f, " | ")?;
                 }
-                write!(f, "r{}}}", row.0)
+                write!(f, "{row}}}")
             }
-            Type::Row(row) => write!(f, "r{}", row.0),
+            Type::Row(row) => write!(f, "{row}"),
             Type::SumType(name, args) => {
                 write!(f, "{name}")?;
-                if !args.is_empty() {
-                    for arg in args {
-                        write!(f, " {arg}")?;
+                for arg in args {
+                    // Parenthesize arguments that take arguments themselves, so
+                    // `List (Option Int)` reads back as the same type
+                    match arg {
+                        Type::SumType(_, inner) if !inner.is_empty() => write!(f, " ({arg})")?,
+                        Type::Fun(..) | Type::Ref(_) => write!(f, " ({arg})")?,
+                        _ => write!(f, " {arg}")?,
                     }
                 }
                 Ok(())
@@ -132,10 +150,23 @@ impl fmt::Display for Type {
                 write!(f, "Ref {inner}")
             }
             Type::Range => write!(f, "Range"),
+            Type::Opaque(tag) => write!(f, "{tag}"),
         }
     }
 }
 
+impl fmt::Display for TypeVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "t{}", self.0)
+    }
+}
+
+impl fmt::Display for RowVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
 impl fmt::Display for TypeScheme {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.vars.is_empty() && self.row_vars.is_empty() {
@@ -147,14 +178,14 @@ impl fmt::Display for TypeScheme {
                 if !first {
                     write!(f, ", ")?;
                 }
-                write!(f, "t{}", var.0)?;
+                write!(f, "{var}")?;
                 first = false;
             }
             for row_var in self.row_vars.iter() {
                 if !first {
                     write!(f, ", ")?;
                 }
-                write!(f, "r{}", row_var.0)?;
+                write!(f, "{row_var}")?;
                 first = false;
             }
             write!(f, ". {}", self.ty)
@@ -490,7 +521,11 @@ mod tests {
     fn test_display_sum_type_nested() {
         let inner = Type::SumType("Option".to_string(), vec![Type::Int]);
         let outer = Type::SumType("List".to_string(), vec![inner]);
-        assert_eq!(format!("{outer}"), "List Option Int");
+        assert_eq!(format!("{outer}"), "List (Option Int)");
+        let either = Type::SumType("Either".to_string(), vec![outer, Type::SumType("Unit".to_string(), vec![])]);
+        assert_eq!(format!("{either}"), "Either (List (Option Int)) Unit");
+        let funs = Type::SumType("List".to_string(), vec![Type::Fun(Box::new(Type::Int), Box::new(Type::Int))]);
+        assert_eq!(format!("{funs}"), "List (Int -> Int)");
     }
 
     #[test]
@@ -525,6 +560,14 @@ mod tests {
         assert!(RowVar(5) > RowVar(3));
     }
 
+    #[test]
+    fn test_display_type_and_row_vars() {
+        assert_eq!(TypeVar(3).to_string(), "t3");
+        assert_eq!(RowVar(1).to_string(), "r1");
+        assert_eq!(TypeVar(3).to_string(), Type::Var(TypeVar(3)).to_string());
+        assert_eq!(RowVar(1).to_string(), Type::Row(RowVar(1)).to_string());
+    }
+
     #[test]
     fn test_display_row() {
         let ty = Type::Row(RowVar(0));
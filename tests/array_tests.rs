@@ -184,3 +184,15 @@ fn test_array_display() {
     assert!(display.contains("|]"));
     assert!(display.contains("size: 3"));
 }
+
+#[test]
+fn test_indexing_chains_with_projection_and_application() {
+    assert_eq!(
+        parse_and_eval("let pairs = [|(1, 2), (3, 4)|] in pairs[1].0"),
+        Ok(Value::Int(3))
+    );
+    assert_eq!(
+        parse_and_eval("let inc = fun x -> x + 1 in let arr = [|41|] in inc arr[0]"),
+        Ok(Value::Int(42))
+    );
+}
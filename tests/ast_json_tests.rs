@@ -0,0 +1,68 @@
+#![cfg(feature = "serde")]
+/// Round trips of programs through the JSON form of the AST
+///
+/// Only built with the `serde` feature.
+use parlang::check::NO_TYPECHECK_MARKER;
+use parlang::{expr_from_json, parse, parse_to_json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// The `.par` files under `dir`, recursively
+fn par_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            par_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "par") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_program_files_round_trip() {
+    let mut files = Vec::new();
+    par_files(Path::new("examples"), &mut files);
+    par_files(Path::new("tests"), &mut files);
+    assert!(!files.is_empty());
+    // Parsing and comparing the larger files recurses deeply, as check-examples allows for
+    thread::Builder::new()
+        .stack_size(512 * 1024 * 1024)
+        .spawn(move || {
+            for path in files {
+                let source = fs::read_to_string(&path).unwrap();
+                let source = source.strip_prefix(NO_TYPECHECK_MARKER).unwrap_or(&source);
+                let expr = parse(source).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+                let json = parse_to_json(source).unwrap();
+                assert_eq!(expr_from_json(&json), Ok(expr), "{}", path.display());
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_every_construct_round_trips() {
+    for source in [
+        "let x: Int = 1 in x + 2 * 3 - 4 / 2",
+        "let f = fun (n: Int) -> n in f 1",
+        "rec f -> fun n -> if n == 0 then 1 elif n == 1 then 1 else n * f (n - 1)",
+        "let a = 1; let b = 2; a + b",
+        "let a = 1;",
+        "match (1, true) with | (0, _) -> 'z' | (n, false) -> 'f' | _ -> 'o'",
+        "function | [] -> 0 | x :: _ -> x",
+        "let p = { name: \"ann\", age: 3 } in match p with | { age: a } -> (p.name, a, (1, 2).0)",
+        "type Pair = Int -> Bool in 0",
+        "type Tree a = Leaf | Node (Tree a) a (Tree a) in Node Leaf 1 Leaf",
+        "let arr = [|1.5, 2.5|] in (arr[0], 7b, -3)",
+        "let r = ref 1 in let u = r := !r + 1 in (!r, 1..5)",
+        "try 1 / 0 catch err -> err.message ^ \"!\"",
+        "load \"examples/stdlib.par\" in double 2",
+        "\"x = {1 + 1}\"",
+    ] {
+        let expr = parse(source).unwrap_or_else(|e| panic!("{source}: {e}"));
+        assert_eq!(expr_from_json(&parse_to_json(source).unwrap()), Ok(expr), "{source}");
+    }
+}
@@ -0,0 +1,459 @@
+/// Integration tests for the prelude built-in functions
+use parlang::builtins::MEMO_CAPACITY;
+use parlang::eval::apply;
+use parlang::{eval, eval_with_config, parse, typecheck_in, Environment, EvalConfig, EvalError, Type, TypeEnv, Value};
+
+fn run(source: &str) -> Result<Value, EvalError> {
+    let expr = parse(source).unwrap();
+    eval(&expr, &Environment::with_builtins())
+}
+
+fn check(source: &str) -> Result<Type, String> {
+    let expr = parse(source).unwrap();
+    typecheck_in(&expr, &mut TypeEnv::with_builtins()).map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_min_max_int() {
+    assert_eq!(run("min 3 7"), Ok(Value::Int(3)));
+    assert_eq!(run("max 3 7"), Ok(Value::Int(7)));
+    assert_eq!(run("min (-2) (-2)"), Ok(Value::Int(-2)));
+}
+
+#[test]
+fn test_min_max_float_and_char() {
+    assert_eq!(run("max 1.5 0.25"), Ok(Value::Float(1.5)));
+    assert_eq!(run("float_min 1.5 0.25"), Ok(Value::Float(0.25)));
+    assert_eq!(run("min 'x' 'b'"), Ok(Value::Char('b')));
+    assert_eq!(run("char_max 'x' 'b'"), Ok(Value::Char('x')));
+}
+
+#[test]
+fn test_min_incomparable_kinds() {
+    assert!(matches!(run("min 1 2.0"), Err(EvalError::TypeError(msg)) if msg.contains("cannot compare")));
+    assert!(matches!(run("max true false"), Err(EvalError::TypeError(_))));
+}
+
+#[test]
+fn test_clamp() {
+    assert_eq!(run("clamp 0 10 5"), Ok(Value::Int(5)));
+    assert_eq!(run("clamp 0 10 (-3)"), Ok(Value::Int(0)));
+    assert_eq!(run("clamp 0 10 42"), Ok(Value::Int(10)));
+    assert_eq!(run("float_clamp 0.0 1.0 1.5"), Ok(Value::Float(1.0)));
+    assert_eq!(run("char_clamp 'a' 'z' 'A'"), Ok(Value::Char('a')));
+}
+
+#[test]
+fn test_clamp_boundaries() {
+    // Values exactly on a bound are kept
+    assert_eq!(run("clamp 0 10 0"), Ok(Value::Int(0)));
+    assert_eq!(run("clamp 0 10 10"), Ok(Value::Int(10)));
+    // A single-point range
+    assert_eq!(run("clamp 4 4 9"), Ok(Value::Int(4)));
+    // Inverted bounds are an error
+    assert!(matches!(run("clamp 10 0 5"), Err(EvalError::TypeError(msg)) if msg.contains("lower bound")));
+}
+
+#[test]
+fn test_partial_application() {
+    assert_eq!(run("let at_least_zero = max 0 in at_least_zero (-5)"), Ok(Value::Int(0)));
+    assert_eq!(run("let percent = clamp 0 100 in (percent 150, percent 50)"),
+        Ok(Value::Tuple(vec![Value::Int(100), Value::Int(50)])));
+}
+
+#[test]
+fn test_max_by_custom_predicate_over_tuples() {
+    // Order (name, score) pairs by score only
+    let source = "let by_score = fun a -> fun b -> a.1 < b.1 in \
+                  max_by by_score ('a', 10) ('b', 20)";
+    assert_eq!(run(source), Ok(Value::Tuple(vec![Value::Char('b'), Value::Int(20)])));
+}
+
+#[test]
+fn test_min_by_custom_predicate() {
+    let source = "let by_score = fun a -> fun b -> a.1 < b.1 in \
+                  min_by by_score ('a', 10) ('b', 20)";
+    assert_eq!(run(source), Ok(Value::Tuple(vec![Value::Char('a'), Value::Int(10)])));
+    // Ties keep the first argument
+    assert_eq!(run("min_by (fun a -> fun b -> false) 1 2"), Ok(Value::Int(1)));
+    assert_eq!(run("max_by (fun a -> fun b -> false) 1 2"), Ok(Value::Int(1)));
+}
+
+#[test]
+fn test_max_by_predicate_must_return_bool() {
+    assert!(matches!(run("max_by (fun a -> fun b -> 1) 1 2"), Err(EvalError::TypeError(_))));
+}
+
+#[test]
+fn test_builtin_types() {
+    assert_eq!(check("min 1 2"), Ok(Type::Int));
+    assert_eq!(check("clamp 0 10 5"), Ok(Type::Int));
+    assert_eq!(check("float_max 1.0 2.0"), Ok(Type::Float));
+    assert_eq!(check("char_min 'a' 'b'"), Ok(Type::Char));
+    assert!(check("min 1.0 2.0").is_err());
+    assert_eq!(check("max_by (fun a -> fun b -> a < b) 1 2"), Ok(Type::Int));
+    assert_eq!(
+        check("min_by (fun a -> fun b -> a.score < b.score) { score: 1 } { score: 2 }"),
+        Ok(Type::Record(std::collections::HashMap::from([("score".to_string(), Type::Int)])))
+    );
+}
+
+#[test]
+fn test_builtins_can_be_shadowed() {
+    assert_eq!(run("let min = fun a -> fun b -> 0 in min 3 7"), Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_builtins_absent_from_empty_environment() {
+    let expr = parse("min 1 2").unwrap();
+    assert!(matches!(eval(&expr, &Environment::new()), Err(EvalError::UnboundVariable(..))));
+}
+
+/// Declares the `List` type that string literals desugar to
+const LIST: &str = "type List a = Nil | Cons a (List a) in ";
+
+fn string(s: &str) -> Value {
+    s.chars().rev().fold(Value::Variant("Nil".to_string(), vec![]), |rest, c| {
+        Value::Variant("Cons".to_string(), vec![Value::Char(c), rest])
+    })
+}
+
+#[test]
+fn test_tuple_to_array() {
+    assert_eq!(
+        run("tuple_to_array (1, 2, 3)"),
+        Ok(Value::Array(3, vec![Value::Int(1), Value::Int(2), Value::Int(3)]))
+    );
+    assert_eq!(run("(tuple_to_array ('a', 'b'))[1]"), Ok(Value::Char('b')));
+    // Variants of different constructors are the same kind
+    assert!(matches!(
+        run("type Option a = Some a | None in tuple_to_array (Some 1, None)"),
+        Ok(Value::Array(2, _))
+    ));
+    assert_eq!(run("tuple_to_array ()"), Ok(Value::Array(0, vec![])));
+}
+
+#[test]
+fn test_tuple_to_array_errors() {
+    assert!(matches!(
+        run("tuple_to_array (1, true, 3)"),
+        Err(EvalError::TypeError(msg)) if msg.contains("element 0 is Int and element 1 is Bool")
+    ));
+    assert!(matches!(
+        run("tuple_to_array [|1, 2|]"),
+        Err(EvalError::TypeError(msg)) if msg.contains("expected a tuple")
+    ));
+}
+
+#[test]
+fn test_array_to_tuple() {
+    assert_eq!(
+        run("array_to_tuple [|1, 2|]"),
+        Ok(Value::Tuple(vec![Value::Int(1), Value::Int(2)]))
+    );
+    assert_eq!(run("(array_to_tuple [|'x', 'y', 'z'|]).2"), Ok(Value::Char('z')));
+    assert_eq!(run("array_to_tuple (tuple_to_array (4, 5))"), run("(4, 5)"));
+}
+
+#[test]
+fn test_array_to_tuple_errors() {
+    assert!(matches!(
+        run("array_to_tuple [|1|]"),
+        Err(EvalError::TypeError(msg)) if msg.contains("has 1 elements")
+    ));
+    assert!(matches!(
+        run("array_to_tuple [|1, 2, 3, 4, 5, 6, 7, 8, 9|]"),
+        Err(EvalError::TypeError(msg)) if msg.contains("2 to 8")
+    ));
+    assert!(matches!(run("array_to_tuple (1, 2)"), Err(EvalError::TypeError(_))));
+}
+
+#[test]
+fn test_field_names() {
+    assert_eq!(
+        run("field_names { name: 'a', age: 3, id: 7 }"),
+        Ok(Value::Array(3, vec![string("age"), string("id"), string("name")]))
+    );
+    assert_eq!(run("(field_names { x: 1 })[0]"), Ok(string("x")));
+    assert_eq!(run("field_names {}"), Ok(Value::Array(0, vec![])));
+    assert!(matches!(run("field_names (1, 2)"), Err(EvalError::RecordExpected(_))));
+}
+
+#[test]
+fn test_has_field() {
+    assert_eq!(run(&format!("{LIST}has_field {{ x: 1, y: 2 }} \"y\"")), Ok(Value::Bool(true)));
+    assert_eq!(run(&format!("{LIST}has_field {{ x: 1, y: 2 }} \"z\"")), Ok(Value::Bool(false)));
+    assert_eq!(run(&format!("{LIST}has_field {{}} \"\"")), Ok(Value::Bool(false)));
+    assert!(matches!(run(&format!("{LIST}has_field 42 \"x\"")), Err(EvalError::RecordExpected(_))));
+    assert!(matches!(
+        run("has_field { x: 1 } 'x'"),
+        Err(EvalError::TypeError(msg)) if msg.contains("field name string")
+    ));
+}
+
+#[test]
+fn test_conversion_builtin_types() {
+    assert_eq!(check(&format!("{LIST}has_field {{ x: 1 }} \"x\"")), Ok(Type::Bool));
+    assert!(check(&format!("{LIST}has_field 1 \"x\"")).is_err());
+    assert_eq!(
+        check("(field_names { x: 1, y: true })[0]"),
+        Ok(Type::SumType("List".to_string(), vec![Type::Char]))
+    );
+    // Tuple/array conversions are checked at runtime only
+    assert!(check("tuple_to_array (1, 2)").is_ok());
+}
+
+#[test]
+fn test_field_names_typed_use() {
+    // The unknown size unifies with the array a user function indexes
+    let source = "let first = fun xs -> xs[0] in first (field_names { a: 1 })";
+    assert_eq!(check(source), Ok(Type::SumType("List".to_string(), vec![Type::Char])));
+    assert_eq!(run(source), Ok(string("a")));
+    let source = "let pick = fun xs -> fun i -> xs[i] in pick (field_names { a: 1, b: true }) 1";
+    assert_eq!(check(source), Ok(Type::SumType("List".to_string(), vec![Type::Char])));
+    assert_eq!(run(source), Ok(string("b")));
+    // But its elements are still strings
+    assert!(check("let first = fun xs -> xs[0] + 1 in first (field_names { a: 1 })").is_err());
+}
+
+#[test]
+fn test_show() {
+    assert_eq!(run("show 42"), Ok(string("42")));
+    assert_eq!(run("show (1, true)"), Ok(string("(1, true)")));
+    assert_eq!(run("show 'c'"), Ok(string("c")));
+    assert_eq!(run(&format!("{LIST}show \"text\"")), Ok(string("text")));
+}
+
+#[test]
+fn test_approx_eq_truth_table() {
+    let mut env = Environment::with_builtins();
+    env.bind("nan".to_string(), Value::Float(f64::NAN));
+    env.bind("inf".to_string(), Value::Float(f64::INFINITY));
+    env.bind("neg_inf".to_string(), Value::Float(f64::NEG_INFINITY));
+    let approx = |source: &str| eval(&parse(source).unwrap(), &env);
+    let cases = [
+        ("approx_eq 1.0 1.0 0.0", true),
+        ("approx_eq 0.1 0.10000001 0.000001", true),
+        ("approx_eq 1.0 1.1 0.01", false),
+        // The tolerance is inclusive
+        ("approx_eq 1.0 1.5 0.5", true),
+        ("approx_eq 1.5 1.0 0.5", true),
+        ("approx_eq 1.0 1.5 0.4999", false),
+        ("approx_eq nan nan 1.0", false),
+        ("approx_eq nan 1.0 inf", false),
+        ("approx_eq 1.0 nan inf", false),
+        ("approx_eq inf inf 0.0", true),
+        ("approx_eq neg_inf neg_inf 0.0", true),
+        ("approx_eq inf neg_inf inf", false),
+        ("approx_eq inf 1000000.0 inf", false),
+        ("approx_eq 1.0 2.0 inf", true),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(approx(source), Ok(Value::Bool(expected)), "{source}");
+    }
+    assert!(matches!(approx("approx_eq 1.0 1.0 (0.0 - 1.0)"), Err(EvalError::TypeError(msg)) if msg.contains("negative")));
+    assert_eq!(check("approx_eq 1.0 2.0 0.5"), Ok(Type::Bool));
+    assert!(check("approx_eq 1 2 0").is_err());
+}
+
+#[test]
+fn test_string_interpolation() {
+    let source = format!("{LIST}{}", r#"let name = "Ada" in let n = 2 in "{name} has {n * 3} {"items"} {{ok}}""#);
+    assert_eq!(run(&source), Ok(string("Ada has 6 items {ok}")));
+    assert_eq!(check(&source), Ok(Type::SumType("List".to_string(), vec![Type::Char])));
+    assert_eq!(run(&format!("{LIST}{}", r#""a" ^ "b" ^ "" ^ "c""#)), Ok(string("abc")));
+    assert!(check(&format!("{LIST}{}", r#""a" ^ 1"#)).is_err());
+    assert!(matches!(run("1 ^ 2"), Err(EvalError::TypeError(_))));
+}
+
+/// Run `test` on a thread with a large stack
+///
+/// Each level of recursion in the evaluator takes several large stack frames
+/// in debug builds, more than a test thread's default stack holds for fib.
+fn with_large_stack(test: impl FnOnce() + Send + 'static) {
+    let handle = std::thread::Builder::new().stack_size(256 * 1024 * 1024).spawn(test).unwrap();
+    if let Err(panic) = handle.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+#[test]
+fn test_memo_fix_fib() {
+    with_large_stack(|| {
+        let step = "fun fib -> fun n -> if n < 2 then n else fib (n - 1) + fib (n - 2)";
+        let naive = "rec fib -> fun n -> if n < 2 then n else fib (n - 1) + fib (n - 2)";
+        for n in 0..15 {
+            assert_eq!(run(&format!("memo_fix ({step}) {n}")), run(&format!("({naive}) {n}")), "fib {n}");
+            assert_eq!(run(&format!("memo ({naive}) {n}")), run(&format!("({naive}) {n}")), "fib {n}");
+        }
+        // Exponential without the cache; one call per n with it
+        let mut env = Environment::with_builtins();
+        env.set_step_limit(Some(100_000));
+        assert_eq!(eval(&parse(&format!("memo_fix ({step}) 35")).unwrap(), &env), Ok(Value::Int(9_227_465)));
+
+        let fib_type = Type::Fun(Box::new(Type::Int), Box::new(Type::Int));
+        assert_eq!(check(&format!("memo_fix ({step})")), Ok(fib_type.clone()));
+        assert_eq!(check(&format!("memo ({naive})")), Ok(fib_type));
+        assert!(check("memo (fun b -> if b then 1 else 0)").is_err());
+    });
+}
+
+#[test]
+fn test_memo_errors_and_cache_bound() {
+    assert!(matches!(run("memo 1"), Err(EvalError::TypeError(msg)) if msg.contains("expected a function")));
+    assert!(matches!(
+        run("memo (fun c -> c) 'a'"),
+        Err(EvalError::TypeError(msg)) if msg == "memo: memoized functions take an Int argument, got 'a'"
+    ));
+
+    let square = run("memo (fun n -> n * n)").unwrap();
+    let Value::Builtin(builtin) = &square else { panic!("expected a builtin, got {square}") };
+    for n in 0..100_000 {
+        assert_eq!(apply(square.clone(), Value::Int(n)), Ok(Value::Int(n * n)));
+    }
+    assert_eq!(builtin.cache_len(), Some(MEMO_CAPACITY));
+    assert_eq!(apply(square.clone(), Value::Int(3)), Ok(Value::Int(9)));
+    assert_eq!(builtin.cache_len(), Some(MEMO_CAPACITY));
+}
+
+#[test]
+fn test_abs() {
+    assert_eq!(run("abs (-4)"), Ok(Value::Int(4)));
+    assert_eq!(run("abs 4"), Ok(Value::Int(4)));
+    assert_eq!(run("float_abs (-2.5)"), Ok(Value::Float(2.5)));
+    assert_eq!(
+        run("abs (-9223372036854775807 - 1)"),
+        Err(EvalError::TypeError("Integer overflow in abs".to_string()))
+    );
+    assert!(matches!(run("abs true"), Err(EvalError::TypeError(msg)) if msg.contains("expected an Int or a Float")));
+    assert_eq!(check("abs"), Ok(Type::Fun(Box::new(Type::Int), Box::new(Type::Int))));
+    assert!(check("abs 1.5").is_err());
+}
+
+#[test]
+fn test_int_of_bool() {
+    assert_eq!(run("int_of_bool true + int_of_bool false"), Ok(Value::Int(1)));
+    assert_eq!(check("int_of_bool"), Ok(Type::Fun(Box::new(Type::Bool), Box::new(Type::Int))));
+    assert!(check("int_of_bool 1").is_err());
+    assert_eq!(run("bool_to_int true"), Ok(Value::Int(1)));
+    assert_eq!(check("bool_to_int"), check("int_of_bool"));
+}
+
+#[test]
+fn test_ord_and_chr() {
+    assert_eq!(run("ord 'a'"), Ok(Value::Int(97)));
+    assert_eq!(run("chr 955"), Ok(Value::Char('λ')));
+    assert_eq!(run("chr (ord 'x' + 1)"), Ok(Value::Char('y')));
+    assert_eq!(check("ord 'a'"), Ok(Type::Int));
+    assert_eq!(check("chr"), Ok(Type::Fun(Box::new(Type::Int), Box::new(Type::Char))));
+    assert!(check("ord 97").is_err());
+}
+
+#[test]
+fn test_chr_rejects_invalid_code_points() {
+    for source in ["chr (-1)", "chr 55296", "chr 1114112", "chr 4294967393"] {
+        assert!(
+            matches!(run(source), Err(EvalError::TypeError(msg)) if msg.contains("not a valid code point")),
+            "{source}"
+        );
+    }
+    assert_eq!(run("chr 1114111"), Ok(Value::Char('\u{10FFFF}')));
+}
+
+#[test]
+fn test_conversions_are_first_class() {
+    let map = "let rec map = fun f -> fun xs -> match xs with | Nil -> Nil | Cons x rest -> Cons (f x) (map f rest) in ";
+    assert_eq!(run(&format!("{map}map ord \"ab\"")), run("[97, 98]"));
+    assert_eq!(run(&format!("{map}map chr [104, 105]")), run("\"hi\""));
+    assert_eq!(check(&format!("{map}map int_to_float [1, 2]")), check("[1.0, 2.0]"));
+    assert_eq!(run("let to_char = chr in to_char 65"), Ok(Value::Char('A')));
+}
+
+#[test]
+fn test_int_float_conversions() {
+    assert_eq!(run("int_to_float 3"), Ok(Value::Float(3.0)));
+    assert_eq!(run("float_to_int 2.9"), Ok(Value::Int(2)));
+    assert_eq!(run("float_to_int (-2.9)"), Ok(Value::Int(-2)));
+    assert_eq!(run("float_to_int (int_to_float (-9223372036854775807 - 1))"), Ok(Value::Int(i64::MIN)));
+    for source in ["float_to_int (0.0 / 0.0)", "float_to_int (1.0 / 0.0)", "float_to_int 9223372036854775808.0"] {
+        assert!(
+            matches!(run(source), Err(EvalError::TypeError(msg)) if msg.contains("out of the Int range")),
+            "{source}"
+        );
+    }
+    assert_eq!(check("float_to_int 1.5"), Ok(Type::Int));
+    assert!(check("int_to_float 1.5").is_err());
+}
+
+#[test]
+fn test_print_returns_its_argument() {
+    assert_eq!(run("print 5 + 1"), Ok(Value::Int(6)));
+    assert_eq!(run("print (1, true)"), run("(1, true)"));
+    assert_eq!(check("print 'a'"), Ok(Type::Char));
+    assert_eq!(check("(print min) 1 2"), Ok(Type::Int));
+}
+
+/// Evaluate a program with the debug built-ins enabled
+fn run_debug(source: &str) -> Result<Value, EvalError> {
+    let env = Environment::with_builtins();
+    env.set_debug_builtins(true);
+    eval(&parse(source).unwrap(), &env)
+}
+
+/// The sorted field names of a record value
+fn field_names(value: &Value) -> Vec<String> {
+    let Value::Record(fields) = value else { panic!("expected a record, got {value}") };
+    let mut names: Vec<String> = fields.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn test_debug_env_lists_bindings_in_scope() {
+    let source = "let outer = 1 in \
+                  let f = fun x -> let inner = x + outer in debug_env () in \
+                  let later = 2 in \
+                  f 5";
+    let record = run_debug(source).unwrap();
+    assert_eq!(field_names(&record), ["inner", "outer", "x"]);
+    let Value::Record(fields) = record else { unreachable!() };
+    assert_eq!(fields["inner"], Value::Int(6));
+    assert_eq!(fields["x"], Value::Int(5));
+}
+
+#[test]
+fn test_debug_env_renders_functions() {
+    let record = run_debug("let g = fun y -> y in let pair = (g, 1) in debug_env ()").unwrap();
+    let Value::Record(fields) = record else { panic!("expected a record") };
+    assert_eq!(fields["g"].as_string().as_deref(), Some("<function y>"));
+    let Value::Tuple(pair) = &fields["pair"] else { panic!("expected a tuple") };
+    assert_eq!(pair[0].as_string().as_deref(), Some("<function y>"));
+    assert_eq!(pair[1], Value::Int(1));
+
+    // The parameter a pattern is desugared with is neither listed nor shown
+    let record = run_debug("let g = fun (a, _) -> debug_env () in let h = fun _ -> 0 in (g (1, 2), h)").unwrap();
+    let Value::Tuple(pair) = record else { panic!("expected a tuple") };
+    assert_eq!(field_names(&pair[0]), ["a"]);
+    assert_eq!(pair[1].to_string(), "<function>");
+}
+
+#[test]
+fn test_debug_env_field_access_type_checks() {
+    assert!(check("let x = 1 in (debug_env ()).x").is_ok());
+    assert!(check("debug_env 1").is_err());
+    assert_eq!(run_debug("let x = 41 in (debug_env ()).x + 1"), Ok(Value::Int(42)));
+}
+
+#[test]
+fn test_debug_env_disabled_by_default() {
+    match run("let x = 1 in debug_env ()") {
+        Err(EvalError::TypeError(msg)) => assert!(msg.contains("--debug-builtins"), "{msg}"),
+        other => panic!("expected an error, got {other:?}"),
+    }
+    let expr = parse("let x = 1 in debug_env ()").unwrap();
+    let config = EvalConfig { debug_builtins: true, ..EvalConfig::default() };
+    let env = Environment::with_builtins();
+    assert_eq!(eval_with_config(&expr, &env, &config).map(|record| field_names(&record)), Ok(vec!["x".to_string()]));
+    assert!(!env.debug_builtins());
+    assert!(eval(&expr, &env).is_err());
+}
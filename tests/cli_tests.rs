@@ -1,7 +1,13 @@
 /// CLI integration tests
 /// These tests verify the command-line interface functionality
+///
+/// They run the `parlang` binary Cargo built for this test run
+/// (`CARGO_BIN_EXE_parlang`) rather than `cargo run`, so the binary has the
+/// same features as the tests, e.g. `--dump-ast-json` under `--features serde`.
 use std::fs;
-use std::process::Command;
+use std::path::Path;
+use std::io::Write;
+use std::process::{Command, Stdio};
 use std::env;
 
 #[test]
@@ -11,8 +17,8 @@ fn test_cli_file_execution() {
     fs::write(&test_file, "1 + 2 + 3").unwrap();
 
     // Execute the file with the CLI
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", test_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[test_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -28,8 +34,8 @@ fn test_cli_file_execution() {
 fn test_cli_file_not_found() {
     // Try to execute a non-existent file
     let nonexistent = env::temp_dir().join("nonexistent_file.par");
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", nonexistent.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[nonexistent.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -44,8 +50,8 @@ fn test_cli_parse_error() {
     let test_file = env::temp_dir().join("test_parse_error.par");
     fs::write(&test_file, "let x = in y").unwrap();
 
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", test_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[test_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -54,7 +60,8 @@ fn test_cli_parse_error() {
 
     assert!(!output.status.success());
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("Parse error"));
+    assert!(stderr.contains("parse error at line 1, column 9: expected an expression, found 'in'"), "{stderr}");
+    assert!(stderr.contains("1 | let x = in y\n  |         ^"), "{stderr}");
 }
 
 #[test]
@@ -63,8 +70,8 @@ fn test_cli_eval_error() {
     let test_file = env::temp_dir().join("test_eval_error.par");
     fs::write(&test_file, "1 / 0").unwrap();
 
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", test_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[test_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -83,11 +90,8 @@ fn test_cli_dump_ast() {
     let dot_file = env::temp_dir().join("test_ast.dot");
     fs::write(&test_file, "1 + 2").unwrap();
 
-    let output = Command::new("cargo")
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
         .args(&[
-            "run",
-            "--quiet",
-            "--",
             test_file.to_str().unwrap(),
             "--dump-ast",
             dot_file.to_str().unwrap(),
@@ -113,8 +117,8 @@ fn test_cli_dump_ast() {
 fn test_cli_dump_ast_without_file() {
     // Try to dump AST without providing a source file
     let dot_file = env::temp_dir().join("test.dot");
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", "--dump-ast", dot_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&["--dump-ast", dot_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -136,8 +140,8 @@ fn test_cli_complex_program() {
     ";
     fs::write(&test_file, program).unwrap();
 
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", test_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[test_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -161,8 +165,8 @@ x + y + z
     ";
     fs::write(&test_file, program).unwrap();
 
-    let output = Command::new("cargo")
-        .args(&["run", "--quiet", "--", test_file.to_str().unwrap()])
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(&[test_file.to_str().unwrap()])
         .output()
         .expect("Failed to execute command");
 
@@ -173,3 +177,852 @@ x + y + z
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert_eq!(stdout.trim(), "60");
 }
+
+#[test]
+fn test_cli_float_precision() {
+    let test_file = env::temp_dir().join("test_float_precision.par");
+    fs::write(&test_file, "let rate = 0.1; (rate * 3.0, 2.5)").unwrap();
+
+    let exact = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([test_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    let rounded = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([test_file.to_str().unwrap(), "--float-precision", "6"])
+        .output()
+        .expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    assert!(exact.status.success());
+    assert_eq!(String::from_utf8_lossy(&exact.stdout).trim(), "(0.30000000000000004, 2.5)");
+    assert!(rounded.status.success());
+    assert_eq!(String::from_utf8_lossy(&rounded.stdout).trim(), "(0.3, 2.5)");
+}
+
+#[test]
+fn test_cli_dump_ast_with_limit() {
+    // A flat tuple of 10_000 elements: 10_001 nodes
+    let source = format!("({})", vec!["1"; 10_000].join(", "));
+    let test_file = env::temp_dir().join("test_dump_limit.par");
+    let dot_file = env::temp_dir().join("test_dump_limit.dot");
+    fs::write(&test_file, source).unwrap();
+
+    let start = std::time::Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([
+            test_file.to_str().unwrap(),
+            "--dump-ast",
+            dot_file.to_str().unwrap(),
+            "--dump-limit",
+            "100",
+        ])
+        .output()
+        .expect("Failed to execute command");
+    let elapsed = start.elapsed();
+
+    let dot_content = fs::read_to_string(&dot_file).unwrap_or_default();
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+    let _ = fs::remove_file(&dot_file);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("(1, 1, 1"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("truncated to 100 of 10001 nodes"));
+
+    assert!(dot_content.contains("100 of 10001 nodes shown"));
+    let node_entries = dot_content
+        .lines()
+        .filter(|line| line.trim_start().starts_with("node") && !line.contains("->"))
+        .count();
+    assert!(node_entries <= 101, "too many nodes: {node_entries}");
+    assert!(elapsed.as_secs() < 30, "dump took {elapsed:?}");
+}
+
+#[test]
+fn test_cli_dump_ast_creates_missing_directories() {
+    let test_file = env::temp_dir().join("test_dump_nested.par");
+    let out_dir = env::temp_dir().join("test_dump_nested_out");
+    let dot_file = out_dir.join("a").join("b").join("ast.dot");
+    let _ = fs::remove_dir_all(&out_dir);
+    fs::write(&test_file, "1 + 2").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([test_file.to_str().unwrap(), "--dump-ast", dot_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+    let dot_content = fs::read_to_string(&dot_file).unwrap_or_default();
+
+    let _ = fs::remove_file(&test_file);
+    let _ = fs::remove_dir_all(&out_dir);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dot_content.starts_with("digraph AST {"));
+}
+
+#[test]
+fn test_cli_dump_ast_to_stdout() {
+    let test_file = env::temp_dir().join("test_dump_stdout.par");
+    fs::write(&test_file, "let u = print 5 in 1 + 2").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([test_file.to_str().unwrap(), "--dump-ast", "-"])
+        .output()
+        .expect("Failed to execute command");
+
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("digraph AST {"), "{stdout}");
+    assert!(stdout.ends_with("}\n"), "{stdout}");
+    assert_eq!(stdout.matches("digraph").count(), 1);
+    assert!(!stdout.lines().any(|line| line == "5" || line == "3"), "{stdout}");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("AST dumped to"));
+}
+
+#[test]
+fn test_cli_dump_ast_after_optimizing() {
+    let test_file = env::temp_dir().join("test_dump_optimized.par");
+    fs::write(&test_file, "let x = 5 in x * 2 + 1").unwrap();
+
+    let dump = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .arg(&test_file)
+            .args(["--dump-ast", "-"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let before = dump(&[]);
+    let after = dump(&["--optimize"]);
+
+    let _ = fs::remove_file(&test_file);
+
+    assert!(before.contains("label=\"Let\\nx\""), "{before}");
+    assert_eq!(after.matches("label=").count(), 1, "{after}");
+    assert!(after.contains("label=\"Int\\n11\""), "{after}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_cli_dump_ast_json() {
+    let dir = env::temp_dir().join("test_dump_ast_json");
+    let test_file = dir.join("program.par");
+    let json_file = dir.join("out").join("ast.json");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&test_file, "let u = print 5 in 1 + 2").unwrap();
+
+    let run = |target: &str| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .arg(&test_file)
+            .args(["--dump-ast-json", target])
+            .output()
+            .expect("Failed to execute command")
+    };
+    let to_stdout = run("-");
+    let to_file = run(json_file.to_str().unwrap());
+    let written = fs::read_to_string(&json_file);
+
+    let _ = fs::remove_dir_all(&dir);
+
+    let expected = parlang::parse_to_json("let u = print 5 in 1 + 2").unwrap();
+    assert!(to_stdout.status.success());
+    assert_eq!(String::from_utf8_lossy(&to_stdout.stdout), format!("{expected}\n"));
+    assert!(to_file.status.success());
+    assert_eq!(String::from_utf8_lossy(&to_file.stdout), "5\n3\n");
+    assert_eq!(written.unwrap(), expected);
+}
+
+#[test]
+fn test_cli_dump_limit_counts_pattern_nodes() {
+    let source = "let x = (1, 2, 3, 4, 5, 6, 7) in match x with | (a, b, c, d, e, f, g) -> 1";
+    let test_file = env::temp_dir().join("test_dump_limit_patterns.par");
+    fs::write(&test_file, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .arg(&test_file)
+        .args(["--dump-ast", "-", "--dump-limit", "5"])
+        .output()
+        .expect("Failed to execute command");
+    let _ = fs::remove_file(&test_file);
+
+    // The warning and the sentinel count the DOT nodes the limit counts
+    let total = parlang::dot::dot_node_count(&parlang::parse(source).unwrap());
+    assert_eq!(total, 21);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("5 of {total} nodes shown")));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("truncated to 5 of {total} nodes")));
+}
+
+#[test]
+fn test_cli_dump_limit_on_a_deep_ast() {
+    let test_file = env::temp_dir().join("test_dump_limit_deep.par");
+    let dot_file = env::temp_dir().join("test_dump_limit_deep.dot");
+    fs::write(&test_file, vec!["1"; 60_000].join("+")).unwrap();
+
+    // Counting the nodes for the warning must not draw the whole tree
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .arg(&test_file)
+        .arg("--dump-ast")
+        .arg(&dot_file)
+        .args(["--dump-limit", "100"])
+        .output()
+        .expect("Failed to execute command");
+    let written = fs::read_to_string(&dot_file);
+    let _ = fs::remove_file(&test_file);
+    let _ = fs::remove_file(&dot_file);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "60000\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated to 100 of 119999 nodes"));
+    assert!(written.unwrap().contains("100 of 119999 nodes shown"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_cli_dump_ast_json_with_limit() {
+    let test_file = env::temp_dir().join("test_dump_json_limit.par");
+    fs::write(&test_file, format!("({})", vec!["1"; 10_000].join(", "))).unwrap();
+
+    // --dump-limit applies to the JSON dump without --dump-ast
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .arg(&test_file)
+        .args(["--dump-ast-json", "-", "--dump-limit", "100"])
+        .output()
+        .expect("Failed to execute command");
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json = String::from_utf8_lossy(&output.stdout);
+    assert!(json.trim_end().ends_with(r#""truncated":true,"node_count":10001}"#), "{json}");
+    assert_eq!(json.matches(r#"{"Int":1}"#).count(), 99);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("truncated to 100 of 10001 nodes"));
+}
+
+#[test]
+fn test_cli_dump_typed_ast() {
+    let dir = env::temp_dir().join("test_dump_typed_ast");
+    let test_file = dir.join("program.par");
+    let ill_typed = dir.join("ill_typed.par");
+    let dot_file = dir.join("out").join("typed.dot");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&test_file, "let u = print 5 in 1 + 2").unwrap();
+    fs::write(&ill_typed, "1 + true").unwrap();
+
+    let run = |file: &Path, target: &str| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .arg(file)
+            .args(["--dump-typed-ast", target])
+            .output()
+            .expect("Failed to execute command")
+    };
+    let to_stdout = run(&test_file, "-");
+    let to_file = run(&test_file, dot_file.to_str().unwrap());
+    let written = fs::read_to_string(&dot_file);
+    let rejected = run(&ill_typed, dir.join("rejected.dot").to_str().unwrap());
+    let rejected_written = dir.join("rejected.dot").exists();
+
+    let _ = fs::remove_dir_all(&dir);
+
+    let expected = parlang::dot::typed_ast_to_dot(&parlang::parse("let u = print 5 in 1 + 2").unwrap(), &parlang::TypeEnv::with_builtins()).unwrap();
+    assert!(to_stdout.status.success());
+    assert_eq!(String::from_utf8_lossy(&to_stdout.stdout), expected);
+    assert!(to_file.status.success());
+    assert_eq!(String::from_utf8_lossy(&to_file.stdout), "5\n3\n");
+    assert_eq!(written.unwrap(), expected);
+    assert!(!rejected.status.success());
+    assert!(String::from_utf8_lossy(&rejected.stderr).starts_with("Type error: "));
+    assert!(rejected.stdout.is_empty());
+    assert!(!rejected_written);
+}
+
+#[test]
+fn test_cli_fmt() {
+    let dir = env::temp_dir().join("test_cli_fmt");
+    let test_file = dir.join("program.par");
+    let marked = dir.join("marked.par");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(&test_file, "let   x = (1 + 2)  in\n  match x with | 3 -> \"three\" | _ -> \"other\"").unwrap();
+    fs::write(&marked, "-- no-typecheck\n(1)").unwrap();
+
+    let run = |args: &[&str], file: &Path| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .arg("fmt")
+            .args(args)
+            .arg(file)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let checked = run(&["--check"], &test_file);
+    let unchanged = fs::read_to_string(&test_file).unwrap();
+    let formatted = run(&[], &test_file);
+    let rewritten = fs::read_to_string(&test_file).unwrap();
+    let rechecked = run(&["--check"], &test_file);
+    let narrow = run(&["--check", "--width", "10"], &test_file);
+    let marked_formatted = run(&[], &marked);
+    let marked_rewritten = fs::read_to_string(&marked).unwrap();
+
+    let _ = fs::remove_dir_all(&dir);
+
+    let expected = "let x = 1 + 2 in\nmatch x with\n| 3 -> \"three\"\n| _ -> \"other\"\n";
+    assert_eq!(checked.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&checked.stdout), expected);
+    assert!(unchanged.starts_with("let   x"));
+    assert!(formatted.status.success());
+    assert_eq!(rewritten, expected);
+    assert!(rechecked.status.success());
+    assert_eq!(String::from_utf8_lossy(&rechecked.stdout), expected);
+    assert_eq!(narrow.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&narrow.stdout).starts_with("let x =\n  1 + 2\nin\n"));
+    assert!(marked_formatted.status.success());
+    assert_eq!(marked_rewritten, "-- no-typecheck\n1\n");
+}
+
+#[test]
+fn test_cli_fmt_minimal() {
+    let test_file = env::temp_dir().join("test_cli_fmt_minimal.par");
+    let aligned = "-- no-typecheck\nlet table = [(1,   \"one\"),\n             (20,  \"twenty\")];\n";
+    fs::write(&test_file, format!("{aligned}let   n = (1 + (2));\nn")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(["fmt", "--minimal"])
+        .arg(&test_file)
+        .output()
+        .expect("Failed to execute command");
+    let rewritten = fs::read_to_string(&test_file).unwrap();
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success());
+    assert_eq!(rewritten, format!("{aligned}let n = 1 + 2;\nn\n"));
+}
+
+#[test]
+fn test_cli_dump_ast_unwritable_path() {
+    let test_file = env::temp_dir().join("test_dump_unwritable.par");
+    let dot_dir = env::temp_dir().join("test_dump_unwritable_dir");
+    fs::write(&test_file, "1 + 2").unwrap();
+    fs::create_dir_all(&dot_dir).unwrap();
+
+    // A relative path naming a directory, resolved against the working directory
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args([test_file.to_str().unwrap(), "--dump-ast", "test_dump_unwritable_dir"])
+        .current_dir(env::temp_dir())
+        .output()
+        .expect("Failed to execute command");
+
+    let _ = fs::remove_file(&test_file);
+    let _ = fs::remove_dir(&dot_dir);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Failed to write DOT file 'test_dump_unwritable_dir'"), "{stderr}");
+    assert!(stderr.contains(&dot_dir.display().to_string()), "{stderr}");
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_cli_prune_unused_verbose_load() {
+    let library = env::temp_dir().join("test_prune_cli_lib.par");
+    let test_file = env::temp_dir().join("test_prune_cli.par");
+    fs::write(&library, "let helper = fun x -> x + 1;\nlet used = fun x -> helper x;\nlet unused = 1 / 0;\n").unwrap();
+    fs::write(&test_file, format!("load \"{}\" in used 41", library.display())).unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args([test_file.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let unpruned = run(&[]);
+    let pruned = run(&["--prune-unused", "--verbose-load"]);
+
+    // Clean up
+    let _ = fs::remove_file(&library);
+    let _ = fs::remove_file(&test_file);
+
+    // Without pruning the failing unused binding aborts the load
+    assert!(!unpruned.status.success());
+    assert!(pruned.status.success());
+    assert_eq!(String::from_utf8_lossy(&pruned.stdout).trim(), "42");
+    let stderr = String::from_utf8_lossy(&pruned.stderr);
+    assert!(stderr.contains(&format!("Pruned from {}: unused", library.display())));
+}
+
+#[test]
+fn test_cli_load_paths() {
+    // app/main.par loads util.par next to it, which loads helpers.par from lib/
+    let dir = env::temp_dir().join("test_cli_load_paths");
+    fs::create_dir_all(dir.join("app")).unwrap();
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("app").join("main.par"), "load \"util.par\" in inc2 40").unwrap();
+    fs::write(dir.join("app").join("util.par"), "load \"helpers.par\" in let inc2 = fun x -> inc (inc x) in 0").unwrap();
+    fs::write(dir.join("lib").join("helpers.par"), "let inc = fun x -> x + 1;").unwrap();
+    let main = dir.join("app").join("main.par");
+    let lib = dir.join("lib");
+
+    // Run from elsewhere, so nothing resolves against the working directory
+    let run = |args: &[&str], search_path: Option<&Path>| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_parlang"));
+        command.args(args).arg(&main).current_dir(env::temp_dir()).env_remove("PARLANG_PATH");
+        if let Some(search_path) = search_path {
+            command.env("PARLANG_PATH", search_path);
+        }
+        command.output().expect("Failed to execute command")
+    };
+    let missing = run(&["--typecheck"], None);
+    let included = run(&["--typecheck", "--include-dir", lib.to_str().unwrap()], None);
+    let from_env = run(&["--typecheck"], Some(&lib));
+
+    // Clean up
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(!missing.status.success());
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    let tried = dir.join("app").join("helpers.par");
+    assert!(stderr.contains(&format!("Failed to read file 'helpers.par': no such file, tried '{}', 'helpers.par'", tried.display())), "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&included.stdout).trim(), "42");
+    assert_eq!(String::from_utf8_lossy(&from_env.stdout).trim(), "42");
+}
+
+#[cfg(feature = "config")]
+#[test]
+fn test_cli_include_paths_from_parlang_toml() {
+    let dir = env::temp_dir().join("test_cli_include_paths");
+    fs::create_dir_all(dir.join("app")).unwrap();
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::create_dir_all(dir.join("other")).unwrap();
+    fs::write(dir.join("parlang.toml"), "include_paths = [\"lib\"]\n").unwrap();
+    fs::write(dir.join("app").join("main.par"), "load \"helpers.par\" in inc 40").unwrap();
+    fs::write(dir.join("lib").join("helpers.par"), "let inc = fun x -> x + 2;").unwrap();
+    fs::write(dir.join("other").join("helpers.par"), "let inc = fun x -> x + 4;").unwrap();
+    let main = dir.join("app").join("main.par");
+    let other = dir.join("other");
+
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args(args)
+            .arg(&main)
+            .current_dir(env::temp_dir())
+            .env_remove("PARLANG_PATH")
+            .output()
+            .expect("Failed to execute command")
+    };
+    let from_file = run(&[]);
+    let from_flag = run(&["--include-dir", other.to_str().unwrap()]);
+
+    let _ = fs::remove_dir_all(&dir);
+
+    // include_paths is resolved next to parlang.toml, and --include-dir replaces it
+    assert_eq!(String::from_utf8_lossy(&from_file.stdout).trim(), "42", "{}", String::from_utf8_lossy(&from_file.stderr));
+    assert_eq!(String::from_utf8_lossy(&from_flag.stdout).trim(), "44");
+}
+
+#[test]
+fn test_cli_load_empty_library_warns() {
+    let library = env::temp_dir().join("test_empty_cli_lib.par");
+    let test_file = env::temp_dir().join("test_empty_cli.par");
+    fs::write(&library, "\u{feff}\n  \n").unwrap();
+    fs::write(&test_file, format!("load \"{}\" in 7", library.display())).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(["--typecheck", test_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&library);
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "7");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("Warning: loaded '{}': 0 bindings", library.display())), "{stderr}");
+}
+
+#[test]
+fn test_cli_check_examples() {
+    let dir = env::temp_dir().join("test_check_examples_cli");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("good.par"), "let x = 20;\nx + 1").unwrap();
+    fs::write(dir.join("loop.par"), "(rec loop -> fun n -> loop (n + 1)) 0").unwrap();
+
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args(["check-examples", dir.to_str().unwrap(), "--step-limit", "5000"])
+            .output()
+            .expect("Failed to execute command")
+    };
+    let failing = run();
+    fs::remove_file(dir.join("loop.par")).unwrap();
+    let passing = run();
+
+    // Clean up
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(!failing.status.success());
+    let stdout = String::from_utf8_lossy(&failing.stdout);
+    assert!(stdout.contains("error: Step limit exceeded"), "{stdout}");
+    assert!(stdout.contains("1 of 2 files passed"), "{stdout}");
+    assert!(passing.status.success());
+    assert!(String::from_utf8_lossy(&passing.stdout).contains("1 of 1 files passed"));
+}
+
+#[test]
+fn test_cli_parlang_toml() {
+    let project = env::temp_dir().join("test_parlang_toml_cli");
+    let _ = fs::remove_dir_all(&project);
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("parlang.toml"), "float_precision = 3\nmax_steps = 1000\ncolour = \"always\"\n").unwrap();
+    let program = project.join("src").join("main.par");
+    fs::write(&program, "1.0 / 3.0").unwrap();
+    let looping = project.join("src").join("loop.par");
+    fs::write(&looping, "(rec loop -> fun n -> loop (n + 1)) 0").unwrap();
+
+    let run = |file: &std::path::Path, extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args([file.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let from_file = run(&program, &[]);
+    let overridden = run(&program, &["--float-precision", "5"]);
+    let ignored = run(&program, &["--no-config"]);
+    let limited = run(&looping, &[]);
+    let shown = run(&program, &["--show-config", "--optimize"]);
+
+    // Clean up
+    let _ = fs::remove_dir_all(&project);
+
+    assert_eq!(String::from_utf8_lossy(&from_file.stdout).trim(), "0.333");
+    let stderr = String::from_utf8_lossy(&from_file.stderr);
+    assert!(stderr.contains("parlang.toml:3: unknown key 'colour'"), "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&overridden.stdout).trim(), "0.33333");
+    assert_eq!(String::from_utf8_lossy(&ignored.stdout).trim(), "0.3333333333333333");
+    assert!(!limited.status.success());
+    assert!(String::from_utf8_lossy(&limited.stderr).contains("Step limit exceeded"));
+
+    let shown = String::from_utf8_lossy(&shown.stdout);
+    assert!(shown.contains("parlang.toml\n"), "{shown}");
+    assert!(shown.contains("float_precision = 3\n"), "{shown}");
+    assert!(shown.contains("optimize = true\n"), "{shown}");
+    assert!(shown.contains("max_steps = 1000\n"), "{shown}");
+}
+
+#[test]
+fn test_cli_max_depth() {
+    let test_file = env::temp_dir().join("test_max_depth.par");
+    fs::write(&test_file, "let rec down = fun n -> if n == 0 then 0 else 1 + down (n - 1) in down 5000").unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args([test_file.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let limited = run(&["--max-depth", "100"]);
+    let unlimited = run(&[]);
+
+    let _ = fs::remove_file(&test_file);
+
+    assert_eq!(limited.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&limited.stderr).trim(),
+        "Error: Recursion limit exceeded: more than 100 nested function calls"
+    );
+    assert!(unlimited.status.success());
+    assert_eq!(String::from_utf8_lossy(&unlimited.stdout).trim(), "5000");
+}
+
+#[test]
+fn test_cli_repl_survives_runaway_recursion() {
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute command");
+    repl.stdin.take().unwrap().write_all(b"(rec f -> fun n -> 1 + f n) 0\n1 + 1\n").unwrap();
+    let output = repl.wait_with_output().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success());
+    assert!(stderr.contains("Recursion limit exceeded: more than 10000 nested function calls"), "{stderr}");
+    assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "2"));
+}
+
+#[test]
+fn test_cli_typecheck_flag() {
+    let test_file = env::temp_dir().join("test_typecheck_flag.par");
+    fs::write(&test_file, "let f = fun x -> x + 1 in (f 2, f true)").unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args([test_file.to_str().unwrap()])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let unchecked = run(&[]);
+    let checked = run(&["--typecheck"]);
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    // Without the flag the error is only found while evaluating
+    assert!(String::from_utf8_lossy(&unchecked.stderr).contains("cannot apply '+' to true (Bool) and 1 (Int)"));
+    assert!(!checked.status.success());
+    assert!(checked.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&checked.stderr);
+    // The error points at the call that fails to check
+    assert_eq!(
+        stderr.trim_end(),
+        "Type error at 1:33: Cannot unify types: Int and Bool\n  |\n1 | let f = fun x -> x + 1 in (f 2, f true)\n  |                                 ^^^^^^",
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_runtime_error_shows_source_line() {
+    let test_file = env::temp_dir().join("test_runtime_error_span.par");
+    fs::write(&test_file, "let half = fun n -> n / 0 in\nlet g = fun x -> half x + 1 in\ng 10").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang")).arg(&test_file).output().expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // The division is underlined, and the calls that led to it follow, innermost first
+    assert_eq!(
+        stderr.trim_end(),
+        "Error at 1:21: Division by zero\n  |\n1 | let half = fun n -> n / 0 in\n  |                     ^^^^^\n  in application of 'half'\n  in application of 'g'",
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_strict_matches_flag() {
+    let test_file = env::temp_dir().join("test_strict_matches_flag.par");
+    fs::write(&test_file, "type Option a = Some a | None in\nlet unwrap = fun o ->\n  match o with\n  | Some x -> x\nin unwrap (Some 3)").unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang")).arg(&test_file).args(extra).output().expect("Failed to execute command")
+    };
+    let warned = run(&[]);
+    let strict = run(&["--strict-matches"]);
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    // The warning comes before the program runs, and only once
+    assert!(warned.status.success());
+    assert_eq!(String::from_utf8_lossy(&warned.stdout).trim(), "3");
+    let stderr = String::from_utf8_lossy(&warned.stderr);
+    assert_eq!(stderr.trim_end(), "Warning: match on line 3 is non-exhaustive, missing: None", "{stderr}");
+    assert!(!strict.status.success());
+    assert!(strict.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+    assert_eq!(stderr.trim_end(), "Error: match on line 3 is non-exhaustive, missing: None", "{stderr}");
+}
+
+#[test]
+fn test_cli_lint_warnings_and_deny_warnings_flag() {
+    let test_file = env::temp_dir().join("test_deny_warnings_flag.par");
+    fs::write(&test_file, "let lenght = fun xs -> 0 in\nlet unused = 1 in\nlenght [1]").unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang")).arg(&test_file).args(extra).output().expect("Failed to execute command")
+    };
+    let warned = run(&[]);
+    let all = run(&["--warn", "unused-binding"]);
+    let denied = run(&["--warn", "unused-binding", "--deny-warnings"]);
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    // Nothing is unbound, and unused bindings are only reported when asked for
+    assert!(warned.status.success());
+    assert!(warned.stderr.is_empty(), "{}", String::from_utf8_lossy(&warned.stderr));
+    assert!(all.status.success());
+    assert_eq!(String::from_utf8_lossy(&all.stdout).trim(), "0");
+    let stderr = String::from_utf8_lossy(&all.stderr);
+    assert!(stderr.starts_with("Warning at 1:14: Unused parameter 'xs'"), "{stderr}");
+    assert!(stderr.contains("Warning at 2:14: Unused let binding 'unused'"), "{stderr}");
+    assert!(!denied.status.success());
+    assert!(denied.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&denied.stderr).starts_with("Error at 1:14: Unused parameter 'xs'"));
+}
+
+#[test]
+fn test_cli_unbound_variable_warning() {
+    let test_file = env::temp_dir().join("test_unbound_variable_warning.par");
+    fs::write(&test_file, "let lenght = fun xs -> 0 in\nlength [1]").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang")).arg(&test_file).output().expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    // The program runs into the same problem, which is reported once, as the error
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "Error at 2:1: Unbound variable: length (did you mean `lenght`?)\n  |\n2 | length [1]\n  | ^^^^^^\n"
+    );
+}
+
+#[test]
+fn test_cli_unbound_variable_warning_not_reached() {
+    let test_file = env::temp_dir().join("test_unbound_variable_warning_not_reached.par");
+    fs::write(&test_file, "let lenght = fun xs -> 0 in
+if true then 1 else length [1]").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang")).arg(&test_file).output().expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    // Evaluation does not get to the name, so only the warning reports it
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("Warning at 2:21: Unbound variable: length (did you mean `lenght`?) [unbound-variable]\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_cli_optimize_keeps_suggestions() {
+    let test_file = env::temp_dir().join("test_optimize_keeps_suggestions.par");
+    fs::write(&test_file, "let foo = 1 in foo + fop").unwrap();
+
+    let error = |extra: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .arg(&test_file)
+            .args(extra)
+            .output()
+            .expect("Failed to execute command");
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        stderr.lines().find(|line| line.starts_with("Error")).unwrap_or_default().to_string()
+    };
+    let plain = error(&[]);
+    let optimized = error(&["--optimize"]);
+
+    let _ = fs::remove_file(&test_file);
+
+    assert_eq!(plain, "Error at 1:22: Unbound variable: fop (did you mean `foo`?)");
+    assert_eq!(optimized, plain);
+}
+
+#[test]
+fn test_cli_optimize_keeps_error_locations() {
+    let test_file = env::temp_dir().join("test_optimize_keeps_error_locations.par");
+    fs::write(&test_file, "let one = 1;\n(fun x -> x + one) true").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .arg(&test_file)
+        .arg("--optimize")
+        .output()
+        .expect("Failed to execute command");
+
+    let _ = fs::remove_file(&test_file);
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error at 2:11: Type error:"), "{stderr}");
+    assert!(stderr.contains("2 | (fun x -> x + one) true\n  |           ^^^^^^^"), "{stderr}");
+}
+
+#[test]
+fn test_cli_profile_flag() {
+    let test_file = env::temp_dir().join("test_profile_flag.par");
+    fs::write(&test_file, "let sq = fun x -> x * x;\nlet cube = fun x -> x * sq x;\ncube 2 + cube 3").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(["--profile", "--profile-top", "1", test_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "35");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let table: Vec<&str> = stderr.lines().skip_while(|line| !line.starts_with("function")).collect();
+    assert_eq!(table[1].split_whitespace().take(2).collect::<Vec<_>>(), ["cube", "2"]);
+    assert_eq!(table[2], "(1 more function not shown)");
+}
+
+#[test]
+fn test_cli_language_spec() {
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_parlang"))
+            .args(["language-spec"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute command")
+    };
+    let json = run(&["--json"]);
+    assert!(json.status.success());
+    let json = String::from_utf8_lossy(&json.stdout);
+    assert_eq!(json.trim_end(), parlang::language_spec().to_json());
+    assert!(json.contains("\"symbol\": \"^\",\n      \"precedence\": 4,\n      \"associativity\": \"right\""), "{json}");
+    let text = run(&[]);
+
+    let text = String::from_utf8_lossy(&text.stdout);
+    assert!(text.contains("  ^   precedence 4, right-associative\n"), "{text}");
+    assert!(text.contains("  show : t0 -> List Char\n"), "{text}");
+}
+
+#[test]
+fn test_cli_version_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang")).args(["--version", "--json"]).output().expect("Failed to execute command");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.trim_end(), parlang::format::version_json());
+    let features: Vec<&str> = [("config", cfg!(feature = "config")), ("serde", cfg!(feature = "serde"))]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature)
+        .collect();
+    let features = format!("[{}]", features.iter().map(|feature| format!("\"{feature}\"")).collect::<Vec<_>>().join(", "));
+    assert_eq!(
+        stdout.trim_end(),
+        format!("{{\"version\": \"{}\", \"parlang_format\": {}, \"features\": {features}}}", env!("CARGO_PKG_VERSION"), parlang::FORMAT_VERSION)
+    );
+
+    let plain = Command::new(env!("CARGO_BIN_EXE_parlang")).arg("--version").output().expect("Failed to execute command");
+    assert_eq!(String::from_utf8_lossy(&plain.stdout).trim_end(), format!("parlang {}", env!("CARGO_PKG_VERSION")));
+}
+
+#[test]
+fn test_cli_print_writes_values_in_evaluation_order() {
+    let test_file = env::temp_dir().join("test_print_cli.par");
+    fs::write(&test_file, "let x = print 1 in print \"two\" ^ show (print (x + 2))").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_parlang"))
+        .args(["--typecheck", test_file.to_str().unwrap()])
+        .output()
+        .expect("Failed to execute command");
+
+    // Clean up
+    let _ = fs::remove_file(&test_file);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().collect::<Vec<_>>(), ["1", "two", "3", "\"two3\""]);
+}
@@ -0,0 +1,115 @@
+/// Runs the `parlang` snippets in the crate's doc comments, and tests the extractor
+use parlang::snippets::{extract_doc_snippets, run_snippet, Snippet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Add the `.rs` files under `dir` to `files`
+fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("Failed to read directory") {
+        let path = entry.expect("Failed to read directory entry").path();
+        if path.is_dir() {
+            collect_rust_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_doc_snippets_in_src() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    collect_rust_files(&root.join("src"), &mut files);
+    files.sort();
+
+    let mut snippets = Vec::new();
+    for path in &files {
+        let contents = fs::read_to_string(path).expect("Failed to read source file");
+        let name = path.strip_prefix(root).unwrap_or(path).display().to_string();
+        snippets.extend(extract_doc_snippets(&name, &contents));
+    }
+    assert!(
+        snippets.iter().any(|snippet| snippet.file == "src/lib.rs"),
+        "Expected the crate examples in src/lib.rs to be found"
+    );
+
+    let failures: Vec<String> = snippets
+        .iter()
+        .filter_map(|snippet| run_snippet(snippet).err())
+        .map(|failure| failure.to_string())
+        .collect();
+    assert!(failures.is_empty(), "Failing doc snippets:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_extract_detects_parlang_fences_only() {
+    let contents = r#"
+/// ```text
+/// not checked
+/// ```
+///
+/// ```rust
+/// let ignored = 1;
+/// ```
+///
+/// ```parlang
+/// 1 + 2 -- => 3
+/// ```
+fn f() {}
+"#;
+    let snippets = extract_doc_snippets("f.rs", contents);
+    assert_eq!(
+        snippets,
+        vec![Snippet {
+            file: "f.rs".to_string(),
+            line: 11,
+            source: "1 + 2".to_string(),
+            expected_value: Some("3".to_string()),
+            expected_type: None,
+        }]
+    );
+}
+
+#[test]
+fn test_extract_multiple_snippets_per_file() {
+    let contents = "\
+//! ```parlang
+//! let x = 1
+//! in x -- => 1
+//! ```
+
+/// ```parlang
+/// fun x -> x -- : t0 -> t0
+/// ```
+fn g() {}
+";
+    let snippets = extract_doc_snippets("g.rs", contents);
+    assert_eq!(snippets.len(), 2);
+    assert_eq!((snippets[0].line, snippets[0].source.as_str()), (2, "let x = 1\nin x"));
+    assert_eq!(snippets[0].expected_value.as_deref(), Some("1"));
+    assert_eq!((snippets[1].line, snippets[1].source.as_str()), (7, "fun x -> x"));
+    assert_eq!(snippets[1].expected_type.as_deref(), Some("t0 -> t0"));
+}
+
+#[test]
+fn test_extract_drops_fence_left_open() {
+    let contents = "/// ```parlang\n/// 1\nfn h() {}\n/// ```\n";
+    assert!(extract_doc_snippets("h.rs", contents).is_empty());
+}
+
+#[test]
+fn test_failing_snippet_reports_file_and_line() {
+    let contents = "\n\n/// ```parlang\n/// 1 + 2 -- => 4\n/// ```\n";
+    let snippets = extract_doc_snippets("src/example.rs", contents);
+    let failure = run_snippet(&snippets[0]).unwrap_err();
+    assert_eq!(failure.to_string(), "src/example.rs:4: expected 4, got 3");
+
+    let contents = "/// ```parlang\n/// 1 + true -- : Int\n/// ```\n";
+    let snippets = extract_doc_snippets("src/example.rs", contents);
+    let failure = run_snippet(&snippets[0]).unwrap_err();
+    assert_eq!((failure.file.as_str(), failure.line), ("src/example.rs", 2));
+    assert!(failure.message.starts_with("type error:"), "{}", failure.message);
+
+    let snippet = Snippet { file: "x.rs".to_string(), line: 9, source: "let in".to_string(), expected_value: None, expected_type: None };
+    assert!(run_snippet(&snippet).unwrap_err().to_string().starts_with("x.rs:9: parse error:"));
+}
@@ -1,6 +1,6 @@
 /// Tests for error handling edge cases and boundary conditions
 /// These tests verify proper error handling in various edge cases
-use parlang::{eval, parse, Environment, Value};
+use parlang::{eval, parse, Environment, Expr, Value};
 
 fn parse_and_eval(input: &str) -> Result<Value, String> {
     let expr = parse(input)?;
@@ -328,20 +328,20 @@ fn test_constructor_arity_mismatch_none_with_arg() {
 
 #[test]
 fn test_parse_empty_input() {
-    // Empty input defaults to 0 in parlang
+    // Empty input has no body (it evaluates to unit)
     let code = "";
     let result = parse(code);
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), parse("0").unwrap());
+    assert_eq!(result.unwrap(), Expr::EmptyBody);
 }
 
 #[test]
 fn test_parse_whitespace_only() {
-    // Whitespace-only input also defaults to 0
+    // Whitespace-only input also has no body
     let code = "   \n\t  ";
     let result = parse(code);
     assert!(result.is_ok());
-    assert_eq!(result.unwrap(), parse("0").unwrap());
+    assert_eq!(result.unwrap(), Expr::EmptyBody);
 }
 
 #[test]
@@ -20,6 +20,38 @@ fn test_tco_deep_recursion_countdown() {
     assert_eq!(result, Ok(Value::Int(0)));
 }
 
+#[test]
+fn test_tco_match_arm_in_tail_position() {
+    // The recursive call is a match arm's entire body
+    let code = r"
+        (rec countdown -> fun n ->
+            match n with
+            | 0 -> 0
+            | _ -> countdown (n - 1)
+        ) 1000000
+    ";
+    let expr = parse(code).unwrap();
+    let env = Environment::new();
+    let result = eval(&expr, &env);
+    assert_eq!(result, Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_tco_let_body_in_tail_position() {
+    // The recursive call is the body of a let, inside a match arm
+    let code = r"
+        (rec count -> fun pair ->
+            match pair with
+            | (0, total) -> total
+            | (n, total) -> let next = n - 1 in let bigger = total + 1 in count (next, bigger)
+        ) (100000, 0)
+    ";
+    let expr = parse(code).unwrap();
+    let env = Environment::new();
+    let result = eval(&expr, &env);
+    assert_eq!(result, Ok(Value::Int(100_000)));
+}
+
 #[test]
 fn test_non_tail_recursive_sum() {
     // Test non-tail recursive sum (addition happens after recursive call)
@@ -131,6 +163,71 @@ fn test_tco_nested_function_calls() {
     assert_eq!(result, Ok(Value::Int(0)));
 }
 
+/// Evaluate `code` with both evaluators, which must agree
+fn eval_both(code: &str) -> Result<Value, EvalError> {
+    let expr = parse(code).unwrap();
+    let results: Vec<_> = [false, true]
+        .into_iter()
+        .map(|resolved| {
+            let mut env = Environment::with_builtins();
+            env.set_resolved(resolved);
+            eval(&expr, &env)
+        })
+        .collect();
+    assert_eq!(results[0], results[1], "{code}");
+    results[0].clone()
+}
+
+#[test]
+fn test_tco_curried_match_loop() {
+    // A two-argument loop whose recursive call is a match arm
+    let code = r"
+        let rec count = fun total -> fun n ->
+            match n with
+            | 0 -> total
+            | _ -> count (total + 1) (n - 1)
+        in count 0 1000000
+    ";
+    assert_eq!(eval_both(code), Ok(Value::Int(1_000_000)));
+}
+
+#[test]
+fn test_tco_mutual_recursion() {
+    // ping and pong call each other in tail position
+    let code = r"
+        let rec ping = fun n ->
+            let rec pong = fun m -> if m == 0 then false else ping (m - 1) in
+            if n == 0 then true else pong (n - 1)
+        in ping 1000000
+    ";
+    assert_eq!(eval_both(code), Ok(Value::Bool(true)));
+}
+
+#[test]
+fn test_tco_calls_to_other_functions() {
+    // Tail calls through a let body and a sequence, to a function passed in
+    let code = r"
+        let step = fun k -> fun n -> let next = n - 1 in k next;
+        let rec loop = fun n -> match n with | 0 -> 0 | _ -> step loop n;
+        loop 1000000
+    ";
+    assert_eq!(eval_both(code), Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_tco_keeps_non_tail_calls() {
+    // Only the call in tail position is made in place; errors still reach the caller
+    let code = r"
+        let rec ping = fun n ->
+            let rec pong = fun m -> if m == 0 then 1 / 0 else ping (m - 1) in
+            if n == 0 then 0 else pong (n - 1)
+        in try ping 1001 catch e -> e.kind
+    ";
+    assert_eq!(eval_both(code).unwrap().to_string(), "\"DivisionByZero\"");
+    let code = "let rec down = fun n -> if n == 0 then 0 else 1 + down (n - 1) in down 10";
+    assert_eq!(eval_both(code), Ok(Value::Int(10)));
+}
+
 // Non-Tail Recursive Functions (should still work, just not optimized)
 
 #[test]
@@ -186,7 +283,7 @@ fn test_unbound_variable_error() {
     let expr = parse(code).unwrap();
     let env = Environment::new();
     let result = eval(&expr, &env);
-    assert!(matches!(result, Err(EvalError::UnboundVariable(_))));
+    assert!(matches!(result, Err(EvalError::UnboundVariable(..))));
 }
 
 #[test]
@@ -216,7 +313,7 @@ fn test_type_error_arithmetic_on_boolean() {
     let expr = parse(code).unwrap();
     let env = Environment::new();
     let result = eval(&expr, &env);
-    assert!(matches!(result, Err(EvalError::TypeError(_))));
+    assert!(matches!(result, Err(EvalError::BinOpTypeMismatch { left_kind: "Bool", right_kind: "Bool", .. })));
 }
 
 #[test]
@@ -225,8 +322,8 @@ fn test_type_error_comparison_mixed_types() {
     let code = "42 == true";
     let expr = parse(code).unwrap();
     let env = Environment::new();
-    let result = eval(&expr, &env);
-    assert!(matches!(result, Err(EvalError::TypeError(_))));
+    let result = eval(&expr, &env).unwrap_err();
+    assert_eq!(result.to_string(), "Type error: cannot apply '==' to 42 (Int) and true (Bool)");
 }
 
 #[test]
@@ -472,3 +569,25 @@ fn test_long_constructor_chain() {
     let result = eval(&expr, &env);
     assert_eq!(result, Ok(Value::Int(1)));
 }
+
+#[test]
+fn test_let_rec() {
+    let eval_str = |code: &str| eval(&parse(code).unwrap(), &Environment::new());
+    let fact = "let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1)";
+    assert_eq!(eval_str(&format!("{fact} in fact 5")), Ok(Value::Int(120)));
+    assert_eq!(eval_str(&format!("{fact}; fact 6")), Ok(Value::Int(720)));
+    // Same as the two-name form
+    assert_eq!(
+        eval_str(&format!("{fact} in fact 10")),
+        eval_str("let fact = rec f -> fun n -> if n == 0 then 1 else n * f (n - 1) in fact 10")
+    );
+    // Tail calls through a let rec binding still run in constant stack
+    assert_eq!(
+        eval_str("let rec down = fun n -> if n == 0 then 0 else down (n - 1) in down 100000"),
+        Ok(Value::Int(0))
+    );
+    assert_eq!(
+        eval_str("let rec evens = fun n -> if n == 0 then true else if n == 1 then false else evens (n - 2); evens 10"),
+        Ok(Value::Bool(true))
+    );
+}
@@ -46,10 +46,7 @@ fn prove_non_exhaustive_option_missing_none() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"None".to_string()), 
-                "Should report None as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Constructor("None".to_string(), vec![])), "Should report None as missing, got: {:?}", result.missing);
 }
 
 /// Test that non-exhaustive Option match (missing Some) gets warning
@@ -75,10 +72,7 @@ fn prove_non_exhaustive_option_missing_some() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"Some".to_string()), 
-                "Should report Some as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Constructor("Some".to_string(), vec![Pattern::Wildcard])), "Should report Some as missing, got: {:?}", result.missing);
 }
 
 /// Test exhaustive Either match
@@ -129,10 +123,7 @@ fn prove_non_exhaustive_either_missing_right() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"Right".to_string()), 
-                "Should report Right as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Constructor("Right".to_string(), vec![Pattern::Wildcard])), "Should report Right as missing, got: {:?}", result.missing);
 }
 
 /// Test exhaustive boolean match
@@ -167,10 +158,7 @@ fn prove_non_exhaustive_bool_missing_false() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"false".to_string()), 
-                "Should report false as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Literal(Literal::Bool(false))), "Should report false as missing, got: {:?}", result.missing);
 }
 
 /// Test non-exhaustive boolean match (missing true)
@@ -188,10 +176,7 @@ fn prove_non_exhaustive_bool_missing_true() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"true".to_string()), 
-                "Should report true as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Literal(Literal::Bool(true))), "Should report true as missing, got: {:?}", result.missing);
 }
 
 /// Test wildcard makes match exhaustive
@@ -292,10 +277,7 @@ fn prove_non_exhaustive_list_missing_nil() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        assert!(missing.contains(&"Nil".to_string()), 
-                "Should report Nil as missing, got: {:?}", missing);
-    }
+    assert!(result.missing.contains(&Pattern::Constructor("Nil".to_string(), vec![])), "Should report Nil as missing, got: {:?}", result.missing);
 }
 
 /// Test integer patterns are non-exhaustive without wildcard
@@ -410,12 +392,9 @@ fn prove_multiple_constructors_some_missing() {
     let result = check_exhaustiveness(&patterns, &env);
     assert!(!result.is_exhaustive(), "Should be non-exhaustive");
     
-    if let parlang::ExhaustivenessResult::NonExhaustive(missing) = result {
-        // Should report both Pending and Archived as missing
-        assert!(missing.len() == 2, "Should have 2 missing constructors, got: {:?}", missing);
-        assert!(missing.contains(&"Pending".to_string()), "Should have Pending");
-        assert!(missing.contains(&"Archived".to_string()), "Should have Archived");
-    }
+    assert_eq!(result.missing.len(), 2, "Should have 2 missing constructors, got: {:?}", result.missing);
+    assert!(result.missing.contains(&Pattern::Constructor("Pending".to_string(), vec![])), "Should report Pending as missing, got: {:?}", result.missing);
+    assert!(result.missing.contains(&Pattern::Constructor("Archived".to_string(), vec![])), "Should report Archived as missing, got: {:?}", result.missing);
 }
 
 /// Integration test: Full program execution with exhaustive match
@@ -70,9 +70,22 @@ fn test_float_division() {
 
 #[test]
 fn test_float_division_by_zero() {
-    let expr = parse("10.0 / 0.0").unwrap();
     let env = Environment::new();
-    assert!(eval(&expr, &env).is_err());
+    assert_eq!(eval(&parse("10.0 / 0.0").unwrap(), &env), Ok(Value::Float(f64::INFINITY)));
+    assert_eq!(eval(&parse("-1.0 / 0.0").unwrap(), &env), Ok(Value::Float(f64::NEG_INFINITY)));
+    let nan = eval(&parse("0.0 / 0.0").unwrap(), &env).unwrap();
+    assert!(matches!(nan, Value::Float(x) if x.is_nan()));
+}
+
+#[test]
+fn test_float_display() {
+    let env = Environment::new();
+    let show = |source: &str| eval(&parse(source).unwrap(), &env).unwrap().to_string();
+    assert_eq!(show("1.5 + 1.5"), "3.0");
+    assert_eq!(show("2.5"), "2.5");
+    assert_eq!(show("-2.0"), "-2.0");
+    assert_eq!(show("1.0 / 0.0"), "inf");
+    assert_eq!(show("3"), "3");
 }
 
 #[test]
@@ -0,0 +1,93 @@
+/// Tests that folding constants never changes what a program does
+///
+/// Every example program and a set of programs with constants to fold are
+/// evaluated as written, after `fold_constants` and after the whole
+/// `optimize` pipeline, and all three runs must agree on the result or error.
+use parlang::check::{DEFAULT_STEP_LIMIT, NO_TYPECHECK_MARKER};
+use parlang::optimize::{fold_constants, optimize};
+use parlang::{eval, parse, Environment, Expr};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// Programs with constants in every position the pass folds
+const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3 - 4 / 2",
+    "let x = 5 in let y = x * 2 in if y > 8 then x + y else 0",
+    "let f = fun n -> n * (2 + 3) in f 4",
+    "let k = 3 in let add = fun x -> x + k in let k = 100 in add 1",
+    "if 1 == 1 then (1, 2).1 else 0",
+    "if false then 1 elif 2 < 1 then 2 elif true then 3 else 4",
+    "let t = (1, (true, 'c')) in (t.1.0, (2.5, 4b).1)",
+    "let x = 10 in match x - 10 with | 0 -> \"zero\" | _ -> \"other\"",
+    "let d = 2 - 2 in 10 / d",
+    "let big = 9223372036854775807 in big + 1",
+    "if 0 then 1 else 2",
+    "(1, 2).3",
+    "let r = ref 0 in let a = r := !r + 1 in let b = r := !r * 5 in !r",
+    "let x = 1 in let f = fun y -> let x = y in x + 1 in f 41 + x",
+    "try 1 / (3 - 3) catch err -> err.kind",
+];
+
+/// Evaluate a parsed program, returning how its result or error prints
+fn run(expr: &Expr) -> Result<String, String> {
+    let mut env = Environment::with_builtins();
+    env.set_step_limit(Some(DEFAULT_STEP_LIMIT));
+    eval(expr, &env).map(|value| value.to_string()).map_err(|e| e.to_string())
+}
+
+/// Check that `source` evaluates the same before and after optimizing
+fn assert_preserved(name: &str, source: &str) {
+    let expr = parse(source).unwrap_or_else(|e| panic!("{name}: {e}"));
+    let expected = run(&expr);
+    assert_eq!(run(&fold_constants(expr.clone())), expected, "fold_constants changed {name}");
+    assert_eq!(run(&optimize(&expr)), expected, "optimize changed {name}");
+}
+
+/// The `.par` files under `dir`, recursively
+fn par_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            par_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "par") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_folding_preserves_programs() {
+    for source in PROGRAMS {
+        assert_preserved(source, source);
+    }
+}
+
+#[test]
+fn test_folding_preserves_example_programs() {
+    let mut files = Vec::new();
+    par_files(Path::new("examples"), &mut files);
+    files.sort();
+    assert!(!files.is_empty());
+    // Some examples recurse deeply, as check-examples allows for
+    thread::Builder::new()
+        .stack_size(512 * 1024 * 1024)
+        .spawn(move || {
+            for path in files {
+                let source = fs::read_to_string(&path).unwrap();
+                let source = source.strip_prefix(NO_TYPECHECK_MARKER).unwrap_or(&source);
+                assert_preserved(&path.display().to_string(), source);
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_folding_shrinks_constant_programs() {
+    let expr = parse("let x = 5 in if x > 3 then x * 2 + 1 else 0").unwrap();
+    assert_eq!(fold_constants(expr), Expr::Int(11));
+    let expr = parse("fun y -> y + 2 * 3").unwrap();
+    assert_eq!(fold_constants(expr), parse("fun y -> y + 6").unwrap());
+}
@@ -0,0 +1,59 @@
+/// Integration tests for guard clauses in match arms: `| p when g -> e`
+use parlang::{eval, parse, typecheck, Environment, EvalError, Type, Value};
+
+fn run(source: &str) -> Result<Value, EvalError> {
+    let expr = parse(source).expect("Parse error");
+    eval(&expr, &Environment::with_builtins())
+}
+
+#[test]
+fn test_guard_sees_pattern_variables() {
+    let classify = "let classify = fun x -> match x with | n when n > 10 -> 2 | n when n > 0 -> 1 | _ -> 0 in ";
+    assert_eq!(run(&format!("{classify}classify 42")), Ok(Value::Int(2)));
+    assert_eq!(run(&format!("{classify}classify 7")), Ok(Value::Int(1)));
+    assert_eq!(run(&format!("{classify}classify (-3)")), Ok(Value::Int(0)));
+    assert_eq!(run("match (3, 4) with | (a, b) when a > b -> a | (a, b) -> b"), Ok(Value::Int(4)));
+}
+
+#[test]
+fn test_failed_guard_falls_through_to_later_arms() {
+    // The second arm's pattern also matches, with its own bindings
+    assert_eq!(
+        run("match [5, 1] with | x :: rest when x < 3 -> 0 | y :: z :: _ -> y + z | _ -> 99"),
+        Ok(Value::Int(6))
+    );
+    // Guards may use variables from outside the match
+    assert_eq!(run("let limit = 3 in match 4 with | n when n <= limit -> true | _ -> false"), Ok(Value::Bool(false)));
+    // When every guard fails there is no arm to take
+    assert_eq!(run("match 1 with | n when n > 5 -> n"), Err(EvalError::PatternMatchNonExhaustive));
+}
+
+#[test]
+fn test_guard_errors() {
+    assert!(matches!(run("match 1 with | n when n -> 1 | _ -> 0"), Err(e) if e.to_string().contains("Match guard must be a boolean")));
+    let err = run("match 1 with | n when n / 0 == 1 -> 1 | _ -> 0").unwrap_err();
+    assert_eq!(err.root(), &EvalError::DivisionByZero);
+    assert!(err.to_string().contains("in match arm 'n'"), "{err}");
+}
+
+#[test]
+fn test_guards_in_function_and_tail_calls() {
+    let sign = "let sign = function | 0 -> 0 | n when n < 0 -> -1 | _ -> 1 in ";
+    assert_eq!(run(&format!("{sign}(sign (-8), sign 0, sign 8)")), run("(-1, 0, 1)"));
+    // A guarded arm's result is still in tail position
+    assert_eq!(
+        run("let rec count = fun acc -> fun n -> match n with | m when m > 0 -> count (acc + 1) (m - 1) | _ -> acc in count 0 100000"),
+        Ok(Value::Int(100000))
+    );
+}
+
+#[test]
+fn test_guard_types() {
+    let check = |source: &str| typecheck(&parse(source).unwrap());
+    assert_eq!(check("fun x -> match x with | n when n > 10 -> true | _ -> false").map(|ty| ty.to_string()), Ok("Int -> Bool".to_string()));
+    // The guard constrains the pattern's variables
+    assert_eq!(check("fun p -> match p with | (a, b) when b -> a | (a, _) -> a + 1").map(|ty| ty.to_string()), Ok("(Int, Bool) -> Int".to_string()));
+    assert_eq!(check("match 1 with | n when n + 1 -> n | _ -> 0").unwrap_err().to_string(), "In match arm 1 (n): Cannot unify types: Int and Bool");
+    assert!(check("match 1 with | n when undefined_name -> n | _ -> 0").is_err());
+    assert_eq!(check("match 'a' with | c when c == 'a' -> 1 | _ -> 2"), Ok(Type::Int));
+}
@@ -0,0 +1,107 @@
+/// Tests that the integer arithmetic fast path never changes what a program does
+///
+/// Each program runs with the fast path off and with it forced on for every
+/// integer operation, and the two runs must agree on the result, the error,
+/// and the number of steps taken.
+use parlang::core::INT_FAST_PATH_MIN_OPS;
+use parlang::profile::Profiler;
+use parlang::{eval, parse, Environment, EvalError, Value};
+use std::rc::Rc;
+
+/// Programs built from integer arithmetic, including ones that fail or fall back
+const PROGRAMS: &[&str] = &[
+    "1 + 2 * 3",
+    "10 - 4 - 3",
+    "2 * 3 + 4 * 5 - 6 / 2",
+    "let a = 7 in let b = 3 in a * a - b * b",
+    "let a = 7 in let b = 3 in a / b * b + a - a / b * b",
+    "let x = 5 in x * x * x - 2 * x * x + 3 * x - 4",
+    "let x = 5 in x * x + 1 < x * 6",
+    "let x = 5 in x * 2 == 10",
+    "let x = 5 in x - 1 != x + 1",
+    "let x = -3 in x * x >= 9",
+    "let x = 4 in if x * x - 16 == 0 then x + x * x else 0",
+    "(fun a -> fun b -> a * b + a - b) 6 7",
+    "(rec f -> fun n -> if n <= 0 then 0 else n * n + f (n - 1)) 20",
+    "9223372036854775807 + 1",
+    "let big = 9223372036854775807 in big * 2 - 1",
+    "let m = -9223372036854775807 - 1 in m / (0 - 1)",
+    "-9223372036854775807 - 2 + 1",
+    "1 + 2 / 0",
+    "let z = 0 in 10 / z + 1",
+    "let z = 0 in (1 / z) * missing",
+    "missing + 1 * 2",
+    "let x = 1.5 in x * 2.0 + x",
+    "let x = 1.5 in x * 2 + 1",
+    "let b = true in b + 1 * 2",
+    "let b = true in b == 1 + 1",
+    "let f = fun x -> x in f + 1 + 2",
+    "let c = 'a' in c < 'b'",
+    "let s = 0b1 in s + 0b2 * 0b3",
+    "let x = 3 in (x + 1, x * 2 - 1).1",
+    "let r = ref 2 in !r * !r + 1",
+    "let a = [|1, 2, 3|] in a[0] + a[1] * a[2]",
+];
+
+/// Evaluate `source` under an optional step limit, returning the result and the steps taken
+fn run(source: &str, fast_path: Option<usize>, step_limit: Option<u64>) -> (Result<Value, EvalError>, u64) {
+    let profiler = Rc::new(Profiler::new());
+    let mut env = Environment::with_builtins();
+    env.set_int_fast_path(fast_path);
+    env.set_step_limit(step_limit);
+    env.set_profiler(Some(Rc::clone(&profiler)));
+    let result = eval(&parse(source).expect("Parse error"), &env);
+    (result, profiler.report().total_steps)
+}
+
+#[test]
+fn test_forced_fast_path_matches_general_path() {
+    for source in PROGRAMS {
+        assert_eq!(run(source, Some(1), None), run(source, None, None), "{source}");
+    }
+}
+
+#[test]
+fn test_forced_fast_path_matches_general_path_under_step_limits() {
+    for source in PROGRAMS {
+        let (_, steps) = run(source, None, None);
+        for limit in 0..=steps {
+            assert_eq!(run(source, Some(1), Some(limit)), run(source, None, Some(limit)), "{source} with limit {limit}");
+        }
+    }
+}
+
+#[test]
+fn test_default_fast_path_matches_general_path() {
+    for source in PROGRAMS {
+        let default = {
+            let profiler = Rc::new(Profiler::new());
+            let mut env = Environment::with_builtins();
+            env.set_profiler(Some(Rc::clone(&profiler)));
+            (eval(&parse(source).unwrap(), &env), profiler.report().total_steps)
+        };
+        assert_eq!(default, run(source, None, None), "{source}");
+    }
+}
+
+#[test]
+fn test_force_feature_sets_the_default_threshold() {
+    let expected = if cfg!(feature = "force-int-fast-path") { 1 } else { INT_FAST_PATH_MIN_OPS };
+    assert_eq!(Environment::new().int_fast_path(), Some(expected));
+    assert_eq!(Environment::with_builtins().int_fast_path(), Some(expected));
+}
+
+#[test]
+fn test_fast_path_keeps_overflow_and_division_errors() {
+    let (result, _) = run("let x = 9223372036854775807 in x * 2 + 1", Some(1), None);
+    assert_eq!(result, Err(EvalError::TypeError("Integer overflow in multiplication".to_string())));
+    let (result, _) = run("let z = 0 in 1 + 10 / z", Some(1), None);
+    assert_eq!(result, Err(EvalError::DivisionByZero));
+}
+
+#[test]
+fn test_fast_path_in_recursive_loop() {
+    let source = "(rec loop -> fun n -> if n == 0 then 0 else loop (n - 1) + n * n - n * (n - 1)) 10";
+    assert_eq!(run(source, Some(1), None), run(source, None, None));
+    assert_eq!(run(source, Some(1), None).0, Ok(Value::Int(55)));
+}
@@ -161,6 +161,41 @@ fn test_function_with_let() {
     assert_eq!(parse_and_eval("let inc = fun x -> x + 1 in inc 41"), Ok(Value::Int(42)));
 }
 
+#[test]
+fn test_let_function_definition() {
+    assert_eq!(parse_and_eval("let add x y = x + y in add 1 2"), Ok(Value::Int(3)));
+    assert_eq!(parse_and_eval("let add (x : Int) (y : Int) = x + y in let inc = add 1 in inc 41"), Ok(Value::Int(42)));
+    assert_eq!(parse_and_eval("let rec fact n = if n == 0 then 1 else n * fact (n - 1); fact 5"), Ok(Value::Int(120)));
+
+    let (_, env) = parse_eval_and_extract("let twice f x = f (f x);", &Environment::new()).unwrap();
+    assert_eq!(parse_eval_and_extract("twice (fun n -> n * 3) 2", &env).map(|(value, _)| value), Ok(Value::Int(18)));
+}
+
+#[test]
+fn test_binding_patterns() {
+    assert_eq!(parse_and_eval("(fun (x, y) -> x + y) (3, 4)"), Ok(Value::Int(7)));
+    assert_eq!(parse_and_eval("let pair = (6, 7) in let (a, b) = pair in a * b"), Ok(Value::Int(42)));
+    assert_eq!(parse_and_eval("let ((a, _), c) = ((1, 2), 3) in a + c"), Ok(Value::Int(4)));
+    assert_eq!(parse_and_eval("let _ = 1 in (fun _ -> 2) true"), Ok(Value::Int(2)));
+    assert_eq!(parse_and_eval("let { x: a, y: b } = { x: 1, y: 2 } in a - b"), Ok(Value::Int(-1)));
+    assert_eq!(parse_and_eval("type T = Wrap Int in let (Wrap n) = Wrap 5 in n"), Ok(Value::Int(5)));
+    assert_eq!(parse_and_eval("let add (x, y) z = x + y + z in add (1, 2) 3"), Ok(Value::Int(6)));
+
+    // Top-level pattern bindings bind each variable, evaluating the value once
+    let (_, env) = parse_eval_and_extract("let r = ref 0; let (a, b) = (let u = r := !r + 1 in !r, 10);", &Environment::new()).unwrap();
+    assert_eq!(parse_eval_and_extract("(a, b, !r)", &env).map(|(value, _)| value), parse_and_eval("(1, 10, 1)"));
+
+    // A value the pattern does not match is an error
+    assert_eq!(parse_and_eval("let (1, b) = (2, 3) in b"), Err("Value (2, 3) does not match pattern (1, b)".to_string()));
+    assert_eq!(parse_and_eval("let (a, b) = 1 in a"), Err("Value 1 does not match pattern (a, b)".to_string()));
+    assert_eq!(parse_and_eval("(fun (0, x) -> x) (1, 2)"), Err("Value (1, 2) does not match pattern (0, x)".to_string()));
+    assert_eq!(parse_and_eval("let (a, b) = 1;\na"), Err("Value 1 does not match pattern (a, b)".to_string()));
+    assert_eq!(parse_and_eval("let (1, a) = (2, 3);\na"), Err("in let binding 'a'\n  Value (2, 3) does not match pattern (1, a)".to_string()));
+    // ... unlike a `match` that has no arm for it
+    assert_eq!(parse_and_eval("match (2, 3) with | (1, b) -> b"), Err("Pattern match is non-exhaustive".to_string()));
+    assert!(parse("let rec (a, b) = (1, 2) in a").is_err());
+}
+
 #[test]
 fn test_double_function() {
     assert_eq!(parse_and_eval("let double = fun x -> x + x in double 21"), Ok(Value::Int(42)));
@@ -350,6 +385,17 @@ fn test_repl_persistence_load_library() {
     assert_eq!(value3, Value::Int(20));
 }
 
+#[test]
+fn test_repl_persistence_namespaced_load() {
+    // A namespaced load persists as the namespace alone
+    let env = Environment::new();
+
+    let (_, env) = parse_eval_and_extract("load \"examples/stdlib.par\" as Std in 0", &env).unwrap();
+    let (value, env) = parse_eval_and_extract("Std.double 21", &env).unwrap();
+    assert_eq!(value, Value::Int(42));
+    assert!(parse_eval_and_extract("double 21", &env).is_err());
+}
+
 #[test]
 fn test_repl_persistence_shadowing() {
     // Define a variable, then shadow it
@@ -400,7 +446,7 @@ fn test_optional_body_simple_binding() {
     let env = Environment::new();
     
     let (value, env) = parse_eval_and_extract("let x = 42;", &env).unwrap();
-    assert_eq!(value, Value::Int(0)); // Should default to 0
+    assert_eq!(value, Value::Unit); // No body: evaluates to unit
     
     // Use the variable
     let (value, _) = parse_eval_and_extract("x", &env).unwrap();
@@ -413,7 +459,7 @@ fn test_optional_body_function_definition() {
     let env = Environment::new();
     
     let (value, env) = parse_eval_and_extract("let double = fun x -> x + x;", &env).unwrap();
-    assert_eq!(value, Value::Int(0)); // Should default to 0
+    assert_eq!(value, Value::Unit); // No body: evaluates to unit
     
     // Use the function
     let (value, _) = parse_eval_and_extract("double 21", &env).unwrap();
@@ -426,7 +472,7 @@ fn test_optional_body_multiple_bindings() {
     let env = Environment::new();
     
     let (value, env) = parse_eval_and_extract("let x = 1; let y = 2; let z = 3;", &env).unwrap();
-    assert_eq!(value, Value::Int(0)); // Should default to 0
+    assert_eq!(value, Value::Unit); // No body: evaluates to unit
     
     // Use the variables
     let (value, _) = parse_eval_and_extract("x + y + z", &env).unwrap();
@@ -439,7 +485,7 @@ fn test_optional_body_load_library() {
     let env = Environment::new();
     
     let (value, env) = parse_eval_and_extract("load \"examples/stdlib.par\"", &env).unwrap();
-    assert_eq!(value, Value::Int(0)); // Should default to 0
+    assert_eq!(value, Value::Unit); // No body: evaluates to unit
     
     // Use functions from the loaded library
     let (value, _) = parse_eval_and_extract("double 21", &env).unwrap();
@@ -979,7 +1025,7 @@ fn test_tuple_simple() {
 #[test]
 fn test_tuple_empty() {
     let result = parse_and_eval("()");
-    assert_eq!(result, Ok(Value::Tuple(vec![])));
+    assert_eq!(result, Ok(Value::Unit));
 }
 
 #[test]
@@ -1304,6 +1350,14 @@ fn test_char_pattern_matching() {
     assert_eq!(parse_and_eval(code), Ok(Value::Int(1)));
 }
 
+#[test]
+fn test_char_pattern_matching_on_variable() {
+    let classify = "let classify = fun c -> match c with | 'a' -> 1 | '\\'' -> 2 | _ -> 0 in ";
+    assert_eq!(parse_and_eval(&format!("{classify}classify 'a'")), Ok(Value::Int(1)));
+    assert_eq!(parse_and_eval(&format!("{classify}classify '\\''")), Ok(Value::Int(2)));
+    assert_eq!(parse_and_eval(&format!("{classify}classify 'z'")), Ok(Value::Int(0)));
+}
+
 #[test]
 fn test_char_pattern_matching_escape() {
     let code = "match '\\n' with | '\\n' -> 10 | '\\t' -> 20 | _ -> 30";
@@ -1585,3 +1639,93 @@ fn test_string_pattern_match_empty() {
     assert_eq!(parse_and_eval(code), Ok(Value::Int(0)));
 }
 
+
+#[test]
+fn test_string_literal_patterns() {
+    let classify = |input: &str| {
+        parse_and_eval(&format!(
+            r#"
+            type List a = Nil | Cons a (List a) in
+            let answer = fun s -> match s with
+                | "yes" -> 1
+                | "no" -> 0
+                | "" -> -1
+                | _ -> -2
+            in answer "{input}"
+            "#
+        ))
+    };
+    assert_eq!(classify("yes"), Ok(Value::Int(1)));
+    assert_eq!(classify("no"), Ok(Value::Int(0)));
+    assert_eq!(classify(""), Ok(Value::Int(-1)));
+    assert_eq!(classify("yess"), Ok(Value::Int(-2)));
+    assert_eq!(classify("ye"), Ok(Value::Int(-2)));
+
+    // Inside constructor patterns, with escapes
+    let code = r#"
+        type List a = Nil | Cons a (List a) in
+        type Option a = Some a | None in
+        match Some "a\n" with
+        | Some "a\n" -> true
+        | _ -> false
+    "#;
+    assert_eq!(parse_and_eval(code), Ok(Value::Bool(true)));
+    assert!(parse("match s with | \"x{s}\" -> 1 | _ -> 0").is_err());
+}
+
+#[test]
+fn test_string_equality_operators() {
+    let list = "type List a = Nil | Cons a (List a) in ";
+    assert_eq!(parse_and_eval(&format!("{list}\"abc\" == \"abc\"")), Ok(Value::Bool(true)));
+    assert_eq!(parse_and_eval(&format!("{list}\"abc\" == \"abd\"")), Ok(Value::Bool(false)));
+    assert_eq!(parse_and_eval(&format!("{list}\"ab\" != \"abc\"")), Ok(Value::Bool(true)));
+    assert_eq!(parse_and_eval(&format!("{list}\"\" == \"\"")), Ok(Value::Bool(true)));
+    assert_eq!(parse_and_eval(&format!("{list}\"ab\" ^ \"c\" == \"abc\"")), Ok(Value::Bool(true)));
+    // Other lists compare structurally too
+    assert_eq!(parse_and_eval(&format!("{list}Cons 1 Nil == Cons 1 Nil")), Ok(Value::Bool(true)));
+
+    let expr = parse(&format!("{list}\"a\" == \"b\"")).unwrap();
+    assert_eq!(parlang::typecheck(&expr), Ok(parlang::Type::Bool));
+}
+
+#[test]
+fn test_string_display_round_trips() {
+    let code = r#"
+        type List a = Nil | Cons a (List a) in
+        "say \"hi\"\n\t{{ok}} \\ 世界"
+    "#;
+    let value = parse_and_eval(code).unwrap();
+    assert_eq!(value.as_string().as_deref(), Some("say \"hi\"\n\t{ok} \\ 世界"));
+    let shown = value.to_string();
+    assert_eq!(shown, r#""say \"hi\"\n\t{{ok}} \\ 世界""#);
+    let reparsed = parse_and_eval(&format!("type List a = Nil | Cons a (List a) in {shown}")).unwrap();
+    assert_eq!(reparsed, value);
+
+    // Lists of other values still print as constructors
+    let numbers = parse_and_eval("type List a = Nil | Cons a (List a) in Cons 1 Nil").unwrap();
+    assert_eq!(numbers.to_string(), "Cons(1, Nil)");
+    assert_eq!(numbers.as_string(), None);
+}
+
+#[test]
+fn test_unary_minus_on_expressions() {
+    assert_eq!(parse_and_eval("let x = 5 in -x"), Ok(Value::Int(-5)));
+    assert_eq!(parse_and_eval("-(1 + 2)"), Ok(Value::Int(-3)));
+    assert_eq!(parse_and_eval("let f = fun n -> n * 2 in -f 3"), Ok(Value::Int(-6)));
+    assert_eq!(parse_and_eval("let x = 3 in 10 - -x"), Ok(Value::Int(13)));
+    assert_eq!(parse_and_eval("let x = 3 in -x * -x"), Ok(Value::Int(9)));
+    assert_eq!(parse_and_eval("let x = 4 in x -1"), Ok(Value::Int(3)));
+    assert_eq!(parse_and_eval("let x = 1.5 in -x"), Ok(Value::Float(-1.5)));
+}
+
+#[test]
+fn test_unary_minus_errors() {
+    assert_eq!(
+        parse_and_eval("-true"),
+        Err("Type error: Cannot negate true (Bool): negation requires an Int or a Float".to_string())
+    );
+    assert_eq!(
+        parse_and_eval("let m = -9223372036854775807 - 1 in -m"),
+        Err("Type error: Integer overflow in negation".to_string())
+    );
+}
@@ -0,0 +1,154 @@
+/// Tests for the machine-readable language summary
+///
+/// The summary is read from the parser's own tables, so these tests check
+/// it against the parser's behavior rather than against the tables.
+use parlang::language_spec::language_spec;
+use parlang::parser::Associativity;
+use parlang::{parse, BinOp, Expr};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// Lowercase words in the docs and examples, as candidate identifiers
+fn corpus_words() -> BTreeSet<String> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = vec![root.join("README.md"), root.join("docs/LANGUAGE_SPEC.md")];
+    let mut dirs = vec![root.join("examples")];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "par") {
+                files.push(path);
+            }
+        }
+    }
+    let mut words = BTreeSet::new();
+    for file in files {
+        let text = fs::read_to_string(file).unwrap();
+        for word in text.split(|c: char| !c.is_ascii_alphanumeric() && c != '_') {
+            if word.starts_with(|c: char| c.is_ascii_lowercase()) {
+                words.insert(word.to_string());
+            }
+        }
+    }
+    words
+}
+
+#[test]
+fn test_keywords_are_exactly_the_rejected_identifiers() {
+    let spec = language_spec();
+    let mut words = corpus_words();
+    words.extend(spec.keywords.iter().map(|keyword| (*keyword).to_string()));
+    assert!(words.len() > 100, "corpus too small: {}", words.len());
+
+    for word in &words {
+        let rejected = parse(&format!("let {word} = 1 in {word}")).is_err();
+        assert_eq!(rejected, spec.keywords.contains(&word.as_str()), "{word}");
+    }
+}
+
+#[test]
+fn test_operator_precedences() {
+    use Associativity::{Left, None, Right};
+    let expected = [
+        (":=", 1, Right),
+        ("==", 2, None),
+        ("!=", 2, None),
+        ("<=", 2, None),
+        (">=", 2, None),
+        ("<", 2, None),
+        (">", 2, None),
+        ("..", 3, None),
+        ("^", 4, Right),
+        ("::", 5, Right),
+        ("+", 6, Left),
+        ("-", 6, Left),
+        ("*", 7, Left),
+        ("/", 7, Left),
+    ];
+    let spec = language_spec();
+    let actual: Vec<_> = spec.operators.iter().map(|op| (op.symbol, op.precedence, op.associativity)).collect();
+    assert_eq!(actual, expected);
+    assert_eq!(spec.application_precedence, 8);
+}
+
+/// The operator symbol at the root of a parsed infix expression
+fn root_symbol(expr: &Expr) -> String {
+    match expr {
+        Expr::BinOp(op, _, _) => op.to_string(),
+        Expr::Range(_, _) => "..".to_string(),
+        Expr::RefAssign(_, _) => ":=".to_string(),
+        Expr::Constructor(name, _) if name == "Cons" => "::".to_string(),
+        other => panic!("not an infix expression: {other:?}"),
+    }
+}
+
+#[test]
+fn test_parser_follows_operator_table() {
+    let operators = language_spec().operators;
+    for low in &operators {
+        for high in operators.iter().filter(|high| high.precedence > low.precedence) {
+            // The looser operator ends up at the root, whichever side it is on
+            for source in [format!("a {} b {} c", low.symbol, high.symbol), format!("a {} b {} c", high.symbol, low.symbol)] {
+                let expr = parse(&source).unwrap_or_else(|e| panic!("{source}: {e}"));
+                assert_eq!(root_symbol(&expr), low.symbol, "{source}");
+            }
+        }
+
+        let chain = format!("a {} b {} c", low.symbol, low.symbol);
+        match (low.associativity, parse(&chain)) {
+            (Associativity::Left, Ok(Expr::BinOp(_, left, _))) => assert_eq!(root_symbol(&left), low.symbol),
+            (Associativity::Right, Ok(Expr::BinOp(_, _, right) | Expr::RefAssign(_, right))) => {
+                assert_eq!(root_symbol(&right), low.symbol);
+            }
+            (Associativity::Right, Ok(Expr::Constructor(_, args))) => assert_eq!(root_symbol(&args[1]), low.symbol),
+            (Associativity::None, Err(_)) => {}
+            (_, result) => panic!("{chain}: {result:?}"),
+        }
+    }
+    assert_eq!(parse("f x + 1"), parse("(f x) + 1"));
+    assert!(matches!(parse("2 * 3").unwrap(), Expr::BinOp(BinOp::Mul, _, _)));
+}
+
+#[test]
+fn test_literal_examples_parse() {
+    for literal in language_spec().literals {
+        for example in literal.examples {
+            assert!(parse(example).is_ok(), "{}: {example}", literal.kind);
+        }
+    }
+}
+
+#[test]
+fn test_builtins_match_the_prelude() {
+    let spec = language_spec();
+    let names: Vec<&str> = spec.builtins.iter().map(|builtin| builtin.name.as_str()).collect();
+    let prelude: Vec<String> = parlang::builtins::prelude_values().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, prelude);
+    let show = spec.builtins.iter().find(|builtin| builtin.name == "show").unwrap();
+    assert_eq!(show.ty, "t0 -> List Char");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_json_output_is_stable() {
+    let json = language_spec().to_json();
+    assert_eq!(json, language_spec().to_json());
+    assert!(json.starts_with("{\n  \"parlang_format\": 1,\n  \"keywords\": [\n    \"let\",\n    \"in\",\n"), "{json}");
+    let keys: Vec<usize> = ["\"operators\"", "\"application_precedence\"", "\"builtins\"", "\"literals\""]
+        .iter()
+        .map(|key| json.find(key).unwrap())
+        .collect();
+    assert!(keys.is_sorted(), "{json}");
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let find = |list: &str, key: &str, name: &str| {
+        value[list].as_array().unwrap().iter().find(|item| item[key] == name).unwrap().clone()
+    };
+    assert_eq!(find("operators", "symbol", "^"), serde_json::json!({"symbol": "^", "precedence": 4, "associativity": "right"}));
+    assert_eq!(value["application_precedence"], 8);
+    assert_eq!(find("builtins", "name", "min"), serde_json::json!({"name": "min", "type": "Int -> Int -> Int"}));
+    assert_eq!(find("literals", "kind", "Char"), serde_json::json!({"kind": "Char", "examples": ["'a'", "'\\n'"]}));
+}
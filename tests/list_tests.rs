@@ -0,0 +1,104 @@
+/// Tests for the prelude `List` type: list literals, `::`, and list patterns
+use parlang::{eval, parse, typecheck_in, Environment, Expr, TypeEnv, TypeError, Value};
+
+/// Evaluate a program with the prelude
+fn run(source: &str) -> Value {
+    let expr = parse(source).unwrap_or_else(|e| panic!("{source}: {e}"));
+    eval(&expr, &Environment::with_builtins()).unwrap_or_else(|e| panic!("{source}: {e}"))
+}
+
+/// Type check a program with the prelude, rendering its type with type
+/// variables renumbered from `t0` in order of appearance
+fn type_of(source: &str) -> Result<String, TypeError> {
+    let expr = parse(source).unwrap_or_else(|e| panic!("{source}: {e}"));
+    let rendered = typecheck_in(&expr, &mut TypeEnv::with_builtins())?.to_string();
+    let mut seen: Vec<String> = Vec::new();
+    let words = rendered.split(' ').map(|word| {
+        let name = word.trim_matches(|c| c == '(' || c == ')');
+        if !(name.starts_with('t') && name.len() > 1 && name[1..].chars().all(|c| c.is_ascii_digit())) {
+            return word.to_string();
+        }
+        let index = seen.iter().position(|var| var == name).unwrap_or_else(|| {
+            seen.push(name.to_string());
+            seen.len() - 1
+        });
+        word.replace(name, &format!("t{index}"))
+    });
+    Ok(words.collect::<Vec<_>>().join(" "))
+}
+
+fn list(values: Vec<Value>) -> Value {
+    values.into_iter().rev().fold(Value::Variant("Nil".to_string(), vec![]), |rest, value| {
+        Value::Variant("Cons".to_string(), vec![value, rest])
+    })
+}
+
+fn ints(values: &[i64]) -> Value {
+    list(values.iter().map(|n| Value::Int(*n)).collect())
+}
+
+const LENGTH: &str = "let length = rec length -> fun xs -> match xs with | [] -> 0 | _ :: rest -> 1 + length rest in ";
+const MAP: &str = "let map = rec map -> fun f -> fun xs -> match xs with | [] -> [] | x :: rest -> f x :: map f rest in ";
+const SUM: &str = "let sum = rec sum -> fun xs -> match xs with | [] -> 0 | x :: rest -> x + sum rest in ";
+
+#[test]
+fn test_list_literals_desugar_to_constructors() {
+    assert_eq!(parse("[1, 2]"), parse("Cons 1 (Cons 2 Nil)"));
+    assert_eq!(parse("[]"), Ok(Expr::Constructor("Nil".to_string(), vec![])));
+    assert_eq!(run("[1, 2, 3]"), ints(&[1, 2, 3]));
+    assert_eq!(run("[[1], []]"), list(vec![ints(&[1]), ints(&[])]));
+}
+
+#[test]
+fn test_cons_is_right_associative_below_arithmetic() {
+    assert_eq!(parse("1 :: 2 :: []"), parse("[1, 2]"));
+    assert_eq!(parse("x + 1 :: xs"), parse("Cons (x + 1) xs"));
+    assert_eq!(parse("f x :: xs"), parse("Cons (f x) xs"));
+    assert_eq!(run("let x = 1 in x * 10 :: [x + 1]"), ints(&[10, 2]));
+}
+
+#[test]
+fn test_list_argument_is_not_an_index() {
+    assert_eq!(run("let head = fun xs -> match xs with | x :: _ -> x | [] -> 0 in head [7]"), Value::Int(7));
+    assert_eq!(run("let a = [|4, 5|] in a[1]"), Value::Int(5));
+}
+
+#[test]
+fn test_list_patterns() {
+    let classify = "let classify = fun xs -> match xs with | [] -> 0 | [a] -> a | [a, b] -> a * b | a :: b :: _ -> a + b in ";
+    assert_eq!(run(&format!("{classify}classify []")), Value::Int(0));
+    assert_eq!(run(&format!("{classify}classify [5]")), Value::Int(5));
+    assert_eq!(run(&format!("{classify}classify [6, 7]")), Value::Int(42));
+    assert_eq!(run(&format!("{classify}classify [1, 2, 3]")), Value::Int(3));
+    assert_eq!(run("match [(1, 2)] with | [(a, b)] -> a + b | _ -> 0"), Value::Int(3));
+}
+
+#[test]
+fn test_recursive_length_map_and_sum() {
+    assert_eq!(run(&format!("{LENGTH}length [4, 5, 6]")), Value::Int(3));
+    assert_eq!(run(&format!("{LENGTH}length []")), Value::Int(0));
+    assert_eq!(run(&format!("{MAP}map (fun x -> x * x) [1, 2, 3]")), ints(&[1, 4, 9]));
+    assert_eq!(run(&format!("{SUM}sum [1, 2, 3, 4]")), Value::Int(10));
+    assert_eq!(run(&format!("{MAP}{SUM}sum (map (fun x -> x + 1) [1, 2, 3])")), Value::Int(9));
+}
+
+#[test]
+fn test_list_types() {
+    assert_eq!(type_of("[1, 2]").unwrap(), "List Int");
+    assert_eq!(type_of("true :: []").unwrap(), "List Bool");
+    assert_eq!(type_of("[\"a\", \"b\"]").unwrap(), "List (List Char)");
+    assert_eq!(type_of(&format!("{LENGTH}length")).unwrap(), "List t0 -> Int");
+    assert_eq!(type_of(&format!("{MAP}map")).unwrap(), "(t0 -> t1) -> List t0 -> List t1");
+    assert_eq!(type_of(&format!("{SUM}sum [1, 2]")).unwrap(), "Int");
+    assert!(type_of("[1, true]").is_err());
+    assert!(type_of("1 :: [true]").is_err());
+    assert!(type_of("match [1] with | [] -> 0 | x :: _ -> x == true").is_err());
+}
+
+#[test]
+fn test_user_list_type_replaces_the_prelude_one() {
+    let source = "type List a = Empty | Node a (List a) in match Node 1 Empty with | Node x _ -> x | Empty -> 0";
+    assert_eq!(run(source), Value::Int(1));
+    assert_eq!(type_of(source).unwrap(), "Int");
+    assert_eq!(run("type List a = Nil | Cons a (List a) in [1]"), ints(&[1]));
+}
@@ -0,0 +1,90 @@
+/// Tests that the evaluator and the type checker follow the same numeric rules
+///
+/// Every combination of a governed operator and two scalar kinds is tried
+/// with representative operands. Combinations listed in `NUMERIC_RULES` must
+/// type check and evaluate to the listed result kind; all others must be
+/// rejected by both layers.
+use parlang::numeric_rules::{governs, result_kind, ScalarKind, NUMERIC_RULES};
+use parlang::{eval, parse, typecheck, BinOp, Environment, EvalError};
+
+const OPERATORS: [BinOp; 8] = [
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Lt,
+    BinOp::Le,
+    BinOp::Gt,
+    BinOp::Ge,
+];
+
+/// A literal of `kind`, chosen so that no operation on two of them overflows or divides by zero
+fn operand(kind: ScalarKind, left: bool) -> &'static str {
+    match (kind, left) {
+        (ScalarKind::Int, true) => "6",
+        (ScalarKind::Int, false) => "3",
+        (ScalarKind::Float, true) => "6.0",
+        (ScalarKind::Float, false) => "3.0",
+        (ScalarKind::Byte, true) => "6b",
+        (ScalarKind::Byte, false) => "3b",
+        (ScalarKind::Char, true) => "'b'",
+        (ScalarKind::Char, false) => "'a'",
+        (ScalarKind::Bool, true) => "true",
+        (ScalarKind::Bool, false) => "false",
+    }
+}
+
+/// Programs applying `op` to operands of the given kinds, directly and through variables
+fn programs(op: BinOp, left: ScalarKind, right: ScalarKind) -> [String; 2] {
+    let (a, b) = (operand(left, true), operand(right, false));
+    [format!("{a} {op} {b}"), format!("let a = {a} in let b = {b} in a {op} b")]
+}
+
+#[test]
+fn test_table_covers_only_governed_operators() {
+    assert!(NUMERIC_RULES.iter().all(|rule| OPERATORS.contains(&rule.op)));
+    assert!(OPERATORS.iter().all(|op| governs(*op)));
+    assert!(!governs(BinOp::Eq) && !governs(BinOp::Neq) && !governs(BinOp::Concat));
+}
+
+#[test]
+fn test_allowed_combinations_typecheck_and_evaluate() {
+    for rule in NUMERIC_RULES {
+        for source in programs(rule.op, rule.left, rule.right) {
+            let expr = parse(&source).unwrap_or_else(|e| panic!("{source}: {e}"));
+            let ty = typecheck(&expr).unwrap_or_else(|e| panic!("{source}: {e}"));
+            assert_eq!(ty, rule.result.to_type(), "{source}");
+            let value = eval(&expr, &Environment::new()).unwrap_or_else(|e| panic!("{source}: {e}"));
+            assert_eq!(ScalarKind::of_value(&value), Some(rule.result), "{source}");
+        }
+    }
+}
+
+#[test]
+fn test_disallowed_combinations_are_rejected_by_both_layers() {
+    for op in OPERATORS {
+        for left in ScalarKind::ALL {
+            for right in ScalarKind::ALL {
+                if result_kind(op, left, right).is_some() {
+                    continue;
+                }
+                for source in programs(op, left, right) {
+                    let expr = parse(&source).unwrap_or_else(|e| panic!("{source}: {e}"));
+                    assert!(typecheck(&expr).is_err(), "{source} type checks");
+                    assert!(
+                        matches!(eval(&expr, &Environment::new()), Err(EvalError::BinOpTypeMismatch { .. })),
+                        "{source} evaluates"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_unknown_operands_default_to_int() {
+    let expr = parse("fun a -> fun b -> a < b").unwrap();
+    assert_eq!(typecheck(&expr).unwrap().to_string(), "Int -> Int -> Bool");
+    let expr = parse("fun a -> a + 1.5").unwrap();
+    assert_eq!(typecheck(&expr).unwrap().to_string(), "Float -> Float");
+}
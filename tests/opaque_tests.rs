@@ -0,0 +1,112 @@
+/// Integration tests for opaque host values passed through `ParLang` code
+use parlang::builtins::Builtin;
+use parlang::{eval, parse, typecheck_in, Environment, EvalError, Type, TypeEnv, TypeScheme, Value};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+/// Stand-in for an embedder's database connection
+struct Connection {
+    name: String,
+    queries: Mutex<Vec<i64>>,
+}
+
+/// `query conn n` records `n` on the connection and returns `n * 10`
+fn query_builtin() -> Value {
+    Value::Builtin(Builtin::new(
+        "query",
+        2,
+        Rc::new(|args: &[Value]| {
+            let conn = args[0]
+                .downcast_opaque::<Connection>("Connection")
+                .ok_or_else(|| EvalError::TypeError(format!("query: expected a Connection, got {}", args[0])))?;
+            let Value::Int(n) = args[1] else {
+                return Err(EvalError::TypeError(format!("query: expected an Int, got {}", args[1])));
+            };
+            conn.queries.lock().unwrap().push(n);
+            Ok(Value::Int(n * 10))
+        }),
+    ))
+}
+
+fn host_env(conn: &Value) -> Environment {
+    let mut env = Environment::new();
+    env.bind("query".to_string(), query_builtin());
+    env.bind("db".to_string(), conn.clone());
+    env
+}
+
+fn host_type_env() -> TypeEnv {
+    let connection = Type::Opaque("Connection".to_string());
+    let mut tenv = TypeEnv::new();
+    tenv.declare_opaque("Connection");
+    let query_ty = Type::Fun(
+        Box::new(connection.clone()),
+        Box::new(Type::Fun(Box::new(Type::Int), Box::new(Type::Int))),
+    );
+    tenv.bind("query".to_string(), TypeScheme { vars: vec![], row_vars: vec![], ty: query_ty });
+    tenv.bind("db".to_string(), TypeScheme { vars: vec![], row_vars: vec![], ty: connection });
+    tenv
+}
+
+fn connection(name: &str) -> Value {
+    Value::opaque("Connection", Connection { name: name.to_string(), queries: Mutex::new(Vec::new()) })
+}
+
+#[test]
+fn test_handle_threads_through_functions() {
+    let conn = connection("main");
+    let source = "let run_twice = fun c -> fun n -> query c n + query c (n + 1) in \
+                  let pair = (db, 3) in \
+                  run_twice pair.0 pair.1";
+    let expr = parse(source).unwrap();
+    assert_eq!(typecheck_in(&expr, &mut host_type_env()), Ok(Type::Int));
+    assert_eq!(eval(&expr, &host_env(&conn)), Ok(Value::Int(70)));
+
+    let handle = conn.downcast_opaque::<Connection>("Connection").unwrap();
+    assert_eq!(handle.name, "main");
+    assert_eq!(*handle.queries.lock().unwrap(), vec![3, 4]);
+}
+
+#[test]
+fn test_handle_round_trips_by_identity() {
+    let conn = connection("main");
+    let result = eval(&parse("let keep = fun c -> c in (keep db, 1)").unwrap(), &host_env(&conn)).unwrap();
+    assert_eq!(result, Value::Tuple(vec![conn.clone(), Value::Int(1)]));
+    assert_eq!(result.to_string(), "(<opaque: Connection>, 1)");
+
+    // Equal only to itself, not to another handle with the same contents
+    assert_ne!(conn, connection("main"));
+    let mut env = host_env(&conn);
+    env.bind("other".to_string(), connection("main"));
+    assert_eq!(eval(&parse("(db == db, db == other, db != other)").unwrap(), &env),
+        Ok(Value::Tuple(vec![Value::Bool(true), Value::Bool(false), Value::Bool(true)])));
+    assert!(conn.downcast_opaque::<String>("Connection").is_none());
+}
+
+#[test]
+fn test_type_rejections() {
+    let mut tenv = host_type_env();
+    // `match` is not typed yet, so literal patterns are only rejected at runtime
+    for source in ["db + 1", "query 1 2", "db < db", "if db then 1 else 2"] {
+        let expr = parse(source).unwrap();
+        assert!(typecheck_in(&expr, &mut tenv).is_err(), "{source} should not typecheck");
+    }
+    // Declared tags can be used in annotations
+    let expr = parse("let c : Connection = db in query c 1").unwrap();
+    assert_eq!(typecheck_in(&expr, &mut tenv), Ok(Type::Int));
+    assert!(typecheck_in(&parse("let c : Connection = 1 in c").unwrap(), &mut tenv).is_err());
+}
+
+#[test]
+fn test_runtime_rejections() {
+    let env = host_env(&connection("main"));
+    let run = |source: &str| eval(&parse(source).unwrap(), &env);
+    assert!(matches!(run("db + 1"), Err(EvalError::TypeError(msg)) if msg.contains("Opaque Connection value")));
+    assert!(matches!(run("db < db"), Err(EvalError::TypeError(msg)) if msg.contains("operator <")));
+    assert!(matches!(
+        run("match (1, db) with | (1, 0) -> 1 | _ -> 2"),
+        Err(EvalError::TypeError(msg)) if msg.contains("literal pattern 0")
+    ));
+    assert_eq!(run("match (1, db) with | (1, c) -> query c 5 | _ -> 0"), Ok(Value::Int(50)));
+    assert!(matches!(run("query 1 2"), Err(EvalError::TypeError(msg)) if msg.contains("expected a Connection")));
+}
@@ -0,0 +1,75 @@
+/// Integration tests for matching host values with `parse_pattern` and `match_value`
+use parlang::ast::Pattern;
+use parlang::{eval, match_value, parse, parse_pattern, Environment, Fields, Value};
+
+fn bindings(pairs: &[(&str, Value)]) -> Vec<(String, Value)> {
+    pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+}
+
+#[test]
+fn test_match_tuple_pattern_against_host_value() {
+    let pattern = parse_pattern("(0, port, _)").unwrap();
+    let route = Value::Tuple(vec![Value::Int(0), Value::Int(8080), Value::Bool(true)]);
+    assert_eq!(match_value(&pattern, &route), Some(bindings(&[("port", Value::Int(8080))])));
+
+    let other = Value::Tuple(vec![Value::Int(1), Value::Int(8080), Value::Bool(true)]);
+    assert_eq!(match_value(&pattern, &other), None);
+    let short = Value::Tuple(vec![Value::Int(0), Value::Int(8080)]);
+    assert_eq!(match_value(&pattern, &short), None);
+}
+
+#[test]
+fn test_match_constructor_pattern() {
+    let pattern = parse_pattern("Some (Cons x _)").unwrap();
+    let list = Value::Variant("Cons".to_string(), vec![Value::Int(1), Value::Variant("Nil".to_string(), vec![])]);
+    let value = Value::Variant("Some".to_string(), vec![list]);
+    assert_eq!(match_value(&pattern, &value), Some(bindings(&[("x", Value::Int(1))])));
+    assert_eq!(match_value(&pattern, &Value::Variant("None".to_string(), vec![])), None);
+}
+
+#[test]
+fn test_match_partial_record_pattern() {
+    let pattern = parse_pattern("{ host: h, port: 443 }").unwrap();
+    let config = Value::Record(Fields::from([
+        ("host".to_string(), Value::Int(10)),
+        ("port".to_string(), Value::Int(443)),
+        ("tls".to_string(), Value::Bool(true)),
+    ]));
+    assert_eq!(match_value(&pattern, &config), Some(bindings(&[("h", Value::Int(10))])));
+}
+
+#[test]
+fn test_bindings_follow_pattern_order() {
+    let pattern = parse_pattern("(b, (a, c))").unwrap();
+    let value = Value::Tuple(vec![Value::Int(1), Value::Tuple(vec![Value::Int(2), Value::Int(3)])]);
+    assert_eq!(
+        match_value(&pattern, &value),
+        Some(bindings(&[("b", Value::Int(1)), ("a", Value::Int(2)), ("c", Value::Int(3))]))
+    );
+}
+
+#[test]
+fn test_match_value_agrees_with_match_expression() {
+    let value = eval(&parse("type Option a = Some a | None in (1, Some 2)").unwrap(), &Environment::with_builtins()).unwrap();
+    let pattern = parse_pattern("(x, Some y)").unwrap();
+    assert_eq!(match_value(&pattern, &value), Some(bindings(&[("x", Value::Int(1)), ("y", Value::Int(2))])));
+}
+
+#[test]
+fn test_parse_pattern_rejects_duplicate_binders() {
+    assert_eq!(
+        parse_pattern("(x, Some x)"),
+        Err("Parse error: variable 'x' is bound more than once in pattern '(x, Some x)'".to_string())
+    );
+    assert!(parse_pattern("{ a: v, b: v }").is_err());
+    assert!(parse_pattern("(_, _)").is_ok());
+    let err = parse("match (1, 2) with | (a, a) -> a").unwrap_err().to_string();
+    assert!(err.contains("variable 'a' is bound more than once"), "{err}");
+}
+
+#[test]
+fn test_parse_pattern_rejects_trailing_input() {
+    assert!(matches!(parse_pattern("  x  "), Ok(Pattern::Var(name)) if name == "x"));
+    assert_eq!(parse_pattern("x -> 1"), Err("Unexpected input after pattern: '-> 1'".to_string()));
+    assert!(parse_pattern("").is_err());
+}
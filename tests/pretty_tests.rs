@@ -0,0 +1,67 @@
+/// Tests that pretty-printing never changes what a program parses to
+///
+/// Every `.par` file under `examples/` and `tests/` is parsed, printed at
+/// several widths and parsed again, and the two trees must be equal.
+/// Printing the printed program again must not change it.
+use parlang::check::NO_TYPECHECK_MARKER;
+use parlang::parse;
+use parlang::pretty::{pretty, DEFAULT_WIDTH};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/// The `.par` files under `dir`, recursively
+fn par_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            par_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "par") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_pretty_round_trips_example_programs() {
+    let mut files = Vec::new();
+    par_files(Path::new("examples"), &mut files);
+    par_files(Path::new("tests"), &mut files);
+    files.sort();
+    assert!(!files.is_empty());
+    // Some examples nest deeply enough to need a large stack to parse
+    thread::Builder::new()
+        .stack_size(512 * 1024 * 1024)
+        .spawn(move || {
+            for path in files {
+                let source = fs::read_to_string(&path).unwrap();
+                let source = source.strip_prefix(NO_TYPECHECK_MARKER).unwrap_or(&source);
+                let expr = parse(source).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+                for width in [20, 40, DEFAULT_WIDTH, 200] {
+                    let printed = pretty(&expr, width);
+                    let reparsed = parse(&printed)
+                        .unwrap_or_else(|e| panic!("{} at width {width} does not parse: {e}\n{printed}", path.display()));
+                    assert_eq!(reparsed, expr, "{} at width {width} parses differently:\n{printed}", path.display());
+                    assert_eq!(pretty(&reparsed, width), printed, "{} at width {width} is not stable", path.display());
+                }
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn test_pretty_keeps_lines_within_the_width() {
+    let source = "let total = fun items -> fold_left (fun acc -> fun item -> acc + item.price * item.quantity) 0 items in \
+                  let order = [{ price: 250, quantity: 2 }, { price: 1200, quantity: 1 }, { price: 75, quantity: 12 }] in \
+                  match total order with | 0 -> \"empty order\" | n -> \"total: {n}\"";
+    let expr = parse(source).unwrap();
+    for width in [30, 50, DEFAULT_WIDTH] {
+        let printed = pretty(&expr, width);
+        for line in printed.lines() {
+            assert!(line.chars().count() <= width, "line over {width} columns: {line:?}\n{printed}");
+        }
+        assert_eq!(parse(&printed), Ok(expr.clone()));
+    }
+}
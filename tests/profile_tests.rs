@@ -0,0 +1,131 @@
+/// Tests for the call profiler
+///
+/// Profiles count evaluation steps rather than time, so the reports are
+/// exact and can be compared directly.
+use parlang::profile::{FunctionProfile, Profiler};
+use parlang::{eval, parse, Environment, Value};
+use std::rc::Rc;
+
+/// Evaluate `src` with a fresh profiler attached, returning the result and the profiler
+fn profile(src: &str) -> (Value, Rc<Profiler>) {
+    let profiler = Rc::new(Profiler::new());
+    let mut env = Environment::with_builtins();
+    env.set_profiler(Some(Rc::clone(&profiler)));
+    let value = eval(&parse(src).unwrap(), &env).unwrap();
+    (value, profiler)
+}
+
+/// Run `test` on a thread with a large stack
+///
+/// Each level of recursion in the evaluator takes several large stack frames
+/// in debug builds, more than a test thread's default stack holds.
+fn with_large_stack(test: impl FnOnce() + Send + 'static) {
+    let handle = std::thread::Builder::new().stack_size(256 * 1024 * 1024).spawn(test).unwrap();
+    if let Err(panic) = handle.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+/// Calls recorded for `name`
+fn calls(functions: &[FunctionProfile], name: &str) -> u64 {
+    functions.iter().find(|function| function.name == name).map_or(0, |function| function.calls)
+}
+
+#[test]
+fn test_profile_orders_functions_by_steps() {
+    with_large_stack(|| {
+        let (value, profiler) = profile(
+            "let helper = fun x -> x + 1;
+             let expensive = rec expensive -> fun n -> if n == 0 then 0 else n + expensive (n - 1);
+             let loop = rec loop -> fun i -> if i == 0 then 0 else helper i + loop (i - 1);
+             loop 10 + expensive 100",
+        );
+        assert_eq!(value, Value::Int(65 + 5050));
+
+        let report = profiler.report();
+        let names: Vec<&str> = report.functions.iter().map(|function| function.name.as_str()).collect();
+        assert_eq!(names, ["expensive", "loop", "helper"]);
+        assert_eq!(calls(&report.functions, "helper"), 10);
+        assert_eq!(calls(&report.functions, "loop"), 11);
+        assert_eq!(calls(&report.functions, "expensive"), 101);
+        // Inclusive: the loop's steps contain those of the helper it calls
+        assert!(report.functions[1].steps > report.functions[2].steps);
+        assert!(report.total_steps > report.functions[0].steps + report.functions[1].steps);
+    });
+}
+
+#[test]
+fn test_profile_is_deterministic() {
+    let src = "let sq = fun x -> x * x in sq 3 + sq 4";
+    assert_eq!(profile(src).1.report(), profile(src).1.report());
+}
+
+#[test]
+fn test_profile_counts_tail_calls() {
+    let (_, profiler) = profile("let count = rec count -> fun n -> if n == 0 then 0 else count (n - 1) in count 1000");
+    assert_eq!(calls(&profiler.report().functions, "count"), 1001);
+}
+
+#[test]
+fn test_profile_counts_mutual_tail_calls() {
+    let (_, profiler) = profile(
+        "let rec ping = fun n -> let rec pong = fun m -> if m == 0 then 0 else ping (m - 1) in \
+         if n == 0 then 0 else pong (n - 1) in ping 1000",
+    );
+    let report = profiler.report();
+    assert_eq!(calls(&report.functions, "ping"), 501);
+    assert_eq!(calls(&report.functions, "pong"), 500);
+}
+
+#[test]
+fn test_profile_curried_application_is_one_call() {
+    let (_, profiler) = profile(
+        "let add = fun a -> fun b -> a + b;
+         let adder = fun a -> let unused = 0 in fun b -> a + b;
+         add 1 2 + adder 3 4",
+    );
+    let report = profiler.report();
+    assert_eq!(calls(&report.functions, "add"), 1);
+    assert_eq!(calls(&report.functions, "adder"), 1);
+    assert_eq!(report.functions.len(), 2);
+}
+
+#[test]
+fn test_profile_names_anonymous_functions() {
+    let (_, profiler) = profile("let twice = fun f -> fun x -> f (f x) in twice (fun y -> y + 1) 0 + (fun z -> z) 1");
+    let report = profiler.report();
+    assert_eq!(calls(&report.functions, "twice"), 1);
+    // Called through the parameter `f`
+    assert_eq!(calls(&report.functions, "f"), 2);
+    assert_eq!(calls(&report.functions, "<anonymous@1>"), 1);
+}
+
+#[test]
+fn test_profile_counts_closures_called_by_builtins() {
+    let (value, profiler) = profile("min_by (fun a -> fun b -> a > b) 3 2");
+    assert_eq!(value, Value::Int(3));
+    assert_eq!(calls(&profiler.report().functions, "<anonymous@1>"), 1);
+}
+
+#[test]
+fn test_profile_render_limits_rows() {
+    let (_, profiler) = profile("let a = fun x -> x in let b = fun x -> x + x in b (a 1)");
+    let table = profiler.report().render(Some(1));
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("function"));
+    assert!(lines[1].starts_with("b "));
+    assert_eq!(lines[2], "(1 more function not shown)");
+    assert!(lines[3].starts_with("total: "));
+}
+
+#[test]
+fn test_disabled_profiler_records_nothing() {
+    let profiler = Rc::new(Profiler::new());
+    profiler.set_enabled(false);
+    let mut env = Environment::new();
+    env.set_profiler(Some(Rc::clone(&profiler)));
+    eval(&parse("(fun x -> x) 1").unwrap(), &env).unwrap();
+    let report = profiler.report();
+    assert_eq!(report.total_steps, 0);
+    assert!(report.functions.is_empty());
+}
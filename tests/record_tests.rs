@@ -386,3 +386,35 @@ fn test_record_type_error_field_not_found() {
         other => panic!("Expected FieldNotFound or RecordFieldMismatch type error, got {:?}", other),
     }
 }
+
+#[test]
+fn test_record_fields_evaluate_left_to_right() {
+    let source = r#"
+        let r = ref 0 in
+        let record = { second: let u = r := !r + 1 in !r, first: let u = r := !r * 10 in !r } in
+        (record.second, record.first)
+    "#;
+    let expr = parse(source).expect("Parse error");
+    let result = eval(&expr, &Environment::new()).expect("Eval error");
+    assert_eq!(format!("{}", result), "(1, 10)");
+}
+
+#[test]
+fn test_record_display_and_missing_field() {
+    let expr = parse("{y: 2, x: 1}").expect("Parse error");
+    let result = eval(&expr, &Environment::new()).expect("Eval error");
+    // Fields are printed in the order the record was built
+    assert_eq!(format!("{}", result), "{y: 2, x: 1}");
+    let expr = parse("let r = {b: true, c: 'c', a: 1} in {x: r.a, w: r}").expect("Parse error");
+    let result = eval(&expr, &Environment::new()).expect("Eval error");
+    assert_eq!(format!("{}", result), "{x: 1, w: {b: true, c: 'c', a: 1}}");
+    // The order does not take part in equality
+    let reordered = eval(&parse("{w: {a: 1, c: 'c', b: true}, x: 1}").unwrap(), &Environment::new()).unwrap();
+    assert_eq!(result, reordered);
+
+    let expr = parse("{y: 2, x: 1}.z").expect("Parse error");
+    assert_eq!(
+        eval(&expr, &Environment::new()),
+        Err(EvalError::FieldNotFound("z".to_string(), vec!["y".to_string(), "x".to_string()]))
+    );
+}
@@ -35,7 +35,7 @@ fn test_ref_assignment() {
     let result = eval(&expr, &env).unwrap();
     
     // Assignment returns unit
-    assert_eq!(result, Value::Tuple(vec![]));
+    assert_eq!(result, Value::Unit);
 }
 
 #[test]
@@ -461,3 +461,50 @@ fn test_ref_with_arithmetic() {
     // Should be 10 + (20 * 2) = 50
     assert_eq!(result, Value::Int(50));
 }
+
+#[test]
+fn test_ref_equality_is_identity() {
+    let code = r"
+        let r1 = ref 10 in
+        let r2 = ref 10 in
+        let r3 = r1 in
+        (r1 == r2, r1 == r3, r1 != r2, !r1 == !r2)
+    ";
+    let result = eval(&parse(code).unwrap(), &Environment::new()).unwrap();
+    assert_eq!(result, Value::Tuple([false, true, true, true].map(Value::Bool).to_vec()));
+
+    // Assigning through one name is seen through the other, and they stay equal
+    let code = "let r1 = ref 1 in let r2 = r1 in let u = r2 := 5 in (r1 == r2, !r1)";
+    let result = eval(&parse(code).unwrap(), &Environment::new()).unwrap();
+    assert_eq!(result, Value::Tuple(vec![Value::Bool(true), Value::Int(5)]));
+}
+
+#[test]
+fn test_assignment_returns_unit() {
+    let code = "let r = ref 0 in match r := 1 with | () -> !r";
+    let result = eval(&parse(code).unwrap(), &Environment::new()).unwrap();
+    assert_eq!(result, Value::Int(1));
+
+    let code = "let r = ref 0 in (r := 1) == ()";
+    let result = eval(&parse(code).unwrap(), &Environment::new()).unwrap();
+    assert_eq!(result, Value::Bool(true));
+
+    let expr = parse("let r = ref 0 in r := 1").unwrap();
+    assert_eq!(eval(&expr, &Environment::new()).unwrap().to_string(), "()");
+    assert_eq!(typecheck(&expr).unwrap().to_string(), "()");
+}
+
+#[test]
+fn test_typecheck_ref_passed_to_function() {
+    let expr = parse("let f = fun r -> !r in f (ref 1)").unwrap();
+    assert_eq!(typecheck(&expr).unwrap().to_string(), "Int");
+
+    // Assigning through a parameter
+    let code = "let set = fun r -> fun v -> r := v in let c = ref 1 in let u = set c 7 in !c";
+    let expr = parse(code).unwrap();
+    assert_eq!(typecheck(&expr).unwrap().to_string(), "Int");
+    assert_eq!(eval(&expr, &Environment::new()).unwrap(), Value::Int(7));
+
+    // The contents still have to agree
+    assert!(typecheck(&parse("let set = fun r -> r := 1 in set (ref true)").unwrap()).is_err());
+}
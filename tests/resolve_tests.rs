@@ -0,0 +1,122 @@
+/// Tests that resolving variables to slots never changes what a program does
+///
+/// Each program runs with the tree-walking evaluator and with the resolved
+/// one, and the two runs must agree on the result, the error, the number of
+/// steps taken and the profile of the calls made.
+use parlang::profile::{ProfileReport, Profiler};
+use parlang::{eval, parse, Environment, EvalError, Value};
+use std::rc::Rc;
+
+/// Programs exercising every way a variable can be bound and looked up
+const PROGRAMS: &[&str] = &[
+    "let x = 1 in let y = 2 in x + y",
+    "let x = 1 in let x = x + 1 in x * 10",
+    "(fun a -> fun b -> fun c -> a * 100 + b * 10 + c) 1 2 3",
+    "let add = fun a -> fun b -> a + b in let inc = add 1 in (inc 41, add 2 3)",
+    "let k = 5 in let f = fun x -> fun y -> x + y + k in let g = f 1 in g 2 + g 3",
+    "let a = 1 in let f = fun b -> let g = fun c -> a + b + c in g in f 2 3",
+    "(rec fact -> fun n -> if n == 0 then 1 else n * fact (n - 1)) 10",
+    "let rec fib = fun n -> if n < 2 then n else fib (n - 1) + fib (n - 2) in fib 7",
+    "let rec loop = fun n -> if n == 0 then 0 else loop (n - 1) in loop 5000",
+    "let rec even = fun n -> if n == 0 then true else odd (n - 1) in even",
+    "let x = 0 in match (1, 2) with | (x, y) -> y - x | _ -> x",
+    "match [1, 2, 3] with | a :: b :: rest -> a + b | _ -> 0",
+    "let r = { a: 1, b: 2 } in r.a + r.b",
+    "let t = (1, (2, 3)) in t.1.0",
+    "type Shape = Circle Int | Square Int in match Square 4 with | Circle r -> r | Square s -> s * s",
+    "let counter = ref 0 in let bump = fun u -> counter := !counter + 1 in let a = bump () in let b = bump () in !counter",
+    "let arr = [|1, 2, 3|] in arr[0] + arr[2]",
+    "let f = fun x -> x in f",
+    "let f = fun a -> fun b -> a in f 1",
+    "rec f -> fun n -> f",
+    "let compose = fun f -> fun g -> fun x -> f (g x) in compose (fun x -> x * 2) (fun x -> x + 1) 5",
+    "let apply = fun f -> f 3 in apply (fun n -> n * n)",
+    "let x = 5 in x * x * x - 2 * x * x + 3 * x - 4",
+    "missing",
+    "let f = fun x -> x + y in f 1",
+    "let lenght = 3 in length + 1",
+    "let f = fun n -> n + 1 in f true",
+    "let g = fun x -> fun y -> x / y in g 1 0",
+    "(rec f -> 3)",
+    "let x = 1 in x 2",
+    "if 1 then 2 else 3",
+    "let rec loop = fun n -> if n then 0 else loop (n - 1) in loop 3",
+    "let f = fun x -> let y = x / 0 in y in f 1",
+    "let head = fun xs -> match xs with | [] -> 0 | x :: _ -> x / 0 in head [1]",
+    "let rec down = fun n -> match n with | 0 -> missing | _ -> let r = down (n - 1) in r + 1 in down 3",
+    "let k = 10 in match (3, 4) with | (a, b) when a + b > k -> a | (a, b) when a < b -> let c = b in c * k | _ -> 0",
+    "let rec count = fun n -> match n with | m when m > 0 -> count (m - 1) | _ -> 0 in count 5000",
+    "match 1 with | n when n -> 1 | _ -> 0",
+    "match 1 with | n when n / 0 == 1 -> 1 | _ -> 0",
+];
+
+/// Evaluate `source` under an optional step limit, returning the printed result and the call profile
+///
+/// Closures are compared by how they print, since the environments they
+/// capture record which evaluator created them.
+fn run(source: &str, resolved: bool, step_limit: Option<u64>) -> (Result<String, EvalError>, ProfileReport) {
+    let profiler = Rc::new(Profiler::new());
+    let mut env = Environment::with_builtins();
+    env.set_resolved(resolved);
+    env.set_step_limit(step_limit);
+    env.set_profiler(Some(Rc::clone(&profiler)));
+    let result = eval(&parse(source).unwrap_or_else(|e| panic!("{source}: {e}")), &env);
+    (result.map(|value| value.to_string()), profiler.report())
+}
+
+#[test]
+fn test_resolved_path_matches_tree_walker() {
+    for source in PROGRAMS {
+        assert_eq!(run(source, true, None), run(source, false, None), "{source}");
+    }
+}
+
+#[test]
+fn test_resolved_path_matches_tree_walker_under_step_limits() {
+    for source in PROGRAMS.iter().filter(|source| !source.contains("5000")) {
+        let (_, report) = run(source, false, None);
+        for limit in 0..=report.total_steps {
+            assert_eq!(
+                run(source, true, Some(limit)).0,
+                run(source, false, Some(limit)).0,
+                "{source} with limit {limit}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_unbound_variable_messages_are_identical() {
+    for source in ["missing", "let f = fun x -> x + y in f 1", "let lenght = 3 in length + 1", "(fun x -> fun y -> z) 1 2"] {
+        let (resolved, _) = run(source, true, None);
+        let (walked, _) = run(source, false, None);
+        assert_eq!(resolved.unwrap_err().to_string(), walked.unwrap_err().to_string(), "{source}");
+    }
+    let (result, _) = run("let lenght = 3 in length + 1", true, None);
+    assert!(result.unwrap_err().to_string().contains("lenght"));
+}
+
+#[test]
+fn test_globals_are_looked_up_by_name() {
+    let mut env = Environment::with_builtins();
+    env.set_resolved(true);
+    let env = env.extend("base".to_string(), Value::Int(40));
+    let expr = parse("let f = fun x -> x + base in f 2").unwrap();
+    assert_eq!(eval(&expr, &env), Ok(Value::Int(42)));
+}
+
+#[test]
+fn test_debug_env_sees_local_bindings() {
+    let source = "let a = 1 in let f = fun b -> (debug_env ()).b + a in f 2";
+    let results: Vec<_> = [true, false]
+        .into_iter()
+        .map(|resolved| {
+            let mut env = Environment::with_builtins();
+            env.set_resolved(resolved);
+            env.set_debug_builtins(true);
+            eval(&parse(source).unwrap(), &env)
+        })
+        .collect();
+    assert_eq!(results[0], Ok(Value::Int(3)));
+    assert_eq!(results[0], results[1]);
+}
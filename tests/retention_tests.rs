@@ -0,0 +1,64 @@
+/// Tests for limiting the environment closures in a result retain
+use parlang::retention::{captured_bindings, estimated_size};
+use parlang::{eval_with_config, parse, Environment, EvalConfig, EvalError, Value};
+
+const MILLION: usize = 1_000_000;
+
+fn env_with_big_array() -> Environment {
+    let mut env = Environment::with_builtins();
+    env.bind("big".to_string(), Value::Array(MILLION, vec![Value::Int(0); MILLION]));
+    env.bind("offset".to_string(), Value::Int(1));
+    env
+}
+
+const LIMIT: EvalConfig = EvalConfig { max_returned_closure_env: Some(64 * 1024), debug_builtins: false };
+
+#[test]
+fn test_closure_capturing_large_array_is_rejected() {
+    let expr = parse("let lookup = fun i -> big[i] + offset in lookup").unwrap();
+    match eval_with_config(&expr, &env_with_big_array(), &LIMIT) {
+        Err(EvalError::ResultRetainsTooMuch { binding_names, estimated_size }) => {
+            assert_eq!(binding_names, ["big", "offset"]);
+            assert!(estimated_size > MILLION, "{estimated_size}");
+        }
+        other => panic!("expected ResultRetainsTooMuch, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_limit_off_by_default() {
+    let expr = parse("let lookup = fun i -> big[i] + offset in lookup").unwrap();
+    let result = eval_with_config(&expr, &env_with_big_array(), &EvalConfig::default());
+    assert!(matches!(result, Ok(Value::Closure(..))));
+}
+
+#[test]
+fn test_unused_bindings_are_trimmed() {
+    // The closure could reach `big`, but its body never mentions it
+    let expr = parse("fun i -> i + offset").unwrap();
+    let result = eval_with_config(&expr, &env_with_big_array(), &LIMIT).unwrap();
+    assert_eq!(captured_bindings(&result), [("offset".to_string(), estimated_size(&Value::Int(1)))]);
+}
+
+#[test]
+fn test_closures_inside_results_are_checked() {
+    let expr = parse("(1, { get: fun i -> big[i] })").unwrap();
+    let result = eval_with_config(&expr, &env_with_big_array(), &LIMIT);
+    assert!(matches!(result, Err(EvalError::ResultRetainsTooMuch { .. })), "{result:?}");
+
+    // A closure captured by another closure counts towards the outer one
+    let expr = parse("let get = fun i -> big[i] in fun i -> get i").unwrap();
+    match eval_with_config(&expr, &env_with_big_array(), &LIMIT) {
+        Err(EvalError::ResultRetainsTooMuch { binding_names, .. }) => assert_eq!(binding_names, ["get"]),
+        other => panic!("expected ResultRetainsTooMuch, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_error_message_names_bindings() {
+    let error = EvalError::ResultRetainsTooMuch { binding_names: vec!["big".to_string()], estimated_size: 100 };
+    assert_eq!(
+        error.to_string(),
+        "Result retains too much: a returned closure captures about 100 bytes, mostly big"
+    );
+}
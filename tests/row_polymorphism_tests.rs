@@ -1,5 +1,6 @@
 /// Integration tests for row polymorphism in record types
 use parlang::{eval, parse, typecheck, Environment, Type};
+use std::collections::HashMap;
 
 /// Test basic row polymorphic function: fun r -> r.field
 #[test]
@@ -55,17 +56,11 @@ fn test_row_polymorphic_type_inference() {
     "#;
     
     let expr = parse(source).expect("Parse error");
-    let ty = typecheck(&expr);
-    
-    // This is currently expected to fail because we can't yet handle
-    // multiple field accesses on the same row variable in a single pass
-    // The issue is that after accessing r.x, the type becomes {x: Int | r0}
-    // and then accessing r.y fails because y is not in the known fields
-    // This would require more sophisticated constraint tracking
-    assert!(ty.is_err() || {
-        let type_str = format!("{}", ty.unwrap());
-        type_str.contains("Int")
-    });
+    let ty = typecheck(&expr).expect("Type error");
+    
+    // Accessing r.y after r.x extends the row of r with y
+    let type_str = ty.to_string();
+    assert!(type_str.starts_with("{x: Int, y: Int | r") && type_str.ends_with("} -> Int"), "{type_str}");
 }
 
 /// Test row polymorphic function can accept records with extra fields
@@ -351,3 +346,39 @@ fn test_row_polymorphic_let_generalization() {
     
     assert_eq!(format!("{}", result), "55");
 }
+
+/// Test that a record missing a field the function reads is rejected
+#[test]
+fn test_row_polymorphic_missing_field_is_rejected() {
+    for source in ["(fun r -> r.x + r.y) {x: 1}", "let addXY = fun r -> r.x + r.y in addXY { y: 2, z: 3 }"] {
+        let expr = parse(source).expect("Parse error");
+        assert!(typecheck(&expr).is_err(), "{source}");
+    }
+}
+
+/// Test that unifying with a closed record closes the row
+#[test]
+fn test_row_variable_is_bound_by_closed_record() {
+    let source = "fun r -> let a = r.x in if true then r else { x: 1 }";
+    let expr = parse(source).expect("Parse error");
+    assert_eq!(typecheck(&expr).expect("Type error").to_string(), "{x: Int} -> {x: Int}");
+
+    // The result has exactly the fields of { x: 1 }, so y is not available
+    let source = "let f = fun r -> let a = r.x in if true then r else { x: 1 } in (f { x: 1, y: true }).y";
+    let expr = parse(source).expect("Parse error");
+    assert!(typecheck(&expr).is_err());
+}
+
+/// Test that two open records unify by adding the missing fields to one row
+#[test]
+fn test_open_records_share_their_row() {
+    let pick = "let pick = fun a -> fun b -> let u = a.x in let v = b.x + b.y in if true then a else b in pick";
+    let expr = parse(&format!("{pick} {{ x: 1, y: 2 }} {{ x: 3, y: 4 }}")).expect("Parse error");
+    assert_eq!(
+        typecheck(&expr),
+        Ok(Type::Record(HashMap::from([("x".to_string(), Type::Int), ("y".to_string(), Type::Int)])))
+    );
+
+    let expr = parse(&format!("{pick} {{ x: 1 }} {{ x: 2, y: 3 }}")).expect("Parse error");
+    assert!(typecheck(&expr).is_err());
+}
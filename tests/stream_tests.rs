@@ -0,0 +1,79 @@
+/// Tests for streaming large evaluation results to the host
+///
+/// The whole test binary runs under a counting allocator so the test can
+/// check how much memory converting a result takes. Keep it to one test, as
+/// tests in the same binary run concurrently and would skew the counts.
+use parlang::stream::eval_stream;
+use parlang::{parse, Environment, Value};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct CountingAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(bytes: usize) {
+    let now = CURRENT.fetch_add(bytes, Ordering::SeqCst) + bytes;
+    PEAK.fetch_max(now, Ordering::SeqCst);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        grow(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size > layout.size() {
+            grow(new_size - layout.size());
+        } else {
+            CURRENT.fetch_sub(layout.size() - new_size, Ordering::SeqCst);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Run `f` and return its result with the most memory it held at once
+fn peak_during<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = f();
+    (result, PEAK.load(Ordering::SeqCst) - before)
+}
+
+#[test]
+fn test_stream_million_ints_without_second_copy() {
+    const N: usize = 1_000_000;
+    let value_copy = N * size_of::<Value>();
+
+    let mut env = Environment::new();
+    env.bind("big".to_string(), Value::Array(N, (0..).take(N).map(Value::Int).collect()));
+
+    // Sanity check of the measurement: copying the result costs a full Value array
+    let (copy, cloned) = peak_during(|| env.lookup("big").cloned());
+    assert!(cloned >= value_copy, "clone took {cloned} bytes");
+    drop(copy);
+
+    let stream = eval_stream(&parse("big").unwrap(), &env).unwrap();
+    drop(env);
+    assert_eq!(stream.len(), N);
+
+    let (numbers, converted) = peak_during(|| stream.map_items::<i64>().collect::<Result<Vec<i64>, _>>().unwrap());
+    assert_eq!(numbers.len(), N);
+    assert_eq!((numbers[0], numbers[N - 1]), (0, i64::try_from(N).unwrap() - 1));
+    // Only the Vec<i64> being built is new; nothing Value-shaped is copied
+    assert!(
+        converted < value_copy / 2,
+        "converting took {converted} bytes; a copy of the result is {value_copy} bytes"
+    );
+}
@@ -1,5 +1,5 @@
 /// Tests for sum type (algebraic data type) functionality
-use parlang::{eval, parse, Environment, Value};
+use parlang::{eval, parse, typecheck, Environment, EvalError, TypeError, Value};
 
 /// Test parsing a simple Option type definition
 #[test]
@@ -248,6 +248,49 @@ fn test_unknown_constructor_error() {
     assert!(result.is_err(), "Should fail with unknown constructor");
 }
 
+/// Test that a misspelled constructor suggests the registered one
+#[test]
+fn test_unknown_constructor_suggests_registered() {
+    let input = "type Option a = Some a | None in Noen";
+    let expr = parse(input).expect("Parse failed");
+    let err = eval(&expr, &Environment::new()).unwrap_err();
+    assert_eq!(err, EvalError::UnknownConstructor("Noen".to_string(), vec!["None".to_string()]));
+    assert_eq!(err.to_string(), "Unknown constructor: Noen (did you mean `None`?)");
+    assert_eq!(
+        typecheck(&expr),
+        Err(TypeError::UnknownConstructor("Noen".to_string(), vec!["None".to_string()]))
+    );
+}
+
+/// Test that a capitalized or misspelled boolean suggests the literal
+#[test]
+fn test_misspelled_boolean_suggests_literal() {
+    let expr = parse("Flase").expect("Parse failed");
+    let err = eval(&expr, &Environment::new()).unwrap_err();
+    assert_eq!(err.to_string(), "Unknown constructor: Flase (did you mean the literal `false`?)");
+    assert!(matches!(typecheck(&expr), Err(TypeError::UnknownConstructor(_, s)) if s == ["false"]));
+
+    let expr = parse("flase").expect("Parse failed");
+    let err = eval(&expr, &Environment::new()).unwrap_err();
+    assert_eq!(err, EvalError::UnboundVariable("flase".to_string(), vec!["false".to_string()]));
+    assert_eq!(err.to_string(), "Unbound variable: flase (did you mean the literal `false`?)");
+}
+
+/// Test that a misspelled variable suggests variables in scope, not constructors
+#[test]
+fn test_unbound_variable_suggests_variables() {
+    let input = "type Option a = Some a | None in let count = 1 in cuont";
+    let expr = parse(input).expect("Parse failed");
+    let err = eval(&expr, &Environment::new()).unwrap_err();
+    assert_eq!(err, EvalError::UnboundVariable("cuont".to_string(), vec!["count".to_string()]));
+    assert_eq!(
+        typecheck(&expr),
+        Err(TypeError::UnboundVariable("cuont".to_string(), vec!["count".to_string()]))
+    );
+    let expr = parse("type Option a = Some a | None in some").expect("Parse failed");
+    assert_eq!(eval(&expr, &Environment::new()), Err(EvalError::UnboundVariable("some".to_string(), vec![])));
+}
+
 /// Test pattern match on wrong constructor
 #[test]
 fn test_pattern_match_wrong_constructor() {
@@ -368,3 +411,41 @@ fn test_result_type() {
     assert!(result.is_ok(), "Eval failed: {:?}", result.err());
     assert_eq!(format!("{}", result.unwrap()), "100");
 }
+
+/// Test that each constructor argument type is a separate payload
+#[test]
+fn test_constructor_payload_types_are_separate() {
+    let expr = parse("type Tree k v = Leaf | Node k v (Tree k v) (Tree k v) | Pair Int Int in 0").unwrap();
+    let parlang::Expr::TypeDef { constructors, .. } = expr else { panic!("Expected a type definition") };
+    let arities: Vec<(&str, usize)> = constructors.iter().map(|(name, payload)| (name.as_str(), payload.len())).collect();
+    assert_eq!(arities, [("Leaf", 0), ("Node", 4), ("Pair", 2)]);
+
+    let ty = typecheck(&parse("type Pair = Pair Int Int in Pair 1 2").unwrap());
+    assert_eq!(ty.map(|ty| ty.to_string()), Ok("Pair".to_string()));
+}
+
+/// Test structural equality on constructed values
+#[test]
+fn test_constructed_value_equality() {
+    let run = |input: &str| eval(&parse(input).expect("Parse failed"), &Environment::new());
+    let option = "type Option a = Some a | None in ";
+    assert_eq!(run(&format!("{option}Some 1 == Some 1")), Ok(Value::Bool(true)));
+    assert_eq!(run(&format!("{option}Some 1 == Some 2")), Ok(Value::Bool(false)));
+    assert_eq!(run(&format!("{option}Some 1 != None")), Ok(Value::Bool(true)));
+    assert_eq!(run(&format!("{option}None == None")), Ok(Value::Bool(true)));
+    assert_eq!(run(&format!("{option}Some (Some true) == Some (Some true)")), Ok(Value::Bool(true)));
+
+    // Payloads without equality cannot be compared
+    let result = run(&format!("{option}Some (fun x -> x) == Some (fun x -> x)"));
+    assert!(matches!(result, Err(EvalError::BinOpTypeMismatch { left_kind: "function", .. })));
+
+    // Lists compare element by element, down to the last one
+    let cons = |n: i64, rest: Value| Value::Variant("Cons".to_string(), vec![Value::Int(n), rest]);
+    let list = |last: i64| (1..100).fold(cons(last, Value::Variant("Nil".to_string(), vec![])), |rest, n| cons(n, rest));
+    let mut env = Environment::new();
+    env.bind("xs".to_string(), list(0));
+    env.bind("ys".to_string(), list(0));
+    env.bind("zs".to_string(), list(1));
+    assert_eq!(eval(&parse("xs == ys").unwrap(), &env), Ok(Value::Bool(true)));
+    assert_eq!(eval(&parse("xs == zs").unwrap(), &env), Ok(Value::Bool(false)));
+}
@@ -0,0 +1,65 @@
+/// Integration tests for the evaluation trace of runtime errors
+use parlang::{eval, parse, Environment, EvalError, Value, MAX_TRACE_FRAMES};
+
+fn run(source: &str) -> Result<Value, EvalError> {
+    let expr = parse(source).expect("Parse error");
+    eval(&expr, &Environment::with_builtins())
+}
+
+#[test]
+fn test_trace_names_enclosing_call_and_binding() {
+    let source = "let fact = rec fact -> fun n -> let result = if n == 0 then m else n * fact (n - 1) in result in fact 2";
+    let error = run(source).unwrap_err();
+    assert_eq!(
+        error.trace(),
+        [
+            "in application of 'fact'",
+            "in let binding 'result'",
+            "in application of 'fact'",
+            "in let binding 'result'",
+            "in application of 'fact'",
+            "in let binding 'result'",
+        ]
+    );
+    assert!(matches!(error.root(), EvalError::UnboundVariable(name, _) if name == "m"));
+}
+
+#[test]
+fn test_trace_is_printed_outermost_first_and_indented() {
+    let error = run("let f = fun x -> let y = x / 0 in y in f 1").unwrap_err();
+    assert_eq!(error.to_string(), "in application of 'f'\n  in let binding 'y'\n    Division by zero");
+}
+
+#[test]
+fn test_trace_names_match_arms() {
+    let error = run("let head = fun xs -> match xs with | [] -> 0 | x :: _ -> x / 0 in head [1]").unwrap_err();
+    assert_eq!(error.trace(), ["in application of 'head'", "in match arm 'Cons x _'"]);
+    assert_eq!(error.root(), &EvalError::DivisionByZero);
+}
+
+#[test]
+fn test_errors_in_arguments_are_not_traced_to_the_call() {
+    let error = run("let f = fun x -> x in f (1 / 0)").unwrap_err();
+    assert_eq!(error, EvalError::DivisionByZero);
+    let error = run("let x = 1 in x 2").unwrap_err();
+    assert!(error.trace().is_empty());
+}
+
+#[test]
+fn test_trace_is_bounded() {
+    let source = "let down = rec down -> fun n -> if n == 0 then 1 / 0 else 1 + down (n - 1) in down 22";
+    let error = run(source).unwrap_err();
+    let trace = error.trace();
+    assert_eq!(trace.len(), MAX_TRACE_FRAMES + 1);
+    assert_eq!(trace[0], "...");
+    assert!(trace[1..].iter().all(|frame| *frame == "in application of 'down'"));
+    assert_eq!(error.root(), &EvalError::DivisionByZero);
+}
+
+#[test]
+fn test_handlers_see_the_error_without_its_trace() {
+    let source = "try (let f = fun x -> let y = x / 0 in y in f 1) catch err -> (err.kind, err.message)";
+    assert_eq!(run(source).unwrap().to_string(), "(\"DivisionByZero\", \"Division by zero\")");
+    let error = run("let f = fun x -> (1, 2).5 in try f 0 catch err -> err.message").unwrap();
+    assert!(error.to_string().starts_with("\"Index out of bounds"));
+}
@@ -0,0 +1,78 @@
+/// Integration tests for `try ... catch` error recovery
+use parlang::{eval, parse, typecheck, Environment, EvalError, Type, Value};
+
+fn run(source: &str) -> Result<Value, EvalError> {
+    let expr = parse(source).expect("Parse error");
+    eval(&expr, &Environment::with_builtins())
+}
+
+#[test]
+fn test_try_without_error_returns_body() {
+    assert_eq!(run("try 10 / 2 catch err -> 0"), Ok(Value::Int(5)));
+}
+
+#[test]
+fn test_catch_division_by_zero() {
+    assert_eq!(run("try 1 / 0 catch err -> 0"), Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_catch_index_out_of_bounds() {
+    assert_eq!(run("try (1, 2).5 catch err -> 0"), Ok(Value::Int(0)));
+}
+
+#[test]
+fn test_handler_sees_kind_and_message() {
+    let kind = run("try 1 / 0 catch err -> err.kind").unwrap();
+    assert_eq!(kind.to_string(), "\"DivisionByZero\"");
+    let message = run("try 1 / 0 catch err -> err.message").unwrap();
+    assert_eq!(message.to_string(), "\"Division by zero\"");
+}
+
+#[test]
+fn test_innermost_handler_catches() {
+    let result = run("try (try 1 / 0 catch inner -> 1) catch outer -> 2");
+    assert_eq!(result, Ok(Value::Int(1)));
+}
+
+#[test]
+fn test_error_in_handler_reaches_outer_try() {
+    let result = run("try (try 1 / 0 catch inner -> (1, 2).5) catch outer -> outer.kind").unwrap();
+    assert_eq!(result.to_string(), "\"IndexOutOfBounds\"");
+}
+
+#[test]
+fn test_unbound_variable_is_not_caught() {
+    let result = run("try missing catch err -> 0");
+    assert!(matches!(result, Err(EvalError::UnboundVariable(..))));
+}
+
+#[test]
+fn test_step_limit_is_not_caught() {
+    let expr = parse("try (rec loop -> fun n -> loop (n + 1)) 0 catch err -> 0").unwrap();
+    let mut env = Environment::with_builtins();
+    env.set_step_limit(Some(1000));
+    assert_eq!(eval(&expr, &env), Err(EvalError::StepLimitExceeded(1000)));
+}
+
+#[test]
+fn test_try_and_catch_are_keywords() {
+    assert!(parse("let try = 1 in try").is_err());
+    assert!(parse("let catch = 1 in catch").is_err());
+}
+
+#[test]
+fn test_typecheck_try() {
+    let expr = parse("try 1 / 0 catch err -> 0").unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::Int));
+
+    // Declares the `List` type that string literals desugar to
+    let expr = parse("type List a = Nil | Cons a (List a) in try \"ok\" catch err -> err.message").unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::SumType("List".to_string(), vec![Type::Char])));
+}
+
+#[test]
+fn test_typecheck_try_branches_must_agree() {
+    let expr = parse("try 1 catch err -> true").unwrap();
+    assert!(typecheck(&expr).is_err());
+}
@@ -93,6 +93,16 @@ fn test_typecheck_complex_function_type_alias() {
     assert_eq!(format!("{}", result.unwrap()), "Int -> Bool");
 }
 
+/// Test an alias of a built-in type used in an annotation
+#[test]
+fn test_typecheck_builtin_alias_in_annotation() {
+    let input = "type MyInt = Int in let x : MyInt = 42 in x";
+    let expr = parse(input).expect("Parse failed");
+    let result = typecheck(&expr);
+    assert!(result.is_ok(), "Typecheck failed: {:?}", result.err());
+    assert_eq!(format!("{}", result.unwrap()), "Int");
+}
+
 /// Test nested type aliases
 #[test]
 fn test_nested_type_aliases() {
@@ -1,19 +1,47 @@
 /// Tests for explicit type annotations
 /// This module tests parsing and type checking of explicit type annotations
-use parlang::{ast::*, parser::parse, typechecker::typecheck, types::Type};
+use parlang::{ast::*, parser::parse, typechecker::{typecheck, typecheck_in, TypeEnv, TypeError}, types::Type};
 
 #[test]
 fn test_parse_fun_with_type_annotation() {
-    // fun (x : Int) -> x + 1  (with parentheses to clarify)
-    let result = parse("fun (x : Int) -> x + 1");
-    // Without parentheses, we need to be careful about precedence
-    // Try without parens but with simpler body
-    let result2 = parse("fun x -> x");
-    assert!(result2.is_ok());
-    
-    // For now, let's test that we can parse with explicit syntax
-    let result3 = parse("let f = fun x -> x in f");
-    assert!(result3.is_ok());
+    let int = Some(TypeAnnotation::Concrete("Int".to_string()));
+    let expected = Expr::Fun("x".to_string(), int, Box::new(Expr::Var("x".to_string())));
+    assert_eq!(parse("fun (x : Int) -> x"), Ok(expected.clone()));
+    assert_eq!(parse("fun x : Int -> x"), Ok(expected));
+}
+
+#[test]
+fn test_parse_fun_with_arrow_and_applied_annotations() {
+    let int = || Box::new(TypeAnnotation::Concrete("Int".to_string()));
+    let body = || Box::new(Expr::Var("f".to_string()));
+
+    // An arrow type needs parentheses, around the parameter or the type
+    let arrow = Expr::Fun("f".to_string(), Some(TypeAnnotation::Fun(int(), int())), body());
+    assert_eq!(parse("fun (f : Int -> Int) -> f"), Ok(arrow.clone()));
+    assert_eq!(parse("fun f : (Int -> Int) -> f"), Ok(arrow));
+
+    let option = TypeAnnotation::App("Option".to_string(), vec![TypeAnnotation::Var("a".to_string())]);
+    assert_eq!(parse("fun f : Option a -> f"), Ok(Expr::Fun("f".to_string(), Some(option), body())));
+}
+
+#[test]
+fn test_parse_malformed_annotation_points_at_annotation() {
+    let message = |source: &str| parse(source).unwrap_err().description();
+    assert_eq!(message("let x : Int -> = 1 in x"), "malformed type annotation at 'Int -> = 1 in x'");
+    assert_eq!(message("fun (x : ) -> x"), "malformed type annotation at ') -> x'");
+    assert_eq!(
+        message("let y = 1;\nlet f : (Int -> = fun x -> x;\nf y"),
+        "malformed type annotation at '(Int -> = fun x -> x;'"
+    );
+}
+
+#[test]
+fn test_typecheck_annotated_fun_parameter() {
+    let expr = parse("fun (x : Int) -> x").unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::Fun(Box::new(Type::Int), Box::new(Type::Int))));
+
+    let expr = parse("(fun x : Bool -> x) 1").unwrap();
+    assert!(typecheck(&expr).is_err());
 }
 
 #[test]
@@ -252,3 +280,143 @@ fn test_display_let_without_annotation() {
     );
     assert_eq!(format!("{}", expr), "(let x = 42 in x)");
 }
+
+// Integer literals are never silently converted to Float
+
+fn int_literal_error(source: &str) -> TypeError {
+    typecheck(&parse(source).unwrap()).unwrap_err()
+}
+
+#[test]
+fn test_int_literal_in_float_annotated_let() {
+    assert_eq!(int_literal_error("let x : Float = 3 in x"), TypeError::IntLiteralForFloat(3));
+    assert_eq!(
+        int_literal_error("let x : Float = 3; x").to_string(),
+        "integer literal 3 used where Float is expected; write 3.0"
+    );
+    assert_eq!(int_literal_error("let x : Float = -3 in x").to_string(), "integer literal -3 used where Float is expected; write -3.0");
+    assert_eq!(typecheck(&parse("let x : Float = 3.0 in x").unwrap()), Ok(Type::Float));
+}
+
+#[test]
+fn test_annotation_mismatch_is_reported_at_the_value() {
+    for (source, value) in [("let x : Float = 3 in\nx + 1.0", "3"), ("let b : Bool = 1 + 2;\nb", "1 + 2")] {
+        let (expr, spans) = parlang::parse_program_with_spans(source).unwrap();
+        let error = parlang::typecheck_with_spans(&expr, &spans, &mut TypeEnv::new()).unwrap_err();
+        let span = error.span().unwrap();
+        assert_eq!(&source[span.start..span.end], value, "{error}");
+    }
+}
+
+#[test]
+fn test_int_literal_for_float_parameter() {
+    assert_eq!(int_literal_error("let half : Float -> Float = fun x -> x / 2.0 in half 7"), TypeError::IntLiteralForFloat(7));
+    assert_eq!(int_literal_error("let half = fun x -> x / 2.0 in half 7"), TypeError::IntLiteralForFloat(7));
+}
+
+#[test]
+fn test_mixed_arithmetic_is_not_converted() {
+    assert_eq!(int_literal_error("let x : Float = 1.5 in x + 2"), TypeError::IntLiteralForFloat(2));
+    assert_eq!(int_literal_error("let x : Float = 1.5 in 2 * x"), TypeError::IntLiteralForFloat(2));
+    assert_eq!(int_literal_error("let x : Float = 1.5 in x < 1"), TypeError::IntLiteralForFloat(1));
+    // Only literals get the targeted message; an Int-typed expression is a plain mismatch
+    let n_plus_x = int_literal_error("let n = 1 in let x : Float = 1.5 in x + n");
+    assert!(matches!(n_plus_x, TypeError::UnificationError(_, _)), "{n_plus_x}");
+    assert!(matches!(int_literal_error("let x : Int = 1.5 in x"), TypeError::UnificationError(_, _)));
+}
+
+/// Type check a program with the prelude, which defines `List`
+fn typecheck_prelude(source: &str) -> Result<Type, TypeError> {
+    typecheck_in(&parse(source).unwrap(), &mut TypeEnv::with_builtins())
+}
+
+/// Type check a program that is expected to be well-typed
+fn typecheck_ok(source: &str) -> Type {
+    typecheck_prelude(source).unwrap_or_else(|e| panic!("{source}: {e}"))
+}
+
+/// Type check a program that is expected to be rejected
+fn typecheck_err(source: &str) -> TypeError {
+    typecheck_prelude(source).unwrap_err()
+}
+
+const OPTION: &str = "type Option a = Some a | None in ";
+const EITHER: &str = "type Either a b = Left a | Right b in ";
+
+fn sum_type(name: &str, args: Vec<Type>) -> Type {
+    Type::SumType(name.to_string(), args)
+}
+
+#[test]
+fn test_let_with_applied_annotation() {
+    let option_int = sum_type("Option", vec![Type::Int]);
+    assert_eq!(typecheck_ok(&format!("{OPTION}let o : Option Int = None in o")), option_int);
+    assert_eq!(typecheck_ok("let xs : List Int = [] in xs"), sum_type("List", vec![Type::Int]));
+    assert_eq!(
+        typecheck_ok(&format!("{EITHER}let e : Either Int Bool = Left 1 in e")),
+        sum_type("Either", vec![Type::Int, Type::Bool])
+    );
+    assert_eq!(
+        typecheck_ok(&format!("{OPTION}let xs : List (Option Int) = [Some 1, None] in xs")),
+        sum_type("List", vec![option_int])
+    );
+    assert!(matches!(
+        typecheck_err(&format!("{OPTION}let o : Option Bool = Some 1 in o")),
+        TypeError::UnificationError(..)
+    ));
+}
+
+#[test]
+fn test_fun_param_with_applied_annotation() {
+    let option_int = || sum_type("Option", vec![Type::Int]);
+    let get = "fun (o : Option Int) -> match o with | Some n -> n | None -> 0";
+    assert_eq!(typecheck_ok(&format!("{OPTION}{get}")), Type::Fun(Box::new(option_int()), Box::new(Type::Int)));
+    assert_eq!(typecheck_ok(&format!("{OPTION}({get}) (Some 3)")), Type::Int);
+    assert!(matches!(typecheck_err(&format!("{OPTION}({get}) (Some true)")), TypeError::UnificationError(..)));
+
+    // Arrows mix with applications on either side
+    let apply = "fun (f : Option Int -> List Int) -> f (Some 1)";
+    assert_eq!(
+        typecheck_ok(&format!("{OPTION}{apply}")),
+        Type::Fun(
+            Box::new(Type::Fun(Box::new(option_int()), Box::new(sum_type("List", vec![Type::Int])))),
+            Box::new(sum_type("List", vec![Type::Int]))
+        )
+    );
+    let wrap = "fun (f : Int -> Int) -> fun (o : Option (Int -> Int)) -> match o with | Some g -> g | None -> f";
+    assert!(typecheck_prelude(&format!("{OPTION}{wrap}")).is_ok());
+}
+
+#[test]
+fn test_applied_annotation_arity_mismatch() {
+    assert_eq!(
+        typecheck_err(&format!("{OPTION}let o : Option Int Bool = None in o")),
+        TypeError::TypeArityMismatch("Option".to_string(), 1, 2)
+    );
+    assert_eq!(
+        typecheck_err(&format!("{OPTION}fun (o : Option) -> o")),
+        TypeError::TypeArityMismatch("Option".to_string(), 1, 0)
+    );
+    assert_eq!(
+        typecheck_err(&format!("{EITHER}fun (e : List (Either Int)) -> e")),
+        TypeError::TypeArityMismatch("Either".to_string(), 2, 1)
+    );
+    assert_eq!(typecheck_err("let x : Int Bool = 1 in x"), TypeError::TypeArityMismatch("Int".to_string(), 0, 1));
+    assert_eq!(
+        typecheck_err(&format!("{OPTION}let o : Option Int Bool = None in o")).to_string(),
+        "Type 'Option' expects 1 type arguments, but got 2"
+    );
+    assert!(matches!(typecheck_err("let o : Maybe Int = 1 in o"), TypeError::UnboundVariable(ref name, _) if name == "Maybe"));
+}
+
+#[test]
+fn test_annotation_variables_are_shared() {
+    let ty = typecheck_ok("fun (f : a -> a) -> f");
+    let Type::Fun(param, _) = &ty else { panic!("{ty}") };
+    assert!(matches!(**param, Type::Fun(ref arg, ref ret) if arg == ret), "{ty}");
+    assert_eq!(typecheck_ok("let first : a -> b -> a = fun x -> fun y -> x in first 1 true"), Type::Int);
+    assert!(matches!(
+        typecheck_err("let first : a -> a -> a = fun x -> fun y -> x in first 1 true"),
+        TypeError::UnificationError(..)
+    ));
+}
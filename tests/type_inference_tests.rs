@@ -1,6 +1,10 @@
 /// Integration tests for type inference system
 /// These tests verify the complete type inference pipeline
-use parlang::{parse, typecheck, Type};
+use parlang::typechecker::canonicalize;
+use parlang::{parse, typecheck, typecheck_in, LoadPath, Type, TypeEnv, TypeError, TypeScheme, TypeVar};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
 
 #[test]
 fn test_complete_program_int() {
@@ -243,14 +247,14 @@ fn test_type_env_default() {
 #[test]
 fn test_type_error_display_unbound_variable() {
     use parlang::TypeError;
-    let error = TypeError::UnboundVariable("x".to_string());
+    let error = TypeError::UnboundVariable("x".to_string(), vec![]);
     assert_eq!(format!("{error}"), "Unbound variable: x");
 }
 
 #[test]
 fn test_type_error_display_unification_error() {
     use parlang::TypeError;
-    let error = TypeError::UnificationError(Type::Int, Type::Bool);
+    let error = TypeError::UnificationError(Box::new(Type::Int), Box::new(Type::Bool));
     assert_eq!(format!("{error}"), "Cannot unify types: Int and Bool");
 }
 
@@ -264,8 +268,12 @@ fn test_type_error_display_occurs_check() {
 #[test]
 fn test_type_error_display_recursion() {
     use parlang::TypeError;
-    let error = TypeError::RecursionRequiresAnnotation;
-    assert_eq!(format!("{error}"), "Recursive functions require type annotations");
+    let error = TypeError::RecursionRequiresAnnotation("f".to_string());
+    assert_eq!(
+        format!("{error}"),
+        "Recursive function 'f' calls itself at a type other than its own, which needs a type annotation: \
+         let f : <type> = rec f -> ..."
+    );
 }
 
 #[test]
@@ -280,6 +288,73 @@ fn test_recursion_supported() {
     }
 }
 
+/// The generalized type of a program's result, e.g. `forall t0. t0 -> t0`
+fn generalized_type(source: &str) -> String {
+    let ty = typecheck_in(&parse(source).unwrap(), &mut TypeEnv::with_builtins()).unwrap();
+    TypeEnv::new().generalize(&ty).to_string()
+}
+
+/// A `map` over the prelude's lists
+const MAP: &str = "let map = rec map -> fun f -> fun xs -> match xs with | Nil -> Nil | Cons h t -> Cons (f h) (map f t) in ";
+
+/// A type whose recursive values nest lists one level deeper each time
+const NESTED: &str = "type Nested a = Flat a | Nest (Nested (List a)) in ";
+
+/// Counts the `Nest`s around a `Nested`, calling itself at `Nested (List a)`
+const DEPTH: &str = "rec depth -> fun n -> match n with | Flat _ -> 0 | Nest inner -> 1 + depth inner";
+
+#[test]
+fn test_let_bound_recursive_function_is_polymorphic() {
+    let expr = parse("let id_rec = rec f -> fun x -> x in (id_rec 1, id_rec true)").unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::Tuple(vec![Type::Int, Type::Bool])));
+    assert_eq!(generalized_type("let id_rec = rec f -> fun x -> x in id_rec"), "forall t0. t0 -> t0");
+
+    assert_eq!(generalized_type(&format!("{MAP}map")), "forall t0, t1. (t0 -> t1) -> List t0 -> List t1");
+    let source = format!("{MAP}(map (fun n -> n + 1) [1, 2], map (fun b -> if b then 'y' else 'n') [true])");
+    let list = |ty| Type::SumType("List".to_string(), vec![ty]);
+    assert_eq!(
+        typecheck_in(&parse(&source).unwrap(), &mut TypeEnv::with_builtins()),
+        Ok(Type::Tuple(vec![list(Type::Int), list(Type::Char)]))
+    );
+}
+
+#[test]
+fn test_polymorphic_recursion_requires_annotation() {
+    let source = format!("{NESTED}let depth = {DEPTH} in depth (Flat 1)");
+    assert_eq!(
+        typecheck_in(&parse(&source).unwrap(), &mut TypeEnv::with_builtins()),
+        Err(TypeError::RecursionRequiresAnnotation("depth".to_string()))
+    );
+    let error = typecheck(&parse("rec f -> fun x -> f (fun y -> x)").unwrap());
+    assert_eq!(error, Err(TypeError::RecursionRequiresAnnotation("f".to_string())));
+
+    // A type that really is infinite is still an occurs check failure
+    assert!(matches!(typecheck(&parse("rec f -> fun x -> f").unwrap()), Err(TypeError::OccursCheckFailed(..))));
+    assert!(matches!(typecheck(&parse("rec f -> fun x -> x x").unwrap()), Err(TypeError::OccursCheckFailed(..))));
+}
+
+#[test]
+fn test_annotated_polymorphic_recursion() {
+    let source = format!("{NESTED}let depth : Nested a -> Int = {DEPTH} in (depth (Nest (Nest (Flat [[1]]))), depth (Flat true))");
+    assert_eq!(
+        typecheck_in(&parse(&source).unwrap(), &mut TypeEnv::with_builtins()),
+        Ok(Type::Tuple(vec![Type::Int, Type::Int]))
+    );
+    assert_eq!(
+        generalized_type(&format!("{NESTED}let depth : Nested a -> Int = {DEPTH} in depth")),
+        "forall t0. Nested t0 -> Int"
+    );
+
+    // The annotation must be as general as the function, and a type it fixes
+    // still rules out the calls at other types
+    let less_general = format!("{NESTED}let depth : Nested a -> b = {DEPTH} in depth");
+    let error = typecheck_in(&parse(&less_general).unwrap(), &mut TypeEnv::with_builtins());
+    assert!(matches!(error, Err(TypeError::UnificationError(..))), "{error:?}");
+    let monomorphic = format!("{NESTED}let depth : Nested Int -> Int = {DEPTH} in depth");
+    let error = typecheck_in(&parse(&monomorphic).unwrap(), &mut TypeEnv::with_builtins());
+    assert!(matches!(error, Err(TypeError::InMatchArm(2, ..))), "{error:?}");
+}
+
 #[test]
 fn test_tuple_type_inference() {
     // Tuples currently return type variables (simplified implementation)
@@ -307,10 +382,137 @@ fn test_match_type_inference() {
 
 #[test]
 fn test_load_type_inference() {
-    // Load expressions currently return type variables
+    // Loaded libraries are type checked, so a missing library is an error
     let expr = parse("load \"test.par\" in 42").unwrap();
     let result = typecheck(&expr);
-    assert!(result.is_ok());
+    assert!(matches!(result, Err(TypeError::LoadError(msg)) if msg.contains("test.par")));
+
+    let expr = parse("load \"examples/stdlib.par\" in 42").unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::Int));
+}
+
+#[test]
+fn test_load_as_type_inference() {
+    let lib = write_library("namespaced.par", "let id = fun x -> x;\nlet add = fun a -> fun b -> a + b;\n0");
+    // Each use of a namespaced binding is instantiated afresh
+    let expr = parse(&format!("load \"{lib}\" as M in (M.id true, M.add (M.id 1) 2)")).unwrap();
+    assert_eq!(typecheck(&expr), Ok(Type::Tuple(vec![Type::Bool, Type::Int])));
+
+    let expr = parse(&format!("load \"{lib}\" as M in M.nope")).unwrap();
+    assert!(matches!(typecheck(&expr), Err(TypeError::FieldNotFound(..))));
+    // The library's names are not bound unqualified
+    let expr = parse(&format!("load \"{lib}\" as M in id 1")).unwrap();
+    assert!(matches!(typecheck(&expr), Err(TypeError::UnboundVariable(..))));
+}
+
+#[test]
+fn test_load_paths_type_inference() {
+    // Libraries are found next to the library loading them, and cycles are reported
+    let dir = env::temp_dir().join(format!("parlang_typing_{}_load_paths", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("inner.par"), "let flag = true;").unwrap();
+    fs::write(dir.join("outer.par"), "load \"inner.par\" in let negated = if flag then false else true in 0").unwrap();
+    fs::write(dir.join("cycle.par"), "load \"cycle.par\" in 0").unwrap();
+
+    let mut tenv = TypeEnv::with_builtins();
+    tenv.set_load_path(LoadPath { dir: Some(dir.clone()), search_path: Vec::new() });
+    let expr = parse("load \"outer.par\" in negated").unwrap();
+    assert_eq!(typecheck_in(&expr, &mut tenv.clone()), Ok(Type::Bool));
+    let expr = parse("load \"cycle.par\" in 0").unwrap();
+    let result = typecheck_in(&expr, &mut tenv);
+    assert!(matches!(&result, Err(TypeError::InLibrary(_, inner)) if inner.to_string().contains("Circular load")), "{result:?}");
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+/// Write `source` to a fresh temporary library and return its path
+fn write_library(name: &str, source: &str) -> String {
+    let path = env::temp_dir().join(format!("parlang_typing_{}_{name}", std::process::id()));
+    fs::write(&path, source).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+/// Pretty-print the schemes a library exported, one per line
+fn library_schemes(tenv: &TypeEnv, path: &str) -> String {
+    let library = tenv.loaded_library(path).expect("library was loaded");
+    library.schemes.iter().fold(String::new(), |mut out, (name, scheme)| {
+        let _ = writeln!(out, "{name} : {scheme}");
+        out
+    })
+}
+
+#[test]
+fn test_library_schemes_independent_of_entry_program() {
+    let lib = write_library(
+        "combinators.par",
+        "let compose = fun f -> fun g -> fun x -> f (g x);\nlet const = fun a -> fun b -> a;\nlet twice = fun f -> compose f f;\n0",
+    );
+    let entries = [
+        format!("load \"{lib}\" in twice (fun n -> n + 1) 1"),
+        format!("let f = fun x -> fun y -> fun z -> x in let g = f 1 true in load \"{lib}\" in const (g 2) 3"),
+    ];
+    let mut printed = Vec::new();
+    for source in &entries {
+        let mut tenv = TypeEnv::with_builtins();
+        assert_eq!(typecheck_in(&parse(source).unwrap(), &mut tenv), Ok(Type::Int), "{source}");
+        printed.push(library_schemes(&tenv, &lib));
+    }
+    assert_eq!(printed[0], printed[1]);
+    assert_eq!(
+        printed[0],
+        "compose : forall t0, t1, t2. (t0 -> t1) -> (t2 -> t0) -> t2 -> t1\n\
+         const : forall t0, t1. t0 -> t1 -> t0\n\
+         twice : forall t0. (t0 -> t0) -> t0 -> t0\n"
+    );
+}
+
+#[test]
+fn test_library_cache_hit_in_diamond() {
+    let base = write_library("diamond_base.par", "let inc = fun n -> n + 1;\n0");
+    let left = write_library("diamond_left.par", &format!("load \"{base}\" in\nlet left = fun n -> inc n in\n0"));
+    let right = write_library("diamond_right.par", &format!("load \"{base}\" in\nlet right = fun n -> inc (inc n) in\n0"));
+    let source = format!("load \"{left}\" in load \"{right}\" in left 1 + right 2 + inc 3");
+    let mut tenv = TypeEnv::with_builtins();
+    assert_eq!(typecheck_in(&parse(&source).unwrap(), &mut tenv), Ok(Type::Int));
+    assert_eq!(tenv.library_cache_hits(), 1);
+    assert_eq!(library_schemes(&tenv, &base), "inc : Int -> Int\n");
+}
+
+#[test]
+fn test_library_uses_types_defined_by_loader() {
+    // As at runtime, a library sees the sum types of the program loading it
+    let lib = write_library("loader_types.par", "let unwrap = fun o -> match o with | Got x -> x | Missing -> 0;\n0");
+    let source = format!("type Found a = Got a | Missing in load \"{lib}\" in unwrap (Got 3)");
+    let mut tenv = TypeEnv::with_builtins();
+    assert_eq!(typecheck_in(&parse(&source).unwrap(), &mut tenv), Ok(Type::Int));
+    assert_eq!(library_schemes(&tenv, &lib), "unwrap : Found Int -> Int\n");
+    // Loaded where the type is not defined, it is checked again and fails
+    let mut tenv = TypeEnv::with_builtins();
+    let result = typecheck_in(&parse(&format!("load \"{lib}\" in 0")).unwrap(), &mut tenv);
+    assert!(matches!(&result, Err(TypeError::InLibrary(_, inner)) if inner.to_string().contains("Unknown constructor")), "{result:?}");
+}
+
+#[test]
+fn test_library_errors_name_the_library() {
+    let lib = write_library("broken.par", "let bad = 1 + true;\n0");
+    let result = typecheck(&parse(&format!("load \"{lib}\" in 0")).unwrap());
+    assert!(matches!(result, Err(TypeError::InLibrary(path, _)) if path == lib));
+}
+
+#[test]
+fn test_canonicalize_renumbers_quantified_vars() {
+    let var = |n| Type::Var(TypeVar(n));
+    let fun = |a: Type, b: Type| Type::Fun(Box::new(a), Box::new(b));
+    // t7 is free, so quantified variables are numbered around it
+    let scheme = TypeScheme {
+        vars: vec![TypeVar(3), TypeVar(9)],
+        row_vars: vec![],
+        ty: fun(var(9), fun(var(7), var(3))),
+    };
+    let canonical = canonicalize(&scheme);
+    assert_eq!(canonical.vars, vec![TypeVar(0), TypeVar(1)]);
+    assert_eq!(canonical.ty, fun(var(0), fun(var(7), var(1))));
+    assert_eq!(canonicalize(&canonical), canonical);
 }
 
 #[test]
@@ -359,17 +561,30 @@ fn test_rec_with_let_binding() {
     assert_eq!(ty, Type::Int);
 }
 
+#[test]
+fn test_let_rec_type() {
+    let expr = parse("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1) in fact").unwrap();
+    assert_eq!(typecheck(&expr).unwrap(), Type::Fun(Box::new(Type::Int), Box::new(Type::Int)));
+    let expr = parse("let rec fact = fun n -> if n == 0 then 1 else n * fact (n - 1); fact 5").unwrap();
+    assert_eq!(typecheck(&expr).unwrap(), Type::Int);
+
+    // The binding is generalized after the fixpoint is typed
+    let expr = parse("let rec loop = fun x -> fun n -> if n == 0 then x else loop x (n - 1) in (loop 1 2, loop true 2)").unwrap();
+    assert_eq!(typecheck(&expr).unwrap(), Type::Tuple(vec![Type::Int, Type::Bool]));
+
+    let expr = parse("let rec f : Int -> Bool = fun n -> if n == 0 then 0 else f (n - 1) in f").unwrap();
+    assert!(typecheck(&expr).is_err());
+}
+
 #[test]
 fn test_rec_curried_function() {
     // Test curried recursive function: rec f -> fun x -> fun y -> if y == 0 then x else f (x + 1) (y - 1)
-    // Note: This creates an infinite type due to the recursive structure, so it should fail the occurs check
     let expr = parse("rec f -> fun x -> fun y -> if y == 0 then x else f (x + 1) (y - 1)").unwrap();
-    let result = typecheck(&expr);
-    // This actually fails with occurs check because f's type would be infinite
-    assert!(result.is_err(), "Curried recursive function creates infinite type");
-    if let Err(e) = result {
-        assert!(matches!(e, parlang::TypeError::OccursCheckFailed(_, _)));
-    }
+    let ty = typecheck(&expr).unwrap();
+    assert_eq!(
+        ty,
+        Type::Fun(Box::new(Type::Int), Box::new(Type::Fun(Box::new(Type::Int), Box::new(Type::Int))))
+    );
 }
 
 #[test]
@@ -588,3 +803,98 @@ fn test_unit_in_if() {
     let ty = typecheck(&expr).unwrap();
     assert_eq!(ty, Type::Unit);
 }
+
+#[test]
+fn test_tuple_types() {
+    let ty = typecheck(&parse("((1, 'a'), true, fun x -> x + 1)").unwrap()).unwrap();
+    assert_eq!(ty.to_string(), "((Int, Char), Bool, Int -> Int)");
+    assert_eq!(typecheck(&parse("(1, true).0 + 1").unwrap()), Ok(Type::Int));
+    assert_eq!(typecheck(&parse("let p = (1, (2.5, 'c')) in p.1.1").unwrap()), Ok(Type::Char));
+    assert_eq!(typecheck(&parse("if true then (1, 2) else (3, 4)").unwrap()).unwrap().to_string(), "(Int, Int)");
+    // The arity of a tuple reaching a projection through a variable is unknown
+    assert!(typecheck(&parse("fun p -> p.0").unwrap()).is_ok());
+}
+
+#[test]
+fn test_tuple_type_errors() {
+    assert_eq!(
+        typecheck(&parse("(1, true).1 + 1").unwrap()),
+        Err(TypeError::UnificationError(Box::new(Type::Bool), Box::new(Type::Int)))
+    );
+    assert_eq!(typecheck(&parse("(1, true).2").unwrap()), Err(TypeError::TupleIndexOutOfRange(2, 2)));
+    assert_eq!(typecheck(&parse("let n = 5 in n.0").unwrap()), Err(TypeError::TupleExpected("Int".to_string())));
+
+    let err = typecheck(&parse("if true then (1, 2) else (1, 2, 3)").unwrap()).unwrap_err();
+    assert_eq!(err, TypeError::TupleArityMismatch(2, 3));
+    assert_eq!(err.to_string(), "Cannot unify a tuple of 2 elements with a tuple of 3 elements");
+}
+
+#[test]
+fn test_match_types() {
+    let ty = |source: &str| typecheck(&parse(source).unwrap()).map(|ty| ty.to_string()).map_err(|e| e.to_string());
+    // Literal patterns fix the scrutinee's type, variable patterns bind it
+    assert_eq!(ty("fun x -> match x with | 0 -> true | _ -> false"), Ok("Int -> Bool".to_string()));
+    assert_eq!(ty("match 3 with | n -> n + 1"), Ok("Int".to_string()));
+    // Tuple patterns decompose
+    assert_eq!(ty("match (1, 'a') with | (n, c) -> if c == 'a' then n else 0"), Ok("Int".to_string()));
+    assert_eq!(ty("fun p -> match p with | (a, true) -> a + 1 | (_, false) -> 0"), Ok("(Int, Bool) -> Int".to_string()));
+    // Constructor patterns use the constructor's payload types
+    assert_eq!(
+        ty("type Option a = Some a | None in fun o -> match o with | Some x -> x + 1 | None -> 0"),
+        Ok("Option Int -> Int".to_string())
+    );
+    assert_eq!(
+        ty("type Tree a = Leaf | Node a (Tree a) (Tree a) in match Node 1 Leaf Leaf with | Node v _ _ -> v | Leaf -> 0"),
+        Ok("Int".to_string())
+    );
+    // Record patterns may name only some fields
+    assert_eq!(ty("match { x: 1, y: true } with | { x: n } -> n * 2"), Ok("Int".to_string()));
+    // Constraints found in the match reach the rest of the program
+    assert_eq!(ty("fun x -> let y = match x with | 'a' -> 1 | _ -> 2 in x"), Ok("Char -> Char".to_string()));
+}
+
+#[test]
+fn test_binding_pattern_types() {
+    let ty = |source: &str| typecheck(&parse(source).unwrap()).map(|ty| ty.to_string()).map_err(|e| e.to_string());
+    assert_eq!(ty("fun (x, y) -> x + y"), Ok("(Int, Int) -> Int".to_string()));
+    assert_eq!(ty("let (a, (b, c)) = (1, (true, 'c')) in if b then a else 0"), Ok("Int".to_string()));
+    assert_eq!(ty("let swap (a, b) = (b, a) in swap (1, true)"), Ok("(Bool, Int)".to_string()));
+    assert_eq!(ty("fun { x: n } -> n * 2").map(|ty| ty.starts_with("{x: Int")), Ok(true));
+    // The pattern must fit the value
+    assert!(ty("let (a, b) = (1, 2, 3) in a").is_err());
+    assert!(ty("(fun (a, b) -> a) 5").is_err());
+}
+
+#[test]
+fn test_match_errors_name_the_arm() {
+    let err = typecheck(&parse("match 5 with | 0 -> 1 | _ -> true").unwrap()).unwrap_err();
+    assert_eq!(
+        err,
+        TypeError::InMatchArm(2, "_".to_string(), Box::new(TypeError::UnificationError(Box::new(Type::Int), Box::new(Type::Bool))))
+    );
+    assert_eq!(err.to_string(), "In match arm 2 (_): Cannot unify types: Int and Bool");
+
+    let err = typecheck(&parse("match true with | 0 -> 1 | _ -> 2").unwrap()).unwrap_err();
+    assert_eq!(err.to_string(), "In match arm 1 (0): Cannot unify types: Bool and Int");
+
+    let err = typecheck(&parse("match (1, 2) with | (a, b, c) -> a").unwrap()).unwrap_err();
+    assert!(matches!(err, TypeError::InMatchArm(1, _, ref inner) if **inner == TypeError::TupleArityMismatch(2, 3)), "{err}");
+
+    let err = typecheck(&parse("type Option a = Some a | None in match None with | Some -> 1 | None -> 0").unwrap()).unwrap_err();
+    assert!(matches!(err, TypeError::InMatchArm(1, _, ref inner) if matches!(**inner, TypeError::ConstructorArityMismatch(..))), "{err}");
+
+    // Errors in an arm's body are attributed to the arm too
+    let err = typecheck(&parse("match 1 with | n -> n + true").unwrap()).unwrap_err();
+    assert!(err.to_string().starts_with("In match arm 1 (n): "), "{err}");
+}
+
+#[test]
+fn test_unary_minus_types() {
+    let ty = |source: &str| typecheck(&parse(source).unwrap()).map(|ty| ty.to_string());
+    assert_eq!(ty("let x = 1 in -x"), Ok("Int".to_string()));
+    assert_eq!(ty("let x = 1.5 in -x"), Ok("Float".to_string()));
+    assert_eq!(ty("fun x -> -x"), Ok("Int -> Int".to_string()));
+    assert_eq!(ty("fun (x : Float) -> -x"), Ok("Float -> Float".to_string()));
+    assert!(ty("-true").is_err());
+    assert!(ty("-'a'").is_err());
+}
@@ -144,7 +144,7 @@ fn test_type_error_is_error() {
     use parlang::TypeError;
     use std::error::Error;
     
-    let err = TypeError::UnboundVariable("x".to_string());
+    let err = TypeError::UnboundVariable("x".to_string(), vec![]);
     let _: &dyn Error = &err;  // Should compile if it implements Error
 }
 
@@ -181,8 +181,9 @@ fn test_rec_if_non_boolean_in_rec() {
     let code = r"(rec f -> fun n -> if n then 1 else 0) 5";
     let expr = parse(code).unwrap();
     let env = Environment::new();
-    let result = eval(&expr, &env);
-    assert!(matches!(result, Err(EvalError::TypeError(_))));
+    let error = eval(&expr, &env).unwrap_err();
+    assert!(matches!(error.root(), EvalError::TypeError(_)));
+    assert_eq!(error.trace(), ["in application of 'f'"]);
 }
 
 #[test]